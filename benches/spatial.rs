@@ -0,0 +1,104 @@
+//! Nearest-atom and radius queries through the molecule's k-d tree against a plain scan over
+//! every atom. Run with `cargo bench --bench spatial`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use glam::Vec3;
+use molweaver::{AtomId, Molecule};
+
+/// Atoms on a jittered cubic lattice about 1.5 Å apart, like a dense solvent box.
+fn solvent_box(side: usize) -> Molecule {
+    let mut molecule = Molecule::new("box");
+    for i in 0..side * side * side {
+        let (x, y, z) = (i % side, i / side % side, i / (side * side));
+        let jitter = (i as f32 * 0.618).fract() * 0.4;
+        let position = [
+            x as f32 * 1.5 + jitter,
+            y as f32 * 1.5,
+            z as f32 * 1.5 - jitter,
+        ];
+        molecule.insert_atom("O".into(), position);
+    }
+    molecule
+}
+
+/// Points spread through the box by a fixed low-discrepancy sequence.
+fn probes(side: usize, count: usize) -> Vec<[f32; 3]> {
+    let extent = side as f32 * 1.5;
+    (0..count)
+        .map(|i| {
+            let t = i as f32;
+            [0.618, 0.755, 0.569].map(|step| (t * step).fract() * extent)
+        })
+        .collect()
+}
+
+fn scan_nearest(molecule: &Molecule, point: [f32; 3]) -> Option<AtomId> {
+    let point = Vec3::from(point);
+    molecule
+        .atoms_in_order()
+        .min_by(|a, b| {
+            let (a, b) = (Vec3::from(a.position), Vec3::from(b.position));
+            a.distance_squared(point)
+                .total_cmp(&b.distance_squared(point))
+        })
+        .map(|atom| atom.id)
+}
+
+fn scan_within(molecule: &Molecule, point: [f32; 3], radius: f32) -> Vec<AtomId> {
+    let point = Vec3::from(point);
+    molecule
+        .atoms_in_order()
+        .filter(|atom| Vec3::from(atom.position).distance(point) <= radius)
+        .map(|atom| atom.id)
+        .collect()
+}
+
+fn queries(c: &mut Criterion) {
+    for side in [10, 22] {
+        let molecule = solvent_box(side);
+        let points = probes(side, 100);
+        let atoms = molecule.atom_count();
+        molecule.nearest_atom([0.0; 3]);
+
+        let mut group = c.benchmark_group("nearest_atom");
+        group.bench_with_input(BenchmarkId::new("kdtree", atoms), &points, |b, points| {
+            b.iter(|| {
+                points
+                    .iter()
+                    .filter_map(|&p| molecule.nearest_atom(black_box(p)))
+                    .count()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("scan", atoms), &points, |b, points| {
+            b.iter(|| {
+                points
+                    .iter()
+                    .filter_map(|&p| scan_nearest(&molecule, black_box(p)))
+                    .count()
+            })
+        });
+        group.finish();
+
+        let mut group = c.benchmark_group("atoms_within_4A");
+        group.bench_with_input(BenchmarkId::new("kdtree", atoms), &points, |b, points| {
+            b.iter(|| {
+                points
+                    .iter()
+                    .map(|&p| molecule.atoms_within(black_box(p), 4.0).len())
+                    .sum::<usize>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("scan", atoms), &points, |b, points| {
+            b.iter(|| {
+                points
+                    .iter()
+                    .map(|&p| scan_within(&molecule, black_box(p), 4.0).len())
+                    .sum::<usize>()
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, queries);
+criterion_main!(benches);
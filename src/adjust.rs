@@ -0,0 +1,200 @@
+//! Setting bond lengths and angles by moving one side of the structure as a rigid piece.
+//!
+//! Cutting the bond between two atoms splits the molecule in two. For a length, the side with
+//! fewer atoms is translated along the bond, so small groups move and the rest stays put; for
+//! an angle A–B–C, the side holding C turns about B. Bonds in rings cannot be split this way
+//! and are refused.
+
+use std::collections::HashSet;
+
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+use crate::transform::about;
+use crate::{AtomId, Command, Molecule};
+
+impl Molecule {
+    /// Atoms still connected to `start` once the bond between `start` and `cut` is ignored,
+    /// `start` included. `None` when `cut` is reached some other way, as in a ring.
+    pub fn side_of(&self, start: AtomId, cut: AtomId) -> Option<Vec<AtomId>> {
+        self.get_atom(start)?;
+        let mut side = vec![start];
+        let mut seen = HashSet::from([start]);
+        let mut index = 0;
+        while let Some(&current) = side.get(index) {
+            index += 1;
+            for neighbor in self.neighbors(current) {
+                if current == start && neighbor == cut {
+                    continue;
+                }
+                if neighbor == cut {
+                    return None;
+                }
+                if seen.insert(neighbor) {
+                    side.push(neighbor);
+                }
+            }
+        }
+        Some(side)
+    }
+}
+
+impl Command {
+    /// One step making the distance between bonded atoms `a` and `b` equal to `length`, by
+    /// moving the smaller side of the bond along it.
+    pub fn set_bond_length(
+        molecule: &Molecule,
+        a: AtomId,
+        b: AtomId,
+        length: f32,
+    ) -> Result<Command, String> {
+        if molecule.bond_between(a, b).is_none() {
+            return Err("atoms are not bonded".to_string());
+        }
+        if !(length > 0.0 && length.is_finite()) {
+            return Err(format!("invalid bond length {length}"));
+        }
+        let position = |id| {
+            molecule
+                .get_atom(id)
+                .map(|atom| Vec3::from_array(atom.position))
+        };
+        let (Some(pa), Some(pb)) = (position(a), position(b)) else {
+            return Err("atom not found".to_string());
+        };
+        let axis = (pb - pa)
+            .try_normalize()
+            .ok_or_else(|| "atoms overlap".to_string())?;
+        let ring = || "bond is in a ring".to_string();
+        let side_a = molecule.side_of(a, b).ok_or_else(ring)?;
+        let side_b = molecule.side_of(b, a).ok_or_else(ring)?;
+        let stretch = length - pa.distance(pb);
+        let (moving, delta) = if side_b.len() <= side_a.len() {
+            (side_b, axis * stretch)
+        } else {
+            (side_a, -axis * stretch)
+        };
+        Command::transform_atoms(molecule, &moving, Mat4::from_translation(delta))
+            .ok_or_else(|| "nothing to move".to_string())
+    }
+
+    /// One step making the angle `a`–`vertex`–`c` equal to `degrees`, by turning `c` and
+    /// everything beyond it about `vertex` in the plane of the three atoms.
+    pub fn set_angle(
+        molecule: &Molecule,
+        a: AtomId,
+        vertex: AtomId,
+        c: AtomId,
+        degrees: f32,
+    ) -> Result<Command, String> {
+        if !(0.0..=180.0).contains(&degrees) {
+            return Err(format!("invalid angle {degrees}"));
+        }
+        let moving = molecule
+            .side_of(c, vertex)
+            .filter(|side| !side.contains(&a))
+            .ok_or_else(|| "both ends are connected beyond the vertex".to_string())?;
+        let position = |id| {
+            molecule
+                .get_atom(id)
+                .map(|atom| Vec3::from_array(atom.position))
+        };
+        let (Some(pa), Some(center), Some(pc)) = (position(a), position(vertex), position(c))
+        else {
+            return Err("atom not found".to_string());
+        };
+        let (u, v) = (pa - center, pc - center);
+        if u.length_squared() == 0.0 || v.length_squared() == 0.0 {
+            return Err("angle undefined".to_string());
+        }
+        // atan2 stays accurate near straight angles, where acos does not.
+        let current = u.cross(v).length().atan2(u.dot(v)).to_degrees();
+        // Turning about u × v opens the angle; a straight angle has no plane, so any
+        // perpendicular will do.
+        let axis = u
+            .cross(v)
+            .try_normalize()
+            .unwrap_or_else(|| u.any_orthonormal_vector());
+        let turn = Quat::from_axis_angle(axis, (degrees - current).to_radians());
+        Command::transform_atoms(molecule, &moving, about(center, Mat3::from_quat(turn)))
+            .ok_or_else(|| "nothing to move".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::angle;
+    use crate::CommandHistory;
+
+    #[test]
+    fn stretches_the_smaller_side() {
+        let mut molecule = Molecule::new("propanol");
+        let ids: Vec<AtomId> = ["C", "C", "C", "O"]
+            .iter()
+            .enumerate()
+            .map(|(x, element)| {
+                molecule.insert_atom(element.to_string(), [x as f32 * 1.5, 0.0, 0.0])
+            })
+            .collect();
+        for pair in ids.windows(2) {
+            molecule.add_bond(pair[0], pair[1]).unwrap();
+        }
+        assert_eq!(molecule.side_of(ids[2], ids[1]), Some(vec![ids[2], ids[3]]));
+
+        let mut history = CommandHistory::new(8);
+        let command = Command::set_bond_length(&molecule, ids[3], ids[2], 1.43).unwrap();
+        history.execute(command, &mut molecule).unwrap();
+        assert_eq!(molecule.get_atom(ids[2]).unwrap().position, [3.0, 0.0, 0.0]);
+        let o = molecule.get_atom(ids[3]).unwrap().position;
+        assert!((o[0] - 4.43).abs() < 1e-5, "{o:?}");
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.get_atom(ids[3]).unwrap().position, [4.5, 0.0, 0.0]);
+
+        // Moving the first carbon takes nothing else with it.
+        let command = Command::set_bond_length(&molecule, ids[1], ids[0], 1.0).unwrap();
+        history.execute(command, &mut molecule).unwrap();
+        assert_eq!(molecule.get_atom(ids[0]).unwrap().position, [0.5, 0.0, 0.0]);
+        assert!(Command::set_bond_length(&molecule, ids[0], ids[3], 1.0).is_err());
+        assert!(Command::set_bond_length(&molecule, ids[0], ids[1], -1.0).is_err());
+
+        molecule.add_bond(ids[0], ids[3]).unwrap();
+        assert_eq!(molecule.side_of(ids[0], ids[1]), None);
+        let refused = Command::set_bond_length(&molecule, ids[0], ids[1], 1.5);
+        assert_eq!(refused.unwrap_err(), "bond is in a ring");
+    }
+
+    #[test]
+    fn turns_the_far_side_about_the_vertex() {
+        let mut molecule = Molecule::new("propanol");
+        let a = molecule.insert_atom("C".into(), [-1.5, 0.0, 0.0]);
+        let vertex = molecule.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let c = molecule.insert_atom("C".into(), [0.0, 1.5, 0.0]);
+        let o = molecule.insert_atom("O".into(), [0.0, 2.9, 0.0]);
+        for (x, y) in [(a, vertex), (vertex, c), (c, o)] {
+            molecule.add_bond(x, y).unwrap();
+        }
+
+        let mut history = CommandHistory::new(8);
+        let command = Command::set_angle(&molecule, a, vertex, c, 109.5).unwrap();
+        history.execute(command, &mut molecule).unwrap();
+        assert!((angle(&molecule, a, vertex, c).unwrap() - 109.5).abs() < 1e-3);
+        assert!((angle(&molecule, vertex, c, o).unwrap() - 180.0).abs() < 1e-2);
+        assert_eq!(molecule.get_atom(a).unwrap().position, [-1.5, 0.0, 0.0]);
+        let z = |id| molecule.get_atom(id).unwrap().position[2];
+        assert!(z(c).abs() < 1e-5 && z(o).abs() < 1e-5, "stays in plane");
+        assert_eq!(history.undo_len(), 1);
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.get_atom(o).unwrap().position, [0.0, 2.9, 0.0]);
+
+        let straight = Command::set_angle(&molecule, a, vertex, c, 180.0).unwrap();
+        history.execute(straight, &mut molecule).unwrap();
+        let opened = Command::set_angle(&molecule, a, vertex, c, 120.0).unwrap();
+        history.execute(opened, &mut molecule).unwrap();
+        let opened = angle(&molecule, a, vertex, c).unwrap();
+        assert!((opened - 120.0).abs() < 1e-3, "{opened}");
+
+        assert!(Command::set_angle(&molecule, a, vertex, c, 200.0).is_err());
+        molecule.add_bond(a, o).unwrap();
+        assert!(Command::set_angle(&molecule, a, vertex, c, 100.0).is_err());
+    }
+}
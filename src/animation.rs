@@ -0,0 +1,215 @@
+//! Camera animations for movies: keyframed cameras at chosen times, sampled into one camera
+//! per output frame, and full turns of the view.
+//!
+//! Between two keyframes the camera moves as in [`Camera::interpolate`] at constant speed, so
+//! a path through several keyframes flies on without stopping at each one. Frames are written
+//! as numbered PNGs, which [`ffmpeg_args`] turns into a video.
+
+use std::f32::consts::TAU;
+use std::path::{Path, PathBuf};
+
+use crate::camera::Camera;
+use crate::trajectory::frame_path;
+
+/// Output frame rate when none is chosen.
+pub const DEFAULT_FPS: u32 = 24;
+
+/// A camera to pass through `time` seconds into the animation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    pub time: f32,
+    pub camera: Camera,
+}
+
+/// Keyframes in time order, at most one per time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Adds `keyframe` in time order, replacing one already at the same time. Negative times
+    /// count as 0.
+    pub fn insert(&mut self, keyframe: Keyframe) {
+        let keyframe = Keyframe {
+            time: keyframe.time.max(0.0),
+            ..keyframe
+        };
+        match self
+            .keyframes
+            .binary_search_by(|probe| probe.time.total_cmp(&keyframe.time))
+        {
+            Ok(index) => self.keyframes[index] = keyframe,
+            Err(index) => self.keyframes.insert(index, keyframe),
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Keyframe> {
+        (index < self.keyframes.len()).then(|| self.keyframes.remove(index))
+    }
+
+    /// Time of the last keyframe; the animation starts at 0.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// The camera at `time`: held at the first keyframe before it and at the last after it.
+    pub fn camera_at(&self, time: f32) -> Option<Camera> {
+        let after = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+        match (after.checked_sub(1), self.keyframes.get(after)) {
+            (None, next) => next.map(|keyframe| keyframe.camera),
+            (Some(index), None) => Some(self.keyframes[index].camera),
+            (Some(index), Some(next)) => {
+                let previous = &self.keyframes[index];
+                let span = next.time - previous.time;
+                let t = (time - previous.time) / span.max(f32::EPSILON);
+                Some(previous.camera.interpolate(&next.camera, t))
+            }
+        }
+    }
+
+    /// One camera per frame at `fps`, from 0 up to and including [`CameraPath::duration`].
+    pub fn frames(&self, fps: u32) -> Vec<Camera> {
+        if self.keyframes.is_empty() {
+            return Vec::new();
+        }
+        let fps = fps.max(1) as f32;
+        let count = (self.duration() * fps).round() as usize + 1;
+        (0..count)
+            .filter_map(|frame| self.camera_at(frame as f32 / fps))
+            .collect()
+    }
+}
+
+/// `frames` cameras turning once around the view's vertical axis from `camera`, as in
+/// [`Camera::turn`]. The last frame stops one step short of the first, so the movie loops.
+pub fn turntable(camera: &Camera, frames: usize) -> Vec<Camera> {
+    (0..frames)
+        .map(|frame| {
+            let mut turned = *camera;
+            turned.turn(TAU * frame as f32 / frames as f32);
+            turned
+        })
+        .collect()
+}
+
+/// Arguments for `ffmpeg` encoding the frames numbered from 1 next to `base` (see
+/// [`frame_path`]) into an H.264 video at `fps`. The video goes next to the frames, named after
+/// `base` with an `.mp4` extension, and is returned with the arguments.
+pub fn ffmpeg_args(base: &Path, fps: u32) -> (Vec<String>, PathBuf) {
+    let pattern = frame_path(base, 1, "png")
+        .to_string_lossy()
+        .replace("_0001.png", "_%04d.png");
+    let video = base.with_extension("mp4");
+    let args = [
+        "-y",
+        "-framerate",
+        &fps.max(1).to_string(),
+        "-start_number",
+        "1",
+        "-i",
+        &pattern,
+        // Even dimensions and 4:2:0 chroma keep the video playable everywhere.
+        "-vf",
+        "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+        "-c:v",
+        "libx264",
+        "-pix_fmt",
+        "yuv420p",
+        &video.to_string_lossy(),
+    ]
+    .map(str::to_string)
+    .to_vec();
+    (args, video)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    fn at(time: f32, x: f32, distance: f32) -> Keyframe {
+        Keyframe {
+            time,
+            camera: Camera {
+                target: Vec3::new(x, 0.0, 0.0),
+                distance,
+                ..Camera::default()
+            },
+        }
+    }
+
+    #[test]
+    fn keyframes_stay_sorted_and_unique() {
+        let mut path = CameraPath::default();
+        path.insert(at(2.0, 2.0, 8.0));
+        path.insert(at(0.0, 0.0, 8.0));
+        path.insert(at(1.0, 1.0, 8.0));
+        path.insert(at(1.0, 5.0, 8.0));
+        path.insert(at(-3.0, 7.0, 8.0));
+        let times: Vec<f32> = path.keyframes().iter().map(|k| k.time).collect();
+        assert_eq!(times, [0.0, 1.0, 2.0]);
+        assert_eq!(path.keyframes()[0].camera.target.x, 7.0);
+        assert_eq!(path.keyframes()[1].camera.target.x, 5.0);
+        assert_eq!(path.duration(), 2.0);
+        assert_eq!(path.remove(1).map(|k| k.time), Some(1.0));
+        assert_eq!(path.remove(5), None);
+    }
+
+    #[test]
+    fn samples_the_path_at_a_constant_rate() {
+        let mut path = CameraPath::default();
+        assert!(path.frames(DEFAULT_FPS).is_empty());
+        path.insert(at(1.0, 0.0, 10.0));
+        path.insert(at(3.0, 4.0, 20.0));
+        let middle = path.camera_at(2.0).unwrap();
+        assert!((middle.target.x - 2.0).abs() < 1e-5);
+        assert!((middle.distance - 15.0).abs() < 1e-5);
+        assert_eq!(path.camera_at(0.0).unwrap().target.x, 0.0);
+        assert_eq!(path.camera_at(9.0).unwrap().target.x, 4.0);
+
+        let frames = path.frames(2);
+        assert_eq!(frames.len(), 7);
+        let xs: Vec<f32> = frames.iter().map(|camera| camera.target.x).collect();
+        for (x, expected) in xs.iter().zip([0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 4.0]) {
+            assert!((x - expected).abs() < 1e-5, "{xs:?}");
+        }
+    }
+
+    #[test]
+    fn turntable_makes_one_looping_turn() {
+        let camera = Camera::default();
+        let frames = turntable(&camera, 4);
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0], camera);
+        for (index, frame) in frames.iter().enumerate() {
+            let turned = frame.yaw - camera.yaw;
+            assert!((turned - TAU * index as f32 / 4.0).abs() < 1e-5, "{turned}");
+            assert_eq!((frame.pitch, frame.target), (camera.pitch, camera.target));
+        }
+        assert!(turntable(&camera, 0).is_empty());
+    }
+
+    #[test]
+    fn encodes_the_numbered_frames_next_to_them() {
+        let (args, video) = ffmpeg_args(Path::new("out/spin.png"), 30);
+        assert_eq!(video, Path::new("out/spin.mp4"));
+        let input = args.iter().position(|arg| arg == "-i").unwrap();
+        assert_eq!(args[input + 1], "out/spin_%04d.png");
+        assert_eq!(
+            args[args.iter().position(|arg| arg == "-framerate").unwrap() + 1],
+            "30"
+        );
+        assert_eq!(args.last().unwrap(), "out/spin.mp4");
+    }
+}
@@ -0,0 +1,271 @@
+//! Mechanism drawings on the 3D structure: curly arrows for electron flow, straight arrows for
+//! the direction of a reaction, and partial bonds for transition states.
+//!
+//! Annotations refer to atoms by id, so they follow the atoms as they move and are kept (but
+//! not drawn) while their atoms are deleted. They are drawn over the view in screen space:
+//! [`curve`] and [`arrowhead`] give the arrow shapes and [`bond_strokes`] the lines of a
+//! partial bond.
+
+use glam::{Vec2, Vec3};
+
+use crate::{AtomId, Molecule};
+
+/// How far a curly arrow bows out from the straight line, as a fraction of its length.
+const CURL: f32 = 0.35;
+/// Half-angle between an arrowhead's barbs and its shaft, in radians.
+const BARB_ANGLE: f32 = 0.45;
+
+/// Where an arrow starts or ends: on an atom (a lone pair or charge), or halfway between two
+/// atoms (a bond, or one being formed).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Site {
+    Atom(AtomId),
+    Between(AtomId, AtomId),
+}
+
+impl Site {
+    pub fn position(&self, molecule: &Molecule) -> Option<Vec3> {
+        let at = |id| Some(Vec3::from_array(molecule.get_atom(id)?.position));
+        match *self {
+            Site::Atom(id) => at(id),
+            Site::Between(a, b) => Some((at(a)? + at(b)?) * 0.5),
+        }
+    }
+
+    /// Written as `3` or `3-4`.
+    pub fn label(&self) -> String {
+        match self {
+            Site::Atom(id) => id.value().to_string(),
+            Site::Between(a, b) => format!("{}-{}", a.value(), b.value()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let id = |text: &str| text.parse().ok().map(AtomId::new);
+        match text.split_once('-') {
+            Some((a, b)) => Some(Site::Between(id(a)?, id(b)?)),
+            None => Some(Site::Atom(id(text)?)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArrowKind {
+    /// Electron pair movement, with a full head.
+    #[default]
+    Curly,
+    /// Single electron movement, with a half head.
+    FishHook,
+    /// Reaction direction.
+    Straight,
+}
+
+impl ArrowKind {
+    pub const ALL: [ArrowKind; 3] = [ArrowKind::Curly, ArrowKind::FishHook, ArrowKind::Straight];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ArrowKind::Curly => "Curly",
+            ArrowKind::FishHook => "Fish-hook",
+            ArrowKind::Straight => "Straight",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.label().eq_ignore_ascii_case(text))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arrow {
+    pub kind: ArrowKind,
+    pub from: Site,
+    pub to: Site,
+}
+
+impl Arrow {
+    /// An arrow between sites given by atoms picked in order: one atom for an atom end, two
+    /// for a bond end, the start's first. `None` unless exactly that many atoms were picked.
+    pub fn from_picks(
+        kind: ArrowKind,
+        atoms: &[AtomId],
+        from_bond: bool,
+        to_bond: bool,
+    ) -> Option<Arrow> {
+        let site = |atoms: &[AtomId]| match *atoms {
+            [atom] => Some(Site::Atom(atom)),
+            [a, b] => Some(Site::Between(a, b)),
+            _ => None,
+        };
+        let split = 1 + usize::from(from_bond);
+        if atoms.len() != split + 1 + usize::from(to_bond) {
+            return None;
+        }
+        Some(Arrow {
+            kind,
+            from: site(&atoms[..split])?,
+            to: site(&atoms[split..])?,
+        })
+    }
+}
+
+/// A bond drawn with a fractional order between two atoms, bonded or not: 0.5 for a bond
+/// half made or broken, 1.5 for a delocalized one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PartialBond {
+    pub a: AtomId,
+    pub b: AtomId,
+    pub order: f32,
+}
+
+/// The annotations of a structure, kept with the session.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Annotations {
+    pub arrows: Vec<Arrow>,
+    pub partial_bonds: Vec<PartialBond>,
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        self.arrows.is_empty() && self.partial_bonds.is_empty()
+    }
+
+    /// Adds a partial bond, replacing any already drawn between the same atoms.
+    pub fn set_partial_bond(&mut self, a: AtomId, b: AtomId, order: f32) {
+        self.partial_bonds.retain(|bond| !same_pair(bond, a, b));
+        self.partial_bonds.push(PartialBond { a, b, order });
+    }
+
+    /// End positions of the arrows whose atoms are all in `molecule`.
+    pub fn arrow_ends(&self, molecule: &Molecule) -> Vec<(Arrow, Vec3, Vec3)> {
+        self.arrows
+            .iter()
+            .filter_map(|arrow| {
+                let from = arrow.from.position(molecule)?;
+                let to = arrow.to.position(molecule)?;
+                Some((*arrow, from, to))
+            })
+            .collect()
+    }
+
+    /// End positions of the partial bonds whose atoms are both in `molecule`.
+    pub fn partial_bond_ends(&self, molecule: &Molecule) -> Vec<(PartialBond, Vec3, Vec3)> {
+        self.partial_bonds
+            .iter()
+            .filter_map(|bond| {
+                let a = Site::Atom(bond.a).position(molecule)?;
+                let b = Site::Atom(bond.b).position(molecule)?;
+                Some((*bond, a, b))
+            })
+            .collect()
+    }
+}
+
+fn same_pair(bond: &PartialBond, a: AtomId, b: AtomId) -> bool {
+    (bond.a, bond.b) == (a, b) || (bond.a, bond.b) == (b, a)
+}
+
+/// Points along an arrow's shaft from `from` to `to`. Straight arrows are a single segment;
+/// curly ones bow out towards `(to - from).perp()` as a quadratic Bézier curve.
+pub fn curve(kind: ArrowKind, from: Vec2, to: Vec2, segments: usize) -> Vec<Vec2> {
+    if kind == ArrowKind::Straight {
+        return vec![from, to];
+    }
+    let control = (from + to) * 0.5 + (to - from).perp() * CURL;
+    let segments = segments.max(1);
+    (0..=segments)
+        .map(|step| {
+            let t = step as f32 / segments as f32;
+            from.lerp(control, t).lerp(control.lerp(to, t), t)
+        })
+        .collect()
+}
+
+/// The head at the end of `shaft` as a polyline through the tip, `size` long: both barbs for
+/// a full head, the outer one for a fish-hook.
+pub fn arrowhead(kind: ArrowKind, shaft: &[Vec2], size: f32) -> Vec<Vec2> {
+    let [.., before, tip] = shaft else {
+        return Vec::new();
+    };
+    let Some(back) = (*before - *tip).try_normalize() else {
+        return Vec::new();
+    };
+    let barb = |angle: f32| *tip + Vec2::from_angle(angle).rotate(back) * size;
+    match kind {
+        ArrowKind::FishHook => vec![barb(-BARB_ANGLE), *tip],
+        _ => vec![barb(-BARB_ANGLE), *tip, barb(BARB_ANGLE)],
+    }
+}
+
+/// Lines drawing a bond of `order`, as offsets across the bond in line spacings and whether
+/// each is dashed: one solid line per whole order, then a dashed one for any fraction.
+pub fn bond_strokes(order: f32) -> Vec<(f32, bool)> {
+    let whole = order.max(0.0).floor() as usize;
+    let dashed = order - whole as f32 > 0.05;
+    let count = whole + usize::from(dashed);
+    (0..count)
+        .map(|index| {
+            let offset = index as f32 - (count as f32 - 1.0) * 0.5;
+            (offset, index == whole)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sites_follow_their_atoms_and_round_trip() {
+        let mut molecule = Molecule::new("hcl");
+        let h = molecule.insert_atom("H".into(), [0.0; 3]);
+        let cl = molecule.insert_atom("Cl".into(), [2.0, 0.0, 0.0]);
+        let bond = Site::Between(h, cl);
+        assert_eq!(bond.position(&molecule), Some(Vec3::X));
+        assert_eq!(Site::parse(&bond.label()), Some(bond));
+        assert_eq!(Site::parse("7"), Some(Site::Atom(AtomId::new(7))));
+        assert_eq!(Site::parse("7-"), None);
+
+        let picked = Arrow::from_picks(ArrowKind::Curly, &[h, cl, cl], true, false).unwrap();
+        assert_eq!((picked.from, picked.to), (bond, Site::Atom(cl)));
+        assert!(Arrow::from_picks(ArrowKind::Curly, &[h, cl], true, false).is_none());
+        let mut annotations = Annotations::default();
+        annotations.arrows.push(picked);
+        annotations.set_partial_bond(h, cl, 0.5);
+        annotations.set_partial_bond(cl, h, 1.5);
+        assert_eq!(annotations.partial_bonds.len(), 1);
+        molecule.set_atom_position(cl, [4.0, 0.0, 0.0]);
+        assert_eq!(annotations.arrow_ends(&molecule)[0].2, Vec3::X * 4.0);
+        molecule.remove_atom(h);
+        assert!(annotations.arrow_ends(&molecule).is_empty());
+        assert!(annotations.partial_bond_ends(&molecule).is_empty());
+    }
+
+    #[test]
+    fn arrows_curl_aside_and_end_in_a_head() {
+        let (from, to) = (Vec2::ZERO, Vec2::new(10.0, 0.0));
+        assert_eq!(curve(ArrowKind::Straight, from, to, 8), [from, to]);
+        let shaft = curve(ArrowKind::Curly, from, to, 8);
+        assert_eq!(shaft.len(), 9);
+        assert_eq!((shaft[0], shaft[8]), (from, to));
+        assert!(shaft[4].y > 1.0, "{:?}", shaft[4]);
+
+        let head = arrowhead(ArrowKind::Curly, &[from, to], 2.0);
+        assert_eq!(head.len(), 3);
+        assert_eq!(head[1], to);
+        assert!(head[0].x < to.x && head[2].x < to.x);
+        assert!((head[0].distance(to) - 2.0).abs() < 1e-5);
+        assert_eq!(arrowhead(ArrowKind::FishHook, &shaft, 2.0).len(), 2);
+        assert!(arrowhead(ArrowKind::Curly, &[to], 2.0).is_empty());
+    }
+
+    #[test]
+    fn partial_bonds_add_a_dashed_line() {
+        assert_eq!(bond_strokes(0.5), [(0.0, true)]);
+        assert_eq!(bond_strokes(1.0), [(0.0, false)]);
+        assert_eq!(bond_strokes(1.5), [(-0.5, false), (0.5, true)]);
+        assert_eq!(bond_strokes(2.5).len(), 3);
+    }
+}
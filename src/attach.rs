@@ -0,0 +1,284 @@
+//! Attaching a fragment to an atom: its first atom is bonded along the anchor's open direction
+//! and the rest is turned about the new bond to the least crowded rotamer.
+
+use std::f32::consts::TAU;
+
+use glam::{Quat, Vec3};
+
+use crate::elements::{normalize_symbol, vdw_radius};
+use crate::hydrogens::{bond_length, hydrogen_positions};
+use crate::{AtomId, Command, Molecule};
+
+/// Rotamers tried about the new bond, evenly spaced over a full turn.
+const ROTAMER_STEPS: usize = 36;
+/// Atoms farther than this, in Å, from every fragment atom barely add to the crowding and
+/// are skipped.
+const CROWDING_RANGE: f32 = 8.0;
+/// Approximate X–H bond length taken off each side when estimating a heavy-atom bond.
+const HYDROGEN_RADIUS: f32 = 0.31;
+
+/// A fragment placed for attachment by [`place_fragment`].
+#[derive(Debug, Clone)]
+pub struct Placement {
+    /// The fragment moved into place; its first atom is the one to bond to the anchor.
+    pub fragment: Molecule,
+    /// Terminal hydrogen on the anchor whose place the fragment takes, when the anchor had no
+    /// open valence left.
+    pub replaced: Option<AtomId>,
+}
+
+impl Command {
+    /// One composite step that merges `fragment` placed by [`place_fragment`], bonds its first
+    /// atom to `anchor` and deletes the hydrogen it replaces. `None` for an empty fragment or a
+    /// missing anchor.
+    pub fn attach_fragment(
+        molecule: &Molecule,
+        anchor: AtomId,
+        fragment: &Molecule,
+    ) -> Option<Command> {
+        let placement = place_fragment(molecule, anchor, fragment)?;
+        // The merge inserts the fragment in order, so its first atom takes the next free id.
+        let head = AtomId::new(molecule.next_atom_id);
+        let mut commands: Vec<Command> = placement
+            .replaced
+            .map(|atom_id| Command::DeleteAtom {
+                atom_id,
+                removed: None,
+            })
+            .into_iter()
+            .collect();
+        commands.push(Command::Merge {
+            fragment: Box::new(placement.fragment),
+            mapping: None,
+        });
+        commands.push(Command::AddBond {
+            atom_a: anchor,
+            atom_b: head,
+            bond_id: None,
+        });
+        Some(Command::Composite { commands })
+    }
+
+    /// One step growing a new `element` atom from `anchor` at idealized geometry: tetrahedral,
+    /// trigonal or linear from the anchor's bonds, in place of a terminal hydrogen when the
+    /// anchor has no open valence. `None` for a missing anchor.
+    pub fn sprout(molecule: &Molecule, anchor: AtomId, element: &str) -> Option<Command> {
+        let mut atom = Molecule::new(element);
+        atom.insert_atom(element.to_string(), [0.0; 3]);
+        Command::attach_fragment(molecule, anchor, &atom)
+    }
+}
+
+/// Moves `fragment` so its first atom sits one single-bond length from `anchor` along the
+/// anchor's open direction, with the first atom's own open direction pointing back at the
+/// anchor, then turns it about that bond to the rotamer with the fewest close contacts. A
+/// saturated first atom gives up one terminal hydrogen for the bond.
+pub fn place_fragment(
+    molecule: &Molecule,
+    anchor: AtomId,
+    fragment: &Molecule,
+) -> Option<Placement> {
+    let anchor_atom = molecule.get_atom(anchor)?;
+    let head = fragment.atoms_in_order().next()?;
+    let center = Vec3::from_array(anchor_atom.position);
+    let (outward, replaced) = open_direction(molecule, anchor);
+    let (inward, dropped) = open_direction(fragment, head.id);
+    let origin = Vec3::from_array(head.position);
+    let target = center + outward * single_bond_length(&anchor_atom.element, &head.element);
+    let align = Quat::from_rotation_arc(inward, -outward);
+    let offsets: Vec<(AtomId, Vec3)> = fragment
+        .atoms_in_order()
+        .filter(|atom| Some(atom.id) != dropped)
+        .map(|atom| (atom.id, align * (Vec3::from_array(atom.position) - origin)))
+        .collect();
+    let reach = offsets
+        .iter()
+        .map(|(_, offset)| offset.length())
+        .fold(0.0, f32::max);
+    let surroundings: Vec<(Vec3, f32)> = molecule
+        .atoms_within(target.to_array(), reach + CROWDING_RANGE)
+        .into_iter()
+        .filter(|&id| id != anchor && Some(id) != replaced)
+        .filter_map(|id| molecule.get_atom(id))
+        .map(|atom| (Vec3::from_array(atom.position), vdw_radius(&atom.element)))
+        .collect();
+    let rotamer = (0..ROTAMER_STEPS)
+        .map(|step| Quat::from_axis_angle(outward, step as f32 * TAU / ROTAMER_STEPS as f32))
+        .map(|turn| {
+            let crowding: f32 = offsets
+                .iter()
+                .filter(|(id, _)| *id != head.id)
+                .map(|(id, offset)| {
+                    let position = target + turn * *offset;
+                    let radius = fragment
+                        .get_atom(*id)
+                        .map_or(0.0, |atom| vdw_radius(&atom.element));
+                    crowding(position, radius, &surroundings)
+                })
+                .sum();
+            (turn, crowding)
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(Quat::IDENTITY, |(turn, _)| turn);
+    let mut placed = fragment.clone();
+    if let Some(hydrogen) = dropped {
+        placed.remove_atom(hydrogen);
+    }
+    for (id, offset) in offsets {
+        placed.set_atom_position(id, (target + rotamer * offset).to_array());
+    }
+    Some(Placement {
+        fragment: placed,
+        replaced,
+    })
+}
+
+/// Unit direction from `anchor` for a new bond, and the hydrogen it displaces. An open
+/// valence is used first, then a terminal hydrogen, then the side away from all neighbors.
+fn open_direction(molecule: &Molecule, anchor: AtomId) -> (Vec3, Option<AtomId>) {
+    let Some(atom) = molecule.get_atom(anchor) else {
+        return (Vec3::X, None);
+    };
+    let center = Vec3::from_array(atom.position);
+    if let Some(direction) = hydrogen_positions(molecule, anchor)
+        .first()
+        .and_then(|position| (*position - center).try_normalize())
+    {
+        return (direction, None);
+    }
+    let neighbors: Vec<(AtomId, Vec3)> = molecule
+        .neighbors(anchor)
+        .into_iter()
+        .filter_map(|id| Some((id, Vec3::from_array(molecule.get_atom(id)?.position))))
+        .collect();
+    let hydrogen = neighbors.iter().find(|(id, position)| {
+        molecule
+            .get_atom(*id)
+            .is_some_and(|atom| normalize_symbol(&atom.element) == "H")
+            && molecule.neighbors(*id).len() == 1
+            && *position != center
+    });
+    if let Some((id, position)) = hydrogen {
+        return ((*position - center).normalize(), Some(*id));
+    }
+    let away: Vec3 = neighbors
+        .iter()
+        .map(|(_, position)| (center - *position).normalize_or_zero())
+        .sum();
+    (away.try_normalize().unwrap_or(Vec3::X), None)
+}
+
+/// Estimated single-bond length between two elements, from their bond lengths to hydrogen.
+pub(crate) fn single_bond_length(a: &str, b: &str) -> f32 {
+    match (normalize_symbol(a).as_str(), normalize_symbol(b).as_str()) {
+        ("H", other) | (other, "H") => bond_length(other),
+        _ => bond_length(a) + bond_length(b) - 2.0 * HYDROGEN_RADIUS,
+    }
+}
+
+/// Steep repulsion summed over `surroundings`, each given as position and van der Waals
+/// radius.
+fn crowding(position: Vec3, radius: f32, surroundings: &[(Vec3, f32)]) -> f32 {
+    surroundings
+        .iter()
+        .map(|(other, other_radius)| {
+            let contact = 0.5 * (radius + other_radius);
+            let distance = position.distance(*other).max(1e-3);
+            (contact / distance).powi(6)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{angle, dihedral};
+    use crate::CommandHistory;
+
+    fn ethyl() -> Molecule {
+        let mut fragment = Molecule::new("ethyl");
+        let a = fragment.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let b = fragment.insert_atom("C".into(), [0.0, 1.54, 0.0]);
+        fragment.add_bond(a, b).unwrap();
+        fragment
+    }
+
+    #[test]
+    fn bonds_along_open_valence_in_least_crowded_rotamer() {
+        let mut molecule = Molecule::new("ethane");
+        let a = molecule.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let b = molecule.insert_atom("C".into(), [1.54, 0.0, 0.0]);
+        molecule.add_bond(a, b).unwrap();
+        let mut history = CommandHistory::new(10);
+        let command = Command::attach_fragment(&molecule, b, &ethyl()).unwrap();
+        history.execute(command, &mut molecule).unwrap();
+        assert_eq!(molecule.formula(), "C4");
+        assert_eq!(history.undo_len(), 1);
+
+        let head = molecule
+            .neighbors(b)
+            .into_iter()
+            .find(|&id| id != a)
+            .unwrap();
+        let tail = molecule
+            .neighbors(head)
+            .into_iter()
+            .find(|&id| id != b)
+            .unwrap();
+        let length = Vec3::from(molecule.get_atom(b).unwrap().position)
+            .distance(Vec3::from(molecule.get_atom(head).unwrap().position));
+        assert!((length - 1.56).abs() < 0.01, "{length}");
+        let bend = angle(&molecule, a, b, head).unwrap();
+        assert!((bend - 109.47).abs() < 0.5, "{bend}");
+        let torsion = dihedral(&molecule, a, b, head, tail).unwrap();
+        assert!(torsion.abs() > 170.0, "{torsion}");
+
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.atom_count(), 2);
+        assert_eq!(molecule.bonds().count(), 1);
+    }
+
+    #[test]
+    fn replaces_a_terminal_hydrogen_on_a_saturated_anchor() {
+        let mut molecule = Molecule::new("methane");
+        let c = molecule.insert_atom("C".into(), [0.0; 3]);
+        molecule.add_hydrogens().unwrap();
+        let mut ethane = ethyl();
+        ethane.add_hydrogens().unwrap();
+        let command = Command::attach_fragment(&molecule, c, &ethane).unwrap();
+        let mut history = CommandHistory::new(10);
+        history.execute(command, &mut molecule).unwrap();
+        assert_eq!(molecule.formula(), "C3H8");
+        let neighbors = molecule.neighbors(c);
+        assert_eq!(neighbors.len(), 4);
+        for pair in [(0, 1), (1, 2), (2, 3), (0, 3)] {
+            let value = angle(&molecule, neighbors[pair.0], c, neighbors[pair.1]).unwrap();
+            assert!((value - 109.47).abs() < 0.5, "{value}");
+        }
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.formula(), "CH4");
+    }
+
+    #[test]
+    fn sprouts_follow_the_anchor_hybridization() {
+        let mut molecule = Molecule::new("formaldehyde");
+        let c = molecule.insert_atom("C".into(), [0.0; 3]);
+        let o = molecule.insert_atom("O".into(), [1.21, 0.0, 0.0]);
+        let double = molecule.add_bond(c, o).unwrap();
+        molecule.set_bond_order(double, 2);
+        let mut history = CommandHistory::new(10);
+        for _ in 0..2 {
+            let command = Command::sprout(&molecule, c, "H").unwrap();
+            history.execute(command, &mut molecule).unwrap();
+        }
+        assert_eq!(molecule.formula(), "CH2O");
+        let grown = molecule.neighbors(c);
+        for &h in grown.iter().filter(|&&id| id != o) {
+            let value = angle(&molecule, o, c, h).unwrap();
+            assert!((value - 120.0).abs() < 0.5, "{value}");
+            let length = Vec3::from(molecule.get_atom(h).unwrap().position).length();
+            assert!((length - 1.09).abs() < 0.01, "{length}");
+        }
+        assert!(Command::sprout(&molecule, AtomId::new(99), "C").is_none());
+    }
+}
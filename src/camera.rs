@@ -0,0 +1,403 @@
+//! The view camera shared by the window, `--camera`, session files and saved views.
+//!
+//! Orbit and fly modes keep a yaw/pitch view direction around world +Y; the trackball keeps a
+//! free orientation that can roll. Every mode looks along [`Camera::eye_direction`] reversed.
+
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3};
+
+use crate::geometry::{self, BoundingBox};
+use crate::{AtomId, Molecule};
+
+pub const FIELD_OF_VIEW_DEGREES: f32 = 45.0;
+pub const NEAR_PLANE: f32 = 0.1;
+pub const FAR_PLANE: f32 = 200.0;
+/// Orbit pitch stops this far short of the poles, in radians.
+pub const MAX_PITCH: f32 = 1.4;
+/// Range of the orbit distance, in Å.
+pub const MIN_DISTANCE: f32 = 2.0;
+pub const MAX_DISTANCE: f32 = 60.0;
+/// Radians per pixel dragged.
+const ROTATE_SPEED: f32 = 0.01;
+/// Closest the fly camera may get to an atom center, in Å.
+const FLY_CLEARANCE: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CameraMode {
+    /// Yaw/pitch around a fixed world up axis; pitch is clamped short of the poles.
+    Orbit,
+    /// Free quaternion rotation that can pass over the poles and roll.
+    Trackball,
+    /// First-person WASD movement with mouse look; yaw/pitch give the view direction.
+    Fly,
+}
+
+impl CameraMode {
+    pub const ALL: [CameraMode; 3] = [CameraMode::Orbit, CameraMode::Trackball, CameraMode::Fly];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CameraMode::Orbit => "Orbit",
+            CameraMode::Trackball => "Trackball",
+            CameraMode::Fly => "Fly",
+        }
+    }
+}
+
+/// A named viewpoint to return to.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SavedView {
+    pub name: String,
+    pub camera: Camera,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Camera {
+    pub mode: CameraMode,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Trackball frame: maps camera-local axes (+Z pointing from the target to the eye) to
+    /// world space.
+    pub orientation: Quat,
+    pub distance: f32,
+    pub target: Vec3,
+    /// Fly mode eye position; the other modes derive it from the target.
+    pub eye: Vec3,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::Orbit,
+            yaw: 0.8,
+            pitch: 0.3,
+            orientation: Quat::IDENTITY,
+            distance: 8.0,
+            target: Vec3::ZERO,
+            eye: Vec3::ZERO,
+        }
+    }
+}
+
+impl Camera {
+    pub fn position(&self) -> Vec3 {
+        match self.mode {
+            CameraMode::Fly => self.eye,
+            _ => self.target + self.eye_direction() * self.distance,
+        }
+    }
+
+    /// Unit vector from the point in view back towards the eye.
+    pub fn eye_direction(&self) -> Vec3 {
+        match self.mode {
+            CameraMode::Orbit | CameraMode::Fly => {
+                let (yaw_sin, yaw_cos) = self.yaw.sin_cos();
+                let (pitch_sin, pitch_cos) = self.pitch.sin_cos();
+                Vec3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin)
+            }
+            CameraMode::Trackball => self.orientation * Vec3::Z,
+        }
+    }
+
+    pub fn up(&self) -> Vec3 {
+        match self.mode {
+            CameraMode::Orbit | CameraMode::Fly => Vec3::Y,
+            CameraMode::Trackball => self.orientation * Vec3::Y,
+        }
+    }
+
+    /// The point the view turns around: the target, or in fly mode the point `distance` ahead
+    /// of the eye.
+    pub fn pivot(&self) -> Vec3 {
+        match self.mode {
+            CameraMode::Fly => self.eye - self.eye_direction() * self.distance,
+            _ => self.target,
+        }
+    }
+
+    pub fn view_proj(&self, aspect: f32) -> Mat4 {
+        let position = self.position();
+        let view = Mat4::look_at_rh(position, position - self.eye_direction(), self.up());
+        let proj = Mat4::perspective_rh(
+            FIELD_OF_VIEW_DEGREES.to_radians(),
+            aspect,
+            NEAR_PLANE,
+            FAR_PLANE,
+        );
+        proj * view
+    }
+
+    /// Rotates for a drag of `delta` pixels.
+    pub fn rotate(&mut self, delta: Vec2) {
+        match self.mode {
+            CameraMode::Orbit => {
+                self.yaw -= delta.x * ROTATE_SPEED;
+                self.pitch = (self.pitch - delta.y * ROTATE_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+            }
+            CameraMode::Trackball => {
+                // Spin about the camera-local axis perpendicular to the drag so the molecule
+                // follows the cursor.
+                let Some(axis) = Vec3::new(delta.y, delta.x, 0.0).try_normalize() else {
+                    return;
+                };
+                let spin = Quat::from_axis_angle(axis, -delta.length() * ROTATE_SPEED);
+                self.orientation = (self.orientation * spin).normalize();
+            }
+            CameraMode::Fly => {
+                // Mouse look turns the view with the drag rather than orbiting against it.
+                self.yaw += delta.x * ROTATE_SPEED;
+                self.pitch = (self.pitch + delta.y * ROTATE_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+            }
+        }
+    }
+
+    /// Spins the view about the screen's vertical axis by `angle` radians, as on a turntable.
+    /// Fly mode looks around instead.
+    pub fn turn(&mut self, angle: f32) {
+        match self.mode {
+            CameraMode::Orbit | CameraMode::Fly => self.yaw += angle,
+            CameraMode::Trackball => {
+                self.orientation = (self.orientation * Quat::from_rotation_y(angle)).normalize();
+            }
+        }
+    }
+
+    /// Moves in by the fraction `delta` of the distance, or out when negative.
+    pub fn zoom(&mut self, delta: f32) {
+        self.distance = (self.distance * (1.0 - delta)).clamp(MIN_DISTANCE, MAX_DISTANCE);
+    }
+
+    /// Sets the orbit angles and distance, keeping the target and the current mode.
+    pub fn set_orbit(&mut self, yaw: f32, pitch: f32, distance: f32) {
+        let mode = self.mode;
+        *self = Camera {
+            mode: CameraMode::Orbit,
+            yaw,
+            pitch: pitch.clamp(-MAX_PITCH, MAX_PITCH),
+            distance: distance.clamp(MIN_DISTANCE, MAX_DISTANCE),
+            target: self.pivot(),
+            ..*self
+        };
+        self.set_mode(mode);
+    }
+
+    /// Switches mode while keeping the current eye position and view direction; roll is
+    /// dropped when leaving the trackball, and leaving fly mode orbits the point `distance`
+    /// ahead of the eye.
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        let position = self.position();
+        let direction = self.eye_direction();
+        self.target = self.pivot();
+        self.mode = mode;
+        match mode {
+            CameraMode::Orbit | CameraMode::Fly => {
+                self.pitch = direction
+                    .y
+                    .clamp(-1.0, 1.0)
+                    .asin()
+                    .clamp(-MAX_PITCH, MAX_PITCH);
+                self.yaw = direction.z.atan2(direction.x);
+                self.eye = position;
+            }
+            CameraMode::Trackball => self.orientation = upright_orientation(direction),
+        }
+    }
+
+    /// Fly mode movement: `forward`, `right` and `up` are in -1..=1 and the move is `step`
+    /// long. Each axis is tried on its own when the full move is blocked, so the camera
+    /// slides along atoms instead of stopping dead.
+    pub fn fly(
+        &mut self,
+        forward: f32,
+        right: f32,
+        up: f32,
+        step: f32,
+        molecule: Option<&Molecule>,
+    ) {
+        let ahead = -self.eye_direction();
+        let side = ahead.cross(Vec3::Y).normalize_or_zero();
+        let moves = [ahead * forward, side * right, Vec3::Y * up];
+        let total: Vec3 = moves.iter().sum();
+        if total.length_squared() <= f32::EPSILON {
+            return;
+        }
+        let scale = step / total.length();
+        let blocked = |from: Vec3, to: Vec3| {
+            molecule.is_some_and(|molecule| {
+                let inside = |point: Vec3| {
+                    molecule.atoms_in_order().any(|atom| {
+                        Vec3::from_array(atom.position).distance_squared(point)
+                            < FLY_CLEARANCE * FLY_CLEARANCE
+                    })
+                };
+                // Never trap a camera that already starts inside an atom.
+                inside(to) && !inside(from)
+            })
+        };
+        let full = self.eye + total * scale;
+        if !blocked(self.eye, full) {
+            self.eye = full;
+            return;
+        }
+        for part in moves {
+            let next = self.eye + part * scale;
+            if !blocked(self.eye, next) {
+                self.eye = next;
+            }
+        }
+    }
+
+    /// Removes trackball roll so world +Y points up on screen again.
+    pub fn reset_up(&mut self) {
+        if self.mode == CameraMode::Trackball {
+            self.orientation = upright_orientation(self.eye_direction());
+        }
+    }
+
+    /// Centers on `center` and backs off until a sphere of `radius` fills the view height,
+    /// keeping the view direction.
+    pub fn look_at_sphere(&mut self, center: Vec3, radius: f32) {
+        self.target = center;
+        self.distance = (radius / (FIELD_OF_VIEW_DEGREES.to_radians() * 0.5).sin())
+            .clamp(MIN_DISTANCE, MAX_DISTANCE);
+        if self.mode == CameraMode::Fly {
+            self.eye = self.target + self.eye_direction() * self.distance;
+        }
+    }
+
+    /// Frames every atom, with `padding` Å added around the atom centers. Returns `false`, and
+    /// leaves the camera alone, for an empty molecule.
+    pub fn fit(&mut self, molecule: &Molecule, padding: f32) -> bool {
+        let Some(bounds) = geometry::bounding_box(molecule) else {
+            return false;
+        };
+        self.look_at_box(&bounds, padding);
+        true
+    }
+
+    /// Frames `atoms` like [`Camera::fit`]; ids not in `molecule` are ignored.
+    pub fn frame(&mut self, molecule: &Molecule, atoms: &[AtomId], padding: f32) -> bool {
+        let mut positions = atoms
+            .iter()
+            .filter_map(|&id| Some(Vec3::from_array(molecule.get_atom(id)?.position)));
+        let Some(first) = positions.next() else {
+            return false;
+        };
+        let (min, max) = positions.fold((first, first), |(min, max), position| {
+            (min.min(position), max.max(position))
+        });
+        let bounds = BoundingBox {
+            min: min.to_array(),
+            max: max.to_array(),
+        };
+        self.look_at_box(&bounds, padding);
+        true
+    }
+
+    fn look_at_box(&mut self, bounds: &BoundingBox, padding: f32) {
+        self.look_at_sphere(Vec3::from_array(bounds.center()), bounds.radius() + padding);
+    }
+
+    /// The view `t` of the way from `self` to `other`: the pivot and distance move in a
+    /// straight line and the view direction turns along the shortest arc. The result keeps
+    /// `self`'s mode until `t` reaches 1.
+    pub fn interpolate(&self, other: &Camera, t: f32) -> Camera {
+        if t <= 0.0 {
+            return *self;
+        }
+        if t >= 1.0 {
+            return *other;
+        }
+        let mut camera = Camera {
+            mode: CameraMode::Trackball,
+            orientation: self.frame_orientation().slerp(other.frame_orientation(), t),
+            distance: self.distance + (other.distance - self.distance) * t,
+            target: self.pivot().lerp(other.pivot(), t),
+            ..*self
+        };
+        camera.set_mode(self.mode);
+        camera
+    }
+
+    /// The camera frame as in [`Camera::orientation`], for any mode.
+    fn frame_orientation(&self) -> Quat {
+        match self.mode {
+            CameraMode::Trackball => self.orientation,
+            CameraMode::Orbit | CameraMode::Fly => upright_orientation(self.eye_direction()),
+        }
+    }
+}
+
+/// Camera frame looking back along `eye_direction` with world +Y as close to up as possible.
+fn upright_orientation(eye_direction: Vec3) -> Quat {
+    let back = eye_direction.normalize();
+    let right = Vec3::Y.cross(back).try_normalize().unwrap_or(Vec3::X);
+    let up = back.cross(right);
+    Quat::from_mat3(&Mat3::from_cols(right, up, back))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        a.distance(b) < 1e-4
+    }
+
+    #[test]
+    fn modes_keep_the_view_when_switched() {
+        let mut camera = Camera {
+            target: Vec3::new(1.0, 2.0, 3.0),
+            ..Camera::default()
+        };
+        let (position, direction) = (camera.position(), camera.eye_direction());
+        for mode in [CameraMode::Trackball, CameraMode::Fly, CameraMode::Orbit] {
+            camera.set_mode(mode);
+            assert!(close(camera.position(), position), "{mode:?}");
+            assert!(close(camera.eye_direction(), direction), "{mode:?}");
+        }
+        assert!(close(camera.target, Vec3::new(1.0, 2.0, 3.0)));
+
+        camera.set_orbit(0.0, 3.0, 500.0);
+        assert_eq!((camera.pitch, camera.distance), (MAX_PITCH, MAX_DISTANCE));
+        assert_eq!(camera.mode, CameraMode::Orbit);
+    }
+
+    #[test]
+    fn frames_atoms_and_interpolates_between_views() {
+        let mut molecule = Molecule::new("pair");
+        let a = molecule.insert_atom("C".to_string(), [10.0, 0.0, 0.0]);
+        let b = molecule.insert_atom("C".to_string(), [14.0, 0.0, 0.0]);
+        let far = molecule.insert_atom("C".to_string(), [-30.0, 0.0, 0.0]);
+        let mut camera = Camera::default();
+        assert!(camera.frame(&molecule, &[a, b], 1.0));
+        assert!(close(camera.target, Vec3::new(12.0, 0.0, 0.0)));
+        let expected = 3.0 / (FIELD_OF_VIEW_DEGREES.to_radians() * 0.5).sin();
+        assert!((camera.distance - expected).abs() < 1e-4);
+        assert!(!camera.frame(&Molecule::new("empty"), &[far], 1.0));
+
+        let start = camera;
+        let mut end = start;
+        end.target = Vec3::ZERO;
+        end.yaw += 1.0;
+        end.distance = 20.0;
+        assert_eq!(start.interpolate(&end, 0.0), start);
+        assert_eq!(start.interpolate(&end, 1.0), end);
+        let middle = start.interpolate(&end, 0.5);
+        assert!(close(middle.target, Vec3::new(6.0, 0.0, 0.0)));
+        assert!((middle.distance - (start.distance + 20.0) * 0.5).abs() < 1e-4);
+        assert!((middle.yaw - (start.yaw + 0.5)).abs() < 1e-4);
+        assert!((middle.pitch - start.pitch).abs() < 1e-4);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_as_json() {
+        let mut camera = Camera::default();
+        camera.set_mode(CameraMode::Trackball);
+        let json = serde_json::to_string(&camera).unwrap();
+        assert_eq!(serde_json::from_str::<Camera>(&json).unwrap(), camera);
+    }
+}
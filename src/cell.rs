@@ -0,0 +1,195 @@
+use glam::{Mat3, Vec3};
+
+use crate::elements::vdw_radius;
+use crate::Molecule;
+
+const AVOGADRO_PER_CUBIC_ANGSTROM: f32 = 0.602_214_1;
+const VOLUME_GRID_SPACING: f32 = 0.25;
+
+/// Crystallographic unit cell; lengths in Å, angles in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnitCell {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub alpha: f32,
+    pub beta: f32,
+    pub gamma: f32,
+}
+
+impl UnitCell {
+    pub fn new(a: f32, b: f32, c: f32, alpha: f32, beta: f32, gamma: f32) -> Self {
+        Self {
+            a,
+            b,
+            c,
+            alpha,
+            beta,
+            gamma,
+        }
+    }
+
+    pub fn cubic(a: f32) -> Self {
+        Self::new(a, a, a, 90.0, 90.0, 90.0)
+    }
+
+    /// Lattice vectors as the columns of the returned matrix, with `a` along X and `b` in the XY plane.
+    pub fn matrix(&self) -> Mat3 {
+        let (cos_alpha, cos_beta) = (self.alpha.to_radians().cos(), self.beta.to_radians().cos());
+        let (sin_gamma, cos_gamma) = self.gamma.to_radians().sin_cos();
+        let a = Vec3::new(self.a, 0.0, 0.0);
+        let b = Vec3::new(self.b * cos_gamma, self.b * sin_gamma, 0.0);
+        let cx = self.c * cos_beta;
+        let cy = self.c * (cos_alpha - cos_beta * cos_gamma) / sin_gamma;
+        let cz = (self.c * self.c - cx * cx - cy * cy).max(0.0).sqrt();
+        Mat3::from_cols(a, b, Vec3::new(cx, cy, cz))
+    }
+
+    pub fn from_matrix(matrix: Mat3) -> Self {
+        let (a, b, c) = (matrix.x_axis, matrix.y_axis, matrix.z_axis);
+        let angle = |u: Vec3, v: Vec3| u.angle_between(v).to_degrees();
+        Self::new(
+            a.length(),
+            b.length(),
+            c.length(),
+            angle(b, c),
+            angle(a, c),
+            angle(a, b),
+        )
+    }
+
+    pub fn volume(&self) -> f32 {
+        let (cos_alpha, cos_beta, cos_gamma) = (
+            self.alpha.to_radians().cos(),
+            self.beta.to_radians().cos(),
+            self.gamma.to_radians().cos(),
+        );
+        let factor = 1.0 - cos_alpha * cos_alpha - cos_beta * cos_beta - cos_gamma * cos_gamma
+            + 2.0 * cos_alpha * cos_beta * cos_gamma;
+        self.a * self.b * self.c * factor.max(0.0).sqrt()
+    }
+
+    pub fn to_fractional(&self, position: [f32; 3]) -> [f32; 3] {
+        (self.matrix().inverse() * Vec3::from_array(position)).to_array()
+    }
+
+    pub fn to_cartesian(&self, fractional: [f32; 3]) -> [f32; 3] {
+        (self.matrix() * Vec3::from_array(fractional)).to_array()
+    }
+}
+
+/// Which coordinates stay fixed when the cell of a populated molecule changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CoordinateLock {
+    Cartesian,
+    Fractional,
+}
+
+/// Quantities derived from the cell and its contents, treating the molecule as one formula unit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellMetrics {
+    pub volume: f32,
+    /// g/cm³ for `z` formula units per cell.
+    pub density: f32,
+    /// Fraction of the cell occupied by the van der Waals volume of `z` formula units.
+    pub packing_coefficient: f32,
+}
+
+pub fn cell_metrics(molecule: &Molecule, z: u32) -> Option<CellMetrics> {
+    let volume = molecule.cell()?.volume();
+    Some(CellMetrics {
+        volume,
+        density: density(molecule, z)?,
+        packing_coefficient: z as f32 * vdw_volume(molecule, VOLUME_GRID_SPACING) / volume,
+    })
+}
+
+/// g/cm³ for `z` formula units per cell, or `None` without a cell of positive volume.
+pub fn density(molecule: &Molecule, z: u32) -> Option<f32> {
+    let volume = molecule.cell()?.volume();
+    if volume <= 0.0 {
+        return None;
+    }
+    Some(z as f32 * molecule.molecular_weight() / (AVOGADRO_PER_CUBIC_ANGSTROM * volume))
+}
+
+/// Volume of the union of van der Waals spheres, estimated by counting occupied grid points.
+pub fn vdw_volume(molecule: &Molecule, spacing: f32) -> f32 {
+    let spheres: Vec<(Vec3, f32)> = molecule
+        .atoms_in_order()
+        .map(|atom| (Vec3::from_array(atom.position), vdw_radius(&atom.element)))
+        .collect();
+    if spheres.is_empty() || spacing <= 0.0 {
+        return 0.0;
+    }
+    let mut min = Vec3::splat(f32::MAX);
+    let mut max = Vec3::splat(f32::MIN);
+    for (center, radius) in &spheres {
+        min = min.min(*center - Vec3::splat(*radius));
+        max = max.max(*center + Vec3::splat(*radius));
+    }
+    let dims = ((max - min) / spacing).ceil().as_uvec3() + 1;
+    let (nx, ny, nz) = (dims.x as usize, dims.y as usize, dims.z as usize);
+    let mut occupied = vec![false; nx * ny * nz];
+    for (center, radius) in &spheres {
+        let lo = ((*center - Vec3::splat(*radius) - min) / spacing)
+            .floor()
+            .as_uvec3();
+        let hi = ((*center + Vec3::splat(*radius) - min) / spacing)
+            .ceil()
+            .as_uvec3();
+        let radius_sq = radius * radius;
+        for ix in lo.x..=hi.x.min(dims.x - 1) {
+            for iy in lo.y..=hi.y.min(dims.y - 1) {
+                for iz in lo.z..=hi.z.min(dims.z - 1) {
+                    let point = min + Vec3::new(ix as f32, iy as f32, iz as f32) * spacing;
+                    if point.distance_squared(*center) <= radius_sq {
+                        occupied[(ix as usize * ny + iy as usize) * nz + iz as usize] = true;
+                    }
+                }
+            }
+        }
+    }
+    let count = occupied.iter().filter(|cell| **cell).count();
+    count as f32 * spacing * spacing * spacing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orthorhombic_volume_and_matrix() {
+        let cell = UnitCell::new(2.0, 3.0, 4.0, 90.0, 90.0, 90.0);
+        assert!((cell.volume() - 24.0).abs() < 1e-4);
+        let back = UnitCell::from_matrix(cell.matrix());
+        assert!((back.b - 3.0).abs() < 1e-4);
+        assert!((back.gamma - 90.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn fractional_round_trip() {
+        let cell = UnitCell::new(5.0, 6.0, 7.0, 80.0, 95.0, 110.0);
+        let cartesian = cell.to_cartesian([0.25, 0.5, 0.75]);
+        let fractional = cell.to_fractional(cartesian);
+        for (value, expected) in fractional.iter().zip([0.25, 0.5, 0.75]) {
+            assert!((value - expected).abs() < 1e-4);
+        }
+        assert!((cell.matrix().determinant() - cell.volume()).abs() < 1e-2);
+    }
+
+    #[test]
+    fn metrics_for_single_atom() {
+        let mut molecule = Molecule::new("argon");
+        molecule.insert_atom("Ar".into(), [0.0, 0.0, 0.0]);
+        assert!(cell_metrics(&molecule, 1).is_none());
+        molecule.set_cell(Some(UnitCell::cubic(10.0)));
+        let metrics = cell_metrics(&molecule, 4).unwrap();
+        assert!((metrics.volume - 1000.0).abs() < 1e-2);
+        assert!((metrics.density - 4.0 * 39.948 / 602.2141).abs() < 1e-3);
+        let sphere = 4.0 / 3.0 * std::f32::consts::PI * 1.88_f32.powi(3);
+        assert!((metrics.packing_coefficient - 4.0 * sphere / 1000.0).abs() < 0.01);
+    }
+}
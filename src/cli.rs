@@ -0,0 +1,214 @@
+use crate::formats::cml::write_cml;
+use crate::formats::extxyz::write_extxyz;
+use crate::formats::gaussian::{write_gaussian, GaussianSettings};
+use crate::formats::sdf::write_sdf;
+use crate::session::{write_session, Session};
+use crate::zmatrix::write_zmatrix;
+use crate::{write_xyz, Molecule, XYZ_PRECISION};
+
+/// Path argument that reads the structure from standard input.
+pub const STDIN_PATH: &str = "-";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepresentationArg {
+    BallAndStick,
+    SpaceFilling,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraArg {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+}
+
+/// Format written to stdout in headless mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Session,
+    ZMatrix,
+    Xyz,
+    /// Extended XYZ with the cell, metadata and per-atom properties.
+    ExtXyz,
+    Sdf,
+    Cml,
+    /// Gaussian input with the default route section.
+    Gaussian,
+}
+
+impl OutputFormat {
+    pub fn write(self, molecule: &Molecule) -> String {
+        match self {
+            OutputFormat::Session => write_session(&Session::new(molecule.clone())),
+            OutputFormat::ZMatrix => write_zmatrix(molecule),
+            OutputFormat::Xyz => write_xyz(molecule, XYZ_PRECISION),
+            OutputFormat::ExtXyz => write_extxyz(molecule, XYZ_PRECISION),
+            OutputFormat::Sdf => write_sdf(molecule),
+            OutputFormat::Cml => write_cml(molecule),
+            OutputFormat::Gaussian => write_gaussian(molecule, &GaussianSettings::default()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StartupOptions {
+    pub path: Option<String>,
+    pub representation: Option<RepresentationArg>,
+    pub camera: Option<CameraArg>,
+    pub select: Vec<u64>,
+    pub screenshot: Option<String>,
+    pub exit: bool,
+    /// Apply the presentation post-processing to `--screenshot`.
+    pub presentation: bool,
+    pub headless: bool,
+    pub output_format: OutputFormat,
+    pub gallery: Option<String>,
+    /// Skip lines that fail to parse instead of refusing the file.
+    pub lenient: bool,
+    /// Check the drawn instances against the structure after every edit, as debug builds do.
+    pub check_render: bool,
+}
+
+pub const USAGE: &str = "usage: molweaver [FILE|-] [--representation ball|spacefill] \
+[--camera YAW,PITCH,DIST] [--select ID,ID,...] [--screenshot OUT.png [--presentation]] [--exit] \
+[--headless [--to session|zmat|xyz|extxyz|sdf|cml|gjf]] [--gallery DIR] [--lenient] [--check-render]";
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<StartupOptions, String> {
+    let mut options = StartupOptions::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .ok_or_else(|| format!("{flag} requires a value"))
+        };
+        match arg.as_str() {
+            "--representation" => {
+                let value = value(&arg)?;
+                options.representation = Some(match value.as_str() {
+                    "ball" | "ballstick" | "ball-and-stick" => RepresentationArg::BallAndStick,
+                    "spacefill" | "space-filling" => RepresentationArg::SpaceFilling,
+                    _ => return Err(format!("unknown representation {value}")),
+                });
+            }
+            "--camera" => {
+                let value = value(&arg)?;
+                let parts = parse_list::<f32>(&value, "--camera")?;
+                let [yaw, pitch, distance] = parts[..] else {
+                    return Err("--camera expects YAW,PITCH,DIST".to_string());
+                };
+                options.camera = Some(CameraArg {
+                    yaw,
+                    pitch,
+                    distance,
+                });
+            }
+            "--select" => options.select = parse_list(&value(&arg)?, "--select")?,
+            "--screenshot" => options.screenshot = Some(value(&arg)?),
+            "--exit" => options.exit = true,
+            "--presentation" => options.presentation = true,
+            "--headless" => options.headless = true,
+            "--gallery" => options.gallery = Some(value(&arg)?),
+            "--lenient" => options.lenient = true,
+            "--check-render" => options.check_render = true,
+            "--to" => {
+                let value = value(&arg)?;
+                options.output_format = match value.as_str() {
+                    "session" | "mwsession" => OutputFormat::Session,
+                    "zmat" | "zmatrix" => OutputFormat::ZMatrix,
+                    "xyz" => OutputFormat::Xyz,
+                    "extxyz" => OutputFormat::ExtXyz,
+                    "sdf" | "mol" => OutputFormat::Sdf,
+                    "cml" => OutputFormat::Cml,
+                    "gjf" | "com" | "gaussian" => OutputFormat::Gaussian,
+                    _ => return Err(format!("unknown output format {value}")),
+                };
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown flag {flag}")),
+            _ if options.path.is_none() => options.path = Some(arg),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+    if options.headless && options.screenshot.is_some() {
+        return Err("--screenshot needs a window and cannot be used with --headless".to_string());
+    }
+    Ok(options)
+}
+
+fn parse_list<T: std::str::FromStr>(value: &str, flag: &str) -> Result<Vec<T>, String> {
+    value
+        .split(',')
+        .map(|part| {
+            part.trim()
+                .parse()
+                .map_err(|_| format!("invalid value {part} for {flag}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn parse_full_command_line() {
+        let options = parse_args(args(
+            "mol.xyz --representation spacefill --camera 0.5,0.2,12 --select 3,7,9 \
+             --screenshot out.png --presentation --exit --gallery runs",
+        ))
+        .unwrap();
+        assert_eq!(options.path.as_deref(), Some("mol.xyz"));
+        assert_eq!(
+            options.representation,
+            Some(RepresentationArg::SpaceFilling)
+        );
+        assert_eq!(
+            options.camera,
+            Some(CameraArg {
+                yaw: 0.5,
+                pitch: 0.2,
+                distance: 12.0
+            })
+        );
+        assert_eq!(options.select, [3, 7, 9]);
+        assert_eq!(options.screenshot.as_deref(), Some("out.png"));
+        assert!(options.exit);
+        assert!(options.presentation);
+        assert_eq!(options.gallery.as_deref(), Some("runs"));
+    }
+
+    #[test]
+    fn parse_rejects_bad_input() {
+        assert!(parse_args(args("--camera 1,2")).is_err());
+        assert!(parse_args(args("--select a")).is_err());
+        assert!(parse_args(args("--bogus")).is_err());
+        assert!(parse_args(args("--screenshot")).is_err());
+        assert!(parse_args(args("--headless --screenshot out.png")).is_err());
+        assert!(parse_args(args("- --to pdf")).is_err());
+        assert_eq!(parse_args(Vec::new()).unwrap(), StartupOptions::default());
+    }
+
+    #[test]
+    fn parse_stdin_headless() {
+        let options = parse_args(args("- --headless --to session")).unwrap();
+        assert_eq!(options.path.as_deref(), Some(STDIN_PATH));
+        assert!(options.headless);
+        assert_eq!(options.output_format, OutputFormat::Session);
+        let options = parse_args(args("mol.xyz --headless --to zmat --lenient")).unwrap();
+        assert_eq!(options.output_format, OutputFormat::ZMatrix);
+        assert!(options.lenient && !options.check_render);
+        assert!(parse_args(args("--check-render")).unwrap().check_render);
+        let options = parse_args(args("mol.pdb --headless --to xyz")).unwrap();
+        assert_eq!(options.output_format, OutputFormat::Xyz);
+        let options = parse_args(args("mol.xyz --headless --to sdf")).unwrap();
+        assert_eq!(options.output_format, OutputFormat::Sdf);
+        let options = parse_args(args("mol.xyz --headless --to cml")).unwrap();
+        assert_eq!(options.output_format, OutputFormat::Cml);
+        let options = parse_args(args("mol.xyz --headless --to com")).unwrap();
+        assert_eq!(options.output_format, OutputFormat::Gaussian);
+    }
+}
@@ -0,0 +1,79 @@
+//! Element counts, mass fractions and density for the Composition window.
+
+use crate::cell::density;
+use crate::elements::atomic_mass;
+use crate::Molecule;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElementShare {
+    pub symbol: String,
+    pub count: usize,
+    /// Fraction of the molecular weight, 0 to 1; 0 for elements without a known mass.
+    pub mass_fraction: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Composition {
+    /// In Hill order.
+    pub elements: Vec<ElementShare>,
+    pub atom_count: usize,
+    /// g/mol.
+    pub molecular_weight: f32,
+    /// g/cm³ with `z` formula units per cell; `None` without a cell.
+    pub density: Option<f32>,
+}
+
+pub fn composition(molecule: &Molecule, z: u32) -> Composition {
+    let molecular_weight = molecule.molecular_weight();
+    let elements = molecule
+        .element_counts()
+        .into_iter()
+        .map(|(symbol, count)| {
+            let mass = atomic_mass(&symbol).unwrap_or(0.0) * count as f32;
+            ElementShare {
+                mass_fraction: if molecular_weight > 0.0 {
+                    mass / molecular_weight
+                } else {
+                    0.0
+                },
+                symbol,
+                count,
+            }
+        })
+        .collect();
+    Composition {
+        elements,
+        atom_count: molecule.atom_count(),
+        molecular_weight,
+        density: density(molecule, z),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UnitCell;
+
+    #[test]
+    fn shares_add_up() {
+        let mut molecule = Molecule::new("nacl");
+        molecule.insert_atom("Na".into(), [0.0; 3]);
+        molecule.insert_atom("Cl".into(), [2.82, 0.0, 0.0]);
+        let salt = composition(&molecule, 4);
+        assert_eq!(salt.atom_count, 2);
+        assert_eq!(salt.density, None);
+        assert_eq!(
+            salt.elements
+                .iter()
+                .map(|share| (share.symbol.as_str(), share.count))
+                .collect::<Vec<_>>(),
+            [("Cl", 1), ("Na", 1)]
+        );
+        assert!((salt.elements[1].mass_fraction - 0.3934).abs() < 1e-3);
+
+        // Rock salt: four formula units in a 5.64 Å cube.
+        molecule.set_cell(Some(UnitCell::cubic(5.64)));
+        let density = composition(&molecule, 4).density.unwrap();
+        assert!((density - 2.165).abs() < 0.01);
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use crate::{AtomId, Bond, BondId, Molecule};
+
+const POSITION_TOLERANCE: f32 = 1e-4;
+
+/// Differences between two versions of a molecule, matched by atom id.
+///
+/// Bonds are matched by their endpoint pair, so a bond that was removed and re-added
+/// between the same atoms is only reported if its order or kind changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MoleculeDiff {
+    /// Atoms only present in the new molecule.
+    pub added_atoms: Vec<AtomId>,
+    /// Atoms only present in the old molecule.
+    pub removed_atoms: Vec<AtomId>,
+    /// Atoms whose element, charge or position changed.
+    pub modified_atoms: Vec<AtomId>,
+    /// Bond ids from the new molecule.
+    pub added_bonds: Vec<BondId>,
+    /// Bond ids from the old molecule.
+    pub removed_bonds: Vec<BondId>,
+    /// Bond ids from the new molecule of bonds whose order or kind changed.
+    pub modified_bonds: Vec<BondId>,
+}
+
+impl MoleculeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_atoms.is_empty()
+            && self.removed_atoms.is_empty()
+            && self.modified_atoms.is_empty()
+            && self.added_bonds.is_empty()
+            && self.removed_bonds.is_empty()
+            && self.modified_bonds.is_empty()
+    }
+}
+
+pub fn diff(old: &Molecule, new: &Molecule) -> MoleculeDiff {
+    let mut result = MoleculeDiff::default();
+    for atom in new.atoms_in_order() {
+        match old.get_atom(atom.id) {
+            None => result.added_atoms.push(atom.id),
+            Some(previous) => {
+                let moved = previous
+                    .position
+                    .iter()
+                    .zip(atom.position)
+                    .any(|(a, b)| (a - b).abs() > POSITION_TOLERANCE);
+                if moved || previous.element != atom.element || previous.charge != atom.charge {
+                    result.modified_atoms.push(atom.id);
+                }
+            }
+        }
+    }
+    for atom in old.atoms_in_order() {
+        if new.get_atom(atom.id).is_none() {
+            result.removed_atoms.push(atom.id);
+        }
+    }
+
+    let old_pairs = bond_pairs(old);
+    let new_pairs = bond_pairs(new);
+    for (pair, bond) in &new_pairs {
+        match old_pairs.get(pair) {
+            None => result.added_bonds.push(bond.id),
+            Some(previous) if previous.order != bond.order || previous.kind != bond.kind => {
+                result.modified_bonds.push(bond.id)
+            }
+            Some(_) => {}
+        }
+    }
+    result.removed_bonds = old_pairs
+        .iter()
+        .filter(|(pair, _)| !new_pairs.contains_key(pair))
+        .map(|(_, bond)| bond.id)
+        .collect();
+    result.added_bonds.sort();
+    result.removed_bonds.sort();
+    result.modified_bonds.sort();
+    result
+}
+
+fn bond_pairs(molecule: &Molecule) -> HashMap<(AtomId, AtomId), &Bond> {
+    molecule
+        .bonds()
+        .map(|bond| ((bond.a.min(bond.b), bond.a.max(bond.b)), bond))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BondKind;
+
+    #[test]
+    fn diff_reports_atom_and_bond_changes() {
+        let mut old = Molecule::new("old");
+        let a = old.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let b = old.insert_atom("O".into(), [1.2, 0.0, 0.0]);
+        let c = old.insert_atom("H".into(), [-1.0, 0.0, 0.0]);
+        let old_bond = old.add_bond(a, c).unwrap();
+        let mut new = old.clone();
+        new.remove_atom(c);
+        new.set_atom_position(b, [1.3, 0.0, 0.0]);
+        let d = new.insert_atom("N".into(), [0.0, 1.0, 0.0]);
+        let new_bond = new.add_bond(a, d).unwrap();
+
+        let result = diff(&old, &new);
+        assert_eq!(result.added_atoms, [d]);
+        assert_eq!(result.removed_atoms, [c]);
+        assert_eq!(result.modified_atoms, [b]);
+        assert_eq!(result.added_bonds, [new_bond]);
+        assert_eq!(result.removed_bonds, [old_bond]);
+        assert!(result.modified_bonds.is_empty());
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn diff_reports_charge_and_bond_order_changes() {
+        let mut old = Molecule::new("old");
+        let c = old.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let o = old.insert_atom("O".into(), [1.2, 0.0, 0.0]);
+        let n = old.insert_atom("N".into(), [-1.4, 0.0, 0.0]);
+        let co = old.add_bond(c, o).unwrap();
+        let cn = old.add_bond(c, n).unwrap();
+        let mut new = old.clone();
+        new.set_bond_order(co, 2);
+        new.set_formal_charge(n, 1);
+
+        let result = diff(&old, &new);
+        assert_eq!(result.modified_atoms, [n]);
+        assert_eq!(result.modified_bonds, [co]);
+        assert!(result.added_bonds.is_empty() && result.removed_bonds.is_empty());
+
+        new.set_bond_kind(cn, BondKind::Coordination).unwrap();
+        assert_eq!(diff(&old, &new).modified_bonds, [co, cn]);
+        assert!(!diff(&old, &new).is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+//! Manual docking: the interaction energy between a guest fragment and the rest of the
+//! structure (the host), and rigid moves of the guest.
+//!
+//! The score is a 12-6 Lennard-Jones term with per-element well depths and van der Waals
+//! contact distances, plus Coulomb with a dielectric of 4. Charges are the `partial_charge`
+//! property when an atom has one (MOL2 files carry it) and the formal charge otherwise. Pairs
+//! beyond the cutoff are skipped. It ranks rough poses; it is not a docking score to report.
+
+use std::collections::HashSet;
+
+use glam::{Quat, Vec3};
+
+use crate::elements::{normalize_symbol, vdw_radius};
+use crate::spatial::SpatialGrid;
+use crate::{AtomId, Command, Molecule};
+
+/// Coulomb's constant in kcal·Å/(mol·e²).
+const COULOMB: f32 = 332.0637;
+const DIELECTRIC: f32 = 4.0;
+/// Pairs farther apart than this, in Å, don't contribute.
+const CUTOFF: f32 = 10.0;
+/// Overlap of van der Waals spheres, in Å, beyond which a contact counts as a clash.
+const CLASH_OVERLAP: f32 = 0.6;
+
+/// Host–guest interaction energy from [`score`], in kcal/mol.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DockingScore {
+    pub lennard_jones: f32,
+    pub electrostatic: f32,
+    /// Host and guest atom of each clashing pair, closest first.
+    pub clashes: Vec<(AtomId, AtomId)>,
+}
+
+impl DockingScore {
+    pub fn total(&self) -> f32 {
+        self.lennard_jones + self.electrostatic
+    }
+
+    /// Every atom taking part in a clash.
+    pub fn clashing_atoms(&self) -> HashSet<AtomId> {
+        self.clashes
+            .iter()
+            .flat_map(|&(host, guest)| [host, guest])
+            .collect()
+    }
+}
+
+/// The bond-connected fragment holding `atom`, which is the guest when docking it.
+pub fn guest_of(molecule: &Molecule, atom: AtomId) -> Vec<AtomId> {
+    molecule
+        .connected_components()
+        .into_iter()
+        .find(|component| component.contains(&atom))
+        .unwrap_or_default()
+}
+
+/// Interaction energy between `guest` and every other atom of `molecule`.
+pub fn score(molecule: &Molecule, guest: &[AtomId]) -> DockingScore {
+    let in_guest: HashSet<AtomId> = guest.iter().copied().collect();
+    let host: Vec<AtomId> = molecule
+        .atom_ids()
+        .into_iter()
+        .filter(|id| !in_guest.contains(id))
+        .collect();
+    let position = |id: AtomId| {
+        molecule
+            .get_atom(id)
+            .map_or(Vec3::ZERO, |atom| Vec3::from_array(atom.position))
+    };
+    let host_positions: Vec<Vec3> = host.iter().map(|&id| position(id)).collect();
+    let grid = SpatialGrid::new(&host_positions, CUTOFF);
+    let mut result = DockingScore::default();
+    let mut clashes = Vec::new();
+    for &guest_id in guest {
+        let Some(guest_atom) = molecule.get_atom(guest_id) else {
+            continue;
+        };
+        let guest_position = Vec3::from_array(guest_atom.position);
+        let guest_charge = charge(molecule, guest_id);
+        for index in grid.candidates(guest_position) {
+            let host_id = host[index];
+            let distance = guest_position.distance(host_positions[index]);
+            if distance > CUTOFF {
+                continue;
+            }
+            let distance = distance.max(0.1);
+            let Some(host_atom) = molecule.get_atom(host_id) else {
+                continue;
+            };
+            let contact = vdw_radius(&host_atom.element) + vdw_radius(&guest_atom.element);
+            let depth = (well_depth(&host_atom.element) * well_depth(&guest_atom.element)).sqrt();
+            let ratio = (contact / distance).powi(6);
+            result.lennard_jones += depth * (ratio * ratio - 2.0 * ratio);
+            result.electrostatic +=
+                COULOMB * charge(molecule, host_id) * guest_charge / (DIELECTRIC * distance);
+            if distance < contact - CLASH_OVERLAP {
+                clashes.push((distance, host_id, guest_id));
+            }
+        }
+    }
+    clashes.sort_by(|a, b| a.0.total_cmp(&b.0));
+    result.clashes = clashes
+        .into_iter()
+        .map(|(_, host, guest)| (host, guest))
+        .collect();
+    result
+}
+
+/// UFF well depths in kcal/mol.
+pub(crate) fn well_depth(element: &str) -> f32 {
+    match normalize_symbol(element).as_str() {
+        "H" => 0.044,
+        "C" => 0.105,
+        "N" => 0.069,
+        "O" => 0.060,
+        "F" => 0.050,
+        "P" => 0.305,
+        "S" => 0.274,
+        "Cl" => 0.227,
+        "Br" => 0.251,
+        "I" => 0.339,
+        _ => 0.1,
+    }
+}
+
+fn charge(molecule: &Molecule, id: AtomId) -> f32 {
+    molecule
+        .properties
+        .get("partial_charge", id)
+        .and_then(|value| value.trim().parse().ok())
+        .or_else(|| molecule.get_atom(id).map(|atom| atom.charge as f32))
+        .unwrap_or(0.0)
+}
+
+impl Command {
+    /// One composite step that turns `atoms` by `rotation` about their centroid and then
+    /// shifts them by `translation`.
+    pub fn move_rigid(
+        molecule: &Molecule,
+        atoms: &[AtomId],
+        rotation: Quat,
+        translation: Vec3,
+    ) -> Command {
+        let positions: Vec<(AtomId, Vec3)> = atoms
+            .iter()
+            .filter_map(|&id| Some((id, Vec3::from_array(molecule.get_atom(id)?.position))))
+            .collect();
+        let centroid = positions
+            .iter()
+            .map(|(_, position)| *position)
+            .sum::<Vec3>()
+            / positions.len().max(1) as f32;
+        let commands = positions
+            .into_iter()
+            .map(|(atom_id, from)| Command::MoveAtom {
+                atom_id,
+                from: from.to_array(),
+                to: (centroid + rotation * (from - centroid) + translation).to_array(),
+            })
+            .collect();
+        Command::Composite { commands }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CommandHistory;
+
+    #[test]
+    fn scores_contacts_charges_and_clashes() {
+        let mut molecule = Molecule::new("pair");
+        let host = molecule.insert_atom("C".into(), [0.0; 3]);
+        let contact = 2.0 * vdw_radius("C");
+        let guest = molecule.insert_atom("C".into(), [contact, 0.0, 0.0]);
+        let at_minimum = score(&molecule, &guest_of(&molecule, guest));
+        assert!((at_minimum.lennard_jones + 0.105).abs() < 1e-4);
+        assert_eq!(at_minimum.electrostatic, 0.0);
+        assert!(at_minimum.clashes.is_empty());
+
+        molecule.set_atom_position(guest, [1.5, 0.0, 0.0]);
+        let clashing = score(&molecule, &[guest]);
+        assert!(clashing.lennard_jones > 0.0);
+        assert_eq!(clashing.clashes, vec![(host, guest)]);
+        assert_eq!(clashing.clashing_atoms().len(), 2);
+
+        molecule.set_atom_position(guest, [CUTOFF + 1.0, 0.0, 0.0]);
+        assert_eq!(score(&molecule, &[guest]).total(), 0.0);
+
+        let mut salt = Molecule::new("salt");
+        let na = salt.insert_atom("Na".into(), [0.0; 3]);
+        let cl = salt.insert_atom("Cl".into(), [3.0, 0.0, 0.0]);
+        salt.set_formal_charge(na, 1);
+        salt.properties.set("partial_charge", cl, "-0.5");
+        let coulomb = score(&salt, &[cl]).electrostatic;
+        assert!((coulomb + COULOMB * 0.5 / 12.0).abs() < 1e-3, "{coulomb}");
+    }
+
+    #[test]
+    fn rigid_moves_keep_the_guest_shape_and_undo_in_one_step() {
+        let mut molecule = Molecule::new("host-guest");
+        molecule.insert_atom("O".into(), [0.0; 3]);
+        let a = molecule.insert_atom("C".into(), [5.0, 0.0, 0.0]);
+        let b = molecule.insert_atom("C".into(), [6.54, 0.0, 0.0]);
+        molecule.add_bond(a, b).unwrap();
+        let guest = guest_of(&molecule, a);
+        assert_eq!(guest, vec![a, b]);
+        let mut history = CommandHistory::new(10);
+        let turn = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+        let command = Command::move_rigid(&molecule, &guest, turn, Vec3::Y);
+        history.execute(command, &mut molecule).unwrap();
+        let [pa, pb] = [a, b].map(|id| Vec3::from(molecule.get_atom(id).unwrap().position));
+        assert!((pa.distance(pb) - 1.54).abs() < 1e-4);
+        assert!(((pa + pb) * 0.5 - Vec3::new(5.77, 1.0, 0.0)).length() < 1e-4);
+        assert!((pb - pa).normalize().abs_diff_eq(Vec3::Y, 1e-4));
+        assert_eq!(history.undo_len(), 1);
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.get_atom(b).unwrap().position, [6.54, 0.0, 0.0]);
+    }
+}
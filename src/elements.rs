@@ -0,0 +1,144 @@
+struct ElementData {
+    symbol: &'static str,
+    number: u8,
+    mass: f32,
+    vdw_radius: f32,
+}
+
+// (symbol, atomic number, standard atomic weight, Bondi/Alvarez van der Waals radius in Å)
+const ELEMENT_TABLE: &[(&str, u8, f32, f32)] = &[
+    ("H", 1, 1.008, 1.20),
+    ("He", 2, 4.0026, 1.40),
+    ("Li", 3, 6.94, 1.82),
+    ("Be", 4, 9.0122, 1.53),
+    ("B", 5, 10.81, 1.92),
+    ("C", 6, 12.011, 1.70),
+    ("N", 7, 14.007, 1.55),
+    ("O", 8, 15.999, 1.52),
+    ("F", 9, 18.998, 1.47),
+    ("Ne", 10, 20.180, 1.54),
+    ("Na", 11, 22.990, 2.27),
+    ("Mg", 12, 24.305, 1.73),
+    ("Al", 13, 26.982, 1.84),
+    ("Si", 14, 28.085, 2.10),
+    ("P", 15, 30.974, 1.80),
+    ("S", 16, 32.06, 1.80),
+    ("Cl", 17, 35.45, 1.75),
+    ("Ar", 18, 39.948, 1.88),
+    ("K", 19, 39.098, 2.75),
+    ("Ca", 20, 40.078, 2.31),
+    ("Sc", 21, 44.956, 2.15),
+    ("Ti", 22, 47.867, 2.11),
+    ("V", 23, 50.942, 2.07),
+    ("Cr", 24, 51.996, 2.06),
+    ("Mn", 25, 54.938, 2.05),
+    ("Fe", 26, 55.845, 2.04),
+    ("Co", 27, 58.933, 2.00),
+    ("Ni", 28, 58.693, 1.97),
+    ("Cu", 29, 63.546, 1.96),
+    ("Zn", 30, 65.38, 2.01),
+    ("Ga", 31, 69.723, 1.87),
+    ("Ge", 32, 72.630, 2.11),
+    ("As", 33, 74.922, 1.85),
+    ("Se", 34, 78.971, 1.90),
+    ("Br", 35, 79.904, 1.85),
+    ("Kr", 36, 83.798, 2.02),
+    ("Rb", 37, 85.468, 3.03),
+    ("Sr", 38, 87.62, 2.49),
+    ("Y", 39, 88.906, 2.32),
+    ("Zr", 40, 91.224, 2.23),
+    ("Nb", 41, 92.906, 2.18),
+    ("Mo", 42, 95.95, 2.17),
+    ("Ru", 44, 101.07, 2.13),
+    ("Rh", 45, 102.91, 2.10),
+    ("Pd", 46, 106.42, 2.10),
+    ("Ag", 47, 107.87, 2.11),
+    ("Cd", 48, 112.41, 2.18),
+    ("In", 49, 114.82, 1.93),
+    ("Sn", 50, 118.71, 2.17),
+    ("Sb", 51, 121.76, 2.06),
+    ("Te", 52, 127.60, 2.06),
+    ("I", 53, 126.90, 1.98),
+    ("Xe", 54, 131.29, 2.16),
+    ("Cs", 55, 132.91, 3.43),
+    ("Ba", 56, 137.33, 2.68),
+    ("W", 74, 183.84, 2.18),
+    ("Re", 75, 186.21, 2.16),
+    ("Os", 76, 190.23, 2.16),
+    ("Ir", 77, 192.22, 2.13),
+    ("Pt", 78, 195.08, 2.13),
+    ("Au", 79, 196.97, 2.14),
+    ("Hg", 80, 200.59, 2.23),
+    ("Pb", 82, 207.2, 2.02),
+    ("Bi", 83, 208.98, 2.07),
+    ("U", 92, 238.03, 1.86),
+];
+
+const DEFAULT_VDW_RADIUS: f32 = 2.0;
+
+fn lookup(element: &str) -> Option<ElementData> {
+    let element = element.trim();
+    ELEMENT_TABLE
+        .iter()
+        .find(|entry| entry.0.eq_ignore_ascii_case(element))
+        .map(|&(symbol, number, mass, vdw_radius)| ElementData {
+            symbol,
+            number,
+            mass,
+            vdw_radius,
+        })
+}
+
+pub fn normalize_symbol(element: &str) -> String {
+    match lookup(element) {
+        Some(data) => data.symbol.to_string(),
+        None => element.trim().to_string(),
+    }
+}
+
+pub fn atomic_number(element: &str) -> Option<u8> {
+    lookup(element).map(|data| data.number)
+}
+
+pub fn atomic_mass(element: &str) -> Option<f32> {
+    lookup(element).map(|data| data.mass)
+}
+
+pub fn vdw_radius(element: &str) -> f32 {
+    lookup(element)
+        .map(|data| data.vdw_radius)
+        .unwrap_or(DEFAULT_VDW_RADIUS)
+}
+
+/// Single-bond covalent radius in Å, 1.2 for elements without one here.
+pub fn covalent_radius(element: &str) -> f32 {
+    match normalize_symbol(element).as_str() {
+        "H" => 0.31,
+        "B" => 0.84,
+        "C" => 0.76,
+        "N" => 0.71,
+        "O" => 0.66,
+        "F" => 0.57,
+        "Si" => 1.11,
+        "P" => 1.07,
+        "S" => 1.05,
+        "Cl" => 1.02,
+        "Se" => 1.20,
+        "Br" => 1.20,
+        "I" => 1.39,
+        _ => 1.2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_is_case_insensitive() {
+        assert_eq!(atomic_number("cl"), Some(17));
+        assert_eq!(normalize_symbol("CL"), "Cl");
+        assert_eq!(atomic_mass("Xx"), None);
+        assert_eq!(vdw_radius("Xx"), DEFAULT_VDW_RADIUS);
+    }
+}
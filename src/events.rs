@@ -0,0 +1,279 @@
+//! Change notifications from [`Molecule`](crate::Molecule) mutations, so views can follow
+//! edits without diffing the structure.
+//!
+//! A view that keeps its own copy in step with the events can be checked against the
+//! molecule with [`check_mirror`], to catch bookkeeping that drifted.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::{bond_instance_from_positions, AtomId, BondId, Molecule};
+
+/// How far a mirrored position may be from the molecule's before it counts as stale.
+const POSITION_TOLERANCE: f32 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoleculeEvent {
+    AtomAdded(AtomId),
+    /// Sent after a `BondRemoved` for each bond the atom had.
+    AtomRemoved(AtomId),
+    AtomMoved(AtomId),
+    /// Element or formal charge changed.
+    AtomChanged(AtomId),
+    BondAdded(BondId),
+    BondRemoved(BondId),
+    /// Bond order or kind (covalent or coordination) changed.
+    BondChanged(BondId),
+    CellChanged,
+    /// The whole structure was replaced or renumbered; rebuild anything derived from it.
+    Reset,
+}
+
+/// Senders of every live subscription. Clones start without subscribers, so snapshots and
+/// copies of a molecule stay silent.
+#[derive(Default)]
+pub(crate) struct Subscribers(Vec<Sender<MoleculeEvent>>);
+
+impl Subscribers {
+    pub(crate) fn subscribe(&mut self) -> Receiver<MoleculeEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.0.push(sender);
+        receiver
+    }
+
+    /// Drops subscriptions whose receiver is gone.
+    pub(crate) fn emit(&mut self, event: MoleculeEvent) {
+        if !self.0.is_empty() {
+            self.0.retain(|sender| sender.send(event).is_ok());
+        }
+    }
+}
+
+impl Clone for Subscribers {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for Subscribers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} subscribers", self.0.len())
+    }
+}
+
+/// A view's copy of the structure: instance arrays in drawing order with the lookups from
+/// ids into them.
+#[derive(Debug, Clone, Copy)]
+pub struct Mirror<'a> {
+    pub atom_ids: &'a [AtomId],
+    pub atom_positions: &'a [[f32; 3]],
+    pub atom_lookup: &'a HashMap<AtomId, usize>,
+    /// `None` when the view draws no bonds.
+    pub bonds: Option<BondMirror<'a>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BondMirror<'a> {
+    pub ids: &'a [BondId],
+    pub midpoints: &'a [[f32; 3]],
+    pub lookup: &'a HashMap<BondId, usize>,
+    /// Bonds to update when an atom moves.
+    pub by_atom: &'a HashMap<AtomId, Vec<BondId>>,
+}
+
+/// A way a [`Mirror`] differs from the molecule it follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Divergence {
+    MissingAtom(AtomId),
+    /// Mirrored but no longer in the molecule.
+    StaleAtom(AtomId),
+    MisplacedAtom(AtomId),
+    /// The lookup does not point at the atom's instance.
+    AtomLookup(AtomId),
+    MissingBond(BondId),
+    StaleBond(BondId),
+    /// Not drawn between its atoms' current positions.
+    MisplacedBond(BondId),
+    /// The lookup, or an end atom's bond list, does not lead to the bond's instance.
+    BondLookup(BondId),
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergence::MissingAtom(id) => write!(f, "atom {} is not drawn", id.value()),
+            Divergence::StaleAtom(id) => write!(f, "deleted atom {} is still drawn", id.value()),
+            Divergence::MisplacedAtom(id) => write!(f, "atom {} is drawn out of place", id.value()),
+            Divergence::AtomLookup(id) => write!(f, "lookup of atom {} is wrong", id.value()),
+            Divergence::MissingBond(id) => write!(f, "bond {} is not drawn", id.value()),
+            Divergence::StaleBond(id) => write!(f, "deleted bond {} is still drawn", id.value()),
+            Divergence::MisplacedBond(id) => write!(f, "bond {} is drawn out of place", id.value()),
+            Divergence::BondLookup(id) => write!(f, "lookup of bond {} is wrong", id.value()),
+        }
+    }
+}
+
+/// Every way `mirror` differs from `molecule`; empty when the two agree.
+pub fn check_mirror(molecule: &Molecule, mirror: &Mirror) -> Vec<Divergence> {
+    let mut found = Vec::new();
+    for (index, &id) in mirror.atom_ids.iter().enumerate() {
+        if mirror.atom_lookup.get(&id) != Some(&index) {
+            found.push(Divergence::AtomLookup(id));
+        }
+        match molecule.get_atom(id) {
+            None => found.push(Divergence::StaleAtom(id)),
+            Some(atom) => {
+                let drawn = mirror.atom_positions.get(index);
+                if !drawn.is_some_and(|&drawn| close(drawn, atom.position)) {
+                    found.push(Divergence::MisplacedAtom(id));
+                }
+            }
+        }
+    }
+    for (&id, &index) in mirror.atom_lookup {
+        if mirror.atom_ids.get(index) != Some(&id) && !found.contains(&Divergence::AtomLookup(id)) {
+            found.push(Divergence::AtomLookup(id));
+        }
+    }
+    for id in molecule.atom_ids() {
+        if !mirror.atom_lookup.contains_key(&id) && !mirror.atom_ids.contains(&id) {
+            found.push(Divergence::MissingAtom(id));
+        }
+    }
+    let Some(bonds) = mirror.bonds else {
+        return found;
+    };
+    for (index, &id) in bonds.ids.iter().enumerate() {
+        let Some(bond) = molecule.bonds().find(|bond| bond.id == id) else {
+            found.push(Divergence::StaleBond(id));
+            continue;
+        };
+        let listed = |atom| {
+            bonds
+                .by_atom
+                .get(&atom)
+                .is_some_and(|ids| ids.contains(&id))
+        };
+        if bonds.lookup.get(&id) != Some(&index) || !listed(bond.a) || !listed(bond.b) {
+            found.push(Divergence::BondLookup(id));
+        }
+        let ends = molecule.get_atom(bond.a).zip(molecule.get_atom(bond.b));
+        let expected = ends.map(|(a, b)| bond_instance_from_positions(a.position, b.position));
+        let drawn = bonds.midpoints.get(index);
+        if let (Some(expected), Some(&drawn)) = (expected, drawn) {
+            if !close(drawn, expected.midpoint) {
+                found.push(Divergence::MisplacedBond(id));
+            }
+        }
+    }
+    for bond in molecule.bonds() {
+        if !bonds.lookup.contains_key(&bond.id) && !bonds.ids.contains(&bond.id) {
+            found.push(Divergence::MissingBond(bond.id));
+        }
+    }
+    found
+}
+
+fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+    a.iter()
+        .zip(b)
+        .all(|(a, b)| (a - b).abs() <= POSITION_TOLERANCE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, CommandHistory, Molecule};
+
+    #[test]
+    fn mutations_notify_subscribers() {
+        let mut molecule = Molecule::new("water");
+        let events = molecule.subscribe();
+        let o = molecule.insert_atom("O".into(), [0.0; 3]);
+        let h = molecule.insert_atom("H".into(), [1.0, 0.0, 0.0]);
+        let bond = molecule.add_bond(o, h).unwrap();
+        molecule.set_atom_position(h, [0.96, 0.0, 0.0]);
+        molecule.remove_atom(h);
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [
+                MoleculeEvent::AtomAdded(o),
+                MoleculeEvent::AtomAdded(h),
+                MoleculeEvent::BondAdded(bond),
+                MoleculeEvent::AtomMoved(h),
+                MoleculeEvent::BondRemoved(bond),
+                MoleculeEvent::AtomRemoved(h),
+            ]
+        );
+
+        // Copies are silent, and restoring one keeps the original's subscribers.
+        let mut copy = molecule.clone();
+        copy.insert_atom("H".into(), [0.0, 1.0, 0.0]);
+        assert!(events.try_recv().is_err());
+        let mut history = CommandHistory::new(8);
+        let restore = Command::Restore {
+            snapshot: Box::new(copy),
+            previous: None,
+        };
+        history.execute(restore, &mut molecule).unwrap();
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [MoleculeEvent::Reset, MoleculeEvent::Reset]
+        );
+
+        drop(events);
+        molecule.insert_atom("H".into(), [0.0; 3]);
+        assert!(molecule.subscribers.0.is_empty());
+    }
+
+    #[test]
+    fn mirrors_are_checked_against_the_molecule() {
+        let mut molecule = Molecule::new("water");
+        let o = molecule.insert_atom("O".into(), [0.0; 3]);
+        let h = molecule.insert_atom("H".into(), [1.0, 0.0, 0.0]);
+        let bond = molecule.add_bond(o, h).unwrap();
+        let mut atom_ids = vec![o, h];
+        let mut atom_positions = vec![[0.0; 3], [1.0, 0.0, 0.0]];
+        let atom_lookup = HashMap::from([(o, 0), (h, 1)]);
+        let bond_lookup = HashMap::from([(bond, 0)]);
+        let by_atom = HashMap::from([(o, vec![bond]), (h, vec![bond])]);
+        let midpoints = [[0.5, 0.0, 0.0]];
+        let check = |molecule: &Molecule, atom_ids: &[AtomId], atom_positions: &[[f32; 3]]| {
+            let mirror = Mirror {
+                atom_ids,
+                atom_positions,
+                atom_lookup: &atom_lookup,
+                bonds: Some(BondMirror {
+                    ids: &[bond],
+                    midpoints: &midpoints,
+                    lookup: &bond_lookup,
+                    by_atom: &by_atom,
+                }),
+            };
+            check_mirror(molecule, &mirror)
+        };
+        assert!(check(&molecule, &atom_ids, &atom_positions).is_empty());
+
+        // The atom was moved but its bond was not updated.
+        molecule.set_atom_position(h, [2.0, 0.0, 0.0]);
+        atom_positions[1] = [2.0, 0.0, 0.0];
+        assert_eq!(
+            check(&molecule, &atom_ids, &atom_positions),
+            [Divergence::MisplacedBond(bond)]
+        );
+        atom_ids.swap(0, 1);
+        atom_positions.swap(0, 1);
+        let found = check(&molecule, &atom_ids, &atom_positions);
+        assert_eq!(
+            found[..2],
+            [Divergence::AtomLookup(h), Divergence::AtomLookup(o)]
+        );
+        molecule.remove_atom(h);
+        let found = check(&molecule, &atom_ids, &atom_positions);
+        assert!(found.contains(&Divergence::StaleAtom(h)), "{found:?}");
+        assert!(found.contains(&Divergence::StaleBond(bond)), "{found:?}");
+        assert_eq!(found[0].to_string(), "lookup of atom 2 is wrong");
+    }
+}
@@ -0,0 +1,229 @@
+//! Composite figures: several rendered panels tiled into one image, each labeled a, b, c… in
+//! its top-left corner, so multi-panel figures need no image editor.
+
+use crate::camera::{Camera, CameraMode};
+use crate::grid::GridCell;
+use crate::postprocess::Frame;
+
+/// Columns and rows of one glyph in [`GLYPHS`].
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+/// Panel height in pixels per font dot, which makes a label about a sixteenth of the panel tall.
+const LABEL_DOTS_PER_PANEL: u32 = 140;
+const LABEL_INK: [u8; 4] = [0, 0, 0, 255];
+const LABEL_BACKING: [u8; 4] = [255, 255, 255, 255];
+/// Gap between tiled panels, in pixels.
+const PANEL_GAP: u32 = 4;
+const GAP_COLOR: [u8; 4] = [255, 255, 255, 255];
+
+/// 5×7 bitmaps for `a`–`z`, one row per byte from the top, the leftmost column in bit 4.
+const GLYPHS: [[u8; 7]; 26] = [
+    [0x00, 0x00, 0x0e, 0x01, 0x0f, 0x11, 0x0f],
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1e],
+    [0x00, 0x00, 0x0e, 0x10, 0x10, 0x11, 0x0e],
+    [0x01, 0x01, 0x0d, 0x13, 0x11, 0x11, 0x0f],
+    [0x00, 0x00, 0x0e, 0x11, 0x1f, 0x10, 0x0e],
+    [0x06, 0x09, 0x08, 0x1c, 0x08, 0x08, 0x08],
+    [0x00, 0x0f, 0x11, 0x11, 0x0f, 0x01, 0x0e],
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11],
+    [0x04, 0x00, 0x0c, 0x04, 0x04, 0x04, 0x0e],
+    [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0c],
+    [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12],
+    [0x0c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+    [0x00, 0x00, 0x1a, 0x15, 0x15, 0x11, 0x11],
+    [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11],
+    [0x00, 0x00, 0x0e, 0x11, 0x11, 0x11, 0x0e],
+    [0x00, 0x00, 0x1e, 0x11, 0x1e, 0x10, 0x10],
+    [0x00, 0x00, 0x0d, 0x13, 0x0f, 0x01, 0x01],
+    [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10],
+    [0x00, 0x00, 0x0e, 0x10, 0x0e, 0x01, 0x1e],
+    [0x08, 0x08, 0x1c, 0x08, 0x08, 0x09, 0x06],
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0d],
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0a, 0x04],
+    [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0a],
+    [0x00, 0x00, 0x11, 0x0a, 0x04, 0x0a, 0x11],
+    [0x00, 0x00, 0x11, 0x11, 0x0f, 0x01, 0x0e],
+    [0x00, 0x00, 0x1f, 0x02, 0x04, 0x08, 0x1f],
+];
+
+/// Label of the panel at `index`: a–z, then aa, ab… like spreadsheet columns.
+pub fn panel_label(index: usize) -> String {
+    let mut label = Vec::new();
+    let mut rest = index + 1;
+    while rest > 0 {
+        rest -= 1;
+        label.push(b'a' + (rest % 26) as u8);
+        rest /= 26;
+    }
+    label.reverse();
+    String::from_utf8(label).unwrap_or_default()
+}
+
+/// Gives every camera the largest distance among them, so one Ångström spans the same number
+/// of pixels in every panel while each still frames its whole view. Fly cameras place the eye
+/// directly and are left alone.
+pub fn match_scale(cameras: &mut [Camera]) {
+    let orbiting = |camera: &&mut Camera| camera.mode != CameraMode::Fly;
+    let distance = cameras
+        .iter_mut()
+        .filter(orbiting)
+        .map(|camera| camera.distance)
+        .fold(0.0, f32::max);
+    for camera in cameras.iter_mut().filter(orbiting) {
+        camera.distance = distance;
+    }
+}
+
+/// Tiles equally sized `panels` row by row, `columns` per row with a thin gap, and labels
+/// each one. Panels of other sizes are cropped or padded to the first one's.
+pub fn compose(panels: &[Frame], columns: usize) -> Frame {
+    let Some(first) = panels.first() else {
+        return Frame {
+            width: 0,
+            height: 0,
+            pixels: Vec::new(),
+            depth: Vec::new(),
+        };
+    };
+    let columns = columns.clamp(1, panels.len()) as u32;
+    let rows = (panels.len() as u32).div_ceil(columns);
+    let (panel_width, panel_height) = (first.width, first.height);
+    let width = columns * panel_width + (columns - 1) * PANEL_GAP;
+    let height = rows * panel_height + (rows - 1) * PANEL_GAP;
+    let mut figure = Frame {
+        width,
+        height,
+        pixels: GAP_COLOR.repeat((width * height) as usize),
+        depth: vec![f32::INFINITY; (width * height) as usize],
+    };
+    let mut cells = Vec::with_capacity(panels.len());
+    for (index, panel) in panels.iter().enumerate() {
+        let (column, row) = (index as u32 % columns, index as u32 / columns);
+        let (left, top) = (
+            column * (panel_width + PANEL_GAP),
+            row * (panel_height + PANEL_GAP),
+        );
+        for y in 0..panel_height.min(panel.height) {
+            for x in 0..panel_width.min(panel.width) {
+                let source = (y * panel.width + x) as usize;
+                let target = ((top + y) * width + left + x) as usize;
+                figure.pixels[target * 4..target * 4 + 4]
+                    .copy_from_slice(&panel.pixels[source * 4..source * 4 + 4]);
+                figure.depth[target] = panel.depth[source];
+            }
+        }
+        cells.push(GridCell {
+            x: left as f32,
+            y: top as f32,
+            width: panel_width as f32,
+            height: panel_height as f32,
+        });
+    }
+    label_cells(&mut figure, &cells);
+    figure
+}
+
+/// Labels each of `cells` in `frame` with [`panel_label`] near its top-left corner.
+pub fn label_cells(frame: &mut Frame, cells: &[GridCell]) {
+    for (index, cell) in cells.iter().enumerate() {
+        let dot = (cell.height as u32 / LABEL_DOTS_PER_PANEL).max(1);
+        let margin = dot * 2;
+        draw_label(
+            frame,
+            cell.x.round() as u32 + margin,
+            cell.y.round() as u32 + margin,
+            &panel_label(index),
+            dot,
+        );
+    }
+}
+
+/// Draws `text` (lowercase letters; anything else is left blank) with its top-left corner at
+/// `x`, `y`, each font dot `dot` pixels square, on a backing box one dot wider all round.
+pub fn draw_label(frame: &mut Frame, x: u32, y: u32, text: &str, dot: u32) {
+    let advance = (GLYPH_WIDTH + 1) * dot;
+    let box_width = advance * text.chars().count() as u32 + dot;
+    let box_height = (GLYPH_HEIGHT + 2) * dot;
+    fill(frame, x, y, box_width, box_height, LABEL_BACKING);
+    for (index, character) in text.chars().enumerate() {
+        if !character.is_ascii_lowercase() {
+            continue;
+        }
+        let glyph = GLYPHS[(character as u8 - b'a') as usize];
+        let left = x + dot + index as u32 * advance;
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                    let top = y + dot + row as u32 * dot;
+                    fill(frame, left + column * dot, top, dot, dot, LABEL_INK);
+                }
+            }
+        }
+    }
+}
+
+/// Paints a rectangle, clipped to the frame.
+fn fill(frame: &mut Frame, x: u32, y: u32, width: u32, height: u32, color: [u8; 4]) {
+    for row in y..(y + height).min(frame.height) {
+        for column in x..(x + width).min(frame.width) {
+            let index = ((row * frame.width + column) * 4) as usize;
+            frame.pixels[index..index + 4].copy_from_slice(&color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, gray: u8) -> Frame {
+        Frame {
+            width,
+            height,
+            pixels: [gray, gray, gray, 255].repeat((width * height) as usize),
+            depth: vec![5.0; (width * height) as usize],
+        }
+    }
+
+    fn pixel(frame: &Frame, x: u32, y: u32) -> [u8; 4] {
+        let index = ((y * frame.width + x) * 4) as usize;
+        frame.pixels[index..index + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn labels_run_like_spreadsheet_columns() {
+        let labels: Vec<String> = [0, 1, 25, 26, 27, 701, 702]
+            .into_iter()
+            .map(panel_label)
+            .collect();
+        assert_eq!(labels, ["a", "b", "z", "aa", "ab", "zz", "aaa"]);
+    }
+
+    #[test]
+    fn tiles_panels_with_gaps_and_labels() {
+        let panels = [solid(100, 80, 50), solid(100, 80, 100), solid(100, 80, 150)];
+        let figure = compose(&panels, 2);
+        assert_eq!((figure.width, figure.height), (204, 164));
+        assert_eq!(pixel(&figure, 90, 70), [50, 50, 50, 255]);
+        assert_eq!(pixel(&figure, 102, 40), GAP_COLOR);
+        assert_eq!(pixel(&figure, 190, 70), [100, 100, 100, 255]);
+        assert_eq!(pixel(&figure, 90, 150), [150, 150, 150, 255]);
+        assert!(figure.depth[(150 * figure.width + 150) as usize].is_infinite());
+
+        // "a" at one pixel per dot: backing from (2, 2), ink on its third glyph row.
+        assert_eq!(pixel(&figure, 2, 2), LABEL_BACKING);
+        assert_eq!(pixel(&figure, 3 + 1, 3 + 2), LABEL_INK);
+        assert_eq!(pixel(&figure, 3, 3 + 2), LABEL_BACKING);
+        // "c" on the third panel.
+        assert_eq!(pixel(&figure, 3 + 1, 84 + 3 + 2), LABEL_INK);
+    }
+
+    #[test]
+    fn matched_cameras_share_the_widest_distance() {
+        let mut cameras = [Camera::default(), Camera::default()];
+        cameras[0].distance = 5.0;
+        cameras[1].distance = 12.0;
+        match_scale(&mut cameras);
+        assert!(cameras.iter().all(|camera| camera.distance == 12.0));
+    }
+}
@@ -0,0 +1,612 @@
+//! Crystallographic Information File input.
+//!
+//! The first data block is read: the cell from `_cell_length_*` and `_cell_angle_*`, the
+//! symmetry operations from `_space_group_symop_operation_xyz` (or the older
+//! `_symmetry_equiv_pos_as_xyz`) and the atoms from the `_atom_site_` loop, with fractional or,
+//! failing that, cartesian coordinates. The asymmetric unit is expanded by applying every
+//! operation and wrapping the images into the cell; images of an atom on a special position
+//! that land on each other are kept once. Labels go into `atom_name` and type symbols into
+//! `atom_type`. CIF files carry no bonds, so none are made.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+use glam::{Mat3, Vec3};
+
+use super::text::{self, Recovery};
+use crate::elements::{atomic_number, normalize_symbol};
+use crate::{Molecule, UnitCell};
+
+#[derive(Debug, Clone)]
+pub struct CifError {
+    details: String,
+}
+
+impl CifError {
+    fn new(line: usize, details: impl Into<String>) -> Self {
+        Self {
+            details: format!("line {line}: {}", details.into()),
+        }
+    }
+}
+
+impl fmt::Display for CifError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl std::error::Error for CifError {}
+
+/// What to read from a CIF file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CifOptions {
+    /// Fill the cell from the asymmetric unit; otherwise only the listed atoms are read.
+    pub expand_symmetry: bool,
+}
+
+impl Default for CifOptions {
+    fn default() -> Self {
+        Self {
+            expand_symmetry: true,
+        }
+    }
+}
+
+/// Images of one atom closer than this, in Å, are the same atom on a special position.
+const SPECIAL_POSITION_TOLERANCE: f32 = 0.1;
+
+const SYMMETRY_TAGS: [&str; 2] = [
+    "_space_group_symop_operation_xyz",
+    "_symmetry_equiv_pos_as_xyz",
+];
+
+pub fn parse_cif(contents: &str) -> Result<Molecule, CifError> {
+    parse_cif_with(contents, &CifOptions::default(), &mut Recovery::strict())
+}
+
+/// A value or keyword with the line it starts on.
+#[derive(Debug, Clone)]
+struct Token<'a> {
+    text: Cow<'a, str>,
+    line: usize,
+    /// Quoted values and text fields are never keywords, and never `?` or `.` placeholders.
+    quoted: bool,
+}
+
+impl Token<'_> {
+    fn is_keyword(&self) -> bool {
+        !self.quoted
+            && (self.text.starts_with('_')
+                || keyword(&self.text, "data_").is_some()
+                || self.text.eq_ignore_ascii_case("loop_"))
+    }
+
+    /// `None` for the `?` (unknown) and `.` (not applicable) placeholders.
+    fn known(&self) -> Option<&str> {
+        match &*self.text {
+            "?" | "." if !self.quoted => None,
+            text => Some(text),
+        }
+    }
+}
+
+/// The rest of `text` after the case-insensitive `prefix`.
+fn keyword<'t>(text: &'t str, prefix: &str) -> Option<&'t str> {
+    let head = text.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &text[prefix.len()..])
+}
+
+/// Tags are case-insensitive, and DDLm writes `_cell.length_a` for `_cell_length_a`.
+fn tag_name(text: &str) -> String {
+    text.to_ascii_lowercase().replace('.', "_")
+}
+
+fn tokens(contents: &str) -> Result<Vec<Token<'_>>, CifError> {
+    let mut tokens = Vec::new();
+    let mut lines = text::lines(contents);
+    while let Some(line) = lines.next() {
+        let Some(first) = line.text.strip_prefix(';') else {
+            split_line(line.text, line.number, &mut tokens)?;
+            continue;
+        };
+        // A text field runs to the next line starting with a semicolon.
+        let mut value = first.to_string();
+        loop {
+            let next = lines
+                .next()
+                .ok_or_else(|| CifError::new(line.number, "unterminated text field"))?;
+            if let Some(rest) = next.text.strip_prefix(';') {
+                tokens.push(Token {
+                    text: Cow::Owned(value.trim().to_string()),
+                    line: line.number,
+                    quoted: true,
+                });
+                split_line(rest, next.number, &mut tokens)?;
+                break;
+            }
+            value.push('\n');
+            value.push_str(next.text);
+        }
+    }
+    Ok(tokens)
+}
+
+fn split_line<'a>(text: &'a str, line: usize, tokens: &mut Vec<Token<'a>>) -> Result<(), CifError> {
+    let mut rest = text.trim_start();
+    while !rest.is_empty() && !rest.starts_with('#') {
+        let (value, after, quoted) = match rest.chars().next() {
+            Some(quote @ ('\'' | '"')) => {
+                // A quote closes the value only when followed by whitespace or the line end.
+                let body = &rest[1..];
+                let end = body
+                    .char_indices()
+                    .find(|&(index, c)| {
+                        c == quote
+                            && body[index + 1..]
+                                .chars()
+                                .next()
+                                .is_none_or(char::is_whitespace)
+                    })
+                    .map(|(index, _)| index)
+                    .ok_or_else(|| {
+                        CifError::new(line, format!("unterminated quoted value {rest}"))
+                    })?;
+                (&body[..end], &body[end + 1..], true)
+            }
+            _ => {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                (&rest[..end], &rest[end..], false)
+            }
+        };
+        tokens.push(Token {
+            text: Cow::Borrowed(value),
+            line,
+            quoted,
+        });
+        rest = after.trim_start();
+    }
+    Ok(())
+}
+
+struct Loop<'a> {
+    tags: Vec<String>,
+    rows: Vec<Vec<Token<'a>>>,
+}
+
+impl Loop<'_> {
+    fn column(&self, tag: &str) -> Option<usize> {
+        self.tags.iter().position(|name| name == tag)
+    }
+}
+
+struct Block<'a> {
+    name: String,
+    line: usize,
+    items: HashMap<String, Token<'a>>,
+    loops: Vec<Loop<'a>>,
+}
+
+impl Block<'_> {
+    fn item(&self, tag: &str) -> Option<&str> {
+        self.items.get(tag)?.known()
+    }
+}
+
+fn first_block(tokens: Vec<Token<'_>>) -> Result<Block<'_>, CifError> {
+    let last_line = tokens.last().map_or(1, |token| token.line);
+    let mut tokens = tokens.into_iter().peekable();
+    let mut block: Option<Block> = None;
+    while let Some(token) = tokens.next() {
+        if let Some(name) = keyword(&token.text, "data_").filter(|_| !token.quoted) {
+            if block.is_some() {
+                break;
+            }
+            block = Some(Block {
+                name: name.to_string(),
+                line: token.line,
+                items: HashMap::new(),
+                loops: Vec::new(),
+            });
+            continue;
+        }
+        let Some(block) = block.as_mut() else {
+            return Err(CifError::new(
+                token.line,
+                "content before the first data_ block",
+            ));
+        };
+        if !token.quoted && token.text.eq_ignore_ascii_case("loop_") {
+            let mut tags = Vec::new();
+            while let Some(tag) = tokens.next_if(|next| !next.quoted && next.text.starts_with('_'))
+            {
+                tags.push(tag_name(&tag.text));
+            }
+            let mut values = Vec::new();
+            while let Some(value) = tokens.next_if(|next| !next.is_keyword()) {
+                values.push(value);
+            }
+            if tags.is_empty() || values.len() % tags.len() != 0 {
+                return Err(CifError::new(
+                    token.line,
+                    format!("loop has {} tags and {} values", tags.len(), values.len()),
+                ));
+            }
+            let rows = values.chunks(tags.len()).map(<[Token]>::to_vec).collect();
+            block.loops.push(Loop { tags, rows });
+        } else if !token.quoted && token.text.starts_with('_') {
+            let value = tokens
+                .next_if(|next| !next.is_keyword())
+                .ok_or_else(|| CifError::new(token.line, format!("{} has no value", token.text)))?;
+            block.items.insert(tag_name(&token.text), value);
+        } else {
+            return Err(CifError::new(
+                token.line,
+                format!("unexpected value {}", token.text),
+            ));
+        }
+    }
+    block.ok_or_else(|| CifError::new(last_line, "no data_ block"))
+}
+
+/// A CIF number, without the standard uncertainty in parentheses (`5.4310(2)`).
+fn number(token: &Token, what: &str) -> Result<f32, CifError> {
+    let text = token.text.split('(').next().unwrap_or_default();
+    text.parse()
+        .map_err(|_| CifError::new(token.line, format!("invalid {what} {}", token.text)))
+}
+
+/// An operation on fractional coordinates, such as `-x,1/2+y,1/2-z`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SymmetryOperation {
+    rotation: Mat3,
+    translation: Vec3,
+}
+
+impl SymmetryOperation {
+    const IDENTITY: Self = Self {
+        rotation: Mat3::IDENTITY,
+        translation: Vec3::ZERO,
+    };
+
+    fn parse(text: &str) -> Option<Self> {
+        let parts: Vec<&str> = text.split(',').collect();
+        let [x, y, z] = parts[..] else {
+            return None;
+        };
+        let (row_x, shift_x) = parse_component(x)?;
+        let (row_y, shift_y) = parse_component(y)?;
+        let (row_z, shift_z) = parse_component(z)?;
+        Some(Self {
+            rotation: Mat3::from_cols(row_x, row_y, row_z).transpose(),
+            translation: Vec3::new(shift_x, shift_y, shift_z),
+        })
+    }
+
+    fn apply(&self, fractional: Vec3) -> Vec3 {
+        self.rotation * fractional + self.translation
+    }
+}
+
+/// One component such as `-x+1/2` or `x-y`: the coefficients of x, y and z, and the shift.
+fn parse_component(text: &str) -> Option<(Vec3, f32)> {
+    let compact: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    let mut rest = compact.as_str();
+    if rest.is_empty() {
+        return None;
+    }
+    let (mut row, mut shift) = (Vec3::ZERO, 0.0);
+    while !rest.is_empty() {
+        let (sign, after) = match rest.as_bytes()[0] {
+            b'-' => (-1.0, &rest[1..]),
+            b'+' => (1.0, &rest[1..]),
+            _ => (1.0, rest),
+        };
+        let end = after.find(['+', '-']).unwrap_or(after.len());
+        let term = &after[..end];
+        rest = &after[end..];
+        match term.find(['x', 'y', 'z']) {
+            Some(index) if index + 1 == term.len() => {
+                let coefficient = match term[..index].trim_end_matches('*') {
+                    "" => 1.0,
+                    coefficient => fraction(coefficient)?,
+                };
+                let axis = usize::from(term.as_bytes()[index] - b'x');
+                row[axis] += sign * coefficient;
+            }
+            Some(_) => return None,
+            None => shift += sign * fraction(term)?,
+        }
+    }
+    Some((row, shift))
+}
+
+fn fraction(text: &str) -> Option<f32> {
+    match text.split_once('/') {
+        Some((numerator, denominator)) => {
+            Some(numerator.parse::<f32>().ok()? / denominator.parse::<f32>().ok()?)
+        }
+        None => text.parse().ok(),
+    }
+}
+
+/// The element of a type symbol such as `Fe3+`, or of a label such as `C12a` or `Cl1`. A
+/// second letter only counts when lower case, so `CA1` is carbon.
+fn element_of(symbol: &str) -> Option<String> {
+    let mut letters = symbol.chars().take_while(char::is_ascii_alphabetic);
+    let first = letters.next()?;
+    let two: String = letters
+        .next()
+        .filter(char::is_ascii_lowercase)
+        .map(|second| [first, second].iter().collect())
+        .unwrap_or_default();
+    [two, first.to_string()]
+        .into_iter()
+        .find(|candidate| !candidate.is_empty() && atomic_number(candidate).is_some())
+        .map(|candidate| normalize_symbol(&candidate))
+}
+
+struct AtomRecord<'a> {
+    label: &'a str,
+    atom_type: Option<&'a str>,
+    element: String,
+    position: Vec3,
+}
+
+pub fn parse_cif_with(
+    contents: &str,
+    options: &CifOptions,
+    recovery: &mut Recovery,
+) -> Result<Molecule, CifError> {
+    let block = first_block(tokens(contents)?)?;
+
+    let cell_tags = [
+        "_cell_length_a",
+        "_cell_length_b",
+        "_cell_length_c",
+        "_cell_angle_alpha",
+        "_cell_angle_beta",
+        "_cell_angle_gamma",
+    ];
+    let cell = if block.item(cell_tags[0]).is_some() {
+        let mut values = [0.0; 6];
+        for (value, tag) in values.iter_mut().zip(cell_tags) {
+            let token = block
+                .items
+                .get(tag)
+                .filter(|token| token.known().is_some())
+                .ok_or_else(|| CifError::new(block.line, format!("missing {tag}")))?;
+            *value = number(token, tag)?;
+        }
+        let [a, b, c, alpha, beta, gamma] = values;
+        Some(UnitCell::new(a, b, c, alpha, beta, gamma))
+    } else {
+        None
+    };
+
+    let mut operations = Vec::new();
+    let symmetry_loop = block.loops.iter().find_map(|symmetry| {
+        SYMMETRY_TAGS
+            .iter()
+            .find_map(|tag| symmetry.column(tag))
+            .map(|column| (symmetry, column))
+    });
+    let listed: Vec<&Token> = match symmetry_loop {
+        Some((symmetry, column)) => symmetry.rows.iter().map(|row| &row[column]).collect(),
+        None => SYMMETRY_TAGS
+            .iter()
+            .filter_map(|tag| block.items.get(*tag))
+            .collect(),
+    };
+    for token in listed {
+        let operation = SymmetryOperation::parse(&token.text).ok_or_else(|| {
+            CifError::new(
+                token.line,
+                format!("invalid symmetry operation {}", token.text),
+            )
+        });
+        if let Some(operation) = recovery.skip_bad(token.line, operation)? {
+            operations.push(operation);
+        }
+    }
+    if operations.is_empty() {
+        operations.push(SymmetryOperation::IDENTITY);
+    }
+
+    let atoms = block
+        .loops
+        .iter()
+        .find(|atoms| atoms.column("_atom_site_label").is_some())
+        .ok_or_else(|| CifError::new(block.line, "no _atom_site_label loop"))?;
+    let columns = |names: [&str; 3]| -> Option<[usize; 3]> {
+        let [x, y, z] = names.map(|name| atoms.column(name));
+        Some([x?, y?, z?])
+    };
+    let (coordinates, fractional) = match (
+        columns([
+            "_atom_site_fract_x",
+            "_atom_site_fract_y",
+            "_atom_site_fract_z",
+        ]),
+        columns([
+            "_atom_site_cartn_x",
+            "_atom_site_cartn_y",
+            "_atom_site_cartn_z",
+        ]),
+    ) {
+        (Some(columns), _) if cell.is_some() => (columns, true),
+        (_, Some(columns)) => (columns, false),
+        (Some(_), None) => {
+            return Err(CifError::new(
+                block.line,
+                "fractional coordinates without a cell",
+            ))
+        }
+        (None, None) => return Err(CifError::new(block.line, "atoms without coordinates")),
+    };
+    let label_column = atoms.column("_atom_site_label").unwrap_or_default();
+    let type_column = atoms.column("_atom_site_type_symbol");
+    let mut records = Vec::with_capacity(atoms.rows.len());
+    for row in &atoms.rows {
+        let record = read_atom(row, label_column, type_column, coordinates);
+        if let Some(record) = recovery.skip_bad(row[0].line, record)? {
+            records.push(record);
+        }
+    }
+
+    let mut molecule = Molecule::new(
+        ["_chemical_name_common", "_chemical_name_systematic"]
+            .into_iter()
+            .find_map(|tag| block.item(tag))
+            .unwrap_or(&block.name),
+    );
+    let matrix = cell.map_or(Mat3::IDENTITY, |cell| cell.matrix());
+    for record in &records {
+        let positions = match cell.filter(|_| options.expand_symmetry) {
+            Some(_) => {
+                let start = if fractional {
+                    record.position
+                } else {
+                    matrix.inverse() * record.position
+                };
+                let mut images: Vec<Vec3> = Vec::with_capacity(operations.len());
+                for operation in &operations {
+                    let image = operation.apply(start);
+                    let image = image - image.floor();
+                    let repeated = images.iter().any(|&other| {
+                        let offset = image - other;
+                        (matrix * (offset - offset.round())).length() < SPECIAL_POSITION_TOLERANCE
+                    });
+                    if !repeated {
+                        images.push(image);
+                    }
+                }
+                images.into_iter().map(|image| matrix * image).collect()
+            }
+            None if fractional => vec![matrix * record.position],
+            None => vec![record.position],
+        };
+        for position in positions {
+            let id = molecule.insert_atom(record.element.clone(), position.to_array());
+            molecule.properties.set("atom_name", id, record.label);
+            if let Some(atom_type) = record.atom_type {
+                molecule.properties.set("atom_type", id, atom_type);
+            }
+        }
+    }
+    molecule.set_cell(cell);
+    Ok(molecule)
+}
+
+fn read_atom<'t>(
+    row: &'t [Token],
+    label: usize,
+    atom_type: Option<usize>,
+    coordinates: [usize; 3],
+) -> Result<AtomRecord<'t>, CifError> {
+    let label = &row[label];
+    let atom_type = atom_type.and_then(|column| row[column].known());
+    let element = atom_type
+        .and_then(element_of)
+        .or_else(|| element_of(&label.text))
+        .ok_or_else(|| CifError::new(label.line, format!("no element for atom {}", label.text)))?;
+    let [x, y, z] = coordinates.map(|column| number(&row[column], "coordinate"));
+    Ok(AtomRecord {
+        label: &label.text,
+        atom_type,
+        element,
+        position: Vec3::new(x?, y?, z?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONOCLINIC: &str = "\
+# P 21/c with one atom on an inversion centre
+data_test_phase
+_chemical_name_common 'copper(II) chloride'
+_cell_length_a    5.000(2)
+_cell_length_b    6.000
+_cell_length_c    7.000
+_cell_angle_alpha 90
+_cell_angle_beta  100.0(1)
+_cell_angle_gamma 90
+_publ_section_comment
+;
+Two lines of
+free text.
+;
+loop_
+_space_group_symop_operation_xyz
+'x, y, z'
+'-x, 1/2+y, 1/2-z'
+'-x, -y, -z'
+'x, 1/2-y, 1/2+z'
+loop_
+_atom_site_label
+_atom_site_type_symbol
+_atom_site_fract_x
+_atom_site_fract_y
+_atom_site_fract_z
+Cu1 Cu2+ 0.0 0.0 0.0
+Cl1 Cl1- 0.2500(3) 0.1000(2) 0.3000(4)
+";
+
+    #[test]
+    fn expands_the_asymmetric_unit_into_the_cell() {
+        let molecule = parse_cif(MONOCLINIC).unwrap();
+        assert_eq!(molecule.name, "copper(II) chloride");
+        let cell = molecule.cell().unwrap();
+        assert_eq!((cell.a, cell.beta), (5.0, 100.0));
+        // Copper sits on the inversion centre: two images, not four.
+        assert_eq!(molecule.formula(), "Cl4Cu2");
+        let atoms: Vec<_> = molecule.atoms_in_order().collect();
+        assert_eq!(
+            molecule.properties.get("atom_name", atoms[2].id),
+            Some("Cl1")
+        );
+        assert_eq!(
+            molecule.properties.get("atom_type", atoms[0].id),
+            Some("Cu2+")
+        );
+        let image = cell.to_fractional(atoms[3].position);
+        for (got, expected) in image.iter().zip([0.75, 0.6, 0.2]) {
+            assert!((got - expected).abs() < 1e-4, "{image:?}");
+        }
+
+        let options = CifOptions {
+            expand_symmetry: false,
+        };
+        let unit = parse_cif_with(MONOCLINIC, &options, &mut Recovery::strict()).unwrap();
+        assert_eq!(unit.atom_count(), 2);
+    }
+
+    #[test]
+    fn reads_operations_and_reports_bad_rows() {
+        let operation = SymmetryOperation::parse("x-y, X, -z+0.5").unwrap();
+        let image = operation.apply(Vec3::new(0.3, 0.1, 0.2));
+        assert!(image.distance(Vec3::new(0.2, 0.3, 0.3)) < 1e-6);
+        assert_eq!(SymmetryOperation::parse("x, y"), None);
+        assert_eq!(element_of("C12a").as_deref(), Some("C"));
+        assert_eq!(element_of("CA1").as_deref(), Some("C"));
+        assert_eq!(element_of("Fe3+").as_deref(), Some("Fe"));
+
+        let bad = MONOCLINIC.replace("0.1000(2)", "0.1x00");
+        assert_eq!(
+            parse_cif(&bad).unwrap_err().to_string(),
+            "line 28: invalid coordinate 0.1x00"
+        );
+        let mut recovery = Recovery::lenient();
+        let molecule = parse_cif_with(&bad, &CifOptions::default(), &mut recovery).unwrap();
+        assert_eq!(molecule.formula(), "Cu2");
+        assert_eq!(recovery.into_problems().len(), 1);
+    }
+}
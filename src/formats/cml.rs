@@ -0,0 +1,557 @@
+//! Chemical Markup Language input and output.
+//!
+//! The first `molecule` element is read, with its atoms and bonds in either the element form
+//! (`<atom id="a1" elementType="C" x3="…"/>`) or the older array form
+//! (`<atomArray atomID="a1 a2" elementType="C O" x3="…"/>`). Atoms need `x3`/`y3`/`z3` or,
+//! failing that, `x2`/`y2` coordinates. Formal charges and bond orders are kept; aromatic
+//! bonds load as single bonds and `hydrogenCount` is ignored. Namespace prefixes are dropped,
+//! and everything outside atoms, bonds and the molecule name is skipped.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::sync::Arc;
+
+use crate::valence::{StandardValence, Unrestricted};
+use crate::{AtomId, Molecule};
+
+#[derive(Debug, Clone)]
+pub struct CmlError {
+    details: String,
+}
+
+impl CmlError {
+    fn new(line: usize, details: impl Into<String>) -> Self {
+        Self {
+            details: format!("line {line}: {}", details.into()),
+        }
+    }
+}
+
+impl fmt::Display for CmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl std::error::Error for CmlError {}
+
+/// A start, end or empty-element tag.
+struct Tag<'a> {
+    /// Local name, without any namespace prefix.
+    name: &'a str,
+    attributes: Vec<(&'a str, String)>,
+    /// Text between the previous tag and this one.
+    text: &'a str,
+    line: usize,
+    end: bool,
+    empty: bool,
+}
+
+impl Tag<'_> {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whitespace-separated values of an array attribute.
+    fn values(&self, name: &str) -> Vec<&str> {
+        self.attribute(name)
+            .map_or_else(Vec::new, |value| value.split_whitespace().collect())
+    }
+}
+
+/// Tags of an XML document, skipping the declaration, comments, CDATA and doctype.
+struct Tags<'a> {
+    contents: &'a str,
+    offset: usize,
+    line: usize,
+    /// Offset up to which newlines are counted in `line`.
+    counted: usize,
+}
+
+impl<'a> Tags<'a> {
+    fn new(contents: &'a str) -> Self {
+        Self {
+            contents: contents.strip_prefix('\u{feff}').unwrap_or(contents),
+            offset: 0,
+            line: 1,
+            counted: 0,
+        }
+    }
+
+    fn line_at(&mut self, offset: usize) -> usize {
+        self.line += self.contents[self.counted..offset].matches('\n').count();
+        self.counted = offset;
+        self.line
+    }
+
+    fn read_tag(&mut self, start: usize, text: &'a str) -> Result<Tag<'a>, CmlError> {
+        let line = self.line_at(start);
+        let bytes = self.contents.as_bytes();
+        let mut position = start + 1;
+        let end = bytes.get(position) == Some(&b'/');
+        if end {
+            position += 1;
+        }
+        let name_end = self.contents[position..]
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .map_or(self.contents.len(), |length| position + length);
+        let qualified = &self.contents[position..name_end];
+        if qualified.is_empty() {
+            return Err(CmlError::new(line, "tag without a name"));
+        }
+        let name = qualified.rsplit(':').next().unwrap_or(qualified);
+        position = name_end;
+        let mut attributes = Vec::new();
+        loop {
+            let rest = self.contents[position..].trim_start();
+            if let Some(after) = rest.strip_prefix("/>") {
+                self.offset = self.contents.len() - after.len();
+                return Ok(Tag {
+                    name,
+                    attributes,
+                    text,
+                    line,
+                    end,
+                    empty: true,
+                });
+            }
+            if let Some(after) = rest.strip_prefix('>') {
+                self.offset = self.contents.len() - after.len();
+                return Ok(Tag {
+                    name,
+                    attributes,
+                    text,
+                    line,
+                    end,
+                    empty: false,
+                });
+            }
+            let (key, after) = rest
+                .split_once('=')
+                .filter(|(key, _)| !key.trim().is_empty() && !key.contains(['<', '>']))
+                .ok_or_else(|| CmlError::new(line, format!("unterminated tag {qualified}")))?;
+            let key = key.trim();
+            let after = after.trim_start();
+            let quote = after
+                .chars()
+                .next()
+                .filter(|c| matches!(c, '"' | '\''))
+                .ok_or_else(|| CmlError::new(line, format!("unquoted attribute {key}")))?;
+            let (value, after) = after[1..]
+                .split_once(quote)
+                .ok_or_else(|| CmlError::new(line, format!("unterminated attribute {key}")))?;
+            let value = unescape(value).map_err(|details| CmlError::new(line, details))?;
+            attributes.push((key.rsplit(':').next().unwrap_or(key), value.into_owned()));
+            position = self.contents.len() - after.len();
+        }
+    }
+}
+
+impl<'a> Iterator for Tags<'a> {
+    type Item = Result<Tag<'a>, CmlError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut text_start = self.offset;
+        loop {
+            let start = self.offset + self.contents[self.offset..].find('<')?;
+            let rest = &self.contents[start..];
+            let skipped = [
+                ("<!--", "-->"),
+                ("<![CDATA[", "]]>"),
+                ("<?", "?>"),
+                ("<!", ">"),
+            ]
+            .into_iter()
+            .find(|(open, _)| rest.starts_with(open));
+            let Some((_, close)) = skipped else {
+                let text = &self.contents[text_start..start];
+                return Some(self.read_tag(start, text));
+            };
+            let Some(length) = rest.find(close) else {
+                let line = self.line_at(start);
+                self.offset = self.contents.len();
+                return Some(Err(CmlError::new(line, "unterminated markup")));
+            };
+            self.offset = start + length + close.len();
+            text_start = self.offset;
+        }
+    }
+}
+
+/// `value` with XML character and entity references replaced.
+fn unescape(value: &str) -> Result<Cow<'_, str>, String> {
+    if !value.contains('&') {
+        return Ok(Cow::Borrowed(value));
+    }
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let (reference, after) = rest[start + 1..]
+            .split_once(';')
+            .ok_or_else(|| format!("unterminated reference in {value}"))?;
+        let c = match reference {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match reference.strip_prefix('#') {
+                Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16)
+                    .ok()
+                    .and_then(char::from_u32),
+                Some(decimal) => decimal.parse().ok().and_then(char::from_u32),
+                None => None,
+            },
+        };
+        out.push(c.ok_or_else(|| format!("unknown reference &{reference};"))?);
+        rest = after;
+    }
+    out.push_str(rest);
+    Ok(Cow::Owned(out))
+}
+
+fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['<', '>', '&', '"']) {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;"),
+    )
+}
+
+struct AtomRecord {
+    id: String,
+    element: String,
+    position: [f32; 3],
+    charge: i32,
+    line: usize,
+}
+
+struct BondRecord {
+    a: String,
+    b: String,
+    order: u8,
+    line: usize,
+}
+
+pub fn parse_cml(contents: &str) -> Result<Molecule, CmlError> {
+    let mut name = None;
+    let mut atoms = Vec::new();
+    let mut bonds = Vec::new();
+    let mut depth = 0;
+    let mut last_line = 1;
+    for tag in Tags::new(contents) {
+        let tag = tag?;
+        last_line = tag.line;
+        match (tag.name, tag.end) {
+            ("molecule", false) => {
+                if depth == 0 {
+                    name = tag.attribute("title").map(str::to_string);
+                }
+                if !tag.empty {
+                    depth += 1;
+                }
+            }
+            ("molecule", true) if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            _ if depth == 0 => {}
+            ("name", true) if depth == 1 && name.is_none() => {
+                let text = unescape(tag.text.trim()).map_err(|err| CmlError::new(tag.line, err))?;
+                name = Some(text.into_owned());
+            }
+            ("atom", false) => atoms.push(read_atom(&tag)?),
+            ("atomArray", false) if tag.attribute("atomID").is_some() => {
+                atoms.extend(read_atom_array(&tag)?);
+            }
+            ("bond", false) => bonds.push(read_bond(&tag)?),
+            ("bondArray", false) if tag.attribute("atomRef1").is_some() => {
+                bonds.extend(read_bond_array(&tag)?);
+            }
+            _ => {}
+        }
+    }
+    if atoms.is_empty() {
+        return Err(CmlError::new(last_line, "no molecule with atoms"));
+    }
+
+    let mut molecule = Molecule::new(name.unwrap_or_default());
+    molecule.set_valence_model(Arc::new(Unrestricted));
+    let mut ids: HashMap<&str, AtomId> = HashMap::with_capacity(atoms.len());
+    for atom in &atoms {
+        let id = molecule.insert_atom(atom.element.clone(), atom.position);
+        molecule.set_formal_charge(id, atom.charge);
+        if ids.insert(&atom.id, id).is_some() {
+            return Err(CmlError::new(
+                atom.line,
+                format!("duplicate atom id {}", atom.id),
+            ));
+        }
+    }
+    for bond in &bonds {
+        let atom = |reference: &str| {
+            ids.get(reference).copied().ok_or_else(|| {
+                CmlError::new(bond.line, format!("bond to unknown atom {reference}"))
+            })
+        };
+        let id = molecule
+            .add_bond(atom(&bond.a)?, atom(&bond.b)?)
+            .map_err(|err| CmlError::new(bond.line, err))?;
+        molecule.set_bond_order(id, bond.order);
+    }
+    molecule.set_valence_model(Arc::new(StandardValence));
+    Ok(molecule)
+}
+
+fn number<T: std::str::FromStr>(tag: &Tag, what: &str, value: &str) -> Result<T, CmlError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| CmlError::new(tag.line, format!("invalid {what} {value}")))
+}
+
+fn read_atom(tag: &Tag) -> Result<AtomRecord, CmlError> {
+    let id = tag.attribute("id").unwrap_or_default();
+    let element = tag
+        .attribute("elementType")
+        .ok_or_else(|| CmlError::new(tag.line, format!("atom {id} has no elementType")))?;
+    let coordinate = |axis: &str| -> Result<Option<f32>, CmlError> {
+        tag.attribute(axis)
+            .map(|value| number(tag, axis, value))
+            .transpose()
+    };
+    let position = match (coordinate("x3")?, coordinate("y3")?, coordinate("z3")?) {
+        (Some(x), Some(y), Some(z)) => [x, y, z],
+        _ => match (coordinate("x2")?, coordinate("y2")?) {
+            (Some(x), Some(y)) => [x, y, 0.0],
+            _ => {
+                return Err(CmlError::new(
+                    tag.line,
+                    format!("atom {id} has no coordinates"),
+                ))
+            }
+        },
+    };
+    let charge = match tag.attribute("formalCharge") {
+        Some(value) => number(tag, "formalCharge", value)?,
+        None => 0,
+    };
+    Ok(AtomRecord {
+        id: id.to_string(),
+        element: element.to_string(),
+        position,
+        charge,
+        line: tag.line,
+    })
+}
+
+fn read_atom_array(tag: &Tag) -> Result<Vec<AtomRecord>, CmlError> {
+    let ids = tag.values("atomID");
+    let column = |name: &str| -> Result<Option<Vec<&str>>, CmlError> {
+        let values = tag.values(name);
+        match values.len() {
+            0 => Ok(None),
+            length if length == ids.len() => Ok(Some(values)),
+            length => Err(CmlError::new(
+                tag.line,
+                format!("{name} has {length} values for {} atoms", ids.len()),
+            )),
+        }
+    };
+    let elements = column("elementType")?
+        .ok_or_else(|| CmlError::new(tag.line, "atomArray without elementType"))?;
+    let (x, y, z) = match (column("x3")?, column("y3")?, column("z3")?) {
+        (Some(x), Some(y), Some(z)) => (x, y, Some(z)),
+        _ => match (column("x2")?, column("y2")?) {
+            (Some(x), Some(y)) => (x, y, None),
+            _ => return Err(CmlError::new(tag.line, "atomArray without coordinates")),
+        },
+    };
+    let charges = column("formalCharge")?;
+    (0..ids.len())
+        .map(|index| {
+            Ok(AtomRecord {
+                id: ids[index].to_string(),
+                element: elements[index].to_string(),
+                position: [
+                    number(tag, "x", x[index])?,
+                    number(tag, "y", y[index])?,
+                    z.as_ref().map_or(Ok(0.0), |z| number(tag, "z", z[index]))?,
+                ],
+                charge: charges
+                    .as_ref()
+                    .map_or(Ok(0), |charges| number(tag, "formalCharge", charges[index]))?,
+                line: tag.line,
+            })
+        })
+        .collect()
+}
+
+/// CML orders are numbers or S, D, T and A; aromatic and unknown orders load as single.
+fn bond_order(order: Option<&str>) -> u8 {
+    match order.map(str::trim) {
+        Some("2" | "D") => 2,
+        Some("3" | "T") => 3,
+        _ => 1,
+    }
+}
+
+fn read_bond(tag: &Tag) -> Result<BondRecord, CmlError> {
+    let [a, b] = tag.values("atomRefs2")[..] else {
+        return Err(CmlError::new(tag.line, "bond without two atomRefs2"));
+    };
+    Ok(BondRecord {
+        a: a.to_string(),
+        b: b.to_string(),
+        order: bond_order(tag.attribute("order")),
+        line: tag.line,
+    })
+}
+
+fn read_bond_array(tag: &Tag) -> Result<Vec<BondRecord>, CmlError> {
+    let first = tag.values("atomRef1");
+    let second = tag.values("atomRef2");
+    let orders = tag.values("order");
+    if second.len() != first.len() || !(orders.is_empty() || orders.len() == first.len()) {
+        return Err(CmlError::new(
+            tag.line,
+            "bondArray columns differ in length",
+        ));
+    }
+    Ok(first
+        .iter()
+        .zip(&second)
+        .enumerate()
+        .map(|(index, (a, b))| BondRecord {
+            a: a.to_string(),
+            b: b.to_string(),
+            order: bond_order(orders.get(index).copied()),
+            line: tag.line,
+        })
+        .collect())
+}
+
+/// One `molecule` element in the CML schema namespace, with 3D coordinates, formal charges
+/// and bond orders.
+pub fn write_cml(molecule: &Molecule) -> String {
+    let atoms: Vec<_> = molecule.atoms_in_order().collect();
+    let index: HashMap<AtomId, usize> = atoms
+        .iter()
+        .enumerate()
+        .map(|(position, atom)| (atom.id, position + 1))
+        .collect();
+    let mut bonds: Vec<_> = molecule.bonds().collect();
+    bonds.sort_by_key(|bond| bond.id);
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        out,
+        "<molecule xmlns=\"http://www.xml-cml.org/schema\" id=\"m1\" title=\"{}\">",
+        escape(molecule.name.lines().next().unwrap_or("").trim())
+    );
+    out.push_str("  <atomArray>\n");
+    for atom in &atoms {
+        let [x, y, z] = atom.position;
+        let _ = write!(
+            out,
+            "    <atom id=\"a{}\" elementType=\"{}\" x3=\"{x:.6}\" y3=\"{y:.6}\" z3=\"{z:.6}\"",
+            index[&atom.id],
+            escape(&atom.element)
+        );
+        if atom.charge != 0 {
+            let _ = write!(out, " formalCharge=\"{}\"", atom.charge);
+        }
+        out.push_str("/>\n");
+    }
+    out.push_str("  </atomArray>\n");
+    if !bonds.is_empty() {
+        out.push_str("  <bondArray>\n");
+        for bond in &bonds {
+            let _ = writeln!(
+                out,
+                "    <bond atomRefs2=\"a{} a{}\" order=\"{}\"/>",
+                index[&bond.a],
+                index[&bond.b],
+                bond.order.clamp(1, 3)
+            );
+        }
+        out.push_str("  </bondArray>\n");
+    }
+    out.push_str("</molecule>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_element_and_array_forms() {
+        let elements = r#"<?xml version="1.0"?>
+<!-- written by an old pipeline -->
+<cml:cml xmlns:cml="http://www.xml-cml.org/schema">
+  <cml:molecule id="m1">
+    <cml:name>formate &amp; co</cml:name>
+    <cml:atomArray>
+      <cml:atom id="c1" elementType="C" x3="0.0" y3="0.0" z3="0.0"/>
+      <cml:atom id="o1" elementType="O" x3="1.25" y3="0.0" z3="0.0"/>
+      <cml:atom id="o2" elementType="O" x2="-0.6" y2="1.1" formalCharge="-1"/>
+    </cml:atomArray>
+    <cml:bondArray>
+      <cml:bond atomRefs2="c1 o1" order="D"/>
+      <cml:bond atomRefs2="c1 o2" order="1"/>
+    </cml:bondArray>
+  </cml:molecule>
+</cml:cml>
+"#;
+        let arrays = r#"<molecule title="formate &amp; co">
+  <atomArray atomID="c1 o1 o2" elementType="C O O" x2="0.0 1.25 -0.6" y2="0.0 0.0 1.1"
+             formalCharge="0 0 -1"/>
+  <bondArray atomRef1="c1 c1" atomRef2="o1 o2" order="2 1"/>
+</molecule>"#;
+        for contents in [elements, arrays] {
+            let molecule = parse_cml(contents).unwrap();
+            assert_eq!(molecule.name, "formate & co");
+            let atoms: Vec<_> = molecule.atoms_in_order().collect();
+            assert_eq!(atoms[2].position, [-0.6, 1.1, 0.0]);
+            assert_eq!(atoms[2].charge, -1);
+            let mut orders: Vec<u8> = molecule.bonds().map(|bond| bond.order).collect();
+            orders.sort();
+            assert_eq!(orders, [1, 2]);
+        }
+
+        let dangling = elements.replace("c1 o2", "c1 o9");
+        assert_eq!(
+            parse_cml(&dangling).unwrap_err().to_string(),
+            "line 13: bond to unknown atom o9"
+        );
+    }
+
+    #[test]
+    fn written_molecule_reads_back() {
+        let mut molecule = parse_cml(
+            r#"<molecule><atomArray atomID="a b" elementType="N H" x3="0 1" y3="0 0" z3="0 0"/>
+<bondArray atomRef1="a" atomRef2="b"/></molecule>"#,
+        )
+        .unwrap();
+        molecule.name = "<ammonium> \"fragment\"".to_string();
+        molecule.set_formal_charge(molecule.atom_ids()[0], 1);
+        let reread = parse_cml(&write_cml(&molecule)).unwrap();
+        assert_eq!(reread.name, molecule.name);
+        assert_eq!(reread.formula(), molecule.formula());
+        let charges: Vec<i32> = reread.atoms_in_order().map(|atom| atom.charge).collect();
+        assert_eq!(charges, [1, 0]);
+        assert_eq!(reread.bonds().count(), 1);
+    }
+}
@@ -0,0 +1,415 @@
+//! Extended XYZ, as written by ASE and many simulation packages: the comment line of each
+//! frame holds `key=value` pairs, and its `Properties` key lists the atom columns as
+//! `name:type:count` triples, e.g. `Properties=species:S:1:pos:R:3:charges:R:1:forces:R:3`.
+//!
+//! [`parse_xyz`](crate::parse_xyz) reads a frame as extended XYZ when its comment line has a
+//! `Properties` key. Columns besides species and positions become per-atom properties named
+//! after the column, kept as written, with the values of a multi-column entry such as forces
+//! separated by spaces. `Lattice` becomes the unit cell, which keeps only lengths and angles,
+//! `name` the molecule name, and every other key goes to [`Molecule::info`] as written; a bare
+//! key reads as `T`.
+
+use std::fmt::Write as _;
+
+use glam::{Mat3, Vec3};
+
+use super::text::{FieldError, Line};
+use crate::{Molecule, UnitCell};
+
+/// Key of the atom column list.
+pub const PROPERTIES_KEY: &str = "Properties";
+/// Key of the lattice vectors, nine numbers a, b, c.
+pub const LATTICE_KEY: &str = "Lattice";
+/// Key holding the molecule name.
+pub const NAME_KEY: &str = "name";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Column {
+    name: String,
+    /// `S`, `R`, `I` or `L`.
+    kind: char,
+    count: usize,
+}
+
+/// What an extended XYZ comment line says about its frame.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Header {
+    columns: Vec<Column>,
+    pub name: Option<String>,
+    pub cell: Option<UnitCell>,
+    pub info: Vec<(String, String)>,
+}
+
+/// One atom line read against a [`Header`].
+pub(crate) struct AtomLine<'a> {
+    pub element: &'a str,
+    pub position: [f32; 3],
+    /// Property name and value for each column besides species and positions.
+    pub values: Vec<(&'a str, String)>,
+}
+
+impl Header {
+    /// `None` when `comment` has no `Properties` key, so the frame is plain XYZ.
+    pub fn parse(comment: &str) -> Result<Option<Self>, String> {
+        if !comment.to_ascii_lowercase().contains("properties=") {
+            return Ok(None);
+        }
+        let pairs = split_pairs(comment)?;
+        let Some(spec) = pairs
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(PROPERTIES_KEY))
+            .map(|(_, value)| value)
+        else {
+            return Ok(None);
+        };
+        let mut header = Self {
+            columns: parse_columns(spec)?,
+            name: None,
+            cell: None,
+            info: Vec::new(),
+        };
+        for (key, value) in pairs {
+            if key.eq_ignore_ascii_case(PROPERTIES_KEY) {
+                continue;
+            } else if key.eq_ignore_ascii_case(LATTICE_KEY) {
+                header.cell = Some(parse_lattice(&value)?);
+            } else if key == NAME_KEY {
+                header.name = Some(value);
+            } else {
+                header.info.push((key, value));
+            }
+        }
+        Ok(Some(header))
+    }
+
+    pub fn read_atom<'a>(&'a self, line: &Line<'a>) -> Result<AtomLine<'a>, FieldError> {
+        let mut fields = line.fields();
+        let mut element = None;
+        let mut position = None;
+        let mut values = Vec::new();
+        for column in &self.columns {
+            match column.name.as_str() {
+                "species" => element = Some(fields.expect("species")?.text),
+                "pos" => {
+                    let x = fields.expect("x")?.parse("x")?;
+                    let y = fields.expect("y")?.parse("y")?;
+                    let z = fields.expect("z")?.parse("z")?;
+                    position = Some([x, y, z]);
+                }
+                name => {
+                    let mut parts = Vec::with_capacity(column.count);
+                    for _ in 0..column.count {
+                        let field = fields.expect(name)?;
+                        match column.kind {
+                            'R' => drop(field.parse::<f64>(name)?),
+                            'I' => drop(field.parse::<i64>(name)?),
+                            _ => {}
+                        }
+                        parts.push(field.text);
+                    }
+                    values.push((name, parts.join(" ")));
+                }
+            }
+        }
+        // Both columns are required by `parse_columns`.
+        Ok(AtomLine {
+            element: element.unwrap_or_default(),
+            position: position.unwrap_or_default(),
+            values,
+        })
+    }
+}
+
+/// The `key=value` pairs of a comment line in order. Values may be quoted with `"` (`\"` and
+/// `\\` escape inside) or braces.
+fn split_pairs(comment: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = Vec::new();
+    let mut chars = comment.trim().chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(pairs);
+        }
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+            key.push(c);
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.next_if_eq(&'=').is_none() {
+            pairs.push((key, "T".to_string()));
+            continue;
+        }
+        if key.is_empty() {
+            return Err("`=` without a key in the comment line".to_string());
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut value = String::new();
+        match chars.peek() {
+            Some('"') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('\\') => value.extend(chars.next()),
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(format!("unterminated quote in the value of {key}")),
+                    }
+                }
+            }
+            Some('{') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(format!("unterminated brace in the value of {key}")),
+                    }
+                }
+            }
+            _ => {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    value.push(c);
+                }
+            }
+        }
+        pairs.push((key, value));
+    }
+}
+
+fn parse_columns(spec: &str) -> Result<Vec<Column>, String> {
+    let parts: Vec<&str> = spec.split(':').collect();
+    if !parts.len().is_multiple_of(3) {
+        return Err(format!(
+            "Properties={spec} is not a list of name:type:count"
+        ));
+    }
+    let mut columns = Vec::new();
+    for triple in parts.chunks(3) {
+        let name = triple[0].to_string();
+        let kind = match triple[1] {
+            "S" | "R" | "I" | "L" => triple[1].chars().next().unwrap_or('S'),
+            other => return Err(format!("unknown column type {other} for {name}")),
+        };
+        let count = triple[2]
+            .parse()
+            .ok()
+            .filter(|&count| count > 0)
+            .ok_or_else(|| format!("invalid column count {} for {name}", triple[2]))?;
+        if columns.iter().any(|column: &Column| column.name == name) {
+            return Err(format!("column {name} is listed twice"));
+        }
+        columns.push(Column { name, kind, count });
+    }
+    let has = |name: &str, kind: char, count: usize| {
+        columns.contains(&Column {
+            name: name.to_string(),
+            kind,
+            count,
+        })
+    };
+    if !has("species", 'S', 1) {
+        return Err("Properties has no species:S:1 column".to_string());
+    }
+    if !has("pos", 'R', 3) {
+        return Err("Properties has no pos:R:3 column".to_string());
+    }
+    Ok(columns)
+}
+
+fn parse_lattice(value: &str) -> Result<UnitCell, String> {
+    let numbers = value
+        .split_whitespace()
+        .map(|part| part.parse::<f32>().ok())
+        .collect::<Option<Vec<_>>>()
+        .filter(|numbers| numbers.len() == 9)
+        .ok_or_else(|| format!("Lattice={value} is not nine numbers"))?;
+    Ok(UnitCell::from_matrix(Mat3::from_cols_slice(&numbers)))
+}
+
+/// `value` as it can stand after `=`: quoted when it holds whitespace, quotes or `=`.
+fn quote(value: &str) -> String {
+    if !value.is_empty()
+        && !value.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '=' | '\\' | '{'))
+    {
+        return value.to_string();
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Type and width of the column a property is written as: integer, real or logical when
+/// every value fits, else a one-wide string with whitespace replaced by `_`.
+fn column_for(name: &str, values: &[Option<&str>]) -> Column {
+    let present: Vec<&str> = values.iter().flatten().copied().collect();
+    let count = present
+        .first()
+        .map_or(1, |value| value.split_whitespace().count());
+    let uniform = count > 0
+        && present
+            .iter()
+            .all(|value| value.split_whitespace().count() == count);
+    let tokens = || present.iter().flat_map(|value| value.split_whitespace());
+    let kind = if !uniform {
+        'S'
+    } else if tokens().all(|token| token.parse::<i64>().is_ok()) {
+        'I'
+    } else if tokens().all(|token| token.parse::<f64>().is_ok()) {
+        'R'
+    } else if tokens().all(|token| matches!(token, "T" | "F" | "True" | "False")) {
+        'L'
+    } else {
+        'S'
+    };
+    Column {
+        name: name
+            .chars()
+            .map(|c| {
+                if c.is_whitespace() || c == ':' {
+                    '_'
+                } else {
+                    c
+                }
+            })
+            .collect(),
+        kind,
+        count: if kind == 'S' { 1 } else { count },
+    }
+}
+
+/// The molecule as one extended XYZ frame: `Lattice` from the cell, `name` and the entries
+/// of [`Molecule::info`] on the comment line, then species, positions with `precision`
+/// decimal places and one column per atom property. Atoms without a value get `0`, `F` or
+/// `_` depending on the column type.
+pub fn write_extxyz(molecule: &Molecule, precision: usize) -> String {
+    let atoms: Vec<_> = molecule.atoms_in_order().collect();
+    let properties: Vec<(&str, Column)> = molecule
+        .properties
+        .names()
+        .filter(|name| !matches!(*name, "species" | "pos"))
+        .map(|name| {
+            let values: Vec<Option<&str>> = atoms
+                .iter()
+                .map(|atom| molecule.properties.get(name, atom.id))
+                .collect();
+            (name, column_for(name, &values))
+        })
+        .collect();
+
+    let mut out = format!("{}\n", atoms.len());
+    if let Some(cell) = molecule.cell() {
+        let matrix = cell.matrix();
+        let numbers: Vec<String> = [matrix.x_axis, matrix.y_axis, matrix.z_axis]
+            .iter()
+            .flat_map(|axis: &Vec3| axis.to_array())
+            .map(|value| value.to_string())
+            .collect();
+        let _ = write!(out, "{LATTICE_KEY}=\"{}\" ", numbers.join(" "));
+    }
+    let _ = write!(out, "{PROPERTIES_KEY}=species:S:1:pos:R:3");
+    for (_, column) in &properties {
+        let _ = write!(out, ":{}:{}:{}", column.name, column.kind, column.count);
+    }
+    let name = molecule.name.lines().next().unwrap_or("").trim();
+    if !name.is_empty() {
+        let _ = write!(out, " {NAME_KEY}={}", quote(name));
+    }
+    for (key, value) in &molecule.info {
+        let key: String = key.split_whitespace().collect::<Vec<_>>().join("_");
+        let _ = write!(out, " {}={}", key.replace('=', "_"), quote(value));
+    }
+    out.push('\n');
+
+    let width = precision + 6;
+    for atom in atoms {
+        let [x, y, z] = atom.position;
+        let _ = write!(
+            out,
+            "{:<2} {x:>width$.precision$} {y:>width$.precision$} {z:>width$.precision$}",
+            atom.element
+        );
+        for (name, column) in &properties {
+            match molecule.properties.get(name, atom.id) {
+                Some(value) if column.kind == 'S' => {
+                    let word = value.split_whitespace().collect::<Vec<_>>().join("_");
+                    let _ = write!(out, " {}", if word.is_empty() { "_" } else { &word });
+                }
+                Some(value) => {
+                    for token in value.split_whitespace() {
+                        let _ = write!(out, " {token}");
+                    }
+                }
+                None => {
+                    let blank = match column.kind {
+                        'S' => "_",
+                        'L' => "F",
+                        _ => "0",
+                    };
+                    for _ in 0..column.count {
+                        let _ = write!(out, " {blank}");
+                    }
+                }
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_xyz;
+
+    const WATER: &str = r#"3
+Lattice="10.0 0.0 0.0 0.0 10.0 0.0 0.0 0.0 12.0" Properties=species:S:1:pos:R:3:charges:R:1:forces:R:3:fixed:L:1 energy=-14.2 pbc="T T T" name="water dimer half" converged
+O 0.0 0.0 0.0 -0.8 0.01 0.02 0.03 F
+H 0.96 0.0 0.0 0.4 -0.01 0.0 0.0 T
+H -0.24 0.93 0.0 0.4 0.0 -0.02 -0.03 F
+"#;
+
+    #[test]
+    fn reads_metadata_and_columns_then_writes_them_back() {
+        let molecule = parse_xyz(WATER).unwrap();
+        assert_eq!(molecule.name, "water dimer half");
+        assert_eq!(molecule.atom_count(), 3);
+        let cell = molecule.cell().unwrap();
+        assert!((cell.c - 12.0).abs() < 1e-5 && (cell.gamma - 90.0).abs() < 1e-3);
+        assert_eq!(
+            molecule.info.get("energy").map(String::as_str),
+            Some("-14.2")
+        );
+        assert_eq!(molecule.info.get("pbc").map(String::as_str), Some("T T T"));
+        assert_eq!(
+            molecule.info.get("converged").map(String::as_str),
+            Some("T")
+        );
+        let o = molecule.atom_ids()[0];
+        assert_eq!(molecule.properties.get("charges", o), Some("-0.8"));
+        assert_eq!(molecule.properties.get("forces", o), Some("0.01 0.02 0.03"));
+
+        let written = write_extxyz(&molecule, 4);
+        let comment = written.lines().nth(1).unwrap();
+        assert!(comment.contains("Properties=species:S:1:pos:R:3:charges:R:1:fixed:L:1:forces:R:3"));
+        assert!(comment.contains("energy=-14.2") && comment.contains("pbc=\"T T T\""));
+        let reread = parse_xyz(&written).unwrap();
+        assert_eq!(reread.name, molecule.name);
+        assert_eq!(reread.info, molecule.info);
+        assert_eq!(reread.properties, molecule.properties);
+    }
+
+    #[test]
+    fn rejects_broken_headers_and_short_lines() {
+        let missing_pos = "1\nProperties=species:S:1\nC\n";
+        assert!(parse_xyz(missing_pos)
+            .unwrap_err()
+            .to_string()
+            .contains("pos:R:3"));
+        let short = "1\nProperties=species:S:1:pos:R:3:forces:R:3\nC 0 0 0 1.0\n";
+        assert!(parse_xyz(short)
+            .unwrap_err()
+            .to_string()
+            .contains("missing forces"));
+        // An ordinary comment that merely mentions a word is plain XYZ.
+        let plain = parse_xyz("1\nproperties of methane\nC 0 0 0\n").unwrap();
+        assert_eq!(plain.name, "properties of methane");
+    }
+}
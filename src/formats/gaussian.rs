@@ -0,0 +1,227 @@
+//! Gaussian input files (`.gjf`, `.com`) with cartesian coordinates.
+//!
+//! The file holds the Link 0 commands, the route section, the molecule name as the title,
+//! charge and multiplicity and one line per atom. A periodic molecule also gets one `Tv` line
+//! per lattice vector.
+
+use std::fmt::Write as _;
+
+use crate::elements::atomic_number;
+use crate::Molecule;
+
+pub const DEFAULT_ROUTE: &str = "#P B3LYP/6-31G(d) Opt Freq";
+
+/// Route sections offered in the export window.
+pub const ROUTE_PRESETS: [(&str, &str); 4] = [
+    ("Optimize + frequencies", DEFAULT_ROUTE),
+    ("Single point", "#P B3LYP/6-31G(d) SP"),
+    (
+        "Dispersion-corrected optimization",
+        "#P B3LYP/6-31G(d) EmpiricalDispersion=GD3BJ Opt",
+    ),
+    ("Excited states", "#P CAM-B3LYP/6-31+G(d) TD(NStates=10)"),
+];
+
+const FALLBACK_TITLE: &str = "MolWeaver structure";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GaussianSettings {
+    /// Link 0 commands such as `%nprocshared=8`, one per line; the `%` may be left out.
+    pub link0: String,
+    pub route: String,
+    /// `None` uses the sum of the formal charges.
+    pub charge: Option<i32>,
+    /// `None` uses the lowest multiplicity the electron count allows.
+    pub multiplicity: Option<u32>,
+}
+
+impl Default for GaussianSettings {
+    fn default() -> Self {
+        Self {
+            link0: String::new(),
+            route: DEFAULT_ROUTE.to_string(),
+            charge: None,
+            multiplicity: None,
+        }
+    }
+}
+
+impl GaussianSettings {
+    pub fn charge(&self, molecule: &Molecule) -> i32 {
+        self.charge
+            .unwrap_or_else(|| molecule.atoms_in_order().map(|atom| atom.charge).sum())
+    }
+
+    pub fn multiplicity(&self, molecule: &Molecule) -> u32 {
+        self.multiplicity
+            .unwrap_or_else(|| match electron_count(molecule, self.charge(molecule)) {
+                Some(electrons) if electrons % 2 == 1 => 2,
+                _ => 1,
+            })
+    }
+
+    /// Why Gaussian would reject the charge and multiplicity, if it would.
+    pub fn spin_problem(&self, molecule: &Molecule) -> Option<String> {
+        let charge = self.charge(molecule);
+        let multiplicity = self.multiplicity(molecule);
+        if multiplicity == 0 {
+            return Some("multiplicity must be at least 1".to_string());
+        }
+        let electrons = electron_count(molecule, charge)?;
+        if electrons % 2 == multiplicity % 2 {
+            return Some(format!(
+                "{electrons} electrons cannot have multiplicity {multiplicity}"
+            ));
+        }
+        None
+    }
+}
+
+/// Electrons left after removing `charge`, or `None` when an element is unknown or the charge
+/// leaves fewer than none.
+fn electron_count(molecule: &Molecule, charge: i32) -> Option<u32> {
+    let protons = molecule
+        .atoms_in_order()
+        .map(|atom| atomic_number(&atom.element).map(u32::from))
+        .sum::<Option<u32>>()?;
+    u32::try_from(i64::from(protons) - i64::from(charge)).ok()
+}
+
+pub fn write_gaussian(molecule: &Molecule, settings: &GaussianSettings) -> String {
+    let mut out = String::new();
+    for command in settings
+        .link0
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        if !command.starts_with('%') {
+            out.push('%');
+        }
+        out.push_str(command);
+        out.push('\n');
+    }
+    let route = settings
+        .route
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if !route.starts_with('#') {
+        out.push_str("# ");
+    }
+    out.push_str(&route);
+    out.push_str("\n\n");
+
+    // A blank title line would end the title section early.
+    let title = molecule.name.lines().next().unwrap_or("").trim();
+    out.push_str(if title.is_empty() {
+        FALLBACK_TITLE
+    } else {
+        title
+    });
+    out.push_str("\n\n");
+
+    let _ = writeln!(
+        out,
+        "{} {}",
+        settings.charge(molecule),
+        settings.multiplicity(molecule)
+    );
+    for atom in molecule.atoms_in_order() {
+        let [x, y, z] = atom.position;
+        let _ = writeln!(out, "{:<3}{x:14.6}{y:14.6}{z:14.6}", atom.element);
+    }
+    if let Some(cell) = molecule.cell() {
+        let matrix = cell.matrix();
+        for vector in [matrix.x_axis, matrix.y_axis, matrix.z_axis] {
+            let _ = writeln!(
+                out,
+                "Tv {:14.6}{:14.6}{:14.6}",
+                tidy(vector.x),
+                tidy(vector.y),
+                tidy(vector.z)
+            );
+        }
+    }
+    // Gaussian reads the molecule specification up to a blank line.
+    out.push('\n');
+    out
+}
+
+/// `value` without the rounding noise the cell angles leave in components that should be zero.
+fn tidy(value: f32) -> f32 {
+    if value.abs() < 1e-5 {
+        0.0
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UnitCell;
+
+    fn hydroxide() -> Molecule {
+        let mut molecule = Molecule::new("hydroxide");
+        let o = molecule.insert_atom("O".to_string(), [0.0, 0.0, 0.0]);
+        let h = molecule.insert_atom("H".to_string(), [0.97, 0.0, 0.0]);
+        molecule.add_bond(o, h).unwrap();
+        molecule.set_formal_charge(o, -1);
+        molecule
+    }
+
+    #[test]
+    fn writes_sections_with_derived_charge_and_multiplicity() {
+        let molecule = hydroxide();
+        let settings = GaussianSettings {
+            link0: "nprocshared=4\n%mem=2GB".to_string(),
+            ..GaussianSettings::default()
+        };
+        let lines: Vec<String> = write_gaussian(&molecule, &settings)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "%nprocshared=4",
+                "%mem=2GB",
+                DEFAULT_ROUTE,
+                "",
+                "hydroxide",
+                "",
+                "-1 1",
+                "O        0.000000      0.000000      0.000000",
+                "H        0.970000      0.000000      0.000000",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_spin_and_writes_lattice_vectors() {
+        let mut molecule = hydroxide();
+        let radical = GaussianSettings {
+            charge: Some(0),
+            ..GaussianSettings::default()
+        };
+        assert_eq!(radical.multiplicity(&molecule), 2);
+        assert_eq!(radical.spin_problem(&molecule), None);
+        let wrong = GaussianSettings {
+            multiplicity: Some(1),
+            ..radical
+        };
+        assert_eq!(
+            wrong.spin_problem(&molecule).as_deref(),
+            Some("9 electrons cannot have multiplicity 1")
+        );
+
+        molecule.set_cell(Some(UnitCell::cubic(10.0)));
+        molecule.name.clear();
+        let text = write_gaussian(&molecule, &GaussianSettings::default());
+        assert!(text.contains(&format!("\n{FALLBACK_TITLE}\n")));
+        assert_eq!(text.matches("\nTv ").count(), 3);
+        assert!(text.ends_with("\nTv       0.000000      0.000000     10.000000\n\n"));
+    }
+}
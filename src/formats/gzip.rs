@@ -0,0 +1,101 @@
+//! Transparent gzip decompression for every reader.
+//!
+//! Compression is recognized by the magic bytes, so a compressed file opens whatever it is
+//! called; the `.gz` suffix only has to be dropped before the format is judged by extension.
+
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use flate2::bufread;
+use flate2::read::MultiGzDecoder;
+
+/// The first two bytes of every gzip member.
+pub const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// `bytes` as text, decompressed first if they are gzip data. Concatenated members, as
+/// written by `cat a.gz b.gz`, are read as one stream.
+pub fn decode(bytes: Vec<u8>) -> io::Result<String> {
+    let bytes = if bytes.starts_with(&MAGIC) {
+        let mut plain = Vec::new();
+        MultiGzDecoder::new(bytes.as_slice()).read_to_end(&mut plain)?;
+        plain
+    } else {
+        bytes
+    };
+    String::from_utf8(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Like [`std::fs::read_to_string`], decompressing gzip files.
+pub fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
+    decode(std::fs::read(path)?)
+}
+
+/// Everything in `reader`, decompressing gzip data.
+pub fn read_all(mut reader: impl Read) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    decode(bytes)
+}
+
+/// `reader`, decompressed as it is read if it starts with gzip data; for the streaming
+/// readers.
+pub fn decompress<'a>(mut reader: impl BufRead + 'a) -> io::Result<Box<dyn BufRead + 'a>> {
+    if reader.fill_buf()?.starts_with(&MAGIC) {
+        Ok(Box::new(BufReader::new(bufread::MultiGzDecoder::new(
+            reader,
+        ))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// `path` without a trailing `.gz`, in any case, for judging the format by extension.
+pub fn strip_suffix(path: &str) -> &str {
+    match path.len().checked_sub(3) {
+        Some(at) if path.is_char_boundary(at) && path[at..].eq_ignore_ascii_case(".gz") => {
+            &path[..at]
+        }
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    fn compress(text: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn decodes_by_magic_bytes() {
+        let text = "1\nwater\nO 0 0 0\n";
+        assert_eq!(decode(text.as_bytes().to_vec()).unwrap(), text);
+        assert_eq!(decode(compress(text)).unwrap(), text);
+
+        let mut members = compress("1\n");
+        members.extend(compress("two\n"));
+        assert_eq!(read_all(members.as_slice()).unwrap(), "1\ntwo\n");
+        let streamed = io::read_to_string(decompress(members.as_slice()).unwrap()).unwrap();
+        assert_eq!(streamed, "1\ntwo\n");
+
+        let mut truncated = compress(text);
+        truncated.truncate(12);
+        assert!(decode(truncated).is_err());
+    }
+
+    #[test]
+    fn strips_only_a_gz_suffix() {
+        assert_eq!(strip_suffix("1crn.pdb.gz"), "1crn.pdb");
+        assert_eq!(strip_suffix("MOL.SDF.GZ"), "MOL.SDF");
+        assert_eq!(strip_suffix("water.xyz"), "water.xyz");
+        assert_eq!(strip_suffix("gz"), "gz");
+    }
+}
@@ -0,0 +1,251 @@
+//! Readers and writers for file formats beyond XYZ and Z-matrices, and [`load`], which reads
+//! any of them after working out which one it is.
+
+pub mod cif;
+pub mod cml;
+pub mod extxyz;
+pub mod gaussian;
+pub mod gzip;
+pub mod mol2;
+pub mod pdb;
+pub mod sdf;
+pub mod smiles;
+pub mod stream;
+pub mod text;
+
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use self::cif::CifOptions;
+use self::pdb::PdbOptions;
+use self::sdf::SdfOptions;
+use self::text::{Problem, Recovery};
+use crate::{zmatrix, Molecule, XyzOptions};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Xyz,
+    ZMatrix,
+    Sdf,
+    Pdb,
+    Cml,
+    Mol2,
+    Cif,
+}
+
+impl Format {
+    /// Judged by the extension, ignoring a `.gz` suffix.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(gzip::strip_suffix(path))
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref()? {
+            "xyz" | "extxyz" => Some(Format::Xyz),
+            "zmat" => Some(Format::ZMatrix),
+            "mol" | "sdf" | "sd" => Some(Format::Sdf),
+            "pdb" | "ent" => Some(Format::Pdb),
+            "cml" => Some(Format::Cml),
+            "mol2" => Some(Format::Mol2),
+            "cif" => Some(Format::Cif),
+            _ => None,
+        }
+    }
+
+    /// Guessed from the start of a file: markers only one format uses, then the shape of the
+    /// first line.
+    pub fn sniff(head: &str) -> Option<Self> {
+        let lines: Vec<&str> = text::lines(head).map(|line| line.text).collect();
+        let starts = |prefixes: &[&str]| {
+            lines
+                .iter()
+                .any(|line| prefixes.iter().any(|prefix| line.starts_with(prefix)))
+        };
+        if head.trim_start().starts_with('<') {
+            return Some(Format::Cml);
+        }
+        if starts(&["@<TRIPOS>"]) {
+            return Some(Format::Mol2);
+        }
+        if starts(&["data_"]) {
+            return Some(Format::Cif);
+        }
+        if starts(&["ATOM  ", "HETATM", "HEADER", "CRYST1", "MODEL "]) {
+            return Some(Format::Pdb);
+        }
+        if lines
+            .iter()
+            .any(|line| line.contains("V2000") || line.contains("V3000"))
+            || starts(&["M  END", "$$$$"])
+        {
+            return Some(Format::Sdf);
+        }
+        let first = lines.iter().find(|line| !line.trim().is_empty())?;
+        let mut fields = first.split_whitespace();
+        let field = fields.next()?;
+        if fields.next().is_some() {
+            return None;
+        }
+        if field.parse::<usize>().is_ok() {
+            Some(Format::Xyz)
+        } else if field.chars().all(|c| c.is_ascii_alphabetic()) {
+            // A Z-matrix starts with the first atom's element alone on its line.
+            Some(Format::ZMatrix)
+        } else {
+            None
+        }
+    }
+
+    /// The extension when it is known, otherwise the contents; XYZ when neither decides.
+    pub fn detect(name: Option<&str>, head: &str) -> Self {
+        name.and_then(Format::from_path)
+            .or_else(|| Format::sniff(head))
+            .unwrap_or(Format::Xyz)
+    }
+}
+
+/// Per-format choices for [`load`].
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Skip lines that fail to parse instead of refusing the file. Z-matrices and CML are
+    /// always read strictly: a skipped Z-matrix line would shift every later reference, and
+    /// CML is XML.
+    pub lenient: bool,
+    pub xyz: XyzOptions,
+    pub sdf: SdfOptions,
+    pub pdb: PdbOptions,
+    pub cif: CifOptions,
+}
+
+#[derive(Debug, Clone)]
+pub struct Loaded {
+    pub molecule: Molecule,
+    pub format: Format,
+    /// What a lenient read skipped.
+    pub problems: Vec<Problem>,
+}
+
+/// What [`load`] reads.
+#[derive(Debug, Clone, Copy)]
+pub enum Input<'a> {
+    Path(&'a Path),
+    /// File contents, with the file name when there is one for its extension.
+    Bytes {
+        bytes: &'a [u8],
+        name: Option<&'a str>,
+    },
+}
+
+impl<'a> From<&'a Path> for Input<'a> {
+    fn from(path: &'a Path) -> Self {
+        Input::Path(path)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Input<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        Input::Bytes { bytes, name: None }
+    }
+}
+
+/// Reads a structure in any supported format, plain or gzipped. See [`Format::detect`] for
+/// how the format is chosen.
+pub fn load<'a>(input: impl Into<Input<'a>>, options: &LoadOptions) -> Result<Loaded, String> {
+    match input.into() {
+        Input::Path(path) => {
+            let file = std::fs::File::open(path).map_err(|err| err.to_string())?;
+            load_reader(file, Some(&path.to_string_lossy()), options)
+        }
+        Input::Bytes { bytes, name } => load_reader(bytes, name, options),
+    }
+}
+
+/// Like [`load`], from a reader. XYZ and PDB are read a line at a time; the other formats
+/// are read into memory first.
+pub fn load_reader(
+    reader: impl Read,
+    name: Option<&str>,
+    options: &LoadOptions,
+) -> Result<Loaded, String> {
+    let mut reader = gzip::decompress(BufReader::new(reader)).map_err(|err| err.to_string())?;
+    let head = reader.fill_buf().map_err(|err| err.to_string())?;
+    let format = Format::detect(name, &String::from_utf8_lossy(head));
+    let mut recovery = if options.lenient {
+        Recovery::lenient()
+    } else {
+        Recovery::strict()
+    };
+    let text = |reader| io::read_to_string(reader).map_err(|err| err.to_string());
+    let molecule = match format {
+        Format::ZMatrix => zmatrix::parse_zmatrix(&text(reader)?).map_err(|err| err.to_string()),
+        Format::Cml => cml::parse_cml(&text(reader)?).map_err(|err| err.to_string()),
+        // Docking output holds several poses; the first one is read.
+        Format::Mol2 => mol2::parse_mol2_with(&text(reader)?, &mut recovery)
+            .map_err(|err| err.to_string())
+            .and_then(|molecules| {
+                molecules
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| "no molecules".to_string())
+            }),
+        Format::Sdf => sdf::parse_mol_with(&text(reader)?, &options.sdf, &mut recovery)
+            .map_err(|err| err.to_string()),
+        Format::Pdb => {
+            pdb::read_pdb_with(reader, &options.pdb, &mut recovery).map_err(|err| err.to_string())
+        }
+        Format::Cif => cif::parse_cif_with(&text(reader)?, &options.cif, &mut recovery)
+            .map_err(|err| err.to_string()),
+        Format::Xyz => {
+            crate::read_xyz_with(reader, &options.xyz, &mut recovery).map_err(|err| err.to_string())
+        }
+    }?;
+    Ok(Loaded {
+        molecule,
+        format,
+        problems: recovery.into_problems(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_by_extension_then_content() {
+        assert_eq!(Format::from_path("1CRN.PDB.gz"), Some(Format::Pdb));
+        assert_eq!(Format::from_path("notes.txt"), None);
+        let pdb = "HEADER    PLANT PROTEIN\nATOM      1  N   THR A   1      17.047  14.099   3.625";
+        assert_eq!(Format::sniff(pdb), Some(Format::Pdb));
+        assert_eq!(Format::sniff("@<TRIPOS>MOLECULE\nx\n"), Some(Format::Mol2));
+        assert_eq!(Format::sniff("<?xml version=\"1.0\"?>"), Some(Format::Cml));
+        assert_eq!(
+            Format::sniff("data_nacl\n_cell_length_a 5.64\n"),
+            Some(Format::Cif)
+        );
+        assert_eq!(Format::sniff("3\nwater\n"), Some(Format::Xyz));
+        assert_eq!(Format::sniff("O\nH 1 0.96\n"), Some(Format::ZMatrix));
+        assert_eq!(Format::sniff("hello world"), None);
+        // A known extension wins over the contents.
+        assert_eq!(Format::detect(Some("a.xyz"), pdb), Format::Xyz);
+        assert_eq!(Format::detect(Some("a.txt"), pdb), Format::Pdb);
+        assert_eq!(Format::detect(None, ""), Format::Xyz);
+    }
+
+    #[test]
+    fn loads_bytes_without_a_name() {
+        let loaded = load(
+            &b"2\nhydroxide\nO 0 0 0\nH 0.97 0 0\n"[..],
+            &LoadOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(loaded.format, Format::Xyz);
+        assert_eq!(loaded.molecule.atom_count(), 2);
+
+        let input = Input::Bytes {
+            bytes: b"C\nH 1 1.09\n",
+            name: Some("methyl.zmat"),
+        };
+        let loaded = load(input, &LoadOptions::default()).unwrap();
+        assert_eq!(loaded.format, Format::ZMatrix);
+        assert_eq!(loaded.molecule.atom_count(), 2);
+    }
+}
@@ -0,0 +1,296 @@
+//! Tripos MOL2 input, as written by docking tools.
+//!
+//! Each `@<TRIPOS>MOLECULE` section becomes one [`Molecule`]. The element comes from the
+//! Sybyl atom type (`C.ar` is carbon); the type itself, the atom name, the substructure name
+//! and the partial charge go into [`Molecule::properties`] under `atom_type`, `atom_name`,
+//! `residue` and `partial_charge`. Bond types 1–3 keep their order; aromatic, amide and other
+//! bond types load as single bonds. Other sections are ignored.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use super::text::{self, FieldError, Line, Problem, Recovery};
+use crate::elements::{atomic_number, normalize_symbol};
+use crate::valence::{StandardValence, Unrestricted};
+use crate::{AtomId, Molecule};
+
+#[derive(Debug, Clone)]
+pub struct Mol2Error {
+    details: String,
+}
+
+impl Mol2Error {
+    fn new(line: usize, details: impl Into<String>) -> Self {
+        Self {
+            details: format!("line {line}: {}", details.into()),
+        }
+    }
+}
+
+impl fmt::Display for Mol2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl std::error::Error for Mol2Error {}
+
+impl From<FieldError> for Mol2Error {
+    fn from(err: FieldError) -> Self {
+        Self {
+            details: err.to_string(),
+        }
+    }
+}
+
+const SECTION_PREFIX: &str = "@<TRIPOS>";
+
+/// Every molecule of a MOL2 file, in order.
+pub fn parse_mol2(contents: &str) -> Result<Vec<Molecule>, Mol2Error> {
+    parse_mol2_with(contents, &mut Recovery::strict())
+}
+
+/// Like [`parse_mol2`], but skips atom and bond lines that fail to parse and returns what was
+/// skipped.
+pub fn parse_mol2_lenient(contents: &str) -> Result<(Vec<Molecule>, Vec<Problem>), Mol2Error> {
+    let mut recovery = Recovery::lenient();
+    let molecules = parse_mol2_with(contents, &mut recovery)?;
+    Ok((molecules, recovery.into_problems()))
+}
+
+pub fn parse_mol2_with(
+    contents: &str,
+    recovery: &mut Recovery,
+) -> Result<Vec<Molecule>, Mol2Error> {
+    let lines: Vec<Line> = text::lines(contents)
+        .filter(|line| !line.is_blank() && !line.text.trim_start().starts_with('#'))
+        .collect();
+    // Each molecule runs from its MOLECULE section to the next one.
+    let starts: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| section(line) == Some("MOLECULE"))
+        .map(|(index, _)| index)
+        .collect();
+    if starts.is_empty() {
+        let last = lines.last().map_or(1, |line| line.number);
+        return Err(Mol2Error::new(last, "no @<TRIPOS>MOLECULE section"));
+    }
+    starts
+        .iter()
+        .enumerate()
+        .map(|(index, &start)| {
+            let end = starts.get(index + 1).copied().unwrap_or(lines.len());
+            parse_molecule(&lines[start..end], recovery)
+        })
+        .collect()
+}
+
+/// The section name of a `@<TRIPOS>NAME` line.
+fn section<'a>(line: &Line<'a>) -> Option<&'a str> {
+    line.text.trim().strip_prefix(SECTION_PREFIX)
+}
+
+/// The data lines of section `name` within `lines`.
+fn section_lines<'l, 'a>(lines: &'l [Line<'a>], name: &str) -> &'l [Line<'a>] {
+    let Some(start) = lines.iter().position(|line| section(line) == Some(name)) else {
+        return &[];
+    };
+    let body = &lines[start + 1..];
+    let end = body
+        .iter()
+        .position(|line| section(line).is_some())
+        .unwrap_or(body.len());
+    &body[..end]
+}
+
+/// `lines` start at the MOLECULE section header.
+fn parse_molecule(lines: &[Line], recovery: &mut Recovery) -> Result<Molecule, Mol2Error> {
+    let header = section_lines(lines, "MOLECULE");
+    let name = header.first().map_or("", |line| line.text.trim());
+    let mut molecule = Molecule::new(name);
+    molecule.set_valence_model(Arc::new(Unrestricted));
+
+    let mut ids: HashMap<u32, AtomId> = HashMap::new();
+    for line in section_lines(lines, "ATOM") {
+        let atom = recovery.skip_bad(line.number, parse_atom(line))?;
+        let Some(atom) = atom else {
+            continue;
+        };
+        let id = molecule.insert_atom(atom.element, atom.position);
+        let properties = &mut molecule.properties;
+        properties.set("atom_name", id, atom.name);
+        properties.set("atom_type", id, atom.atom_type);
+        if let Some(residue) = atom.residue {
+            properties.set("residue", id, residue);
+        }
+        if let Some(charge) = atom.charge {
+            properties.set("partial_charge", id, charge);
+        }
+        if ids.insert(atom.serial, id).is_some() {
+            let err = Mol2Error::new(line.number, format!("duplicate atom id {}", atom.serial));
+            recovery.tolerate(line.number, err)?;
+        }
+    }
+    for line in section_lines(lines, "BOND") {
+        let result = add_bond(&mut molecule, &ids, line);
+        recovery.skip_bad(line.number, result)?;
+    }
+    molecule.set_valence_model(Arc::new(StandardValence));
+    Ok(molecule)
+}
+
+struct AtomRecord<'a> {
+    serial: u32,
+    name: &'a str,
+    position: [f32; 3],
+    atom_type: &'a str,
+    element: String,
+    residue: Option<&'a str>,
+    charge: Option<&'a str>,
+}
+
+fn parse_atom<'a>(line: &Line<'a>) -> Result<AtomRecord<'a>, Mol2Error> {
+    let mut fields = line.fields();
+    let serial = fields.expect("atom id")?.parse("atom id")?;
+    let name = fields.expect("atom name")?.text;
+    let x = fields.expect("x")?.parse("x")?;
+    let y = fields.expect("y")?.parse("y")?;
+    let z = fields.expect("z")?.parse("z")?;
+    let type_field = fields.expect("atom type")?;
+    let element = element_from_type(type_field.text)
+        .ok_or_else(|| type_field.error(format!("unknown atom type {}", type_field.text)))?;
+    let _substructure_id = fields.next();
+    let residue = fields.next().map(|field| field.text);
+    let charge = match fields.next() {
+        Some(field) => {
+            field.parse::<f32>("partial charge")?;
+            Some(field.text)
+        }
+        None => None,
+    };
+    Ok(AtomRecord {
+        serial,
+        name,
+        position: [x, y, z],
+        atom_type: type_field.text,
+        element,
+        residue,
+        charge,
+    })
+}
+
+/// Element of a Sybyl atom type: the part before the dot, with dummy atoms and lone pairs
+/// kept under their own symbols.
+fn element_from_type(atom_type: &str) -> Option<String> {
+    let symbol = atom_type.split('.').next().unwrap_or(atom_type);
+    match symbol {
+        "Du" | "LP" | "Any" | "Hal" | "Het" | "Hev" => Some(symbol.to_string()),
+        _ => atomic_number(symbol).map(|_| normalize_symbol(symbol)),
+    }
+}
+
+/// MOL2 bond types 1–3 keep their order; `ar`, `am`, `du`, `un` and `nc` load as single.
+fn bond_order(bond_type: &str) -> u8 {
+    match bond_type {
+        "2" => 2,
+        "3" => 3,
+        _ => 1,
+    }
+}
+
+fn add_bond(
+    molecule: &mut Molecule,
+    ids: &HashMap<u32, AtomId>,
+    line: &Line,
+) -> Result<(), Mol2Error> {
+    let mut fields = line.fields();
+    fields.expect("bond id")?;
+    let mut atom = |what: &str| -> Result<AtomId, Mol2Error> {
+        let field = fields.expect(what)?;
+        let serial: u32 = field.parse(what)?;
+        ids.get(&serial).copied().ok_or_else(|| {
+            field
+                .error(format!("{what} {serial} does not exist"))
+                .into()
+        })
+    };
+    let a = atom("origin atom")?;
+    let b = atom("target atom")?;
+    let order = bond_order(fields.expect("bond type")?.text);
+    let bond = molecule
+        .add_bond(a, b)
+        .map_err(|err| Mol2Error::new(line.number, err))?;
+    molecule.set_bond_order(bond, order);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCKED_POSES: &str = "\
+# written by a docking run
+@<TRIPOS>MOLECULE
+acetate pose 1
+ 4 3 1 0 0
+SMALL
+USER_CHARGES
+
+@<TRIPOS>ATOM
+      1 C1          0.0000    0.0000    0.0000 C.3       1  ACT1       -0.2000
+      2 C2          1.5200    0.0000    0.0000 C.2       1  ACT1        0.7000
+      3 O1          2.1500    1.0800    0.0000 O.co2     1  ACT1       -0.7500
+      4 O2          2.1500   -1.0800    0.0000 O.co2     1  ACT1       -0.7500
+@<TRIPOS>BOND
+     1     1     2    1
+     2     2     3    ar
+     3     2     4    2
+@<TRIPOS>SUBSTRUCTURE
+     1 ACT1        1 GROUP
+@<TRIPOS>MOLECULE
+chloride pose 2
+ 1 0 0 0 0
+SMALL
+NO_CHARGES
+@<TRIPOS>ATOM
+      1 CL         5.0000    0.0000    0.0000 Cl
+";
+
+    #[test]
+    fn reads_types_charges_and_bond_orders() {
+        let molecules = parse_mol2(DOCKED_POSES).unwrap();
+        assert_eq!(molecules.len(), 2);
+        let acetate = &molecules[0];
+        assert_eq!(acetate.name, "acetate pose 1");
+        assert_eq!(acetate.formula(), "C2O2");
+        let atoms = acetate.atom_ids();
+        assert_eq!(acetate.properties.get("atom_type", atoms[2]), Some("O.co2"));
+        assert_eq!(
+            acetate.properties.get("partial_charge", atoms[1]),
+            Some("0.7000")
+        );
+        assert_eq!(acetate.properties.get("residue", atoms[0]), Some("ACT1"));
+        let mut orders: Vec<u8> = acetate.bonds().map(|bond| bond.order).collect();
+        orders.sort();
+        assert_eq!(orders, [1, 1, 2]);
+        let chloride = &molecules[1];
+        assert_eq!(chloride.atoms_in_order().next().unwrap().element, "Cl");
+        assert_eq!(
+            chloride
+                .properties
+                .get("partial_charge", chloride.atom_ids()[0]),
+            None
+        );
+
+        let bad = DOCKED_POSES.replace("     3     2     4    2", "     3     2     9    2");
+        assert_eq!(
+            parse_mol2(&bad).unwrap_err().to_string(),
+            "line 16, column 18: target atom 9 does not exist"
+        );
+        let (molecules, problems) = parse_mol2_lenient(&bad).unwrap();
+        assert_eq!(molecules[0].bonds().count(), 2);
+        assert_eq!(problems.len(), 1);
+    }
+}
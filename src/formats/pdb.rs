@@ -0,0 +1,376 @@
+//! Protein Data Bank input: ATOM and HETATM records of the first model, or of every model
+//! with [`PdbOptions::all_models`], with bonds from CONECT records.
+//!
+//! Residue and chain data go into [`Molecule::properties`] under `atom_name`, `residue`,
+//! `residue_number` (with any insertion code), `chain` and `occupancy`. Of the alternate
+//! locations of an atom only the one with the highest occupancy is kept, the first on a tie.
+//! No bonds are perceived from distances.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufRead};
+use std::sync::Arc;
+
+use super::stream::LineReader;
+use super::text::{FieldError, Line, Problem, Recovery};
+use crate::elements::{atomic_number, normalize_symbol};
+use crate::valence::{StandardValence, Unrestricted};
+use crate::{AtomId, Molecule};
+
+#[derive(Debug, Clone)]
+pub struct PdbError {
+    details: String,
+}
+
+impl PdbError {
+    fn new(line: usize, details: impl Into<String>) -> Self {
+        Self {
+            details: format!("line {line}: {}", details.into()),
+        }
+    }
+}
+
+impl fmt::Display for PdbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl std::error::Error for PdbError {}
+
+impl From<FieldError> for PdbError {
+    fn from(err: FieldError) -> Self {
+        Self {
+            details: err.to_string(),
+        }
+    }
+}
+
+impl From<io::Error> for PdbError {
+    fn from(err: io::Error) -> Self {
+        Self {
+            details: err.to_string(),
+        }
+    }
+}
+
+/// What to read from a PDB file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PdbOptions {
+    /// Read every model, tagging atoms with a `model` property, instead of the first only.
+    pub all_models: bool,
+    /// Keep water residues (HOH, WAT, ...).
+    pub keep_waters: bool,
+}
+
+impl Default for PdbOptions {
+    fn default() -> Self {
+        Self {
+            all_models: false,
+            keep_waters: true,
+        }
+    }
+}
+
+/// Residue names of water molecules.
+pub(crate) const WATER_RESIDUES: [&str; 6] = ["HOH", "WAT", "H2O", "DOD", "SOL", "TIP"];
+
+/// One ATOM or HETATM record.
+struct AtomRecord {
+    /// 1-based model number.
+    model: usize,
+    serial: u32,
+    name: String,
+    alt_loc: char,
+    residue: String,
+    chain: String,
+    /// Sequence number with any insertion code appended.
+    residue_number: String,
+    position: [f32; 3],
+    occupancy: f32,
+    element: String,
+    charge: i32,
+}
+
+impl AtomRecord {
+    /// Records sharing a key are alternate locations of one atom.
+    fn key(&self) -> (usize, &str, &str, &str, &str) {
+        (
+            self.model,
+            &self.chain,
+            &self.residue_number,
+            &self.residue,
+            &self.name,
+        )
+    }
+}
+
+pub fn parse_pdb(contents: &str) -> Result<Molecule, PdbError> {
+    parse_pdb_with(contents, &PdbOptions::default(), &mut Recovery::strict())
+}
+
+/// Like [`parse_pdb`], but skips ATOM, HETATM and CONECT records that fail to parse and
+/// returns what was skipped.
+pub fn parse_pdb_lenient(contents: &str) -> Result<(Molecule, Vec<Problem>), PdbError> {
+    let mut recovery = Recovery::lenient();
+    let molecule = parse_pdb_with(contents, &PdbOptions::default(), &mut recovery)?;
+    Ok((molecule, recovery.into_problems()))
+}
+
+pub fn parse_pdb_with(
+    contents: &str,
+    options: &PdbOptions,
+    recovery: &mut Recovery,
+) -> Result<Molecule, PdbError> {
+    read_pdb_with(contents.as_bytes(), options, recovery)
+}
+
+/// Like [`parse_pdb_with`], reading a line at a time; only the atom and CONECT records are
+/// kept until the molecule is built.
+pub fn read_pdb_with(
+    reader: impl BufRead,
+    options: &PdbOptions,
+    recovery: &mut Recovery,
+) -> Result<Molecule, PdbError> {
+    let mut records = Vec::new();
+    let mut conects = Vec::new();
+    let mut title = String::new();
+    let mut id_code = String::new();
+    let mut last_line = 0;
+    // CONECT records follow the last model, so later models are skipped rather than ending
+    // the read.
+    let mut model = 1;
+    let mut lines = LineReader::new(reader);
+    while let Some(line) = lines.next_line()? {
+        last_line = line.number;
+        match line.column(0, 6).text {
+            "ATOM" | "HETATM" if model == 1 || options.all_models => {
+                let record = recovery.skip_bad(line.number, parse_atom(&line, model))?;
+                records.extend(record.filter(|record| {
+                    options.keep_waters || !WATER_RESIDUES.contains(&record.residue.as_str())
+                }));
+            }
+            "CONECT" => conects.push((line.number, line.text.to_string())),
+            "HEADER" => id_code = line.column(62, 66).text.to_string(),
+            "TITLE" => {
+                if !title.is_empty() {
+                    title.push(' ');
+                }
+                title.push_str(line.column(10, 80).text);
+            }
+            "ENDMDL" => model += 1,
+            "END" => break,
+            _ => {}
+        }
+    }
+    if records.is_empty() {
+        return Err(PdbError::new(last_line, "no ATOM or HETATM records"));
+    }
+
+    // Index of the record kept for each atom with alternate locations.
+    let mut chosen: HashMap<(usize, &str, &str, &str, &str), usize> = HashMap::new();
+    for (index, record) in records.iter().enumerate() {
+        if record.alt_loc == ' ' {
+            continue;
+        }
+        chosen
+            .entry(record.key())
+            .and_modify(|best| {
+                if record.occupancy > records[*best].occupancy {
+                    *best = index;
+                }
+            })
+            .or_insert(index);
+    }
+
+    let mut molecule = Molecule::new(if title.is_empty() { id_code } else { title });
+    molecule.set_valence_model(Arc::new(Unrestricted));
+    // Serial numbers restart in each model.
+    let models = records.last().map_or(1, |record| record.model);
+    let mut ids: Vec<HashMap<u32, AtomId>> = vec![HashMap::new(); models];
+    for (index, record) in records.iter().enumerate() {
+        if record.alt_loc != ' ' && chosen[&record.key()] != index {
+            continue;
+        }
+        let id = molecule.insert_atom(record.element.clone(), record.position);
+        molecule.set_formal_charge(id, record.charge);
+        let properties = &mut molecule.properties;
+        properties.set("atom_name", id, record.name.as_str());
+        properties.set("residue", id, record.residue.as_str());
+        properties.set("residue_number", id, record.residue_number.as_str());
+        properties.set("chain", id, record.chain.as_str());
+        properties.set("occupancy", id, format!("{:.2}", record.occupancy));
+        if options.all_models {
+            properties.set("model", id, record.model.to_string());
+        }
+        ids[record.model - 1].insert(record.serial, id);
+    }
+
+    let mut bonded = HashSet::new();
+    for (number, text) in &conects {
+        let line = Line {
+            number: *number,
+            text,
+        };
+        let result = ids
+            .iter()
+            .try_for_each(|ids| connect(&mut molecule, ids, &mut bonded, &line));
+        recovery.skip_bad(line.number, result)?;
+    }
+    molecule.set_valence_model(Arc::new(StandardValence));
+    Ok(molecule)
+}
+
+/// Adds the bonds of one CONECT record that are not in `bonded` yet.
+fn connect(
+    molecule: &mut Molecule,
+    ids: &HashMap<u32, AtomId>,
+    bonded: &mut HashSet<(AtomId, AtomId)>,
+    line: &Line,
+) -> Result<(), PdbError> {
+    let from: u32 = line.column(6, 11).parse("atom serial")?;
+    // Bonds to skipped alternate locations are dropped with them.
+    let Some(&a) = ids.get(&from) else {
+        return Ok(());
+    };
+    for start in [11, 16, 21, 26] {
+        let field = line.column(start, start + 5);
+        if field.is_empty() {
+            continue;
+        }
+        let Some(&b) = ids.get(&field.parse("atom serial")?) else {
+            continue;
+        };
+        if a != b && bonded.insert((a.min(b), a.max(b))) {
+            molecule
+                .add_bond(a, b)
+                .map_err(|err| PdbError::new(line.number, err))?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_atom(line: &Line, model: usize) -> Result<AtomRecord, PdbError> {
+    let name = line.column(12, 16).text;
+    let element = match line.column(76, 78).text {
+        "" => element_from_name(line.text.get(12..16).unwrap_or(name))
+            .ok_or_else(|| PdbError::new(line.number, format!("no element for atom {name}")))?,
+        symbol => normalize_symbol(symbol),
+    };
+    // Charges are written as a digit followed by the sign, e.g. "2+".
+    let charge_field = line.column(78, 80);
+    let charge = match charge_field.text.as_bytes() {
+        [] => 0,
+        [digit @ b'0'..=b'9', b'+'] => i32::from(digit - b'0'),
+        [digit @ b'0'..=b'9', b'-'] => -i32::from(digit - b'0'),
+        _ => return Err(charge_field.error("invalid charge").into()),
+    };
+    let occupancy = line.column(54, 60);
+    Ok(AtomRecord {
+        model,
+        serial: line.column(6, 11).parse("atom serial")?,
+        name: name.to_string(),
+        alt_loc: line
+            .text
+            .get(16..17)
+            .and_then(|s| s.chars().next())
+            .unwrap_or(' '),
+        residue: line.column(17, 20).text.to_string(),
+        chain: line.column(21, 22).text.to_string(),
+        residue_number: format!("{}{}", line.column(22, 26).text, line.column(26, 27).text),
+        position: [
+            line.column(30, 38).parse("x")?,
+            line.column(38, 46).parse("y")?,
+            line.column(46, 54).parse("z")?,
+        ],
+        occupancy: if occupancy.is_empty() {
+            1.0
+        } else {
+            occupancy.parse("occupancy")?
+        },
+        element,
+        charge,
+    })
+}
+
+/// Element for files without the element column. Names of one-letter elements start in the
+/// second column of the field ("␣CA␣" is carbon), two-letter ones in the first ("CA␣␣" is
+/// calcium).
+fn element_from_name(field: &str) -> Option<String> {
+    let letters: String = field
+        .trim_start()
+        .chars()
+        .skip_while(|c| c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    let candidates = if field.starts_with(' ') {
+        [letters.get(..1), None]
+    } else {
+        [letters.get(..2), letters.get(..1)]
+    };
+    let symbol = candidates
+        .into_iter()
+        .flatten()
+        .find(|symbol| atomic_number(symbol).is_some())?;
+    Some(normalize_symbol(symbol))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERINE_FRAGMENT: &str = "\
+HEADER    HYDROLASE                               01-JAN-00   1ABC
+TITLE     SERINE WITH ALTERNATE
+TITLE    2 SIDE CHAIN
+ATOM      1  N   SER A  12      10.000  11.000  12.000  1.00 20.00
+ATOM      2  CA  SER A  12      11.400  11.000  12.000  1.00 20.00           C
+ATOM      3  CB ASER A  12      12.000  12.400  12.000  0.40 20.00           C
+ATOM      4  CB BSER A  12      12.000   9.600  12.000  0.60 20.00           C
+HETATM    5 CA    CA B 101A     20.000  20.000  20.000  0.50 30.00
+HETATM    6  O   HOH B 102      15.000  15.000  15.000  1.00 30.00           O1-
+ENDMDL
+ATOM      7  N   GLY A  13       0.000   0.000   0.000  1.00 20.00           N
+CONECT    1    2
+CONECT    2    1    3    4
+CONECT    3    2
+CONECT    4    2
+END
+";
+
+    #[test]
+    fn reads_residues_chains_and_alternate_locations() {
+        let molecule = parse_pdb(SERINE_FRAGMENT).unwrap();
+        assert_eq!(molecule.name, "SERINE WITH ALTERNATE SIDE CHAIN");
+        let atoms: Vec<_> = molecule.atoms_in_order().collect();
+        let elements: Vec<&str> = atoms.iter().map(|atom| atom.element.as_str()).collect();
+        assert_eq!(elements, ["N", "C", "C", "Ca", "O"]);
+        // The B location of CB has the higher occupancy.
+        assert_eq!(atoms[2].position, [12.0, 9.6, 12.0]);
+        assert_eq!(atoms[4].charge, -1);
+        assert_eq!(molecule.bonds().count(), 2);
+
+        let property = |index: usize, name: &str| molecule.properties.get(name, atoms[index].id);
+        assert_eq!(property(1, "atom_name"), Some("CA"));
+        assert_eq!(property(1, "residue"), Some("SER"));
+        assert_eq!(property(1, "chain"), Some("A"));
+        assert_eq!(property(3, "residue_number"), Some("101A"));
+        assert_eq!(property(2, "occupancy"), Some("0.60"));
+
+        let options = PdbOptions {
+            all_models: true,
+            keep_waters: false,
+        };
+        let models = parse_pdb_with(SERINE_FRAGMENT, &options, &mut Recovery::strict()).unwrap();
+        let atoms: Vec<_> = models.atoms_in_order().collect();
+        assert_eq!(atoms.len(), 5);
+        assert_eq!(models.properties.get("model", atoms[4].id), Some("2"));
+        assert_eq!(models.bonds().count(), 2);
+
+        let bad = SERINE_FRAGMENT.replace("11.400", "11.4x0");
+        assert_eq!(
+            parse_pdb(&bad).unwrap_err().to_string(),
+            "line 5, column 33: invalid x"
+        );
+    }
+}
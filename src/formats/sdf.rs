@@ -0,0 +1,668 @@
+//! MDL Molfile (V2000 and V3000) and SD file input and output.
+//!
+//! Each record becomes one [`Molecule`] named after its header line, with formal charges and
+//! bond orders. Aromatic and query bond types load as single bonds and type 9 as a
+//! coordination bond; stereo flags and isotopes are ignored. Bonds are taken as written,
+//! without a valence check.
+//!
+//! Per-atom properties travel as `atom.prop.NAME` data items holding one whitespace-free
+//! value per atom in file order, `n/a` where an atom has none. Other data items are ignored.
+
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::sync::Arc;
+
+use super::text::{self, Field, FieldError, Line, Problem, Recovery};
+use crate::valence::{StandardValence, Unrestricted};
+use crate::{AtomId, BondKind, Molecule};
+
+#[derive(Debug, Clone)]
+pub struct SdfError {
+    details: String,
+}
+
+impl SdfError {
+    fn new(line: usize, details: impl Into<String>) -> Self {
+        Self {
+            details: format!("line {line}: {}", details.into()),
+        }
+    }
+}
+
+impl fmt::Display for SdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl std::error::Error for SdfError {}
+
+impl From<FieldError> for SdfError {
+    fn from(err: FieldError) -> Self {
+        Self {
+            details: err.to_string(),
+        }
+    }
+}
+
+/// Every record of an SD file, in order. A single Molfile is a one-record SD file.
+pub fn parse_sdf(contents: &str) -> Result<Vec<Molecule>, SdfError> {
+    read_sdf(contents, &mut Recovery::strict())
+}
+
+/// Like [`parse_sdf`], but skips atom, bond and charge lines that fail to parse, keeps what
+/// there is of a truncated record and drops records whose header is unreadable. Returns what
+/// was skipped.
+pub fn parse_sdf_lenient(contents: &str) -> Result<(Vec<Molecule>, Vec<Problem>), SdfError> {
+    let mut recovery = Recovery::lenient();
+    let molecules = read_sdf(contents, &mut recovery)?;
+    Ok((molecules, recovery.into_problems()))
+}
+
+fn read_sdf(contents: &str, recovery: &mut Recovery) -> Result<Vec<Molecule>, SdfError> {
+    let lines: Vec<Line> = text::lines(contents).collect();
+    let mut molecules = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let end = lines[start..]
+            .iter()
+            .position(|line| line.text.trim_end() == "$$$$")
+            .map_or(lines.len(), |offset| start + offset);
+        if lines[start..end].iter().any(|line| !line.is_blank()) {
+            let record = parse_record(&lines[start..end], recovery);
+            molecules.extend(recovery.skip_bad(lines[start].number, record)?);
+        }
+        start = end + 1;
+    }
+    Ok(molecules)
+}
+
+/// Which record of an SD file to open.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SdfOptions {
+    /// 0-based index among the records that were read.
+    pub record: usize,
+}
+
+/// The first record of a Molfile or SD file.
+pub fn parse_mol(contents: &str) -> Result<Molecule, SdfError> {
+    parse_mol_with(contents, &SdfOptions::default(), &mut Recovery::strict())
+}
+
+/// The first readable record, read as [`parse_sdf_lenient`] does.
+pub fn parse_mol_lenient(contents: &str) -> Result<(Molecule, Vec<Problem>), SdfError> {
+    let mut recovery = Recovery::lenient();
+    let molecule = parse_mol_with(contents, &SdfOptions::default(), &mut recovery)?;
+    Ok((molecule, recovery.into_problems()))
+}
+
+/// The record `options` asks for.
+pub fn parse_mol_with(
+    contents: &str,
+    options: &SdfOptions,
+    recovery: &mut Recovery,
+) -> Result<Molecule, SdfError> {
+    let molecules = read_sdf(contents, recovery)?;
+    let count = molecules.len();
+    molecules.into_iter().nth(options.record).ok_or_else(|| {
+        SdfError::new(
+            1,
+            match count {
+                0 => "no readable records".to_string(),
+                _ => format!(
+                    "record {} requested, but the file has {count}",
+                    options.record + 1
+                ),
+            },
+        )
+    })
+}
+
+/// Prefix of the data items that hold per-atom properties.
+const ATOM_PROPERTY_PREFIX: &str = "atom.prop.";
+/// Per-atom property value for atoms without one.
+const MISSING_VALUE: &str = "n/a";
+/// Larger molecules are written as V3000, which has no fixed-width count fields.
+const V2000_MAX_COUNT: usize = 999;
+const V3000_PREFIX: &str = "M  V30 ";
+
+/// `record` holds the lines of one record, never empty.
+fn parse_record(record: &[Line], recovery: &mut Recovery) -> Result<Molecule, SdfError> {
+    if record.len() < 4 {
+        return Err(SdfError::new(record[0].number, "truncated header"));
+    }
+    let mut molecule = Molecule::new(record[0].text.trim());
+    molecule.set_valence_model(Arc::new(Unrestricted));
+    let counts = &record[3];
+    let body = &record[4..];
+    if counts.text.contains("V3000") {
+        parse_v3000(&mut molecule, body, recovery)?;
+    } else {
+        parse_v2000(&mut molecule, counts, body, recovery)?;
+    }
+    read_atom_properties(&mut molecule, record);
+    molecule.set_valence_model(Arc::new(StandardValence));
+    Ok(molecule)
+}
+
+/// Fills `molecule.properties` from the record's `atom.prop.*` data items. Atoms are in file
+/// order, as the parsers inserted them.
+fn read_atom_properties(molecule: &mut Molecule, record: &[Line]) {
+    let atoms = molecule.atom_ids();
+    let mut lines = record
+        .iter()
+        .skip_while(|line| !line.text.starts_with("M  END"))
+        .skip(1);
+    while let Some(line) = lines.next() {
+        let name = line
+            .text
+            .strip_prefix('>')
+            .and_then(|header| Some(header.split_once('<')?.1.split_once('>')?.0))
+            .and_then(|field| field.strip_prefix(ATOM_PROPERTY_PREFIX));
+        let values: Vec<&str> = lines
+            .by_ref()
+            .take_while(|line| !line.is_blank())
+            .flat_map(|line| line.text.split_whitespace())
+            .collect();
+        let Some(name) = name else {
+            continue;
+        };
+        for (&atom, value) in atoms.iter().zip(values) {
+            if value != MISSING_VALUE {
+                molecule.properties.set(name, atom, value);
+            }
+        }
+    }
+}
+
+/// The V3000 bond type for coordination bonds; V2000 has none, so molecules with them are
+/// written as V3000.
+const COORDINATION_BOND_TYPE: u8 = 9;
+
+/// MDL bond types 1–3 are single, double and triple; everything else loads as single.
+fn bond_order(bond_type: u8) -> u8 {
+    match bond_type {
+        2 | 3 => bond_type,
+        _ => 1,
+    }
+}
+
+/// Atom with the 1-based file index in `field`; `ids` maps file indices to atoms.
+fn atom_at(ids: &HashMap<usize, AtomId>, field: Field, what: &str) -> Result<AtomId, SdfError> {
+    let index: usize = field.parse(what)?;
+    ids.get(&index)
+        .copied()
+        .ok_or_else(|| field.error(format!("{what} {index} does not exist")).into())
+}
+
+/// Bonds the atoms with the file indices in `a` and `b`.
+fn bond_between(
+    molecule: &mut Molecule,
+    ids: &HashMap<usize, AtomId>,
+    a: Field,
+    b: Field,
+    bond_type: Field,
+) -> Result<(), SdfError> {
+    let a = atom_at(ids, a, "bonded atom")?;
+    let b = atom_at(ids, b, "bonded atom")?;
+    let code = bond_type.parse("bond type")?;
+    let bond = if code == COORDINATION_BOND_TYPE {
+        molecule.add_coordination_bond(a, b)
+    } else {
+        molecule.add_bond(a, b)
+    }
+    .map_err(|err| SdfError::new(bond_type.line, err))?;
+    let order = bond_order(code);
+    molecule.set_bond_order(bond, order);
+    Ok(())
+}
+
+fn parse_v2000(
+    molecule: &mut Molecule,
+    counts: &Line,
+    body: &[Line],
+    recovery: &mut Recovery,
+) -> Result<(), SdfError> {
+    let mut atom_count: usize = counts.column(0, 3).parse("atom count")?;
+    let mut bond_count: usize = counts.column(3, 6).parse("bond count")?;
+    if body.len() < atom_count + bond_count {
+        let end = counts.number + body.len() + 1;
+        let err = SdfError::new(end, "fewer atom and bond lines than counted");
+        recovery.tolerate(end, err)?;
+        atom_count = atom_count.min(body.len());
+        bond_count = body.len() - atom_count;
+    }
+    let mut ids = HashMap::with_capacity(atom_count);
+    for (index, line) in body[..atom_count].iter().enumerate() {
+        if let Some((element, position, charge)) =
+            recovery.skip_bad(line.number, v2000_atom(line))?
+        {
+            let id = molecule.insert_atom(element.to_string(), position);
+            molecule.set_formal_charge(id, charge);
+            ids.insert(index + 1, id);
+        }
+    }
+    for line in &body[atom_count..atom_count + bond_count] {
+        let bond = v2000_bond(molecule, &ids, line);
+        recovery.skip_bad(line.number, bond)?;
+    }
+
+    let mut charges_reset = false;
+    for line in &body[atom_count + bond_count..] {
+        if line.text.starts_with("M  END") {
+            break;
+        }
+        if !line.text.starts_with("M  CHG") {
+            continue;
+        }
+        // Any CHG line supersedes every charge in the atom block.
+        if !charges_reset {
+            for &id in ids.values() {
+                molecule.set_formal_charge(id, 0);
+            }
+            charges_reset = true;
+        }
+        let charges = recovery.skip_bad(line.number, v2000_charges(&ids, line))?;
+        for (id, charge) in charges.into_iter().flatten() {
+            molecule.set_formal_charge(id, charge);
+        }
+    }
+    Ok(())
+}
+
+/// Element, position and charge of an atom block line.
+fn v2000_atom<'a>(line: &Line<'a>) -> Result<(&'a str, [f32; 3], i32), SdfError> {
+    let x = line.column(0, 10).parse("x")?;
+    let y = line.column(10, 20).parse("y")?;
+    let z = line.column(20, 30).parse("z")?;
+    let element = line.column(31, 34);
+    if element.is_empty() {
+        return Err(element.error("missing element").into());
+    }
+    // Old-style charge codes: 1–3 are +3..+1, 5–7 are -1..-3, 4 is a radical.
+    let code = line.column(36, 39);
+    let charge = if code.is_empty() {
+        0
+    } else {
+        match code.parse::<i32>("charge code")? {
+            code @ (1..=3 | 5..=7) => 4 - code,
+            _ => 0,
+        }
+    };
+    Ok((element.text, [x, y, z], charge))
+}
+
+fn v2000_bond(
+    molecule: &mut Molecule,
+    ids: &HashMap<usize, AtomId>,
+    line: &Line,
+) -> Result<(), SdfError> {
+    bond_between(
+        molecule,
+        ids,
+        line.column(0, 3),
+        line.column(3, 6),
+        line.column(6, 9),
+    )
+}
+
+/// Atoms and charges of an `M  CHG` line.
+fn v2000_charges(
+    ids: &HashMap<usize, AtomId>,
+    line: &Line,
+) -> Result<Vec<(AtomId, i32)>, SdfError> {
+    // "M", "CHG" and the entry count come before the pairs.
+    let pairs: Vec<Field> = line.fields().skip(3).collect();
+    if !pairs.len().is_multiple_of(2) {
+        return Err(SdfError::new(line.number, "unpaired charge entry"));
+    }
+    pairs
+        .chunks(2)
+        .map(|pair| {
+            Ok((
+                atom_at(ids, pair[0], "charged atom")?,
+                pair[1].parse("charge")?,
+            ))
+        })
+        .collect()
+}
+
+fn parse_v3000(
+    molecule: &mut Molecule,
+    body: &[Line],
+    recovery: &mut Recovery,
+) -> Result<(), SdfError> {
+    // Join continuation lines (ending in '-'). Entries keep the "M  V30 " prefix so columns
+    // match the file for entries on one line.
+    let mut entries: Vec<(usize, String)> = Vec::new();
+    let mut continued = false;
+    for line in body {
+        if line.text.starts_with("M  END") {
+            break;
+        }
+        let Some(text) = line.text.strip_prefix(V3000_PREFIX) else {
+            continue;
+        };
+        let (text, continues) = match text.trim_end().strip_suffix('-') {
+            Some(text) => (text, true),
+            None => (text.trim_end(), false),
+        };
+        match entries.last_mut() {
+            Some((_, entry)) if continued => entry.push_str(text),
+            _ => entries.push((line.number, format!("{V3000_PREFIX}{text}"))),
+        }
+        continued = continues;
+    }
+
+    let mut ids = HashMap::new();
+    let mut block = String::new();
+    for (number, entry) in &entries {
+        let line = Line {
+            number: *number,
+            text: entry,
+        };
+        let fields: Vec<Field> = line.fields().skip(2).collect();
+        let result = match fields.as_slice() {
+            [keyword, name, ..] if keyword.text == "BEGIN" => {
+                block = name.text.to_string();
+                Ok(())
+            }
+            [keyword, ..] if keyword.text == "END" => {
+                block.clear();
+                Ok(())
+            }
+            [index, element, x, y, z, _map, options @ ..] if block == "ATOM" => {
+                v3000_atom(molecule, &mut ids, [index, element, x, y, z], options)
+            }
+            [_, bond_type, a, b, ..] if block == "BOND" => {
+                bond_between(molecule, &ids, *a, *b, *bond_type)
+            }
+            _ if block == "ATOM" || block == "BOND" => {
+                Err(SdfError::new(*number, format!("malformed {block} entry")))
+            }
+            _ => Ok(()),
+        };
+        recovery.skip_bad(*number, result)?;
+    }
+    Ok(())
+}
+
+/// Inserts the atom of an ATOM block entry once all of its fields have parsed.
+fn v3000_atom(
+    molecule: &mut Molecule,
+    ids: &mut HashMap<usize, AtomId>,
+    [index, element, x, y, z]: [&Field; 5],
+    options: &[Field],
+) -> Result<(), SdfError> {
+    let index: usize = index.parse("atom index")?;
+    let position = [x.parse("x")?, y.parse("y")?, z.parse("z")?];
+    let mut charge = 0;
+    for option in options {
+        if let Some(text) = option.text.strip_prefix("CHG=") {
+            let value = Field {
+                text,
+                column: option.column + "CHG=".len(),
+                ..*option
+            };
+            charge = value.parse("charge")?;
+        }
+    }
+    let id = molecule.insert_atom(element.text.to_string(), position);
+    molecule.set_formal_charge(id, charge);
+    ids.insert(index, id);
+    Ok(())
+}
+
+/// One SD record (ending in `$$$$`) with bond orders, formal charges and per-atom
+/// properties. Whitespace inside property values becomes `_`.
+pub fn write_sdf(molecule: &Molecule) -> String {
+    let atoms: Vec<_> = molecule.atoms_in_order().collect();
+    let index: HashMap<AtomId, usize> = atoms
+        .iter()
+        .enumerate()
+        .map(|(position, atom)| (atom.id, position + 1))
+        .collect();
+    let mut bonds: Vec<_> = molecule.bonds().collect();
+    bonds.sort_by_key(|bond| bond.id);
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", molecule.name.lines().next().unwrap_or(""));
+    out.push_str("  MolWeaver\n\n");
+    let coordination = bonds.iter().any(|bond| bond.kind == BondKind::Coordination);
+    if atoms.len() <= V2000_MAX_COUNT && bonds.len() <= V2000_MAX_COUNT && !coordination {
+        let _ = writeln!(
+            out,
+            "{:3}{:3}  0  0  0  0  0  0  0  0999 V2000",
+            atoms.len(),
+            bonds.len()
+        );
+        for atom in &atoms {
+            let [x, y, z] = atom.position;
+            // Old-style charge code; M  CHG below carries charges beyond ±3 as well.
+            let code = match atom.charge {
+                charge @ -3..=3 if charge != 0 => 4 - charge,
+                _ => 0,
+            };
+            let _ = writeln!(
+                out,
+                "{x:10.4}{y:10.4}{z:10.4} {:<3} 0{code:3}  0  0  0  0  0  0  0  0  0  0",
+                atom.element
+            );
+        }
+        for bond in &bonds {
+            let _ = writeln!(
+                out,
+                "{:3}{:3}{:3}  0",
+                index[&bond.a],
+                index[&bond.b],
+                bond.order.clamp(1, 3)
+            );
+        }
+        let charged: Vec<_> = atoms.iter().filter(|atom| atom.charge != 0).collect();
+        for chunk in charged.chunks(8) {
+            let _ = write!(out, "M  CHG{:3}", chunk.len());
+            for atom in chunk {
+                let _ = write!(out, " {:3} {:3}", index[&atom.id], atom.charge);
+            }
+            out.push('\n');
+        }
+    } else {
+        out.push_str("  0  0  0     0  0            999 V3000\n");
+        out.push_str("M  V30 BEGIN CTAB\n");
+        let _ = writeln!(out, "M  V30 COUNTS {} {} 0 0 0", atoms.len(), bonds.len());
+        out.push_str("M  V30 BEGIN ATOM\n");
+        for (position, atom) in atoms.iter().enumerate() {
+            let [x, y, z] = atom.position;
+            let _ = write!(
+                out,
+                "M  V30 {} {} {x:.4} {y:.4} {z:.4} 0",
+                position + 1,
+                atom.element
+            );
+            if atom.charge != 0 {
+                let _ = write!(out, " CHG={}", atom.charge);
+            }
+            out.push('\n');
+        }
+        out.push_str("M  V30 END ATOM\nM  V30 BEGIN BOND\n");
+        for (position, bond) in bonds.iter().enumerate() {
+            let bond_type = match bond.kind {
+                BondKind::Covalent => bond.order.clamp(1, 3),
+                BondKind::Coordination => COORDINATION_BOND_TYPE,
+            };
+            let _ = writeln!(
+                out,
+                "M  V30 {} {bond_type} {} {}",
+                position + 1,
+                index[&bond.a],
+                index[&bond.b]
+            );
+        }
+        out.push_str("M  V30 END BOND\nM  V30 END CTAB\n");
+    }
+    out.push_str("M  END\n");
+
+    for name in molecule.properties.names() {
+        let values: Vec<String> = atoms
+            .iter()
+            .map(|atom| match molecule.properties.get(name, atom.id) {
+                Some(value) if !value.trim().is_empty() => {
+                    value.split_whitespace().collect::<Vec<_>>().join("_")
+                }
+                _ => MISSING_VALUE.to_string(),
+            })
+            .collect();
+        if values.iter().all(|value| value == MISSING_VALUE) {
+            continue;
+        }
+        let _ = writeln!(out, "> <{ATOM_PROPERTY_PREFIX}{name}>");
+        let _ = writeln!(out, "{}\n", values.join(" "));
+    }
+    out.push_str("$$$$\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACETATE_V2000: &str = "\
+acetate
+  MolWeaver
+
+  4  3  0  0  0  0  0  0  0  0999 V2000
+    0.0000    0.0000    0.0000 C   0  0  0  0  0  0  0  0  0  0  0  0
+    1.5200    0.0000    0.0000 C   0  0  0  0  0  0  0  0  0  0  0  0
+    2.1500    1.0800    0.0000 O   0  0  0  0  0  0  0  0  0  0  0  0
+    2.1500   -1.0800    0.0000 O   0  5  0  0  0  0  0  0  0  0  0  0
+  1  2  1  0
+  2  3  2  0
+  2  4  1  0
+M  END
+> <source>
+test
+
+$$$$
+";
+
+    const AMMONIUM_V3000: &str = "\
+ammonium
+  MolWeaver
+
+  0  0  0     0  0            999 V3000
+M  V30 BEGIN CTAB
+M  V30 COUNTS 2 1 0 0 0
+M  V30 BEGIN ATOM
+M  V30 1 N 0.0 0.0 0.0 0 CHG=1
+M  V30 2 H 1.01 0.0 -
+M  V30 0.0 0
+M  V30 END ATOM
+M  V30 BEGIN BOND
+M  V30 1 1 1 2
+M  V30 END BOND
+M  V30 END CTAB
+M  END
+$$$$
+";
+
+    #[test]
+    fn reads_v2000_and_v3000_records() {
+        let contents = format!("{ACETATE_V2000}{AMMONIUM_V3000}");
+        let molecules = parse_sdf(&contents).unwrap();
+        assert_eq!(molecules.len(), 2);
+
+        let acetate = &molecules[0];
+        assert_eq!(acetate.name, "acetate");
+        assert_eq!(acetate.formula(), "C2O2");
+        let charges: Vec<i32> = acetate.atoms_in_order().map(|atom| atom.charge).collect();
+        assert_eq!(charges, [0, 0, 0, -1]);
+        let mut orders: Vec<u8> = acetate.bonds().map(|bond| bond.order).collect();
+        orders.sort();
+        assert_eq!(orders, [1, 1, 2]);
+
+        let ammonium = &molecules[1];
+        assert_eq!(ammonium.name, "ammonium");
+        let atoms: Vec<_> = ammonium.atoms_in_order().collect();
+        assert_eq!(atoms[0].charge, 1);
+        assert_eq!(atoms[1].position, [1.01, 0.0, 0.0]);
+        assert_eq!(ammonium.bonds().count(), 1);
+
+        let second = SdfOptions { record: 1 };
+        let picked = parse_mol_with(&contents, &second, &mut Recovery::strict()).unwrap();
+        assert_eq!(picked.name, "ammonium");
+        let err = parse_mol_with(ACETATE_V2000, &second, &mut Recovery::strict()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 1: record 2 requested, but the file has 1"
+        );
+
+        // CHG properties override the atom block.
+        let charged = ACETATE_V2000.replace("M  END", "M  CHG  1   3  -1\nM  END");
+        let charges: Vec<i32> = parse_mol(&charged)
+            .unwrap()
+            .atoms_in_order()
+            .map(|atom| atom.charge)
+            .collect();
+        assert_eq!(charges, [0, 0, -1, 0]);
+
+        let dangling = ACETATE_V2000.replace("  2  4  1  0", "  2  9  1  0");
+        let err = parse_sdf(&dangling).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 11, column 6: bonded atom 9 does not exist"
+        );
+        let (molecules, problems) = parse_sdf_lenient(&dangling).unwrap();
+        assert_eq!(molecules[0].bonds().count(), 2);
+        assert_eq!(problems[0].line, 11);
+    }
+
+    #[test]
+    fn written_records_read_back() {
+        let mut written = parse_mol(ACETATE_V2000).unwrap();
+        written.set_formal_charge(written.atom_ids()[2], 5);
+        written
+            .properties
+            .set("shift", written.atom_ids()[0], "21.3");
+        written
+            .properties
+            .set("shift", written.atom_ids()[1], "178 ppm");
+        let reread = parse_mol(&write_sdf(&written)).unwrap();
+        let charges: Vec<i32> = reread.atoms_in_order().map(|atom| atom.charge).collect();
+        assert_eq!(charges, [0, 0, 5, -1]);
+        assert_eq!(reread.bonds().filter(|bond| bond.order == 2).count(), 1);
+        let shifts: Vec<_> = reread
+            .atom_ids()
+            .into_iter()
+            .map(|id| reread.properties.get("shift", id))
+            .collect();
+        assert_eq!(shifts, [Some("21.3"), Some("178_ppm"), None, None]);
+
+        // V2000 has no coordination bond type.
+        let sodium = written.insert_atom("Na".into(), [3.0, 0.0, 0.0]);
+        written
+            .add_coordination_bond(written.atom_ids()[3], sodium)
+            .unwrap();
+        let contents = write_sdf(&written);
+        assert!(contents.lines().nth(3).unwrap().ends_with("V3000"));
+        let reread = parse_mol(&contents).unwrap();
+        let kinds: Vec<BondKind> = reread.sorted_bonds().iter().map(|bond| bond.kind).collect();
+        assert_eq!(kinds.last(), Some(&BondKind::Coordination));
+        assert_eq!(reread.bonds().filter(|bond| bond.order == 2).count(), 1);
+
+        // Past 999 atoms the counts no longer fit V2000 and the record switches to V3000.
+        let mut chain = Molecule::new("chain");
+        let mut previous = None;
+        for index in 0..1000 {
+            let id = chain.insert_atom("C".into(), [index as f32 * 1.5, 0.0, 0.0]);
+            if let Some(previous) = previous {
+                chain.add_bond(previous, id).unwrap();
+            }
+            previous = Some(id);
+        }
+        let contents = write_sdf(&chain);
+        assert!(contents.lines().nth(3).unwrap().ends_with("V3000"));
+        let reread = parse_mol(&contents).unwrap();
+        assert_eq!(reread.atom_count(), 1000);
+        assert_eq!(reread.bonds().count(), 999);
+    }
+}
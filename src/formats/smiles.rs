@@ -0,0 +1,696 @@
+//! SMILES input: elements, bond orders, charges, branches and ring closures, given rough 3D
+//! coordinates by an [`Embedder`] and completed with hydrogens so the result can be edited
+//! like any other structure.
+//!
+//! Lowercase aromatic atoms are kekulized into alternating single and double bonds. Stereo
+//! marks (`@`, `/`, `\`), isotopes and atom classes are read but have no effect; wildcard
+//! atoms and quadruple bonds are rejected.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::f32::consts::TAU;
+use std::fmt;
+use std::sync::Arc;
+
+use glam::{Quat, Vec3};
+
+use crate::elements::{atomic_number, covalent_radius, normalize_symbol};
+use crate::hydrogens::{hydrogen_positions, standard_valence};
+use crate::valence::{StandardValence, Unrestricted};
+use crate::{AtomId, Molecule};
+
+/// Distance between the starting points of disconnected parts (`.` in the string).
+const COMPONENT_SPACING: f32 = 8.0;
+const RELAX_ITERATIONS: usize = 500;
+/// Non-bonded heavy atoms closer than this are pushed apart.
+const REPULSION_RANGE: f32 = 3.0;
+
+#[derive(Debug, Clone)]
+pub struct SmilesError {
+    details: String,
+}
+
+impl SmilesError {
+    fn new(position: usize, details: impl Into<String>) -> Self {
+        Self {
+            details: format!("position {position}: {}", details.into()),
+        }
+    }
+}
+
+impl fmt::Display for SmilesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl std::error::Error for SmilesError {}
+
+/// Turns a bare molecular graph into 3D coordinates. Called before hydrogens are added, with
+/// every atom at the origin; implementations set each atom's position.
+pub trait Embedder {
+    fn embed(&self, molecule: &mut Molecule);
+}
+
+/// Places atoms outward from the first one at ideal bond angles, then relaxes bond lengths,
+/// bond angles and non-bonded contacts. Good enough to build on, not a conformer search.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RoughEmbedder;
+
+/// Parses `smiles` and embeds it with [`RoughEmbedder`].
+pub fn parse_smiles(smiles: &str) -> Result<Molecule, SmilesError> {
+    parse_smiles_with(smiles, &RoughEmbedder)
+}
+
+pub fn parse_smiles_with(smiles: &str, embedder: &dyn Embedder) -> Result<Molecule, SmilesError> {
+    let graph = parse_graph(smiles)?;
+    let orders = kekulize(&graph)?;
+
+    let mut molecule = Molecule::new(smiles.trim());
+    molecule.set_valence_model(Arc::new(Unrestricted));
+    let ids: Vec<AtomId> = graph
+        .atoms
+        .iter()
+        .map(|atom| {
+            let id = molecule.insert_atom(atom.element.clone(), [0.0; 3]);
+            molecule.set_formal_charge(id, atom.charge);
+            id
+        })
+        .collect();
+    for (bond, order) in graph.bonds.iter().zip(orders) {
+        let id = molecule
+            .add_bond(ids[bond.a], ids[bond.b])
+            .map_err(|err| SmilesError::new(bond.at, err))?;
+        molecule.set_bond_order(id, order);
+    }
+    embedder.embed(&mut molecule);
+
+    for (atom, &id) in graph.atoms.iter().zip(&ids) {
+        let positions = hydrogen_positions(&molecule, id);
+        // Bracket atoms carry exactly the hydrogens they list.
+        let count = atom.hydrogens.map_or(positions.len(), usize::from);
+        for position in positions.into_iter().take(count) {
+            let hydrogen = molecule.insert_atom("H".to_string(), position.to_array());
+            molecule
+                .add_bond(id, hydrogen)
+                .map_err(|err| SmilesError::new(atom.at, err))?;
+        }
+    }
+    molecule.set_valence_model(Arc::new(StandardValence));
+    Ok(molecule)
+}
+
+struct ParsedAtom {
+    element: String,
+    aromatic: bool,
+    charge: i32,
+    /// Explicit hydrogen count of a bracket atom; `None` for the organic subset.
+    hydrogens: Option<u8>,
+    /// 1-based character position, for errors.
+    at: usize,
+}
+
+struct ParsedBond {
+    a: usize,
+    b: usize,
+    /// `None` for aromatic bonds, resolved by [`kekulize`].
+    order: Option<u8>,
+    at: usize,
+}
+
+#[derive(Default)]
+struct Graph {
+    atoms: Vec<ParsedAtom>,
+    bonds: Vec<ParsedBond>,
+}
+
+impl Graph {
+    fn add_bond(
+        &mut self,
+        a: usize,
+        b: usize,
+        symbol: Option<char>,
+        at: usize,
+    ) -> Result<(), SmilesError> {
+        if a == b {
+            return Err(SmilesError::new(at, "atom bonded to itself"));
+        }
+        if self
+            .bonds
+            .iter()
+            .any(|bond| (bond.a, bond.b) == (a, b) || (bond.a, bond.b) == (b, a))
+        {
+            return Err(SmilesError::new(at, "atoms bonded twice"));
+        }
+        let order = match symbol {
+            Some('-' | '/' | '\\') => Some(1),
+            Some('=') => Some(2),
+            Some('#') => Some(3),
+            Some(':') => None,
+            Some(other) => return Err(SmilesError::new(at, format!("unsupported bond {other:?}"))),
+            None if self.atoms[a].aromatic && self.atoms[b].aromatic => None,
+            None => Some(1),
+        };
+        self.bonds.push(ParsedBond { a, b, order, at });
+        Ok(())
+    }
+}
+
+fn parse_graph(smiles: &str) -> Result<Graph, SmilesError> {
+    let chars: Vec<char> = smiles.trim().chars().collect();
+    let mut graph = Graph::default();
+    let mut previous: Option<usize> = None;
+    let mut branches: Vec<usize> = Vec::new();
+    let mut pending_bond: Option<(char, usize)> = None;
+    // Open ring closures by number: atom, bond symbol and position.
+    let mut rings: HashMap<u32, (usize, Option<char>, usize)> = HashMap::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let at = i + 1;
+        let c = chars[i];
+        i += 1;
+        match c {
+            '(' => {
+                let atom =
+                    previous.ok_or_else(|| SmilesError::new(at, "branch before any atom"))?;
+                branches.push(atom);
+            }
+            ')' => {
+                if pending_bond.is_some() {
+                    return Err(SmilesError::new(at, "bond without a second atom"));
+                }
+                previous = Some(
+                    branches
+                        .pop()
+                        .ok_or_else(|| SmilesError::new(at, "unmatched ')'"))?,
+                );
+            }
+            '-' | '=' | '#' | '$' | ':' | '/' | '\\' => {
+                if pending_bond.is_some() {
+                    return Err(SmilesError::new(at, "two bonds in a row"));
+                }
+                pending_bond = Some((c, at));
+            }
+            '.' => {
+                if pending_bond.is_some() {
+                    return Err(SmilesError::new(at, "bond without a second atom"));
+                }
+                previous = None;
+            }
+            '%' | '0'..='9' => {
+                let number = if c == '%' {
+                    let digits: String = chars.iter().skip(i).take(2).collect();
+                    i += 2;
+                    match digits.parse() {
+                        Ok(number) if digits.len() == 2 => number,
+                        _ => return Err(SmilesError::new(at, "'%' needs two digits")),
+                    }
+                } else {
+                    c.to_digit(10).unwrap_or_default()
+                };
+                let atom =
+                    previous.ok_or_else(|| SmilesError::new(at, "ring closure before any atom"))?;
+                let symbol = pending_bond.take().map(|(symbol, _)| symbol);
+                match rings.remove(&number) {
+                    Some((other, opening, _)) => {
+                        if symbol.is_some() && opening.is_some() && symbol != opening {
+                            return Err(SmilesError::new(
+                                at,
+                                format!("ring {number} closes with a different bond"),
+                            ));
+                        }
+                        graph.add_bond(other, atom, symbol.or(opening), at)?;
+                    }
+                    None => {
+                        rings.insert(number, (atom, symbol, at));
+                    }
+                }
+            }
+            _ => {
+                let atom = if c == '[' {
+                    let close = chars[i..]
+                        .iter()
+                        .position(|&c| c == ']')
+                        .ok_or_else(|| SmilesError::new(at, "unclosed '['"))?;
+                    let content: String = chars[i..i + close].iter().collect();
+                    i += close + 1;
+                    parse_bracket_atom(&content, at)?
+                } else {
+                    let two: String = chars[i - 1..chars.len().min(i + 1)].iter().collect();
+                    let symbol = if two == "Cl" || two == "Br" {
+                        i += 1;
+                        two.as_str()
+                    } else {
+                        &two[..c.len_utf8()]
+                    };
+                    organic_atom(symbol, at)?
+                };
+                let index = graph.atoms.len();
+                graph.atoms.push(atom);
+                match (previous, pending_bond.take()) {
+                    (Some(previous), bond) => {
+                        graph.add_bond(previous, index, bond.map(|(symbol, _)| symbol), at)?
+                    }
+                    (None, Some((_, bond_at))) => {
+                        return Err(SmilesError::new(bond_at, "bond without a first atom"))
+                    }
+                    (None, None) => {}
+                }
+                previous = Some(index);
+            }
+        }
+    }
+
+    let end = chars.len();
+    if let Some((_, at)) = pending_bond {
+        return Err(SmilesError::new(at, "bond without a second atom"));
+    }
+    if !branches.is_empty() {
+        return Err(SmilesError::new(end, "unclosed '('"));
+    }
+    if let Some((number, (_, _, at))) = rings.iter().min_by_key(|(_, (_, _, at))| *at) {
+        return Err(SmilesError::new(
+            *at,
+            format!("ring {number} is never closed"),
+        ));
+    }
+    if graph.atoms.is_empty() {
+        return Err(SmilesError::new(end, "no atoms"));
+    }
+    Ok(graph)
+}
+
+fn organic_atom(symbol: &str, at: usize) -> Result<ParsedAtom, SmilesError> {
+    let aromatic = match symbol {
+        "B" | "C" | "N" | "O" | "P" | "S" | "F" | "Cl" | "Br" | "I" => false,
+        "b" | "c" | "n" | "o" | "p" | "s" => true,
+        "*" => return Err(SmilesError::new(at, "wildcard atoms are not supported")),
+        _ => return Err(SmilesError::new(at, format!("unexpected {symbol:?}"))),
+    };
+    Ok(ParsedAtom {
+        element: normalize_symbol(symbol),
+        aromatic,
+        charge: 0,
+        hydrogens: None,
+        at,
+    })
+}
+
+/// Reads `[isotope symbol chirality hcount charge :class]`, given the text between the brackets.
+fn parse_bracket_atom(content: &str, at: usize) -> Result<ParsedAtom, SmilesError> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut i = 0;
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while chars.get(*i).is_some_and(char::is_ascii_digit) {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>()
+    };
+    digits(&mut i);
+
+    let first = *chars
+        .get(i)
+        .ok_or_else(|| SmilesError::new(at, "empty bracket atom"))?;
+    if first == '*' {
+        return Err(SmilesError::new(at, "wildcard atoms are not supported"));
+    }
+    let aromatic = first.is_ascii_lowercase();
+    let two: String = chars[i..chars.len().min(i + 2)].iter().collect();
+    let two_letter = two.len() == 2
+        && two.chars().nth(1).is_some_and(|c| c.is_ascii_lowercase())
+        && if aromatic {
+            matches!(two.as_str(), "se" | "as")
+        } else {
+            atomic_number(&two).is_some()
+        };
+    let symbol = if two_letter { two } else { first.to_string() };
+    i += symbol.len();
+    if atomic_number(&symbol).is_none()
+        || aromatic
+            && !matches!(
+                symbol.as_str(),
+                "b" | "c" | "n" | "o" | "p" | "s" | "se" | "as"
+            )
+    {
+        return Err(SmilesError::new(at, format!("unknown element {symbol:?}")));
+    }
+
+    // Chirality: @, @@ or a class such as @TH1, @SP2, @OH12.
+    while chars.get(i) == Some(&'@') {
+        i += 1;
+    }
+    if chars.get(i).is_some_and(char::is_ascii_uppercase)
+        && chars.get(i + 1).is_some_and(char::is_ascii_uppercase)
+    {
+        i += 2;
+        digits(&mut i);
+    }
+
+    let mut hydrogens = 0;
+    if chars.get(i) == Some(&'H') {
+        i += 1;
+        hydrogens = match digits(&mut i).as_str() {
+            "" => 1,
+            count => count
+                .parse()
+                .map_err(|_| SmilesError::new(at, "invalid hydrogen count"))?,
+        };
+    }
+
+    let mut charge = 0;
+    if let Some(&sign @ ('+' | '-')) = chars.get(i) {
+        let unit = if sign == '+' { 1 } else { -1 };
+        i += 1;
+        charge = match digits(&mut i).as_str() {
+            "" => {
+                let mut count = 1;
+                while chars.get(i) == Some(&sign) {
+                    count += 1;
+                    i += 1;
+                }
+                count * unit
+            }
+            magnitude => {
+                unit * magnitude
+                    .parse::<i32>()
+                    .map_err(|_| SmilesError::new(at, "invalid charge"))?
+            }
+        };
+    }
+
+    if chars.get(i) == Some(&':') {
+        i += 1;
+        digits(&mut i);
+    }
+    if let Some(c) = chars.get(i) {
+        return Err(SmilesError::new(
+            at,
+            format!("unexpected {c:?} in bracket atom"),
+        ));
+    }
+    Ok(ParsedAtom {
+        element: normalize_symbol(&symbol),
+        aromatic,
+        charge,
+        hydrogens: Some(hydrogens),
+        at,
+    })
+}
+
+/// Bond orders with aromatic bonds resolved: every aromatic atom with a free valence gets
+/// exactly one double bond to an aromatic neighbor.
+fn kekulize(graph: &Graph) -> Result<Vec<u8>, SmilesError> {
+    let mut orders: Vec<u8> = graph
+        .bonds
+        .iter()
+        .map(|bond| bond.order.unwrap_or(1))
+        .collect();
+    let mut used = vec![0; graph.atoms.len()];
+    for (bond, order) in graph.bonds.iter().zip(&orders) {
+        used[bond.a] += i32::from(*order);
+        used[bond.b] += i32::from(*order);
+    }
+    let needs_double: Vec<bool> = graph
+        .atoms
+        .iter()
+        .zip(&used)
+        .map(|(atom, used)| {
+            let valence = standard_valence(&atom.element, atom.charge).unwrap_or(0);
+            let hydrogens = atom.hydrogens.map_or(0, i32::from);
+            atom.aromatic && valence - used - hydrogens >= 1
+        })
+        .collect();
+
+    let mut candidates = vec![Vec::new(); graph.atoms.len()];
+    for (index, bond) in graph.bonds.iter().enumerate() {
+        if bond.order.is_none() && needs_double[bond.a] && needs_double[bond.b] {
+            candidates[bond.a].push((bond.b, index));
+            candidates[bond.b].push((bond.a, index));
+        }
+    }
+    let mut matched = vec![false; graph.atoms.len()];
+    for (index, needs) in needs_double.iter().enumerate() {
+        matched[index] = !needs;
+    }
+    let mut doubles = Vec::new();
+    if !match_doubles(&candidates, &mut matched, &mut doubles) {
+        let stuck = matched.iter().position(|matched| !matched).unwrap_or(0);
+        return Err(SmilesError::new(
+            graph.atoms[stuck].at,
+            "aromatic system cannot be kekulized",
+        ));
+    }
+    for index in doubles {
+        orders[index] = 2;
+    }
+    Ok(orders)
+}
+
+/// Backtracking perfect matching of the unmatched atoms over `candidates`.
+fn match_doubles(
+    candidates: &[Vec<(usize, usize)>],
+    matched: &mut [bool],
+    doubles: &mut Vec<usize>,
+) -> bool {
+    let Some(atom) = matched.iter().position(|matched| !matched) else {
+        return true;
+    };
+    matched[atom] = true;
+    for &(other, bond) in &candidates[atom] {
+        if matched[other] {
+            continue;
+        }
+        matched[other] = true;
+        doubles.push(bond);
+        if match_doubles(candidates, matched, doubles) {
+            return true;
+        }
+        doubles.pop();
+        matched[other] = false;
+    }
+    matched[atom] = false;
+    false
+}
+
+impl Embedder for RoughEmbedder {
+    fn embed(&self, molecule: &mut Molecule) {
+        let ids = molecule.atom_ids();
+        let index: HashMap<AtomId, usize> =
+            ids.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+        let radii: Vec<f32> = ids
+            .iter()
+            .map(|&id| {
+                molecule
+                    .get_atom(id)
+                    .map_or(1.0, |atom| covalent_radius(&atom.element))
+            })
+            .collect();
+        let mut neighbors = vec![Vec::new(); ids.len()];
+        let mut lengths = HashMap::new();
+        let mut doubles = vec![0; ids.len()];
+        let mut triples = vec![false; ids.len()];
+        for bond in molecule.bonds() {
+            let (a, b) = (index[&bond.a], index[&bond.b]);
+            neighbors[a].push(b);
+            neighbors[b].push(a);
+            let scale = match bond.order {
+                2 => 0.87,
+                3 => 0.78,
+                _ => 1.0,
+            };
+            lengths.insert((a.min(b), a.max(b)), (radii[a] + radii[b]) * scale);
+            for end in [a, b] {
+                doubles[end] += usize::from(bond.order == 2);
+                triples[end] |= bond.order >= 3;
+            }
+        }
+        let angles: Vec<f32> = (0..ids.len())
+            .map(|i| {
+                let degrees: f32 = if triples[i] || doubles[i] >= 2 {
+                    180.0
+                } else if neighbors[i].len() > 4 {
+                    90.0
+                } else if doubles[i] == 1 {
+                    120.0
+                } else {
+                    109.47
+                };
+                degrees.to_radians()
+            })
+            .collect();
+        let length = |a: usize, b: usize| lengths[&(a.min(b), a.max(b))];
+
+        // Bonds and 1-3 distances from the ideal angle at the middle atom.
+        let mut springs = Vec::new();
+        for (&(a, b), &target) in &lengths {
+            springs.push((a, b, target, 1.0));
+        }
+        for (center, around) in neighbors.iter().enumerate() {
+            for (k, &a) in around.iter().enumerate() {
+                // In three-membered rings the bond wins over the angle.
+                for &b in &around[k + 1..] {
+                    if lengths.contains_key(&(a.min(b), a.max(b))) {
+                        continue;
+                    }
+                    let (la, lb) = (length(center, a), length(center, b));
+                    let target = (la * la + lb * lb - 2.0 * la * lb * angles[center].cos()).sqrt();
+                    springs.push((a.min(b), a.max(b), target, 0.5));
+                }
+            }
+        }
+        let linked: HashSet<(usize, usize)> = springs.iter().map(|&(a, b, _, _)| (a, b)).collect();
+
+        let mut positions = initial_positions(&neighbors, &angles, length);
+        for _ in 0..RELAX_ITERATIONS {
+            for &(a, b, target, weight) in &springs {
+                pull(&mut positions, a, b, target, weight);
+            }
+            for a in 0..positions.len() {
+                for b in a + 1..positions.len() {
+                    if !linked.contains(&(a, b))
+                        && positions[a].distance(positions[b]) < REPULSION_RANGE
+                    {
+                        pull(&mut positions, a, b, REPULSION_RANGE, 0.1);
+                    }
+                }
+            }
+        }
+        for (&id, position) in ids.iter().zip(positions) {
+            molecule.set_atom_position(id, position.to_array());
+        }
+    }
+}
+
+/// Breadth-first placement at ideal angles: children of an atom spread on a cone around the
+/// direction back to its parent, turned away from the grandparent so chains zig-zag.
+fn initial_positions(
+    neighbors: &[Vec<usize>],
+    angles: &[f32],
+    length: impl Fn(usize, usize) -> f32,
+) -> Vec<Vec3> {
+    let mut positions = vec![Vec3::ZERO; neighbors.len()];
+    let mut parents: Vec<Option<usize>> = vec![None; neighbors.len()];
+    let mut placed = vec![false; neighbors.len()];
+    let mut components = 0;
+    for root in 0..neighbors.len() {
+        if placed[root] {
+            continue;
+        }
+        positions[root] = Vec3::Y * COMPONENT_SPACING * components as f32;
+        placed[root] = true;
+        components += 1;
+        let mut queue = VecDeque::from([root]);
+        while let Some(atom) = queue.pop_front() {
+            let children: Vec<usize> = neighbors[atom]
+                .iter()
+                .copied()
+                .filter(|&other| !placed[other])
+                .collect();
+            let center = positions[atom];
+            let angle = angles[atom];
+            let directions: Vec<Vec3> = match parents[atom] {
+                Some(parent) => {
+                    let back = (positions[parent] - center).normalize();
+                    let reference = parents[parent]
+                        .map(|grandparent| positions[grandparent] - positions[parent])
+                        .and_then(|side| (back * side.dot(back) - side).try_normalize())
+                        .unwrap_or_else(|| back.any_orthonormal_vector());
+                    spread(back, reference, angle, children.len())
+                }
+                None => {
+                    let mut directions = vec![Vec3::X];
+                    directions.extend(spread(
+                        Vec3::X,
+                        Vec3::Y,
+                        angle,
+                        children.len().saturating_sub(1),
+                    ));
+                    directions
+                }
+            };
+            for (&child, direction) in children.iter().zip(directions) {
+                // A slight out-of-plane offset keeps rings from starting folded flat.
+                let tilt = Vec3::Z * 0.05 * ((child % 5) as f32 - 2.0);
+                positions[child] = center + direction * length(atom, child) + tilt;
+                parents[child] = Some(atom);
+                placed[child] = true;
+                queue.push_back(child);
+            }
+        }
+    }
+    positions
+}
+
+/// `count` directions at `angle` from `axis`, evenly turned about it starting at `reference`.
+fn spread(axis: Vec3, reference: Vec3, angle: f32, count: usize) -> Vec<Vec3> {
+    (0..count)
+        .map(|k| {
+            let around = Quat::from_axis_angle(axis, k as f32 * TAU / count as f32);
+            axis * angle.cos() + around * reference * angle.sin()
+        })
+        .collect()
+}
+
+/// Moves `a` and `b` symmetrically toward being `target` apart.
+fn pull(positions: &mut [Vec3], a: usize, b: usize, target: f32, weight: f32) {
+    let delta = positions[b] - positions[a];
+    let distance = delta.length();
+    if distance < 1e-4 {
+        positions[b] += Vec3::new(0.01, 0.02, 0.03);
+        return;
+    }
+    let shift = delta * ((distance - target) / distance * weight * 0.5);
+    positions[a] += shift;
+    positions[b] -= shift;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance(molecule: &Molecule, a: AtomId, b: AtomId) -> f32 {
+        let position = |id| Vec3::from_array(molecule.get_atom(id).unwrap().position);
+        position(a).distance(position(b))
+    }
+
+    #[test]
+    fn builds_kekulized_rings_with_hydrogens() {
+        let benzene = parse_smiles("c1ccccc1").unwrap();
+        assert_eq!(benzene.formula(), "C6H6");
+        assert_eq!(benzene.bonds().filter(|bond| bond.order == 2).count(), 3);
+        for bond in benzene.bonds() {
+            let length = distance(&benzene, bond.a, bond.b);
+            let expected = if bond.order == 2 { 1.32 } else { 1.52 };
+            if benzene.get_atom(bond.a).unwrap().element == "C"
+                && benzene.get_atom(bond.b).unwrap().element == "C"
+            {
+                assert!((length - expected).abs() < 0.15, "{length}");
+            }
+        }
+
+        assert_eq!(parse_smiles("c1cc[nH]c1").unwrap().formula(), "C4H5N");
+        assert_eq!(parse_smiles("n1ccccc1").unwrap().formula(), "C5H5N");
+        let acetate = parse_smiles("CC(=O)[O-].[Na+]").unwrap();
+        assert_eq!(acetate.formula(), "C2H3NaO2");
+        let charges: i32 = acetate.atoms_in_order().map(|atom| atom.charge).sum();
+        assert_eq!(charges, 0);
+        assert_eq!(
+            parse_smiles("OC1CCCC%10.C%101").unwrap().formula(),
+            "C6H12O"
+        );
+        assert_eq!(parse_smiles("C#N").unwrap().formula(), "CHN");
+    }
+
+    #[test]
+    fn reports_error_positions() {
+        let error = |smiles: &str| parse_smiles(smiles).unwrap_err().to_string();
+        assert_eq!(error("CC(C"), "position 4: unclosed '('");
+        assert_eq!(error("C1CC"), "position 2: ring 1 is never closed");
+        assert_eq!(error("CC[Q]"), "position 3: unknown element \"Q\"");
+        assert_eq!(
+            error("c1cccc1"),
+            "position 1: aromatic system cannot be kekulized"
+        );
+        assert_eq!(error("C=)"), "position 3: bond without a second atom");
+    }
+}
@@ -0,0 +1,176 @@
+//! Reading text a line at a time from any [`BufRead`], so huge files load without the whole
+//! text in memory.
+//!
+//! [`LineReader`] splits lines exactly as [`lines`](super::text::lines) does. Wrap the file in
+//! [`Progress`] to hear how far the read has got.
+
+use std::io::{self, BufRead, Read};
+
+use super::text::Line;
+
+const BYTE_ORDER_MARK: &[u8] = "\u{feff}".as_bytes();
+
+/// Lines of a reader, one at a time; each borrows the reader until the next call.
+#[derive(Debug)]
+pub struct LineReader<R> {
+    reader: R,
+    bytes: Vec<u8>,
+    line: String,
+    number: usize,
+    /// The last line ended with `\r`, so a `\n` right after it belongs to that line end.
+    after_cr: bool,
+    /// The current line was peeked and is returned again by the next call.
+    peeked: bool,
+}
+
+impl<R: BufRead> LineReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            bytes: Vec::new(),
+            line: String::new(),
+            number: 0,
+            after_cr: false,
+            peeked: false,
+        }
+    }
+
+    /// The next line, or `None` at the end. Invalid UTF-8 is an
+    /// [`io::ErrorKind::InvalidData`] error naming the line.
+    pub fn next_line(&mut self) -> io::Result<Option<Line<'_>>> {
+        if !std::mem::take(&mut self.peeked) && !self.advance()? {
+            return Ok(None);
+        }
+        Ok(Some(self.current()))
+    }
+
+    /// The line the next call to [`LineReader::next_line`] returns.
+    pub fn peek_line(&mut self) -> io::Result<Option<Line<'_>>> {
+        if !self.peeked {
+            if !self.advance()? {
+                return Ok(None);
+            }
+            self.peeked = true;
+        }
+        Ok(Some(self.current()))
+    }
+
+    fn current(&self) -> Line<'_> {
+        Line {
+            number: self.number,
+            text: &self.line,
+        }
+    }
+
+    /// Reads the next line into `line`; `false` at the end of the input.
+    fn advance(&mut self) -> io::Result<bool> {
+        if std::mem::take(&mut self.after_cr) && self.reader.fill_buf()?.first() == Some(&b'\n') {
+            self.reader.consume(1);
+        }
+        self.bytes.clear();
+        let mut ended = false;
+        while !ended {
+            let available = self.reader.fill_buf()?;
+            if available.is_empty() {
+                break;
+            }
+            let used = match available.iter().position(|&b| b == b'\n' || b == b'\r') {
+                Some(end) => {
+                    self.bytes.extend_from_slice(&available[..end]);
+                    self.after_cr = available[end] == b'\r';
+                    ended = true;
+                    end + 1
+                }
+                None => {
+                    self.bytes.extend_from_slice(available);
+                    available.len()
+                }
+            };
+            self.reader.consume(used);
+        }
+        if self.number == 0 && self.bytes.starts_with(BYTE_ORDER_MARK) {
+            self.bytes.drain(..BYTE_ORDER_MARK.len());
+        }
+        // Like `text::lines`, a final line end does not start another line.
+        if !ended && self.bytes.is_empty() {
+            return Ok(false);
+        }
+        self.number += 1;
+        let text = std::str::from_utf8(&self.bytes).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: invalid UTF-8", self.number),
+            )
+        })?;
+        self.line.clear();
+        self.line.push_str(text);
+        Ok(true)
+    }
+}
+
+/// Passes reads through, calling `report` with the total number of bytes read so far.
+#[derive(Debug)]
+pub struct Progress<R, F> {
+    inner: R,
+    read: u64,
+    report: F,
+}
+
+impl<R: Read, F: FnMut(u64)> Progress<R, F> {
+    pub fn new(inner: R, report: F) -> Self {
+        Self {
+            inner,
+            read: 0,
+            report,
+        }
+    }
+}
+
+impl<R: Read, F: FnMut(u64)> Read for Progress<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.read += count as u64;
+        (self.report)(self.read);
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::*;
+    use crate::formats::text;
+
+    #[test]
+    fn splits_like_text_lines_across_small_buffers() {
+        let contents = "\u{feff}3\r\nwater\rO 0 0 0\n\nH 1 0 0\r\n";
+        let expected: Vec<(usize, String)> = text::lines(contents)
+            .map(|line| (line.number, line.text.to_string()))
+            .collect();
+        // A two-byte buffer splits the byte-order mark and every CRLF.
+        let mut lines = LineReader::new(BufReader::with_capacity(2, contents.as_bytes()));
+        assert_eq!(lines.peek_line().unwrap().map(|line| line.number), Some(1));
+        let mut read = Vec::new();
+        while let Some(line) = lines.next_line().unwrap() {
+            read.push((line.number, line.text.to_string()));
+        }
+        assert_eq!(read, expected);
+
+        let mut bad = LineReader::new(&b"ok\n\xff\n"[..]);
+        bad.next_line().unwrap();
+        assert_eq!(
+            bad.next_line().unwrap_err().to_string(),
+            "line 2: invalid UTF-8"
+        );
+    }
+
+    #[test]
+    fn reports_bytes_read() {
+        let mut seen = Vec::new();
+        let mut reader = Progress::new(&b"0123456789"[..], |bytes| seen.push(bytes));
+        let mut buf = [0; 4];
+        while reader.read(&mut buf).unwrap() > 0 {}
+        assert_eq!(seen, [4, 8, 10, 10]);
+    }
+}
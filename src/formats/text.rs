@@ -0,0 +1,311 @@
+//! Line and field splitting shared by the text readers.
+//!
+//! Files from other tools arrive with a byte-order mark, CRLF or lone CR line ends, tabs or
+//! full-width spaces between fields, Fortran double-precision exponents (`1.0D-3`) and, from
+//! Japanese Windows environments, full-width digits and signs. All of these are accepted
+//! here, and a field that still fails to parse is reported by line and column.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    line: usize,
+    column: usize,
+    details: String,
+}
+
+impl FieldError {
+    pub fn new(line: usize, column: usize, details: impl Into<String>) -> Self {
+        Self {
+            line,
+            column,
+            details: details.into(),
+        }
+    }
+
+    /// 1-based line number.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// 1-based column in characters.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.details
+        )
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+/// One line without its line end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'a> {
+    /// 1-based line number.
+    pub number: usize,
+    pub text: &'a str,
+}
+
+/// Lines of `contents`, split at `\n`, `\r\n` or a lone `\r`, after any byte-order mark. Like
+/// [`str::lines`], a final line end does not start another line.
+pub fn lines(contents: &str) -> impl Iterator<Item = Line<'_>> {
+    let mut rest = contents.strip_prefix('\u{feff}').unwrap_or(contents);
+    let mut number = 0;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let end = rest.find(['\r', '\n']).unwrap_or(rest.len());
+        let text = &rest[..end];
+        let next = if rest[end..].starts_with("\r\n") {
+            end + 2
+        } else {
+            (end + 1).min(rest.len())
+        };
+        rest = &rest[next..];
+        number += 1;
+        Some(Line { number, text })
+    })
+}
+
+impl<'a> Line<'a> {
+    pub fn is_blank(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+
+    /// Fields separated by any whitespace, tabs and full-width spaces included.
+    pub fn fields(&self) -> Fields<'a> {
+        self.fields_split_by(char::is_whitespace)
+    }
+
+    /// Fields separated by runs of characters matching `separator`.
+    pub fn fields_split_by(&self, separator: fn(char) -> bool) -> Fields<'a> {
+        Fields {
+            line: *self,
+            offset: 0,
+            separator,
+        }
+    }
+
+    /// The trimmed fixed-width byte columns `start..end`, empty when the line is shorter.
+    pub fn column(&self, start: usize, end: usize) -> Field<'a> {
+        let raw = self.text.get(start..end.min(self.text.len())).unwrap_or("");
+        let leading = raw.len() - raw.trim_start().len();
+        self.field_at(start.min(self.text.len()) + leading, raw.trim())
+    }
+
+    fn field_at(&self, offset: usize, text: &'a str) -> Field<'a> {
+        Field {
+            text,
+            line: self.number,
+            column: column_of(self.text, offset),
+        }
+    }
+}
+
+/// 1-based character column of the byte `offset` in `text`.
+fn column_of(text: &str, offset: usize) -> usize {
+    text.get(..offset)
+        .map_or(offset, |before| before.chars().count())
+        + 1
+}
+
+/// A field of a [`Line`] with its position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field<'a> {
+    pub text: &'a str,
+    pub line: usize,
+    /// 1-based column of the first character, in characters.
+    pub column: usize,
+}
+
+impl Field<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Parses the field as a number after [`normalize_number`]; `what` names it in the error.
+    pub fn parse<T: FromStr>(&self, what: &str) -> Result<T, FieldError> {
+        normalize_number(self.text)
+            .parse()
+            .map_err(|_| self.error(format!("invalid {what}")))
+    }
+
+    pub fn error(&self, details: impl Into<String>) -> FieldError {
+        FieldError::new(self.line, self.column, details)
+    }
+}
+
+/// Iterator over the fields of a line; see [`Line::fields`].
+#[derive(Debug, Clone)]
+pub struct Fields<'a> {
+    line: Line<'a>,
+    offset: usize,
+    separator: fn(char) -> bool,
+}
+
+impl<'a> Fields<'a> {
+    /// The next field, or an error naming `what` at the end of the line.
+    pub fn expect(&mut self, what: &str) -> Result<Field<'a>, FieldError> {
+        self.next().ok_or_else(|| {
+            let end = self.line.text.trim_end().len();
+            FieldError::new(
+                self.line.number,
+                column_of(self.line.text, end),
+                format!("missing {what}"),
+            )
+        })
+    }
+
+    /// The unsplit text after the fields read so far, without leading separators.
+    pub fn rest(&self) -> &'a str {
+        self.line.text[self.offset..].trim_start_matches(self.separator)
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Field<'a>;
+
+    fn next(&mut self) -> Option<Field<'a>> {
+        let text = self.line.text;
+        let start = self.offset + text[self.offset..].find(|c| !(self.separator)(c))?;
+        let end = text[start..]
+            .find(self.separator)
+            .map_or(text.len(), |length| start + length);
+        self.offset = end;
+        Some(self.line.field_at(start, &text[start..end]))
+    }
+}
+
+/// Something a lenient reader skipped or worked around.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// 1-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Whether a reader stops at the first bad line or records it and carries on, for files that
+/// are mostly intact (a truncated last frame, one mangled record).
+#[derive(Debug, Default)]
+pub struct Recovery {
+    lenient: bool,
+    problems: Vec<Problem>,
+}
+
+impl Recovery {
+    pub fn strict() -> Self {
+        Self::default()
+    }
+
+    pub fn lenient() -> Self {
+        Self {
+            lenient: true,
+            problems: Vec::new(),
+        }
+    }
+
+    /// Returns `err` when strict; when lenient, records it against `line` instead.
+    pub fn tolerate<E: fmt::Display>(&mut self, line: usize, err: E) -> Result<(), E> {
+        if !self.lenient {
+            return Err(err);
+        }
+        self.problems.push(Problem {
+            line,
+            message: err.to_string(),
+        });
+        Ok(())
+    }
+
+    /// `Some` value on success; on failure the error when strict, `None` when lenient.
+    pub fn skip_bad<T, E: fmt::Display>(
+        &mut self,
+        line: usize,
+        result: Result<T, E>,
+    ) -> Result<Option<T>, E> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(err) => self.tolerate(line, err).map(|()| None),
+        }
+    }
+
+    pub fn into_problems(self) -> Vec<Problem> {
+        self.problems
+    }
+}
+
+/// `text` with full-width characters and the minus sign turned into ASCII and a Fortran `D`
+/// exponent into `e`, ready for [`str::parse`]. Borrows when nothing changes.
+pub fn normalize_number(text: &str) -> Cow<'_, str> {
+    if text
+        .bytes()
+        .all(|byte| byte.is_ascii() && !matches!(byte, b'd' | b'D'))
+    {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(
+        text.chars()
+            .map(|c| match c {
+                // The full-width forms block mirrors printable ASCII.
+                '\u{ff01}'..='\u{ff5e}' => char::from_u32(u32::from(c) - 0xfee0).unwrap_or(c),
+                '\u{2212}' => '-',
+                _ => c,
+            })
+            .map(|c| if c.eq_ignore_ascii_case(&'d') { 'e' } else { c })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_lines_and_fields_from_foreign_files() {
+        let contents = "\u{feff}2\r\nwater\rO\t0.0\u{3000}０．５ 1.0D-3\n";
+        let lines: Vec<Line> = lines(contents).collect();
+        let texts: Vec<&str> = lines.iter().map(|line| line.text).collect();
+        assert_eq!(texts, ["2", "water", "O\t0.0\u{3000}０．５ 1.0D-3"]);
+        assert_eq!(lines[2].number, 3);
+
+        let mut fields = lines[2].fields();
+        assert_eq!(fields.expect("element").unwrap().text, "O");
+        let values: Vec<f32> = fields
+            .by_ref()
+            .map(|field| field.parse("coordinate").unwrap())
+            .collect();
+        assert_eq!(values, [0.0, 0.5, 1.0e-3]);
+        assert_eq!(
+            fields.expect("charge").unwrap_err().to_string(),
+            "line 3, column 17: missing charge"
+        );
+
+        let line = Line {
+            number: 7,
+            text: "C  １.2x  3",
+        };
+        let bad = line.fields().nth(1).unwrap();
+        assert_eq!(
+            bad.parse::<f32>("x").unwrap_err().to_string(),
+            "line 7, column 4: invalid x"
+        );
+        assert_eq!(line.column(2, 6).column, 4);
+        assert_eq!(line.fields().rest(), "C  １.2x  3");
+    }
+}
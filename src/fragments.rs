@@ -0,0 +1,180 @@
+//! A built-in library of common building blocks: substituent groups, the twenty amino acids
+//! and common solvents.
+//!
+//! Templates are stored as SMILES and built on demand with [`parse_smiles`], so they come with
+//! hydrogens and rough 3D coordinates. The first atom is the one bonded when a template is
+//! attached to an atom.
+
+use glam::Vec3;
+
+use crate::formats::smiles::{parse_smiles, SmilesError};
+use crate::geometry::centroid;
+use crate::Molecule;
+use FragmentCategory::{AminoAcid, Group, Solvent};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FragmentCategory {
+    /// Small groups meant to be attached to an atom.
+    Group,
+    AminoAcid,
+    Solvent,
+}
+
+impl FragmentCategory {
+    pub const ALL: [FragmentCategory; 3] = [
+        FragmentCategory::Group,
+        FragmentCategory::AminoAcid,
+        FragmentCategory::Solvent,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FragmentCategory::Group => "Groups",
+            FragmentCategory::AminoAcid => "Amino acids",
+            FragmentCategory::Solvent => "Solvents",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fragment {
+    pub name: &'static str,
+    pub category: FragmentCategory,
+    pub smiles: &'static str,
+}
+
+const fn fragment(
+    name: &'static str,
+    category: FragmentCategory,
+    smiles: &'static str,
+) -> Fragment {
+    Fragment {
+        name,
+        category,
+        smiles,
+    }
+}
+
+const LIBRARY: [Fragment; 50] = [
+    fragment("Methyl", Group, "C"),
+    fragment("Ethyl", Group, "CC"),
+    fragment("Isopropyl", Group, "C(C)C"),
+    fragment("tert-Butyl", Group, "C(C)(C)C"),
+    fragment("Phenyl", Group, "c1ccccc1"),
+    fragment("Benzyl", Group, "Cc1ccccc1"),
+    fragment("Hydroxyl", Group, "O"),
+    fragment("Methoxy", Group, "OC"),
+    fragment("Amino", Group, "N"),
+    fragment("Carboxyl", Group, "C(=O)O"),
+    fragment("Formyl", Group, "C=O"),
+    fragment("Acetyl", Group, "C(C)=O"),
+    fragment("Cyano", Group, "C#N"),
+    fragment("Nitro", Group, "[N+](=O)[O-]"),
+    fragment("Trifluoromethyl", Group, "C(F)(F)F"),
+    fragment("Thiol", Group, "S"),
+    fragment("Glycine", AminoAcid, "NCC(=O)O"),
+    fragment("Alanine", AminoAcid, "NC(C)C(=O)O"),
+    fragment("Valine", AminoAcid, "NC(C(C)C)C(=O)O"),
+    fragment("Leucine", AminoAcid, "NC(CC(C)C)C(=O)O"),
+    fragment("Isoleucine", AminoAcid, "NC(C(C)CC)C(=O)O"),
+    fragment("Proline", AminoAcid, "N1CCCC1C(=O)O"),
+    fragment("Phenylalanine", AminoAcid, "NC(Cc1ccccc1)C(=O)O"),
+    fragment("Tyrosine", AminoAcid, "NC(Cc1ccc(O)cc1)C(=O)O"),
+    fragment("Tryptophan", AminoAcid, "NC(Cc1c[nH]c2ccccc12)C(=O)O"),
+    fragment("Serine", AminoAcid, "NC(CO)C(=O)O"),
+    fragment("Threonine", AminoAcid, "NC(C(C)O)C(=O)O"),
+    fragment("Cysteine", AminoAcid, "NC(CS)C(=O)O"),
+    fragment("Methionine", AminoAcid, "NC(CCSC)C(=O)O"),
+    fragment("Asparagine", AminoAcid, "NC(CC(N)=O)C(=O)O"),
+    fragment("Glutamine", AminoAcid, "NC(CCC(N)=O)C(=O)O"),
+    fragment("Aspartic acid", AminoAcid, "NC(CC(=O)O)C(=O)O"),
+    fragment("Glutamic acid", AminoAcid, "NC(CCC(=O)O)C(=O)O"),
+    fragment("Lysine", AminoAcid, "NC(CCCCN)C(=O)O"),
+    fragment("Arginine", AminoAcid, "NC(CCCNC(N)=N)C(=O)O"),
+    fragment("Histidine", AminoAcid, "NC(Cc1c[nH]cn1)C(=O)O"),
+    fragment("Water", Solvent, "O"),
+    fragment("Methanol", Solvent, "CO"),
+    fragment("Ethanol", Solvent, "CCO"),
+    fragment("Acetone", Solvent, "CC(C)=O"),
+    fragment("Acetonitrile", Solvent, "CC#N"),
+    fragment("DMSO", Solvent, "CS(C)=O"),
+    fragment("DMF", Solvent, "CN(C)C=O"),
+    fragment("THF", Solvent, "C1CCOC1"),
+    fragment("Dichloromethane", Solvent, "ClCCl"),
+    fragment("Chloroform", Solvent, "ClC(Cl)Cl"),
+    fragment("Benzene", Solvent, "c1ccccc1"),
+    fragment("Toluene", Solvent, "Cc1ccccc1"),
+    fragment("Hexane", Solvent, "CCCCCC"),
+    fragment("Ethyl acetate", Solvent, "CCOC(C)=O"),
+];
+
+impl Fragment {
+    /// Every built-in template, grouped by category.
+    pub fn library() -> &'static [Fragment] {
+        &LIBRARY
+    }
+
+    /// The template called `name`, ignoring case.
+    pub fn find(name: &str) -> Option<&'static Fragment> {
+        LIBRARY
+            .iter()
+            .find(|fragment| fragment.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The template as a structure with hydrogens, named after it.
+    pub fn build(&self) -> Result<Molecule, SmilesError> {
+        let mut molecule = parse_smiles(self.smiles)?;
+        molecule.name = self.name.to_string();
+        Ok(molecule)
+    }
+
+    /// The template built with its centroid at `center`, ready to merge as a separate piece.
+    pub fn build_at(&self, center: Vec3) -> Result<Molecule, SmilesError> {
+        let mut molecule = self.build()?;
+        let shift = center - centroid(&molecule).map_or(Vec3::ZERO, Vec3::from_array);
+        for id in molecule.atom_ids() {
+            if let Some(atom) = molecule.get_atom(id) {
+                let moved = Vec3::from_array(atom.position) + shift;
+                molecule.set_atom_position(id, moved.to_array());
+            }
+        }
+        Ok(molecule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_template_builds() {
+        for fragment in Fragment::library() {
+            let molecule = fragment
+                .build()
+                .unwrap_or_else(|err| panic!("{}: {err}", fragment.name));
+            assert_eq!(molecule.name, fragment.name);
+            assert!(molecule.atom_count() > 0, "{}", fragment.name);
+        }
+        for category in FragmentCategory::ALL {
+            assert!(LIBRARY.iter().any(|fragment| fragment.category == category));
+        }
+        assert_eq!(
+            Fragment::library()
+                .iter()
+                .filter(|fragment| fragment.category == FragmentCategory::AminoAcid)
+                .count(),
+            20
+        );
+    }
+
+    #[test]
+    fn templates_are_found_and_placed() {
+        let benzene = Fragment::find("benzene").unwrap();
+        assert_eq!(benzene.build().unwrap().formula(), "C6H6");
+        assert!(Fragment::find("unobtainium").is_none());
+        let center = Vec3::new(5.0, -2.0, 1.0);
+        let water = Fragment::find("Water").unwrap().build_at(center).unwrap();
+        let placed = Vec3::from_array(centroid(&water).unwrap());
+        assert!(placed.distance(center) < 1e-4, "{placed}");
+    }
+}
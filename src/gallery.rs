@@ -0,0 +1,133 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use glam::{Mat3, Vec3};
+
+use crate::elements::vdw_radius;
+use crate::formats;
+use crate::palette::Palette;
+use crate::Molecule;
+
+/// File extensions picked up when scanning a watched directory.
+pub const STRUCTURE_EXTENSIONS: &[&str] = &[
+    "xyz", "zmat", "mol", "sdf", "sd", "pdb", "ent", "cml", "mol2", "cif",
+];
+
+const THUMBNAIL_RADIUS_SCALE: f32 = 0.5;
+const THUMBNAIL_MARGIN: f32 = 0.05;
+
+/// One atom of a thumbnail, in unit-square coordinates with y pointing down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThumbnailDisc {
+    pub center: [f32; 2],
+    pub radius: f32,
+    pub color: [f32; 3],
+}
+
+/// Orthographic projection of the atoms, fitted to the unit square and sorted back to front
+/// so the discs can be painted in order.
+pub fn thumbnail(
+    molecule: &Molecule,
+    yaw: f32,
+    pitch: f32,
+    palette: Palette,
+) -> Vec<ThumbnailDisc> {
+    let rotation = Mat3::from_rotation_x(pitch) * Mat3::from_rotation_y(yaw);
+    let mut projected: Vec<(Vec3, f32, [f32; 3])> = molecule
+        .atoms_in_order()
+        .map(|atom| {
+            (
+                rotation * Vec3::from_array(atom.position),
+                vdw_radius(&atom.element) * THUMBNAIL_RADIUS_SCALE,
+                palette.element_color(&atom.element),
+            )
+        })
+        .collect();
+    if projected.is_empty() {
+        return Vec::new();
+    }
+    let mut min = Vec3::splat(f32::MAX);
+    let mut max = Vec3::splat(f32::MIN);
+    for (position, radius, _) in &projected {
+        min = min.min(*position - Vec3::splat(*radius));
+        max = max.max(*position + Vec3::splat(*radius));
+    }
+    let extent = (max.x - min.x).max(max.y - min.y).max(f32::EPSILON);
+    let scale = (1.0 - 2.0 * THUMBNAIL_MARGIN) / extent;
+    let center = (min + max) * 0.5;
+    projected.sort_by(|a, b| a.0.z.total_cmp(&b.0.z));
+    projected
+        .into_iter()
+        .map(|(position, radius, color)| ThumbnailDisc {
+            center: [
+                0.5 + (position.x - center.x) * scale,
+                0.5 - (position.y - center.y) * scale,
+            ],
+            radius: radius * scale,
+            color,
+        })
+        .collect()
+}
+
+/// Structure files, plain or gzipped, directly inside `dir` with their modification times, sorted by path.
+pub fn scan_directory(dir: &Path) -> io::Result<Vec<(PathBuf, SystemTime)>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let supported = Path::new(formats::gzip::strip_suffix(&path.to_string_lossy()))
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                STRUCTURE_EXTENSIONS
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(ext))
+            });
+        let metadata = entry.metadata()?;
+        if supported && metadata.is_file() {
+            files.push((path, metadata.modified()?));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thumbnail_fits_unit_square_back_to_front() {
+        let mut molecule = Molecule::new("co");
+        molecule.insert_atom("O".into(), [0.0, 0.0, 1.0]);
+        molecule.insert_atom("C".into(), [4.0, 2.0, -1.0]);
+        let discs = thumbnail(&molecule, 0.0, 0.0, Palette::Standard);
+        assert_eq!(discs.len(), 2);
+        assert_eq!(discs[0].color, Palette::Standard.element_color("C"));
+        for disc in &discs {
+            for coordinate in disc.center {
+                assert!(coordinate - disc.radius >= -1e-4);
+                assert!(coordinate + disc.radius <= 1.0 + 1e-4);
+            }
+        }
+        assert!(discs[0].center[1] < discs[1].center[1]);
+        assert!(thumbnail(&Molecule::new("empty"), 0.0, 0.0, Palette::Standard).is_empty());
+    }
+
+    #[test]
+    fn scan_filters_by_extension() {
+        let dir = std::env::temp_dir().join(format!("molweaver-gallery-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("b.xyz"), "").unwrap();
+        std::fs::write(dir.join("a.XYZ"), "").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        let files = scan_directory(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let names: Vec<_> = files
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["a.XYZ", "b.xyz"]);
+    }
+}
@@ -0,0 +1,276 @@
+use glam::{Mat3, Vec3};
+
+use crate::elements::atomic_mass;
+use crate::{AtomId, Molecule};
+
+const JACOBI_SWEEPS: usize = 32;
+
+/// Axis-aligned bounding box of atom centers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl BoundingBox {
+    pub fn center(&self) -> [f32; 3] {
+        ((Vec3::from_array(self.min) + Vec3::from_array(self.max)) * 0.5).to_array()
+    }
+
+    pub fn size(&self) -> [f32; 3] {
+        (Vec3::from_array(self.max) - Vec3::from_array(self.min)).to_array()
+    }
+
+    /// Radius of the sphere through the box corners, centered on [`BoundingBox::center`].
+    pub fn radius(&self) -> f32 {
+        Vec3::from_array(self.size()).length() * 0.5
+    }
+}
+
+/// Principal moments of inertia in amu·Å², sorted ascending, with matching unit axes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrincipalMoments {
+    pub moments: [f32; 3],
+    pub axes: [[f32; 3]; 3],
+}
+
+pub fn centroid(molecule: &Molecule) -> Option<[f32; 3]> {
+    let count = molecule.atom_count();
+    if count == 0 {
+        return None;
+    }
+    let sum: Vec3 = molecule
+        .atoms_in_order()
+        .map(|atom| Vec3::from_array(atom.position))
+        .sum();
+    Some((sum / count as f32).to_array())
+}
+
+/// Mass-weighted center; atoms with unknown elements carry no mass.
+pub fn center_of_mass(molecule: &Molecule) -> Option<[f32; 3]> {
+    let mut total = 0.0;
+    let mut sum = Vec3::ZERO;
+    for atom in molecule.atoms_in_order() {
+        let mass = atomic_mass(&atom.element).unwrap_or(0.0);
+        total += mass;
+        sum += Vec3::from_array(atom.position) * mass;
+    }
+    (total > 0.0).then(|| (sum / total).to_array())
+}
+
+pub fn bounding_box(molecule: &Molecule) -> Option<BoundingBox> {
+    let mut atoms = molecule.atoms_in_order();
+    let first = Vec3::from_array(atoms.next()?.position);
+    let (min, max) = atoms.fold((first, first), |(min, max), atom| {
+        let position = Vec3::from_array(atom.position);
+        (min.min(position), max.max(position))
+    });
+    Some(BoundingBox {
+        min: min.to_array(),
+        max: max.to_array(),
+    })
+}
+
+/// Angle `a`–`vertex`–`c` in degrees; `None` if an atom is missing or coincides with the vertex.
+pub fn angle(molecule: &Molecule, a: AtomId, vertex: AtomId, c: AtomId) -> Option<f32> {
+    let position = |id| {
+        molecule
+            .get_atom(id)
+            .map(|atom| Vec3::from_array(atom.position))
+    };
+    let center = position(vertex)?;
+    let (u, v) = (position(a)? - center, position(c)? - center);
+    if u.length_squared() == 0.0 || v.length_squared() == 0.0 {
+        return None;
+    }
+    Some(u.angle_between(v).to_degrees())
+}
+
+/// Dihedral `a`–`b`–`c`–`d` in degrees within (-180, 180], positive for clockwise rotation
+/// of `a` onto `d` looking from `b` to `c`; `None` if an atom is missing or either plane is
+/// undefined.
+pub fn dihedral(molecule: &Molecule, a: AtomId, b: AtomId, c: AtomId, d: AtomId) -> Option<f32> {
+    let position = |id| {
+        molecule
+            .get_atom(id)
+            .map(|atom| Vec3::from_array(atom.position))
+    };
+    let (pa, pb, pc, pd) = (position(a)?, position(b)?, position(c)?, position(d)?);
+    let (b1, b2, b3) = (pb - pa, pc - pb, pd - pc);
+    let (n1, n2) = (b1.cross(b2), b2.cross(b3));
+    if n1.length_squared() == 0.0 || n2.length_squared() == 0.0 {
+        return None;
+    }
+    let y = n1.cross(n2).dot(b2.normalize());
+    Some(y.atan2(n1.dot(n2)).to_degrees())
+}
+
+/// Inertia tensor about the center of mass.
+pub fn inertia_tensor(molecule: &Molecule) -> Option<Mat3> {
+    let center = Vec3::from_array(center_of_mass(molecule)?);
+    let mut tensor = Mat3::ZERO;
+    for atom in molecule.atoms_in_order() {
+        let mass = atomic_mass(&atom.element).unwrap_or(0.0);
+        let r = Vec3::from_array(atom.position) - center;
+        let outer = Mat3::from_cols(r * r.x, r * r.y, r * r.z);
+        tensor += (Mat3::IDENTITY * r.length_squared() - outer) * mass;
+    }
+    Some(tensor)
+}
+
+pub fn principal_moments(molecule: &Molecule) -> Option<PrincipalMoments> {
+    let (values, vectors) = symmetric_eigen(inertia_tensor(molecule)?);
+    let mut order = [0, 1, 2];
+    order.sort_by(|&i, &j| values[i].total_cmp(&values[j]));
+    let mut axes = order.map(|i| vectors.col(i));
+    // Keep the frame right-handed so it can be used directly as a rotation.
+    if axes[0].cross(axes[1]).dot(axes[2]) < 0.0 {
+        axes[2] = -axes[2];
+    }
+    Some(PrincipalMoments {
+        moments: order.map(|i| values[i]),
+        axes: axes.map(|axis| axis.to_array()),
+    })
+}
+
+/// Cyclic Jacobi rotations; returns eigenvalues and eigenvectors as matrix columns.
+fn symmetric_eigen(matrix: Mat3) -> ([f32; 3], Mat3) {
+    let mut a = matrix.to_cols_array_2d();
+    let mut v = Mat3::IDENTITY.to_cols_array_2d();
+    for _ in 0..JACOBI_SWEEPS {
+        let off = a[0][1].abs() + a[0][2].abs() + a[1][2].abs();
+        if off <= f32::EPSILON * (a[0][0].abs() + a[1][1].abs() + a[2][2].abs()) {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0.0 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            for column in a.iter_mut() {
+                let (ap, aq) = (column[p], column[q]);
+                column[p] = c * ap - s * aq;
+                column[q] = s * ap + c * aq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            a[p] = std::array::from_fn(|k| c * row_p[k] - s * row_q[k]);
+            a[q] = std::array::from_fn(|k| s * row_p[k] + c * row_q[k]);
+            for column in v.iter_mut() {
+                let (vp, vq) = (column[p], column[q]);
+                column[p] = c * vp - s * vq;
+                column[q] = s * vp + c * vq;
+            }
+        }
+    }
+    // `v` was accumulated row-wise, so its transpose holds the eigenvectors as columns.
+    let vectors = Mat3::from_cols_array_2d(&v).transpose();
+    ([a[0][0], a[1][1], a[2][2]], vectors)
+}
+
+/// Sideways offsets of the extra lines drawn for double and triple bonds, in line spacings.
+const DOUBLE_BOND_STRANDS: [f32; 1] = [1.0];
+const TRIPLE_BOND_STRANDS: [f32; 2] = [-1.0, 1.0];
+
+/// Offsets across the bond, in line spacings, of the thinner lines drawn beside a bond of
+/// `order`: one for a double bond, one on each side for a triple bond.
+pub fn order_strands(order: u8) -> &'static [f32] {
+    match order {
+        0 | 1 => &[],
+        2 => &DOUBLE_BOND_STRANDS,
+        _ => &TRIPLE_BOND_STRANDS,
+    }
+}
+
+/// Unit vector across the bond from `a` to `b`, towards the first of `substituents` (atoms
+/// bonded to either end) that is off the bond axis, so a double bond's second line sits on
+/// the side of its substituents, inside a ring. Without one, any perpendicular.
+pub fn bond_side(a: Vec3, b: Vec3, substituents: impl IntoIterator<Item = Vec3>) -> Vec3 {
+    let axis = (b - a).normalize_or_zero();
+    substituents
+        .into_iter()
+        .find_map(|position| {
+            let away = position - a;
+            (away - axis * away.dot(axis)).try_normalize()
+        })
+        .unwrap_or_else(|| axis.any_orthonormal_vector())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn water() -> Molecule {
+        let mut molecule = Molecule::new("water");
+        molecule.insert_atom("O".into(), [0.0, 0.0, 0.0]);
+        molecule.insert_atom("H".into(), [0.757, 0.586, 0.0]);
+        molecule.insert_atom("H".into(), [-0.757, 0.586, 0.0]);
+        molecule
+    }
+
+    #[test]
+    fn centers_and_bounds() {
+        let molecule = water();
+        let center = centroid(&molecule).unwrap();
+        assert!((center[1] - 0.586 * 2.0 / 3.0).abs() < 1e-5);
+        let com = center_of_mass(&molecule).unwrap();
+        assert!(com[0].abs() < 1e-6);
+        assert!(com[1] > 0.0 && com[1] < center[1]);
+        let bounds = bounding_box(&molecule).unwrap();
+        assert_eq!(bounds.min, [-0.757, 0.0, 0.0]);
+        assert_eq!(bounds.max, [0.757, 0.586, 0.0]);
+        assert!(bounding_box(&Molecule::new("empty")).is_none());
+        assert!(centroid(&Molecule::new("empty")).is_none());
+    }
+
+    #[test]
+    fn double_bond_lines_lean_towards_substituents() {
+        let (a, b) = (Vec3::ZERO, Vec3::X * 1.34);
+        let side = bond_side(a, b, [Vec3::new(2.0, 0.0, 0.0), Vec3::new(-0.6, -0.9, 0.0)]);
+        assert!((side - Vec3::NEG_Y).length() < 1e-5, "{side}");
+        let lone = bond_side(a, b, []);
+        assert!(lone.dot(Vec3::X).abs() < 1e-5 && (lone.length() - 1.0).abs() < 1e-5);
+        assert!(order_strands(1).is_empty());
+        assert_eq!(order_strands(2).len(), 1);
+        assert_eq!(order_strands(3).len(), 2);
+    }
+
+    #[test]
+    fn dihedral_sign_and_degenerate_cases() {
+        let mut molecule = Molecule::new("dihedral");
+        let a = molecule.insert_atom("C".into(), [1.0, 0.0, 0.0]);
+        let b = molecule.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let c = molecule.insert_atom("C".into(), [0.0, 0.0, 1.0]);
+        let d = molecule.insert_atom("C".into(), [0.0, 1.0, 1.0]);
+        assert!((dihedral(&molecule, a, b, c, d).unwrap() - 90.0).abs() < 1e-4);
+        assert!((dihedral(&molecule, d, c, b, a).unwrap() - 90.0).abs() < 1e-4);
+        molecule.set_atom_position(d, [0.0, 0.0, 2.0]);
+        assert!(dihedral(&molecule, a, b, c, d).is_none());
+    }
+
+    #[test]
+    fn principal_moments_of_planar_molecule() {
+        let principal = principal_moments(&water()).unwrap();
+        let [ia, ib, ic] = principal.moments;
+        assert!(ia <= ib && ib <= ic);
+        // Planar rigid rotor: the out-of-plane moment is the sum of the other two.
+        assert!((ia + ib - ic).abs() < 1e-3);
+        assert!((Vec3::from_array(principal.axes[2]).z.abs() - 1.0).abs() < 1e-4);
+        let [a, b, c] = principal.axes.map(Vec3::from_array);
+        assert!((a.cross(b).dot(c) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn eigen_decomposition_of_rotated_diagonal() {
+        let rotation = Mat3::from_euler(glam::EulerRot::XYZ, 0.3, -0.7, 1.1);
+        let matrix =
+            rotation * Mat3::from_diagonal(Vec3::new(1.0, 4.0, 9.0)) * rotation.transpose();
+        let (values, vectors) = symmetric_eigen(matrix);
+        for (i, value) in values.iter().enumerate() {
+            let column = vectors.col(i);
+            assert!((matrix * column - column * *value).length() < 1e-4);
+        }
+    }
+}
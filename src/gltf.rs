@@ -0,0 +1,269 @@
+//! Binary glTF 2.0 (`.glb`) export of the rendered geometry.
+//!
+//! The file holds one unit sphere and one unit cylinder; every atom and bond is a node that
+//! reuses them with its own translation, rotation and scale, so the instancing survives
+//! import into Blender, PowerPoint or AR viewers. The view camera is included as well.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use glam::{Mat3, Quat, Vec3};
+
+use crate::mesh::Mesh;
+use crate::scene::Scene;
+
+const SPHERE_SEGMENTS: u32 = 32;
+const SPHERE_RINGS: u32 = 16;
+const CYLINDER_SEGMENTS: u32 = 24;
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const JSON_CHUNK: &[u8; 4] = b"JSON";
+const BIN_CHUNK: &[u8; 4] = b"BIN\0";
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const NEAR_PLANE: f32 = 0.1;
+
+pub fn write_glb(scene: &Scene) -> Vec<u8> {
+    let mut binary = Vec::new();
+    let mut buffer_views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut primitives = Vec::new();
+    for mesh in [
+        Mesh::sphere(SPHERE_SEGMENTS, SPHERE_RINGS),
+        Mesh::cylinder(CYLINDER_SEGMENTS, false),
+    ] {
+        let first = accessors.len();
+        let (min, max) = mesh.positions.iter().fold(
+            (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+            |(min, max), &point| (min.min(point.into()), max.max(point.into())),
+        );
+        let bounds = format!(
+            r#","min":{},"max":{}"#,
+            array(&min.to_array()),
+            array(&max.to_array())
+        );
+        for (data, target, kind, extra) in [
+            (
+                floats(&mesh.positions),
+                ARRAY_BUFFER,
+                "VEC3",
+                bounds.as_str(),
+            ),
+            (floats(&mesh.normals), ARRAY_BUFFER, "VEC3", ""),
+        ] {
+            accessors.push(format!(
+                r#"{{"bufferView":{},"componentType":{FLOAT},"count":{},"type":"{kind}"{extra}}}"#,
+                buffer_views.len(),
+                mesh.positions.len(),
+            ));
+            buffer_views.push(view(&mut binary, &data, target));
+        }
+        let indices: Vec<u8> = mesh.indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        accessors.push(format!(
+            r#"{{"bufferView":{},"componentType":{UNSIGNED_INT},"count":{},"type":"SCALAR"}}"#,
+            buffer_views.len(),
+            mesh.indices.len(),
+        ));
+        buffer_views.push(view(&mut binary, &indices, ELEMENT_ARRAY_BUFFER));
+        primitives.push(first);
+    }
+
+    let mut materials: Vec<[f32; 3]> = Vec::new();
+    let mut meshes: Vec<(usize, usize)> = Vec::new();
+    let mut mesh_index: HashMap<(usize, [u32; 3]), usize> = HashMap::new();
+    let mut mesh_for = |shape: usize, color: [f32; 3]| {
+        *mesh_index
+            .entry((shape, color.map(f32::to_bits)))
+            .or_insert_with(|| {
+                let material = materials
+                    .iter()
+                    .position(|known| *known == color)
+                    .unwrap_or_else(|| {
+                        materials.push(color);
+                        materials.len() - 1
+                    });
+                meshes.push((shape, material));
+                meshes.len() - 1
+            })
+    };
+    let mut nodes = Vec::new();
+    for (index, sphere) in scene.spheres.iter().enumerate() {
+        let mesh = mesh_for(0, sphere.color);
+        nodes.push(format!(
+            r#"{{"name":"atom {}","mesh":{mesh},"translation":{},"scale":{}}}"#,
+            index + 1,
+            array(&sphere.center),
+            array(&[sphere.radius; 3]),
+        ));
+    }
+    for (index, cylinder) in scene.cylinders.iter().enumerate() {
+        let (start, end) = (Vec3::from(cylinder.start), Vec3::from(cylinder.end));
+        let length = start.distance(end);
+        if length <= f32::EPSILON {
+            continue;
+        }
+        let mesh = mesh_for(1, cylinder.color);
+        let rotation = Quat::from_rotation_arc(Vec3::Y, (end - start) / length);
+        nodes.push(format!(
+            r#"{{"name":"bond {}","mesh":{mesh},"translation":{},"rotation":{},"scale":{}}}"#,
+            index + 1,
+            array(&((start + end) * 0.5).to_array()),
+            array(&rotation.to_array()),
+            array(&[cylinder.radius, length, cylinder.radius]),
+        ));
+    }
+
+    let camera = &scene.camera;
+    let position = Vec3::from(camera.position);
+    let backward = (position - Vec3::from(camera.target)).normalize_or_zero();
+    let right = Vec3::from(camera.up).cross(backward).normalize_or_zero();
+    let orientation = Quat::from_mat3(&Mat3::from_cols(right, backward.cross(right), backward));
+    nodes.push(format!(
+        r#"{{"name":"camera","camera":0,"translation":{},"rotation":{}}}"#,
+        array(&camera.position),
+        array(&orientation.normalize().to_array()),
+    ));
+
+    let mut json = String::new();
+    let _ = write!(
+        json,
+        r#"{{"asset":{{"version":"2.0","generator":"MolWeaver"}},"scene":0,"scenes":[{{"nodes":[{}]}}],"#,
+        (0..nodes.len())
+            .map(|index| index.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    let _ = write!(json, r#""nodes":[{}],"#, nodes.join(","));
+    let _ = write!(
+        json,
+        r#""cameras":[{{"type":"perspective","perspective":{{"yfov":{},"aspectRatio":{},"znear":{NEAR_PLANE}}}}}],"#,
+        camera.fov_degrees.to_radians(),
+        camera.aspect,
+    );
+    let meshes: Vec<String> = meshes
+        .iter()
+        .map(|&(shape, material)| {
+            let first = primitives[shape];
+            format!(
+                r#"{{"primitives":[{{"attributes":{{"POSITION":{},"NORMAL":{}}},"indices":{},"material":{material}}}]}}"#,
+                first,
+                first + 1,
+                first + 2,
+            )
+        })
+        .collect();
+    let _ = write!(json, r#""meshes":[{}],"#, meshes.join(","));
+    let materials: Vec<String> = materials
+        .iter()
+        .map(|color| {
+            let linear = color.map(srgb_to_linear);
+            format!(
+                r#"{{"pbrMetallicRoughness":{{"baseColorFactor":{},"metallicFactor":0,"roughnessFactor":0.5}}}}"#,
+                array(&[linear[0], linear[1], linear[2], 1.0]),
+            )
+        })
+        .collect();
+    let _ = write!(json, r#""materials":[{}],"#, materials.join(","));
+    let _ = write!(json, r#""accessors":[{}],"#, accessors.join(","));
+    let _ = write!(json, r#""bufferViews":[{}],"#, buffer_views.join(","));
+    let _ = write!(json, r#""buffers":[{{"byteLength":{}}}]}}"#, binary.len());
+
+    let mut json = json.into_bytes();
+    pad(&mut json, b' ');
+    pad(&mut binary, 0);
+    let total = 12 + 8 + json.len() + 8 + binary.len();
+    let mut out = Vec::with_capacity(total);
+    out.extend_from_slice(GLB_MAGIC);
+    out.extend_from_slice(&2u32.to_le_bytes());
+    out.extend_from_slice(&(total as u32).to_le_bytes());
+    for (kind, data) in [(JSON_CHUNK, &json), (BIN_CHUNK, &binary)] {
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(kind);
+        out.extend_from_slice(data);
+    }
+    out
+}
+
+/// Appends `data` at a 4-byte aligned offset and returns its buffer view.
+fn view(binary: &mut Vec<u8>, data: &[u8], target: u32) -> String {
+    pad(binary, 0);
+    let offset = binary.len();
+    binary.extend_from_slice(data);
+    format!(
+        r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{},"target":{target}}}"#,
+        data.len()
+    )
+}
+
+fn pad(data: &mut Vec<u8>, byte: u8) {
+    while !data.len().is_multiple_of(4) {
+        data.push(byte);
+    }
+}
+
+fn floats(values: &[[f32; 3]]) -> Vec<u8> {
+    values
+        .iter()
+        .flatten()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+fn array(values: &[f32]) -> String {
+    let parts: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+    format!("[{}]", parts.join(","))
+}
+
+/// glTF color factors are linear; the palette is in sRGB.
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{Cylinder, SceneCamera, Sphere};
+
+    #[test]
+    fn writes_an_instanced_glb() {
+        let sphere = |x: f32, color: [f32; 3]| Sphere {
+            center: [x, 0.0, 0.0],
+            radius: 0.5,
+            color,
+        };
+        let scene = Scene {
+            spheres: vec![
+                sphere(0.0, [1.0, 0.0, 0.0]),
+                sphere(1.5, [1.0, 0.0, 0.0]),
+                sphere(3.0, [1.0, 1.0, 1.0]),
+            ],
+            cylinders: vec![Cylinder {
+                start: [0.0; 3],
+                end: [1.5, 0.0, 0.0],
+                radius: 0.15,
+                color: [0.7; 3],
+            }],
+            camera: SceneCamera::framing(&[], 45.0, 1.0),
+        };
+        let glb = write_glb(&scene);
+        assert_eq!(&glb[..4], GLB_MAGIC);
+        let total = u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize;
+        assert_eq!(total, glb.len());
+        let json_length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        assert_eq!(&glb[16..20], JSON_CHUNK);
+        assert_eq!(json_length % 4, 0);
+        let json = std::str::from_utf8(&glb[20..20 + json_length]).unwrap();
+        // Two sphere colors and one bond color share two sets of geometry accessors.
+        assert_eq!(json.matches(r#""primitives""#).count(), 3);
+        assert_eq!(json.matches(r#""baseColorFactor""#).count(), 3);
+        assert_eq!(json.matches(r#""bufferView""#).count(), 6);
+        assert_eq!(json.matches(r#""mesh":0"#).count(), 2);
+        assert!(json.contains(r#""camera":0"#));
+        assert_eq!(&glb[24 + json_length..28 + json_length], BIN_CHUNK);
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{AtomId, BondId, Molecule};
+
+type Adjacency = HashMap<AtomId, Vec<AtomId>>;
+
+/// Breadth-first walk over bonds, yielding each reachable atom once starting with the root.
+#[derive(Debug, Clone)]
+pub struct Bfs {
+    adjacency: Adjacency,
+    queue: VecDeque<AtomId>,
+    visited: HashSet<AtomId>,
+}
+
+impl Iterator for Bfs {
+    type Item = AtomId;
+
+    fn next(&mut self) -> Option<AtomId> {
+        let current = self.queue.pop_front()?;
+        for neighbor in self.adjacency.get(&current).into_iter().flatten() {
+            if self.visited.insert(*neighbor) {
+                self.queue.push_back(*neighbor);
+            }
+        }
+        Some(current)
+    }
+}
+
+/// Depth-first pre-order walk over bonds; neighbors are visited in ascending id order.
+#[derive(Debug, Clone)]
+pub struct Dfs {
+    adjacency: Adjacency,
+    stack: Vec<AtomId>,
+    visited: HashSet<AtomId>,
+}
+
+impl Iterator for Dfs {
+    type Item = AtomId;
+
+    fn next(&mut self) -> Option<AtomId> {
+        loop {
+            let current = self.stack.pop()?;
+            if !self.visited.insert(current) {
+                continue;
+            }
+            if let Some(neighbors) = self.adjacency.get(&current) {
+                self.stack.extend(
+                    neighbors
+                        .iter()
+                        .rev()
+                        .filter(|id| !self.visited.contains(id)),
+                );
+            }
+            return Some(current);
+        }
+    }
+}
+
+impl Molecule {
+    /// Bonded neighbors of `id` in ascending id order.
+    pub fn neighbors(&self, id: AtomId) -> Vec<AtomId> {
+        let mut neighbors: Vec<AtomId> = self
+            .bonds()
+            .filter_map(|bond| match (bond.a == id, bond.b == id) {
+                (true, _) => Some(bond.b),
+                (_, true) => Some(bond.a),
+                _ => None,
+            })
+            .collect();
+        neighbors.sort();
+        neighbors
+    }
+
+    /// Empty if `start` is not in the molecule.
+    pub fn bfs(&self, start: AtomId) -> Bfs {
+        let root = self.get_atom(start).map(|atom| atom.id);
+        Bfs {
+            adjacency: self.adjacency(),
+            queue: root.into_iter().collect(),
+            visited: root.into_iter().collect(),
+        }
+    }
+
+    /// Empty if `start` is not in the molecule.
+    pub fn dfs(&self, start: AtomId) -> Dfs {
+        Dfs {
+            adjacency: self.adjacency(),
+            stack: self
+                .get_atom(start)
+                .map(|atom| atom.id)
+                .into_iter()
+                .collect(),
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Bond-connected fragments, ordered by their first atom in `atoms_in_order`; each
+    /// component lists its atoms in that order as well.
+    pub fn connected_components(&self) -> Vec<Vec<AtomId>> {
+        let adjacency = self.adjacency();
+        let mut component_of: HashMap<AtomId, usize> = HashMap::new();
+        let mut components: Vec<Vec<AtomId>> = Vec::new();
+        for atom in self.atoms_in_order() {
+            let index = match component_of.get(&atom.id) {
+                Some(index) => *index,
+                None => {
+                    let index = components.len();
+                    components.push(Vec::new());
+                    let mut stack = vec![atom.id];
+                    component_of.insert(atom.id, index);
+                    while let Some(current) = stack.pop() {
+                        for neighbor in adjacency.get(&current).into_iter().flatten() {
+                            if component_of.insert(*neighbor, index).is_none() {
+                                stack.push(*neighbor);
+                            }
+                        }
+                    }
+                    index
+                }
+            };
+            components[index].push(atom.id);
+        }
+        components
+    }
+
+    /// Fewest-bonds path from `from` to `to`, inclusive of both ends.
+    pub fn shortest_path(&self, from: AtomId, to: AtomId) -> Option<Vec<AtomId>> {
+        self.get_atom(from)?;
+        self.get_atom(to)?;
+        let adjacency = self.adjacency();
+        let mut previous: HashMap<AtomId, AtomId> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        let mut visited = HashSet::from([from]);
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                let mut path = vec![to];
+                while let Some(step) = previous.get(path.last()?) {
+                    path.push(*step);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for neighbor in adjacency.get(&current).into_iter().flatten() {
+                if visited.insert(*neighbor) {
+                    previous.insert(*neighbor, current);
+                    queue.push_back(*neighbor);
+                }
+            }
+        }
+        None
+    }
+
+    /// Bonds lying on at least one cycle, i.e. every bond that is not a bridge.
+    pub fn ring_bonds(&self) -> HashSet<BondId> {
+        let mut incident: HashMap<AtomId, Vec<(AtomId, BondId)>> = HashMap::new();
+        for bond in self.bonds() {
+            incident.entry(bond.a).or_default().push((bond.b, bond.id));
+            incident.entry(bond.b).or_default().push((bond.a, bond.id));
+        }
+        // Iterative Tarjan bridge search; frames are (atom, bond walked in on, next edge).
+        let mut discovery: HashMap<AtomId, usize> = HashMap::new();
+        let mut low: HashMap<AtomId, usize> = HashMap::new();
+        let mut bridges = HashSet::new();
+        for root in self.atom_ids() {
+            if discovery.contains_key(&root) {
+                continue;
+            }
+            discovery.insert(root, discovery.len());
+            low.insert(root, discovery[&root]);
+            let mut stack: Vec<(AtomId, Option<BondId>, usize)> = vec![(root, None, 0)];
+            while let Some(&(atom, entry, edge)) = stack.last() {
+                let next = incident.get(&atom).and_then(|edges| edges.get(edge));
+                if let Some(&(neighbor, bond)) = next {
+                    if let Some(frame) = stack.last_mut() {
+                        frame.2 += 1;
+                    }
+                    if Some(bond) == entry {
+                        continue;
+                    }
+                    if let Some(&order) = discovery.get(&neighbor) {
+                        let value = low[&atom].min(order);
+                        low.insert(atom, value);
+                    } else {
+                        let order = discovery.len();
+                        discovery.insert(neighbor, order);
+                        low.insert(neighbor, order);
+                        stack.push((neighbor, Some(bond), 0));
+                    }
+                    continue;
+                }
+                stack.pop();
+                if let (Some(bond), Some(&(parent, _, _))) = (entry, stack.last()) {
+                    let child_low = low[&atom];
+                    if child_low > discovery[&parent] {
+                        bridges.insert(bond);
+                    }
+                    let value = low[&parent].min(child_low);
+                    low.insert(parent, value);
+                }
+            }
+        }
+        self.bonds()
+            .map(|bond| bond.id)
+            .filter(|id| !bridges.contains(id))
+            .collect()
+    }
+
+    /// Atoms with at least one ring bond.
+    pub fn ring_atoms(&self) -> HashSet<AtomId> {
+        let ring_bonds = self.ring_bonds();
+        self.bonds()
+            .filter(|bond| ring_bonds.contains(&bond.id))
+            .flat_map(|bond| [bond.a, bond.b])
+            .collect()
+    }
+
+    fn adjacency(&self) -> Adjacency {
+        let mut adjacency: Adjacency = HashMap::new();
+        for bond in self.bonds() {
+            adjacency.entry(bond.a).or_default().push(bond.b);
+            adjacency.entry(bond.b).or_default().push(bond.a);
+        }
+        for neighbors in adjacency.values_mut() {
+            neighbors.sort();
+        }
+        adjacency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Chain C1-C2-C3 plus a detached oxygen.
+    fn fragments() -> (Molecule, [AtomId; 4]) {
+        let mut molecule = Molecule::new("fragments");
+        let c1 = molecule.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let c2 = molecule.insert_atom("C".into(), [1.5, 0.0, 0.0]);
+        let o = molecule.insert_atom("O".into(), [5.0, 0.0, 0.0]);
+        let c3 = molecule.insert_atom("C".into(), [3.0, 0.0, 0.0]);
+        molecule.add_bond(c1, c2).unwrap();
+        molecule.add_bond(c2, c3).unwrap();
+        (molecule, [c1, c2, c3, o])
+    }
+
+    #[test]
+    fn traversal_orders() {
+        let (molecule, [c1, c2, c3, o]) = fragments();
+        assert_eq!(molecule.neighbors(c2), [c1, c3]);
+        assert_eq!(molecule.bfs(c2).collect::<Vec<_>>(), [c2, c1, c3]);
+        assert_eq!(molecule.dfs(c1).collect::<Vec<_>>(), [c1, c2, c3]);
+        assert_eq!(molecule.bfs(o).collect::<Vec<_>>(), [o]);
+        assert_eq!(molecule.dfs(AtomId::new(99)).count(), 0);
+    }
+
+    #[test]
+    fn components_and_paths() {
+        let (mut molecule, [c1, c2, c3, o]) = fragments();
+        assert_eq!(molecule.connected_components(), [vec![c1, c2, c3], vec![o]]);
+        assert_eq!(molecule.shortest_path(c1, c3), Some(vec![c1, c2, c3]));
+        assert_eq!(molecule.shortest_path(c2, c2), Some(vec![c2]));
+        assert_eq!(molecule.shortest_path(c1, o), None);
+        assert!(molecule.ring_atoms().is_empty());
+        molecule.add_bond(c1, c3).unwrap();
+        assert_eq!(molecule.shortest_path(c1, c3), Some(vec![c1, c3]));
+        let tail = molecule.add_bond(c3, o).unwrap();
+        assert_eq!(molecule.ring_bonds().len(), 3);
+        assert!(!molecule.ring_bonds().contains(&tail));
+        assert_eq!(molecule.ring_atoms(), HashSet::from([c1, c2, c3]));
+    }
+}
@@ -0,0 +1,78 @@
+//! Layout of the multi-sample comparison grid.
+
+/// One sub-viewport, with the origin at the top-left of the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridCell {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl GridCell {
+    pub fn aspect(&self) -> f32 {
+        self.width / self.height.max(1.0)
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+/// Splits a `width` × `height` area into `count` equal cells, row by row, choosing the
+/// column count whose cells come out closest to square.
+pub fn grid_cells(count: usize, width: f32, height: f32) -> Vec<GridCell> {
+    let count = count.max(1);
+    let shape = |columns: usize| {
+        let rows = count.div_ceil(columns);
+        let aspect = (width / columns as f32) / (height / rows as f32).max(f32::EPSILON);
+        aspect.max(f32::EPSILON).ln().abs()
+    };
+    let columns = (1..=count)
+        .min_by(|&a, &b| shape(a).total_cmp(&shape(b)))
+        .unwrap_or(1);
+    let rows = count.div_ceil(columns);
+    let (cell_width, cell_height) = (width / columns as f32, height / rows as f32);
+    (0..count)
+        .map(|index| GridCell {
+            x: (index % columns) as f32 * cell_width,
+            y: (index / columns) as f32 * cell_height,
+            width: cell_width,
+            height: cell_height,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_square_cells() {
+        assert_eq!(
+            grid_cells(1, 800.0, 600.0),
+            [GridCell {
+                x: 0.0,
+                y: 0.0,
+                width: 800.0,
+                height: 600.0
+            }]
+        );
+        let square = grid_cells(4, 800.0, 800.0);
+        assert!(square
+            .iter()
+            .all(|cell| cell.width == 400.0 && cell.height == 400.0));
+        assert_eq!(square[3].x, 400.0);
+        assert_eq!(square[3].y, 400.0);
+
+        let wide = grid_cells(3, 1500.0, 500.0);
+        assert!(wide.iter().all(|cell| cell.y == 0.0 && cell.width == 500.0));
+        assert!(wide[2].contains(1200.0, 250.0));
+        assert!(!wide[1].contains(1200.0, 250.0));
+
+        // Five cells on a 3 × 2 grid leave the last slot empty.
+        let five = grid_cells(5, 900.0, 600.0);
+        assert_eq!(five[4].x, 300.0);
+        assert_eq!(five[4].y, 300.0);
+    }
+}
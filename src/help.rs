@@ -0,0 +1,488 @@
+use crate::geometry::angle;
+use crate::Molecule;
+
+const WATER_ANGLE: f32 = 104.5;
+const WATER_ANGLE_TOLERANCE: f32 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HelpTopic {
+    pub title: &'static str,
+    /// Shortcut or UI location; empty when the feature has none.
+    pub shortcut: &'static str,
+    pub description: &'static str,
+}
+
+pub const HELP_TOPICS: &[HelpTopic] = &[
+    HelpTopic {
+        title: "Rotate camera",
+        shortcut: "Left drag",
+        description: "Orbit the camera around the view target.",
+    },
+    HelpTopic {
+        title: "Trackball camera",
+        shortcut: "Preferences window > Camera",
+        description: "Rotate freely over the poles and roll; Reset up vector turns world up back to the top of the screen.",
+    },
+    HelpTopic {
+        title: "Fly camera",
+        shortcut: "F, then W/A/S/D and Q/E",
+        description: "Toggle a first-person camera for moving inside large structures; drag to look around and scroll to change speed. Atoms block movement.",
+    },
+    HelpTopic {
+        title: "Idle turntable",
+        shortcut: "Preferences window > Idle turntable",
+        description: "Spin the view slowly for presentations; any mouse or keyboard input pauses it for a few seconds.",
+    },
+    HelpTopic {
+        title: "Zoom",
+        shortcut: "Mouse wheel",
+        description: "Move the camera closer to or further from the target.",
+    },
+    HelpTopic {
+        title: "Fit to view",
+        shortcut: "Edit > Fit to View",
+        description: "Center the camera on the molecule and zoom so every atom is visible.",
+    },
+    HelpTopic {
+        title: "Statistics",
+        shortcut: "MolWeaver Status > Statistics",
+        description: "Fragments, atoms per element, estimated memory and GPU instance counts for the loaded structure.",
+    },
+    HelpTopic {
+        title: "Frame selection",
+        shortcut: "Edit > Frame Selection",
+        description: "Center and zoom on the selected atom, the bond target and the focused substructure match.",
+    },
+    HelpTopic {
+        title: "Saved views",
+        shortcut: "Preferences window > Saved views",
+        description: "Name the current viewpoint with Save View and return to it later with Go; views are saved with the session.",
+    },
+    HelpTopic {
+        title: "Select atom",
+        shortcut: "Click, Shift + Click, Ctrl/Cmd + Click",
+        description: "Click an atom to select it; shift-click adds atoms to the selection and ctrl-click toggles them. Moving, deleting, copying and bonding act on every selected atom.",
+    },
+    HelpTopic {
+        title: "Box and lasso selection",
+        shortcut: "Edit > Box / Lasso, then drag",
+        description: "Drag a rectangle or trace a freehand outline to select the atoms inside it; hold shift to add to the selection or ctrl to toggle.",
+    },
+    HelpTopic {
+        title: "Delete atoms",
+        shortcut: "Delete, Backspace",
+        description: "Delete the selected atoms and their bonds in one undoable step; undo restores them, selected.",
+    },
+    HelpTopic {
+        title: "Select bond",
+        shortcut: "Click a bond (Select tool)",
+        description: "Select the bond's first atom with the other as bond target, ready for Remove Bond or a bond note.",
+    },
+    HelpTopic {
+        title: "Add atom",
+        shortcut: "Edit > Insert Atom",
+        description: "Choose an element and click Insert Atom; the atom is placed next to the selection.",
+    },
+    HelpTopic {
+        title: "Build from SMILES",
+        shortcut: "Edit > Build",
+        description: "Type a SMILES string and press Build to replace the structure with it, hydrogens included.",
+    },
+    HelpTopic {
+        title: "Fragment library",
+        shortcut: "Fragments window",
+        description: "Insert a common group, amino acid or solvent: attached to the selected atom, or at the view center when nothing is selected.",
+    },
+    HelpTopic {
+        title: "Open file",
+        shortcut: "Open window",
+        description: "Open a file by path, choosing XYZ frames, the SD record, PDB models and waters, or CIF symmetry expansion first.",
+    },
+    HelpTopic {
+        title: "Problems",
+        shortcut: "Preferences > Lenient loading",
+        description: "When a file fails to load, the Problems window shows why and can reload it leniently, skipping the bad lines and listing each by line and column.",
+    },
+    HelpTopic {
+        title: "Add bond",
+        shortcut: "Edit > Add Bond tool",
+        description: "With the Add Bond tool, click two atoms in turn, or pick a bond target and press Add Bond.",
+    },
+    HelpTopic {
+        title: "Mutate residue",
+        shortcut: "Edit > Mutate",
+        description: "Select an atom of a PDB residue, pick an amino acid and press Mutate to rebuild its side chain on the fixed backbone as one undo step.",
+    },
+    HelpTopic {
+        title: "Pocket Wizard",
+        shortcut: "Pocket Wizard > Carve",
+        description: "Cut a ligand and the residues within a radius of it out of a protein, capping the broken bonds with hydrogens, to get a cluster model for QM; undo restores the protein.",
+    },
+    HelpTopic {
+        title: "Inferred links",
+        shortcut: "Open > Infer disulfides and salt bridges",
+        description: "After a PDB file loads, close cysteine pairs are bonded as disulfides and salt bridges are drawn as dashed lines; click an entry in the Inferred links window to select its atoms.",
+    },
+    HelpTopic {
+        title: "Coordination bonds",
+        shortcut: "Edit > Add Coordination Bond",
+        description: "Join the selection to the bond target with a thin dashed metal-ligand bond that is exempt from valence limits; the Coordination checkbox converts an existing bond.",
+    },
+    HelpTopic {
+        title: "Bond order",
+        shortcut: "Edit > Order, or click a bond with Add Bond",
+        description: "Make the picked bond single, double or triple; double and triple bonds are drawn with extra thinner lines.",
+    },
+    HelpTopic {
+        title: "Bond length",
+        shortcut: "Edit > Set Length",
+        description: "Stretch or shorten the picked bond to the entered length by moving the smaller side along it; ring bonds cannot be changed this way.",
+    },
+    HelpTopic {
+        title: "Bond angle",
+        shortcut: "Select A, B, C, then Edit > Set Angle",
+        description: "Turn C and the atoms beyond it about B until the angle A-B-C matches the entered value.",
+    },
+    HelpTopic {
+        title: "Clean geometry",
+        shortcut: "Edit > Clean Geometry",
+        description: "Relax bond lengths, angles, torsions and close contacts with a UFF-style force field; runs in the background and is undone in one step.",
+    },
+    HelpTopic {
+        title: "Fixed atoms",
+        shortcut: "Edit > Geometry > Fixed",
+        description: "Pin the selected atoms so Clean Geometry only moves the others, for relaxing a ligand inside a rigid pocket.",
+    },
+    HelpTopic {
+        title: "Rotate, mirror and invert",
+        shortcut: "Edit > Geometry > Rotate / Mirror / Invert",
+        description: "Turn the selection, or everything, about a coordinate axis through its centroid, reflect it through a coordinate plane, or invert it to make the enantiomer.",
+    },
+    HelpTopic {
+        title: "Align to principal axes",
+        shortcut: "Edit > Geometry > Center / Align Axes",
+        description: "Move the centroid to the origin and, with Align Axes, turn the principal axes of inertia onto X, Y and Z.",
+    },
+    HelpTopic {
+        title: "Move atom",
+        shortcut: "Edit > Move",
+        description: "Select an atom, set a step and use the axis buttons to nudge it.",
+    },
+    HelpTopic {
+        title: "Unsaved changes",
+        shortcut: "Open window",
+        description: "Opening another file or quitting with unsaved edits asks first. Reopening the current file keeps the undo history, so its edits can be redone.",
+    },
+    HelpTopic {
+        title: "Undo",
+        shortcut: "Ctrl/Cmd + Z",
+        description: "Revert the last edit.",
+    },
+    HelpTopic {
+        title: "Redo",
+        shortcut: "Ctrl/Cmd + Shift + Z or Ctrl/Cmd + Y",
+        description: "Reapply the last undone edit.",
+    },
+    HelpTopic {
+        title: "Copy and paste",
+        shortcut: "Ctrl/Cmd + C, Ctrl/Cmd + V",
+        description: "Copy the selection and paste it as a new, offset fragment.",
+    },
+    HelpTopic {
+        title: "Measure distances, angles and dihedrals",
+        shortcut: "Edit > Measure, then click 2, 3 or 4 atoms",
+        description: "Add a monitor drawn as a dashed line whose label updates as the atoms move; pick the kind and remove monitors in the Measurements window.",
+    },
+    HelpTopic {
+        title: "Label atoms",
+        shortcut: "Status > View",
+        description: "Draw element symbols, atom indices and formal charges next to each atom, each switched on or off separately.",
+    },
+    HelpTopic {
+        title: "Sprout atoms",
+        shortcut: "Edit > Sprout, then click an atom",
+        description: "Grow an atom of the edit element from the clicked atom at tetrahedral, trigonal or linear geometry, replacing a hydrogen if the atom is saturated.",
+    },
+    HelpTopic {
+        title: "Duplicate selection",
+        shortcut: "Ctrl/Cmd + D, Edit > Duplicate",
+        description: "Copy the selected atoms and the bonds among them in place, offset slightly, and select the copy for building repeating units.",
+    },
+    HelpTopic {
+        title: "Representation",
+        shortcut: "Edit > Representation",
+        description: "Switch between ball-and-stick and space-filling spheres.",
+    },
+    HelpTopic {
+        title: "Unit cell",
+        shortcut: "Edit > Edit Cell…",
+        description: "Set lattice parameters and choose whether Cartesian or fractional coordinates stay fixed.",
+    },
+    HelpTopic {
+        title: "Compare",
+        shortcut: "Compare window",
+        description: "Highlight atoms and bonds added, removed or changed relative to an earlier step or the file on disk.",
+    },
+    HelpTopic {
+        title: "Checkpoints and sessions",
+        shortcut: "Checkpoints window",
+        description: "Snapshot named states, revert or branch from them, and save everything to a session file.",
+    },
+    HelpTopic {
+        title: "Provenance log",
+        shortcut: "Provenance window > Export Log",
+        description: "Review the timestamped history of edits, files and exports, and save it as text.",
+    },
+    HelpTopic {
+        title: "Composition",
+        shortcut: "Composition window",
+        description: "Element counts, atom and mass percentages, and the density of periodic systems.",
+    },
+    HelpTopic {
+        title: "Notes",
+        shortcut: "Notes window",
+        description: "Attach free-text notes to atoms or bonds; hover a marker to read one.",
+    },
+    HelpTopic {
+        title: "Mechanism arrows",
+        shortcut: "Annotations window",
+        description: "Draw curly, fish-hook or straight arrows between atoms and bonds, and dashed partial bonds for transition states.",
+    },
+    HelpTopic {
+        title: "Comparison grid",
+        shortcut: "Comparison Grid window",
+        description: "Show several structures side by side in sub-viewports that share one camera.",
+    },
+    HelpTopic {
+        title: "Gallery",
+        shortcut: "Gallery window",
+        description: "Watch a results folder and click a thumbnail to open that structure.",
+    },
+    HelpTopic {
+        title: "Find substructure",
+        shortcut: "Find Substructure window",
+        description: "Highlight every occurrence of a pattern loaded from a file or copied to the clipboard.",
+    },
+    HelpTopic {
+        title: "Query selection",
+        shortcut: "Find Substructure window > Query",
+        description: "Type a SMARTS-like query such as [C:1]=O (carbonyl carbons) or [N;R] (ring nitrogens) to highlight matching atoms.",
+    },
+    HelpTopic {
+        title: "Color-blind palettes",
+        shortcut: "Preferences window",
+        description: "Switch atoms and highlights to deuteranopia- or protanopia-safe colors.",
+    },
+    HelpTopic {
+        title: "Script coloring and selection",
+        shortcut: "Preferences window > Script",
+        description: "Load a Rhai script that registers custom atom colorings and selections; they appear under Coloring and as Script selection in the Find Substructure window.",
+    },
+    HelpTopic {
+        title: "Style overrides",
+        shortcut: "Style Overrides window",
+        description: "Recolor or resize an element or a single atom; saved with the session.",
+    },
+    HelpTopic {
+        title: "Style rules",
+        shortcut: "Style Overrides window > Rules",
+        description: "Restyle or hide atoms by element, charge or fragment size, e.g. `element == Fe -> color orange`; re-applied after every edit and saved with the session.",
+    },
+    HelpTopic {
+        title: "Presentation screenshots",
+        shortcut: "Preferences window > Save Screenshot",
+        description: "Save a supersampled image with soft shadows and depth of field focused on the view target.",
+    },
+    HelpTopic {
+        title: "Sketch atoms",
+        shortcut: "Add Atom tool: click empty space / Move tool: drag an atom",
+        description: "Place or drag atoms with a live bond length and angle readout, snapping to 109.5°, 120° or 180°.",
+    },
+    HelpTopic {
+        title: "Add or remove hydrogens",
+        shortcut: "Edit window > Add Hydrogens / Remove Hydrogens",
+        description: "Fill open valences with hydrogens at idealized geometry, or strip every hydrogen, as one undoable step.",
+    },
+    HelpTopic {
+        title: "Valence rules",
+        shortcut: "Preferences window > Valence rules",
+        description: "Override bond limits per element or per element and charge, e.g. Fe 6 or N +1 4; Permissive allows extra bonds and counts them as warnings.",
+    },
+    HelpTopic {
+        title: "Stereo labels",
+        shortcut: "Preferences window > Label R/S and E/Z",
+        description: "Show CIP descriptors perceived from the 3D coordinates next to chiral centers and double bonds.",
+    },
+    HelpTopic {
+        title: "POV-Ray export",
+        shortcut: "Export window",
+        description: "Write the current view as a POV-Ray scene with ambient bounce lighting and soft shadows for offline path-traced renders.",
+    },
+    HelpTopic {
+        title: "glTF export",
+        shortcut: "Export window",
+        description: "Save atoms and bonds as an instanced binary glTF (.glb) model for Blender, PowerPoint 3D or AR viewers.",
+    },
+    HelpTopic {
+        title: "3D print export",
+        shortcut: "Export window > Export STL",
+        description: "Write the ball-and-stick or space-filling model as closed STL shells at a chosen mm-per-Å scale, thickening bonds below the minimum diameter.",
+    },
+    HelpTopic {
+        title: "Trajectory frames",
+        shortcut: "Export window > Frames / Record edits",
+        description: "Export chosen frames of a multi-frame file as numbered XYZ or SDF files, or record one frame per edit and save the recording as a trajectory for demonstrating construction steps.",
+    },
+    HelpTopic {
+        title: "Extended XYZ",
+        shortcut: "Export window > Save extxyz",
+        description: "Extended XYZ files load their cell, metadata and extra atom columns such as charges and forces; Save extxyz writes them back.",
+    },
+    HelpTopic {
+        title: "Gaussian input",
+        shortcut: "Export window > Gaussian input",
+        description: "Save the geometry as a .gjf file with a route section, Link 0 commands and derived or overridden charge and multiplicity.",
+    },
+    HelpTopic {
+        title: "Help",
+        shortcut: "F1",
+        description: "Show this reference and the guided tutorial.",
+    },
+];
+
+/// Topics containing every whitespace-separated word of `query`, ignoring case.
+pub fn search_help(query: &str) -> Vec<&'static HelpTopic> {
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    HELP_TOPICS
+        .iter()
+        .filter(|topic| {
+            let text =
+                format!("{} {} {}", topic.title, topic.shortcut, topic.description).to_lowercase();
+            words.iter().all(|word| text.contains(word.as_str()))
+        })
+        .collect()
+}
+
+pub struct TutorialStep {
+    pub title: &'static str,
+    pub instructions: &'static str,
+    done: fn(&Molecule) -> bool,
+}
+
+/// Build a water molecule, bond it, then adjust it to the experimental H–O–H angle.
+pub const WATER_TUTORIAL: &[TutorialStep] = &[
+    TutorialStep {
+        title: "Add an oxygen",
+        instructions: "Pick O as the element in the Edit window and press Insert Atom.",
+        done: |molecule| count_element(molecule, "O") >= 1,
+    },
+    TutorialStep {
+        title: "Add two hydrogens",
+        instructions: "Switch the element to H and insert two atoms.",
+        done: |molecule| count_element(molecule, "H") >= 2,
+    },
+    TutorialStep {
+        title: "Bond them",
+        instructions:
+            "With the Add Bond tool, click the oxygen then a hydrogen; repeat for the other one.",
+        done: |molecule| water_angle(molecule).is_some(),
+    },
+    TutorialStep {
+        title: "Measure the angle",
+        instructions: "Select a hydrogen and move it until the H–O–H angle reads about 104.5°.",
+        done: |molecule| {
+            water_angle(molecule)
+                .is_some_and(|value| (value - WATER_ANGLE).abs() <= WATER_ANGLE_TOLERANCE)
+        },
+    },
+];
+
+/// Progress through a tutorial, re-evaluated against the molecule after every command so
+/// undo moves it back as well.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tutorial {
+    completed: usize,
+}
+
+impl Tutorial {
+    pub fn update(&mut self, steps: &[TutorialStep], molecule: &Molecule) {
+        self.completed = steps
+            .iter()
+            .take_while(|step| (step.done)(molecule))
+            .count();
+    }
+
+    /// Index of the step being worked on; equals `steps.len()` once finished.
+    pub fn current(&self) -> usize {
+        self.completed
+    }
+
+    pub fn is_finished(&self, steps: &[TutorialStep]) -> bool {
+        self.completed >= steps.len()
+    }
+}
+
+/// H–O–H angle in degrees at the first oxygen bonded to at least two hydrogens.
+pub fn water_angle(molecule: &Molecule) -> Option<f32> {
+    molecule
+        .atoms_in_order()
+        .filter(|atom| atom.element.trim().eq_ignore_ascii_case("O"))
+        .find_map(|oxygen| {
+            let hydrogens: Vec<_> = molecule
+                .neighbors(oxygen.id)
+                .into_iter()
+                .filter(|id| {
+                    molecule
+                        .get_atom(*id)
+                        .is_some_and(|atom| atom.element.trim().eq_ignore_ascii_case("H"))
+                })
+                .collect();
+            match hydrogens[..] {
+                [first, second, ..] => angle(molecule, first, oxygen.id, second),
+                _ => None,
+            }
+        })
+}
+
+fn count_element(molecule: &Molecule, element: &str) -> usize {
+    molecule
+        .atoms_in_order()
+        .filter(|atom| atom.element.trim().eq_ignore_ascii_case(element))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_matches_all_words() {
+        let results = search_help("ctrl z");
+        assert!(results.iter().any(|topic| topic.title == "Undo"));
+        assert!(results.iter().all(|topic| topic.shortcut.contains('Z')));
+        assert!(search_help("no such feature").is_empty());
+        assert_eq!(search_help("").len(), HELP_TOPICS.len());
+    }
+
+    #[test]
+    fn water_tutorial_tracks_progress() {
+        let mut molecule = Molecule::new("tutorial");
+        let mut tutorial = Tutorial::default();
+        tutorial.update(WATER_TUTORIAL, &molecule);
+        assert_eq!(tutorial.current(), 0);
+        let o = molecule.insert_atom("O".into(), [0.0, 0.0, 0.0]);
+        let h1 = molecule.insert_atom("H".into(), [0.96, 0.0, 0.0]);
+        let h2 = molecule.insert_atom("H".into(), [0.0, 0.96, 0.0]);
+        tutorial.update(WATER_TUTORIAL, &molecule);
+        assert_eq!(tutorial.current(), 2);
+        molecule.add_bond(o, h1).unwrap();
+        molecule.add_bond(o, h2).unwrap();
+        tutorial.update(WATER_TUTORIAL, &molecule);
+        assert_eq!(tutorial.current(), 3);
+        assert!((water_angle(&molecule).unwrap() - 90.0).abs() < 1e-3);
+        let theta = WATER_ANGLE.to_radians();
+        molecule.set_atom_position(h2, [0.96 * theta.cos(), 0.96 * theta.sin(), 0.0]);
+        tutorial.update(WATER_TUTORIAL, &molecule);
+        assert!(tutorial.is_finished(WATER_TUTORIAL));
+    }
+}
@@ -0,0 +1,354 @@
+//! A self-contained HTML page showing the structure, for collaborators without MolWeaver.
+//!
+//! The page embeds the rendered geometry as JSON and a small script that draws it on a 2D
+//! canvas: depth-sorted shaded spheres and bonds, which the reader rotates by dragging and
+//! zooms with the wheel. Notes appear as markers that show their text on hover, and mechanism
+//! annotations are drawn as in the viewer. Nothing is loaded from the network.
+
+use std::fmt::Write as _;
+
+use crate::annotations::{self, Annotations, ArrowKind};
+use crate::notes::Notes;
+use crate::scene::Scene;
+use crate::Molecule;
+
+/// The page for `scene`, titled with the molecule's name, with the notes and annotations on
+/// atoms still in `molecule`.
+pub fn write_html(
+    scene: &Scene,
+    molecule: &Molecule,
+    notes: &Notes,
+    annotations: &Annotations,
+) -> String {
+    let mut data = String::from("{\"spheres\":[");
+    for (index, sphere) in scene.spheres.iter().enumerate() {
+        let [x, y, z] = sphere.center;
+        let separator = if index == 0 { "" } else { "," };
+        let _ = write!(
+            data,
+            "{separator}[{x},{y},{z},{},\"{}\"]",
+            sphere.radius,
+            hex(sphere.color)
+        );
+    }
+    data.push_str("],\"cylinders\":[");
+    for (index, cylinder) in scene.cylinders.iter().enumerate() {
+        let ([ax, ay, az], [bx, by, bz]) = (cylinder.start, cylinder.end);
+        let separator = if index == 0 { "" } else { "," };
+        let _ = write!(
+            data,
+            "{separator}[{ax},{ay},{az},{bx},{by},{bz},{},\"{}\"]",
+            cylinder.radius,
+            hex(cylinder.color)
+        );
+    }
+    data.push_str("],\"notes\":[");
+    for (index, (_, [x, y, z], text)) in notes.markers(molecule).into_iter().enumerate() {
+        let separator = if index == 0 { "" } else { "," };
+        let _ = write!(data, "{separator}[{x},{y},{z},{}]", json_string(text));
+    }
+    data.push_str("],\"arrows\":[");
+    for (index, (arrow, from, to)) in annotations.arrow_ends(molecule).into_iter().enumerate() {
+        let separator = if index == 0 { "" } else { "," };
+        let kind = match arrow.kind {
+            ArrowKind::Curly => "curly",
+            ArrowKind::FishHook => "fishhook",
+            ArrowKind::Straight => "straight",
+        };
+        let _ = write!(
+            data,
+            "{separator}[{},{},{},{},{},{},\"{kind}\"]",
+            from.x, from.y, from.z, to.x, to.y, to.z
+        );
+    }
+    data.push_str("],\"partial\":[");
+    let partial_bonds = annotations.partial_bond_ends(molecule);
+    for (index, (bond, a, b)) in partial_bonds.into_iter().enumerate() {
+        let separator = if index == 0 { "" } else { "," };
+        let strokes: Vec<String> = annotations::bond_strokes(bond.order)
+            .into_iter()
+            .map(|(offset, dashed)| format!("[{offset},{dashed}]"))
+            .collect();
+        let _ = write!(
+            data,
+            "{separator}[{},{},{},{},{},{},[{}]]",
+            a.x,
+            a.y,
+            a.z,
+            b.x,
+            b.y,
+            b.z,
+            strokes.join(",")
+        );
+    }
+    let camera = &scene.camera;
+    let [px, py, pz] = camera.position;
+    let [tx, ty, tz] = camera.target;
+    let [ux, uy, uz] = camera.up;
+    let _ = write!(
+        data,
+        "],\"camera\":{{\"position\":[{px},{py},{pz}],\"target\":[{tx},{ty},{tz}],\
+         \"up\":[{ux},{uy},{uz}],\"fov\":{}}}}}",
+        camera.fov_degrees
+    );
+    // Data first, so a note can't smuggle in a marker the title fills.
+    PAGE.replace("{{DATA}}", &data)
+        .replace("{{TITLE}}", &escape_html(&molecule.name))
+}
+
+/// `#rrggbb` for an sRGB color with channels in [0, 1].
+fn hex(color: [f32; 3]) -> String {
+    let [r, g, b] = color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// A JSON string literal that is also safe inside a `<script>` element.
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '<' => out.push_str("\\u003c"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="generator" content="MolWeaver">
+<title>{{TITLE}}</title>
+<style>
+  html, body { margin: 0; height: 100%; background: #1e2126; overflow: hidden; }
+  canvas { display: block; width: 100%; height: 100%; cursor: grab; }
+  #title { position: fixed; left: 12px; top: 8px; color: #ddd; font: 15px sans-serif; }
+  #hint { position: fixed; left: 12px; bottom: 8px; color: #888; font: 12px sans-serif; }
+  #tip { position: fixed; display: none; max-width: 320px; padding: 4px 8px; background: #fff8dc;
+         border: 1px solid #333; font: 13px sans-serif; white-space: pre-wrap; pointer-events: none; }
+</style>
+</head>
+<body>
+<canvas id="view"></canvas>
+<div id="title">{{TITLE}}</div>
+<div id="hint">Drag to rotate, scroll to zoom, double-click to reset.</div>
+<div id="tip"></div>
+<script>
+"use strict";
+const data = {{DATA}};
+const canvas = document.getElementById("view");
+const tip = document.getElementById("tip");
+const ctx = canvas.getContext("2d");
+const sub = (a, b) => [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+const dot = (a, b) => a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+const cross = (a, b) => [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+const unit = (a) => { const l = Math.hypot(...a) || 1; return [a[0] / l, a[1] / l, a[2] / l]; };
+const turn = (v, axis, angle) => {
+  const c = Math.cos(angle), s = Math.sin(angle), k = cross(axis, v), d = dot(axis, v) * (1 - c);
+  return [v[0] * c + k[0] * s + axis[0] * d, v[1] * c + k[1] * s + axis[1] * d, v[2] * c + k[2] * s + axis[2] * d];
+};
+let view;
+function reset() {
+  const cam = data.camera, back = unit(sub(cam.position, cam.target));
+  const right = unit(cross(cam.up, back));
+  view = { center: cam.target, right, up: cross(back, right), back,
+           distance: Math.hypot(...sub(cam.position, cam.target)) };
+}
+function project(p) {
+  const d = sub(p, view.center);
+  const z = dot(d, view.back), depth = Math.max(view.distance - z, 1e-3);
+  const scale = canvas.height / 2 / Math.tan(data.camera.fov * Math.PI / 360) / depth;
+  return { x: canvas.width / 2 + dot(d, view.right) * scale, y: canvas.height / 2 - dot(d, view.up) * scale, z, scale };
+}
+function shade(hex, amount) {
+  const n = parseInt(hex.slice(1), 16), mix = (c) => Math.round(amount > 0 ? c + (255 - c) * amount : c * (1 + amount));
+  return `rgb(${mix(n >> 16)},${mix((n >> 8) & 255)},${mix(n & 255)})`;
+}
+function arrowhead(x, y, angle, size, half) {
+  ctx.beginPath();
+  ctx.moveTo(x + Math.cos(angle - 0.45) * size, y + Math.sin(angle - 0.45) * size);
+  ctx.lineTo(x, y);
+  if (!half) ctx.lineTo(x + Math.cos(angle + 0.45) * size, y + Math.sin(angle + 0.45) * size);
+  ctx.stroke();
+}
+let markers = [];
+function draw() {
+  const ratio = window.devicePixelRatio || 1;
+  canvas.width = canvas.clientWidth * ratio;
+  canvas.height = canvas.clientHeight * ratio;
+  ctx.clearRect(0, 0, canvas.width, canvas.height);
+  const items = [];
+  for (const [x, y, z, r, color] of data.spheres) {
+    items.push({ p: project([x, y, z]), r, color, sphere: true });
+  }
+  for (const [ax, ay, az, bx, by, bz, r, color] of data.cylinders) {
+    const a = project([ax, ay, az]), b = project([bx, by, bz]);
+    items.push({ p: { z: (a.z + b.z) / 2 }, a, b, r, color });
+  }
+  items.sort((i, j) => i.p.z - j.p.z);
+  for (const item of items) {
+    if (item.sphere) {
+      const { x, y, scale } = item.p, r = item.r * scale;
+      const fill = ctx.createRadialGradient(x - r * 0.35, y - r * 0.35, r * 0.1, x, y, r);
+      fill.addColorStop(0, shade(item.color, 0.6));
+      fill.addColorStop(0.5, item.color);
+      fill.addColorStop(1, shade(item.color, -0.5));
+      ctx.fillStyle = fill;
+      ctx.beginPath();
+      ctx.arc(x, y, r, 0, 2 * Math.PI);
+      ctx.fill();
+    } else {
+      ctx.strokeStyle = item.color;
+      ctx.lineWidth = item.r * (item.a.scale + item.b.scale);
+      ctx.lineCap = "round";
+      ctx.beginPath();
+      ctx.moveTo(item.a.x, item.a.y);
+      ctx.lineTo(item.b.x, item.b.y);
+      ctx.stroke();
+    }
+  }
+  const px = window.devicePixelRatio || 1;
+  ctx.lineCap = "butt";
+  ctx.strokeStyle = "rgb(255,170,60)";
+  ctx.lineWidth = 2 * px;
+  for (const [ax, ay, az, bx, by, bz, strokes] of data.partial) {
+    const a = project([ax, ay, az]), b = project([bx, by, bz]);
+    const l = Math.hypot(b.x - a.x, b.y - a.y) || 1, nx = -(b.y - a.y) / l * 4 * px, ny = (b.x - a.x) / l * 4 * px;
+    for (const [offset, dashed] of strokes) {
+      ctx.setLineDash(dashed ? [5 * px, 4 * px] : []);
+      ctx.beginPath();
+      ctx.moveTo(a.x + nx * offset, a.y + ny * offset);
+      ctx.lineTo(b.x + nx * offset, b.y + ny * offset);
+      ctx.stroke();
+    }
+  }
+  ctx.setLineDash([]);
+  for (const [ax, ay, az, bx, by, bz, kind] of data.arrows) {
+    const a = project([ax, ay, az]), b = project([bx, by, bz]);
+    const dx = b.x - a.x, dy = b.y - a.y, l = Math.hypot(dx, dy), inset = 8 * px;
+    if (l <= 2 * inset) continue;
+    const [sx, sy, ex, ey] = [a.x + dx / l * inset, a.y + dy / l * inset, b.x - dx / l * inset, b.y - dy / l * inset];
+    ctx.strokeStyle = kind === "straight" ? "rgb(230,230,230)" : "rgb(230,60,60)";
+    ctx.beginPath();
+    ctx.moveTo(sx, sy);
+    let angle = Math.atan2(sy - ey, sx - ex);
+    if (kind === "straight") {
+      ctx.lineTo(ex, ey);
+    } else {
+      const cx = (sx + ex) / 2 - (ey - sy) * 0.35, cy = (sy + ey) / 2 + (ex - sx) * 0.35;
+      ctx.quadraticCurveTo(cx, cy, ex, ey);
+      angle = Math.atan2(cy - ey, cx - ex);
+    }
+    ctx.stroke();
+    arrowhead(ex, ey, angle, 10 * px, kind === "fishhook");
+  }
+  markers = [];
+  for (const [x, y, z, text] of data.notes) {
+    const p = project([x, y, z]), mx = p.x + 10 * px, my = p.y - 10 * px;
+    ctx.fillStyle = "rgb(255,200,40)";
+    ctx.strokeStyle = "black";
+    ctx.lineWidth = px;
+    ctx.beginPath();
+    ctx.arc(mx, my, 5 * px, 0, 2 * Math.PI);
+    ctx.fill();
+    ctx.stroke();
+    markers.push({ x: mx / px, y: my / px, text });
+  }
+}
+let drag = null;
+canvas.addEventListener("pointerdown", (e) => { drag = { x: e.clientX, y: e.clientY }; canvas.setPointerCapture(e.pointerId); });
+canvas.addEventListener("pointerup", () => { drag = null; });
+canvas.addEventListener("pointermove", (e) => {
+  if (drag) {
+    const yaw = (e.clientX - drag.x) * 0.01, pitch = (e.clientY - drag.y) * 0.01;
+    drag = { x: e.clientX, y: e.clientY };
+    for (const key of ["right", "back"]) view[key] = turn(view[key], view.up, -yaw);
+    for (const key of ["up", "back"]) view[key] = turn(view[key], view.right, -pitch);
+    draw();
+  }
+  const hit = markers.find((m) => Math.hypot(m.x - e.clientX, m.y - e.clientY) <= 10);
+  tip.style.display = hit ? "block" : "none";
+  if (hit) {
+    tip.textContent = hit.text;
+    tip.style.left = `${e.clientX + 12}px`;
+    tip.style.top = `${e.clientY + 12}px`;
+  }
+});
+canvas.addEventListener("wheel", (e) => { e.preventDefault(); view.distance *= e.deltaY > 0 ? 1.1 : 1 / 1.1; draw(); }, { passive: false });
+canvas.addEventListener("dblclick", () => { reset(); draw(); });
+window.addEventListener("resize", draw);
+reset();
+draw();
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::{Arrow, Site};
+    use crate::notes::NoteTarget;
+    use crate::palette::Palette;
+    use crate::scene::SceneStyle;
+    use crate::style::StyleOverrides;
+
+    #[test]
+    fn embeds_geometry_notes_and_annotations() {
+        let mut molecule = Molecule::new("water <TS>");
+        let o = molecule.insert_atom("O".into(), [0.0; 3]);
+        let h = molecule.insert_atom("H".into(), [0.96, 0.0, 0.0]);
+        molecule.add_bond(o, h).unwrap();
+        let overrides = StyleOverrides::default();
+        let style = SceneStyle {
+            palette: Palette::default(),
+            overrides: &overrides,
+            atom_radius: 0.3,
+            bond_radius: Some(0.1),
+        };
+        let scene = Scene::from_molecule(&molecule, &style, 45.0, 1.5);
+        let mut notes = Notes::default();
+        notes.set(NoteTarget::Atom(o), "lone pair \"here\"</script>");
+        let mut annotations = Annotations::default();
+        annotations.arrows.push(Arrow {
+            kind: ArrowKind::FishHook,
+            from: Site::Atom(o),
+            to: Site::Between(o, h),
+        });
+        annotations.set_partial_bond(o, h, 1.5);
+
+        let page = write_html(&scene, &molecule, &notes, &annotations);
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("<title>water &lt;TS&gt;</title>"));
+        assert!(page.contains(r#""notes":[[0,0,0,"lone pair \"here\"\u003c/script>"]]"#));
+        assert!(page.contains(r#""arrows":[[0,0,0,0.48,0,0,"fishhook"]]"#));
+        assert!(page.contains(r#""partial":[[0,0,0,0.96,0,0,[[-0.5,false],[0.5,true]]]]"#));
+        assert_eq!(page.matches("</script>").count(), 1);
+        assert!(!page.contains("{{"));
+        let spheres = page.split("\"spheres\":[").nth(1).unwrap();
+        assert_eq!(
+            spheres
+                .split("],\"cylinders\"")
+                .next()
+                .unwrap()
+                .matches('#')
+                .count(),
+            2
+        );
+        assert!(!page.contains("http"), "no external resources");
+    }
+}
@@ -0,0 +1,336 @@
+//! Filling open valences with hydrogens at idealized geometry, and stripping them again.
+
+use std::f32::consts::TAU;
+
+use glam::{Quat, Vec3};
+
+use crate::elements::normalize_symbol;
+use crate::{AtomId, BondKind, Command, Molecule};
+
+/// Tetrahedral angle in degrees.
+const TETRAHEDRAL: f32 = 109.47;
+const TRIGONAL: f32 = 120.0;
+
+impl Molecule {
+    /// Adds hydrogens to every atom with open valence and returns how many were added.
+    pub fn add_hydrogens(&mut self) -> Result<usize, String> {
+        let mut command = Command::add_hydrogens(self);
+        command.apply(self)?;
+        Ok(command_len(&command))
+    }
+
+    /// Deletes every hydrogen and returns how many were removed.
+    pub fn remove_hydrogens(&mut self) -> Result<usize, String> {
+        let mut command = Command::remove_hydrogens(self);
+        command.apply(self)?;
+        Ok(command_len(&command))
+    }
+}
+
+impl Command {
+    /// One composite step that inserts each missing hydrogen and bonds it to its parent.
+    pub fn add_hydrogens(molecule: &Molecule) -> Command {
+        let mut next_id = molecule.next_atom_id;
+        let mut commands = Vec::new();
+        for atom in molecule.atoms_in_order() {
+            for position in hydrogen_positions(molecule, atom.id) {
+                let hydrogen = AtomId::new(next_id);
+                next_id += 1;
+                commands.push(Command::InsertAtom {
+                    element: "H".to_string(),
+                    position: position.to_array(),
+                    atom_id: Some(hydrogen),
+                    order_index: None,
+                });
+                commands.push(Command::AddBond {
+                    atom_a: atom.id,
+                    atom_b: hydrogen,
+                    bond_id: None,
+                });
+            }
+        }
+        Command::Composite { commands }
+    }
+
+    /// One composite step deleting every hydrogen along with its bonds.
+    pub fn remove_hydrogens(molecule: &Molecule) -> Command {
+        let commands = molecule
+            .atoms_in_order()
+            .filter(|atom| is_hydrogen(&atom.element))
+            .map(|atom| Command::DeleteAtom {
+                atom_id: atom.id,
+                removed: None,
+            })
+            .collect();
+        Command::Composite { commands }
+    }
+}
+
+fn command_len(command: &Command) -> usize {
+    match command {
+        Command::Composite { commands } => commands
+            .iter()
+            .filter(|command| !matches!(command, Command::AddBond { .. }))
+            .count(),
+        _ => 0,
+    }
+}
+
+fn is_hydrogen(element: &str) -> bool {
+    normalize_symbol(element) == "H"
+}
+
+/// Usual number of bonds, adjusted for formal charge; `None` for elements that don't take
+/// hydrogens here (metals, noble gases, unknown symbols).
+pub(crate) fn standard_valence(element: &str, charge: i32) -> Option<i32> {
+    let valence = match normalize_symbol(element).as_str() {
+        "B" => 3 - charge,
+        "C" | "Si" => 4 - charge.abs(),
+        "N" | "P" => 3 + charge,
+        "O" | "S" | "Se" => 2 + charge,
+        "F" | "Cl" | "Br" | "I" => 1 + charge,
+        _ => return None,
+    };
+    Some(valence.max(0))
+}
+
+pub(crate) fn bond_length(element: &str) -> f32 {
+    match normalize_symbol(element).as_str() {
+        "B" => 1.19,
+        "C" => 1.09,
+        "N" => 1.01,
+        "O" => 0.96,
+        "Si" => 1.48,
+        "P" => 1.42,
+        "S" => 1.34,
+        "Se" => 1.46,
+        "F" => 0.92,
+        "Cl" => 1.27,
+        "Br" => 1.41,
+        "I" => 1.61,
+        _ => 1.0,
+    }
+}
+
+/// Positions for the hydrogens `id` is missing. The electron domains are linear with a triple
+/// bond or two double bonds, trigonal with one double bond and tetrahedral otherwise; new
+/// hydrogens fill the free corners.
+pub fn hydrogen_positions(molecule: &Molecule, id: AtomId) -> Vec<Vec3> {
+    let Some(atom) = molecule.get_atom(id) else {
+        return Vec::new();
+    };
+    let Some(valence) = standard_valence(&atom.element, atom.charge) else {
+        return Vec::new();
+    };
+    let center = Vec3::from_array(atom.position);
+    let bonds: Vec<_> = molecule
+        .bonds()
+        .filter(|bond| bond.a == id || bond.b == id)
+        .collect();
+    // Coordination bonds use a lone pair, not valence.
+    let used: i32 = bonds
+        .iter()
+        .filter(|bond| bond.kind != BondKind::Coordination)
+        .map(|bond| i32::from(bond.order))
+        .sum();
+    let missing = (valence - used).max(0) as usize;
+    if missing == 0 {
+        return Vec::new();
+    }
+    let doubles = bonds.iter().filter(|bond| bond.order == 2).count();
+    let domains = if bonds.iter().any(|bond| bond.order >= 3) || doubles >= 2 {
+        2
+    } else if doubles == 1 {
+        3
+    } else {
+        4
+    };
+    let neighbors: Vec<AtomId> = bonds
+        .iter()
+        .map(|bond| if bond.a == id { bond.b } else { bond.a })
+        .collect();
+    let directions: Vec<Vec3> = neighbors
+        .iter()
+        .filter_map(|&other| molecule.get_atom(other))
+        .map(|other| (Vec3::from_array(other.position) - center).normalize_or_zero())
+        .filter(|direction| *direction != Vec3::ZERO)
+        .collect();
+    let free = free_directions(molecule, &neighbors, &directions, domains);
+    let length = bond_length(&atom.element);
+    free.into_iter()
+        .take(missing)
+        .map(|direction| center + direction * length)
+        .collect()
+}
+
+fn free_directions(
+    molecule: &Molecule,
+    neighbors: &[AtomId],
+    directions: &[Vec3],
+    domains: usize,
+) -> Vec<Vec3> {
+    let angle = if domains == 3 { TRIGONAL } else { TETRAHEDRAL }.to_radians();
+    match directions {
+        [] => match domains {
+            2 => vec![Vec3::X, -Vec3::X],
+            3 => (0..3)
+                .map(|i| Quat::from_rotation_z(i as f32 * TAU / 3.0) * Vec3::X)
+                .collect(),
+            _ => {
+                let down = Quat::from_rotation_x(angle) * Vec3::Z;
+                let mut corners = vec![Vec3::Z];
+                corners.extend((0..3).map(|i| Quat::from_rotation_z(i as f32 * TAU / 3.0) * down));
+                corners
+            }
+        },
+        [u] => {
+            if domains == 2 {
+                return vec![-*u];
+            }
+            // Put the new hydrogens in the plane of the neighbor's own substituents for
+            // trigonal centers, and staggered against them for tetrahedral ones.
+            let mut reference = reference_perpendicular(molecule, neighbors[0], *u);
+            if domains == 4 {
+                reference = Quat::from_axis_angle(*u, TAU / 6.0) * reference;
+            }
+            let count = domains - 1;
+            (0..count)
+                .map(|i| {
+                    let around = Quat::from_axis_angle(*u, i as f32 * TAU / count as f32);
+                    *u * angle.cos() + around * reference * angle.sin()
+                })
+                .collect()
+        }
+        [u, v] => {
+            let bisector = -(*u + *v);
+            let Some(bisector) = bisector.try_normalize() else {
+                return vec![any_perpendicular(*u)];
+            };
+            if domains == 3 {
+                return vec![bisector];
+            }
+            let normal = u.cross(*v).normalize_or_zero();
+            let half = angle * 0.5;
+            vec![
+                bisector * half.cos() + normal * half.sin(),
+                bisector * half.cos() - normal * half.sin(),
+            ]
+        }
+        [u, v, w] if domains == 4 => {
+            let sum = -(*u + *v + *w);
+            vec![sum
+                .try_normalize()
+                .unwrap_or_else(|| (*v - *u).cross(*w - *u).normalize_or_zero())]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// A unit vector perpendicular to `axis`, taken from a substituent of `neighbor` (the atom
+/// `axis` points to) when it has one.
+fn reference_perpendicular(molecule: &Molecule, neighbor: AtomId, axis: Vec3) -> Vec3 {
+    let Some(origin) = molecule.get_atom(neighbor) else {
+        return any_perpendicular(axis);
+    };
+    let origin = Vec3::from_array(origin.position);
+    molecule
+        .neighbors(neighbor)
+        .into_iter()
+        .filter_map(|other| molecule.get_atom(other))
+        .map(|other| (Vec3::from_array(other.position) - origin).reject_from(axis))
+        // The atom being filled lies on the axis; skip it and anything else nearly collinear.
+        .find(|offset| offset.length() > 1e-3)
+        .map(Vec3::normalize)
+        .unwrap_or_else(|| any_perpendicular(axis))
+}
+
+fn any_perpendicular(axis: Vec3) -> Vec3 {
+    axis.any_orthonormal_vector()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::angle;
+    use crate::CommandHistory;
+
+    #[test]
+    fn fills_valence_with_ideal_geometry() {
+        let mut ethene = Molecule::new("ethene");
+        let a = ethene.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let b = ethene.insert_atom("C".into(), [1.33, 0.0, 0.0]);
+        let double = ethene.add_bond(a, b).unwrap();
+        ethene.set_bond_order(double, 2);
+        assert_eq!(ethene.add_hydrogens(), Ok(4));
+        assert_eq!(ethene.formula(), "C2H4");
+        let hydrogens = ethene.neighbors(a);
+        assert_eq!(hydrogens.len(), 3);
+        let hh = angle(&ethene, hydrogens[1], a, hydrogens[2]).unwrap();
+        assert!((hh - 120.0).abs() < 0.1, "{hh}");
+        assert_eq!(ethene.add_hydrogens(), Ok(0));
+
+        let mut water = Molecule::new("water");
+        let o = water.insert_atom("O".into(), [0.0; 3]);
+        assert_eq!(water.add_hydrogens(), Ok(2));
+        let [h1, h2] = water.neighbors(o)[..] else {
+            panic!("expected two hydrogens");
+        };
+        assert!((angle(&water, h1, o, h2).unwrap() - TETRAHEDRAL).abs() < 0.1);
+
+        let mut methane = Molecule::new("methane");
+        let c = methane.insert_atom("C".into(), [0.0; 3]);
+        methane.add_hydrogens().unwrap();
+        let ids = methane.neighbors(c);
+        for pair in [(0, 1), (1, 2), (2, 3), (0, 3)] {
+            let value = angle(&methane, ids[pair.0], c, ids[pair.1]).unwrap();
+            assert!((value - TETRAHEDRAL).abs() < 0.1, "{value}");
+        }
+    }
+
+    #[test]
+    fn coordination_bonds_leave_valence_free() {
+        let mut complex = Molecule::new("ammine");
+        let fe = complex.insert_atom("Fe".into(), [0.0; 3]);
+        let n = complex.insert_atom("N".into(), [2.0, 0.0, 0.0]);
+        let dative = complex.add_bond(n, fe).unwrap();
+        complex
+            .set_bond_kind(dative, BondKind::Coordination)
+            .unwrap();
+        assert_eq!(complex.add_hydrogens(), Ok(3));
+        assert_eq!(complex.formula(), "FeH3N");
+        let fe_direction = -Vec3::X;
+        for h in complex.neighbors(n).into_iter().filter(|&id| id != fe) {
+            let position = Vec3::from_array(complex.get_atom(h).unwrap().position);
+            let direction = (position - Vec3::new(2.0, 0.0, 0.0)).normalize();
+            assert!(
+                direction.dot(fe_direction) < 0.0,
+                "hydrogen points at the metal"
+            );
+        }
+    }
+
+    #[test]
+    fn add_and_remove_are_single_undo_steps() {
+        let mut molecule = Molecule::new("ethane");
+        let a = molecule.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let b = molecule.insert_atom("C".into(), [1.54, 0.0, 0.0]);
+        molecule.add_bond(a, b).unwrap();
+        let mut history = CommandHistory::new(10);
+        history
+            .execute(Command::add_hydrogens(&molecule), &mut molecule)
+            .unwrap();
+        assert_eq!(molecule.formula(), "C2H6");
+        assert_eq!(history.undo_len(), 1);
+        history
+            .execute(Command::remove_hydrogens(&molecule), &mut molecule)
+            .unwrap();
+        assert_eq!(molecule.formula(), "C2");
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.formula(), "C2H6");
+        assert_eq!(molecule.bonds().count(), 7);
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.atom_count(), 2);
+        history.redo(&mut molecule).unwrap();
+        assert_eq!(molecule.formula(), "C2H6");
+    }
+}
@@ -0,0 +1,96 @@
+//! Text drawn next to atoms: any combination of element symbol, atom id and formal charge.
+
+use glam::Vec3;
+
+use crate::{Atom, Molecule};
+
+/// Which parts atom labels show. With none enabled there are no labels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AtomLabels {
+    pub element: bool,
+    pub index: bool,
+    pub charge: bool,
+}
+
+impl AtomLabels {
+    pub fn is_enabled(&self) -> bool {
+        self.element || self.index || self.charge
+    }
+
+    /// The label for `atom`, such as `N12 +`; `None` when it would be empty, as for an
+    /// uncharged atom when only charges are shown.
+    pub fn text(&self, atom: &Atom) -> Option<String> {
+        let mut name = String::new();
+        if self.element {
+            name.push_str(&atom.element);
+        }
+        if self.index {
+            name.push_str(&atom.id.value().to_string());
+        }
+        let charge = (self.charge && atom.charge != 0).then(|| charge_label(atom.charge));
+        match (name.is_empty(), charge) {
+            (true, None) => None,
+            (true, Some(charge)) => Some(charge),
+            (false, None) => Some(name),
+            (false, Some(charge)) => Some(format!("{name} {charge}")),
+        }
+    }
+
+    /// Every label with the atom position it belongs at.
+    pub fn labels(&self, molecule: &Molecule) -> Vec<(Vec3, String)> {
+        if !self.is_enabled() {
+            return Vec::new();
+        }
+        molecule
+            .atoms_in_order()
+            .filter_map(|atom| Some((Vec3::from_array(atom.position), self.text(atom)?)))
+            .collect()
+    }
+}
+
+/// A formal charge as chemists write it: `+`, `2−`.
+pub fn charge_label(charge: i32) -> String {
+    let sign = if charge > 0 { '+' } else { '−' };
+    match charge.unsigned_abs() {
+        0 => "0".to_string(),
+        1 => sign.to_string(),
+        size => format!("{size}{sign}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AtomId;
+
+    #[test]
+    fn labels_combine_the_chosen_parts() {
+        let mut molecule = Molecule::new("ammonium");
+        let n = molecule.insert_atom("N".into(), [0.0; 3]);
+        let h = molecule.insert_atom("H".into(), [1.0, 0.0, 0.0]);
+        molecule.set_formal_charge(n, 1);
+        let atom = |id: AtomId| molecule.get_atom(id).unwrap();
+
+        let everything = AtomLabels {
+            element: true,
+            index: true,
+            charge: true,
+        };
+        assert_eq!(
+            everything.text(atom(n)).unwrap(),
+            format!("N{} +", n.value())
+        );
+        assert_eq!(everything.text(atom(h)).unwrap(), format!("H{}", h.value()));
+
+        let charges = AtomLabels {
+            charge: true,
+            ..AtomLabels::default()
+        };
+        assert_eq!(charges.labels(&molecule), [(Vec3::ZERO, "+".to_string())]);
+        assert!(AtomLabels::default().labels(&molecule).is_empty());
+
+        assert_eq!(charge_label(-2), "2−");
+        assert_eq!(charge_label(-1), "−");
+        assert_eq!(charge_label(3), "3+");
+    }
+}
@@ -1,18 +1,97 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
-
-use glam::Vec3;
+use std::io::{self, BufRead};
+use std::sync::{Arc, OnceLock};
+
+use glam::{Mat4, Vec3};
+
+use crate::events::{MoleculeEvent, Subscribers};
+use crate::formats::extxyz;
+use crate::formats::stream::LineReader;
+use crate::formats::text::{self, FieldError, Problem, Recovery};
+use crate::stereo::{BondStereo, Chirality};
+use crate::valence::{StandardValence, ValenceModel, ValenceWarning};
+
+pub mod adjust;
+pub mod animation;
+pub mod annotations;
+pub mod attach;
+pub mod camera;
+pub mod cell;
+pub mod cli;
+pub mod composition;
+pub mod diff;
+pub mod docking;
+pub mod elements;
+pub mod events;
+pub mod figure;
+pub mod formats;
+pub mod fragments;
+pub mod gallery;
+pub mod geometry;
+pub mod gltf;
+pub mod graph;
+pub mod grid;
+pub mod help;
+pub mod html;
+pub mod hydrogens;
+pub mod labels;
+pub mod measure;
+pub mod mesh;
+pub mod notes;
+pub mod obj;
+pub mod optimize;
+pub mod palette;
+pub mod perturb;
+pub mod pick;
+pub mod pocket;
+pub mod postprocess;
+pub mod povray;
+pub mod properties;
+pub mod provenance;
+pub mod query;
+pub mod residues;
+pub mod scene;
+pub mod scripting;
+pub mod selection;
+#[cfg(feature = "serde")]
+mod serde_impl;
+pub mod session;
+pub mod sketch;
+mod spatial;
+pub mod stats;
+pub mod stereo;
+pub mod stl;
+pub mod style;
+pub mod substructure;
+pub mod sweep;
+pub mod trajectory;
+pub mod transaction;
+pub mod transform;
+pub mod valence;
+pub mod watch;
+pub mod zmatrix;
+
+pub use cell::{CoordinateLock, UnitCell};
+pub use properties::AtomProperties;
+pub use transaction::Transaction;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AtomId(u64);
 
 impl AtomId {
+    pub fn new(value: u64) -> Self {
+        Self(value)
+    }
+
     pub fn value(self) -> u64 {
         self.0
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BondId(u64);
 
 impl BondId {
@@ -22,43 +101,97 @@ impl BondId {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Atom {
     pub id: AtomId,
     pub element: String,
     pub position: [f32; 3],
+    /// Formal charge in units of the elementary charge.
+    pub charge: i32,
+    /// Set by [`Molecule::assign_stereo`]; not persisted.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub stereo: Option<Chirality>,
+}
+
+/// Coordination (dative) bonds, e.g. from a ligand to a metal, don't count towards either
+/// atom's valence limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BondKind {
+    #[default]
+    Covalent,
+    Coordination,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bond {
     pub id: BondId,
     pub a: AtomId,
     pub b: AtomId,
+    /// 1 for single, 2 for double, 3 for triple.
+    pub order: u8,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub kind: BondKind,
+    /// Set by [`Molecule::assign_stereo`]; not persisted.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub stereo: Option<BondStereo>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "serde_impl::MoleculeData",
+        try_from = "serde_impl::MoleculeData"
+    )
+)]
 pub struct Molecule {
     pub name: String,
+    pub properties: AtomProperties,
+    /// Facts about the whole structure as text by key, such as the energy on an extended XYZ
+    /// comment line.
+    pub info: BTreeMap<String, String>,
     atoms: HashMap<AtomId, Atom>,
     atom_order: Vec<AtomId>,
     bonds: HashMap<BondId, Bond>,
     valence_counts: HashMap<AtomId, usize>,
+    valence_model: Arc<dyn ValenceModel>,
+    cell: Option<UnitCell>,
     next_atom_id: u64,
     next_bond_id: u64,
+    subscribers: Subscribers,
+    /// Positions in `atom_order` for [`Molecule::nearest_atom`] and
+    /// [`Molecule::atoms_within`]; built on first use and dropped when atoms change.
+    atom_index: OnceLock<spatial::KdTree>,
 }
 
 impl Molecule {
     pub fn new(name: impl Into<String>) -> Self {
         Self {
             name: name.into(),
+            properties: AtomProperties::default(),
+            info: BTreeMap::new(),
             atoms: HashMap::new(),
             atom_order: Vec::new(),
             bonds: HashMap::new(),
             valence_counts: HashMap::new(),
+            valence_model: Arc::new(StandardValence),
+            cell: None,
             next_atom_id: 1,
             next_bond_id: 1,
+            subscribers: Subscribers::default(),
+            atom_index: OnceLock::new(),
         }
     }
 
+    /// Receives a [`MoleculeEvent`] for every later change to this molecule. Clones do not
+    /// share subscriptions; dropping the receiver ends it.
+    pub fn subscribe(&mut self) -> std::sync::mpsc::Receiver<MoleculeEvent> {
+        self.subscribers.subscribe()
+    }
+
     pub fn atom_count(&self) -> usize {
         self.atoms.len()
     }
@@ -71,10 +204,51 @@ impl Molecule {
         self.atom_order.clone()
     }
 
+    /// Id the next `insert_atom` will assign.
+    pub fn next_atom_id(&self) -> AtomId {
+        AtomId(self.next_atom_id)
+    }
+
     pub fn get_atom(&self, id: AtomId) -> Option<&Atom> {
         self.atoms.get(&id)
     }
 
+    pub fn cell(&self) -> Option<&UnitCell> {
+        self.cell.as_ref()
+    }
+
+    pub fn set_cell(&mut self, cell: Option<UnitCell>) -> Option<UnitCell> {
+        let previous = std::mem::replace(&mut self.cell, cell);
+        self.subscribers.emit(MoleculeEvent::CellChanged);
+        previous
+    }
+
+    pub fn valence_model(&self) -> &dyn ValenceModel {
+        self.valence_model.as_ref()
+    }
+
+    /// Only bonds added from now on are checked against `model`; existing ones stay.
+    pub fn set_valence_model(&mut self, model: Arc<dyn ValenceModel>) -> Arc<dyn ValenceModel> {
+        std::mem::replace(&mut self.valence_model, model)
+    }
+
+    /// Atoms whose covalent bond orders add up to more than the valence model allows, in
+    /// atom order.
+    pub fn valence_warnings(&self) -> Vec<ValenceWarning> {
+        self.atoms_in_order()
+            .filter_map(|atom| {
+                let max = self.valence_model.max_bonds(atom)?;
+                let bonds = self.valence_counts.get(&atom.id).copied().unwrap_or(0);
+                (bonds > max).then(|| ValenceWarning {
+                    atom: atom.id,
+                    element: atom.element.clone(),
+                    bonds,
+                    max,
+                })
+            })
+            .collect()
+    }
+
     pub fn insert_atom(&mut self, element: String, position: [f32; 3]) -> AtomId {
         let id = AtomId(self.next_atom_id);
         self.next_atom_id += 1;
@@ -82,10 +256,14 @@ impl Molecule {
             id,
             element,
             position,
+            charge: 0,
+            stereo: None,
         };
         self.atoms.insert(id, atom);
         self.atom_order.push(id);
+        self.atom_index.take();
         self.valence_counts.insert(id, 0);
+        self.subscribers.emit(MoleculeEvent::AtomAdded(id));
         id
     }
 
@@ -101,6 +279,8 @@ impl Molecule {
             id,
             element,
             position,
+            charge: 0,
+            stereo: None,
         };
         self.atoms.insert(id, atom);
         if let Some(index) = order_index {
@@ -109,7 +289,9 @@ impl Molecule {
         } else {
             self.atom_order.push(id);
         }
+        self.atom_index.take();
         self.valence_counts.entry(id).or_insert(0);
+        self.subscribers.emit(MoleculeEvent::AtomAdded(id));
         id
     }
 
@@ -123,6 +305,7 @@ impl Molecule {
         if order_index < self.atom_order.len() {
             self.atom_order.remove(order_index);
         }
+        self.atom_index.take();
         let bonds: Vec<Bond> = self
             .bonds
             .values()
@@ -131,10 +314,14 @@ impl Molecule {
             .collect();
         for bond in &bonds {
             self.bonds.remove(&bond.id);
-            self.decrement_valence(bond.a);
-            self.decrement_valence(bond.b);
+            if bond.kind == BondKind::Covalent {
+                self.remove_valence(bond.a, bond.order);
+                self.remove_valence(bond.b, bond.order);
+            }
+            self.subscribers.emit(MoleculeEvent::BondRemoved(bond.id));
         }
         self.valence_counts.remove(&id);
+        self.subscribers.emit(MoleculeEvent::AtomRemoved(id));
         Some(RemovedAtom {
             atom,
             order_index,
@@ -145,23 +332,35 @@ impl Molecule {
     pub fn set_atom_position(&mut self, id: AtomId, position: [f32; 3]) -> Option<()> {
         let atom = self.atoms.get_mut(&id)?;
         atom.position = position;
+        self.atom_index.take();
+        self.subscribers.emit(MoleculeEvent::AtomMoved(id));
+        Some(())
+    }
+
+    /// Changes an atom's element in place and returns the old one.
+    pub fn set_element(&mut self, id: AtomId, element: String) -> Option<String> {
+        let atom = self.atoms.get_mut(&id)?;
+        let previous = std::mem::replace(&mut atom.element, element);
+        self.subscribers.emit(MoleculeEvent::AtomChanged(id));
+        Some(previous)
+    }
+
+    pub fn set_formal_charge(&mut self, id: AtomId, charge: i32) -> Option<()> {
+        let atom = self.atoms.get_mut(&id)?;
+        atom.charge = charge;
+        self.subscribers.emit(MoleculeEvent::AtomChanged(id));
         Some(())
     }
 
     pub fn add_bond(&mut self, a: AtomId, b: AtomId) -> Result<BondId, String> {
-        self.ensure_atoms_exist(a, b)?;
-        if self.bond_between(a, b).is_some() {
-            return Err("bond already exists".to_string());
-        }
-        self.ensure_valence_available(a)?;
-        self.ensure_valence_available(b)?;
         let id = BondId(self.next_bond_id);
-        self.next_bond_id += 1;
-        let bond = Bond { id, a, b };
-        self.bonds.insert(id, bond);
-        self.increment_valence(a);
-        self.increment_valence(b);
-        Ok(id)
+        self.insert_bond_of_kind(id, a, b, BondKind::Covalent, 1)
+    }
+
+    /// Adds a [`BondKind::Coordination`] bond, which no valence limit blocks.
+    pub fn add_coordination_bond(&mut self, a: AtomId, b: AtomId) -> Result<BondId, String> {
+        let id = BondId(self.next_bond_id);
+        self.insert_bond_of_kind(id, a, b, BondKind::Coordination, 1)
     }
 
     pub fn insert_bond_with_id(
@@ -169,28 +368,88 @@ impl Molecule {
         id: BondId,
         a: AtomId,
         b: AtomId,
+    ) -> Result<BondId, String> {
+        self.insert_bond_of_kind(id, a, b, BondKind::Covalent, 1)
+    }
+
+    /// Puts back a copy of `bond` with its id, order and kind, e.g. when undoing its removal.
+    pub fn restore_bond(&mut self, bond: &Bond) -> Result<BondId, String> {
+        self.insert_bond_of_kind(bond.id, bond.a, bond.b, bond.kind, bond.order)
+    }
+
+    /// A covalent bond takes `order` of both atoms' valence.
+    fn insert_bond_of_kind(
+        &mut self,
+        id: BondId,
+        a: AtomId,
+        b: AtomId,
+        kind: BondKind,
+        order: u8,
     ) -> Result<BondId, String> {
         self.ensure_atoms_exist(a, b)?;
         self.next_bond_id = self.next_bond_id.max(id.0 + 1);
         if self.bond_between(a, b).is_some() {
             return Err("bond already exists".to_string());
         }
-        self.ensure_valence_available(a)?;
-        self.ensure_valence_available(b)?;
-        let bond = Bond { id, a, b };
+        if kind == BondKind::Covalent {
+            self.ensure_valence_available(a, order)?;
+            self.ensure_valence_available(b, order)?;
+            self.add_valence(a, order);
+            self.add_valence(b, order);
+        }
+        let bond = Bond {
+            id,
+            a,
+            b,
+            order,
+            kind,
+            stereo: None,
+        };
         self.bonds.insert(id, bond);
-        self.increment_valence(a);
-        self.increment_valence(b);
+        self.subscribers.emit(MoleculeEvent::BondAdded(id));
         Ok(id)
     }
 
     pub fn remove_bond(&mut self, id: BondId) -> Option<Bond> {
         let bond = self.bonds.remove(&id)?;
-        self.decrement_valence(bond.a);
-        self.decrement_valence(bond.b);
+        if bond.kind == BondKind::Covalent {
+            self.remove_valence(bond.a, bond.order);
+            self.remove_valence(bond.b, bond.order);
+        }
+        self.subscribers.emit(MoleculeEvent::BondRemoved(id));
         Some(bond)
     }
 
+    /// Turning a bond covalent checks both atoms' valence like adding it would. Returns the
+    /// previous kind.
+    pub fn set_bond_kind(&mut self, id: BondId, kind: BondKind) -> Result<BondKind, String> {
+        let bond = self
+            .bonds
+            .get(&id)
+            .ok_or_else(|| "bond not found".to_string())?;
+        let (a, b, order, previous) = (bond.a, bond.b, bond.order, bond.kind);
+        if previous == kind {
+            return Ok(previous);
+        }
+        match kind {
+            BondKind::Covalent => {
+                self.ensure_valence_available(a, order)?;
+                self.ensure_valence_available(b, order)?;
+                self.add_valence(a, order);
+                self.add_valence(b, order);
+            }
+            BondKind::Coordination => {
+                self.remove_valence(a, order);
+                self.remove_valence(b, order);
+            }
+        }
+        if let Some(bond) = self.bonds.get_mut(&id) {
+            bond.kind = kind;
+        }
+        self.subscribers.emit(MoleculeEvent::BondChanged(id));
+        Ok(previous)
+    }
+
     pub fn bond_between(&self, a: AtomId, b: AtomId) -> Option<BondId> {
         self.bonds
             .values()
@@ -198,10 +457,240 @@ impl Molecule {
             .map(|bond| bond.id)
     }
 
+    /// Sets the order without checking valence; see [`Molecule::check_bond_order`].
+    pub fn set_bond_order(&mut self, id: BondId, order: u8) -> Option<()> {
+        let bond = self.bonds.get_mut(&id)?;
+        let (a, b, previous) = (bond.a, bond.b, bond.order);
+        bond.order = order;
+        if bond.kind == BondKind::Covalent {
+            self.remove_valence(a, previous);
+            self.remove_valence(b, previous);
+            self.add_valence(a, order);
+            self.add_valence(b, order);
+        }
+        self.subscribers.emit(MoleculeEvent::BondChanged(id));
+        Some(())
+    }
+
     pub fn bonds(&self) -> impl Iterator<Item = &Bond> {
         self.bonds.values()
     }
 
+    pub fn get_bond(&self, id: BondId) -> Option<&Bond> {
+        self.bonds.get(&id)
+    }
+
+    pub fn merge(&mut self, other: &Molecule) -> IdMapping {
+        let mut mapping = IdMapping::default();
+        for atom in other.atoms_in_order() {
+            let id = self.insert_atom(atom.element.clone(), atom.position);
+            self.set_formal_charge(id, atom.charge);
+            mapping.atoms.insert(atom.id, id);
+        }
+        for bond in other.sorted_bonds() {
+            let (Some(&a), Some(&b)) = (mapping.atoms.get(&bond.a), mapping.atoms.get(&bond.b))
+            else {
+                continue;
+            };
+            let id = BondId(self.next_bond_id);
+            if let Ok(id) = self.insert_bond_of_kind(id, a, b, bond.kind, bond.order) {
+                mapping.bonds.insert(bond.id, id);
+            }
+        }
+        mapping
+    }
+
+    /// Hill-order formula: C first, then H, then the rest alphabetically; without carbon all
+    /// elements are alphabetical.
+    pub fn formula(&self) -> String {
+        self.element_counts()
+            .into_iter()
+            .map(|(symbol, count)| match count {
+                1 => symbol,
+                count => format!("{symbol}{count}"),
+            })
+            .collect()
+    }
+
+    /// Number of atoms of each element, in the Hill order used by [`Molecule::formula`].
+    pub fn element_counts(&self) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for atom in self.atoms.values() {
+            *counts
+                .entry(elements::normalize_symbol(&atom.element))
+                .or_default() += 1;
+        }
+        let has_carbon = counts.contains_key("C");
+        let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+        counts.sort_by_key(|(symbol, _)| {
+            let rank = match symbol.as_str() {
+                "C" if has_carbon => 0,
+                "H" if has_carbon => 1,
+                _ => 2,
+            };
+            (rank, symbol.clone())
+        });
+        counts
+    }
+
+    /// Sum of standard atomic weights in g/mol; unknown elements contribute nothing.
+    pub fn molecular_weight(&self) -> f32 {
+        self.atoms
+            .values()
+            .filter_map(|atom| elements::atomic_mass(&atom.element))
+            .sum()
+    }
+
+    /// Pairwise distances with rows and columns in `atoms_in_order` order.
+    pub fn distance_matrix(&self) -> Vec<Vec<f32>> {
+        let positions: Vec<Vec3> = self
+            .atoms_in_order()
+            .map(|atom| Vec3::from_array(atom.position))
+            .collect();
+        positions
+            .iter()
+            .map(|a| positions.iter().map(|b| a.distance(*b)).collect())
+            .collect()
+    }
+
+    /// Atom pairs no further apart than `cutoff`, each reported once with the smaller id first.
+    pub fn contacts_within(&self, cutoff: f32) -> Vec<(AtomId, AtomId)> {
+        if cutoff < 0.0 {
+            return Vec::new();
+        }
+        let atoms: Vec<&Atom> = self.atoms_in_order().collect();
+        let positions: Vec<Vec3> = atoms
+            .iter()
+            .map(|atom| Vec3::from_array(atom.position))
+            .collect();
+        let grid = spatial::SpatialGrid::new(&positions, cutoff);
+        let cutoff_sq = cutoff * cutoff;
+        let mut contacts = Vec::new();
+        for (i, position) in positions.iter().enumerate() {
+            for j in grid.candidates(*position) {
+                if j > i && position.distance_squared(positions[j]) <= cutoff_sq {
+                    let (a, b) = (atoms[i].id, atoms[j].id);
+                    contacts.push((a.min(b), a.max(b)));
+                }
+            }
+        }
+        contacts.sort();
+        contacts
+    }
+
+    /// Atom closest to `point`, or `None` for an empty molecule. Queries share a k-d tree
+    /// built on first use, so many of them between edits cost little each.
+    pub fn nearest_atom(&self, point: [f32; 3]) -> Option<AtomId> {
+        let index = self.atom_index().nearest(Vec3::from_array(point))?;
+        self.atom_order.get(index).copied()
+    }
+
+    /// Atoms no further than `radius` from `point`, in atom order.
+    pub fn atoms_within(&self, point: [f32; 3], radius: f32) -> Vec<AtomId> {
+        let mut found = self.atom_index().within(Vec3::from_array(point), radius);
+        found.sort_unstable();
+        found
+            .into_iter()
+            .filter_map(|index| self.atom_order.get(index).copied())
+            .collect()
+    }
+
+    fn atom_index(&self) -> &spatial::KdTree {
+        self.atom_index.get_or_init(|| {
+            let positions: Vec<Vec3> = self
+                .atoms_in_order()
+                .map(|atom| Vec3::from_array(atom.position))
+                .collect();
+            spatial::KdTree::new(&positions)
+        })
+    }
+
+    pub fn extract(&self, atoms: &[AtomId]) -> Molecule {
+        let selected: HashSet<AtomId> = atoms.iter().copied().collect();
+        let mut extracted = Molecule::new(self.name.clone());
+        extracted.cell = self.cell;
+        extracted.valence_model = Arc::clone(&self.valence_model);
+        let mut mapping = HashMap::new();
+        for atom in self.atoms_in_order() {
+            if selected.contains(&atom.id) {
+                let id = extracted.insert_atom(atom.element.clone(), atom.position);
+                extracted.set_formal_charge(id, atom.charge);
+                mapping.insert(atom.id, id);
+            }
+        }
+        extracted.properties = self.properties.clone();
+        extracted.properties.remap(&mapping);
+        for bond in self.sorted_bonds() {
+            if let (Some(&a), Some(&b)) = (mapping.get(&bond.a), mapping.get(&bond.b)) {
+                let id = BondId(extracted.next_bond_id);
+                let _ = extracted.insert_bond_of_kind(id, a, b, bond.kind, bond.order);
+            }
+        }
+        extracted
+    }
+
+    /// Reassigns contiguous ids (atoms in `atom_order`, bonds by previous id) starting at 1.
+    /// Ids held elsewhere, such as in a `CommandHistory`, are invalidated.
+    pub fn renumber(&mut self) -> IdMapping {
+        let mut mapping = IdMapping::default();
+        let mut atoms = HashMap::with_capacity(self.atoms.len());
+        let mut valence_counts = HashMap::with_capacity(self.atoms.len());
+        for (index, old_id) in self.atom_order.iter().enumerate() {
+            let new_id = AtomId(index as u64 + 1);
+            if let Some(mut atom) = self.atoms.remove(old_id) {
+                atom.id = new_id;
+                atoms.insert(new_id, atom);
+            }
+            let valence = self.valence_counts.get(old_id).copied().unwrap_or(0);
+            valence_counts.insert(new_id, valence);
+            mapping.atoms.insert(*old_id, new_id);
+        }
+        let mut bond_ids: Vec<BondId> = self.bonds.keys().copied().collect();
+        bond_ids.sort();
+        let mut bonds = HashMap::with_capacity(bond_ids.len());
+        for (index, old_id) in bond_ids.into_iter().enumerate() {
+            let new_id = BondId(index as u64 + 1);
+            if let Some(bond) = self.bonds.remove(&old_id) {
+                let bond = Bond {
+                    id: new_id,
+                    a: mapping.atoms[&bond.a],
+                    b: mapping.atoms[&bond.b],
+                    order: bond.order,
+                    kind: bond.kind,
+                    stereo: bond.stereo,
+                };
+                bonds.insert(new_id, bond);
+            }
+            mapping.bonds.insert(old_id, new_id);
+        }
+        self.atom_order = (1..=atoms.len() as u64).map(AtomId).collect();
+        self.atom_index.take();
+        self.next_atom_id = atoms.len() as u64 + 1;
+        self.next_bond_id = bonds.len() as u64 + 1;
+        self.atoms = atoms;
+        self.bonds = bonds;
+        self.valence_counts = valence_counts;
+        self.properties.remap(&mapping.atoms);
+        self.subscribers.emit(MoleculeEvent::Reset);
+        mapping
+    }
+
+    /// Swaps in a copy of `other`, keeping this molecule's subscribers, and returns what was
+    /// here before.
+    fn replace_with(&mut self, other: &Molecule) -> Molecule {
+        let subscribers = std::mem::take(&mut self.subscribers);
+        let previous = std::mem::replace(self, other.clone());
+        self.subscribers = subscribers;
+        self.subscribers.emit(MoleculeEvent::Reset);
+        previous
+    }
+
+    fn sorted_bonds(&self) -> Vec<&Bond> {
+        let mut bonds: Vec<&Bond> = self.bonds.values().collect();
+        bonds.sort_by_key(|bond| bond.id);
+        bonds
+    }
+
     fn ensure_atoms_exist(&self, a: AtomId, b: AtomId) -> Result<(), String> {
         if !self.atoms.contains_key(&a) || !self.atoms.contains_key(&b) {
             return Err("atom does not exist".to_string());
@@ -209,14 +698,20 @@ impl Molecule {
         Ok(())
     }
 
-    fn ensure_valence_available(&self, atom_id: AtomId) -> Result<(), String> {
+    /// Whether `atom_id` has `extra` valence left beyond its covalent bond orders.
+    fn ensure_valence_available(&self, atom_id: AtomId, extra: u8) -> Result<(), String> {
         let atom = self
             .atoms
             .get(&atom_id)
             .ok_or_else(|| "atom does not exist".to_string())?;
-        let max_valence = max_valence(&atom.element);
+        let Some(max_valence) = self.valence_model.max_bonds(atom) else {
+            return Ok(());
+        };
+        if self.valence_model.permissive() {
+            return Ok(());
+        }
         let current = self.valence_counts.get(&atom_id).copied().unwrap_or(0);
-        if current + 1 > max_valence {
+        if current + usize::from(extra) > max_valence {
             return Err(format!(
                 "valence exceeded for {} (max {})",
                 atom.element, max_valence
@@ -225,19 +720,45 @@ impl Molecule {
         Ok(())
     }
 
-    fn increment_valence(&mut self, atom_id: AtomId) {
+    /// Whether giving bond `id` order `order` keeps both its atoms within the valence model,
+    /// counting each covalent bond by its order. Lowering an order is always allowed.
+    pub fn check_bond_order(&self, id: BondId, order: u8) -> Result<(), String> {
+        let bond = self
+            .bonds
+            .get(&id)
+            .ok_or_else(|| "bond not found".to_string())?;
+        if order <= bond.order
+            || bond.kind == BondKind::Coordination
+            || self.valence_model.permissive()
+        {
+            return Ok(());
+        }
+        let extra = order - bond.order;
+        self.ensure_valence_available(bond.a, extra)?;
+        self.ensure_valence_available(bond.b, extra)
+    }
+
+    fn add_valence(&mut self, atom_id: AtomId, order: u8) {
         let entry = self.valence_counts.entry(atom_id).or_insert(0);
-        *entry += 1;
+        *entry += usize::from(order);
     }
 
-    fn decrement_valence(&mut self, atom_id: AtomId) {
+    fn remove_valence(&mut self, atom_id: AtomId, order: u8) {
         if let Some(entry) = self.valence_counts.get_mut(&atom_id) {
-            *entry = entry.saturating_sub(1);
+            *entry = entry.saturating_sub(usize::from(order));
         }
     }
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IdMapping {
+    pub atoms: HashMap<AtomId, AtomId>,
+    pub bonds: HashMap<BondId, BondId>,
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RemovedAtom {
     pub atom: Atom,
     pub order_index: usize,
@@ -245,6 +766,7 @@ pub struct RemovedAtom {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Command {
     InsertAtom {
         element: String,
@@ -265,11 +787,58 @@ pub enum Command {
         bond_id: BondId,
         removed: Option<Bond>,
     },
+    /// Adds a [`BondKind::Coordination`] bond.
+    AddCoordinationBond {
+        atom_a: AtomId,
+        atom_b: AtomId,
+        bond_id: Option<BondId>,
+    },
+    SetBondKind {
+        bond_id: BondId,
+        kind: BondKind,
+        previous: Option<BondKind>,
+    },
+    /// Sets a bond to single, double or triple.
+    SetBondOrder {
+        bond_id: BondId,
+        order: u8,
+        previous: Option<u8>,
+    },
     MoveAtom {
         atom_id: AtomId,
         from: [f32; 3],
         to: [f32; 3],
     },
+    /// Rotates, translates or reflects `ids` by `matrix` as one step. Unlike moves, successive
+    /// transforms stay separate history entries.
+    TransformAtoms {
+        ids: Vec<AtomId>,
+        matrix: Mat4,
+        previous: Option<Vec<[f32; 3]>>,
+    },
+    /// Sets one per-atom property value, or clears it when `value` is `None`.
+    SetProperty {
+        atom_id: AtomId,
+        name: String,
+        value: Option<String>,
+        previous: Option<Option<String>>,
+    },
+    Merge {
+        fragment: Box<Molecule>,
+        mapping: Option<IdMapping>,
+    },
+    SetCell {
+        cell: Option<UnitCell>,
+        lock: CoordinateLock,
+        previous: Option<Option<UnitCell>>,
+        moved: Vec<(AtomId, [f32; 3])>,
+    },
+    Restore {
+        snapshot: Box<Molecule>,
+        previous: Option<Box<Molecule>>,
+    },
+    /// Applies in order and undoes in reverse as one history entry.
+    Composite { commands: Vec<Command> },
 }
 
 impl Command {
@@ -324,12 +893,159 @@ impl Command {
                 *removed = Some(bond);
                 Ok(())
             }
+            Command::AddCoordinationBond {
+                atom_a,
+                atom_b,
+                bond_id,
+            } => {
+                let id = bond_id.unwrap_or(BondId(molecule.next_bond_id));
+                let kind = BondKind::Coordination;
+                *bond_id = Some(molecule.insert_bond_of_kind(id, *atom_a, *atom_b, kind, 1)?);
+                Ok(())
+            }
+            Command::SetBondKind {
+                bond_id,
+                kind,
+                previous,
+            } => {
+                *previous = Some(molecule.set_bond_kind(*bond_id, *kind)?);
+                Ok(())
+            }
+            Command::SetBondOrder {
+                bond_id,
+                order,
+                previous,
+            } => {
+                if !(1..=3).contains(order) {
+                    return Err(format!("bond order {order} is not 1, 2 or 3"));
+                }
+                molecule.check_bond_order(*bond_id, *order)?;
+                let bond = molecule
+                    .bonds
+                    .get(bond_id)
+                    .ok_or_else(|| "bond not found".to_string())?;
+                *previous = Some(bond.order);
+                molecule.set_bond_order(*bond_id, *order);
+                Ok(())
+            }
             Command::MoveAtom { atom_id, to, .. } => {
                 molecule
                     .set_atom_position(*atom_id, *to)
                     .ok_or_else(|| "atom not found".to_string())?;
                 Ok(())
             }
+            Command::TransformAtoms {
+                ids,
+                matrix,
+                previous,
+            } => {
+                if !transform::is_rigid(matrix) {
+                    return Err("transform is not rigid".to_string());
+                }
+                let from = ids
+                    .iter()
+                    .map(|id| molecule.get_atom(*id).map(|atom| atom.position))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| "atom not found".to_string())?;
+                for (id, position) in ids.iter().zip(&from) {
+                    let to = matrix.transform_point3(Vec3::from_array(*position));
+                    molecule.set_atom_position(*id, to.to_array());
+                }
+                *previous = Some(from);
+                Ok(())
+            }
+            Command::SetProperty {
+                atom_id,
+                name,
+                value,
+                previous,
+            } => {
+                if molecule.get_atom(*atom_id).is_none() {
+                    return Err("atom not found".to_string());
+                }
+                *previous = Some(match value {
+                    Some(value) => {
+                        let old = molecule.properties.get(name, *atom_id).map(str::to_string);
+                        molecule.properties.set(name, *atom_id, value.clone());
+                        old
+                    }
+                    None => molecule.properties.unset(name, *atom_id),
+                });
+                Ok(())
+            }
+            Command::Merge { fragment, mapping } => {
+                if let Some(mapping) = mapping {
+                    for atom in fragment.atoms_in_order() {
+                        let id = mapping
+                            .atoms
+                            .get(&atom.id)
+                            .copied()
+                            .ok_or_else(|| "missing redo data".to_string())?;
+                        molecule.insert_atom_with_id(id, atom.element.clone(), atom.position, None);
+                        molecule.set_formal_charge(id, atom.charge);
+                    }
+                    for bond in fragment.sorted_bonds() {
+                        let (Some(&id), Some(&a), Some(&b)) = (
+                            mapping.bonds.get(&bond.id),
+                            mapping.atoms.get(&bond.a),
+                            mapping.atoms.get(&bond.b),
+                        ) else {
+                            continue;
+                        };
+                        molecule.restore_bond(&Bond {
+                            id,
+                            a,
+                            b,
+                            ..bond.clone()
+                        })?;
+                    }
+                } else {
+                    *mapping = Some(molecule.merge(fragment));
+                }
+                Ok(())
+            }
+            Command::SetCell {
+                cell,
+                lock,
+                previous,
+                moved,
+            } => {
+                let old_cell = molecule.set_cell(*cell);
+                moved.clear();
+                if let (CoordinateLock::Fractional, Some(old_cell), Some(new_cell)) =
+                    (*lock, old_cell, *cell)
+                {
+                    let transform = new_cell.matrix() * old_cell.matrix().inverse();
+                    for id in molecule.atom_ids() {
+                        let Some(atom) = molecule.get_atom(id) else {
+                            continue;
+                        };
+                        let from = atom.position;
+                        let to = transform * Vec3::from_array(from);
+                        molecule.set_atom_position(id, to.to_array());
+                        moved.push((id, from));
+                    }
+                }
+                *previous = Some(old_cell);
+                Ok(())
+            }
+            Command::Restore { snapshot, previous } => {
+                let replaced = molecule.replace_with(snapshot);
+                *previous = Some(Box::new(replaced));
+                Ok(())
+            }
+            Command::Composite { commands } => {
+                for index in 0..commands.len() {
+                    if let Err(err) = commands[index].apply(molecule) {
+                        // Leave the molecule as it was before the first step.
+                        for applied in commands[..index].iter_mut().rev() {
+                            applied.undo(molecule)?;
+                        }
+                        return Err(err);
+                    }
+                }
+                Ok(())
+            }
         }
     }
 
@@ -354,25 +1070,48 @@ impl Command {
                     removed.atom.position,
                     Some(removed.order_index),
                 );
-                for bond in removed.bonds {
-                    molecule.insert_bond_with_id(bond.id, bond.a, bond.b)?;
+                molecule.set_formal_charge(removed.atom.id, removed.atom.charge);
+                for bond in &removed.bonds {
+                    molecule.restore_bond(bond)?;
                 }
                 Ok(())
             }
             Command::AddBond {
                 bond_id: Some(bond_id),
                 ..
+            }
+            | Command::AddCoordinationBond {
+                bond_id: Some(bond_id),
+                ..
             } => {
                 molecule
                     .remove_bond(*bond_id)
                     .ok_or_else(|| "bond not found".to_string())?;
                 Ok(())
             }
+            Command::SetBondKind {
+                bond_id,
+                previous: Some(previous),
+                ..
+            } => {
+                molecule.set_bond_kind(*bond_id, *previous)?;
+                Ok(())
+            }
+            Command::SetBondOrder {
+                bond_id,
+                previous: Some(previous),
+                ..
+            } => {
+                molecule
+                    .set_bond_order(*bond_id, *previous)
+                    .ok_or_else(|| "bond not found".to_string())?;
+                Ok(())
+            }
             Command::RemoveBond { removed, .. } => {
                 let bond = removed
                     .clone()
                     .ok_or_else(|| "missing undo data".to_string())?;
-                molecule.insert_bond_with_id(bond.id, bond.a, bond.b)?;
+                molecule.restore_bond(&bond)?;
                 Ok(())
             }
             Command::MoveAtom { atom_id, from, .. } => {
@@ -381,6 +1120,67 @@ impl Command {
                     .ok_or_else(|| "atom not found".to_string())?;
                 Ok(())
             }
+            Command::TransformAtoms {
+                ids,
+                previous: Some(previous),
+                ..
+            } => {
+                for (id, position) in ids.iter().zip(previous) {
+                    molecule
+                        .set_atom_position(*id, *position)
+                        .ok_or_else(|| "atom not found".to_string())?;
+                }
+                Ok(())
+            }
+            Command::SetProperty {
+                atom_id,
+                name,
+                previous: Some(previous),
+                ..
+            } => {
+                match previous {
+                    Some(value) => molecule.properties.set(name, *atom_id, value.clone()),
+                    None => {
+                        molecule.properties.unset(name, *atom_id);
+                    }
+                }
+                Ok(())
+            }
+            Command::Merge {
+                mapping: Some(mapping),
+                ..
+            } => {
+                for atom_id in mapping.atoms.values() {
+                    molecule.remove_atom(*atom_id);
+                }
+                Ok(())
+            }
+            Command::SetCell {
+                previous: Some(previous),
+                moved,
+                ..
+            } => {
+                for (id, position) in moved.iter() {
+                    molecule
+                        .set_atom_position(*id, *position)
+                        .ok_or_else(|| "atom not found".to_string())?;
+                }
+                molecule.set_cell(*previous);
+                Ok(())
+            }
+            Command::Restore {
+                previous: Some(previous),
+                ..
+            } => {
+                molecule.replace_with(previous);
+                Ok(())
+            }
+            Command::Composite { commands } => {
+                for command in commands.iter_mut().rev() {
+                    command.undo(molecule)?;
+                }
+                Ok(())
+            }
             _ => Err("command missing data".to_string()),
         }
     }
@@ -398,16 +1198,40 @@ impl Command {
                 *a_to = *to;
                 true
             }
+            // A group move continues when it moves the same atoms.
+            (Command::Composite { commands: ours }, Command::Composite { commands: theirs })
+                if !ours.is_empty()
+                    && ours.iter().all(|command| moved_atom(command).is_some())
+                    && ours
+                        .iter()
+                        .map(moved_atom)
+                        .eq(theirs.iter().map(moved_atom)) =>
+            {
+                for (command, next) in ours.iter_mut().zip(theirs) {
+                    command.merge_with(next);
+                }
+                true
+            }
             _ => false,
         }
     }
 }
 
+/// The atom a [`Command::MoveAtom`] moves.
+fn moved_atom(command: &Command) -> Option<AtomId> {
+    match command {
+        Command::MoveAtom { atom_id, .. } => Some(*atom_id),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CommandHistory {
     undo: Vec<Command>,
     redo: Vec<Command>,
     capacity: usize,
+    /// Undo depth at which the molecule was last saved, `None` once that state is out of reach.
+    saved: Option<usize>,
 }
 
 impl CommandHistory {
@@ -416,6 +1240,7 @@ impl CommandHistory {
             undo: Vec::new(),
             redo: Vec::new(),
             capacity: capacity.max(1),
+            saved: Some(0),
         }
     }
 
@@ -426,14 +1251,22 @@ impl CommandHistory {
     ) -> Result<Command, String> {
         command.apply(molecule)?;
         self.redo.clear();
+        if self.saved.is_some_and(|depth| depth > self.undo.len()) {
+            self.saved = None;
+        }
+        let depth = self.undo.len();
         if let Some(last) = self.undo.last_mut() {
             if last.merge_with(&command) {
+                if self.saved == Some(depth) {
+                    self.saved = None;
+                }
                 return Ok(last.clone());
             }
         }
         self.undo.push(command.clone());
         if self.undo.len() > self.capacity {
             self.undo.remove(0);
+            self.saved = self.saved.and_then(|depth| depth.checked_sub(1));
         }
         Ok(command)
     }
@@ -463,6 +1296,28 @@ impl CommandHistory {
     pub fn can_redo(&self) -> bool {
         !self.redo.is_empty()
     }
+
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Records the current state as the one on disk.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.undo.len());
+    }
+
+    /// Whether the molecule differs from the state last marked saved (or the start).
+    pub fn is_dirty(&self) -> bool {
+        self.saved != Some(self.undo.len())
+    }
+
+    pub fn rewound(&self, molecule: &Molecule, steps: usize) -> Result<Molecule, String> {
+        let mut earlier = molecule.clone();
+        for command in self.undo.iter().rev().take(steps) {
+            command.clone().undo(&mut earlier)?;
+        }
+        Ok(earlier)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -486,54 +1341,260 @@ impl fmt::Display for XyzError {
 
 impl std::error::Error for XyzError {}
 
+impl From<FieldError> for XyzError {
+    fn from(err: FieldError) -> Self {
+        Self::new(err.to_string())
+    }
+}
+
+impl From<io::Error> for XyzError {
+    fn from(err: io::Error) -> Self {
+        Self::new(err.to_string())
+    }
+}
+
+/// Which frames of a multi-frame XYZ file to read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XyzOptions {
+    /// 1-based first frame.
+    pub first_frame: usize,
+    /// 1-based last frame, inclusive; it may lie past the end of the file.
+    pub last_frame: usize,
+}
+
+impl Default for XyzOptions {
+    fn default() -> Self {
+        Self {
+            first_frame: 1,
+            last_frame: 1,
+        }
+    }
+}
+
+/// Per-atom property holding the 1-based frame of each atom when several frames are read.
+pub const XYZ_FRAME_PROPERTY: &str = "frame";
+
+/// The first frame of an XYZ file.
 pub fn parse_xyz(contents: &str) -> Result<Molecule, XyzError> {
-    let mut lines = contents.lines();
+    parse_xyz_with(contents, &XyzOptions::default(), &mut Recovery::strict())
+}
+
+/// Like [`parse_xyz`], but skips atom lines that fail to parse and accepts fewer atoms than
+/// counted, returning what was skipped. An unreadable count reads every remaining line.
+pub fn parse_xyz_lenient(contents: &str) -> Result<(Molecule, Vec<Problem>), XyzError> {
+    let mut recovery = Recovery::lenient();
+    let molecule = parse_xyz_with(contents, &XyzOptions::default(), &mut recovery)?;
+    Ok((molecule, recovery.into_problems()))
+}
+
+/// The frames `options` asks for as one molecule named after the first of them. Frames may
+/// be separated by blank lines.
+pub fn parse_xyz_with(
+    contents: &str,
+    options: &XyzOptions,
+    recovery: &mut Recovery,
+) -> Result<Molecule, XyzError> {
+    read_xyz_with(contents.as_bytes(), options, recovery)
+}
+
+/// Like [`parse_xyz_with`], reading a line at a time so the text is never held in memory.
+pub fn read_xyz_with(
+    reader: impl BufRead,
+    options: &XyzOptions,
+    recovery: &mut Recovery,
+) -> Result<Molecule, XyzError> {
+    if options.first_frame == 0 || options.last_frame < options.first_frame {
+        return Err(XyzError::new(format!(
+            "invalid frame range {}-{}",
+            options.first_frame, options.last_frame
+        )));
+    }
+    let mut lines = LineReader::new(reader);
+    let mut molecule = Molecule::new("");
+    let mut frame = 0;
+    while frame < options.last_frame {
+        if frame > 0 {
+            while lines.peek_line()?.is_some_and(|line| line.is_blank()) {
+                lines.next_line()?;
+            }
+            if lines.peek_line()?.is_none() {
+                break;
+            }
+        }
+        frame += 1;
+        if frame < options.first_frame {
+            skip_xyz_frame(&mut lines)?;
+            continue;
+        }
+        let (header, atoms) = read_xyz_frame(&mut lines, &mut molecule, recovery)?;
+        if frame == options.first_frame {
+            molecule.name = header.name;
+            if header.cell.is_some() {
+                molecule.set_cell(header.cell);
+            }
+            molecule.info.extend(header.info);
+        }
+        if options.last_frame > options.first_frame {
+            for id in atoms {
+                molecule
+                    .properties
+                    .set(XYZ_FRAME_PROPERTY, id, frame.to_string());
+            }
+        }
+    }
+    if frame < options.first_frame {
+        return Err(XyzError::new(format!("the file has only {frame} frames")));
+    }
+    Ok(molecule)
+}
+
+/// Moves past one frame without reading its atoms.
+fn skip_xyz_frame(lines: &mut LineReader<impl BufRead>) -> Result<(), XyzError> {
     let count_line = lines
-        .next()
+        .next_line()?
         .ok_or_else(|| XyzError::new("missing atom count"))?;
     let atom_count: usize = count_line
-        .trim()
-        .parse()
-        .map_err(|_| XyzError::new("invalid atom count"))?;
+        .fields()
+        .expect("atom count")?
+        .parse("atom count")?;
+    for _ in 0..=atom_count {
+        if lines.next_line()?.is_none() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// What the comment line of a frame says: the line itself as the name, or the metadata of an
+/// extended XYZ frame.
+#[derive(Debug, Default)]
+struct XyzHeader {
+    name: String,
+    cell: Option<UnitCell>,
+    info: Vec<(String, String)>,
+}
+
+/// Adds the atoms of one frame to `molecule`, returning its header and the new atoms.
+fn read_xyz_frame(
+    lines: &mut LineReader<impl BufRead>,
+    molecule: &mut Molecule,
+    recovery: &mut Recovery,
+) -> Result<(XyzHeader, Vec<AtomId>), XyzError> {
+    let count_line = lines
+        .next_line()?
+        .ok_or_else(|| XyzError::new("missing atom count"))?;
+    let count_number = count_line.number;
+    let atom_count = count_line
+        .fields()
+        .expect("atom count")
+        .and_then(|field| field.parse::<usize>("atom count"));
+    let atom_count = recovery.skip_bad(count_number, atom_count)?;
 
     let comment_line = lines
-        .next()
+        .next_line()?
         .ok_or_else(|| XyzError::new("missing comment line"))?;
-    let name = comment_line.trim().to_string();
-
-    let mut molecule = Molecule::new(name);
-    for (index, line) in lines.enumerate() {
-        if molecule.atoms.len() >= atom_count {
+    let extended = extxyz::Header::parse(comment_line.text)
+        .map_err(|details| XyzError::new(format!("line {}: {details}", comment_line.number)))?;
+    let comment = comment_line.text.trim().to_string();
+
+    let mut atoms = Vec::new();
+    // A skipped line still counts, so the next frame starts where it should.
+    for _ in 0..atom_count.unwrap_or(usize::MAX) {
+        let Some(line) = lines.next_line()? else {
             break;
+        };
+        if let Some(header) = &extended {
+            let atom = header.read_atom(&line);
+            if let Some(atom) = recovery.skip_bad(line.number, atom)? {
+                let id = molecule.insert_atom(atom.element.to_string(), atom.position);
+                for (name, value) in atom.values {
+                    molecule.properties.set(name, id, value);
+                }
+                atoms.push(id);
+            }
+            continue;
+        }
+        let atom = read_xyz_atom(&line);
+        if let Some((element, position, extras)) = recovery.skip_bad(line.number, atom)? {
+            let id = molecule.insert_atom(element.to_string(), position);
+            for (offset, extra) in extras.into_iter().enumerate() {
+                let name = format!("{XYZ_COLUMN_PREFIX}{}", offset + 5);
+                molecule.properties.set(&name, id, extra);
+            }
+            atoms.push(id);
         }
-        let mut parts = line.split_whitespace();
-        let element = parts
-            .next()
-            .ok_or_else(|| XyzError::new(format!("missing element at line {}", index + 3)))?
-            .to_string();
-        let x: f32 = parts
-            .next()
-            .ok_or_else(|| XyzError::new(format!("missing x at line {}", index + 3)))?
-            .parse()
-            .map_err(|_| XyzError::new(format!("invalid x at line {}", index + 3)))?;
-        let y: f32 = parts
-            .next()
-            .ok_or_else(|| XyzError::new(format!("missing y at line {}", index + 3)))?
-            .parse()
-            .map_err(|_| XyzError::new(format!("invalid y at line {}", index + 3)))?;
-        let z: f32 = parts
-            .next()
-            .ok_or_else(|| XyzError::new(format!("missing z at line {}", index + 3)))?
-            .parse()
-            .map_err(|_| XyzError::new(format!("invalid z at line {}", index + 3)))?;
-        molecule.insert_atom(element, [x, y, z]);
-    }
-
-    if molecule.atoms.len() != atom_count {
-        return Err(XyzError::new("atom count does not match data lines"));
     }
 
-    Ok(molecule)
+    if atom_count.is_some_and(|count| atoms.len() != count) {
+        recovery.tolerate(
+            count_number,
+            XyzError::new("atom count does not match data lines"),
+        )?;
+    }
+
+    let header = match extended {
+        Some(header) => XyzHeader {
+            name: header.name.unwrap_or_default(),
+            cell: header.cell,
+            info: header.info,
+        },
+        None => XyzHeader {
+            name: comment,
+            ..XyzHeader::default()
+        },
+    };
+    Ok((header, atoms))
+}
+
+/// Element, position and any extra columns of one atom line.
+fn read_xyz_atom<'a>(line: &text::Line<'a>) -> Result<(&'a str, [f32; 3], Vec<&'a str>), XyzError> {
+    let mut fields = line.fields();
+    let element = fields.expect("element")?.text;
+    let x = fields.expect("x")?.parse("x")?;
+    let y = fields.expect("y")?.parse("y")?;
+    let z = fields.expect("z")?.parse("z")?;
+    Ok((element, [x, y, z], fields.map(|field| field.text).collect()))
+}
+
+/// Decimal places for XYZ coordinates when none are chosen.
+pub const XYZ_PRECISION: usize = 6;
+
+/// Per-atom property holding an XYZ column after z, followed by its 1-based column number
+/// (`xyz_column_5` is the first extra column). Values are kept as written.
+pub const XYZ_COLUMN_PREFIX: &str = "xyz_column_";
+
+/// Atom count, the molecule name as the comment line, and one `element x y z` line per atom
+/// in order with `precision` decimal places, right-aligned in columns. Extra columns read
+/// from an XYZ file follow z verbatim; atoms without a value in a column get `0`.
+pub fn write_xyz(molecule: &Molecule, precision: usize) -> String {
+    let mut out = format!(
+        "{}\n{}\n",
+        molecule.atom_count(),
+        molecule.name.lines().next().unwrap_or("").trim()
+    );
+    let mut extra_columns: Vec<(usize, &str)> = molecule
+        .properties
+        .names()
+        .filter_map(|name| {
+            let column = name.strip_prefix(XYZ_COLUMN_PREFIX)?.parse().ok()?;
+            Some((column, name))
+        })
+        .collect();
+    extra_columns.sort();
+    let width = precision + 6;
+    for atom in molecule.atoms_in_order() {
+        let [x, y, z] = atom.position;
+        out.push_str(&format!(
+            "{:<2} {x:>width$.precision$} {y:>width$.precision$} {z:>width$.precision$}",
+            atom.element
+        ));
+        for (_, name) in &extra_columns {
+            out.push(' ');
+            out.push_str(molecule.properties.get(name, atom.id).unwrap_or("0"));
+        }
+        out.push('\n');
+    }
+    out
 }
 
 pub fn element_color(element: &str) -> [f32; 3] {
@@ -570,19 +1631,6 @@ pub fn bond_instance_from_positions(a: [f32; 3], b: [f32; 3]) -> BondInstance {
     }
 }
 
-fn max_valence(element: &str) -> usize {
-    match element.trim().to_ascii_uppercase().as_str() {
-        "H" => 1,
-        "C" => 4,
-        "N" => 3,
-        "O" => 2,
-        "F" | "CL" | "BR" | "I" => 1,
-        "P" => 5,
-        "S" => 6,
-        _ => 4,
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -611,6 +1659,110 @@ mod tests {
         assert!(err.to_string().contains("invalid x"));
     }
 
+    #[test]
+    fn write_xyz_round_trips() {
+        let data = "3\nwater\nO 0.0 0.0 0.117\nH 0.0 0.757 -0.467\nH 0.0 -0.757 -0.467\n";
+        let molecule = parse_xyz(data).unwrap();
+        let written = write_xyz(&molecule, 3);
+        assert_eq!(
+            written,
+            "3\nwater\nO      0.000     0.000     0.117\nH      0.000     0.757    -0.467\n\
+             H      0.000    -0.757    -0.467\n"
+        );
+        let reread = parse_xyz(&written).unwrap();
+        assert_eq!(reread.name, "water");
+        assert_eq!(
+            reread
+                .atoms_in_order()
+                .map(|atom| atom.position)
+                .collect::<Vec<_>>(),
+            molecule
+                .atoms_in_order()
+                .map(|atom| atom.position)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn xyz_extra_columns_are_kept() {
+        let data = "2\nwater with charges and tags\nO 0.0 0.0 0.117 -0.834 core\n\
+                    H 0.0 0.757 -0.467 0.417\n";
+        let mut molecule = parse_xyz(data).unwrap();
+        assert_eq!(molecule.name, "water with charges and tags");
+        let ids = molecule.atom_ids();
+        assert_eq!(
+            molecule.properties.get("xyz_column_5", ids[0]),
+            Some("-0.834")
+        );
+        assert_eq!(molecule.properties.get("xyz_column_6", ids[1]), None);
+
+        molecule.insert_atom("H".into(), [0.0, -0.757, -0.467]);
+        assert_eq!(
+            write_xyz(&molecule, 3),
+            "3\nwater with charges and tags\n\
+             O      0.000     0.000     0.117 -0.834 core\n\
+             H      0.000     0.757    -0.467 0.417 0\n\
+             H      0.000    -0.757    -0.467 0 0\n"
+        );
+    }
+
+    #[test]
+    fn xyz_from_windows_and_fortran_tools() {
+        let data = "\u{feff}2\r\nwater\r\nO\t0.0\t0.0\t1.17D-1\r\nH　０．０ 0.757 -0.467\r\n";
+        let molecule = parse_xyz(data).unwrap();
+        assert_eq!(molecule.name, "water");
+        let atoms: Vec<_> = molecule.atoms_in_order().collect();
+        assert_eq!(atoms[0].position, [0.0, 0.0, 0.117]);
+        assert_eq!(atoms[1].position, [0.0, 0.757, -0.467]);
+
+        let err = parse_xyz("1\n\nO 0.0 0,5 0.0\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 3, column 7: invalid y");
+        let err = parse_xyz("1\n\nO 0.0 0.5\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 3, column 10: missing z");
+    }
+
+    #[test]
+    fn reads_a_range_of_xyz_frames() {
+        let data = "1\nfirst\nO 0 0 0\n\n1\nsecond\nO 0 0 1\n1\nthird\nO 0 0 2\n";
+        let read = |first_frame, last_frame| {
+            let options = XyzOptions {
+                first_frame,
+                last_frame,
+            };
+            parse_xyz_with(data, &options, &mut Recovery::strict())
+        };
+        let second = read(2, 2).unwrap();
+        assert_eq!(second.name, "second");
+        assert_eq!(
+            second.atoms_in_order().next().unwrap().position,
+            [0.0, 0.0, 1.0]
+        );
+
+        let tail = read(2, 9).unwrap();
+        let frames: Vec<_> = tail
+            .atom_ids()
+            .into_iter()
+            .map(|id| tail.properties.get(XYZ_FRAME_PROPERTY, id))
+            .collect();
+        assert_eq!(frames, [Some("2"), Some("3")]);
+        assert_eq!(
+            read(5, 5).unwrap_err().to_string(),
+            "the file has only 3 frames"
+        );
+    }
+
+    #[test]
+    fn lenient_xyz_skips_bad_and_missing_lines() {
+        let data = "4\ncut short\nO 0.0 0.0 0.0\nH 0.0 0,7 0.5\nH 0.0 -0.7 0.5\n";
+        assert!(parse_xyz(data).is_err());
+        let (molecule, problems) = parse_xyz_lenient(data).unwrap();
+        assert_eq!(molecule.atom_count(), 2);
+        let messages: Vec<String> = problems.iter().map(Problem::to_string).collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0], "line 4, column 7: invalid y");
+        assert_eq!(problems[0].line, 4);
+    }
+
     #[test]
     fn element_color_mapping() {
         assert_eq!(element_color("H"), [1.0, 1.0, 1.0]);
@@ -642,6 +1794,33 @@ mod tests {
         assert!(molecule.get_atom(id).is_some());
     }
 
+    #[test]
+    fn history_dirty_tracking() {
+        let mut molecule = Molecule::new("test");
+        let mut history = CommandHistory::new(10);
+        let insert = |element: &str, x| Command::InsertAtom {
+            element: element.into(),
+            position: [x, 0.0, 0.0],
+            atom_id: None,
+            order_index: None,
+        };
+        assert!(!history.is_dirty());
+        history.execute(insert("H", 0.0), &mut molecule).unwrap();
+        assert!(history.is_dirty());
+        history.mark_saved();
+        assert!(!history.is_dirty());
+        history.undo(&mut molecule).unwrap();
+        assert!(history.is_dirty());
+        history.redo(&mut molecule).unwrap();
+        assert!(!history.is_dirty());
+
+        // Editing after an undo drops the saved state from the redo stack for good.
+        history.undo(&mut molecule).unwrap();
+        history.execute(insert("O", 1.0), &mut molecule).unwrap();
+        history.undo(&mut molecule).unwrap();
+        assert!(history.is_dirty());
+    }
+
     #[test]
     fn command_delete_with_bonds() {
         let mut molecule = Molecule::new("test");
@@ -661,6 +1840,64 @@ mod tests {
         assert!(molecule.bond_between(a, b).is_some());
     }
 
+    #[test]
+    fn deleting_a_metal_keeps_ligand_valence() {
+        let mut molecule = Molecule::new("ammine");
+        let fe = molecule.insert_atom("Fe".into(), [0.0; 3]);
+        let n = molecule.insert_atom("N".into(), [2.0, 0.0, 0.0]);
+        let dative = molecule.add_bond(n, fe).unwrap();
+        molecule
+            .set_bond_kind(dative, BondKind::Coordination)
+            .unwrap();
+        for y in [-1.0, 0.0, 1.0] {
+            let h = molecule.insert_atom("H".into(), [2.4, y, 0.8]);
+            molecule.add_bond(n, h).unwrap();
+        }
+        let mut history = CommandHistory::new(10);
+        for _ in 0..3 {
+            let delete = Command::DeleteAtom {
+                atom_id: fe,
+                removed: None,
+            };
+            history.execute(delete, &mut molecule).unwrap();
+            history.undo(&mut molecule).unwrap();
+        }
+        assert_eq!(molecule.bonds().count(), 4);
+        let c = molecule.insert_atom("C".into(), [3.5, 0.0, 0.0]);
+        assert_eq!(
+            molecule.add_bond(n, c).unwrap_err(),
+            "valence exceeded for N (max 3)"
+        );
+    }
+
+    #[test]
+    fn double_bonds_count_twice_toward_valence() {
+        let mut molecule = Molecule::new("formaldehyde");
+        let c = molecule.insert_atom("C".into(), [0.0; 3]);
+        let o = molecule.insert_atom("O".into(), [1.2, 0.0, 0.0]);
+        let carbonyl = molecule.add_bond(c, o).unwrap();
+        molecule.check_bond_order(carbonyl, 2).unwrap();
+        molecule.set_bond_order(carbonyl, 2);
+        let hs: Vec<AtomId> = (0..3)
+            .map(|i| molecule.insert_atom("H".into(), [-1.0, i as f32, 0.0]))
+            .collect();
+        molecule.add_bond(c, hs[0]).unwrap();
+        molecule.add_bond(c, hs[1]).unwrap();
+        assert_eq!(
+            molecule.add_bond(c, hs[2]).unwrap_err(),
+            "valence exceeded for C (max 4)"
+        );
+        assert!(molecule.check_bond_order(carbonyl, 3).is_err());
+        assert!(molecule.valence_warnings().is_empty());
+
+        molecule.set_valence_model(Arc::new(valence::Unrestricted));
+        molecule.add_bond(c, hs[2]).unwrap();
+        molecule.set_valence_model(Arc::new(StandardValence));
+        let warnings = molecule.valence_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].atom, warnings[0].bonds), (c, 5));
+    }
+
     #[test]
     fn command_bond_add_remove() {
         let mut molecule = Molecule::new("test");
@@ -753,6 +1990,118 @@ mod tests {
         assert!(!history.can_redo());
     }
 
+    #[test]
+    fn coordination_bonds_skip_valence_and_survive_undo() {
+        let mut molecule = Molecule::new("ferrocene fragment");
+        let iron = molecule.insert_atom("Fe".into(), [0.0; 3]);
+        let carbons: Vec<AtomId> = (0..6)
+            .map(|i| molecule.insert_atom("C".into(), [i as f32, 1.6, 0.0]))
+            .collect();
+        let mut history = CommandHistory::new(20);
+        for carbon in &carbons {
+            let command = Command::AddCoordinationBond {
+                atom_a: *carbon,
+                atom_b: iron,
+                bond_id: None,
+            };
+            history.execute(command, &mut molecule).unwrap();
+        }
+        let chloride = molecule.insert_atom("Cl".into(), [0.0, -2.2, 0.0]);
+        let covalent = molecule.add_bond(iron, chloride).unwrap();
+        let first = molecule.bond_between(carbons[0], iron).unwrap();
+        let make_covalent = Command::SetBondKind {
+            bond_id: first,
+            kind: BondKind::Covalent,
+            previous: None,
+        };
+        history.execute(make_covalent, &mut molecule).unwrap();
+        // Fe allows 4 covalent bonds: Cl and the first carbon leave room for two more.
+        for carbon in &carbons[1..3] {
+            molecule
+                .set_bond_kind(
+                    molecule.bond_between(*carbon, iron).unwrap(),
+                    BondKind::Covalent,
+                )
+                .unwrap();
+        }
+        let fourth = molecule.bond_between(carbons[3], iron).unwrap();
+        assert!(molecule.set_bond_kind(fourth, BondKind::Covalent).is_err());
+        assert_eq!(
+            molecule
+                .bonds()
+                .filter(|bond| bond.kind == BondKind::Coordination)
+                .count(),
+            3
+        );
+
+        let remove = Command::RemoveBond {
+            bond_id: fourth,
+            removed: None,
+        };
+        history.execute(remove, &mut molecule).unwrap();
+        history.undo(&mut molecule).unwrap();
+        let restored = molecule.bonds().find(|bond| bond.id == fourth).unwrap();
+        assert_eq!(restored.kind, BondKind::Coordination);
+        history.undo(&mut molecule).unwrap();
+        let first = molecule.bonds().find(|bond| bond.id == first).unwrap();
+        assert_eq!(first.kind, BondKind::Coordination);
+        assert!(molecule.remove_bond(covalent).is_some());
+        assert_eq!(molecule.extract(&molecule.atom_ids()).bonds().count(), 6);
+    }
+
+    #[test]
+    fn bond_order_changes_are_undoable() {
+        let mut molecule = Molecule::new("ethene");
+        let a = molecule.insert_atom("C".into(), [0.0; 3]);
+        let b = molecule.insert_atom("C".into(), [1.34, 0.0, 0.0]);
+        let bond = molecule.add_bond(a, b).unwrap();
+        let events = molecule.subscribe();
+        let mut history = CommandHistory::new(8);
+        let set = |order| Command::SetBondOrder {
+            bond_id: bond,
+            order,
+            previous: None,
+        };
+        history.execute(set(2), &mut molecule).unwrap();
+        assert_eq!(molecule.bonds().next().unwrap().order, 2);
+        assert!(history.execute(set(4), &mut molecule).is_err());
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.bonds().next().unwrap().order, 1);
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [MoleculeEvent::BondChanged(bond); 2]
+        );
+    }
+
+    #[test]
+    fn bond_orders_respect_valence() {
+        let mut molecule = Molecule::new("methane");
+        let c = molecule.insert_atom("C".into(), [0.0; 3]);
+        let h = molecule.insert_atom("H".into(), [1.09, 0.0, 0.0]);
+        let ch = molecule.add_bond(c, h).unwrap();
+        let mut history = CommandHistory::new(8);
+        let set = |bond_id, order| Command::SetBondOrder {
+            bond_id,
+            order,
+            previous: None,
+        };
+        let err = history.execute(set(ch, 2), &mut molecule).unwrap_err();
+        assert_eq!(err, "valence exceeded for H (max 1)");
+        assert_eq!(molecule.bonds().next().unwrap().order, 1);
+        assert_eq!(history.undo_len(), 0);
+
+        let o = molecule.insert_atom("O".into(), [-1.2, 0.0, 0.0]);
+        let co = molecule.add_bond(c, o).unwrap();
+        history.execute(set(co, 2), &mut molecule).unwrap();
+        let n = molecule.insert_atom("N".into(), [0.0, 1.4, 0.0]);
+        let cn = molecule.add_bond(c, n).unwrap();
+        // C already uses 1 + 2 + 1 of its four: neither C=N nor a C≡O fits.
+        assert!(history.execute(set(cn, 2), &mut molecule).is_err());
+        assert!(history.execute(set(co, 3), &mut molecule).is_err());
+        history.execute(set(co, 1), &mut molecule).unwrap();
+        history.execute(set(cn, 2), &mut molecule).unwrap();
+    }
+
     #[test]
     fn failed_command_does_not_mutate() {
         let mut molecule = Molecule::new("test");
@@ -822,6 +2171,263 @@ mod tests {
         assert!(!history.can_redo());
     }
 
+    #[test]
+    fn merge_assigns_fresh_ids() {
+        let mut molecule = Molecule::new("base");
+        let c = molecule.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let mut fragment = Molecule::new("fragment");
+        let o = fragment.insert_atom("O".into(), [0.0, 0.0, 0.0]);
+        let h = fragment.insert_atom("H".into(), [1.0, 0.0, 0.0]);
+        let bond = fragment.add_bond(o, h).unwrap();
+        let mapping = molecule.merge(&fragment);
+        assert_eq!(molecule.atom_count(), 3);
+        let new_o = mapping.atoms[&o];
+        let new_h = mapping.atoms[&h];
+        assert_ne!(new_o, c);
+        assert_eq!(molecule.get_atom(new_o).unwrap().element, "O");
+        assert_eq!(
+            molecule.bond_between(new_o, new_h),
+            Some(mapping.bonds[&bond])
+        );
+    }
+
+    #[test]
+    fn extract_keeps_internal_bonds_only() {
+        let mut molecule = Molecule::new("chain");
+        let a = molecule.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let b = molecule.insert_atom("C".into(), [1.5, 0.0, 0.0]);
+        let c = molecule.insert_atom("O".into(), [3.0, 0.0, 0.0]);
+        molecule.add_bond(a, b).unwrap();
+        molecule.add_bond(b, c).unwrap();
+        let extracted = molecule.extract(&[c, b]);
+        assert_eq!(extracted.atom_count(), 2);
+        assert_eq!(extracted.bonds().count(), 1);
+        let elements: Vec<&str> = extracted
+            .atoms_in_order()
+            .map(|atom| atom.element.as_str())
+            .collect();
+        assert_eq!(elements, ["C", "O"]);
+    }
+
+    #[test]
+    fn renumber_compacts_ids_in_order() {
+        let mut molecule = Molecule::new("edited");
+        let a = molecule.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let removed = molecule.insert_atom("H".into(), [1.0, 0.0, 0.0]);
+        let b = molecule.insert_atom("O".into(), [2.0, 0.0, 0.0]);
+        molecule.remove_atom(removed);
+        molecule.insert_atom_with_id(AtomId(10), "N".into(), [3.0, 0.0, 0.0], Some(0));
+        molecule.add_bond(a, b).unwrap();
+        let mapping = molecule.renumber();
+        let ids: Vec<u64> = molecule.atom_ids().iter().map(|id| id.value()).collect();
+        assert_eq!(ids, [1, 2, 3]);
+        assert_eq!(mapping.atoms[&AtomId(10)], AtomId(1));
+        assert_eq!(molecule.get_atom(AtomId(1)).unwrap().element, "N");
+        let (new_a, new_b) = (mapping.atoms[&a], mapping.atoms[&b]);
+        assert_eq!(molecule.bond_between(new_a, new_b), Some(BondId(1)));
+        assert_eq!(molecule.insert_atom("H".into(), [0.0; 3]), AtomId(4));
+    }
+
+    #[test]
+    fn formula_uses_hill_order() {
+        let data = "5\nmethanol\nC 0 0 0\nO 1.4 0 0\nH 0 1 0\nH 0 -1 0\nh 1.8 0.9 0\n";
+        let molecule = parse_xyz(data).unwrap();
+        assert_eq!(molecule.formula(), "CH3O");
+        assert!((molecule.molecular_weight() - 31.034).abs() < 1e-3);
+        let mut salt = Molecule::new("salt");
+        salt.insert_atom("Na".into(), [0.0, 0.0, 0.0]);
+        salt.insert_atom("Cl".into(), [2.8, 0.0, 0.0]);
+        salt.insert_atom("H".into(), [5.0, 0.0, 0.0]);
+        assert_eq!(salt.formula(), "ClHNa");
+    }
+
+    #[test]
+    fn command_merge_undo_redo() {
+        let mut molecule = Molecule::new("base");
+        molecule.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let mut fragment = Molecule::new("fragment");
+        let o = fragment.insert_atom("O".into(), [0.0, 0.0, 0.0]);
+        let h = fragment.insert_atom("H".into(), [1.0, 0.0, 0.0]);
+        fragment.add_bond(o, h).unwrap();
+        let mut history = CommandHistory::new(10);
+        let executed = history
+            .execute(
+                Command::Merge {
+                    fragment: Box::new(fragment),
+                    mapping: None,
+                },
+                &mut molecule,
+            )
+            .unwrap();
+        let mapping = match executed {
+            Command::Merge { mapping, .. } => mapping.unwrap(),
+            _ => panic!("expected merge"),
+        };
+        assert_eq!(molecule.atom_count(), 3);
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.atom_count(), 1);
+        assert_eq!(molecule.bonds().count(), 0);
+        history.redo(&mut molecule).unwrap();
+        let (new_o, new_h) = (mapping.atoms[&o], mapping.atoms[&h]);
+        assert!(molecule.get_atom(new_o).is_some());
+        assert!(molecule.bond_between(new_o, new_h).is_some());
+    }
+
+    #[test]
+    fn command_set_cell_keeps_fractional() {
+        let mut molecule = Molecule::new("crystal");
+        molecule.set_cell(Some(UnitCell::cubic(4.0)));
+        let a = molecule.insert_atom("Na".into(), [2.0, 1.0, 0.0]);
+        let mut history = CommandHistory::new(10);
+        history
+            .execute(
+                Command::SetCell {
+                    cell: Some(UnitCell::cubic(8.0)),
+                    lock: CoordinateLock::Fractional,
+                    previous: None,
+                    moved: Vec::new(),
+                },
+                &mut molecule,
+            )
+            .unwrap();
+        let position = molecule.get_atom(a).unwrap().position;
+        assert!((position[0] - 4.0).abs() < 1e-4);
+        assert!((position[1] - 2.0).abs() < 1e-4);
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.get_atom(a).unwrap().position, [2.0, 1.0, 0.0]);
+        assert_eq!(molecule.cell(), Some(&UnitCell::cubic(4.0)));
+        history
+            .execute(
+                Command::SetCell {
+                    cell: Some(UnitCell::cubic(8.0)),
+                    lock: CoordinateLock::Cartesian,
+                    previous: None,
+                    moved: Vec::new(),
+                },
+                &mut molecule,
+            )
+            .unwrap();
+        assert_eq!(molecule.get_atom(a).unwrap().position, [2.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn history_rewound_leaves_state_untouched() {
+        let mut molecule = Molecule::new("test");
+        let mut history = CommandHistory::new(10);
+        for element in ["C", "O"] {
+            let command = Command::InsertAtom {
+                element: element.into(),
+                position: [0.0, 0.0, 0.0],
+                atom_id: None,
+                order_index: None,
+            };
+            history.execute(command, &mut molecule).unwrap();
+        }
+        let earlier = history.rewound(&molecule, 1).unwrap();
+        assert_eq!(earlier.atom_count(), 1);
+        assert_eq!(molecule.atom_count(), 2);
+        assert_eq!(history.undo_len(), 2);
+    }
+
+    #[test]
+    fn command_restore_snapshot() {
+        let mut molecule = Molecule::new("test");
+        let a = molecule.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let snapshot = molecule.clone();
+        molecule.set_atom_position(a, [1.0, 0.0, 0.0]);
+        molecule.insert_atom("O".into(), [2.0, 0.0, 0.0]);
+        let mut history = CommandHistory::new(10);
+        let command = Command::Restore {
+            snapshot: Box::new(snapshot),
+            previous: None,
+        };
+        history.execute(command, &mut molecule).unwrap();
+        assert_eq!(molecule.atom_count(), 1);
+        assert_eq!(molecule.get_atom(a).unwrap().position, [0.0, 0.0, 0.0]);
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.atom_count(), 2);
+        assert_eq!(molecule.get_atom(a).unwrap().position, [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn contacts_match_distance_matrix() {
+        let mut molecule = Molecule::new("lattice");
+        for i in 0..60 {
+            let t = i as f32;
+            let position = [(t * 1.37) % 5.0, (t * 2.11) % 4.0 - 2.0, (t * 0.73) % 3.0];
+            molecule.insert_atom("C".into(), position);
+        }
+        let ids = molecule.atom_ids();
+        let matrix = molecule.distance_matrix();
+        assert_eq!(matrix.len(), 60);
+        assert_eq!(matrix[3][3], 0.0);
+        assert_eq!(matrix[2][7], matrix[7][2]);
+        let mut expected = Vec::new();
+        for i in 0..ids.len() {
+            for j in i + 1..ids.len() {
+                if matrix[i][j] <= 1.2 {
+                    expected.push((ids[i], ids[j]));
+                }
+            }
+        }
+        assert!(!expected.is_empty());
+        assert_eq!(molecule.contacts_within(1.2), expected);
+    }
+
+    #[test]
+    fn zero_cutoff_contacts_far_from_the_origin() {
+        let mut molecule = Molecule::new("far");
+        let a = molecule.insert_atom("C".into(), [1.0e6, -3.0e9, 512.0]);
+        let b = molecule.insert_atom("C".into(), [1.0e6, -3.0e9, 512.0]);
+        molecule.insert_atom("C".into(), [1.0e6, -3.0e9, 600.0]);
+        assert_eq!(molecule.contacts_within(0.0), [(a, b)]);
+        assert_eq!(molecule.contacts_within(100.0).len(), 3);
+    }
+
+    #[test]
+    fn nearest_and_radius_queries_match_a_scan_and_follow_edits() {
+        let mut molecule = Molecule::new("cloud");
+        for i in 0..200 {
+            let t = i as f32;
+            let position = [(t * 1.37) % 9.0, (t * 2.11) % 7.0 - 3.0, (t * 0.73) % 5.0];
+            molecule.insert_atom("C".into(), position);
+        }
+        let distance = |id: AtomId, point: [f32; 3]| {
+            Vec3::from(molecule.get_atom(id).unwrap().position).distance(Vec3::from(point))
+        };
+        for i in 0..25 {
+            let t = i as f32;
+            let point = [
+                (t * 0.91) % 10.0 - 0.5,
+                (t * 1.73) % 8.0 - 4.0,
+                (t * 2.9) % 6.0,
+            ];
+            let nearest = molecule.nearest_atom(point).unwrap();
+            let closest = molecule
+                .atom_ids()
+                .into_iter()
+                .map(|id| distance(id, point))
+                .fold(f32::INFINITY, f32::min);
+            assert_eq!(distance(nearest, point), closest);
+            let expected: Vec<AtomId> = molecule
+                .atom_ids()
+                .into_iter()
+                .filter(|&id| distance(id, point) <= 1.5)
+                .collect();
+            assert_eq!(molecule.atoms_within(point, 1.5), expected);
+        }
+
+        let far = [50.0, 50.0, 50.0];
+        let moved = molecule.atom_ids()[17];
+        molecule.set_atom_position(moved, far);
+        assert_eq!(molecule.nearest_atom(far), Some(moved));
+        molecule.remove_atom(moved);
+        assert_ne!(molecule.nearest_atom(far), Some(moved));
+        let added = molecule.insert_atom("O".into(), far);
+        assert_eq!(molecule.atoms_within(far, 0.1), vec![added]);
+        assert!(Molecule::new("empty").nearest_atom(far).is_none());
+    }
+
     #[test]
     fn bond_instance_direction_and_length() {
         let instance = bond_instance_from_positions([0.0, 0.0, 0.0], [0.0, 2.0, 0.0]);
@@ -1,29 +1,134 @@
-use std::collections::HashMap;
-use std::sync::mpsc;
+use std::collections::{HashMap, HashSet};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::{Duration, Instant};
 
 use bytemuck::{Pod, Zeroable};
-use glam::{Mat4, Vec2, Vec3, Vec4};
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3};
 use wgpu::util::DeviceExt;
 use winit::event::{ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
-use winit::keyboard::Key;
+use winit::keyboard::{Key, NamedKey};
 use winit::window::{Window, WindowBuilder};
 
+use molweaver::animation::{self, CameraPath, Keyframe};
+use molweaver::annotations::{self, Annotations, Arrow, ArrowKind, PartialBond};
+use molweaver::camera::{
+    Camera, CameraMode, SavedView, FAR_PLANE, FIELD_OF_VIEW_DEGREES, NEAR_PLANE,
+};
+use molweaver::cell::CellMetrics;
+use molweaver::cli::{self, OutputFormat, RepresentationArg, StartupOptions};
+use molweaver::composition::{self, Composition};
+use molweaver::diff::{diff, MoleculeDiff};
+use molweaver::docking;
+use molweaver::events::{check_mirror, BondMirror, Divergence, Mirror, MoleculeEvent};
+use molweaver::figure;
+use molweaver::formats::gaussian::{self, GaussianSettings};
+use molweaver::formats::sdf;
+use molweaver::formats::smiles;
+use molweaver::formats::text::Problem;
+use molweaver::formats::{self, cml, extxyz, gzip, stream, Format, LoadOptions};
+use molweaver::fragments::{Fragment, FragmentCategory};
+use molweaver::gallery::{self, ThumbnailDisc};
+use molweaver::geometry;
+use molweaver::gltf::write_glb;
+use molweaver::grid::{grid_cells, GridCell};
+use molweaver::help::{self, Tutorial, WATER_TUTORIAL};
+use molweaver::html::write_html;
+use molweaver::labels::AtomLabels;
+use molweaver::measure::{MeasureKind, Measurement, Measurements};
+use molweaver::notes::{NoteTarget, Notes};
+use molweaver::obj::{write_mtl, write_obj};
+use molweaver::optimize::{self, MinimizeOptions, Minimized};
+use molweaver::palette::Palette;
+use molweaver::perturb;
+use molweaver::pick::{self, Ray, Region};
+use molweaver::pocket::{self, PocketOptions};
+use molweaver::postprocess::{self, Frame, PresentationSettings};
+use molweaver::povray::write_povray;
+use molweaver::provenance::{self, ProvenanceLog};
+use molweaver::query;
+use molweaver::residues::{self, InferredLink, LinkKind, ResidueId};
+use molweaver::scene::{Scene, SceneCamera, SceneStyle};
+use molweaver::scripting::Hooks;
+use molweaver::selection::{SelectMode, Selection};
+use molweaver::session::{parse_session, write_session, Checkpoint, Session};
+use molweaver::sketch::{self, BondGuide, SNAP_ANGLES};
+use molweaver::stats::{GpuUsage, SceneStats};
+use molweaver::stl::{write_stl, PrintSettings};
+use molweaver::style::{
+    parse_rules, BondDash, BondStyle, StyleOverride, StyleOverrides, StyleRule,
+};
+use molweaver::substructure;
+use molweaver::sweep;
+use molweaver::trajectory::{self, EditRecorder};
+use molweaver::transform::{self, MirrorPlane};
+use molweaver::valence::{ValenceModel, ValenceRules};
+use molweaver::watch::FileWatch;
 use molweaver::{
-    bond_instance_from_positions, element_color, Atom, AtomId, BondId, Command, CommandHistory,
-    Molecule,
+    bond_instance_from_positions, write_xyz, Atom, AtomId, Bond, BondId, BondKind, Command,
+    CommandHistory, CoordinateLock, Molecule, UnitCell, XYZ_FRAME_PROPERTY, XYZ_PRECISION,
 };
 
 const SAMPLE_PATH: &str = "assets/sample.xyz";
+const SESSION_PATH: &str = "session.mwsession";
 const SPHERE_SEGMENTS: u32 = 32;
 const SPHERE_RINGS: u32 = 16;
 const CYLINDER_SEGMENTS: u32 = 24;
 const ATOM_RADIUS: f32 = 0.5;
 const SPACE_FILL_RADIUS: f32 = 0.9;
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+const FLY_KEYS: [&str; 6] = ["w", "a", "s", "d", "q", "e"];
+const MAX_FRAME_SECONDS: f32 = 0.1;
 const BOND_RADIUS: f32 = 0.15;
+/// Radius of the extra lines of double and triple bonds, and the spacing between lines, as
+/// fractions of the bond radius.
+const ORDER_STRAND_RADIUS: f32 = 0.5;
+const ORDER_STRAND_SPACING: f32 = 2.0;
+/// Fraction of the bond trimmed from each end of an extra line.
+const ORDER_STRAND_TRIM: f32 = 0.18;
 const HISTORY_CAPACITY: usize = 100;
+const PASTE_OFFSET: Vec3 = Vec3::new(1.0, 1.0, 0.0);
+const FLAG_SELECTED: u32 = 1;
+const FLAG_ADDED: u32 = 2;
+const FLAG_MODIFIED: u32 = 4;
+const FLAG_MATCH: u32 = 8;
+/// Drawn dashed, for coordination bonds.
+const FLAG_DASHED: u32 = 16;
+/// Drawn as a row of dots.
+const FLAG_DOTTED: u32 = 64;
+/// Atoms in a docking clash.
+const FLAG_CLASH: u32 = 32;
+const FIND_MATCH_LIMIT: usize = 1000;
+const GALLERY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the open file is checked for changes when it is watched.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const THUMBNAIL_SIZE: f32 = 96.0;
+const THUMBNAIL_YAW: f32 = 0.8;
+const THUMBNAIL_PITCH: f32 = 0.3;
+const NOTE_MARKER_RADIUS: f32 = 5.0;
+/// Screen offset of note markers from their atom or bond, so they don't cover it.
+const NOTE_MARKER_OFFSET: f32 = 10.0;
+/// Gap, in pixels, left between an arrow's ends and the centers of the sites it joins.
+const ARROW_INSET: f32 = 8.0;
+const ARROWHEAD_SIZE: f32 = 10.0;
+/// Spacing, in pixels, between the parallel lines of a partial bond.
+const PARTIAL_BOND_SPACING: f32 = 4.0;
+/// How close, in degrees, a sketched angle must be to an ideal one to snap onto it.
+const ANGLE_SNAP_TOLERANCE: f32 = 6.0;
+/// How long the turntable stays paused after the last mouse or keyboard input.
+const TURNTABLE_IDLE: Duration = Duration::from_secs(3);
+/// How long going to a saved view takes.
+const VIEW_TRANSITION: Duration = Duration::from_millis(600);
+/// Smallest pooled instance buffer; sizes are powers of two above this.
+const INSTANCE_BUFFER_MIN_BYTES: u64 = 4096;
+/// An instance buffer is replaced by a smaller one once at most 1/N of it is in use.
+const BUFFER_SHRINK_RATIO: usize = 4;
+/// Idle pooled buffers beyond this many bytes are freed, largest first.
+const BUFFER_POOL_BUDGET: u64 = 64 << 20;
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
@@ -104,6 +209,21 @@ struct BondInstanceData {
     flags: u32,
 }
 
+/// Where one double or triple bond's extra lines sit in the strand buffer, and the atoms
+/// whose positions decide where they are drawn.
+struct OrderStrands {
+    bond: BondId,
+    a: AtomId,
+    b: AtomId,
+    order: u8,
+    /// Atoms bonded to either end, which pick the side the lines go on.
+    substituents: Vec<AtomId>,
+    /// The bonds at either end, this one included, whose removal or change moves the lines.
+    neighbors: Vec<BondId>,
+    /// Strand buffer slots of the lines, one per line.
+    slots: Vec<usize>,
+}
+
 impl BondInstanceData {
     fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
@@ -150,32 +270,11 @@ impl BondInstanceData {
 struct CameraUniform {
     view_proj: [[f32; 4]; 4],
     camera_pos: [f32; 4],
-}
-
-struct Camera {
-    yaw: f32,
-    pitch: f32,
-    distance: f32,
-    target: Vec3,
-}
-
-impl Camera {
-    fn position(&self) -> Vec3 {
-        let (yaw_sin, yaw_cos) = self.yaw.sin_cos();
-        let (pitch_sin, pitch_cos) = self.pitch.sin_cos();
-        Vec3::new(
-            self.distance * pitch_cos * yaw_cos,
-            self.distance * pitch_sin,
-            self.distance * pitch_cos * yaw_sin,
-        ) + self.target
-    }
-
-    fn view_proj(&self, aspect: f32) -> Mat4 {
-        let position = self.position();
-        let view = Mat4::look_at_rh(position, self.target, Vec3::Y);
-        let proj = Mat4::perspective_rh(45.0_f32.to_radians(), aspect, 0.1, 200.0);
-        proj * view
-    }
+    selected_color: [f32; 4],
+    added_color: [f32; 4],
+    modified_color: [f32; 4],
+    match_color: [f32; 4],
+    clash_color: [f32; 4],
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -183,7 +282,15 @@ enum Tool {
     Select,
     AddAtom,
     AddBond,
+    /// Click an atom to grow a bonded atom of the edit element from it.
+    Sprout,
     Move,
+    /// Drag out a rectangle to select the atoms inside it.
+    BoxSelect,
+    /// Trace a freehand outline to select the atoms inside it.
+    Lasso,
+    /// Click atoms to add a distance, angle or dihedral monitor.
+    Measure,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -192,54 +299,646 @@ enum Representation {
     SpaceFilling,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    PovRay,
+    Gltf,
+    Obj,
+    Stl,
+}
+
+impl ExportFormat {
+    const ALL: [ExportFormat; 4] = [
+        ExportFormat::PovRay,
+        ExportFormat::Gltf,
+        ExportFormat::Obj,
+        ExportFormat::Stl,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            ExportFormat::PovRay => "POV-Ray",
+            ExportFormat::Gltf => "glTF",
+            ExportFormat::Obj => "OBJ",
+            ExportFormat::Stl => "STL",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::PovRay => "pov",
+            ExportFormat::Gltf => "glb",
+            ExportFormat::Obj => "obj",
+            ExportFormat::Stl => "stl",
+        }
+    }
+
+    /// Writes `path`; OBJ also writes its materials next to it as a `.mtl` file.
+    fn write(self, scene: &Scene, print: &PrintSettings, path: &Path) -> std::io::Result<()> {
+        let contents = match self {
+            ExportFormat::PovRay => write_povray(scene).into_bytes(),
+            ExportFormat::Gltf => write_glb(scene),
+            ExportFormat::Obj => {
+                let materials = path.with_extension("mtl");
+                std::fs::write(&materials, write_mtl(scene))?;
+                let name = materials.file_name().unwrap_or_default().to_string_lossy();
+                write_obj(scene, &name).into_bytes()
+            }
+            ExportFormat::Stl => write_stl(scene, print),
+        };
+        std::fs::write(path, contents)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CellInputMode {
+    Parameters,
+    Matrix,
+}
+
+struct CellEditor {
+    mode: CellInputMode,
+    parameters: [f32; 6],
+    vectors: [[f32; 3]; 3],
+    lock: CoordinateLock,
+}
+
+impl CellEditor {
+    fn new(cell: Option<UnitCell>) -> Self {
+        let cell = cell.unwrap_or(UnitCell::cubic(10.0));
+        let matrix = cell.matrix();
+        Self {
+            mode: CellInputMode::Parameters,
+            parameters: [cell.a, cell.b, cell.c, cell.alpha, cell.beta, cell.gamma],
+            vectors: [
+                matrix.x_axis.to_array(),
+                matrix.y_axis.to_array(),
+                matrix.z_axis.to_array(),
+            ],
+            lock: CoordinateLock::Fractional,
+        }
+    }
+
+    fn cell(&self) -> UnitCell {
+        match self.mode {
+            CellInputMode::Parameters => {
+                let [a, b, c, alpha, beta, gamma] = self.parameters;
+                UnitCell::new(a, b, c, alpha, beta, gamma)
+            }
+            CellInputMode::Matrix => UnitCell::from_matrix(Mat3::from_cols(
+                Vec3::from_array(self.vectors[0]),
+                Vec3::from_array(self.vectors[1]),
+                Vec3::from_array(self.vectors[2]),
+            )),
+        }
+    }
+}
+
+/// An action that would throw away unsaved edits, held back until the user confirms it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiscardAction {
+    Open,
+    GalleryOpen(usize),
+    OpenSession,
+    Exit,
+}
+
+impl DiscardAction {
+    fn label(self) -> &'static str {
+        match self {
+            DiscardAction::Exit => "Quit without saving",
+            _ => "Discard and open",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CheckpointAction {
+    Take,
+    Revert(usize),
+    Branch(usize),
+    Delete(usize),
+    SaveSession,
+    OpenSession,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FindRequest {
+    File,
+    Clipboard,
+    Query,
+    /// The script selection named in `find_selection`.
+    Script,
+    Focus(Option<usize>),
+    Clear,
+}
+
+/// Distinct atom sets matched by a substructure search, with the bonds they cover.
+struct FindMatch {
+    atoms: Vec<AtomId>,
+    bonds: Vec<BondId>,
+}
+
+enum CompareRequest {
+    History(usize),
+    File,
+    Clear,
+}
+
+/// A molecule shown beside the current one in the comparison grid.
+struct GridSample {
+    name: String,
+    molecule: Molecule,
+}
+
+enum GridAction {
+    AddFile,
+    AddCurrent,
+    AddGallery,
+    Remove(usize),
+    Clear,
+}
+
+/// How files are read; the per-format parts are set in the Open window.
+#[derive(Debug, Clone)]
+struct ImportOptions {
+    load: LoadOptions,
+    /// Bond disulfides and find salt bridges in PDB files after reading.
+    infer_links: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            load: LoadOptions::default(),
+            infer_links: true,
+        }
+    }
+}
+
+impl ImportOptions {
+    /// Default per-format choices, for files other than the one being opened.
+    fn defaults(lenient: bool) -> Self {
+        Self {
+            load: LoadOptions {
+                lenient,
+                ..LoadOptions::default()
+            },
+            ..Self::default()
+        }
+    }
+}
+
+struct GalleryItem {
+    path: PathBuf,
+    molecule: Result<Molecule, String>,
+    discs: Vec<ThumbnailDisc>,
+}
+
+enum GalleryEvent {
+    Updated(PathBuf, Result<Box<Molecule>, String>),
+    Removed(PathBuf),
+    Failed(String),
+}
+
+/// A watched results folder; dropping it stops the polling thread.
+struct Gallery {
+    items: Vec<GalleryItem>,
+    palette: Palette,
+    rx: mpsc::Receiver<GalleryEvent>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Gallery {
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        for item in &mut self.items {
+            if let Ok(molecule) = &item.molecule {
+                item.discs = gallery::thumbnail(molecule, THUMBNAIL_YAW, THUMBNAIL_PITCH, palette);
+            }
+        }
+    }
+
+    fn apply(&mut self, event: GalleryEvent) -> Option<String> {
+        match event {
+            GalleryEvent::Updated(path, molecule) => {
+                let molecule = molecule.map(|boxed| *boxed);
+                let discs = molecule
+                    .as_ref()
+                    .map(|mol| {
+                        gallery::thumbnail(mol, THUMBNAIL_YAW, THUMBNAIL_PITCH, self.palette)
+                    })
+                    .unwrap_or_default();
+                let item = GalleryItem {
+                    path,
+                    molecule,
+                    discs,
+                };
+                match self
+                    .items
+                    .binary_search_by(|probe| probe.path.cmp(&item.path))
+                {
+                    Ok(index) => self.items[index] = item,
+                    Err(index) => self.items.insert(index, item),
+                }
+            }
+            GalleryEvent::Removed(path) => self.items.retain(|item| item.path != path),
+            GalleryEvent::Failed(err) => return Some(err),
+        }
+        None
+    }
+}
+
+impl Drop for Gallery {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
 struct UiState {
     camera: Camera,
+    camera_before_fly: CameraMode,
+    /// Start, end and start time of the move to a saved view.
+    camera_transition: Option<(Camera, Camera, Instant)>,
+    views: Vec<SavedView>,
+    view_name: String,
+    /// Fly mode speed in Å per second.
+    fly_speed: f32,
+    /// Movement keys currently held, lowercased.
+    fly_keys: HashSet<String>,
+    /// Slowly spin the view while nobody is using the mouse or keyboard.
+    turntable: bool,
+    /// Turntable speed in degrees per second.
+    turntable_speed: f32,
+    last_interaction: Instant,
     dragging: bool,
+    /// Atom being dragged with the Move tool; while set, dragging moves it instead of the view.
+    atom_drag: Option<AtomId>,
+    /// Cursor path of a rectangle or lasso being dragged out; while set, dragging draws it
+    /// instead of turning the view.
+    region_drag: Option<Vec<Vec2>>,
     last_cursor: Option<Vec2>,
     drag_distance: f32,
     camera_dirty: bool,
-    selection: Option<AtomId>,
+    /// Draw R/S and E/Z descriptors next to stereocenters and double bonds.
+    stereo_labels: bool,
+    /// The molecule changed since descriptors were last assigned.
+    stereo_dirty: bool,
+    /// Compare the GPU instances with the structure after every edit and rebuild them when
+    /// they differ. On in debug builds and with `--check-render`.
+    check_render: bool,
+    /// Selected atoms; the last picked is the primary atom for single-atom tools.
+    selection: Selection,
     frame_timer: Instant,
     fps: f32,
     file_name: String,
     tool: Tool,
+    /// Snap sketched bond angles to 109.5°, 120° and 180°.
+    angle_snap: bool,
     edit_element: String,
+    smiles: String,
     move_step: f32,
+    cell_z: u32,
+    /// The Cell window's cell and metrics, kept between frames because the van der Waals
+    /// volume behind the packing coefficient is slow to rasterize.
+    cell_info: Option<(UnitCell, CellMetrics)>,
+    /// Atoms, the cell or `cell_z` changed since `cell_info` was computed.
+    cell_info_dirty: bool,
+    /// The formula and weight the status window shows.
+    formula: Option<(String, f32)>,
+    composition: Option<Composition>,
+    /// Atoms were added, removed or changed, or the cell or `cell_z` changed, since
+    /// `formula` and `composition` were computed.
+    composition_dirty: bool,
+    cell_editor: Option<CellEditor>,
+    clipboard: Option<Molecule>,
+    /// Bond pasted fragments to the selected atom, turned to the least crowded rotamer.
+    paste_onto_selection: bool,
+    source_path: String,
+    compare_steps: usize,
+    compare_rx: Option<mpsc::Receiver<LoadResult>>,
+    diff: Option<MoleculeDiff>,
+    checkpoints: Vec<Checkpoint>,
+    checkpoint_name: String,
+    session_path: String,
+    session_rx: Option<mpsc::Receiver<Result<Session, String>>>,
+    save_rx: Option<mpsc::Receiver<Result<String, String>>>,
+    pending_screenshot: Option<String>,
+    screenshot_path: String,
+    /// Post-process screenshots with depth of field, soft shadows and supersampling.
+    presentation: bool,
+    presentation_settings: PresentationSettings,
+    screenshot_rx: Option<mpsc::Receiver<Result<String, String>>>,
+    exit_after_screenshot: bool,
+    pending_figure: Option<String>,
+    camera_path: CameraPath,
+    /// Time in seconds the next keyframe is added at.
+    keyframe_time: f32,
+    animation_fps: u32,
+    /// Base name of rendered frames; the frame number is appended to its stem.
+    animation_path: String,
+    /// Start of the in-window preview of `camera_path`.
+    animation_preview: Option<Instant>,
+    movie: Option<MovieRender>,
+    turntable_frames: usize,
+    /// Run ffmpeg on rendered frames to make an MP4.
+    encode_video: bool,
+    video_rx: Option<mpsc::Receiver<Result<PathBuf, String>>>,
+    figure_path: String,
+    figure_columns: usize,
+    /// Give every saved view in a figure the same zoom.
+    figure_same_scale: bool,
+    export_path: String,
+    /// Decimal places for Save XYZ.
+    xyz_precision: usize,
+    /// Frames of a multi-frame file to export, e.g. `1-5, 8`; blank for all.
+    frame_selection: String,
+    /// Set while edits are being recorded as a trajectory.
+    recorder: Option<EditRecorder>,
+    print_settings: PrintSettings,
+    gaussian: GaussianSettings,
+    gallery_dir: String,
+    gallery: Option<Gallery>,
+    /// Lay the current molecule and `grid_samples` out side by side with one shared camera.
+    grid_enabled: bool,
+    grid_samples: Vec<GridSample>,
+    grid_path: String,
+    grid_rx: Option<mpsc::Receiver<LoadResult>>,
+    /// The samples or their styling changed since the grid instances were built.
+    grid_dirty: bool,
+    find_path: String,
+    find_query: String,
+    find_selection: String,
+    find_rx: Option<mpsc::Receiver<LoadResult>>,
+    /// Geometry cleanup running in the background.
+    cleanup_rx: Option<mpsc::Receiver<Minimized>>,
+    mirror_plane: MirrorPlane,
+    /// Index into `transform::AXES` and angle in degrees for the Rotate button.
+    rotation_axis: usize,
+    rotation_degrees: f32,
+    find_matches: Vec<FindMatch>,
+    find_focus: Option<usize>,
+    show_help: bool,
+    help_query: String,
+    tutorial: Option<Tutorial>,
     bond_target: Option<AtomId>,
+    /// Length in Å the Set Length button gives the selected bond.
+    bond_length: f32,
+    /// Angle in degrees the Set Angle button gives three selected atoms.
+    bond_angle: f32,
+    /// Amino acid the selected atom's residue is mutated into.
+    mutation_target: &'static str,
+    /// Ligand and shell chosen in the Pocket Wizard.
+    pocket_ligand: Option<ResidueId>,
+    pocket_options: PocketOptions,
+    /// Docking translation step in Å and rotation step in degrees.
+    docking_step: f32,
+    docking_angle: f32,
+    highlight_clashes: bool,
+    /// Atoms currently tinted as clashing.
+    docking_clashes: HashSet<AtomId>,
+    /// Substituents and output base path of the Substitution Sweep window.
+    sweep_list: String,
+    sweep_path: String,
+    /// Settings of the Perturbed Copies window; found atoms are frozen when asked.
+    perturb_count: usize,
+    perturb_amplitude: f32,
+    perturb_seed: u64,
+    perturb_freeze_found: bool,
+    perturb_path: String,
     status_message: String,
+    open_path: String,
+    import: ImportOptions,
+    /// Reopening the current file restores it as one undoable step instead of starting over.
+    keep_history_on_reload: bool,
+    /// The load in flight reopens the current file with its history kept.
+    reloading: bool,
+    /// Reload the open file when another program rewrites it; see `watch::FileWatch`.
+    watch_file: bool,
+    file_watch: Option<FileWatch>,
+    watch_polled: Instant,
+    confirm_discard: Option<DiscardAction>,
+    /// What a lenient open skipped, or why the last open failed.
+    problems: Vec<Problem>,
+    load_error: Option<String>,
+    show_problems: bool,
+    /// Set while the file is being read in the background.
+    load_progress: Option<Arc<LoadProgress>>,
+    /// Disulfides bonded and salt bridges found when the file was read; see
+    /// `residues::infer_links`.
+    inferred_links: Vec<InferredLink>,
+    show_links: bool,
+    /// Rules every molecule shown gets; see `sync_valence_rules`.
+    valence_rules: Arc<ValenceRules>,
+    /// Overrides on top of the standard table, as typed in the Preferences window.
+    valence_overrides: String,
+    valence_permissive: bool,
+    valence_error: Option<String>,
     modifiers: winit::keyboard::ModifiersState,
     representation: Representation,
+    palette: Palette,
+    hooks: Hooks,
+    script_path: String,
+    /// Script coloring shown instead of the palette's element colors.
+    coloring: Option<String>,
+    /// The molecule or the active coloring changed since the colors were computed.
+    coloring_dirty: bool,
+    /// Atoms edits touched since the colors were computed, whose rules and coloring are
+    /// re-evaluated on their own unless `coloring_dirty` asks for everything.
+    recolor_atoms: HashSet<AtomId>,
+    style: StyleOverrides,
+    /// Text of the style rules being edited; applied with "Apply rules".
+    style_rules: String,
+    style_element: String,
+    style_color: Option<[f32; 3]>,
+    style_radius_scale: Option<f32>,
+    /// Only applied to bonds.
+    style_dash: Option<BondDash>,
+    notes: Notes,
+    /// The atom or bond `note_draft` was loaded from.
+    note_target: Option<NoteTarget>,
+    note_draft: String,
+    annotations: Annotations,
+    measurements: Measurements,
+    /// What the text drawn next to each atom shows.
+    atom_labels: AtomLabels,
+    show_annotations: bool,
+    arrow_kind: ArrowKind,
+    /// Whether a new arrow starts, or ends, on the bond between two picked atoms.
+    arrow_from_bond: bool,
+    arrow_to_bond: bool,
+    partial_order: f32,
+    provenance: ProvenanceLog,
+    provenance_path: String,
 }
 
 impl UiState {
     fn new() -> Self {
         Self {
-            camera: Camera {
-                yaw: 0.8,
-                pitch: 0.3,
-                distance: 8.0,
-                target: Vec3::ZERO,
-            },
+            camera: Camera::default(),
+            camera_before_fly: CameraMode::Orbit,
+            camera_transition: None,
+            views: Vec::new(),
+            view_name: String::new(),
+            fly_speed: 5.0,
+            fly_keys: HashSet::new(),
+            turntable: false,
+            turntable_speed: 10.0,
+            last_interaction: Instant::now(),
             dragging: false,
+            atom_drag: None,
+            region_drag: None,
             last_cursor: None,
             drag_distance: 0.0,
             camera_dirty: true,
-            selection: None,
+            stereo_labels: false,
+            stereo_dirty: true,
+            check_render: cfg!(debug_assertions),
+            selection: Selection::new(),
             frame_timer: Instant::now(),
             fps: 0.0,
             file_name: SAMPLE_PATH.to_string(),
             tool: Tool::Select,
+            angle_snap: true,
             edit_element: "C".to_string(),
+            smiles: String::new(),
             move_step: 0.25,
+            cell_z: 1,
+            cell_info: None,
+            cell_info_dirty: true,
+            formula: None,
+            composition: None,
+            composition_dirty: true,
+            cell_editor: None,
+            clipboard: None,
+            paste_onto_selection: true,
+            source_path: SAMPLE_PATH.to_string(),
+            compare_steps: 1,
+            compare_rx: None,
+            diff: None,
+            checkpoints: Vec::new(),
+            checkpoint_name: String::new(),
+            session_path: SESSION_PATH.to_string(),
+            session_rx: None,
+            save_rx: None,
+            pending_screenshot: None,
+            screenshot_path: "screenshot.png".to_string(),
+            presentation: false,
+            presentation_settings: PresentationSettings::default(),
+            screenshot_rx: None,
+            exit_after_screenshot: false,
+            pending_figure: None,
+            camera_path: CameraPath::default(),
+            keyframe_time: 0.0,
+            animation_fps: animation::DEFAULT_FPS,
+            animation_path: "movie.png".to_string(),
+            animation_preview: None,
+            movie: None,
+            turntable_frames: 120,
+            encode_video: false,
+            video_rx: None,
+            figure_path: "figure.png".to_string(),
+            figure_columns: 2,
+            figure_same_scale: true,
+            export_path: "scene.pov".to_string(),
+            xyz_precision: XYZ_PRECISION,
+            frame_selection: String::new(),
+            recorder: None,
+            print_settings: PrintSettings::default(),
+            gaussian: GaussianSettings::default(),
+            gallery_dir: String::new(),
+            gallery: None,
+            grid_enabled: false,
+            grid_samples: Vec::new(),
+            grid_path: String::new(),
+            grid_rx: None,
+            grid_dirty: false,
+            find_path: String::new(),
+            find_query: String::new(),
+            find_selection: String::new(),
+            find_rx: None,
+            cleanup_rx: None,
+            mirror_plane: MirrorPlane::default(),
+            rotation_axis: 1,
+            rotation_degrees: 90.0,
+            find_matches: Vec::new(),
+            find_focus: None,
+            show_help: false,
+            help_query: String::new(),
+            tutorial: None,
             bond_target: None,
+            bond_length: 1.54,
+            bond_angle: 109.5,
+            mutation_target: residues::AMINO_ACIDS[0],
+            pocket_ligand: None,
+            pocket_options: PocketOptions::default(),
+            docking_step: 0.5,
+            docking_angle: 15.0,
+            highlight_clashes: true,
+            docking_clashes: HashSet::new(),
+            sweep_list: "F, Cl, Br, Me, OMe, CF3".to_string(),
+            sweep_path: "sweep.xyz".to_string(),
+            perturb_count: 10,
+            perturb_amplitude: 0.2,
+            perturb_seed: 1,
+            perturb_freeze_found: false,
+            perturb_path: "perturbed.xyz".to_string(),
             status_message: String::new(),
+            open_path: SAMPLE_PATH.to_string(),
+            import: ImportOptions::default(),
+            keep_history_on_reload: true,
+            reloading: false,
+            watch_file: false,
+            file_watch: None,
+            watch_polled: Instant::now(),
+            confirm_discard: None,
+            problems: Vec::new(),
+            load_error: None,
+            show_problems: false,
+            load_progress: None,
+            inferred_links: Vec::new(),
+            show_links: false,
+            valence_rules: Arc::new(ValenceRules::default()),
+            valence_overrides: String::new(),
+            valence_permissive: false,
+            valence_error: None,
             modifiers: winit::keyboard::ModifiersState::default(),
             representation: Representation::BallAndStick,
+            palette: Palette::default(),
+            hooks: Hooks::default(),
+            script_path: String::new(),
+            coloring: None,
+            coloring_dirty: false,
+            recolor_atoms: HashSet::new(),
+            style: StyleOverrides::default(),
+            style_rules: String::new(),
+            style_element: "C".to_string(),
+            style_color: None,
+            style_radius_scale: None,
+            style_dash: None,
+            notes: Notes::default(),
+            note_target: None,
+            note_draft: String::new(),
+            annotations: Annotations::default(),
+            measurements: Measurements::default(),
+            atom_labels: AtomLabels::default(),
+            show_annotations: true,
+            arrow_kind: ArrowKind::Curly,
+            arrow_from_bond: false,
+            arrow_to_bond: true,
+            partial_order: 0.5,
+            provenance: ProvenanceLog::default(),
+            provenance_path: "provenance.txt".to_string(),
         }
     }
 
     fn update_cursor(&mut self, position: Vec2) {
-        if self.dragging {
+        if let Some(path) = self.region_drag.as_mut() {
+            if let Some(last) = self.last_cursor {
+                self.drag_distance += position.distance(last);
+            }
+            path.push(position);
+        } else if self.dragging && self.atom_drag.is_none() {
             if let Some(last) = self.last_cursor {
                 let delta = position - last;
                 self.drag_distance += delta.length();
@@ -249,15 +948,88 @@ impl UiState {
         self.last_cursor = Some(position);
     }
 
+    /// The rectangle or lasso dragged out so far, in window pixels.
+    fn drag_region(&self) -> Option<Region> {
+        let path = self.region_drag.as_ref()?;
+        match self.tool {
+            Tool::BoxSelect => Some(Region::Rectangle(*path.first()?, *path.last()?)),
+            _ => Some(Region::Lasso(path.clone())),
+        }
+    }
+
     fn orbit(&mut self, delta: Vec2) {
-        let speed = 0.01;
-        self.camera.yaw -= delta.x * speed;
-        self.camera.pitch = (self.camera.pitch - delta.y * speed).clamp(-1.4, 1.4);
+        self.camera_transition = None;
+        self.camera.rotate(delta);
         self.camera_dirty = true;
     }
 
     fn zoom(&mut self, delta: f32) {
-        self.camera.distance = (self.camera.distance * (1.0 - delta)).clamp(2.0, 60.0);
+        if self.camera.mode == CameraMode::Fly {
+            self.fly_speed = (self.fly_speed * (1.0 + delta)).clamp(0.5, 100.0);
+            self.status_message = format!("fly speed {:.1} Å/s", self.fly_speed);
+            return;
+        }
+        self.camera_transition = None;
+        self.camera.zoom(delta);
+        self.camera_dirty = true;
+    }
+
+    fn fit_to_view(&mut self, molecule: &Molecule) {
+        if self.camera.fit(molecule, SPACE_FILL_RADIUS) {
+            self.camera_transition = None;
+            self.camera_dirty = true;
+        }
+    }
+
+    /// Frames the selected atom, the bond target and the focused substructure match.
+    fn frame_selection(&mut self, molecule: &Molecule) {
+        let mut atoms: Vec<AtomId> = self.selection.atoms().to_vec();
+        atoms.extend(self.bond_target);
+        if let Some(found) = self
+            .find_focus
+            .and_then(|index| self.find_matches.get(index))
+        {
+            atoms.extend(&found.atoms);
+        }
+        if self.camera.frame(molecule, &atoms, SPACE_FILL_RADIUS) {
+            self.camera_transition = None;
+            self.camera_dirty = true;
+        } else {
+            self.status_message = "nothing selected to frame".to_string();
+        }
+    }
+
+    /// Moves smoothly from the current view to `camera`.
+    fn go_to_view(&mut self, camera: Camera) {
+        self.camera_transition = Some((self.camera, camera, Instant::now()));
+        self.fly_keys.clear();
+    }
+
+    fn update_preview(&mut self) {
+        let Some(started) = self.animation_preview else {
+            return;
+        };
+        let time = started.elapsed().as_secs_f32();
+        if let Some(camera) = self.camera_path.camera_at(time) {
+            self.camera = camera;
+            self.camera_dirty = true;
+        }
+        if time >= self.camera_path.duration() {
+            self.animation_preview = None;
+        }
+    }
+
+    fn update_transition(&mut self) {
+        let Some((start, end, started)) = self.camera_transition else {
+            return;
+        };
+        let t = started.elapsed().as_secs_f32() / VIEW_TRANSITION.as_secs_f32();
+        // Ease in and out.
+        let eased = t.clamp(0.0, 1.0);
+        self.camera = start.interpolate(&end, eased * eased * (3.0 - 2.0 * eased));
+        if t >= 1.0 {
+            self.camera_transition = None;
+        }
         self.camera_dirty = true;
     }
 
@@ -271,7 +1043,70 @@ impl UiState {
         self.drag_distance = 0.0;
     }
 
-    fn update_fps(&mut self) {
+    /// Enters fly mode, or returns to the mode that was active before it.
+    fn toggle_fly(&mut self) {
+        let mode = if self.camera.mode == CameraMode::Fly {
+            self.camera_before_fly
+        } else {
+            self.camera_before_fly = self.camera.mode;
+            CameraMode::Fly
+        };
+        self.camera_transition = None;
+        self.camera.set_mode(mode);
+        self.fly_keys.clear();
+        self.camera_dirty = true;
+    }
+
+    /// Gives `molecule` the current valence rules unless it already has them, e.g. after it
+    /// was loaded or restored by undo.
+    fn sync_valence_rules(&self, molecule: &mut Molecule) {
+        let current: *const dyn ValenceModel = molecule.valence_model();
+        if !std::ptr::addr_eq(current, Arc::as_ptr(&self.valence_rules)) {
+            molecule.set_valence_model(self.valence_rules.clone());
+        }
+    }
+
+    /// Rebuilds the valence rules from the overrides text and the permissive toggle.
+    fn apply_valence_rules(&mut self) {
+        let mut rules = ValenceRules::default();
+        match rules.apply_overrides(&self.valence_overrides) {
+            Ok(()) => {
+                rules.permissive = self.valence_permissive;
+                self.valence_rules = Arc::new(rules);
+                self.valence_error = None;
+            }
+            Err(err) => self.valence_error = Some(format!("valence rules: {err}")),
+        }
+    }
+
+    fn update_fly(&mut self, frame_seconds: f32, molecule: Option<&Molecule>) {
+        if self.camera.mode != CameraMode::Fly || self.fly_keys.is_empty() {
+            return;
+        }
+        let axis = |positive: &str, negative: &str| {
+            self.fly_keys.contains(positive) as i32 as f32
+                - self.fly_keys.contains(negative) as i32 as f32
+        };
+        let (forward, right, up) = (axis("w", "s"), axis("d", "a"), axis("e", "q"));
+        let step = self.fly_speed * frame_seconds.min(MAX_FRAME_SECONDS);
+        self.camera.fly(forward, right, up, step, molecule);
+        self.camera_dirty = true;
+    }
+
+    fn update_turntable(&mut self, frame_seconds: f32) {
+        if !self.turntable
+            || self.camera.mode == CameraMode::Fly
+            || self.last_interaction.elapsed() < TURNTABLE_IDLE
+        {
+            return;
+        }
+        let angle = self.turntable_speed.to_radians() * frame_seconds.min(MAX_FRAME_SECONDS);
+        self.camera.turn(angle);
+        self.camera_dirty = true;
+    }
+
+    /// Advances the FPS estimate and returns the seconds since the previous frame.
+    fn update_fps(&mut self) -> f32 {
         let now = Instant::now();
         let dt = now - self.frame_timer;
         self.frame_timer = now;
@@ -284,11 +1119,12 @@ impl UiState {
                 self.fps * 0.9 + fps * 0.1
             };
         }
+        frame_seconds
     }
 }
 
-struct RenderState<'a> {
-    surface: wgpu::Surface<'a>,
+struct RenderState {
+    surface: wgpu::Surface<'static>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
@@ -310,23 +1146,134 @@ struct RenderState<'a> {
     bond_instance_ids: Vec<BondId>,
     bond_lookup: HashMap<BondId, usize>,
     atom_to_bonds: HashMap<AtomId, Vec<BondId>>,
+    /// Thinner lines beside double and triple bonds, redrawn per bond when bonds near them
+    /// change and rewritten in place when their atoms move.
+    order_strand_buffer: Option<wgpu::Buffer>,
+    order_strand_capacity: usize,
+    order_strand_data: Vec<BondInstanceData>,
+    /// The double or triple bond each strand slot belongs to.
+    order_strand_ids: Vec<BondId>,
+    order_strands: HashMap<BondId, OrderStrands>,
+    /// The double and triple bonds whose lines each atom's position affects.
+    strands_by_atom: HashMap<AtomId, Vec<BondId>>,
+    /// The double and triple bonds whose lines each bond's removal or change moves.
+    strands_by_bond: HashMap<BondId, Vec<BondId>>,
     atom_instance_capacity: usize,
     bond_instance_capacity: usize,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
     depth_texture: Texture,
     representation: Representation,
+    palette: Palette,
+    style: StyleOverrides,
+    /// Per-atom colors from a script coloring; atoms without one use the style and palette.
+    coloring: HashMap<AtomId, [f32; 3]>,
+    buffer_pool: BufferPool,
+    /// Panels drawn after the current molecule while the comparison grid is shown.
+    grid: Option<Vec<GridPanel>>,
+}
+
+/// Instance buffers not currently drawn from, kept for reuse. Sizes are powers of two in
+/// bytes, so atom, bond and grid buffers share them.
+#[derive(Default)]
+struct BufferPool {
+    free: Vec<wgpu::Buffer>,
+    stats: BufferStats,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct BufferStats {
+    /// Bytes in buffers handed out and not yet released.
+    live_bytes: u64,
+    free_bytes: u64,
+    created: u64,
+    reused: u64,
+    /// Buffers replaced by smaller ones after deletions.
+    shrinks: u64,
+}
+
+impl BufferPool {
+    /// A vertex buffer of at least `bytes`, reusing an idle one of the same size if any.
+    fn acquire(&mut self, device: &wgpu::Device, bytes: u64) -> wgpu::Buffer {
+        let size = bytes.max(INSTANCE_BUFFER_MIN_BYTES).next_power_of_two();
+        let buffer = match self.free.iter().position(|buffer| buffer.size() == size) {
+            Some(index) => {
+                self.stats.reused += 1;
+                self.stats.free_bytes -= size;
+                self.free.swap_remove(index)
+            }
+            None => {
+                self.stats.created += 1;
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("instance_buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            }
+        };
+        self.stats.live_bytes += size;
+        buffer
+    }
+
+    fn release(&mut self, buffer: wgpu::Buffer) {
+        self.stats.live_bytes -= buffer.size();
+        self.stats.free_bytes += buffer.size();
+        self.free.push(buffer);
+        while self.stats.free_bytes > BUFFER_POOL_BUDGET {
+            let Some(largest) = (0..self.free.len()).max_by_key(|&index| self.free[index].size())
+            else {
+                break;
+            };
+            let buffer = self.free.swap_remove(largest);
+            self.stats.free_bytes -= buffer.size();
+            buffer.destroy();
+        }
+    }
+}
+
+/// Radius, dash flags and color of `bond` under `style`: coordination bonds are thinner and
+/// dashed unless a bond override says otherwise, and bonds to hidden atoms get no radius.
+fn bond_look(style: &StyleOverrides, palette: Palette, bond: &Bond) -> (f32, u32, [f32; 3]) {
+    let flags = match style.bond_dash(bond) {
+        BondDash::Solid => 0,
+        BondDash::Dashed => FLAG_DASHED,
+        BondDash::Dotted => FLAG_DOTTED,
+    };
+    (
+        style.bond_radius(bond, BOND_RADIUS),
+        flags,
+        style.bond_color(bond.id, palette),
+    )
+}
+
+/// Whether a buffer of `capacity` instances must be replaced to hold `needed`: it is too
+/// small, or deletions left it mostly empty.
+fn needs_resize(needed: usize, capacity: usize, stride: usize) -> bool {
+    needed > capacity
+        || (capacity * stride > INSTANCE_BUFFER_MIN_BYTES as usize
+            && needed <= capacity / BUFFER_SHRINK_RATIO)
+}
+
+/// Instance buffers of one comparison-grid sample; `None` when it has nothing to draw.
+struct GridPanel {
+    atom_buffer: Option<wgpu::Buffer>,
+    atom_count: u32,
+    bond_buffer: Option<wgpu::Buffer>,
+    bond_count: u32,
 }
 
 struct Texture {
+    texture: wgpu::Texture,
     view: wgpu::TextureView,
 }
 
 impl Texture {
-    fn new_depth(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+    /// Copyable so screenshots can read depth back for post-processing.
+    fn new_depth(device: &wgpu::Device, width: u32, height: u32) -> Self {
         let size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
         let texture = device.create_texture(&wgpu::TextureDescriptor {
@@ -335,17 +1282,17 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth24Plus,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         });
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        Self { view }
+        Self { texture, view }
     }
 }
 
-impl<'a> RenderState<'a> {
-    async fn new(window: &'a Window) -> Self {
+impl RenderState {
+    async fn new(window: Arc<Window>) -> Self {
         let size = window.inner_size();
         let instance = wgpu::Instance::default();
         let surface = instance.create_surface(window).expect("create surface");
@@ -410,6 +1357,11 @@ impl<'a> RenderState<'a> {
         let camera_uniform = CameraUniform {
             view_proj: Mat4::IDENTITY.to_cols_array_2d(),
             camera_pos: [0.0; 4],
+            selected_color: [0.0; 4],
+            added_color: [0.0; 4],
+            modified_color: [0.0; 4],
+            match_color: [0.0; 4],
+            clash_color: [0.0; 4],
         };
         let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("camera_buffer"),
@@ -475,7 +1427,7 @@ impl<'a> RenderState<'a> {
                 conservative: false,
             },
             depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth24Plus,
+                format: DEPTH_FORMAT,
                 depth_write_enabled: true,
                 depth_compare: wgpu::CompareFunction::Less,
                 stencil: wgpu::StencilState::default(),
@@ -511,7 +1463,7 @@ impl<'a> RenderState<'a> {
                 conservative: false,
             },
             depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth24Plus,
+                format: DEPTH_FORMAT,
                 depth_write_enabled: true,
                 depth_compare: wgpu::CompareFunction::Less,
                 stencil: wgpu::StencilState::default(),
@@ -521,7 +1473,7 @@ impl<'a> RenderState<'a> {
             multiview: None,
         });
 
-        let depth_texture = Texture::new_depth(&device, &config);
+        let depth_texture = Texture::new_depth(&device, config.width, config.height);
 
         Self {
             surface,
@@ -546,12 +1498,24 @@ impl<'a> RenderState<'a> {
             bond_instance_ids: Vec::new(),
             bond_lookup: HashMap::new(),
             atom_to_bonds: HashMap::new(),
+            order_strand_buffer: None,
+            order_strand_capacity: 0,
+            order_strand_data: Vec::new(),
+            order_strand_ids: Vec::new(),
+            order_strands: HashMap::new(),
+            strands_by_atom: HashMap::new(),
+            strands_by_bond: HashMap::new(),
             atom_instance_capacity: 0,
             bond_instance_capacity: 0,
             camera_buffer,
             camera_bind_group,
             depth_texture,
             representation: Representation::BallAndStick,
+            palette: Palette::default(),
+            style: StyleOverrides::default(),
+            coloring: HashMap::new(),
+            buffer_pool: BufferPool::default(),
+            grid: None,
         }
     }
 
@@ -563,7 +1527,7 @@ impl<'a> RenderState<'a> {
         self.config.width = size.width;
         self.config.height = size.height;
         self.surface.configure(&self.device, &self.config);
-        self.depth_texture = Texture::new_depth(&self.device, &self.config);
+        self.depth_texture = Texture::new_depth(&self.device, size.width, size.height);
     }
 
     fn set_molecule(&mut self, molecule: &Molecule) {
@@ -571,8 +1535,8 @@ impl<'a> RenderState<'a> {
             .atoms_in_order()
             .map(|atom| InstanceData {
                 position: atom.position,
-                radius: self.atom_radius(),
-                color: element_color(&atom.element),
+                radius: self.style.radius(atom, self.atom_radius()),
+                color: self.atom_color(atom),
                 flags: 0,
             })
             .collect();
@@ -594,14 +1558,56 @@ impl<'a> RenderState<'a> {
         self.rebuild_bond_instances(molecule);
     }
 
-    fn set_representation(&mut self, representation: Representation, molecule: &Molecule) {
-        if self.representation == representation {
+    fn set_palette(&mut self, palette: Palette, molecule: &Molecule) {
+        self.palette = palette;
+        self.restyle(molecule);
+    }
+
+    fn set_style(&mut self, style: StyleOverrides, molecule: &Molecule) {
+        self.style = style;
+        self.style.apply_rules(molecule);
+        self.restyle(molecule);
+    }
+
+    /// Re-evaluates the style rules, since an edit can change which atoms they match.
+    fn apply_style_rules(&mut self, molecule: &Molecule) {
+        if self.style.rules.is_empty() {
             return;
         }
-        self.representation = representation;
-        let radius = self.atom_radius();
-        for instance in &mut self.atom_instance_data {
-            instance.radius = radius;
+        self.style.apply_rules(molecule);
+        self.restyle(molecule);
+    }
+
+    /// [`bond_look`] under the current style and palette.
+    fn styled_bond_look(&self, bond: &Bond) -> (f32, u32, [f32; 3]) {
+        bond_look(&self.style, self.palette, bond)
+    }
+
+    fn set_coloring(&mut self, coloring: HashMap<AtomId, [f32; 3]>, molecule: &Molecule) {
+        if coloring.is_empty() && self.coloring.is_empty() {
+            return;
+        }
+        self.coloring = coloring;
+        self.restyle(molecule);
+    }
+
+    fn atom_color(&self, atom: &Atom) -> [f32; 3] {
+        self.coloring
+            .get(&atom.id)
+            .copied()
+            .unwrap_or_else(|| self.style.color(atom, self.palette))
+    }
+
+    /// Recomputes every atom's color and radius, keeping positions and highlight flags.
+    fn restyle(&mut self, molecule: &Molecule) {
+        let base = self.atom_radius();
+        for (index, id) in self.atom_instance_ids.iter().enumerate() {
+            if let Some(atom) = molecule.get_atom(*id) {
+                let color = self.atom_color(atom);
+                let instance = &mut self.atom_instance_data[index];
+                instance.color = color;
+                instance.radius = self.style.radius(atom, base);
+            }
         }
         if let Some(buffer) = &self.atom_instance_buffer {
             if !self.atom_instance_data.is_empty() {
@@ -609,9 +1615,111 @@ impl<'a> RenderState<'a> {
                     .write_buffer(buffer, 0, bytemuck::cast_slice(&self.atom_instance_data));
             }
         }
+        for bond in molecule.bonds() {
+            if let Some(&index) = self.bond_lookup.get(&bond.id) {
+                let (radius, flags, color) = self.styled_bond_look(bond);
+                let instance = &mut self.bond_instance_data[index];
+                instance.radius = radius;
+                instance.color = color;
+                instance.flags = instance.flags & !(FLAG_DASHED | FLAG_DOTTED) | flags;
+            }
+        }
+        if let Some(buffer) = &self.bond_instance_buffer {
+            if !self.bond_instance_data.is_empty() {
+                self.queue
+                    .write_buffer(buffer, 0, bytemuck::cast_slice(&self.bond_instance_data));
+            }
+        }
+        self.rebuild_order_strands(molecule);
+    }
+
+    /// Recomputes the color and radius of just `atoms` and the bonds at them, rewriting only
+    /// their instance slots.
+    fn restyle_atoms(&mut self, molecule: &Molecule, atoms: &HashSet<AtomId>) {
+        let base = self.atom_radius();
+        for id in atoms {
+            let (Some(&index), Some(atom)) = (self.atom_lookup.get(id), molecule.get_atom(*id))
+            else {
+                continue;
+            };
+            let mut data = self.atom_instance_data[index];
+            data.color = self.atom_color(atom);
+            data.radius = self.style.radius(atom, base);
+            self.atom_instance_data[index] = data;
+            self.write_atom_instance(index, data);
+            for bond_id in self.atom_to_bonds.get(id).cloned().unwrap_or_default() {
+                self.update_bond_instance(bond_id, molecule);
+            }
+        }
+        self.refresh_order_strands(molecule, atoms);
+    }
+
+    /// How the instance arrays and lookups differ from `molecule`; empty when in step.
+    fn divergences(&self, molecule: &Molecule) -> Vec<Divergence> {
+        let atom_positions: Vec<[f32; 3]> = self
+            .atom_instance_data
+            .iter()
+            .map(|instance| instance.position)
+            .collect();
+        let bond_midpoints: Vec<[f32; 3]> = self
+            .bond_instance_data
+            .iter()
+            .map(|instance| instance.midpoint)
+            .collect();
+        let mirror = Mirror {
+            atom_ids: &self.atom_instance_ids,
+            atom_positions: &atom_positions,
+            atom_lookup: &self.atom_lookup,
+            bonds: (self.representation != Representation::SpaceFilling).then_some(BondMirror {
+                ids: &self.bond_instance_ids,
+                midpoints: &bond_midpoints,
+                lookup: &self.bond_lookup,
+                by_atom: &self.atom_to_bonds,
+            }),
+        };
+        check_mirror(molecule, &mirror)
+    }
+
+    fn gpu_usage(&self) -> GpuUsage {
+        GpuUsage {
+            atom_instances: self.atom_instance_data.len(),
+            bond_instances: self.bond_instance_data.len(),
+            buffer_bytes: self.buffer_pool.stats.live_bytes,
+            pooled_bytes: self.buffer_pool.stats.free_bytes,
+        }
+    }
+
+    fn set_representation(&mut self, representation: Representation, molecule: &Molecule) {
+        if self.representation == representation {
+            return;
+        }
+        self.representation = representation;
+        self.restyle(molecule);
         self.rebuild_bond_instances(molecule);
     }
 
+    /// The current view for the offline exporters: same representation, colors and camera.
+    fn scene(&self, molecule: &Molecule, camera: &Camera, aspect: f32) -> Scene {
+        let style = SceneStyle {
+            palette: self.palette,
+            overrides: &self.style,
+            atom_radius: self.atom_radius(),
+            bond_radius: (self.representation == Representation::BallAndStick)
+                .then_some(BOND_RADIUS),
+        };
+        let position = camera.position();
+        Scene {
+            camera: SceneCamera {
+                position: position.to_array(),
+                target: (position - camera.eye_direction()).to_array(),
+                up: camera.up().to_array(),
+                fov_degrees: FIELD_OF_VIEW_DEGREES,
+                aspect,
+            },
+            ..Scene::from_molecule(molecule, &style, FIELD_OF_VIEW_DEGREES, aspect)
+        }
+    }
+
     fn atom_radius(&self) -> f32 {
         match self.representation {
             Representation::BallAndStick => ATOM_RADIUS,
@@ -619,6 +1727,95 @@ impl<'a> RenderState<'a> {
         }
     }
 
+    /// Shows the comparison grid with one panel per sample, each shifted so its centroid
+    /// lands on `anchor` and the shared camera frames them all alike; `None` hides the grid.
+    fn set_grid(&mut self, samples: Option<&[GridSample]>, anchor: Option<Vec3>) {
+        for panel in self.grid.take().into_iter().flatten() {
+            for buffer in [panel.atom_buffer, panel.bond_buffer].into_iter().flatten() {
+                self.buffer_pool.release(buffer);
+            }
+        }
+        self.grid = samples.map(|samples| {
+            samples
+                .iter()
+                .map(|sample| self.grid_panel(&sample.molecule, anchor))
+                .collect()
+        });
+    }
+
+    fn grid_panel(&mut self, molecule: &Molecule, anchor: Option<Vec3>) -> GridPanel {
+        let offset = match (anchor, geometry::centroid(molecule)) {
+            (Some(anchor), Some(centroid)) => anchor - Vec3::from_array(centroid),
+            _ => Vec3::ZERO,
+        };
+        let moved = |position: [f32; 3]| (Vec3::from_array(position) + offset).to_array();
+        // Rules match by what an atom is, so they are evaluated per sample.
+        let mut style = self.style.clone();
+        style.apply_rules(molecule);
+        let atoms: Vec<InstanceData> = molecule
+            .atoms_in_order()
+            .map(|atom| InstanceData {
+                position: moved(atom.position),
+                radius: style.radius(atom, self.atom_radius()),
+                color: style.color(atom, self.palette),
+                flags: 0,
+            })
+            .collect();
+        let bonds: Vec<BondInstanceData> = if self.representation == Representation::SpaceFilling {
+            Vec::new()
+        } else {
+            molecule
+                .bonds()
+                .filter_map(|bond| {
+                    let (a, b) = (molecule.get_atom(bond.a)?, molecule.get_atom(bond.b)?);
+                    let instance =
+                        bond_instance_from_positions(moved(a.position), moved(b.position));
+                    let (radius, flags, color) = bond_look(&style, self.palette, bond);
+                    Some(BondInstanceData {
+                        midpoint: instance.midpoint,
+                        direction: instance.direction,
+                        length: instance.length,
+                        radius,
+                        color,
+                        flags,
+                    })
+                })
+                .collect()
+        };
+        let mut buffer = |contents: &[u8]| {
+            (!contents.is_empty()).then(|| {
+                let buffer = self
+                    .buffer_pool
+                    .acquire(&self.device, contents.len() as u64);
+                self.queue.write_buffer(&buffer, 0, contents);
+                buffer
+            })
+        };
+        GridPanel {
+            atom_buffer: buffer(bytemuck::cast_slice(&atoms)),
+            atom_count: atoms.len() as u32,
+            bond_buffer: buffer(bytemuck::cast_slice(&bonds)),
+            bond_count: bonds.len() as u32,
+        }
+    }
+
+    /// Viewports of a `width` × `height` target: the current molecule first, then one per
+    /// grid panel.
+    fn cells(&self, width: f32, height: f32) -> Vec<GridCell> {
+        grid_cells(1 + self.grid.as_ref().map_or(0, Vec::len), width, height)
+    }
+
+    /// Size of the viewport the current molecule is drawn in, at the window's top-left.
+    fn view_size(&self) -> winit::dpi::PhysicalSize<u32> {
+        let cell = self.cells(self.size.width as f32, self.size.height as f32)[0];
+        winit::dpi::PhysicalSize::new(cell.width as u32, cell.height as u32)
+    }
+
+    fn view_aspect(&self) -> f32 {
+        let size = self.view_size();
+        size.width as f32 / size.height.max(1) as f32
+    }
+
     fn rebuild_bond_instances(&mut self, molecule: &Molecule) {
         self.bond_instance_data.clear();
         self.bond_instance_ids.clear();
@@ -626,6 +1823,7 @@ impl<'a> RenderState<'a> {
         self.atom_to_bonds.clear();
         if self.representation == Representation::SpaceFilling {
             self.ensure_bond_capacity(0);
+            self.rebuild_order_strands(molecule);
             return;
         }
         for bond in molecule.bonds() {
@@ -633,6 +1831,7 @@ impl<'a> RenderState<'a> {
                 (molecule.get_atom(bond.a), molecule.get_atom(bond.b))
             {
                 let instance = bond_instance_from_positions(atom_a.position, atom_b.position);
+                let (radius, flags, color) = self.styled_bond_look(bond);
                 self.bond_instance_ids.push(bond.id);
                 self.bond_lookup
                     .insert(bond.id, self.bond_instance_data.len());
@@ -640,9 +1839,9 @@ impl<'a> RenderState<'a> {
                     midpoint: instance.midpoint,
                     direction: instance.direction,
                     length: instance.length,
-                    radius: BOND_RADIUS,
-                    color: [0.7, 0.7, 0.7],
-                    flags: 0,
+                    radius,
+                    color,
+                    flags,
                 });
                 self.atom_to_bonds.entry(bond.a).or_default().push(bond.id);
                 self.atom_to_bonds.entry(bond.b).or_default().push(bond.id);
@@ -655,56 +1854,333 @@ impl<'a> RenderState<'a> {
                     .write_buffer(buffer, 0, bytemuck::cast_slice(&self.bond_instance_data));
             }
         }
+        self.rebuild_order_strands(molecule);
     }
 
-    fn ensure_atom_capacity(&mut self, needed: usize) {
-        if needed <= self.atom_instance_capacity {
-            return;
+    /// Redraws the extra lines of double and triple bonds, beside the bonds as drawn and in
+    /// their colors and highlights.
+    fn rebuild_order_strands(&mut self, molecule: &Molecule) {
+        self.order_strand_data.clear();
+        self.order_strand_ids.clear();
+        self.order_strands.clear();
+        self.strands_by_atom.clear();
+        self.strands_by_bond.clear();
+        let bonds: Vec<BondId> = molecule
+            .bonds()
+            .filter(|bond| bond.order > 1)
+            .map(|bond| bond.id)
+            .collect();
+        for bond_id in bonds {
+            self.add_order_strands(molecule, bond_id);
         }
-        let new_capacity = needed.next_power_of_two().max(1);
-        let buffer_size =
-            (new_capacity * std::mem::size_of::<InstanceData>()) as wgpu::BufferAddress;
-        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("atom_instance_buffer"),
-            size: buffer_size,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        self.ensure_strand_capacity(self.order_strand_data.len());
+        self.write_order_strands(0..self.order_strand_data.len());
+    }
+
+    /// Redraws the extra lines of just `bonds`, after they or bonds next to them were added,
+    /// removed or changed, leaving the other slots of the strand buffer alone.
+    fn update_order_strands(&mut self, molecule: &Molecule, bonds: &HashSet<BondId>) {
+        for &bond_id in bonds {
+            self.remove_order_strands(bond_id);
+        }
+        let start = self.order_strand_data.len();
+        for &bond_id in bonds {
+            self.add_order_strands(molecule, bond_id);
+        }
+        self.ensure_strand_capacity(self.order_strand_data.len());
+        self.write_order_strands(start..self.order_strand_data.len());
+    }
+
+    /// The double and triple bonds whose lines bond `bond_id` moves: itself, those it was
+    /// found next to, and those at its ends now.
+    fn strands_near(&self, molecule: &Molecule, bond_id: BondId) -> Vec<BondId> {
+        let mut near = vec![bond_id];
+        near.extend(self.strands_by_bond.get(&bond_id).into_iter().flatten());
+        if let Some(bond) = molecule.get_bond(bond_id) {
+            for end in [bond.a, bond.b] {
+                near.extend(
+                    self.strands_by_atom
+                        .get(&end)
+                        .into_iter()
+                        .flatten()
+                        .filter(|id| {
+                            self.order_strands
+                                .get(id)
+                                .is_some_and(|strands| strands.a == end || strands.b == end)
+                        }),
+                );
+            }
+        }
+        near
+    }
+
+    /// Appends the extra lines of bond `bond_id` when it is a drawn double or triple bond,
+    /// without writing them to the GPU.
+    fn add_order_strands(&mut self, molecule: &Molecule, bond_id: BondId) {
+        let Some(bond) = molecule.get_bond(bond_id).filter(|bond| bond.order > 1) else {
+            return;
+        };
+        let mut substituents = Vec::new();
+        let mut neighbors = Vec::new();
+        for (end, other) in [(bond.a, bond.b), (bond.b, bond.a)] {
+            for &neighbor in self.atom_to_bonds.get(&end).into_iter().flatten() {
+                if !neighbors.contains(&neighbor) {
+                    neighbors.push(neighbor);
+                }
+                let Some(next) = molecule.get_bond(neighbor) else {
+                    continue;
+                };
+                let id = if next.a == end { next.b } else { next.a };
+                if id != other {
+                    substituents.push(id);
+                }
+            }
+        }
+        let mut strands = OrderStrands {
+            bond: bond.id,
+            a: bond.a,
+            b: bond.b,
+            order: bond.order,
+            substituents,
+            neighbors,
+            slots: Vec::new(),
+        };
+        let instances = self.strand_instances(molecule, &strands);
+        let start = self.order_strand_data.len();
+        strands.slots = (start..start + instances.len()).collect();
+        self.order_strand_data.extend(instances);
+        self.order_strand_ids
+            .extend(std::iter::repeat_n(bond_id, strands.slots.len()));
+        for atom in [strands.a, strands.b]
+            .into_iter()
+            .chain(strands.substituents.iter().copied())
+        {
+            let entry = self.strands_by_atom.entry(atom).or_default();
+            if !entry.contains(&bond_id) {
+                entry.push(bond_id);
+            }
+        }
+        for &neighbor in &strands.neighbors {
+            self.strands_by_bond
+                .entry(neighbor)
+                .or_default()
+                .push(bond_id);
+        }
+        self.order_strands.insert(bond_id, strands);
+    }
+
+    /// Drops the extra lines of bond `bond_id`, moving the last lines into the freed slots.
+    fn remove_order_strands(&mut self, bond_id: BondId) {
+        let Some(strands) = self.order_strands.remove(&bond_id) else {
+            return;
+        };
+        for atom in [strands.a, strands.b].iter().chain(&strands.substituents) {
+            if let Some(ids) = self.strands_by_atom.get_mut(atom) {
+                ids.retain(|&id| id != bond_id);
+                if ids.is_empty() {
+                    self.strands_by_atom.remove(atom);
+                }
+            }
+        }
+        for neighbor in &strands.neighbors {
+            if let Some(ids) = self.strands_by_bond.get_mut(neighbor) {
+                ids.retain(|&id| id != bond_id);
+                if ids.is_empty() {
+                    self.strands_by_bond.remove(neighbor);
+                }
+            }
+        }
+        // Highest first, so the last slot moved into a freed one is never one of these.
+        let mut slots = strands.slots;
+        slots.sort_unstable_by(|a, b| b.cmp(a));
+        for slot in slots {
+            let last = self.order_strand_data.len() - 1;
+            self.order_strand_data.swap_remove(slot);
+            self.order_strand_ids.swap_remove(slot);
+            let Some(&moved) = self.order_strand_ids.get(slot) else {
+                continue;
+            };
+            if let Some(other) = self.order_strands.get_mut(&moved) {
+                for other_slot in &mut other.slots {
+                    if *other_slot == last {
+                        *other_slot = slot;
+                    }
+                }
+            }
+            self.write_order_strands(slot..slot + 1);
+        }
+    }
+
+    /// Copies the strand slots in `range` to the GPU buffer.
+    fn write_order_strands(&self, range: std::ops::Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        if let Some(buffer) = &self.order_strand_buffer {
+            let offset = range.start * std::mem::size_of::<BondInstanceData>();
+            self.queue.write_buffer(
+                buffer,
+                offset as wgpu::BufferAddress,
+                bytemuck::cast_slice(&self.order_strand_data[range]),
+            );
+        }
+    }
+
+    /// The extra lines of one double or triple bond, beside its main instance; none while the
+    /// bond is not drawn.
+    fn strand_instances(
+        &self,
+        molecule: &Molecule,
+        strands: &OrderStrands,
+    ) -> Vec<BondInstanceData> {
+        let position = |id: AtomId| {
+            molecule
+                .get_atom(id)
+                .map(|atom| Vec3::from_array(atom.position))
+        };
+        let Some(main) = self
+            .bond_lookup
+            .get(&strands.bond)
+            .and_then(|&index| self.bond_instance_data.get(index))
+        else {
+            return Vec::new();
+        };
+        let (Some(a), Some(b)) = (position(strands.a), position(strands.b)) else {
+            return Vec::new();
+        };
+        let substituents = strands.substituents.iter().filter_map(|&id| position(id));
+        let side = geometry::bond_side(a, b, substituents);
+        let trim = (b - a) * ORDER_STRAND_TRIM;
+        geometry::order_strands(strands.order)
+            .iter()
+            .map(|offset| {
+                let shift = side * *offset * main.radius * ORDER_STRAND_SPACING;
+                let instance = bond_instance_from_positions(
+                    (a + trim + shift).into(),
+                    (b - trim + shift).into(),
+                );
+                BondInstanceData {
+                    midpoint: instance.midpoint,
+                    direction: instance.direction,
+                    length: instance.length,
+                    radius: main.radius * ORDER_STRAND_RADIUS,
+                    ..*main
+                }
+            })
+            .collect()
+    }
+
+    /// Rewrites in place the extra lines that `atoms` carry or lean towards, after they moved
+    /// or were restyled, leaving the rest of the strand buffer alone.
+    fn refresh_order_strands(&mut self, molecule: &Molecule, atoms: &HashSet<AtomId>) {
+        let affected: HashSet<BondId> = atoms
+            .iter()
+            .filter_map(|atom| self.strands_by_atom.get(atom))
+            .flatten()
+            .copied()
+            .collect();
+        let mut redraw = HashSet::new();
+        for bond_id in affected {
+            let strands = &self.order_strands[&bond_id];
+            let instances = self.strand_instances(molecule, strands);
+            if instances.len() != strands.slots.len() {
+                redraw.insert(bond_id);
+                continue;
+            }
+            for (slot, instance) in strands.slots.clone().into_iter().zip(instances) {
+                self.order_strand_data[slot] = instance;
+                self.write_order_strands(slot..slot + 1);
+            }
+        }
+        if !redraw.is_empty() {
+            self.update_order_strands(molecule, &redraw);
+        }
+    }
+
+    /// Grows the strand buffer to hold `needed` lines, or shrinks it when mostly empty.
+    fn ensure_strand_capacity(&mut self, needed: usize) {
+        let stride = std::mem::size_of::<BondInstanceData>();
+        if !needs_resize(needed, self.order_strand_capacity, stride) {
+            return;
+        }
+        let buffer = self
+            .buffer_pool
+            .acquire(&self.device, (needed.max(1) * stride) as u64);
+        if !self.order_strand_data.is_empty() {
+            self.queue
+                .write_buffer(&buffer, 0, bytemuck::cast_slice(&self.order_strand_data));
+        }
+        let capacity = buffer.size() as usize / stride;
+        if let Some(old) = self.order_strand_buffer.replace(buffer) {
+            if capacity < self.order_strand_capacity {
+                self.buffer_pool.stats.shrinks += 1;
+            }
+            self.buffer_pool.release(old);
+        }
+        self.order_strand_capacity = capacity;
+    }
+
+    /// Grows the atom buffer to hold `needed` instances, or shrinks it when mostly empty.
+    fn ensure_atom_capacity(&mut self, needed: usize) {
+        let stride = std::mem::size_of::<InstanceData>();
+        if !needs_resize(needed, self.atom_instance_capacity, stride) {
+            return;
+        }
+        let buffer = self
+            .buffer_pool
+            .acquire(&self.device, (needed.max(1) * stride) as u64);
         if !self.atom_instance_data.is_empty() {
             self.queue
                 .write_buffer(&buffer, 0, bytemuck::cast_slice(&self.atom_instance_data));
         }
-        self.atom_instance_buffer = Some(buffer);
-        self.atom_instance_capacity = new_capacity;
+        let capacity = buffer.size() as usize / stride;
+        if let Some(old) = self.atom_instance_buffer.replace(buffer) {
+            if capacity < self.atom_instance_capacity {
+                self.buffer_pool.stats.shrinks += 1;
+                self.atom_instance_data.shrink_to(capacity);
+                self.atom_instance_ids.shrink_to(capacity);
+            }
+            self.buffer_pool.release(old);
+        }
+        self.atom_instance_capacity = capacity;
     }
 
     fn ensure_bond_capacity(&mut self, needed: usize) {
-        if needed <= self.bond_instance_capacity {
+        let stride = std::mem::size_of::<BondInstanceData>();
+        if !needs_resize(needed, self.bond_instance_capacity, stride) {
             return;
         }
-        let new_capacity = needed.next_power_of_two().max(1);
-        let buffer_size =
-            (new_capacity * std::mem::size_of::<BondInstanceData>()) as wgpu::BufferAddress;
-        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("bond_instance_buffer"),
-            size: buffer_size,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let buffer = self
+            .buffer_pool
+            .acquire(&self.device, (needed.max(1) * stride) as u64);
         if !self.bond_instance_data.is_empty() {
             self.queue
                 .write_buffer(&buffer, 0, bytemuck::cast_slice(&self.bond_instance_data));
         }
-        self.bond_instance_buffer = Some(buffer);
-        self.bond_instance_capacity = new_capacity;
+        let capacity = buffer.size() as usize / stride;
+        if let Some(old) = self.bond_instance_buffer.replace(buffer) {
+            if capacity < self.bond_instance_capacity {
+                self.buffer_pool.stats.shrinks += 1;
+                self.bond_instance_data.shrink_to(capacity);
+                self.bond_instance_ids.shrink_to(capacity);
+            }
+            self.buffer_pool.release(old);
+        }
+        self.bond_instance_capacity = capacity;
+    }
+
+    /// Shrinks instance buffers that deletions left mostly empty.
+    fn compact_instances(&mut self) {
+        self.ensure_atom_capacity(self.atom_instance_data.len());
+        self.ensure_bond_capacity(self.bond_instance_data.len());
     }
 
     fn add_atom_instance(&mut self, atom: &Atom) {
         let index = self.atom_instance_data.len();
         self.atom_instance_data.push(InstanceData {
             position: atom.position,
-            radius: self.atom_radius(),
-            color: element_color(&atom.element),
+            radius: self.style.radius(atom, self.atom_radius()),
+            color: self.atom_color(atom),
             flags: 0,
         });
         self.atom_instance_ids.push(atom.id);
@@ -776,7 +2252,7 @@ impl<'a> RenderState<'a> {
         if self.representation == Representation::SpaceFilling {
             return;
         }
-        let Some(bond) = molecule.bonds().find(|bond| bond.id == bond_id) else {
+        let Some(bond) = molecule.get_bond(bond_id) else {
             return;
         };
         let (Some(atom_a), Some(atom_b)) = (molecule.get_atom(bond.a), molecule.get_atom(bond.b))
@@ -784,14 +2260,15 @@ impl<'a> RenderState<'a> {
             return;
         };
         let instance = bond_instance_from_positions(atom_a.position, atom_b.position);
+        let (radius, flags, color) = self.styled_bond_look(bond);
         let index = self.bond_instance_data.len();
         self.bond_instance_data.push(BondInstanceData {
             midpoint: instance.midpoint,
             direction: instance.direction,
             length: instance.length,
-            radius: BOND_RADIUS,
-            color: [0.7, 0.7, 0.7],
-            flags: 0,
+            radius,
+            color,
+            flags,
         });
         self.bond_instance_ids.push(bond_id);
         self.bond_lookup.insert(bond_id, index);
@@ -839,7 +2316,7 @@ impl<'a> RenderState<'a> {
         let Some(index) = self.bond_lookup.get(&bond_id).copied() else {
             return;
         };
-        let Some(bond) = molecule.bonds().find(|bond| bond.id == bond_id) else {
+        let Some(bond) = molecule.get_bond(bond_id) else {
             return;
         };
         let (Some(atom_a), Some(atom_b)) = (molecule.get_atom(bond.a), molecule.get_atom(bond.b))
@@ -847,10 +2324,14 @@ impl<'a> RenderState<'a> {
             return;
         };
         let instance = bond_instance_from_positions(atom_a.position, atom_b.position);
+        let (radius, dashed, color) = self.styled_bond_look(bond);
         if let Some(data) = self.bond_instance_data.get_mut(index) {
             data.midpoint = instance.midpoint;
             data.direction = instance.direction;
             data.length = instance.length;
+            data.radius = radius;
+            data.color = color;
+            data.flags = data.flags & !(FLAG_DASHED | FLAG_DOTTED) | dashed;
             if let Some(buffer) = &self.bond_instance_buffer {
                 let offset =
                     (index * std::mem::size_of::<BondInstanceData>()) as wgpu::BufferAddress;
@@ -860,31 +2341,135 @@ impl<'a> RenderState<'a> {
         }
     }
 
-    fn update_selection(&mut self, previous: Option<AtomId>, next: Option<AtomId>) {
-        if let Some(prev) = previous {
-            if let Some(index) = self.atom_lookup.get(&prev).copied() {
-                let updated = self.atom_instance_data.get_mut(index).map(|data| {
-                    data.flags &= !1;
-                    *data
-                });
-                if let Some(data) = updated {
-                    self.write_atom_instance(index, data);
+    /// Moves the selection highlight from the atoms of `previous` to those of `next`,
+    /// rewriting only the atoms in just one of them.
+    fn update_selection(&mut self, previous: &Selection, next: &Selection) {
+        let cleared = previous.atoms().iter().filter(|&&id| !next.contains(id));
+        let added = next.atoms().iter().filter(|&&id| !previous.contains(id));
+        let flagged = cleared
+            .map(|&id| (id, false))
+            .chain(added.map(|&id| (id, true)));
+        for (atom_id, selected) in flagged {
+            let Some(index) = self.atom_lookup.get(&atom_id).copied() else {
+                continue;
+            };
+            let updated = self.atom_instance_data.get_mut(index).map(|data| {
+                if selected {
+                    data.flags |= FLAG_SELECTED;
+                } else {
+                    data.flags &= !FLAG_SELECTED;
                 }
+                *data
+            });
+            if let Some(data) = updated {
+                self.write_atom_instance(index, data);
             }
         }
-        if let Some(next) = next {
-            if let Some(index) = self.atom_lookup.get(&next).copied() {
-                let updated = self.atom_instance_data.get_mut(index).map(|data| {
-                    data.flags |= 1;
-                    *data
-                });
-                if let Some(data) = updated {
-                    self.write_atom_instance(index, data);
-                }
+    }
+
+    fn set_diff_highlight(&mut self, diff: Option<&MoleculeDiff>) {
+        let mask = FLAG_ADDED | FLAG_MODIFIED;
+        let (added, modified): (HashSet<AtomId>, HashSet<AtomId>) = diff
+            .map(|diff| {
+                (
+                    diff.added_atoms.iter().copied().collect(),
+                    diff.modified_atoms.iter().copied().collect(),
+                )
+            })
+            .unwrap_or_default();
+        for index in 0..self.atom_instance_data.len() {
+            let id = self.atom_instance_ids[index];
+            let mut data = self.atom_instance_data[index];
+            let mut flags = data.flags & !mask;
+            if added.contains(&id) {
+                flags |= FLAG_ADDED;
+            } else if modified.contains(&id) {
+                flags |= FLAG_MODIFIED;
+            }
+            if flags != data.flags {
+                data.flags = flags;
+                self.atom_instance_data[index] = data;
+                self.write_atom_instance(index, data);
+            }
+        }
+        let (added_bonds, modified_bonds): (HashSet<BondId>, HashSet<BondId>) = diff
+            .map(|diff| {
+                (
+                    diff.added_bonds.iter().copied().collect(),
+                    diff.modified_bonds.iter().copied().collect(),
+                )
+            })
+            .unwrap_or_default();
+        for index in 0..self.bond_instance_data.len() {
+            let id = self.bond_instance_ids[index];
+            let mut data = self.bond_instance_data[index];
+            let mut flags = data.flags & !mask;
+            if added_bonds.contains(&id) {
+                flags |= FLAG_ADDED;
+            } else if modified_bonds.contains(&id) {
+                flags |= FLAG_MODIFIED;
+            }
+            if flags != data.flags {
+                data.flags = flags;
+                self.bond_instance_data[index] = data;
+                self.write_bond_instance(index, data);
+            }
+        }
+    }
+
+    fn set_match_highlight(&mut self, atoms: &HashSet<AtomId>, bonds: &HashSet<BondId>) {
+        for index in 0..self.atom_instance_data.len() {
+            let mut data = self.atom_instance_data[index];
+            let flags = if atoms.contains(&self.atom_instance_ids[index]) {
+                data.flags | FLAG_MATCH
+            } else {
+                data.flags & !FLAG_MATCH
+            };
+            if flags != data.flags {
+                data.flags = flags;
+                self.atom_instance_data[index] = data;
+                self.write_atom_instance(index, data);
+            }
+        }
+        for index in 0..self.bond_instance_data.len() {
+            let mut data = self.bond_instance_data[index];
+            let flags = if bonds.contains(&self.bond_instance_ids[index]) {
+                data.flags | FLAG_MATCH
+            } else {
+                data.flags & !FLAG_MATCH
+            };
+            if flags != data.flags {
+                data.flags = flags;
+                self.bond_instance_data[index] = data;
+                self.write_bond_instance(index, data);
+            }
+        }
+    }
+
+    fn set_clash_highlight(&mut self, atoms: &HashSet<AtomId>) {
+        for index in 0..self.atom_instance_data.len() {
+            let mut data = self.atom_instance_data[index];
+            let flags = if atoms.contains(&self.atom_instance_ids[index]) {
+                data.flags | FLAG_CLASH
+            } else {
+                data.flags & !FLAG_CLASH
+            };
+            if flags != data.flags {
+                data.flags = flags;
+                self.atom_instance_data[index] = data;
+                self.write_atom_instance(index, data);
             }
         }
     }
 
+    fn write_bond_instance(&self, index: usize, data: BondInstanceData) {
+        if let Some(buffer) = &self.bond_instance_buffer {
+            let offset = (index * std::mem::size_of::<BondInstanceData>()) as wgpu::BufferAddress;
+            self.queue
+                .write_buffer(buffer, offset, bytemuck::bytes_of(&data));
+        }
+    }
+
     fn write_atom_instance(&self, index: usize, data: InstanceData) {
         if let Some(buffer) = &self.atom_instance_buffer {
             let offset = (index * std::mem::size_of::<InstanceData>()) as wgpu::BufferAddress;
@@ -896,9 +2481,15 @@ impl<'a> RenderState<'a> {
     fn update_camera(&self, camera: &Camera, aspect: f32) {
         let view_proj = camera.view_proj(aspect).to_cols_array_2d();
         let position = camera.position();
+        let rgba = |[r, g, b]: [f32; 3]| [r, g, b, 1.0];
         let uniform = CameraUniform {
             view_proj,
             camera_pos: [position.x, position.y, position.z, 1.0],
+            selected_color: rgba(self.palette.selection_color()),
+            added_color: rgba(self.palette.added_color()),
+            modified_color: rgba(self.palette.modified_color()),
+            match_color: rgba(self.palette.match_color()),
+            clash_color: rgba(self.palette.clash_color()),
         };
         self.queue
             .write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&uniform));
@@ -910,44 +2501,271 @@ impl<'a> RenderState<'a> {
         camera: &Camera,
         size: winit::dpi::PhysicalSize<u32>,
     ) -> Option<AtomId> {
-        if size.width == 0 || size.height == 0 {
-            return None;
+        let ray = cursor_ray(cursor, camera, size)?;
+        self.atom_instance_data
+            .iter()
+            .zip(&self.atom_instance_ids)
+            .filter_map(|(instance, &id)| {
+                let center = Vec3::from_array(instance.position);
+                Some((id, pick::ray_sphere(&ray, center, instance.radius)?))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(id, _)| id)
+    }
+
+    /// Atoms drawn with their centers inside `region`, in drawing order.
+    fn atoms_in_region(
+        &self,
+        region: &Region,
+        camera: &Camera,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Vec<AtomId> {
+        let viewport = Vec2::new(size.width as f32, size.height as f32);
+        let view_proj = camera.view_proj(viewport.x / viewport.y.max(1.0));
+        self.atom_instance_data
+            .iter()
+            .zip(&self.atom_instance_ids)
+            .filter(|(instance, _)| instance.radius > 0.0)
+            .filter(|(instance, _)| {
+                let center = Vec3::from_array(instance.position);
+                pick::screen_from_world(center, viewport, view_proj)
+                    .is_some_and(|point| region.contains(point))
+            })
+            .map(|(_, &id)| id)
+            .collect()
+    }
+
+    /// The bond under `cursor`, as drawn; atoms are not considered.
+    fn pick_bond(
+        &self,
+        cursor: Vec2,
+        camera: &Camera,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Option<BondId> {
+        let ray = cursor_ray(cursor, camera, size)?;
+        self.bond_instance_data
+            .iter()
+            .zip(&self.bond_instance_ids)
+            .filter_map(|(instance, &id)| {
+                let midpoint = Vec3::from_array(instance.midpoint);
+                let half = Vec3::from_array(instance.direction) * instance.length * 0.5;
+                let t =
+                    pick::ray_cylinder(&ray, midpoint - half, midpoint + half, instance.radius)?;
+                Some((id, t))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(id, _)| id)
+    }
+
+    /// Renders one frame offscreen at `scale` times the window size and reads back colors
+    /// and linear depth.
+    fn capture(&self, scale: u32) -> Result<Frame, String> {
+        let max_dimension = self.device.limits().max_texture_dimension_2d;
+        let largest = self.config.width.max(self.config.height).max(1);
+        let scale = scale.clamp(1, (max_dimension / largest).max(1));
+        let (width, height) = (self.config.width * scale, self.config.height * scale);
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("capture_texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth = Texture::new_depth(&self.device, width, height);
+        // Both formats are 4 bytes per pixel.
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let bytes_per_row = (width * 4).div_ceil(alignment) * alignment;
+        let readback = |label| {
+            self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: (bytes_per_row * height) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            })
+        };
+        let (color_buffer, depth_buffer) = (readback("capture_buffer"), readback("depth_buffer"));
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("capture_encoder"),
+            });
+        self.encode_scene(&mut encoder, &view, &depth.view, width, height);
+        for (source, aspect, buffer) in [
+            (&texture, wgpu::TextureAspect::All, &color_buffer),
+            (
+                &depth.texture,
+                wgpu::TextureAspect::DepthOnly,
+                &depth_buffer,
+            ),
+        ] {
+            encoder.copy_texture_to_buffer(
+                wgpu::ImageCopyTexture {
+                    texture: source,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect,
+                },
+                wgpu::ImageCopyBuffer {
+                    buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(bytes_per_row),
+                        rows_per_image: Some(height),
+                    },
+                },
+                size,
+            );
         }
-        let ndc = Vec2::new(
-            (2.0 * cursor.x / size.width as f32) - 1.0,
-            1.0 - (2.0 * cursor.y / size.height as f32),
-        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let read = |buffer: &wgpu::Buffer| -> Result<Vec<u8>, String> {
+            let slice = buffer.slice(..);
+            let (tx, rx) = mpsc::channel();
+            slice.map_async(wgpu::MapMode::Read, move |result| {
+                let _ = tx.send(result);
+            });
+            self.device.poll(wgpu::Maintain::Wait);
+            rx.recv()
+                .map_err(|err| err.to_string())?
+                .map_err(|err| err.to_string())?;
+            let mut bytes = Vec::with_capacity((width * height * 4) as usize);
+            {
+                let data = slice.get_mapped_range();
+                for row in data.chunks(bytes_per_row as usize) {
+                    bytes.extend_from_slice(&row[..(width * 4) as usize]);
+                }
+            }
+            buffer.unmap();
+            Ok(bytes)
+        };
+        let mut pixels = read(&color_buffer)?;
+        if matches!(
+            self.config.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        let depth = read(&depth_buffer)?
+            .chunks_exact(4)
+            .map(|bytes| {
+                let value = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                postprocess::linearize_depth(value, NEAR_PLANE, FAR_PLANE)
+            })
+            .collect();
+        Ok(Frame {
+            width,
+            height,
+            pixels,
+            depth,
+        })
+    }
+
+    fn encode_scene(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+        width: u32,
+        height: u32,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("main_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.05,
+                        g: 0.05,
+                        b: 0.08,
+                        a: 1.0,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
 
-        let aspect = size.width as f32 / size.height as f32;
-        let view_proj = camera.view_proj(aspect);
-        let inv_view_proj = view_proj.inverse();
-        let near_point = inv_view_proj * Vec4::new(ndc.x, ndc.y, 0.0, 1.0);
-        let far_point = inv_view_proj * Vec4::new(ndc.x, ndc.y, 1.0, 1.0);
-        let near = near_point.truncate() / near_point.w;
-        let far = far_point.truncate() / far_point.w;
-        let ray_dir = (far - near).normalize();
-        let ray_origin = near;
-
-        let mut best: Option<(AtomId, f32)> = None;
-        for (index, instance) in self.atom_instance_data.iter().enumerate() {
-            let center = Vec3::from_array(instance.position);
-            let to_center = center - ray_origin;
-            let t = ray_dir.dot(to_center);
-            if t < 0.0 {
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        let current = (
+            self.atom_instance_buffer.as_ref(),
+            self.atom_instance_data.len() as u32,
+            [
+                (
+                    self.bond_instance_buffer.as_ref(),
+                    self.bond_instance_data.len() as u32,
+                ),
+                (
+                    self.order_strand_buffer.as_ref(),
+                    self.order_strand_data.len() as u32,
+                ),
+            ],
+        );
+        let panels = self.grid.iter().flatten().map(|panel| {
+            (
+                panel.atom_buffer.as_ref(),
+                panel.atom_count,
+                [(panel.bond_buffer.as_ref(), panel.bond_count), (None, 0)],
+            )
+        });
+        let (width, height) = (width as f32, height as f32);
+        let cells = self.cells(width, height);
+        for (cell, (atoms, atom_count, bond_layers)) in
+            cells.iter().zip(std::iter::once(current).chain(panels))
+        {
+            // Whole pixels keep rounding from pushing the last cell past the target.
+            let (left, top) = (cell.x.round(), cell.y.round());
+            let right = (cell.x + cell.width).round().min(width);
+            let bottom = (cell.y + cell.height).round().min(height);
+            if right <= left || bottom <= top {
                 continue;
             }
-            let closest = ray_origin + ray_dir * t;
-            let dist_sq = center.distance_squared(closest);
-            let radius_sq = instance.radius * instance.radius;
-            if dist_sq <= radius_sq {
-                let atom_id = self.atom_instance_ids[index];
-                match best {
-                    Some((_, best_t)) if t >= best_t => {}
-                    _ => best = Some((atom_id, t)),
-                }
+            render_pass.set_viewport(left, top, right - left, bottom - top, 0.0, 1.0);
+            for (bonds, bond_count) in bond_layers {
+                let Some(bond_buffer) = bonds.filter(|_| bond_count > 0) else {
+                    continue;
+                };
+                render_pass.set_pipeline(&self.bond_pipeline);
+                render_pass.set_vertex_buffer(0, self.cylinder_vertex_buffer.slice(..));
+                render_pass.set_vertex_buffer(1, bond_buffer.slice(..));
+                render_pass.set_index_buffer(
+                    self.cylinder_index_buffer.slice(..),
+                    wgpu::IndexFormat::Uint32,
+                );
+                render_pass.draw_indexed(0..self.cylinder_index_count, 0, 0..bond_count);
+            }
+
+            render_pass.set_pipeline(&self.atom_pipeline);
+            render_pass.set_vertex_buffer(0, self.sphere_vertex_buffer.slice(..));
+            if let Some(instance_buffer) = atoms {
+                render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+                render_pass.set_index_buffer(
+                    self.sphere_index_buffer.slice(..),
+                    wgpu::IndexFormat::Uint32,
+                );
+                render_pass.draw_indexed(0..self.sphere_index_count, 0, 0..atom_count);
             }
         }
-        best.map(|(atom_id, _)| atom_id)
     }
 
     fn render(
@@ -966,67 +2784,13 @@ impl<'a> RenderState<'a> {
                 label: Some("render_encoder"),
             });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("main_render_pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.05,
-                            g: 0.05,
-                            b: 0.08,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
-
-            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
-            if let Some(bond_buffer) = &self.bond_instance_buffer {
-                if !self.bond_instance_data.is_empty() {
-                    render_pass.set_pipeline(&self.bond_pipeline);
-                    render_pass.set_vertex_buffer(0, self.cylinder_vertex_buffer.slice(..));
-                    render_pass.set_vertex_buffer(1, bond_buffer.slice(..));
-                    render_pass.set_index_buffer(
-                        self.cylinder_index_buffer.slice(..),
-                        wgpu::IndexFormat::Uint32,
-                    );
-                    render_pass.draw_indexed(
-                        0..self.cylinder_index_count,
-                        0,
-                        0..self.bond_instance_data.len() as u32,
-                    );
-                }
-            }
-
-            render_pass.set_pipeline(&self.atom_pipeline);
-            render_pass.set_vertex_buffer(0, self.sphere_vertex_buffer.slice(..));
-            if let Some(instance_buffer) = &self.atom_instance_buffer {
-                render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
-                render_pass.set_index_buffer(
-                    self.sphere_index_buffer.slice(..),
-                    wgpu::IndexFormat::Uint32,
-                );
-                render_pass.draw_indexed(
-                    0..self.sphere_index_count,
-                    0,
-                    0..self.atom_instance_data.len() as u32,
-                );
-            }
-        }
+        self.encode_scene(
+            &mut encoder,
+            &view,
+            &self.depth_texture.view,
+            self.config.width,
+            self.config.height,
+        );
 
         egui_renderer.update_buffers(
             &self.device,
@@ -1121,21 +2885,46 @@ fn create_cylinder_mesh(segments: u32) -> (Vec<Vertex>, Vec<u32>) {
 }
 
 fn main() {
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let source_path = options
+        .path
+        .clone()
+        .unwrap_or_else(|| SAMPLE_PATH.to_string());
+    if options.headless {
+        run_headless(&source_path, options.output_format, options.lenient);
+    }
     let event_loop = EventLoop::new().expect("event loop");
-
-    let (tx, rx) = mpsc::channel::<Result<Molecule, String>>();
-    let file_name = SAMPLE_PATH.to_string();
-    thread::spawn(move || {
-        let result = std::fs::read_to_string(&file_name)
-            .map_err(|err| err.to_string())
-            .and_then(|contents| molweaver::parse_xyz(&contents).map_err(|err| err.to_string()));
-        let _ = tx.send(result);
-    });
+    let load_progress = Arc::new(LoadProgress::default());
+    let mut rx = spawn_load(
+        source_path.clone(),
+        ImportOptions::defaults(options.lenient),
+        load_progress.clone(),
+    );
 
     let mut molecule: Option<Molecule> = None;
     let mut ui_state = UiState::new();
+    ui_state.open_path = source_path.clone();
+    ui_state.source_path = source_path;
+    ui_state.load_progress = Some(load_progress);
+    ui_state.import.load.lenient = options.lenient;
+    ui_state.check_render |= options.check_render;
+    if let Some(dir) = &options.gallery {
+        ui_state.gallery_dir = dir.clone();
+        ui_state.gallery = Some(spawn_gallery_watch(
+            PathBuf::from(dir),
+            ui_state.palette,
+            ui_state.import.load.lenient,
+        ));
+    }
+    let mut startup = Some(options);
     let mut history = CommandHistory::new(HISTORY_CAPACITY);
-    let mut window: Option<Window> = None;
+    let mut window: Option<Arc<Window>> = None;
     let mut render_state: Option<RenderState> = None;
     let mut egui_state: Option<egui_winit::State> = None;
     let mut egui_ctx: Option<egui::Context> = None;
@@ -1144,19 +2933,20 @@ fn main() {
     event_loop.set_control_flow(ControlFlow::Poll);
     event_loop
         .run(move |event, target| match event {
-
             // Create window/state on Event::Resumed to avoid moving a borrowed window into the loop.
             Event::Resumed => {
                 if window.is_some() {
                     return;
                 }
-                let created_window = WindowBuilder::new()
-                    .with_title("MolWeaver")
-                    .with_inner_size(winit::dpi::LogicalSize::new(1280.0, 720.0))
-                    .build(target)
-                    .expect("window");
-                let mut created_render_state =
-                    pollster::block_on(RenderState::new(&created_window));
+                let created_window = Arc::new(
+                    WindowBuilder::new()
+                        .with_title("MolWeaver")
+                        .with_inner_size(winit::dpi::LogicalSize::new(1280.0, 720.0))
+                        .build(target)
+                        .expect("window"),
+                );
+                let created_render_state =
+                    pollster::block_on(RenderState::new(created_window.clone()));
                 let created_egui_ctx = egui::Context::default();
                 let viewport_id = created_egui_ctx.viewport_id();
                 let created_egui_state = egui_winit::State::new(
@@ -1183,7 +2973,10 @@ fn main() {
                 egui_renderer = Some(created_egui_renderer);
                 window = Some(created_window);
             }
-            Event::WindowEvent { event, window_id } => {
+            Event::WindowEvent {
+                event: WindowEvent::RedrawRequested,
+                window_id,
+            } => {
                 let (window, render_state, egui_state, egui_ctx, egui_renderer) = match (
                     window.as_ref(),
                     render_state.as_mut(),
@@ -1203,153 +2996,350 @@ fn main() {
                 if window_id != window.id() {
                     return;
                 }
-                if egui_state.on_window_event(window, &event).consumed {
-                    return;
-                }
-                match event {
-                    WindowEvent::CloseRequested => target.exit(),
-                    WindowEvent::Resized(size) => render_state.resize(size),
-                    WindowEvent::ScaleFactorChanged {
-                        mut inner_size_writer,
-                        ..
-                    } => {
-                        let new_size = window.inner_size();
-                        inner_size_writer.request_inner_size(new_size);
-                        render_state.resize(new_size);
-                    }
-                    WindowEvent::ModifiersChanged(modifiers) => {
-                        ui_state.modifiers = modifiers.state();
-                    }
-                    WindowEvent::KeyboardInput { event, .. } => {
-                        if event.state == ElementState::Pressed {
-                            if handle_shortcuts(&event.logical_key, &ui_state.modifiers) {
-                                if let Some(molecule_ref) = molecule.as_mut() {
-                                    match &event.logical_key {
-                                        Key::Character(key) if key.eq_ignore_ascii_case("z") => {
-                                            if ui_state.modifiers.shift_key() {
-                                                redo_command(
-                                                    &mut history,
-                                                    molecule_ref,
-                                                    render_state,
-                                                    &mut ui_state,
-                                                );
-                                            } else {
-                                                undo_command(
-                                                    &mut history,
-                                                    molecule_ref,
-                                                    render_state,
-                                                    &mut ui_state,
-                                                );
-                                            }
-                                        }
-                                        Key::Character(key) if key.eq_ignore_ascii_case("y") => {
-                                            redo_command(
-                                                &mut history,
-                                                molecule_ref,
-                                                render_state,
-                                                &mut ui_state,
-                                            );
+                if let Ok(result) = rx.try_recv() {
+                    ui_state.load_progress = None;
+                    match result {
+                        Ok((loaded, problems, links)) => {
+                            ui_state.file_name =
+                                format!("{} ({})", ui_state.source_path, loaded.name);
+                            if !problems.is_empty() {
+                                ui_state.status_message =
+                                    format!("skipped {} unreadable lines", problems.len());
+                            } else if !links.is_empty() {
+                                let disulfides = links
+                                    .iter()
+                                    .filter(|link| link.kind == LinkKind::Disulfide)
+                                    .count();
+                                ui_state.status_message = format!(
+                                    "inferred {disulfides} disulfides and {} salt bridges",
+                                    links.len() - disulfides
+                                );
+                            }
+                            ui_state.show_links = !links.is_empty();
+                            ui_state.inferred_links = links;
+                            ui_state.show_problems = !problems.is_empty();
+                            ui_state.problems = problems;
+                            ui_state.load_error = None;
+                            if ui_state.watch_file {
+                                ui_state.file_watch = Some(FileWatch::new(&ui_state.source_path));
+                            }
+                            let reloaded = std::mem::take(&mut ui_state.reloading);
+                            match molecule.as_mut().filter(|_| reloaded) {
+                                Some(molecule_ref) => {
+                                    ui_state.provenance.record(format!(
+                                        "reload {} ({} atoms)",
+                                        ui_state.source_path,
+                                        loaded.atom_count()
+                                    ));
+                                    let command = Command::Restore {
+                                        snapshot: Box::new(loaded),
+                                        previous: None,
+                                    };
+                                    apply_command(
+                                        command,
+                                        molecule_ref,
+                                        &mut history,
+                                        render_state,
+                                        &mut ui_state,
+                                    );
+                                    history.mark_saved();
+                                }
+                                None => {
+                                    let action = format!(
+                                        "open {} ({} atoms)",
+                                        ui_state.source_path,
+                                        loaded.atom_count()
+                                    );
+                                    if let Some(recorder) = ui_state.recorder.as_mut() {
+                                        recorder.record(&loaded, &action);
+                                    }
+                                    ui_state.provenance.record(action);
+                                    ui_state.diff = None;
+                                    // Monitors name atoms by id, which a new file reuses.
+                                    ui_state.measurements.clear();
+                                    render_state.set_molecule(&loaded);
+                                    ui_state.stereo_dirty = true;
+                                    ui_state.cell_info_dirty = true;
+                                    ui_state.composition_dirty = true;
+                                    ui_state.coloring_dirty = true;
+                                    ui_state.selection.clear();
+                                    ui_state.bond_target = None;
+                                    history = CommandHistory::new(HISTORY_CAPACITY);
+                                    if let Some(options) = startup.take() {
+                                        apply_startup_options(
+                                            &options,
+                                            &loaded,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                        if options.exit && options.screenshot.is_none() {
+                                            target.exit();
                                         }
-                                        _ => {}
+                                    } else {
+                                        ui_state.fit_to_view(&loaded);
                                     }
+                                    molecule = Some(loaded);
                                 }
                             }
                         }
-                    }
-                    WindowEvent::CursorMoved { position, .. } => {
-                        ui_state.update_cursor(Vec2::new(position.x as f32, position.y as f32));
-                    }
-                    WindowEvent::MouseInput { state, button, .. } => {
-                        if button == MouseButton::Left {
-                            match state {
-                                ElementState::Pressed => ui_state.begin_drag(),
-                                ElementState::Released => {
-                                    if ui_state.drag_distance < 4.0 {
-                                        if let Some(cursor) = ui_state.last_cursor {
-                                            let picked = render_state.pick_atom(
-                                                cursor,
-                                                &ui_state.camera,
-                                                render_state.size,
-                                            );
-                                            handle_click(
-                                                picked,
-                                                render_state,
-                                                &mut ui_state,
-                                                molecule.as_mut(),
-                                                &mut history,
-                                            );
-                                        }
-                                    }
-                                    ui_state.end_drag();
-                                }
+                        Err(err) => {
+                            log::error!("load failed: {err}");
+                            ui_state.file_name = format!("load failed: {err}");
+                            ui_state.problems.clear();
+                            ui_state.inferred_links.clear();
+                            ui_state.load_error = Some(err.clone());
+                            ui_state.show_problems = true;
+                            if startup.as_ref().is_some_and(|options| options.exit) {
+                                eprintln!("load failed: {err}");
+                                std::process::exit(1);
                             }
                         }
                     }
-                    WindowEvent::MouseWheel { delta, .. } => {
-                        let scroll = match delta {
-                            MouseScrollDelta::LineDelta(_, y) => y,
-                            MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 100.0,
-                        };
-                        if scroll.abs() > f32::EPSILON {
-                            ui_state.zoom(scroll * 0.1);
+                }
+
+                let session_result = ui_state
+                    .session_rx
+                    .as_ref()
+                    .and_then(|rx| rx.try_recv().ok());
+                if let Some(result) = session_result {
+                    ui_state.session_rx = None;
+                    match result {
+                        Ok(session) => {
+                            ui_state.file_name =
+                                format!("{} ({})", ui_state.session_path, session.molecule.name);
+                            ui_state.diff = None;
+                            ui_state.inferred_links.clear();
+                            ui_state.checkpoints = session.checkpoints;
+                            ui_state.style_rules = rules_text(&session.style.rules);
+                            ui_state.style = session.style.clone();
+                            ui_state.notes = session.notes;
+                            ui_state.annotations = session.annotations;
+                            ui_state.measurements.clear();
+                            ui_state.provenance = session.provenance;
+                            ui_state.views = session.views;
+                            if let Some(camera) = session.camera {
+                                ui_state.camera = camera;
+                                ui_state.camera_transition = None;
+                                ui_state.fly_keys.clear();
+                                ui_state.camera_dirty = true;
+                            }
+                            ui_state
+                                .provenance
+                                .record(format!("open session {}", ui_state.session_path));
+                            render_state.set_molecule(&session.molecule);
+                            ui_state.stereo_dirty = true;
+                            ui_state.cell_info_dirty = true;
+                            ui_state.composition_dirty = true;
+                            ui_state.coloring_dirty = true;
+                            render_state.set_style(session.style, &session.molecule);
+                            molecule = Some(session.molecule);
+                            ui_state.selection.clear();
+                            ui_state.bond_target = None;
+                            history = CommandHistory::new(HISTORY_CAPACITY);
                         }
+                        Err(err) => ui_state.status_message = format!("session load failed: {err}"),
                     }
-                    _ => {}
                 }
-            }
-            Event::AboutToWait => {
-                if let Some(window) = window.as_ref() {
-                    window.request_redraw();
+                let save_result = ui_state.save_rx.as_ref().and_then(|rx| rx.try_recv().ok());
+                if let Some(result) = save_result {
+                    ui_state.save_rx = None;
+                    ui_state.status_message = match result {
+                        Ok(path) => {
+                            history.mark_saved();
+                            format!("saved {path}")
+                        }
+                        Err(err) => format!("save failed: {err}"),
+                    };
                 }
-            }
-            Event::WindowEvent {
-                event: WindowEvent::RedrawRequested,
-                window_id,
-            } => {
-                let (window, render_state, egui_state, egui_ctx, egui_renderer) = match (
-                    window.as_ref(),
-                    render_state.as_mut(),
-                    egui_state.as_mut(),
-                    egui_ctx.as_ref(),
-                    egui_renderer.as_mut(),
-                ) {
-                    (
-                        Some(window),
-                        Some(render_state),
-                        Some(egui_state),
-                        Some(egui_ctx),
-                        Some(egui_renderer),
-                    ) => (window, render_state, egui_state, egui_ctx, egui_renderer),
-                    _ => return,
-                };
-                if window_id != window.id() {
-                    return;
+
+                let compare_result = ui_state
+                    .compare_rx
+                    .as_ref()
+                    .and_then(|rx| rx.try_recv().ok());
+                if let Some(result) = compare_result {
+                    ui_state.compare_rx = None;
+                    match (result, molecule.as_ref()) {
+                        (Ok((on_disk, ..)), Some(current)) => {
+                            show_diff(diff(&on_disk, current), render_state, &mut ui_state);
+                        }
+                        (Err(err), _) => ui_state.status_message = format!("compare failed: {err}"),
+                        _ => {}
+                    }
                 }
-                if let Ok(result) = rx.try_recv() {
+
+                let grid_result = ui_state.grid_rx.as_ref().and_then(|rx| rx.try_recv().ok());
+                if let Some(result) = grid_result {
+                    ui_state.grid_rx = None;
                     match result {
-                        Ok(loaded) => {
-                            ui_state.file_name = format!("{SAMPLE_PATH} ({})", loaded.name);
-                            render_state.set_molecule(&loaded);
-                            molecule = Some(loaded);
-                            ui_state.selection = None;
-                            ui_state.bond_target = None;
-                            history = CommandHistory::new(HISTORY_CAPACITY);
+                        Ok((sample, ..)) => {
+                            ui_state.grid_samples.push(GridSample {
+                                name: sample_name(&ui_state.grid_path),
+                                molecule: sample,
+                            });
+                            ui_state.grid_enabled = true;
+                            ui_state.grid_dirty = true;
                         }
-                        Err(err) => {
-                            ui_state.file_name = format!("load failed: {err}");
+                        Err(err) => ui_state.status_message = format!("grid: {err}"),
+                    }
+                }
+
+                let find_result = ui_state.find_rx.as_ref().and_then(|rx| rx.try_recv().ok());
+                if let Some(result) = find_result {
+                    ui_state.find_rx = None;
+                    match (result, molecule.as_ref()) {
+                        (Ok((pattern, ..)), Some(current)) => {
+                            run_find(&pattern, current, render_state, &mut ui_state);
+                        }
+                        (Err(err), _) => ui_state.status_message = format!("find failed: {err}"),
+                        _ => {}
+                    }
+                }
+
+                let cleanup_result = ui_state
+                    .cleanup_rx
+                    .as_ref()
+                    .and_then(|rx| rx.try_recv().ok());
+                if let Some(result) = cleanup_result {
+                    ui_state.cleanup_rx = None;
+                    if let Some(molecule_ref) = molecule.as_mut() {
+                        match result.command(molecule_ref) {
+                            Ok(command) => {
+                                if apply_command(
+                                    command,
+                                    molecule_ref,
+                                    &mut history,
+                                    render_state,
+                                    &mut ui_state,
+                                )
+                                .is_some()
+                                {
+                                    ui_state.status_message = format!(
+                                        "cleaned geometry: {:.1} → {:.1} kcal/mol in {} steps{}",
+                                        result.initial_energy,
+                                        result.energy,
+                                        result.steps,
+                                        if result.converged { "" } else { " (not converged)" }
+                                    );
+                                }
+                            }
+                            Err(err) => ui_state.status_message = err,
+                        }
+                    }
+                }
+
+                if let Some(gallery) = ui_state.gallery.as_mut() {
+                    let events: Vec<GalleryEvent> = gallery.rx.try_iter().collect();
+                    for event in events {
+                        if let Some(err) = gallery.apply(event) {
+                            ui_state.status_message = format!("gallery: {err}");
                         }
                     }
                 }
 
-                let aspect =
-                    render_state.size.width as f32 / render_state.size.height.max(1) as f32;
+                if ui_state.grid_dirty {
+                    let anchor = molecule
+                        .as_ref()
+                        .and_then(geometry::centroid)
+                        .map(Vec3::from_array);
+                    let samples = ui_state
+                        .grid_enabled
+                        .then_some(ui_state.grid_samples.as_slice());
+                    render_state.set_grid(samples, anchor);
+                    ui_state.grid_dirty = false;
+                    ui_state.camera_dirty = true;
+                }
+                let aspect = render_state.view_aspect();
                 if ui_state.camera_dirty {
                     render_state.update_camera(&ui_state.camera, aspect);
                     ui_state.camera_dirty = false;
                 }
-                ui_state.update_fps();
+                if let Some(path) = ui_state.pending_screenshot.take() {
+                    let presentation = ui_state.presentation.then_some(PresentationSettings {
+                        focus_distance: ui_state.camera.distance,
+                        ..ui_state.presentation_settings
+                    });
+                    let scale = presentation.map_or(1, |settings| settings.supersample);
+                    match render_state.capture(scale) {
+                        Ok(frame) => {
+                            if presentation.is_some() {
+                                ui_state.status_message = "rendering presentation…".to_string();
+                            }
+                            ui_state.screenshot_rx =
+                                Some(spawn_png_write(path, frame, presentation));
+                        }
+                        Err(err) => ui_state.status_message = format!("screenshot failed: {err}"),
+                    }
+                }
+                if let Some(path) = ui_state.pending_figure.take() {
+                    match capture_figure(render_state, &ui_state) {
+                        Ok((panels, layout)) => {
+                            if ui_state.presentation {
+                                ui_state.status_message = "rendering presentation…".to_string();
+                            }
+                            ui_state.screenshot_rx =
+                                Some(spawn_figure_write(path, panels, layout));
+                        }
+                        Err(err) => ui_state.status_message = format!("figure failed: {err}"),
+                    }
+                    render_state.update_camera(&ui_state.camera, aspect);
+                }
+                if ui_state.movie.is_some() {
+                    render_movie_frame(render_state, &mut ui_state, aspect);
+                }
+                let video_result = ui_state.video_rx.as_ref().and_then(|rx| rx.try_recv().ok());
+                if let Some(result) = video_result {
+                    ui_state.video_rx = None;
+                    ui_state.status_message = match result {
+                        Ok(video) => {
+                            ui_state
+                                .provenance
+                                .record(format!("encode video {}", video.display()));
+                            format!("saved {}", video.display())
+                        }
+                        Err(err) => format!("ffmpeg: {err}"),
+                    };
+                }
+                let screenshot_result = ui_state
+                    .screenshot_rx
+                    .as_ref()
+                    .and_then(|rx| rx.try_recv().ok());
+                if let Some(result) = screenshot_result {
+                    ui_state.screenshot_rx = None;
+                    match result {
+                        Ok(path) => {
+                            ui_state.provenance.record(format!("save screenshot {path}"));
+                            ui_state.status_message = format!("saved {path}");
+                        }
+                        Err(err) => {
+                            log::error!("screenshot failed: {err}");
+                            ui_state.status_message = format!("screenshot failed: {err}");
+                        }
+                    }
+                    if ui_state.exit_after_screenshot {
+                        target.exit();
+                    }
+                }
+                let frame_seconds = ui_state.update_fps();
+                ui_state.update_fly(frame_seconds, molecule.as_ref());
+                ui_state.update_turntable(frame_seconds);
+                ui_state.update_transition();
+                ui_state.update_preview();
+                if let Some(molecule) = molecule.as_mut() {
+                    ui_state.sync_valence_rules(molecule);
+                }
 
                 let atom_count = molecule.as_ref().map(|mol| mol.atom_count()).unwrap_or(0);
+                if ui_state.composition_dirty {
+                    ui_state.formula = molecule
+                        .as_ref()
+                        .map(|mol| (mol.formula(), mol.molecular_weight()));
+                    ui_state.composition = molecule
+                        .as_ref()
+                        .map(|mol| composition::composition(mol, ui_state.cell_z));
+                    ui_state.composition_dirty = false;
+                }
+                let formula = ui_state.formula.clone();
+                let composition = ui_state.composition.clone();
                 let bond_count = molecule
                     .as_ref()
                     .map(|mol| mol.bonds().count())
@@ -1359,308 +3349,4961 @@ fn main() {
                     .map(|mol| mol.atom_ids())
                     .unwrap_or_default();
                 let mut pending_representation = None;
+                let mut pending_command = None;
+                let mut pending_compare = None;
+                let mut pending_checkpoint = None;
+                let mut pending_gallery_open = None;
+                let mut pending_grid = None;
+                let mut gallery_toggled = false;
+                let mut pending_tutorial = None;
+                let mut retry_lenient = false;
+                let mut pending_open = false;
+                let mut discard_answer = None;
+                let mut pending_palette = None;
+                let mut load_script = false;
+                let mut pending_find = None;
+                let mut pending_style = None;
+                let mut pending_export = None;
+                let mut export_html = false;
+                let mut save_xyz = false;
+                let mut save_sdf = false;
+                let mut save_cml = false;
+                let mut save_extxyz = false;
+                let mut save_gaussian = false;
+                let mut pending_frame_export = None;
+                let mut pending_record = None;
+                let mut save_recording = false;
+                let mut build_smiles = false;
+                let mut attach_smiles = false;
+                let mut insert_fragment: Option<&'static Fragment> = None;
+                let undo_len = history.undo_len();
+                if ui_state.cell_info_dirty {
+                    ui_state.cell_info = molecule.as_ref().and_then(|mol| {
+                        let cell = mol.cell().copied()?;
+                        let metrics = molweaver::cell::cell_metrics(mol, ui_state.cell_z)?;
+                        Some((cell, metrics))
+                    });
+                    ui_state.cell_info_dirty = false;
+                }
+                let cell_info = ui_state.cell_info;
+
+                if let Some(molecule_ref) = molecule.as_ref() {
+                    if ui_state.coloring_dirty {
+                        refresh_coloring(molecule_ref, render_state, &mut ui_state);
+                    } else if !ui_state.recolor_atoms.is_empty() {
+                        recolor_atoms(molecule_ref, render_state, &mut ui_state);
+                    }
+                }
+                if ui_state.stereo_labels && ui_state.stereo_dirty {
+                    if let Some(molecule_ref) = molecule.as_mut() {
+                        molecule_ref.assign_stereo();
+                        ui_state.stereo_dirty = false;
+                    }
+                }
+                let stereo_labels = match molecule.as_ref() {
+                    Some(molecule_ref) if ui_state.stereo_labels => stereo_labels(molecule_ref),
+                    _ => Vec::new(),
+                };
+                let atom_labels = molecule
+                    .as_ref()
+                    .map(|molecule_ref| ui_state.atom_labels.labels(molecule_ref))
+                    .unwrap_or_default();
+                let view_proj = ui_state.camera.view_proj(aspect);
+                let note_markers: Vec<(Vec3, String)> = molecule
+                    .as_ref()
+                    .map(|molecule_ref| {
+                        ui_state
+                            .notes
+                            .markers(molecule_ref)
+                            .into_iter()
+                            .map(|(_, position, text)| (Vec3::from_array(position), text.into()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let salt_bridges: Vec<(Vec3, Vec3)> = match &molecule {
+                    Some(molecule_ref) if ui_state.show_links => ui_state
+                        .inferred_links
+                        .iter()
+                        .filter(|link| link.kind == LinkKind::SaltBridge)
+                        .filter_map(|link| {
+                            let a = molecule_ref.get_atom(link.a)?.position;
+                            let b = molecule_ref.get_atom(link.b)?.position;
+                            Some((Vec3::from_array(a), Vec3::from_array(b)))
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                type Ends<T> = Vec<(T, Vec3, Vec3)>;
+                let (arrows, partial_bonds): (Ends<Arrow>, Ends<PartialBond>) = match &molecule {
+                    Some(molecule_ref) if ui_state.show_annotations => (
+                        ui_state.annotations.arrow_ends(molecule_ref),
+                        ui_state.annotations.partial_bond_ends(molecule_ref),
+                    ),
+                    _ => (Vec::new(), Vec::new()),
+                };
+                let monitors: Vec<(Measurement, Vec<Vec3>, String)> = molecule
+                    .as_ref()
+                    .map(|molecule_ref| ui_state.measurements.visible(molecule_ref))
+                    .unwrap_or_default();
+                let bond_hud = molecule
+                    .as_ref()
+                    .and_then(|molecule_ref| sketch_hud(molecule_ref, render_state, &ui_state));
+                let drag_region = ui_state.drag_region();
+                let grid_names: Vec<String> = match render_state.grid {
+                    Some(_) => std::iter::once(sample_name(&ui_state.source_path))
+                        .chain(ui_state.grid_samples.iter().map(|sample| sample.name.clone()))
+                        .collect(),
+                    None => Vec::new(),
+                };
 
                 let raw_input = egui_state.take_egui_input(window);
                 let output = egui_ctx.run(raw_input, |ctx| {
+                    let screen = ctx.screen_rect();
+                    let cells = grid_cells(grid_names.len(), screen.width(), screen.height());
+                    let cell_rect = |cell: &GridCell| {
+                        egui::Rect::from_min_size(
+                            screen.min + egui::vec2(cell.x, cell.y),
+                            egui::vec2(cell.width, cell.height),
+                        )
+                    };
+                    if grid_names.len() > 1 {
+                        let painter = ctx.layer_painter(egui::LayerId::background());
+                        for (cell, name) in cells.iter().zip(&grid_names) {
+                            let rect = cell_rect(cell);
+                            painter.rect_stroke(
+                                rect,
+                                0.0,
+                                egui::Stroke::new(1.0, egui::Color32::from_gray(80)),
+                            );
+                            painter.text(
+                                rect.left_top() + egui::vec2(6.0, 4.0),
+                                egui::Align2::LEFT_TOP,
+                                name,
+                                egui::FontId::proportional(14.0),
+                                egui::Color32::from_gray(200),
+                            );
+                        }
+                    }
+                    let view_rect = cell_rect(&cells[0]);
+                    let project = |position: Vec3| {
+                        let clip = view_proj * position.extend(1.0);
+                        (clip.w > NEAR_PLANE).then(|| {
+                            let ndc = clip.truncate() / clip.w;
+                            egui::pos2(
+                                view_rect.left() + (ndc.x + 1.0) * 0.5 * view_rect.width(),
+                                view_rect.top() + (1.0 - ndc.y) * 0.5 * view_rect.height(),
+                            )
+                        })
+                    };
+                    if !salt_bridges.is_empty() {
+                        let painter = ctx.layer_painter(egui::LayerId::background());
+                        let stroke = egui::Stroke::new(1.5, egui::Color32::from_rgb(120, 200, 255));
+                        for (a, b) in &salt_bridges {
+                            if let (Some(a), Some(b)) = (project(*a), project(*b)) {
+                                painter.extend(egui::Shape::dashed_line(&[a, b], stroke, 4.0, 4.0));
+                            }
+                        }
+                    }
+                    if let Some(region) = &drag_region {
+                        let painter = ctx.layer_painter(egui::LayerId::background());
+                        let scale = ctx.pixels_per_point();
+                        let point = |p: &Vec2| egui::pos2(p.x / scale, p.y / scale);
+                        let stroke = egui::Stroke::new(1.0, egui::Color32::from_rgb(120, 200, 255));
+                        let fill = egui::Color32::from_rgba_unmultiplied(120, 200, 255, 30);
+                        match region {
+                            Region::Rectangle(a, b) => {
+                                let rect = egui::Rect::from_two_pos(point(a), point(b));
+                                painter.rect(rect, 0.0, fill, stroke);
+                            }
+                            Region::Lasso(path) => {
+                                let points = path.iter().map(point).collect();
+                                painter.add(egui::Shape::closed_line(points, stroke));
+                            }
+                        }
+                    }
+                    if !partial_bonds.is_empty() {
+                        let painter = ctx.layer_painter(egui::LayerId::background());
+                        let stroke = egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 170, 60));
+                        for (bond, a, b) in &partial_bonds {
+                            let (Some(a), Some(b)) = (project(*a), project(*b)) else {
+                                continue;
+                            };
+                            let across = (b - a).normalized().rot90() * PARTIAL_BOND_SPACING;
+                            for (offset, dashed) in annotations::bond_strokes(bond.order) {
+                                let line = [a + across * offset, b + across * offset];
+                                if dashed {
+                                    painter.extend(egui::Shape::dashed_line(&line, stroke, 5.0, 4.0));
+                                } else {
+                                    painter.line_segment(line, stroke);
+                                }
+                            }
+                        }
+                    }
+                    if !arrows.is_empty() {
+                        let painter = ctx.layer_painter(egui::LayerId::background());
+                        let to_vec = |point: egui::Pos2| Vec2::new(point.x, point.y);
+                        for (arrow, from, to) in &arrows {
+                            let (Some(from), Some(to)) = (project(*from), project(*to)) else {
+                                continue;
+                            };
+                            let (from, to) = (to_vec(from), to_vec(to));
+                            let inset = (to - from).normalize_or_zero() * ARROW_INSET;
+                            if from.distance(to) <= 2.0 * ARROW_INSET {
+                                continue;
+                            }
+                            let color = match arrow.kind {
+                                ArrowKind::Straight => egui::Color32::from_gray(230),
+                                _ => egui::Color32::from_rgb(230, 60, 60),
+                            };
+                            let stroke = egui::Stroke::new(2.0, color);
+                            let shaft = annotations::curve(arrow.kind, from + inset, to - inset, 24);
+                            let head = annotations::arrowhead(arrow.kind, &shaft, ARROWHEAD_SIZE);
+                            for line in [shaft, head] {
+                                let points = line.iter().map(|p| egui::pos2(p.x, p.y)).collect();
+                                painter.add(egui::Shape::line(points, stroke));
+                            }
+                        }
+                    }
+                    if !note_markers.is_empty() {
+                        let painter = ctx.layer_painter(egui::LayerId::background());
+                        let pointer = ctx
+                            .input(|input| input.pointer.hover_pos())
+                            .filter(|_| !ctx.is_pointer_over_area());
+                        let mut hovered = None;
+                        for (position, text) in &note_markers {
+                            let Some(point) = project(*position) else {
+                                continue;
+                            };
+                            let marker =
+                                point + egui::vec2(NOTE_MARKER_OFFSET, -NOTE_MARKER_OFFSET);
+                            painter.circle(
+                                marker,
+                                NOTE_MARKER_RADIUS,
+                                egui::Color32::from_rgb(255, 200, 40),
+                                egui::Stroke::new(1.0, egui::Color32::BLACK),
+                            );
+                            if pointer.is_some_and(|pointer| {
+                                pointer.distance(marker) <= NOTE_MARKER_RADIUS * 2.0
+                            }) {
+                                hovered = Some(text);
+                            }
+                        }
+                        if let Some(text) = hovered {
+                            egui::show_tooltip_at_pointer(ctx, egui::Id::new("note"), |ui| {
+                                ui.label(text);
+                            });
+                        }
+                    }
+                    if let Some((anchor, position, guide)) = bond_hud
+                        .filter(|_| ui_state.atom_drag.is_some() || !ctx.is_pointer_over_area())
+                    {
+                        if let (Some(from), Some(to)) = (project(anchor), project(position)) {
+                            let painter = ctx.layer_painter(egui::LayerId::background());
+                            let snapped = guide.angle.is_some_and(|(_, angle)| {
+                                SNAP_ANGLES.iter().any(|ideal| (ideal - angle).abs() < 0.05)
+                            });
+                            let color = if snapped {
+                                egui::Color32::from_rgb(120, 230, 120)
+                            } else {
+                                egui::Color32::from_rgb(255, 200, 40)
+                            };
+                            painter.line_segment([from, to], egui::Stroke::new(1.5, color));
+                            let mut text = format!("{:.3} Å", guide.length);
+                            if let Some((_, angle)) = guide.angle {
+                                text.push_str(&format!("  {angle:.1}°"));
+                            }
+                            painter.text(
+                                to + egui::vec2(12.0, -12.0),
+                                egui::Align2::LEFT_BOTTOM,
+                                text,
+                                egui::FontId::proportional(15.0),
+                                color,
+                            );
+                        }
+                    }
+                    if !monitors.is_empty() {
+                        let painter = ctx.layer_painter(egui::LayerId::background());
+                        let color = egui::Color32::from_rgb(255, 220, 80);
+                        let stroke = egui::Stroke::new(1.5, color);
+                        for (_, positions, label) in &monitors {
+                            let Some(points) =
+                                positions.iter().map(|p| project(*p)).collect::<Option<Vec<_>>>()
+                            else {
+                                continue;
+                            };
+                            painter.extend(egui::Shape::dashed_line(&points, stroke, 5.0, 4.0));
+                            let middle = points.iter().fold(egui::Vec2::ZERO, |sum, p| sum + p.to_vec2())
+                                / points.len() as f32;
+                            painter.text(
+                                middle.to_pos2() + egui::vec2(6.0, -6.0),
+                                egui::Align2::LEFT_BOTTOM,
+                                label,
+                                egui::FontId::proportional(14.0),
+                                color,
+                            );
+                        }
+                    }
+                    if !stereo_labels.is_empty() {
+                        let painter = ctx.layer_painter(egui::LayerId::background());
+                        for (position, label) in &stereo_labels {
+                            let Some(point) = project(*position) else {
+                                continue;
+                            };
+                            painter.text(
+                                point,
+                                egui::Align2::CENTER_CENTER,
+                                *label,
+                                egui::FontId::proportional(16.0),
+                                egui::Color32::WHITE,
+                            );
+                        }
+                    }
+                    if !atom_labels.is_empty() {
+                        let painter = ctx.layer_painter(egui::LayerId::background());
+                        for (position, label) in &atom_labels {
+                            let Some(point) = project(*position) else {
+                                continue;
+                            };
+                            painter.text(
+                                point + egui::vec2(6.0, -6.0),
+                                egui::Align2::LEFT_BOTTOM,
+                                label,
+                                egui::FontId::proportional(13.0),
+                                egui::Color32::from_rgb(200, 230, 255),
+                            );
+                        }
+                    }
                     egui::Window::new("MolWeaver Status")
                         .default_pos(egui::pos2(10.0, 10.0))
                         .show(ctx, |ui| {
                             ui.label(format!("Atoms: {atom_count}"));
                             ui.label(format!("Bonds: {bond_count}"));
+                            if let Some((formula, weight)) = &formula {
+                                ui.label(format!("Formula: {formula}"));
+                                ui.label(format!("Mol. weight: {weight:.3} g/mol"));
+                            }
                             ui.label(format!("FPS: {:.1}", ui_state.fps));
+                            let buffers = render_state.buffer_pool.stats;
+                            ui.label(format!(
+                                "GPU buffers: {} KiB in use, {} KiB pooled",
+                                buffers.live_bytes / 1024,
+                                buffers.free_bytes / 1024
+                            ))
+                            .on_hover_text(format!(
+                                "{} created, {} reused, {} shrunk",
+                                buffers.created, buffers.reused, buffers.shrinks
+                            ));
+                            ui.checkbox(&mut ui_state.check_render, "Check renderer")
+                                .on_hover_text(
+                                    "After every edit, compare the drawn atoms and bonds with \
+                                     the structure and rebuild them if they differ",
+                                );
+                            if let Some(molecule_ref) = molecule.as_ref() {
+                                egui::CollapsingHeader::new("Statistics").show(ui, |ui| {
+                                    let stats = SceneStats::compute(molecule_ref)
+                                        .with_gpu(render_state.gpu_usage());
+                                    stats_ui(ui, &stats);
+                                });
+                                if !molecule_ref.info.is_empty() {
+                                    egui::CollapsingHeader::new("Metadata").show(ui, |ui| {
+                                        for (key, value) in &molecule_ref.info {
+                                            ui.label(format!("{key}: {value}"));
+                                        }
+                                    });
+                                }
+                            }
                             ui.label(format!("File: {}", ui_state.file_name));
-                            if let Some(selection) = ui_state.selection {
-                                ui.label(format!("Selected: {}", selection.value()));
-                            } else {
-                                ui.label("Selected: none");
+                            if let Some(progress) = &ui_state.load_progress {
+                                let (read, total) = progress.bytes();
+                                let megabytes = |bytes: u64| bytes as f32 / 1_048_576.0;
+                                let bar = match total {
+                                    Some(total) => egui::ProgressBar::new(
+                                        (read as f32 / total as f32).min(1.0),
+                                    )
+                                    .text(format!(
+                                        "Loading {:.1} of {:.1} MB",
+                                        megabytes(read),
+                                        megabytes(total)
+                                    )),
+                                    None => egui::ProgressBar::new(0.0)
+                                        .animate(true)
+                                        .text(format!("Loading {:.1} MB", megabytes(read))),
+                                };
+                                ui.add(bar);
+                            }
+                            if history.is_dirty() {
+                                ui.label("Unsaved changes");
+                            }
+                            let warnings = molecule
+                                .as_ref()
+                                .map(|mol| mol.valence_warnings())
+                                .unwrap_or_default();
+                            if !warnings.is_empty() {
+                                let details: Vec<String> =
+                                    warnings.iter().map(|warning| warning.to_string()).collect();
+                                ui.colored_label(
+                                    ui.visuals().warn_fg_color,
+                                    format!("Valence warnings: {}", warnings.len()),
+                                )
+                                .on_hover_text(details.join("\n"));
                             }
+                            match ui_state.selection.atoms() {
+                                [] => ui.label("Selected: none"),
+                                [atom] => ui.label(format!("Selected: {}", atom.value())),
+                                atoms => ui.label(format!("Selected: {} atoms", atoms.len())),
+                            };
+                            ui.horizontal(|ui| {
+                                ui.menu_button("View", |ui| {
+                                    ui.label("Atom labels");
+                                    let labels = &mut ui_state.atom_labels;
+                                    ui.checkbox(&mut labels.element, "Element symbols");
+                                    ui.checkbox(&mut labels.index, "Atom indices");
+                                    ui.checkbox(&mut labels.charge, "Charges");
+                                });
+                                if ui.button("Help (F1)").clicked() {
+                                    ui_state.show_help = true;
+                                }
+                            });
                         });
 
-                    egui::Window::new("Edit")
-                        .default_pos(egui::pos2(10.0, 220.0))
+                    let mut show_help = ui_state.show_help;
+                    egui::Window::new("Help")
+                        .default_pos(egui::pos2(640.0, 10.0))
+                        .open(&mut show_help)
                         .show(ctx, |ui| {
-                            ui.label("Representation");
-                            let mut representation = ui_state.representation;
                             ui.horizontal(|ui| {
-                                ui.radio_value(
-                                    &mut representation,
-                                    Representation::BallAndStick,
-                                    "Ball & Stick",
-                                );
-                                ui.radio_value(
-                                    &mut representation,
-                                    Representation::SpaceFilling,
-                                    "Space Filling",
-                                );
+                                ui.label("Search:");
+                                ui.text_edit_singleline(&mut ui_state.help_query);
                             });
-                            if representation != ui_state.representation {
-                                pending_representation = Some(representation);
+                            egui::ScrollArea::vertical()
+                                .max_height(240.0)
+                                .show(ui, |ui| {
+                                    egui::Grid::new("help_topics").striped(true).show(ui, |ui| {
+                                        for topic in help::search_help(&ui_state.help_query) {
+                                            ui.strong(topic.title);
+                                            ui.label(topic.shortcut);
+                                            ui.label(topic.description);
+                                            ui.end_row();
+                                        }
+                                    });
+                                });
+                            ui.separator();
+                            let Some(tutorial) = ui_state.tutorial else {
+                                if ui.button("Start water tutorial").clicked() {
+                                    pending_tutorial = Some(true);
+                                }
+                                return;
+                            };
+                            ui.strong("Tutorial: build water");
+                            for (index, step) in WATER_TUTORIAL.iter().enumerate() {
+                                let marker = if index < tutorial.current() {
+                                    "✔"
+                                } else {
+                                    "•"
+                                };
+                                if index == tutorial.current() {
+                                    ui.strong(format!("{marker} {}", step.title));
+                                    ui.label(step.instructions);
+                                } else {
+                                    ui.label(format!("{marker} {}", step.title));
+                                }
+                            }
+                            if let Some(angle) = molecule.as_ref().and_then(help::water_angle) {
+                                ui.label(format!("H–O–H angle: {angle:.1}°"));
                             }
+                            if tutorial.is_finished(WATER_TUTORIAL) {
+                                ui.label("Done! Undo steps back through the tutorial.");
+                            }
+                            if ui.button("Exit tutorial").clicked() {
+                                pending_tutorial = Some(false);
+                            }
+                        });
+                    ui_state.show_help = show_help;
 
-                            ui.separator();
-                            ui.label("Tool");
-                            ui.horizontal(|ui| {
-                                ui.radio_value(&mut ui_state.tool, Tool::Select, "Select");
-                                ui.radio_value(&mut ui_state.tool, Tool::AddAtom, "Add Atom");
-                                ui.radio_value(&mut ui_state.tool, Tool::AddBond, "Add Bond");
-                                ui.radio_value(&mut ui_state.tool, Tool::Move, "Move");
+                    let mut show_problems = ui_state.show_problems;
+                    egui::Window::new("Problems")
+                        .default_pos(egui::pos2(640.0, 320.0))
+                        .open(&mut show_problems)
+                        .show(ctx, |ui| {
+                            ui.label(format!("Reading {}", ui_state.source_path));
+                            if let Some(err) = &ui_state.load_error {
+                                ui.colored_label(ui.visuals().error_fg_color, err);
+                                if ui
+                                    .button("Load leniently")
+                                    .on_hover_text("Skip the lines that fail to parse")
+                                    .clicked()
+                                {
+                                    retry_lenient = true;
+                                }
+                            }
+                            if !ui_state.problems.is_empty() {
+                                ui.label(format!(
+                                    "{} lines skipped or worked around:",
+                                    ui_state.problems.len()
+                                ));
+                                egui::ScrollArea::vertical()
+                                    .max_height(240.0)
+                                    .show(ui, |ui| {
+                                        for problem in &ui_state.problems {
+                                            ui.label(problem.to_string());
+                                        }
+                                    });
+                            }
+                        });
+                    ui_state.show_problems = show_problems;
+
+                    let mut show_links = ui_state.show_links;
+                    let mut pending_link_focus = None;
+                    egui::Window::new("Inferred links")
+                        .default_pos(egui::pos2(640.0, 120.0))
+                        .open(&mut show_links)
+                        .show(ctx, |ui| {
+                            ui.label(
+                                "Disulfides were bonded; salt bridges are drawn as dashed lines.",
+                            );
+                            egui::ScrollArea::vertical()
+                                .max_height(240.0)
+                                .show(ui, |ui| match molecule.as_ref() {
+                                    Some(molecule_ref) => {
+                                        for link in &ui_state.inferred_links {
+                                            if ui.link(link.describe(molecule_ref)).clicked() {
+                                                pending_link_focus = Some((link.a, link.b));
+                                            }
+                                        }
+                                    }
+                                    None => {
+                                        ui.label("No molecule loaded.");
+                                    }
+                                });
+                        });
+                    ui_state.show_links = show_links;
+                    if let Some((a, b)) = pending_link_focus {
+                        select_only(a, render_state, &mut ui_state);
+                        ui_state.bond_target = Some(b);
+                    }
+
+                    if let Some(action) = ui_state.confirm_discard {
+                        egui::Window::new("Unsaved changes")
+                            .collapsible(false)
+                            .resizable(false)
+                            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                            .show(ctx, |ui| {
+                                ui.label(format!(
+                                    "{} has edits that were not saved.",
+                                    ui_state.file_name
+                                ));
+                                ui.horizontal(|ui| {
+                                    if ui.button(action.label()).clicked() {
+                                        discard_answer = Some(true);
+                                    }
+                                    if ui.button("Cancel").clicked() {
+                                        discard_answer = Some(false);
+                                    }
+                                });
                             });
+                    }
 
-                            ui.separator();
+                    egui::Window::new("Open")
+                        .default_pos(egui::pos2(640.0, 200.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
                             ui.horizontal(|ui| {
-                                let undo_clicked = ui
-                                    .add_enabled(history.can_undo(), egui::Button::new("Undo"))
-                                    .clicked();
-                                let redo_clicked = ui
-                                    .add_enabled(history.can_redo(), egui::Button::new("Redo"))
-                                    .clicked();
-                                if let Some(molecule_ref) = molecule.as_mut() {
-                                    if undo_clicked {
-                                        undo_command(
-                                            &mut history,
-                                            molecule_ref,
-                                            render_state,
-                                            &mut ui_state,
+                                ui.label("File:");
+                                ui.text_edit_singleline(&mut ui_state.open_path);
+                            });
+                            let format = Format::from_path(ui_state.open_path.trim());
+                            let import = &mut ui_state.import.load;
+                            match format.unwrap_or(Format::Xyz) {
+                                Format::Xyz => {
+                                    ui.horizontal(|ui| {
+                                        ui.label("Frames");
+                                        ui.add(
+                                            egui::DragValue::new(&mut import.xyz.first_frame)
+                                                .clamp_range(1..=usize::MAX),
                                         );
-                                    }
-                                    if redo_clicked {
-                                        redo_command(
-                                            &mut history,
-                                            molecule_ref,
-                                            render_state,
-                                            &mut ui_state,
+                                        ui.label("to");
+                                        ui.add(
+                                            egui::DragValue::new(&mut import.xyz.last_frame)
+                                                .clamp_range(import.xyz.first_frame..=usize::MAX),
+                                        );
+                                    })
+                                    .response
+                                    .on_hover_text(
+                                        "Several frames load together, each atom tagged with \
+                                         its frame",
+                                    );
+                                    import.xyz.last_frame =
+                                        import.xyz.last_frame.max(import.xyz.first_frame);
+                                }
+                                Format::Sdf => {
+                                    let mut record = import.sdf.record + 1;
+                                    ui.horizontal(|ui| {
+                                        ui.label("Record");
+                                        ui.add(
+                                            egui::DragValue::new(&mut record)
+                                                .clamp_range(1..=usize::MAX),
+                                        );
+                                    });
+                                    import.sdf.record = record - 1;
+                                }
+                                Format::Pdb => {
+                                    ui.checkbox(&mut import.pdb.all_models, "All models")
+                                        .on_hover_text(
+                                            "Load every MODEL, each atom tagged with its model",
                                         );
+                                    ui.checkbox(&mut import.pdb.keep_waters, "Keep waters");
+                                    ui.checkbox(
+                                        &mut ui_state.import.infer_links,
+                                        "Infer disulfides and salt bridges",
+                                    )
+                                    .on_hover_text(
+                                        "Bond close cysteine SG pairs and mark ion pairs between \
+                                         charged side chains",
+                                    );
+                                }
+                                Format::Cif => {
+                                    ui.checkbox(&mut import.cif.expand_symmetry, "Expand symmetry")
+                                        .on_hover_text(
+                                            "Fill the unit cell from the asymmetric unit",
+                                        );
+                                }
+                                Format::ZMatrix | Format::Cml | Format::Mol2 => {
+                                    ui.label("This format has no options.");
+                                }
+                            }
+                            ui.checkbox(&mut import.lenient, "Lenient loading");
+                            ui.checkbox(
+                                &mut ui_state.keep_history_on_reload,
+                                "Keep undo history when reopening this file",
+                            );
+                            if ui
+                                .checkbox(&mut ui_state.watch_file, "Reload when the file changes")
+                                .on_hover_text(
+                                    "Watch the open file and reload it when another program, \
+                                     such as an optimizer, rewrites it; the view is kept and \
+                                     each reload is one undo step",
+                                )
+                                .changed()
+                            {
+                                ui_state.file_watch = ui_state
+                                    .watch_file
+                                    .then(|| FileWatch::new(&ui_state.source_path));
+                            }
+                            if ui.button("Open").clicked() {
+                                pending_open = true;
+                            }
+                        });
+
+                    if let Some((cell, metrics)) = cell_info {
+                        egui::Window::new("Cell")
+                            .default_pos(egui::pos2(10.0, 150.0))
+                            .show(ctx, |ui| {
+                                ui.label(format!(
+                                    "a, b, c: {:.3}, {:.3}, {:.3} Å",
+                                    cell.a, cell.b, cell.c
+                                ));
+                                ui.label(format!(
+                                    "α, β, γ: {:.2}, {:.2}, {:.2}°",
+                                    cell.alpha, cell.beta, cell.gamma
+                                ));
+                                ui.label(format!("Volume: {:.2} Å³", metrics.volume));
+                                ui.horizontal(|ui| {
+                                    ui.label("Z:");
+                                    let z = ui.add(
+                                        egui::DragValue::new(&mut ui_state.cell_z)
+                                            .clamp_range(1..=64),
+                                    );
+                                    if z.changed() {
+                                        ui_state.cell_info_dirty = true;
+                                        ui_state.composition_dirty = true;
+                                    }
+                                });
+                                ui.label(format!("Density: {:.4} g/cm³", metrics.density));
+                                ui.label(format!(
+                                    "Packing coefficient: {:.3}",
+                                    metrics.packing_coefficient
+                                ));
+                            });
+                    }
+
+                    let mut close_cell_editor = false;
+                    if let Some(editor) = ui_state.cell_editor.as_mut() {
+                        egui::Window::new("Cell Editor")
+                            .default_pos(egui::pos2(320.0, 150.0))
+                            .show(ctx, |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.radio_value(
+                                        &mut editor.mode,
+                                        CellInputMode::Parameters,
+                                        "Parameters",
+                                    );
+                                    ui.radio_value(
+                                        &mut editor.mode,
+                                        CellInputMode::Matrix,
+                                        "Lattice vectors",
+                                    );
+                                });
+                                match editor.mode {
+                                    CellInputMode::Parameters => {
+                                        let labels = ["a", "b", "c", "α", "β", "γ"];
+                                        egui::Grid::new("cell_parameters").show(ui, |ui| {
+                                            for (index, label) in labels.iter().enumerate() {
+                                                ui.label(*label);
+                                                ui.add(
+                                                    egui::DragValue::new(
+                                                        &mut editor.parameters[index],
+                                                    )
+                                                    .speed(0.01)
+                                                    .max_decimals(4),
+                                                );
+                                                if index % 3 == 2 {
+                                                    ui.end_row();
+                                                }
+                                            }
+                                        });
+                                    }
+                                    CellInputMode::Matrix => {
+                                        egui::Grid::new("cell_vectors").show(ui, |ui| {
+                                            for (vector, label) in
+                                                editor.vectors.iter_mut().zip(["a", "b", "c"])
+                                            {
+                                                ui.label(label);
+                                                for component in vector.iter_mut() {
+                                                    ui.add(
+                                                        egui::DragValue::new(component)
+                                                            .speed(0.01)
+                                                            .max_decimals(4),
+                                                    );
+                                                }
+                                                ui.end_row();
+                                            }
+                                        });
                                     }
                                 }
+                                ui.label("Keep fixed");
+                                ui.horizontal(|ui| {
+                                    ui.radio_value(
+                                        &mut editor.lock,
+                                        CoordinateLock::Fractional,
+                                        "Fractional",
+                                    );
+                                    ui.radio_value(
+                                        &mut editor.lock,
+                                        CoordinateLock::Cartesian,
+                                        "Cartesian",
+                                    );
+                                });
+                                ui.horizontal(|ui| {
+                                    if ui.button("Apply").clicked() {
+                                        pending_command = Some(Command::SetCell {
+                                            cell: Some(editor.cell()),
+                                            lock: editor.lock,
+                                            previous: None,
+                                            moved: Vec::new(),
+                                        });
+                                    }
+                                    if ui.button("Remove Cell").clicked() {
+                                        pending_command = Some(Command::SetCell {
+                                            cell: None,
+                                            lock: CoordinateLock::Cartesian,
+                                            previous: None,
+                                            moved: Vec::new(),
+                                        });
+                                        close_cell_editor = true;
+                                    }
+                                    if ui.button("Close").clicked() {
+                                        close_cell_editor = true;
+                                    }
+                                });
                             });
+                    }
+                    if close_cell_editor {
+                        ui_state.cell_editor = None;
+                    }
 
-                            ui.separator();
-                            ui.label("Add Atom");
+                    egui::Window::new("Compare")
+                        .default_pos(egui::pos2(10.0, 640.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
                             ui.horizontal(|ui| {
-                                ui.label("Element:");
-                                ui.text_edit_singleline(&mut ui_state.edit_element);
+                                ui.label("Steps back:");
+                                ui.add(
+                                    egui::DragValue::new(&mut ui_state.compare_steps)
+                                        .clamp_range(1..=undo_len.max(1)),
+                                );
+                                if ui
+                                    .add_enabled(undo_len > 0, egui::Button::new("Compare"))
+                                    .clicked()
+                                {
+                                    pending_compare = Some(CompareRequest::History(
+                                        ui_state.compare_steps.min(undo_len),
+                                    ));
+                                }
                             });
-                            let add_clicked = ui
-                                .add_enabled(molecule.is_some(), egui::Button::new("Insert Atom"))
-                                .clicked();
-                            if add_clicked {
-                                if let Some(molecule_ref) = molecule.as_mut() {
-                                    let position = if let Some(selection) = ui_state.selection {
-                                        molecule_ref
-                                            .get_atom(selection)
-                                            .map(|atom| Vec3::from_array(atom.position))
-                                            .unwrap_or(ui_state.camera.target)
-                                            + Vec3::new(1.0, 0.0, 0.0)
-                                    } else {
-                                        let direction = (ui_state.camera.position()
-                                            - ui_state.camera.target)
-                                            .normalize_or_zero();
-                                        ui_state.camera.target + direction * 1.5
-                                    };
-                                    let command = Command::InsertAtom {
-                                        element: ui_state.edit_element.trim().to_string(),
-                                        position: position.to_array(),
-                                        atom_id: None,
-                                        order_index: None,
-                                    };
-                                    apply_command(
-                                        command,
-                                        molecule_ref,
-                                        &mut history,
-                                        render_state,
-                                        &mut ui_state,
-                                    );
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .add_enabled(
+                                        ui_state.compare_rx.is_none()
+                                            && ui_state.source_path != cli::STDIN_PATH,
+                                        egui::Button::new("Compare with file"),
+                                    )
+                                    .clicked()
+                                {
+                                    pending_compare = Some(CompareRequest::File);
+                                }
+                                if ui
+                                    .add_enabled(
+                                        ui_state.diff.is_some(),
+                                        egui::Button::new("Clear"),
+                                    )
+                                    .clicked()
+                                {
+                                    pending_compare = Some(CompareRequest::Clear);
                                 }
+                            });
+                            if let Some(diff) = &ui_state.diff {
+                                ui.separator();
+                                ui.colored_label(
+                                    color32(ui_state.palette.added_color()),
+                                    format!(
+                                        "Added: {} atoms, {} bonds",
+                                        diff.added_atoms.len(),
+                                        diff.added_bonds.len()
+                                    ),
+                                );
+                                ui.colored_label(
+                                    color32(ui_state.palette.modified_color()),
+                                    format!(
+                                        "Modified: {} atoms, {} bonds",
+                                        diff.modified_atoms.len(),
+                                        diff.modified_bonds.len()
+                                    ),
+                                );
+                                ui.label(format!(
+                                    "Removed: {} atoms, {} bonds",
+                                    diff.removed_atoms.len(),
+                                    diff.removed_bonds.len()
+                                ));
                             }
+                        });
 
-                            ui.separator();
-                            ui.label("Bond");
-                            let mut bond_target = ui_state.bond_target;
-                            egui::ComboBox::from_label("Bond target")
-                                .selected_text(
-                                    bond_target.map_or("None".into(), |id| id.value().to_string()),
-                                )
-                                .show_ui(ui, |ui| {
-                                    ui.selectable_value(&mut bond_target, None, "None");
-                                    for id in &atom_ids {
-                                        ui.selectable_value(
-                                            &mut bond_target,
-                                            Some(*id),
-                                            id.value().to_string(),
-                                        );
+                    egui::Window::new("Checkpoints")
+                        .default_pos(egui::pos2(320.0, 10.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.text_edit_singleline(&mut ui_state.checkpoint_name);
+                                if ui
+                                    .add_enabled(molecule.is_some(), egui::Button::new("Snapshot"))
+                                    .clicked()
+                                {
+                                    pending_checkpoint = Some(CheckpointAction::Take);
+                                }
+                            });
+                            for (index, checkpoint) in ui_state.checkpoints.iter().enumerate() {
+                                ui.horizontal(|ui| {
+                                    ui.label(&checkpoint.name);
+                                    if ui.button("Revert").clicked() {
+                                        pending_checkpoint = Some(CheckpointAction::Revert(index));
+                                    }
+                                    if ui.button("Branch").clicked() {
+                                        pending_checkpoint = Some(CheckpointAction::Branch(index));
+                                    }
+                                    if ui.button("Delete").clicked() {
+                                        pending_checkpoint = Some(CheckpointAction::Delete(index));
                                     }
                                 });
-                            ui_state.bond_target = bond_target;
-                            let add_bond_clicked = ui
-                                .add_enabled(
-                                    ui_state.selection.is_some() && ui_state.bond_target.is_some(),
-                                    egui::Button::new("Add Bond"),
-                                )
-                                .clicked();
-                            let remove_bond_clicked = ui
-                                .add_enabled(
-                                    ui_state.selection.is_some() && ui_state.bond_target.is_some(),
-                                    egui::Button::new("Remove Bond"),
-                                )
-                                .clicked();
-                            if let Some(molecule_ref) = molecule.as_mut() {
-                                if let (Some(a), Some(b)) =
-                                    (ui_state.selection, ui_state.bond_target)
+                            }
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                ui.label("Session:");
+                                ui.text_edit_singleline(&mut ui_state.session_path);
+                            });
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .add_enabled(
+                                        molecule.is_some() && ui_state.save_rx.is_none(),
+                                        egui::Button::new("Save Session"),
+                                    )
+                                    .clicked()
                                 {
-                                    if add_bond_clicked {
-                                        let command = Command::AddBond {
-                                            atom_a: a,
-                                            atom_b: b,
-                                            bond_id: None,
-                                        };
-                                        apply_command(
-                                            command,
-                                            molecule_ref,
-                                            &mut history,
-                                            render_state,
-                                            &mut ui_state,
-                                        );
-                                    }
-                                    if remove_bond_clicked {
-                                        if let Some(bond_id) = molecule_ref.bond_between(a, b) {
-                                            let command = Command::RemoveBond {
-                                                bond_id,
-                                                removed: None,
-                                            };
-                                            apply_command(
-                                                command,
-                                                molecule_ref,
-                                                &mut history,
-                                                render_state,
-                                                &mut ui_state,
-                                            );
-                                        } else {
-                                            ui_state.status_message = "bond not found".to_string();
+                                    pending_checkpoint = Some(CheckpointAction::SaveSession);
+                                }
+                                if ui
+                                    .add_enabled(
+                                        ui_state.session_rx.is_none(),
+                                        egui::Button::new("Open Session"),
+                                    )
+                                    .clicked()
+                                {
+                                    pending_checkpoint = Some(CheckpointAction::OpenSession);
+                                }
+                            });
+                        });
+
+                    egui::Window::new("Gallery")
+                        .default_pos(egui::pos2(320.0, 60.0))
+                        .default_open(ui_state.gallery.is_some())
+                        .show(ctx, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("Folder:");
+                                ui.add_enabled(
+                                    ui_state.gallery.is_none(),
+                                    egui::TextEdit::singleline(&mut ui_state.gallery_dir),
+                                );
+                                let label = if ui_state.gallery.is_some() {
+                                    "Stop"
+                                } else {
+                                    "Watch"
+                                };
+                                if ui.button(label).clicked() {
+                                    gallery_toggled = true;
+                                }
+                            });
+                            let Some(gallery) = ui_state.gallery.as_ref() else {
+                                return;
+                            };
+                            if gallery.items.is_empty() {
+                                ui.label("Waiting for structure files…");
+                            }
+                            egui::ScrollArea::vertical()
+                                .max_height(420.0)
+                                .show(ui, |ui| {
+                                    ui.horizontal_wrapped(|ui| {
+                                        for (index, item) in gallery.items.iter().enumerate() {
+                                            if show_thumbnail(ui, item).clicked() {
+                                                pending_gallery_open = Some(index);
+                                            }
+                                        }
+                                    });
+                                });
+                        });
+
+                    if let Some(composition) = &composition {
+                        egui::Window::new("Composition")
+                            .default_pos(egui::pos2(320.0, 185.0))
+                            .default_open(false)
+                            .show(ctx, |ui| {
+                                egui::Grid::new("composition_grid")
+                                    .striped(true)
+                                    .show(ui, |ui| {
+                                        ui.strong("Element");
+                                        ui.strong("Count");
+                                        ui.strong("Atom %");
+                                        ui.strong("Mass %");
+                                        ui.end_row();
+                                        for share in &composition.elements {
+                                            ui.label(&share.symbol);
+                                            ui.label(share.count.to_string());
+                                            ui.label(format!(
+                                                "{:.2}",
+                                                100.0 * share.count as f32
+                                                    / composition.atom_count.max(1) as f32
+                                            ));
+                                            ui.label(format!("{:.2}", 100.0 * share.mass_fraction));
+                                            ui.end_row();
                                         }
+                                    });
+                                ui.separator();
+                                ui.label(format!("Atoms: {}", composition.atom_count));
+                                ui.label(format!(
+                                    "Mol. weight: {:.3} g/mol",
+                                    composition.molecular_weight
+                                ));
+                                match composition.density {
+                                    Some(density) => {
+                                        ui.horizontal(|ui| {
+                                            ui.label(format!("Density: {density:.4} g/cm³ at Z ="));
+                                            let z = ui.add(
+                                                egui::DragValue::new(&mut ui_state.cell_z)
+                                                    .clamp_range(1..=64),
+                                            );
+                                            if z.changed() {
+                                                ui_state.cell_info_dirty = true;
+                                                ui_state.composition_dirty = true;
+                                            }
+                                        });
+                                    }
+                                    None => {
+                                        ui.label("Density: set a unit cell to estimate it");
                                     }
                                 }
-                            }
+                                if ui.button("Copy as Text").clicked() {
+                                    let mut text = String::from("element\tcount\tmass_fraction\n");
+                                    for share in &composition.elements {
+                                        text.push_str(&format!(
+                                            "{}\t{}\t{:.6}\n",
+                                            share.symbol, share.count, share.mass_fraction
+                                        ));
+                                    }
+                                    if let Some(density) = composition.density {
+                                        text.push_str(&format!("density_g_cm3\t{density:.6}\n"));
+                                    }
+                                    ui.output_mut(|output| output.copied_text = text);
+                                }
+                            });
+                    }
 
+                    egui::Window::new("Provenance")
+                        .default_pos(egui::pos2(320.0, 160.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            egui::ScrollArea::vertical()
+                                .max_height(240.0)
+                                .stick_to_bottom(true)
+                                .show(ui, |ui| {
+                                    for entry in &ui_state.provenance.entries {
+                                        ui.label(format!(
+                                            "{}  {}",
+                                            provenance::format_utc(entry.time),
+                                            entry.action
+                                        ));
+                                    }
+                                });
                             ui.separator();
-                            ui.label("Move Atom");
-                            ui.add(
-                                egui::Slider::new(&mut ui_state.move_step, 0.05..=2.0).text("step"),
-                            );
-                            if let Some(molecule_ref) = molecule.as_mut() {
-                                if let Some(selection) = ui_state.selection {
-                                    let step = ui_state.move_step;
-                                    if ui.button("+X").clicked() {
-                                        apply_move(
-                                            selection,
-                                            Vec3::X * step,
-                                            molecule_ref,
+                            ui.horizontal(|ui| {
+                                ui.label("Path:");
+                                ui.text_edit_singleline(&mut ui_state.provenance_path);
+                                if ui
+                                    .add_enabled(
+                                        !ui_state.provenance.entries.is_empty(),
+                                        egui::Button::new("Export Log"),
+                                    )
+                                    .clicked()
+                                {
+                                    let path = ui_state.provenance_path.trim();
+                                    ui_state.status_message =
+                                        match std::fs::write(path, ui_state.provenance.to_text()) {
+                                            Ok(()) => format!("exported {path}"),
+                                            Err(err) => format!("log export failed: {err}"),
+                                        };
+                                }
+                            });
+                        });
+
+                    egui::Window::new("Notes")
+                        .default_pos(egui::pos2(320.0, 135.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            let Some(molecule_ref) = molecule.as_ref() else {
+                                ui.label("No molecule loaded.");
+                                return;
+                            };
+                            // With a bond target picked, the note goes on that bond.
+                            let target = ui_state.selection.primary().map(|atom| {
+                                ui_state
+                                    .bond_target
+                                    .and_then(|other| molecule_ref.bond_between(atom, other))
+                                    .map_or(NoteTarget::Atom(atom), NoteTarget::Bond)
+                            });
+                            if target != ui_state.note_target {
+                                ui_state.note_target = target;
+                                ui_state.note_draft = target
+                                    .and_then(|target| ui_state.notes.get(target))
+                                    .unwrap_or_default()
+                                    .to_string();
+                            }
+                            match target {
+                                Some(target) => {
+                                    ui.label(match target {
+                                        NoteTarget::Atom(id) => format!("Atom {}", id.value()),
+                                        NoteTarget::Bond(id) => format!("Bond {}", id.value()),
+                                    });
+                                    ui.text_edit_multiline(&mut ui_state.note_draft);
+                                    ui.horizontal(|ui| {
+                                        if ui.button("Save").clicked() {
+                                            ui_state.notes.set(target, &ui_state.note_draft);
+                                        }
+                                        if ui
+                                            .add_enabled(
+                                                ui_state.notes.get(target).is_some(),
+                                                egui::Button::new("Delete"),
+                                            )
+                                            .clicked()
+                                        {
+                                            ui_state.notes.set(target, "");
+                                            ui_state.note_draft.clear();
+                                        }
+                                    });
+                                }
+                                None => {
+                                    ui.label("Select an atom, or an atom and a bond target.");
+                                }
+                            }
+                            let markers = ui_state.notes.markers(molecule_ref);
+                            if markers.is_empty() {
+                                return;
+                            }
+                            ui.separator();
+                            let mut picked = None;
+                            for (target, _, text) in markers {
+                                let heading = match target {
+                                    NoteTarget::Atom(id) => format!("Atom {}", id.value()),
+                                    NoteTarget::Bond(id) => format!("Bond {}", id.value()),
+                                };
+                                let first_line = text.lines().next().unwrap_or_default();
+                                if ui
+                                    .selectable_label(
+                                        Some(target) == ui_state.note_target,
+                                        format!("{heading}: {first_line}"),
+                                    )
+                                    .clicked()
+                                {
+                                    picked = Some(target);
+                                }
+                            }
+                            let picked = match picked {
+                                Some(NoteTarget::Atom(id)) => Some((id, None)),
+                                Some(NoteTarget::Bond(id)) => molecule_ref
+                                    .bonds()
+                                    .find(|bond| bond.id == id)
+                                    .map(|bond| (bond.a, Some(bond.b))),
+                                None => None,
+                            };
+                            if let Some((atom, bond_target)) = picked {
+                                select_only(atom, render_state, &mut ui_state);
+                                ui_state.bond_target = bond_target;
+                            }
+                        });
+
+                    egui::Window::new("Fragments")
+                        .default_pos(egui::pos2(320.0, 222.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            ui.label(if ui_state.selection.primary().is_some() {
+                                "Click to attach to the selected atom."
+                            } else {
+                                "Click to place at the view center."
+                            });
+                            for category in FragmentCategory::ALL {
+                                egui::CollapsingHeader::new(category.label())
+                                    .default_open(category == FragmentCategory::Group)
+                                    .show(ui, |ui| {
+                                        ui.horizontal_wrapped(|ui| {
+                                            let templates = Fragment::library()
+                                                .iter()
+                                                .filter(|fragment| fragment.category == category);
+                                            for fragment in templates {
+                                                if ui
+                                                    .button(fragment.name)
+                                                    .on_hover_text(fragment.smiles)
+                                                    .clicked()
+                                                {
+                                                    insert_fragment = Some(fragment);
+                                                }
+                                            }
+                                        });
+                                    });
+                            }
+                        });
+
+                    egui::Window::new("Annotations")
+                        .default_pos(egui::pos2(320.0, 147.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            ui.checkbox(&mut ui_state.show_annotations, "Show annotations");
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                egui::ComboBox::from_id_source("arrow_kind")
+                                    .selected_text(ui_state.arrow_kind.label())
+                                    .show_ui(ui, |ui| {
+                                        for kind in ArrowKind::ALL {
+                                            ui.selectable_value(
+                                                &mut ui_state.arrow_kind,
+                                                kind,
+                                                kind.label(),
+                                            );
+                                        }
+                                    });
+                                ui.label("arrow from");
+                                site_kind_combo(ui, "arrow_from", &mut ui_state.arrow_from_bond);
+                                ui.label("to");
+                                site_kind_combo(ui, "arrow_to", &mut ui_state.arrow_to_bond);
+                            });
+                            ui.label(
+                                "Pick the atoms in order with shift-click: the start's atom (or \
+                                 both bond atoms), then the end's.",
+                            );
+                            let arrow = Arrow::from_picks(
+                                ui_state.arrow_kind,
+                                ui_state.selection.atoms(),
+                                ui_state.arrow_from_bond,
+                                ui_state.arrow_to_bond,
+                            );
+                            if ui
+                                .add_enabled(arrow.is_some(), egui::Button::new("Add Arrow"))
+                                .clicked()
+                            {
+                                ui_state.annotations.arrows.extend(arrow);
+                            }
+                            ui.separator();
+                            let pair = match *ui_state.selection.atoms() {
+                                [a, b] => Some((a, b)),
+                                _ => None,
+                            };
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::DragValue::new(&mut ui_state.partial_order)
+                                        .speed(0.05)
+                                        .clamp_range(0.1..=3.0)
+                                        .prefix("order "),
+                                );
+                                if ui
+                                    .add_enabled(
+                                        pair.is_some(),
+                                        egui::Button::new("Add Partial Bond"),
+                                    )
+                                    .on_hover_text(
+                                        "Draw a bond of this order between the two selected \
+                                         atoms; fractions are dashed",
+                                    )
+                                    .clicked()
+                                {
+                                    if let Some((a, b)) = pair {
+                                        let order = ui_state.partial_order;
+                                        ui_state.annotations.set_partial_bond(a, b, order);
+                                    }
+                                }
+                            });
+                            if ui_state.annotations.is_empty() {
+                                return;
+                            }
+                            ui.separator();
+                            let mut remove_arrow = None;
+                            for (index, arrow) in ui_state.annotations.arrows.iter().enumerate() {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!(
+                                        "{} arrow {} → {}",
+                                        arrow.kind.label(),
+                                        arrow.from.label(),
+                                        arrow.to.label()
+                                    ));
+                                    if ui.small_button("Delete").clicked() {
+                                        remove_arrow = Some(index);
+                                    }
+                                });
+                            }
+                            let mut remove_bond = None;
+                            for (index, bond) in
+                                ui_state.annotations.partial_bonds.iter().enumerate()
+                            {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!(
+                                        "Partial bond {}–{} ({})",
+                                        bond.a.value(),
+                                        bond.b.value(),
+                                        bond.order
+                                    ));
+                                    if ui.small_button("Delete").clicked() {
+                                        remove_bond = Some(index);
+                                    }
+                                });
+                            }
+                            if let Some(index) = remove_arrow {
+                                ui_state.annotations.arrows.remove(index);
+                            }
+                            if let Some(index) = remove_bond {
+                                ui_state.annotations.partial_bonds.remove(index);
+                            }
+                            if ui.button("Clear All").clicked() {
+                                ui_state.annotations = Annotations::default();
+                            }
+                        });
+
+                    egui::Window::new("Measurements")
+                        .default_pos(egui::pos2(320.0, 234.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            let mut kind = ui_state.measurements.kind;
+                            ui.horizontal(|ui| {
+                                for option in MeasureKind::ALL {
+                                    ui.radio_value(&mut kind, option, option.label());
+                                }
+                            });
+                            ui_state.measurements.set_kind(kind);
+                            let picked = ui_state.measurements.picks().len();
+                            ui.label(if ui_state.tool != Tool::Measure {
+                                "Pick the Measure tool, then click atoms.".to_string()
+                            } else if picked > 0 {
+                                format!("Picked {picked} of {} atoms.", kind.arity())
+                            } else {
+                                format!("Click {} atoms.", kind.arity())
+                            });
+                            ui.separator();
+                            let mut remove = None;
+                            for (index, measurement) in
+                                ui_state.measurements.items.iter().enumerate()
+                            {
+                                let value = molecule
+                                    .as_ref()
+                                    .and_then(|m| measurement.value_label(m))
+                                    .unwrap_or_else(|| "–".to_string());
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("{}: {value}", measurement.label()));
+                                    if ui.small_button("Remove").clicked() {
+                                        remove = Some(index);
+                                    }
+                                });
+                            }
+                            if let Some(index) = remove {
+                                ui_state.measurements.remove(index);
+                            }
+                            if ui
+                                .add_enabled(
+                                    !ui_state.measurements.items.is_empty(),
+                                    egui::Button::new("Clear All"),
+                                )
+                                .clicked()
+                            {
+                                ui_state.measurements.clear();
+                            }
+                        });
+
+                    egui::Window::new("Pocket Wizard")
+                        .default_pos(egui::pos2(320.0, 160.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            let ligands =
+                                molecule.as_ref().map(pocket::ligands).unwrap_or_default();
+                            if ligands.is_empty() {
+                                ui.label("No ligands; open a PDB file with HETATM residues.");
+                                return;
+                            }
+                            let label = |residue: &residues::Residue| {
+                                let id = &residue.id;
+                                format!("{} {}{}", residue.name, id.chain, id.number)
+                            };
+                            ui.label("1. Ligand");
+                            let selected = ligands
+                                .iter()
+                                .find(|residue| {
+                                    ui_state.pocket_ligand.as_ref() == Some(&residue.id)
+                                })
+                                .map_or("Choose…".to_string(), label);
+                            egui::ComboBox::from_id_source("pocket_ligand")
+                                .selected_text(selected)
+                                .show_ui(ui, |ui| {
+                                    for residue in &ligands {
+                                        ui.selectable_value(
+                                            &mut ui_state.pocket_ligand,
+                                            Some(residue.id.clone()),
+                                            label(residue),
+                                        );
+                                    }
+                                });
+                            ui.label("2. Shell");
+                            ui.horizontal(|ui| {
+                                ui.label("Radius");
+                                ui.add(
+                                    egui::DragValue::new(&mut ui_state.pocket_options.radius)
+                                        .speed(0.1)
+                                        .clamp_range(1.0..=15.0)
+                                        .suffix(" Å"),
+                                );
+                            });
+                            ui.checkbox(&mut ui_state.pocket_options.keep_waters, "Keep waters");
+                            ui.label("3. Cluster model");
+                            let carve_clicked = ui
+                                .add_enabled(
+                                    ui_state.pocket_ligand.is_some(),
+                                    egui::Button::new("Carve"),
+                                )
+                                .on_hover_text(
+                                    "Replace the structure with the ligand, the residues within \
+                                     the radius and hydrogen caps; undo brings the protein back",
+                                )
+                                .clicked();
+                            let ligand = ui_state.pocket_ligand.clone().filter(|_| carve_clicked);
+                            let (Some(ligand), Some(molecule_ref)) = (ligand, molecule.as_mut())
+                            else {
+                                return;
+                            };
+                            let options = ui_state.pocket_options;
+                            match pocket::carve_pocket(molecule_ref, &ligand, &options) {
+                                Ok(pocket) => {
+                                    let summary = format!(
+                                        "cluster model: {} atoms, {} residues, {} caps",
+                                        pocket.molecule.atom_count(),
+                                        pocket.residues.len(),
+                                        pocket.caps
+                                    );
+                                    ui_state.provenance.record(format!(
+                                        "carve pocket around {}{} within {:.1} Å",
+                                        ligand.chain, ligand.number, options.radius
+                                    ));
+                                    ui_state.inferred_links.clear();
+                                    ui_state.pocket_ligand = None;
+                                    let command = Command::Restore {
+                                        snapshot: Box::new(pocket.molecule),
+                                        previous: None,
+                                    };
+                                    apply_command(
+                                        command,
+                                        molecule_ref,
+                                        &mut history,
+                                        render_state,
+                                        &mut ui_state,
+                                    );
+                                    ui_state.fit_to_view(molecule_ref);
+                                    ui_state.status_message = summary;
+                                }
+                                Err(err) => ui_state.status_message = err,
+                            }
+                        });
+
+                    let clashes = egui::Window::new("Docking")
+                        .default_pos(egui::pos2(320.0, 185.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            let (Some(molecule_ref), Some(selection)) =
+                                (molecule.as_mut(), ui_state.selection.primary())
+                            else {
+                                ui.label("Select an atom of the molecule to move (the guest).");
+                                return HashSet::new();
+                            };
+                            let guest = docking::guest_of(molecule_ref, selection);
+                            let host = molecule_ref.atom_count() - guest.len();
+                            ui.label(format!("Guest: {} atoms; host: {host} atoms", guest.len()));
+                            if host == 0 {
+                                ui.label("Nothing to dock against; the structure is one fragment.");
+                                return HashSet::new();
+                            }
+                            let score = docking::score(molecule_ref, &guest);
+                            egui::Grid::new("docking_score").show(ui, |ui| {
+                                ui.label("Lennard-Jones");
+                                ui.label(format!("{:.2} kcal/mol", score.lennard_jones));
+                                ui.end_row();
+                                ui.label("Electrostatic");
+                                ui.label(format!("{:.2} kcal/mol", score.electrostatic));
+                                ui.end_row();
+                                ui.label("Total");
+                                ui.strong(format!("{:.2} kcal/mol", score.total()));
+                                ui.end_row();
+                                ui.label("Clashes");
+                                ui.label(score.clashes.len().to_string());
+                                ui.end_row();
+                            });
+                            ui.checkbox(&mut ui_state.highlight_clashes, "Highlight clashes");
+                            ui.add(
+                                egui::Slider::new(&mut ui_state.docking_step, 0.05..=2.0)
+                                    .text("step (Å)"),
+                            );
+                            ui.add(
+                                egui::Slider::new(&mut ui_state.docking_angle, 1.0..=90.0)
+                                    .text("turn (°)"),
+                            );
+                            let (step, angle) =
+                                (ui_state.docking_step, ui_state.docking_angle.to_radians());
+                            let mut motion = None;
+                            for (label, axis) in [("X", Vec3::X), ("Y", Vec3::Y), ("Z", Vec3::Z)] {
+                                ui.horizontal(|ui| {
+                                    if ui.button(format!("+{label}")).clicked() {
+                                        motion = Some((Quat::IDENTITY, axis * step));
+                                    }
+                                    if ui.button(format!("-{label}")).clicked() {
+                                        motion = Some((Quat::IDENTITY, -axis * step));
+                                    }
+                                    if ui.button(format!("⟲{label}")).clicked() {
+                                        motion = Some((Quat::from_axis_angle(axis, angle), Vec3::ZERO));
+                                    }
+                                    if ui.button(format!("⟳{label}")).clicked() {
+                                        motion = Some((Quat::from_axis_angle(axis, -angle), Vec3::ZERO));
+                                    }
+                                });
+                            }
+                            if let Some((rotation, translation)) = motion {
+                                let command = Command::move_rigid(
+                                    molecule_ref,
+                                    &guest,
+                                    rotation,
+                                    translation,
+                                );
+                                apply_command(
+                                    command,
+                                    molecule_ref,
+                                    &mut history,
+                                    render_state,
+                                    &mut ui_state,
+                                );
+                            }
+                            if ui_state.highlight_clashes {
+                                score.clashing_atoms()
+                            } else {
+                                HashSet::new()
+                            }
+                        })
+                        .and_then(|response| response.inner)
+                        .unwrap_or_default();
+                    if !clashes.is_empty() || !ui_state.docking_clashes.is_empty() {
+                        render_state.set_clash_highlight(&clashes);
+                        ui_state.docking_clashes = clashes;
+                    }
+
+                    egui::Window::new("Substitution Sweep")
+                        .default_pos(egui::pos2(320.0, 197.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            let (Some(molecule_ref), Some(site)) =
+                                (molecule.as_ref(), ui_state.selection.primary())
+                            else {
+                                ui.label("Select the atom to substitute.");
+                                return;
+                            };
+                            let element = molecule_ref
+                                .get_atom(site)
+                                .map_or("?", |atom| atom.element.as_str());
+                            ui.label(format!("Site: {element} (atom {})", site.value()));
+                            ui.label("Substituents")
+                                .on_hover_text(
+                                    "Elements, named groups (Me, Et, iPr, tBu, Ph, OH, OMe, NH2, \
+                                     NMe2, CF3, CN, NO2, CHO, COOH) or SMILES attaching through \
+                                     a bracketed first atom such as [CH2]C#N",
+                                );
+                            ui.text_edit_singleline(&mut ui_state.sweep_list);
+                            ui.horizontal(|ui| {
+                                ui.label("Output");
+                                ui.text_edit_singleline(&mut ui_state.sweep_path);
+                            });
+                            if !ui
+                                .button("Export Series")
+                                .on_hover_text(
+                                    "Write one file per substituent, named after the output \
+                                     with the substituent appended; .sdf or XYZ otherwise",
+                                )
+                                .clicked()
+                            {
+                                return;
+                            }
+                            let base = PathBuf::from(&ui_state.sweep_path);
+                            let sdf_output = base
+                                .extension()
+                                .is_some_and(|extension| extension.eq_ignore_ascii_case("sdf"));
+                            let written = sweep::parse_substituents(&ui_state.sweep_list)
+                                .and_then(|list| sweep::sweep(molecule_ref, site, &list))
+                                .and_then(|structures| {
+                                    for (label, structure) in &structures {
+                                        let path = sweep::sweep_path(&base, label);
+                                        let contents = if sdf_output {
+                                            sdf::write_sdf(structure)
+                                        } else {
+                                            write_xyz(structure, ui_state.xyz_precision)
+                                        };
+                                        std::fs::write(&path, contents)
+                                            .map_err(|err| format!("{}: {err}", path.display()))?;
+                                    }
+                                    Ok(structures.len())
+                                });
+                            ui_state.status_message = match written {
+                                Ok(count) => {
+                                    ui_state.provenance.record(format!(
+                                        "substitution sweep at atom {} ({}) to {}",
+                                        site.value(),
+                                        ui_state.sweep_list,
+                                        base.display()
+                                    ));
+                                    format!("exported {count} substituted structures")
+                                }
+                                Err(err) => format!("sweep failed: {err}"),
+                            };
+                        });
+
+                    egui::Window::new("Perturbed Copies")
+                        .default_pos(egui::pos2(320.0, 203.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            let Some(molecule_ref) = molecule.as_ref() else {
+                                ui.label("Load a structure first.");
+                                return;
+                            };
+                            egui::Grid::new("perturb_settings").show(ui, |ui| {
+                                ui.label("Copies");
+                                ui.add(
+                                    egui::DragValue::new(&mut ui_state.perturb_count)
+                                        .clamp_range(1..=10000),
+                                );
+                                ui.end_row();
+                                ui.label("Amplitude");
+                                ui.add(
+                                    egui::DragValue::new(&mut ui_state.perturb_amplitude)
+                                        .speed(0.01)
+                                        .clamp_range(0.0..=5.0)
+                                        .suffix(" Å"),
+                                );
+                                ui.end_row();
+                                ui.label("Seed");
+                                ui.add(egui::DragValue::new(&mut ui_state.perturb_seed));
+                                ui.end_row();
+                                ui.label("Output");
+                                ui.text_edit_singleline(&mut ui_state.perturb_path);
+                                ui.end_row();
+                            });
+                            let frozen: HashSet<AtomId> = ui_state
+                                .find_matches
+                                .iter()
+                                .flat_map(|found| found.atoms.iter().copied())
+                                .collect();
+                            ui.checkbox(
+                                &mut ui_state.perturb_freeze_found,
+                                format!("Freeze found atoms ({})", frozen.len()),
+                            )
+                            .on_hover_text("Atoms matched in the Find window keep their positions");
+                            if !ui
+                                .button("Export Copies")
+                                .on_hover_text(
+                                    "Write each copy as a numbered file next to the output, \
+                                     e.g. perturbed_0001.xyz; .sdf or XYZ otherwise",
+                                )
+                                .clicked()
+                            {
+                                return;
+                            }
+                            let frozen = if ui_state.perturb_freeze_found {
+                                frozen
+                            } else {
+                                HashSet::new()
+                            };
+                            let base = PathBuf::from(&ui_state.perturb_path);
+                            let extension = match base.extension() {
+                                Some(extension) if extension.eq_ignore_ascii_case("sdf") => "sdf",
+                                _ => "xyz",
+                            };
+                            let copies = perturb::perturbations(
+                                molecule_ref,
+                                ui_state.perturb_seed,
+                                ui_state.perturb_amplitude,
+                                ui_state.perturb_count,
+                                &frozen,
+                            );
+                            let written = copies.iter().zip(1..).try_for_each(|(copy, number)| {
+                                let path = trajectory::frame_path(&base, number, extension);
+                                let contents = match extension {
+                                    "sdf" => sdf::write_sdf(copy),
+                                    _ => write_xyz(copy, ui_state.xyz_precision),
+                                };
+                                std::fs::write(&path, contents)
+                                    .map_err(|err| format!("{}: {err}", path.display()))
+                            });
+                            ui_state.status_message = match written {
+                                Ok(()) => {
+                                    ui_state.provenance.record(format!(
+                                        "export {} perturbed copies (seed {}, {:.2} Å, {} frozen) \
+                                         as {}",
+                                        copies.len(),
+                                        ui_state.perturb_seed,
+                                        ui_state.perturb_amplitude,
+                                        frozen.len(),
+                                        trajectory::frame_path(&base, 1, extension).display()
+                                    ));
+                                    format!("exported {} perturbed copies", copies.len())
+                                }
+                                Err(err) => format!("export failed: {err}"),
+                            };
+                        });
+
+                    egui::Window::new("Animation")
+                        .default_pos(egui::pos2(320.0, 210.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            ui.label("Camera keyframes");
+                            let mut go_to = None;
+                            let mut delete = None;
+                            for (index, keyframe) in
+                                ui_state.camera_path.keyframes().iter().enumerate()
+                            {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("{:.2} s", keyframe.time));
+                                    if ui.small_button("Go").clicked() {
+                                        go_to = Some(keyframe.camera);
+                                    }
+                                    if ui.small_button("Delete").clicked() {
+                                        delete = Some(index);
+                                    }
+                                });
+                            }
+                            if let Some(camera) = go_to {
+                                ui_state.go_to_view(camera);
+                            }
+                            if let Some(index) = delete {
+                                ui_state.camera_path.remove(index);
+                            }
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::DragValue::new(&mut ui_state.keyframe_time)
+                                        .speed(0.1)
+                                        .clamp_range(0.0..=3600.0)
+                                        .suffix(" s"),
+                                );
+                                if ui
+                                    .button("Add Keyframe")
+                                    .on_hover_text(
+                                        "Keep the current view at this time; a keyframe \
+                                         already there is replaced",
+                                    )
+                                    .clicked()
+                                {
+                                    ui_state.camera_path.insert(Keyframe {
+                                        time: ui_state.keyframe_time,
+                                        camera: ui_state.camera,
+                                    });
+                                    ui_state.keyframe_time += 2.0;
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Frame rate");
+                                ui.add(
+                                    egui::DragValue::new(&mut ui_state.animation_fps)
+                                        .clamp_range(1..=120)
+                                        .suffix(" fps"),
+                                );
+                            });
+                            let frames =
+                                ui_state.camera_path.frames(ui_state.animation_fps).len();
+                            ui.label(format!(
+                                "{:.2} s, {frames} frames",
+                                ui_state.camera_path.duration()
+                            ));
+                            let has_path = ui_state.camera_path.keyframes().len() >= 2;
+                            let idle = ui_state.movie.is_none() && ui_state.video_rx.is_none();
+                            let mut cameras = None;
+                            ui.horizontal(|ui| {
+                                let playing = ui_state.animation_preview.is_some();
+                                if ui
+                                    .add_enabled(
+                                        has_path,
+                                        egui::Button::new(if playing { "Stop" } else { "Preview" }),
+                                    )
+                                    .clicked()
+                                {
+                                    ui_state.animation_preview =
+                                        (!playing).then(Instant::now);
+                                }
+                                if ui
+                                    .add_enabled(has_path && idle, egui::Button::new("Render Path"))
+                                    .clicked()
+                                {
+                                    cameras =
+                                        Some(ui_state.camera_path.frames(ui_state.animation_fps));
+                                }
+                            });
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                ui.label("Turntable");
+                                ui.add(
+                                    egui::DragValue::new(&mut ui_state.turntable_frames)
+                                        .clamp_range(2..=3600)
+                                        .suffix(" frames"),
+                                );
+                                if ui
+                                    .add_enabled(idle, egui::Button::new("Render Turntable"))
+                                    .on_hover_text("One full turn of the current view")
+                                    .clicked()
+                                {
+                                    cameras = Some(animation::turntable(
+                                        &ui_state.camera,
+                                        ui_state.turntable_frames,
+                                    ));
+                                }
+                            });
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                ui.label("Frames");
+                                ui.text_edit_singleline(&mut ui_state.animation_path)
+                                    .on_hover_text(
+                                        "One numbered PNG per frame, e.g. movie_0001.png, \
+                                         using the presentation settings when they are on",
+                                    );
+                            });
+                            ui.checkbox(&mut ui_state.encode_video, "Encode MP4 with ffmpeg")
+                                .on_hover_text(
+                                    "Run ffmpeg on the finished frames at the frame rate \
+                                     above; it must be on the PATH",
+                                );
+                            if let Some(cameras) = cameras {
+                                let encode =
+                                    ui_state.encode_video.then_some(ui_state.animation_fps);
+                                ui_state.movie = Some(MovieRender {
+                                    cameras,
+                                    base: PathBuf::from(&ui_state.animation_path),
+                                    next: 0,
+                                    encode,
+                                });
+                            }
+                            if ui_state.movie.is_some() && ui.button("Cancel").clicked() {
+                                ui_state.movie = None;
+                                ui_state.status_message = "frame rendering cancelled".to_string();
+                            }
+                            if let Some(movie) = &ui_state.movie {
+                                ui.add(
+                                    egui::ProgressBar::new(
+                                        movie.next as f32 / movie.cameras.len().max(1) as f32,
+                                    )
+                                    .text(format!("{} / {}", movie.next, movie.cameras.len())),
+                                );
+                            }
+                        });
+
+                    egui::Window::new("Comparison Grid")
+                        .default_pos(egui::pos2(320.0, 85.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            if ui
+                                .checkbox(&mut ui_state.grid_enabled, "Show grid")
+                                .changed()
+                            {
+                                ui_state.grid_dirty = true;
+                            }
+                            ui.horizontal(|ui| {
+                                ui.label("File:");
+                                ui.text_edit_singleline(&mut ui_state.grid_path);
+                                if ui
+                                    .add_enabled(
+                                        ui_state.grid_rx.is_none()
+                                            && !ui_state.grid_path.trim().is_empty(),
+                                        egui::Button::new("Add"),
+                                    )
+                                    .clicked()
+                                {
+                                    pending_grid = Some(GridAction::AddFile);
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .add_enabled(molecule.is_some(), egui::Button::new("Add current"))
+                                    .clicked()
+                                {
+                                    pending_grid = Some(GridAction::AddCurrent);
+                                }
+                                let gallery_loaded = ui_state
+                                    .gallery
+                                    .as_ref()
+                                    .is_some_and(|gallery| !gallery.items.is_empty());
+                                if ui
+                                    .add_enabled(gallery_loaded, egui::Button::new("Add gallery"))
+                                    .clicked()
+                                {
+                                    pending_grid = Some(GridAction::AddGallery);
+                                }
+                                if ui
+                                    .add_enabled(
+                                        !ui_state.grid_samples.is_empty(),
+                                        egui::Button::new("Clear"),
+                                    )
+                                    .clicked()
+                                {
+                                    pending_grid = Some(GridAction::Clear);
+                                }
+                            });
+                            for (index, sample) in ui_state.grid_samples.iter().enumerate() {
+                                ui.horizontal(|ui| {
+                                    ui.label(&sample.name);
+                                    if ui.small_button("Remove").clicked() {
+                                        pending_grid = Some(GridAction::Remove(index));
+                                    }
+                                });
+                            }
+                        });
+
+                    egui::Window::new("Find Substructure")
+                        .default_pos(egui::pos2(320.0, 110.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("Pattern:");
+                                ui.text_edit_singleline(&mut ui_state.find_path);
+                                if ui
+                                    .add_enabled(
+                                        molecule.is_some() && ui_state.find_rx.is_none(),
+                                        egui::Button::new("Find"),
+                                    )
+                                    .clicked()
+                                {
+                                    pending_find = Some(FindRequest::File);
+                                }
+                            });
+                            if ui
+                                .add_enabled(
+                                    molecule.is_some() && ui_state.clipboard.is_some(),
+                                    egui::Button::new("Find clipboard"),
+                                )
+                                .clicked()
+                            {
+                                pending_find = Some(FindRequest::Clipboard);
+                            }
+                            ui.horizontal(|ui| {
+                                ui.label("Query:")
+                                    .on_hover_text("SMARTS-like, e.g. [C:1]=O or [N;R]");
+                                let response = ui.text_edit_singleline(&mut ui_state.find_query);
+                                let submitted = response.lost_focus()
+                                    && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                                if ui
+                                    .add_enabled(molecule.is_some(), egui::Button::new("Select"))
+                                    .clicked()
+                                    || (submitted && molecule.is_some())
+                                {
+                                    pending_find = Some(FindRequest::Query);
+                                }
+                            });
+                            if ui_state.hooks.selection_names().next().is_some() {
+                                ui.horizontal(|ui| {
+                                    egui::ComboBox::from_label("Script selection")
+                                        .selected_text(ui_state.find_selection.as_str())
+                                        .show_ui(ui, |ui| {
+                                            for name in ui_state.hooks.selection_names() {
+                                                ui.selectable_value(
+                                                    &mut ui_state.find_selection,
+                                                    name.to_string(),
+                                                    name,
+                                                );
+                                            }
+                                        });
+                                    if ui
+                                        .add_enabled(
+                                            molecule.is_some()
+                                                && !ui_state.find_selection.is_empty(),
+                                            egui::Button::new("Select"),
+                                        )
+                                        .clicked()
+                                    {
+                                        pending_find = Some(FindRequest::Script);
+                                    }
+                                });
+                            }
+                            if ui_state.find_matches.is_empty() {
+                                return;
+                            }
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                ui.label(format!("{} matches", ui_state.find_matches.len()));
+                                if ui.button("Show all").clicked() {
+                                    pending_find = Some(FindRequest::Focus(None));
+                                }
+                                if ui.button("Clear").clicked() {
+                                    pending_find = Some(FindRequest::Clear);
+                                }
+                            });
+                            egui::ScrollArea::vertical()
+                                .max_height(200.0)
+                                .show(ui, |ui| {
+                                    for (index, found) in ui_state.find_matches.iter().enumerate() {
+                                        let atoms: Vec<String> = found
+                                            .atoms
+                                            .iter()
+                                            .map(|id| id.value().to_string())
+                                            .collect();
+                                        let label = format!("#{}: {}", index + 1, atoms.join(", "));
+                                        if ui
+                                            .selectable_label(
+                                                ui_state.find_focus == Some(index),
+                                                label,
+                                            )
+                                            .clicked()
+                                        {
+                                            pending_find = Some(FindRequest::Focus(Some(index)));
+                                        }
+                                    }
+                                });
+                        });
+
+                    egui::Window::new("Style Overrides")
+                        .default_pos(egui::pos2(640.0, 360.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            ui.horizontal(|ui| {
+                                let mut enabled = ui_state.style_color.is_some();
+                                ui.checkbox(&mut enabled, "Color");
+                                let mut color = ui_state.style_color.unwrap_or([1.0, 0.5, 0.0]);
+                                if enabled {
+                                    ui.color_edit_button_rgb(&mut color);
+                                }
+                                ui_state.style_color = enabled.then_some(color);
+                            });
+                            ui.horizontal(|ui| {
+                                let mut enabled = ui_state.style_radius_scale.is_some();
+                                ui.checkbox(&mut enabled, "Radius ×");
+                                let mut scale = ui_state.style_radius_scale.unwrap_or(1.0);
+                                if enabled {
+                                    ui.add(egui::Slider::new(&mut scale, 0.2..=3.0));
+                                }
+                                ui_state.style_radius_scale = enabled.then_some(scale);
+                            });
+                            ui.horizontal(|ui| {
+                                let mut enabled = ui_state.style_dash.is_some();
+                                ui.checkbox(&mut enabled, "Bond dash");
+                                let mut dash = ui_state.style_dash.unwrap_or(BondDash::Dashed);
+                                if enabled {
+                                    egui::ComboBox::from_id_source("style_dash")
+                                        .selected_text(dash.label())
+                                        .show_ui(ui, |ui| {
+                                            for option in BondDash::ALL {
+                                                ui.selectable_value(
+                                                    &mut dash,
+                                                    option,
+                                                    option.label(),
+                                                );
+                                            }
+                                        });
+                                }
+                                ui_state.style_dash = enabled.then_some(dash);
+                            });
+                            let style = StyleOverride {
+                                color: ui_state.style_color,
+                                radius_scale: ui_state.style_radius_scale,
+                            };
+                            ui.horizontal(|ui| {
+                                ui.text_edit_singleline(&mut ui_state.style_element);
+                                if ui.button("Apply to element").clicked() {
+                                    let mut updated = ui_state.style.clone();
+                                    updated.set_element(&ui_state.style_element, style);
+                                    pending_style = Some(updated);
+                                }
+                            });
+                            if let Some(selection) = ui_state.selection.primary() {
+                                if ui
+                                    .button(format!("Apply to atom {}", selection.value()))
+                                    .clicked()
+                                {
+                                    let mut updated = ui_state.style.clone();
+                                    updated.set_atom(selection, style);
+                                    pending_style = Some(updated);
+                                }
+                            }
+                            let bond = molecule.as_ref().and_then(|m| {
+                                m.bond_between(ui_state.selection.primary()?, ui_state.bond_target?)
+                            });
+                            if let Some(bond) = bond {
+                                if ui
+                                    .button(format!("Apply to bond {}", bond.value()))
+                                    .on_hover_text(
+                                        "Restyle the bond between the selection and the bond \
+                                         target, e.g. to emphasize a forming bond",
+                                    )
+                                    .clicked()
+                                {
+                                    let mut updated = ui_state.style.clone();
+                                    updated.set_bond(
+                                        bond,
+                                        BondStyle {
+                                            color: ui_state.style_color,
+                                            radius_scale: ui_state.style_radius_scale,
+                                            dash: ui_state.style_dash,
+                                        },
+                                    );
+                                    pending_style = Some(updated);
+                                }
+                            }
+                            ui.separator();
+                            ui.label("Rules, one per line:");
+                            ui.add(
+                                egui::TextEdit::multiline(&mut ui_state.style_rules)
+                                    .desired_rows(3)
+                                    .hint_text("element == Fe -> radius 1.2, color orange"),
+                            );
+                            ui.horizontal(|ui| {
+                                if ui.button("Apply rules").clicked() {
+                                    match parse_rules(&ui_state.style_rules) {
+                                        Ok(rules) => {
+                                            let mut updated = ui_state.style.clone();
+                                            updated.rules = rules;
+                                            pending_style = Some(updated);
+                                        }
+                                        Err(err) => {
+                                            ui_state.status_message =
+                                                format!("style rules: {err}");
+                                        }
+                                    }
+                                }
+                                let hidden = render_state.style.hidden_count();
+                                if hidden > 0 {
+                                    ui.label(format!("{hidden} atoms hidden"));
+                                }
+                            });
+                            if ui_state.style.is_empty() {
+                                return;
+                            }
+                            ui.separator();
+                            let describe_fields = |color: Option<[f32; 3]>, scale: Option<f32>| {
+                                let mut parts = Vec::new();
+                                if let Some([r, g, b]) = color {
+                                    parts.push(format!("color {r:.2} {g:.2} {b:.2}"));
+                                }
+                                if let Some(scale) = scale {
+                                    parts.push(format!("radius ×{scale:.2}"));
+                                }
+                                parts
+                            };
+                            let describe = |style: &StyleOverride| {
+                                describe_fields(style.color, style.radius_scale).join(", ")
+                            };
+                            for (element, style) in &ui_state.style.elements {
+                                ui.horizontal(|ui| {
+                                    if ui.small_button("✖").clicked() {
+                                        let mut updated = ui_state.style.clone();
+                                        updated.elements.remove(element);
+                                        pending_style = Some(updated);
+                                    }
+                                    ui.label(format!("{element}: {}", describe(style)));
+                                });
+                            }
+                            for (id, style) in &ui_state.style.atoms {
+                                ui.horizontal(|ui| {
+                                    if ui.small_button("✖").clicked() {
+                                        let mut updated = ui_state.style.clone();
+                                        updated.atoms.remove(id);
+                                        pending_style = Some(updated);
+                                    }
+                                    ui.label(format!("atom {}: {}", id.value(), describe(style)));
+                                });
+                            }
+                            for (id, style) in &ui_state.style.bonds {
+                                ui.horizontal(|ui| {
+                                    if ui.small_button("✖").clicked() {
+                                        let mut updated = ui_state.style.clone();
+                                        updated.bonds.remove(id);
+                                        pending_style = Some(updated);
+                                    }
+                                    let mut parts = describe_fields(style.color, style.radius_scale);
+                                    if let Some(dash) = style.dash {
+                                        parts.push(dash.label().to_lowercase());
+                                    }
+                                    ui.label(format!("bond {}: {}", id.value(), parts.join(", ")));
+                                });
+                            }
+                        });
+
+                    egui::Window::new("Preferences")
+                        .default_pos(egui::pos2(640.0, 300.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            let mut palette = ui_state.palette;
+                            egui::ComboBox::from_label("Palette")
+                                .selected_text(palette.label())
+                                .show_ui(ui, |ui| {
+                                    for option in Palette::ALL {
+                                        ui.selectable_value(&mut palette, option, option.label());
+                                    }
+                                });
+                            ui.horizontal(|ui| {
+                                for (label, color) in [
+                                    ("Selected", palette.selection_color()),
+                                    ("Added", palette.added_color()),
+                                    ("Modified", palette.modified_color()),
+                                ] {
+                                    ui.colored_label(color32(color), label);
+                                }
+                            });
+                            if palette != ui_state.palette {
+                                pending_palette = Some(palette);
+                            }
+                            let mut coloring = ui_state.coloring.clone();
+                            egui::ComboBox::from_label("Coloring")
+                                .selected_text(coloring.as_deref().unwrap_or("Element"))
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut coloring, None, "Element");
+                                    for name in ui_state.hooks.coloring_names() {
+                                        ui.selectable_value(
+                                            &mut coloring,
+                                            Some(name.to_string()),
+                                            name,
+                                        );
+                                    }
+                                });
+                            if coloring != ui_state.coloring {
+                                ui_state.coloring = coloring;
+                                ui_state.coloring_dirty = true;
+                            }
+                            ui.horizontal(|ui| {
+                                ui.label("Script:").on_hover_text(
+                                    "Rhai file calling register_coloring or register_selection",
+                                );
+                                ui.text_edit_singleline(&mut ui_state.script_path);
+                                if ui.button("Load").clicked() {
+                                    load_script = true;
+                                }
+                            });
+                            if ui
+                                .checkbox(&mut ui_state.stereo_labels, "Label R/S and E/Z")
+                                .changed()
+                            {
+                                ui_state.stereo_dirty = true;
+                            }
+                            ui.checkbox(&mut ui_state.import.load.lenient, "Lenient loading")
+                                .on_hover_text(
+                                    "Skip lines of XYZ, SD, MOL2, PDB and CIF files that fail to parse \
+                                     and list them in the Problems window",
+                                );
+                            ui.separator();
+                            ui.label("Valence rules")
+                                .on_hover_text(
+                                    "One override per line: ELEMENT [CHARGE] LIMIT, with - for no \
+                                     limit and * for unlisted elements",
+                                );
+                            ui.add(
+                                egui::TextEdit::multiline(&mut ui_state.valence_overrides)
+                                    .hint_text("Fe 6\nI 3\nSi -2 6")
+                                    .desired_rows(3),
+                            );
+                            ui.horizontal(|ui| {
+                                let permissive = ui
+                                    .checkbox(&mut ui_state.valence_permissive, "Permissive")
+                                    .on_hover_text(
+                                        "Allow bonds past the limits and count them as \
+                                         warnings in the status window",
+                                    )
+                                    .changed();
+                                if ui.button("Apply Rules").clicked() || permissive {
+                                    ui_state.apply_valence_rules();
+                                }
+                            });
+                            if let Some(err) = &ui_state.valence_error {
+                                ui.colored_label(ui.visuals().error_fg_color, err);
+                            }
+                            ui.separator();
+                            let mut mode = ui_state.camera.mode;
+                            egui::ComboBox::from_label("Camera")
+                                .selected_text(mode.label())
+                                .show_ui(ui, |ui| {
+                                    for option in CameraMode::ALL {
+                                        ui.selectable_value(&mut mode, option, option.label());
+                                    }
+                                });
+                            if mode != ui_state.camera.mode {
+                                if mode == CameraMode::Fly {
+                                    ui_state.camera_before_fly = ui_state.camera.mode;
+                                }
+                                ui_state.camera.set_mode(mode);
+                                ui_state.fly_keys.clear();
+                                ui_state.camera_dirty = true;
+                            }
+                            ui.add(
+                                egui::Slider::new(&mut ui_state.fly_speed, 0.5..=100.0)
+                                    .logarithmic(true)
+                                    .text("Fly speed (Å/s)"),
+                            );
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut ui_state.turntable, "Idle turntable");
+                                ui.add_enabled(
+                                    ui_state.turntable,
+                                    egui::Slider::new(&mut ui_state.turntable_speed, 1.0..=90.0)
+                                        .text("°/s"),
+                                );
+                            });
+                            if ui
+                                .add_enabled(
+                                    mode == CameraMode::Trackball,
+                                    egui::Button::new("Reset up vector"),
+                                )
+                                .clicked()
+                            {
+                                ui_state.camera.reset_up();
+                                ui_state.camera_dirty = true;
+                            }
+                            ui.label("Saved views");
+                            ui.horizontal(|ui| {
+                                ui.text_edit_singleline(&mut ui_state.view_name);
+                                if ui.button("Save View").clicked() {
+                                    let name = match ui_state.view_name.trim() {
+                                        "" => format!("View {}", ui_state.views.len() + 1),
+                                        name => name.to_string(),
+                                    };
+                                    ui_state.views.push(SavedView {
+                                        name,
+                                        camera: ui_state.camera,
+                                    });
+                                    ui_state.view_name.clear();
+                                }
+                            });
+                            let mut go_to = None;
+                            let mut delete_view = None;
+                            for (index, view) in ui_state.views.iter().enumerate() {
+                                ui.horizontal(|ui| {
+                                    ui.label(&view.name);
+                                    if ui.small_button("Go").clicked() {
+                                        go_to = Some(view.camera);
+                                    }
+                                    if ui.small_button("Delete").clicked() {
+                                        delete_view = Some(index);
+                                    }
+                                });
+                            }
+                            if let Some(camera) = go_to {
+                                ui_state.go_to_view(camera);
+                            }
+                            if let Some(index) = delete_view {
+                                ui_state.views.remove(index);
+                            }
+                            ui.separator();
+                            ui.checkbox(&mut ui_state.presentation, "Presentation screenshots")
+                                .on_hover_text(
+                                    "Depth of field focused on the view target, soft shadows \
+                                     and supersampling; slower, for final images",
+                                );
+                            ui.add_enabled_ui(ui_state.presentation, |ui| {
+                                let settings = &mut ui_state.presentation_settings;
+                                ui.add(
+                                    egui::Slider::new(&mut settings.supersample, 1..=4)
+                                        .text("Supersampling"),
+                                );
+                                ui.add(
+                                    egui::Slider::new(&mut settings.aperture, 0.0..=16.0)
+                                        .text("Blur (px)"),
+                                );
+                                ui.add(
+                                    egui::Slider::new(&mut settings.shadow_strength, 0.0..=1.0)
+                                        .text("Shadows"),
+                                );
+                                ui.add(
+                                    egui::Slider::new(&mut settings.shadow_radius, 2.0..=32.0)
+                                        .text("Shadow radius (px)"),
+                                );
+                            });
+                            ui.horizontal(|ui| {
+                                ui.text_edit_singleline(&mut ui_state.screenshot_path);
+                                if ui
+                                    .add_enabled(
+                                        ui_state.screenshot_rx.is_none(),
+                                        egui::Button::new("Save Screenshot"),
+                                    )
+                                    .clicked()
+                                {
+                                    ui_state.pending_screenshot =
+                                        Some(ui_state.screenshot_path.clone());
+                                }
+                            });
+                            ui.separator();
+                            let grid = render_state.grid.is_some();
+                            ui.label(if grid {
+                                "Figure: the comparison grid, panels labeled a, b, c…"
+                            } else {
+                                "Figure: one labeled panel per saved view"
+                            });
+                            ui.add_enabled_ui(!grid, |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.label("Columns");
+                                    ui.add(
+                                        egui::DragValue::new(&mut ui_state.figure_columns)
+                                            .clamp_range(1..=8),
+                                    );
+                                    ui.checkbox(&mut ui_state.figure_same_scale, "Same scale")
+                                        .on_hover_text(
+                                            "Zoom every view out to the farthest one, so \
+                                             panels share one Å-per-pixel scale",
+                                        );
+                                });
+                            });
+                            ui.horizontal(|ui| {
+                                ui.text_edit_singleline(&mut ui_state.figure_path);
+                                if ui
+                                    .add_enabled(
+                                        ui_state.screenshot_rx.is_none()
+                                            && (grid || !ui_state.views.is_empty()),
+                                        egui::Button::new("Save Figure"),
+                                    )
+                                    .clicked()
+                                {
+                                    ui_state.pending_figure = Some(ui_state.figure_path.clone());
+                                }
+                            });
+                        });
+
+                    egui::Window::new("Export")
+                        .default_pos(egui::pos2(640.0, 420.0))
+                        .default_open(false)
+                        .show(ctx, |ui| {
+                            ui.label(
+                                "POV-Ray scenes render offline with path tracing; glTF \
+                                 models open in Blender, PowerPoint and AR viewers; STL \
+                                 is for 3D printing; the HTML viewer opens in any browser.",
+                            );
+                            let print = &mut ui_state.print_settings;
+                            ui.add(
+                                egui::Slider::new(&mut print.scale, 1.0..=50.0)
+                                    .text("STL scale (mm/Å)"),
+                            );
+                            ui.add(
+                                egui::Slider::new(&mut print.min_strut_diameter, 0.0..=10.0)
+                                    .text("Minimum bond diameter (mm)"),
+                            );
+                            ui.text_edit_singleline(&mut ui_state.export_path);
+                            ui.horizontal(|ui| {
+                                for format in ExportFormat::ALL {
+                                    if ui
+                                        .add_enabled(
+                                            atom_count > 0,
+                                            egui::Button::new(format!("Export {}", format.label())),
+                                        )
+                                        .clicked()
+                                    {
+                                        pending_export = Some(format);
+                                    }
+                                }
+                            });
+                            if ui
+                                .add_enabled(atom_count > 0, egui::Button::new("Export HTML Viewer"))
+                                .on_hover_text(
+                                    "A single web page, with notes and annotations, that \
+                                     rotates the structure in any browser",
+                                )
+                                .clicked()
+                            {
+                                export_html = true;
+                            }
+                            ui.horizontal(|ui| {
+                                ui.label("XYZ decimals:");
+                                ui.add(
+                                    egui::DragValue::new(&mut ui_state.xyz_precision)
+                                        .clamp_range(1..=10),
+                                );
+                                if ui
+                                    .add_enabled(atom_count > 0, egui::Button::new("Save XYZ"))
+                                    .clicked()
+                                {
+                                    save_xyz = true;
+                                }
+                                if ui
+                                    .add_enabled(atom_count > 0, egui::Button::new("Save SDF"))
+                                    .clicked()
+                                {
+                                    save_sdf = true;
+                                }
+                                if ui
+                                    .add_enabled(atom_count > 0, egui::Button::new("Save CML"))
+                                    .clicked()
+                                {
+                                    save_cml = true;
+                                }
+                                if ui
+                                    .add_enabled(atom_count > 0, egui::Button::new("Save extxyz"))
+                                    .on_hover_text(
+                                        "Extended XYZ with the cell, metadata and atom properties",
+                                    )
+                                    .clicked()
+                                {
+                                    save_extxyz = true;
+                                }
+                            });
+                            let has_frames = molecule.as_ref().is_some_and(|molecule_ref| {
+                                molecule_ref
+                                    .properties
+                                    .names()
+                                    .any(|name| name == XYZ_FRAME_PROPERTY)
+                            });
+                            if has_frames {
+                                ui.horizontal(|ui| {
+                                    ui.label("Frames:");
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut ui_state.frame_selection)
+                                            .hint_text("all, or e.g. 1-5, 8")
+                                            .desired_width(120.0),
+                                    );
+                                    for extension in ["xyz", "sdf"] {
+                                        let label = format!("Export as .{extension}");
+                                        if ui
+                                            .button(label)
+                                            .on_hover_text("One file per frame, numbered")
+                                            .clicked()
+                                        {
+                                            pending_frame_export = Some(extension);
+                                        }
+                                    }
+                                });
+                            }
+                            ui.horizontal(|ui| {
+                                let mut recording = ui_state.recorder.is_some();
+                                if ui
+                                    .add_enabled(
+                                        molecule.is_some(),
+                                        egui::Checkbox::new(&mut recording, "Record edits"),
+                                    )
+                                    .on_hover_text("Keep a frame of the structure after every edit")
+                                    .changed()
+                                {
+                                    pending_record = Some(recording);
+                                }
+                                if let Some(recorder) = &ui_state.recorder {
+                                    ui.label(format!("{} frames", recorder.len()));
+                                    if ui.button("Save recording").clicked() {
+                                        save_recording = true;
+                                    }
+                                }
+                            });
+                            ui.collapsing("Gaussian input", |ui| {
+                                let settings = &mut ui_state.gaussian;
+                                ui.horizontal(|ui| {
+                                    ui.label("Route:");
+                                    ui.text_edit_singleline(&mut settings.route);
+                                    egui::ComboBox::from_id_source("gaussian_route")
+                                        .selected_text("Presets")
+                                        .show_ui(ui, |ui| {
+                                            for (label, route) in gaussian::ROUTE_PRESETS {
+                                                if ui
+                                                    .selectable_label(settings.route == route, label)
+                                                    .clicked()
+                                                {
+                                                    settings.route = route.to_string();
+                                                }
+                                            }
+                                        });
+                                });
+                                ui.label("Link 0 commands (one per line):");
+                                ui.add(
+                                    egui::TextEdit::multiline(&mut settings.link0)
+                                        .desired_rows(2)
+                                        .hint_text("%nprocshared=8\n%mem=16GB"),
+                                );
+                                ui.horizontal(|ui| {
+                                    let mut set_charge = settings.charge.is_some();
+                                    ui.checkbox(&mut set_charge, "Charge")
+                                        .on_hover_text("Unchecked: the sum of the formal charges");
+                                    let mut charge = settings.charge.unwrap_or_default();
+                                    ui.add_enabled(set_charge, egui::DragValue::new(&mut charge));
+                                    settings.charge = set_charge.then_some(charge);
+                                    let mut set_multiplicity = settings.multiplicity.is_some();
+                                    ui.checkbox(&mut set_multiplicity, "Multiplicity")
+                                        .on_hover_text(
+                                            "Unchecked: singlet or doublet, by electron count",
+                                        );
+                                    let mut multiplicity = settings.multiplicity.unwrap_or(1);
+                                    ui.add_enabled(
+                                        set_multiplicity,
+                                        egui::DragValue::new(&mut multiplicity).clamp_range(1..=10),
+                                    );
+                                    settings.multiplicity = set_multiplicity.then_some(multiplicity);
+                                });
+                                if let Some(molecule_ref) = molecule.as_ref() {
+                                    ui.label(format!(
+                                        "Writes charge {} and multiplicity {}.",
+                                        settings.charge(molecule_ref),
+                                        settings.multiplicity(molecule_ref)
+                                    ));
+                                    if let Some(problem) = settings.spin_problem(molecule_ref) {
+                                        ui.colored_label(ui.visuals().warn_fg_color, problem);
+                                    }
+                                }
+                                if ui
+                                    .add_enabled(atom_count > 0, egui::Button::new("Save GJF"))
+                                    .clicked()
+                                {
+                                    save_gaussian = true;
+                                }
+                            });
+                        });
+
+                    egui::Window::new("Edit")
+                        .default_pos(egui::pos2(10.0, 220.0))
+                        .show(ctx, |ui| {
+                            ui.label("Representation");
+                            let mut representation = ui_state.representation;
+                            ui.horizontal(|ui| {
+                                ui.radio_value(
+                                    &mut representation,
+                                    Representation::BallAndStick,
+                                    "Ball & Stick",
+                                );
+                                ui.radio_value(
+                                    &mut representation,
+                                    Representation::SpaceFilling,
+                                    "Space Filling",
+                                );
+                            });
+                            if representation != ui_state.representation {
+                                pending_representation = Some(representation);
+                            }
+                            if let Some(molecule_ref) = molecule.as_ref() {
+                                ui.horizontal(|ui| {
+                                    if ui.button("Fit to View").clicked() {
+                                        ui_state.fit_to_view(molecule_ref);
+                                    }
+                                    if ui
+                                        .button("Frame Selection")
+                                        .on_hover_text(
+                                            "Zoom to the selected atom, the bond target and \
+                                             the focused substructure match",
+                                        )
+                                        .clicked()
+                                    {
+                                        ui_state.frame_selection(molecule_ref);
+                                    }
+                                });
+                            }
+
+                            ui.separator();
+                            ui.label("Tool");
+                            ui.horizontal(|ui| {
+                                ui.radio_value(&mut ui_state.tool, Tool::Select, "Select");
+                                ui.radio_value(&mut ui_state.tool, Tool::AddAtom, "Add Atom");
+                                ui.radio_value(&mut ui_state.tool, Tool::AddBond, "Add Bond");
+                                ui.radio_value(&mut ui_state.tool, Tool::Sprout, "Sprout")
+                                    .on_hover_text(
+                                        "Click an atom to grow an atom of the edit element from \
+                                         it at tetrahedral, trigonal or linear geometry",
+                                    );
+                                ui.radio_value(&mut ui_state.tool, Tool::Move, "Move");
+                                ui.radio_value(&mut ui_state.tool, Tool::BoxSelect, "Box");
+                                ui.radio_value(&mut ui_state.tool, Tool::Lasso, "Lasso");
+                                ui.radio_value(&mut ui_state.tool, Tool::Measure, "Measure")
+                                    .on_hover_text(
+                                        "Click atoms to keep a distance, angle or dihedral on \
+                                         screen; choose which in Measurements",
+                                    );
+                            });
+                            if ui_state.tool != Tool::Measure {
+                                ui_state.measurements.cancel();
+                            }
+                            ui.checkbox(
+                                &mut ui_state.angle_snap,
+                                "Snap angles to 109.5°/120°/180°",
+                            );
+
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                let undo_clicked = ui
+                                    .add_enabled(history.can_undo(), egui::Button::new("Undo"))
+                                    .clicked();
+                                let redo_clicked = ui
+                                    .add_enabled(history.can_redo(), egui::Button::new("Redo"))
+                                    .clicked();
+                                if let Some(molecule_ref) = molecule.as_mut() {
+                                    if undo_clicked {
+                                        undo_command(
+                                            &mut history,
+                                            molecule_ref,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                    if redo_clicked {
+                                        redo_command(
+                                            &mut history,
+                                            molecule_ref,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                }
+                            });
+
+                            ui.separator();
+                            ui.label("Add Atom");
+                            ui.horizontal(|ui| {
+                                ui.label("Element:");
+                                ui.text_edit_singleline(&mut ui_state.edit_element);
+                            });
+                            let add_clicked = ui
+                                .add_enabled(molecule.is_some(), egui::Button::new("Insert Atom"))
+                                .clicked();
+                            if add_clicked {
+                                if let Some(molecule_ref) = molecule.as_mut() {
+                                    let position = if let Some(selection) = ui_state.selection.primary() {
+                                        molecule_ref
+                                            .get_atom(selection)
+                                            .map(|atom| Vec3::from_array(atom.position))
+                                            .unwrap_or(ui_state.camera.target)
+                                            + Vec3::new(1.0, 0.0, 0.0)
+                                    } else {
+                                        let direction = (ui_state.camera.position()
+                                            - ui_state.camera.target)
+                                            .normalize_or_zero();
+                                        ui_state.camera.target + direction * 1.5
+                                    };
+                                    let command = Command::InsertAtom {
+                                        element: ui_state.edit_element.trim().to_string(),
+                                        position: position.to_array(),
+                                        atom_id: None,
+                                        order_index: None,
+                                    };
+                                    apply_command(
+                                        command,
+                                        molecule_ref,
+                                        &mut history,
+                                        render_state,
+                                        &mut ui_state,
+                                    );
+                                }
+                            }
+
+                            ui.separator();
+                            ui.label("Build from SMILES");
+                            ui.horizontal(|ui| {
+                                ui.label("SMILES:");
+                                let response = ui.text_edit_singleline(&mut ui_state.smiles);
+                                let entered = response.lost_focus()
+                                    && ui.input(|input| input.key_pressed(egui::Key::Enter));
+                                let has_text = !ui_state.smiles.trim().is_empty();
+                                if ui.add_enabled(has_text, egui::Button::new("Build")).clicked()
+                                    || entered && has_text
+                                {
+                                    build_smiles = true;
+                                }
+                                let can_attach = has_text && ui_state.selection.primary().is_some();
+                                if ui
+                                    .add_enabled(can_attach, egui::Button::new("Attach"))
+                                    .on_hover_text(
+                                        "Bond the first SMILES atom to the selected atom, \
+                                         turned to the least crowded rotamer",
+                                    )
+                                    .clicked()
+                                {
+                                    attach_smiles = true;
+                                }
+                            });
+
+                            ui.separator();
+                            ui.label("Hydrogens");
+                            ui.horizontal(|ui| {
+                                let has_atoms =
+                                    molecule.as_ref().is_some_and(|m| m.atom_count() > 0);
+                                let add_clicked = ui
+                                    .add_enabled(has_atoms, egui::Button::new("Add Hydrogens"))
+                                    .clicked();
+                                let remove_clicked = ui
+                                    .add_enabled(has_atoms, egui::Button::new("Remove Hydrogens"))
+                                    .clicked();
+                                if let Some(molecule_ref) = molecule.as_mut() {
+                                    let command = if add_clicked {
+                                        Some(Command::add_hydrogens(molecule_ref))
+                                    } else if remove_clicked {
+                                        Some(Command::remove_hydrogens(molecule_ref))
+                                    } else {
+                                        None
+                                    };
+                                    if let Some(command) = command {
+                                        apply_command(
+                                            command,
+                                            molecule_ref,
+                                            &mut history,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                }
+                            });
+
+                            ui.separator();
+                            ui.label("Geometry");
+                            ui.horizontal(|ui| {
+                                let busy = ui_state.cleanup_rx.is_some();
+                                let clean_clicked = ui
+                                    .add_enabled(
+                                        !busy && molecule.as_ref().is_some_and(|m| m.atom_count() > 0),
+                                        egui::Button::new("Clean Geometry"),
+                                    )
+                                    .on_hover_text(
+                                        "Relax bond lengths, angles and contacts with a \
+                                         UFF-style force field, as one undo step",
+                                    )
+                                    .clicked();
+                                if busy {
+                                    ui.spinner();
+                                }
+                                if let (true, Some(molecule_ref)) = (clean_clicked, molecule.as_ref())
+                                {
+                                    ui_state.cleanup_rx = Some(spawn_cleanup(molecule_ref.clone()));
+                                    ui_state.status_message = "cleaning geometry…".to_string();
+                                }
+                            });
+                            // Rotating and mirroring act on the selection, or on everything when
+                            // nothing is selected.
+                            let mut center_clicked = false;
+                            let mut align_clicked = false;
+                            ui.horizontal(|ui| {
+                                center_clicked = ui
+                                    .button("Center")
+                                    .on_hover_text("Move the centroid to the origin")
+                                    .clicked();
+                                align_clicked = ui
+                                    .button("Align Axes")
+                                    .on_hover_text(
+                                        "Center at the origin and turn the principal axes of \
+                                         inertia onto X, Y and Z",
+                                    )
+                                    .clicked();
+                            });
+                            let mut rotate_clicked = false;
+                            let mut mirror_clicked = false;
+                            let mut invert_clicked = false;
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::DragValue::new(&mut ui_state.rotation_degrees)
+                                        .speed(1.0)
+                                        .clamp_range(-360.0..=360.0)
+                                        .suffix("°"),
+                                );
+                                egui::ComboBox::from_id_source("rotation_axis")
+                                    .selected_text(format!(
+                                        "about {}",
+                                        transform::AXES[ui_state.rotation_axis].0
+                                    ))
+                                    .show_ui(ui, |ui| {
+                                        for (index, (name, _)) in transform::AXES.iter().enumerate()
+                                        {
+                                            ui.selectable_value(
+                                                &mut ui_state.rotation_axis,
+                                                index,
+                                                format!("about {name}"),
+                                            );
+                                        }
+                                    });
+                                rotate_clicked = ui
+                                    .button("Rotate")
+                                    .on_hover_text("Turn about the axis through the centroid")
+                                    .clicked();
+                            });
+                            ui.horizontal(|ui| {
+                                egui::ComboBox::from_id_source("mirror_plane")
+                                    .selected_text(ui_state.mirror_plane.label())
+                                    .show_ui(ui, |ui| {
+                                        for plane in MirrorPlane::ALL {
+                                            ui.selectable_value(
+                                                &mut ui_state.mirror_plane,
+                                                plane,
+                                                plane.label(),
+                                            );
+                                        }
+                                    });
+                                mirror_clicked = ui
+                                    .button("Mirror")
+                                    .on_hover_text("Reflect through the plane at the centroid")
+                                    .clicked();
+                                invert_clicked = ui
+                                    .button("Invert")
+                                    .on_hover_text("Invert through the centroid")
+                                    .clicked();
+                            });
+                            if let Some(molecule_ref) = molecule.as_mut() {
+                                let atoms = if ui_state.selection.is_empty() {
+                                    molecule_ref.atom_ids()
+                                } else {
+                                    ui_state.selection.atoms().to_vec()
+                                };
+                                let command = if center_clicked {
+                                    Command::center_at_origin(molecule_ref)
+                                } else if align_clicked {
+                                    Command::align_principal_axes(molecule_ref)
+                                } else if rotate_clicked {
+                                    Command::rotate_atoms(
+                                        molecule_ref,
+                                        &atoms,
+                                        transform::AXES[ui_state.rotation_axis].1,
+                                        ui_state.rotation_degrees,
+                                    )
+                                } else if mirror_clicked {
+                                    Command::mirror_atoms(molecule_ref, &atoms, ui_state.mirror_plane)
+                                } else if invert_clicked {
+                                    Command::invert_atoms(molecule_ref, &atoms)
+                                } else {
+                                    None
+                                };
+                                if let Some(command) = command {
+                                    let applied = apply_command(
+                                        command,
+                                        molecule_ref,
+                                        &mut history,
+                                        render_state,
+                                        &mut ui_state,
+                                    );
+                                    // A structure moved to the origin would otherwise drift
+                                    // out of view.
+                                    if applied.is_some() && (center_clicked || align_clicked) {
+                                        ui_state.fit_to_view(molecule_ref);
+                                    }
+                                }
+                            }
+                            // Fixed atoms stay put during cleanup; the checkbox covers the selection.
+                            let selected = ui_state.selection.atoms().to_vec();
+                            let (mut fixed, fixed_count) = molecule.as_ref().map_or((false, 0), |m| {
+                                (
+                                    !selected.is_empty()
+                                        && selected.iter().all(|&id| optimize::is_fixed(m, id)),
+                                    m.atom_ids()
+                                        .into_iter()
+                                        .filter(|&id| optimize::is_fixed(m, id))
+                                        .count(),
+                                )
+                            });
+                            let mut unfix_all = false;
+                            let fixed_changed = ui
+                                .horizontal(|ui| {
+                                    let changed = ui
+                                        .add_enabled(
+                                            !selected.is_empty(),
+                                            egui::Checkbox::new(&mut fixed, "Fixed"),
+                                        )
+                                        .on_hover_text("Keep the selected atoms in place during cleanup")
+                                        .changed();
+                                    ui.label(format!("{fixed_count} fixed"));
+                                    unfix_all = ui
+                                        .add_enabled(fixed_count > 0, egui::Button::new("Unfix All"))
+                                        .clicked();
+                                    changed
+                                })
+                                .inner;
+                            if let Some(molecule_ref) = molecule.as_mut() {
+                                let command = if fixed_changed {
+                                    Command::set_fixed(molecule_ref, &selected, fixed)
+                                } else if unfix_all {
+                                    Command::set_fixed(molecule_ref, &molecule_ref.atom_ids(), false)
+                                } else {
+                                    None
+                                };
+                                if let Some(command) = command {
+                                    apply_command(
+                                        command,
+                                        molecule_ref,
+                                        &mut history,
+                                        render_state,
+                                        &mut ui_state,
+                                    );
+                                }
+                            }
+
+                            ui.separator();
+                            ui.label("Bond");
+                            let mut bond_target = ui_state.bond_target;
+                            egui::ComboBox::from_label("Bond target")
+                                .selected_text(
+                                    bond_target.map_or("None".into(), |id| id.value().to_string()),
+                                )
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut bond_target, None, "None");
+                                    for id in &atom_ids {
+                                        ui.selectable_value(
+                                            &mut bond_target,
+                                            Some(*id),
+                                            id.value().to_string(),
+                                        );
+                                    }
+                                });
+                            ui_state.bond_target = bond_target;
+                            // Bond buttons pair every selected atom with the target.
+                            let can_bond =
+                                !ui_state.selection.is_empty() && ui_state.bond_target.is_some();
+                            let add_bond_clicked = ui
+                                .add_enabled(can_bond, egui::Button::new("Add Bond"))
+                                .clicked();
+                            let remove_bond_clicked = ui
+                                .add_enabled(can_bond, egui::Button::new("Remove Bond"))
+                                .clicked();
+                            let target_bond = molecule.as_ref().and_then(|m| {
+                                let bond_id =
+                                    m.bond_between(ui_state.selection.primary()?, ui_state.bond_target?)?;
+                                m.bonds().find(|bond| bond.id == bond_id).cloned()
+                            });
+                            let add_coordination_clicked = ui
+                                .add_enabled(
+                                    ui_state.selection.primary().is_some()
+                                        && ui_state.bond_target.is_some()
+                                        && target_bond.is_none(),
+                                    egui::Button::new("Add Coordination Bond"),
+                                )
+                                .on_hover_text("Metal-ligand bond, exempt from valence limits")
+                                .clicked();
+                            let mut coordination = target_bond
+                                .as_ref()
+                                .is_some_and(|bond| bond.kind == BondKind::Coordination);
+                            let kind_changed = ui
+                                .add_enabled(
+                                    target_bond.is_some(),
+                                    egui::Checkbox::new(&mut coordination, "Coordination"),
+                                )
+                                .changed();
+                            let mut order = target_bond.as_ref().map_or(1, |bond| bond.order);
+                            let mut order_changed = false;
+                            ui.add_enabled_ui(target_bond.is_some(), |ui| {
+                                egui::ComboBox::from_label("Order")
+                                    .selected_text(bond_order_label(order))
+                                    .show_ui(ui, |ui| {
+                                        for value in 1..=3 {
+                                            order_changed |= ui
+                                                .selectable_value(
+                                                    &mut order,
+                                                    value,
+                                                    bond_order_label(value),
+                                                )
+                                                .changed();
+                                        }
+                                    });
+                            });
+                            let mut set_length_clicked = false;
+                            ui.add_enabled_ui(target_bond.is_some(), |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.add(
+                                        egui::DragValue::new(&mut ui_state.bond_length)
+                                            .speed(0.01)
+                                            .clamp_range(0.5..=5.0)
+                                            .suffix(" Å"),
+                                    );
+                                    set_length_clicked = ui
+                                        .button("Set Length")
+                                        .on_hover_text("Moves the smaller side along the bond")
+                                        .clicked();
+                                });
+                            });
+                            if let Some(molecule_ref) = molecule.as_mut() {
+                                if let (Some(a), Some(b)) =
+                                    (ui_state.selection.primary(), ui_state.bond_target)
+                                {
+                                    let selected = ui_state.selection.atoms().to_vec();
+                                    let bonding = if add_coordination_clicked {
+                                        Some(Some(Command::AddCoordinationBond {
+                                            atom_a: a,
+                                            atom_b: b,
+                                            bond_id: None,
+                                        }))
+                                    } else if add_bond_clicked || remove_bond_clicked {
+                                        Some(Command::bond_atoms_to(
+                                            molecule_ref,
+                                            &selected,
+                                            b,
+                                            remove_bond_clicked,
+                                        ))
+                                    } else {
+                                        None
+                                    };
+                                    match bonding {
+                                        Some(Some(command)) => {
+                                            apply_command(
+                                                command,
+                                                molecule_ref,
+                                                &mut history,
+                                                render_state,
+                                                &mut ui_state,
+                                            );
+                                        }
+                                        Some(None) if remove_bond_clicked => {
+                                            ui_state.status_message = "bond not found".to_string();
+                                        }
+                                        Some(None) => {
+                                            ui_state.status_message = "already bonded".to_string();
+                                        }
+                                        None => {}
+                                    }
+                                    if let Some(bond) = target_bond.as_ref().filter(|_| order_changed)
+                                    {
+                                        let command = Command::SetBondOrder {
+                                            bond_id: bond.id,
+                                            order,
+                                            previous: None,
+                                        };
+                                        apply_command(
+                                            command,
+                                            molecule_ref,
+                                            &mut history,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                    if let Some(bond) = target_bond.filter(|_| kind_changed) {
+                                        let kind = if coordination {
+                                            BondKind::Coordination
+                                        } else {
+                                            BondKind::Covalent
+                                        };
+                                        let command = Command::SetBondKind {
+                                            bond_id: bond.id,
+                                            kind,
+                                            previous: None,
+                                        };
+                                        apply_command(
+                                            command,
+                                            molecule_ref,
+                                            &mut history,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                    if set_length_clicked {
+                                        match Command::set_bond_length(
+                                            molecule_ref,
+                                            a,
+                                            b,
+                                            ui_state.bond_length,
+                                        ) {
+                                            Ok(command) => {
+                                                apply_command(
+                                                    command,
+                                                    molecule_ref,
+                                                    &mut history,
+                                                    render_state,
+                                                    &mut ui_state,
+                                                );
+                                            }
+                                            Err(err) => ui_state.status_message = err,
+                                        }
+                                    }
+                                }
+                            }
+
+                            // Three selected atoms, in pick order, form the angle A–B–C.
+                            let angle_atoms = match ui_state.selection.atoms() {
+                                &[a, vertex, c] => Some((a, vertex, c)),
+                                _ => None,
+                            };
+                            let mut set_angle_clicked = false;
+                            ui.add_enabled_ui(angle_atoms.is_some(), |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.add(
+                                        egui::DragValue::new(&mut ui_state.bond_angle)
+                                            .speed(0.5)
+                                            .clamp_range(0.0..=180.0)
+                                            .suffix("°"),
+                                    );
+                                    set_angle_clicked = ui
+                                        .button("Set Angle")
+                                        .on_hover_text(
+                                            "Select A, B, C in order; turns C's side about B",
+                                        )
+                                        .clicked();
+                                });
+                            });
+                            if let (true, Some((a, vertex, c)), Some(molecule_ref)) =
+                                (set_angle_clicked, angle_atoms, molecule.as_mut())
+                            {
+                                match Command::set_angle(
+                                    molecule_ref,
+                                    a,
+                                    vertex,
+                                    c,
+                                    ui_state.bond_angle,
+                                ) {
+                                    Ok(command) => {
+                                        apply_command(
+                                            command,
+                                            molecule_ref,
+                                            &mut history,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                    Err(err) => ui_state.status_message = err,
+                                }
+                            }
+
+                            let residue = molecule.as_ref().and_then(|m| {
+                                let atom = ui_state.selection.primary()?;
+                                let name = m.properties.get("residue", atom);
+                                Some((name, residues::residue_of(m, atom)?))
+                            });
+                            if let Some((name, residue)) = residue {
+                                ui.separator();
+                                ui.label(format!(
+                                    "Residue {} {}{}",
+                                    name.unwrap_or("?"),
+                                    residue.chain,
+                                    residue.number
+                                ));
+                                let mut mutate_clicked = false;
+                                ui.horizontal(|ui| {
+                                    egui::ComboBox::from_id_source("mutation_target")
+                                        .selected_text(ui_state.mutation_target)
+                                        .show_ui(ui, |ui| {
+                                            for code in residues::AMINO_ACIDS {
+                                                ui.selectable_value(
+                                                    &mut ui_state.mutation_target,
+                                                    code,
+                                                    code,
+                                                );
+                                            }
+                                        });
+                                    mutate_clicked = ui
+                                        .button("Mutate")
+                                        .on_hover_text("Rebuild the side chain; the backbone stays")
+                                        .clicked();
+                                });
+                                let molecule_ref = molecule.as_mut().filter(|_| mutate_clicked);
+                                if let Some(molecule_ref) = molecule_ref {
+                                    let target = ui_state.mutation_target;
+                                    match Command::mutate_residue(molecule_ref, &residue, target) {
+                                        Ok(command) => {
+                                            apply_command(
+                                                command,
+                                                molecule_ref,
+                                                &mut history,
+                                                render_state,
+                                                &mut ui_state,
+                                            );
+                                        }
+                                        Err(err) => ui_state.status_message = err,
+                                    }
+                                }
+                            }
+
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                let copy_clicked = ui
+                                    .add_enabled(
+                                        !ui_state.selection.is_empty(),
+                                        egui::Button::new("Copy"),
+                                    )
+                                    .clicked();
+                                let delete_clicked = ui
+                                    .add_enabled(
+                                        !ui_state.selection.is_empty(),
+                                        egui::Button::new("Delete"),
+                                    )
+                                    .on_hover_text("Delete the selected atoms (Delete key)")
+                                    .clicked();
+                                let paste_clicked = ui
+                                    .add_enabled(
+                                        ui_state.clipboard.is_some(),
+                                        egui::Button::new("Paste"),
+                                    )
+                                    .clicked();
+                                let duplicate_clicked = ui
+                                    .add_enabled(
+                                        !ui_state.selection.is_empty(),
+                                        egui::Button::new("Duplicate"),
+                                    )
+                                    .on_hover_text(
+                                        "Copy the selected atoms in place, offset slightly, and \
+                                         select the copy (Ctrl/Cmd + D)",
+                                    )
+                                    .clicked();
+                                if let Some(molecule_ref) = molecule.as_mut() {
+                                    if copy_clicked {
+                                        copy_selection(molecule_ref, &mut ui_state);
+                                    }
+                                    if delete_clicked {
+                                        delete_selection(
+                                            molecule_ref,
+                                            &mut history,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                    if paste_clicked {
+                                        paste_clipboard(
+                                            molecule_ref,
+                                            &mut history,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                    if duplicate_clicked {
+                                        duplicate_selection(
+                                            molecule_ref,
+                                            &mut history,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                }
+                            });
+                            ui.checkbox(
+                                &mut ui_state.paste_onto_selection,
+                                "Paste onto selected atom",
+                            );
+
+                            ui.separator();
+                            let edit_cell_clicked = ui
+                                .add_enabled(molecule.is_some(), egui::Button::new("Edit Cell…"))
+                                .clicked();
+                            if edit_cell_clicked {
+                                let cell = molecule.as_ref().and_then(|mol| mol.cell().copied());
+                                ui_state.cell_editor = Some(CellEditor::new(cell));
+                            }
+
+                            ui.separator();
+                            ui.label("Move Selection");
+                            ui.add(
+                                egui::Slider::new(&mut ui_state.move_step, 0.05..=2.0).text("step"),
+                            );
+                            if let Some(molecule_ref) = molecule.as_mut() {
+                                if !ui_state.selection.is_empty() {
+                                    let selection = ui_state.selection.atoms().to_vec();
+                                    let step = ui_state.move_step;
+                                    if ui.button("+X").clicked() {
+                                        apply_move(
+                                            &selection,
+                                            Vec3::X * step,
+                                            molecule_ref,
+                                            &mut history,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                    if ui.button("-X").clicked() {
+                                        apply_move(
+                                            &selection,
+                                            -Vec3::X * step,
+                                            molecule_ref,
+                                            &mut history,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                    if ui.button("+Y").clicked() {
+                                        apply_move(
+                                            &selection,
+                                            Vec3::Y * step,
+                                            molecule_ref,
+                                            &mut history,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                    if ui.button("-Y").clicked() {
+                                        apply_move(
+                                            &selection,
+                                            -Vec3::Y * step,
+                                            molecule_ref,
+                                            &mut history,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                    if ui.button("+Z").clicked() {
+                                        apply_move(
+                                            &selection,
+                                            Vec3::Z * step,
+                                            molecule_ref,
+                                            &mut history,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                    if ui.button("-Z").clicked() {
+                                        apply_move(
+                                            &selection,
+                                            -Vec3::Z * step,
+                                            molecule_ref,
+                                            &mut history,
+                                            render_state,
+                                            &mut ui_state,
+                                        );
+                                    }
+                                } else {
+                                    ui.label("Select atoms to move.");
+                                }
+                            }
+
+                            if !ui_state.status_message.is_empty() {
+                                ui.separator();
+                                ui.label(format!("Status: {}", ui_state.status_message));
+                            }
+                        });
+                });
+                egui_state.handle_platform_output(window, output.platform_output);
+                let confirmed = match discard_answer {
+                    Some(true) => ui_state.confirm_discard.take(),
+                    Some(false) => {
+                        ui_state.confirm_discard = None;
+                        None
+                    }
+                    None => None,
+                };
+                match confirmed {
+                    Some(DiscardAction::Open) => pending_open = true,
+                    Some(DiscardAction::GalleryOpen(index)) => pending_gallery_open = Some(index),
+                    Some(DiscardAction::OpenSession) => {
+                        pending_checkpoint = Some(CheckpointAction::OpenSession);
+                    }
+                    Some(DiscardAction::Exit) => target.exit(),
+                    None if history.is_dirty() => {
+                        let reload = ui_state.keep_history_on_reload
+                            && molecule.is_some()
+                            && ui_state.open_path.trim() == ui_state.source_path;
+                        if pending_open && !reload {
+                            pending_open = false;
+                            ui_state.confirm_discard = Some(DiscardAction::Open);
+                        }
+                        if let Some(index) = pending_gallery_open.take() {
+                            ui_state.confirm_discard = Some(DiscardAction::GalleryOpen(index));
+                        }
+                        if let Some(CheckpointAction::OpenSession) = pending_checkpoint {
+                            pending_checkpoint = None;
+                            ui_state.confirm_discard = Some(DiscardAction::OpenSession);
+                        }
+                    }
+                    None => {}
+                }
+                if let (Some(command), Some(molecule_ref)) = (pending_command, molecule.as_mut()) {
+                    apply_command(
+                        command,
+                        molecule_ref,
+                        &mut history,
+                        render_state,
+                        &mut ui_state,
+                    );
+                }
+                if let (Some(action), Some(molecule_ref)) = (pending_checkpoint, molecule.as_mut())
+                {
+                    handle_checkpoint_action(
+                        action,
+                        molecule_ref,
+                        &mut history,
+                        render_state,
+                        &mut ui_state,
+                    );
+                }
+                if build_smiles {
+                    match smiles::parse_smiles(&ui_state.smiles) {
+                        Ok(built) => {
+                            ui_state
+                                .provenance
+                                .record(format!("build from SMILES {}", built.name));
+                            ui_state.status_message =
+                                format!("built {} from SMILES", built.formula());
+                            ui_state.fit_to_view(&built);
+                            match molecule.as_mut() {
+                                Some(molecule_ref) => {
+                                    let command = Command::Restore {
+                                        snapshot: Box::new(built),
+                                        previous: None,
+                                    };
+                                    apply_command(
+                                        command,
+                                        molecule_ref,
+                                        &mut history,
+                                        render_state,
+                                        &mut ui_state,
+                                    );
+                                }
+                                None => {
+                                    render_state.set_molecule(&built);
+                                    ui_state.stereo_dirty = true;
+                                    ui_state.cell_info_dirty = true;
+                                    ui_state.composition_dirty = true;
+                                    ui_state.coloring_dirty = true;
+                                    molecule = Some(built);
+                                }
+                            }
+                        }
+                        Err(err) => ui_state.status_message = format!("SMILES: {err}"),
+                    }
+                }
+                if attach_smiles {
+                    match (smiles::parse_smiles(&ui_state.smiles), molecule.as_mut()) {
+                        (Ok(fragment), Some(molecule_ref)) => {
+                            if let Some(anchor) = ui_state.selection.primary() {
+                                attach_fragment(
+                                    &fragment,
+                                    anchor,
+                                    molecule_ref,
+                                    &mut history,
+                                    render_state,
+                                    &mut ui_state,
+                                );
+                            }
+                        }
+                        (Err(err), _) => ui_state.status_message = format!("SMILES: {err}"),
+                        (Ok(_), None) => {}
+                    }
+                }
+                if let Some(fragment) = insert_fragment {
+                    insert_library_fragment(
+                        fragment,
+                        &mut molecule,
+                        &mut history,
+                        render_state,
+                        &mut ui_state,
+                    );
+                }
+                match pending_tutorial {
+                    Some(true) => {
+                        let blank = Molecule::new("water");
+                        ui_state.edit_element = "O".to_string();
+                        ui_state.tutorial = Some(Tutorial::default());
+                        match molecule.as_mut() {
+                            Some(molecule_ref) => {
+                                let command = Command::Restore {
+                                    snapshot: Box::new(blank),
+                                    previous: None,
+                                };
+                                apply_command(
+                                    command,
+                                    molecule_ref,
+                                    &mut history,
+                                    render_state,
+                                    &mut ui_state,
+                                );
+                            }
+                            None => {
+                                render_state.set_molecule(&blank);
+                                ui_state.stereo_dirty = true;
+                                ui_state.cell_info_dirty = true;
+                                ui_state.composition_dirty = true;
+                                ui_state.coloring_dirty = true;
+                                molecule = Some(blank);
+                            }
+                        }
+                    }
+                    Some(false) => ui_state.tutorial = None,
+                    None => {}
+                }
+                if retry_lenient {
+                    ui_state.import.load.lenient = true;
+                }
+                let mut watched_change = false;
+                if ui_state.load_progress.is_none()
+                    && ui_state.watch_polled.elapsed() >= WATCH_POLL_INTERVAL
+                {
+                    ui_state.watch_polled = Instant::now();
+                    if let Some(watch) = ui_state.file_watch.as_mut() {
+                        watched_change = watch.poll() && !pending_open;
+                    }
+                }
+                if watched_change {
+                    ui_state.reloading = molecule.is_some();
+                    ui_state.status_message =
+                        format!("{} changed on disk; reloading", ui_state.source_path);
+                }
+                if pending_open {
+                    let path = ui_state.open_path.trim().to_string();
+                    ui_state.reloading = ui_state.keep_history_on_reload
+                        && molecule.is_some()
+                        && path == ui_state.source_path;
+                    ui_state.source_path = path;
+                }
+                if retry_lenient || pending_open || watched_change {
+                    ui_state.load_error = None;
+                    ui_state.show_problems = false;
+                    let progress = Arc::new(LoadProgress::default());
+                    ui_state.load_progress = Some(progress.clone());
+                    rx = spawn_load(
+                        ui_state.source_path.clone(),
+                        ui_state.import.clone(),
+                        progress,
+                    );
+                }
+                if gallery_toggled {
+                    ui_state.gallery = match ui_state.gallery.take() {
+                        Some(_) => None,
+                        None => Some(spawn_gallery_watch(
+                            PathBuf::from(&ui_state.gallery_dir),
+                            ui_state.palette,
+                            ui_state.import.load.lenient,
+                        )),
+                    };
+                }
+                let opened = pending_gallery_open
+                    .and_then(|index| ui_state.gallery.as_ref()?.items.get(index))
+                    .map(|item| (item.path.clone(), item.molecule.clone()));
+                if let Some((path, result)) = opened {
+                    match result {
+                        Ok(loaded) => {
+                            ui_state.source_path = path.to_string_lossy().into_owned();
+                            ui_state.file_name =
+                                format!("{} ({})", ui_state.source_path, loaded.name);
+                            ui_state.provenance.record(format!(
+                                "open {} from gallery ({} atoms)",
+                                ui_state.source_path,
+                                loaded.atom_count()
+                            ));
+                            ui_state.diff = None;
+                            render_state.set_molecule(&loaded);
+                            ui_state.stereo_dirty = true;
+                            ui_state.cell_info_dirty = true;
+                            ui_state.composition_dirty = true;
+                            ui_state.coloring_dirty = true;
+                            ui_state.selection.clear();
+                            ui_state.bond_target = None;
+                            ui_state.fit_to_view(&loaded);
+                            history = CommandHistory::new(HISTORY_CAPACITY);
+                            molecule = Some(loaded);
+                        }
+                        Err(err) => {
+                            ui_state.status_message = format!("{}: {err}", path.display());
+                        }
+                    }
+                }
+                if let Some(action) = pending_grid {
+                    match action {
+                        GridAction::AddFile => {
+                            let path = ui_state.grid_path.trim().to_string();
+                            ui_state.grid_rx = Some(spawn_load(
+                                path,
+                                ImportOptions::defaults(ui_state.import.load.lenient),
+                                Arc::default(),
+                            ));
+                        }
+                        GridAction::AddCurrent => {
+                            if let Some(molecule_ref) = molecule.as_ref() {
+                                ui_state.grid_samples.push(GridSample {
+                                    name: sample_name(&ui_state.source_path),
+                                    molecule: molecule_ref.clone(),
+                                });
+                                ui_state.grid_enabled = true;
+                            }
+                        }
+                        GridAction::AddGallery => {
+                            if let Some(gallery) = ui_state.gallery.as_ref() {
+                                let loaded = gallery.items.iter().filter_map(|item| {
+                                    Some(GridSample {
+                                        name: sample_name(&item.path.to_string_lossy()),
+                                        molecule: item.molecule.as_ref().ok()?.clone(),
+                                    })
+                                });
+                                ui_state.grid_samples.extend(loaded);
+                                ui_state.grid_enabled = true;
+                            }
+                        }
+                        GridAction::Remove(index) => {
+                            if index < ui_state.grid_samples.len() {
+                                ui_state.grid_samples.remove(index);
+                            }
+                        }
+                        GridAction::Clear => ui_state.grid_samples.clear(),
+                    }
+                    ui_state.grid_dirty = true;
+                }
+                if let Some(CheckpointAction::OpenSession) = pending_checkpoint {
+                    ui_state.session_rx = Some(spawn_session_load(ui_state.session_path.clone()));
+                }
+                match pending_compare {
+                    Some(CompareRequest::History(steps)) => {
+                        if let Some(current) = molecule.as_ref() {
+                            match history.rewound(current, steps) {
+                                Ok(earlier) => {
+                                    show_diff(diff(&earlier, current), render_state, &mut ui_state);
+                                }
+                                Err(err) => ui_state.status_message = err,
+                            }
+                        }
+                    }
+                    Some(CompareRequest::File) => {
+                        ui_state.compare_rx = Some(spawn_load(
+                            ui_state.source_path.clone(),
+                            ui_state.import.clone(),
+                            Arc::default(),
+                        ));
+                    }
+                    Some(CompareRequest::Clear) => {
+                        ui_state.diff = None;
+                        render_state.set_diff_highlight(None);
+                    }
+                    None => {}
+                }
+                match pending_find {
+                    Some(FindRequest::File) => {
+                        ui_state.find_rx = Some(spawn_load(
+                            ui_state.find_path.clone(),
+                            ImportOptions::defaults(ui_state.import.load.lenient),
+                            Arc::default(),
+                        ));
+                    }
+                    Some(FindRequest::Clipboard) => {
+                        if let (Some(pattern), Some(molecule_ref)) =
+                            (ui_state.clipboard.clone(), molecule.as_ref())
+                        {
+                            run_find(&pattern, molecule_ref, render_state, &mut ui_state);
+                        }
+                    }
+                    Some(FindRequest::Query) => {
+                        if let Some(molecule_ref) = molecule.as_ref() {
+                            run_query(molecule_ref, render_state, &mut ui_state);
+                        }
+                    }
+                    Some(FindRequest::Script) => {
+                        if let Some(molecule_ref) = molecule.as_ref() {
+                            run_script_selection(molecule_ref, render_state, &mut ui_state);
+                        }
+                    }
+                    Some(FindRequest::Focus(focus)) => {
+                        ui_state.find_focus = focus;
+                        show_find_matches(render_state, &ui_state);
+                    }
+                    Some(FindRequest::Clear) => {
+                        ui_state.find_matches.clear();
+                        ui_state.find_focus = None;
+                        show_find_matches(render_state, &ui_state);
+                    }
+                    None => {}
+                }
+                if let Some(style) = pending_style {
+                    ui_state.style_rules = rules_text(&style.rules);
+                    ui_state.style = style.clone();
+                    if let Some(molecule_ref) = molecule.as_ref() {
+                        render_state.set_style(style, molecule_ref);
+                    }
+                    ui_state.grid_dirty = true;
+                }
+                if load_script {
+                    let path = ui_state.script_path.clone();
+                    let loaded = std::fs::read_to_string(&path)
+                        .map_err(|err| err.to_string())
+                        .and_then(|source| {
+                            ui_state
+                                .hooks
+                                .load_script(&source)
+                                .map_err(|err| err.to_string())
+                        });
+                    ui_state.status_message = match loaded {
+                        Ok(count) => {
+                            let mut style = ui_state.style.clone();
+                            for rule in ui_state.hooks.style_rules() {
+                                if !style.rules.contains(rule) {
+                                    style.rules.push(rule.clone());
+                                }
+                            }
+                            if style != ui_state.style {
+                                ui_state.style_rules = rules_text(&style.rules);
+                                if let Some(molecule_ref) = molecule.as_ref() {
+                                    render_state.set_style(style.clone(), molecule_ref);
+                                }
+                                ui_state.style = style;
+                                ui_state.grid_dirty = true;
+                            }
+                            ui_state.coloring_dirty = true;
+                            format!("{path}: registered {count} hooks")
+                        }
+                        Err(err) => format!("script error: {err}"),
+                    };
+                }
+                if let Some(palette) = pending_palette {
+                    ui_state.palette = palette;
+                    ui_state.camera_dirty = true;
+                    if let Some(molecule_ref) = molecule.as_ref() {
+                        render_state.set_palette(palette, molecule_ref);
+                    }
+                    if let Some(gallery) = ui_state.gallery.as_mut() {
+                        gallery.set_palette(palette);
+                    }
+                    ui_state.grid_dirty = true;
+                }
+                if let Some(molecule_ref) = molecule.as_ref().filter(|_| save_xyz) {
+                    let path = Path::new(&ui_state.export_path).with_extension("xyz");
+                    let xyz = write_xyz(molecule_ref, ui_state.xyz_precision);
+                    ui_state.status_message = match std::fs::write(&path, xyz) {
+                        Ok(()) => {
+                            ui_state.provenance.record(format!(
+                                "save XYZ {} ({} decimals)",
+                                path.display(),
+                                ui_state.xyz_precision
+                            ));
+                            history.mark_saved();
+                            format!("saved {}", path.display())
+                        }
+                        Err(err) => format!("save failed: {err}"),
+                    };
+                }
+                if let Some(extension) = pending_frame_export {
+                    let base = PathBuf::from(&ui_state.export_path);
+                    let molecule_ref = molecule.as_ref().ok_or("no molecule".to_string());
+                    let exported = molecule_ref.and_then(|molecule_ref| {
+                        let available = trajectory::frame_numbers(molecule_ref);
+                        let frames =
+                            trajectory::parse_frame_list(&ui_state.frame_selection, &available)?;
+                        for &frame in &frames {
+                            let single = trajectory::extract_frame(molecule_ref, frame);
+                            let contents = match extension {
+                                "sdf" => sdf::write_sdf(&single),
+                                _ => write_xyz(&single, ui_state.xyz_precision),
+                            };
+                            let path = trajectory::frame_path(&base, frame, extension);
+                            std::fs::write(&path, contents)
+                                .map_err(|err| format!("{}: {err}", path.display()))?;
+                        }
+                        Ok(frames.len())
+                    });
+                    ui_state.status_message = match exported {
+                        Ok(count) => {
+                            let first = trajectory::frame_path(&base, 1, extension);
+                            ui_state.provenance.record(format!(
+                                "export {count} frames as {}",
+                                first.display()
+                            ));
+                            format!("exported {count} frames")
+                        }
+                        Err(err) => format!("export failed: {err}"),
+                    };
+                }
+                match (pending_record, molecule.as_ref()) {
+                    (Some(true), Some(molecule_ref)) => {
+                        ui_state.recorder =
+                            Some(EditRecorder::new(molecule_ref, ui_state.xyz_precision));
+                    }
+                    (Some(false), _) => ui_state.recorder = None,
+                    _ => {}
+                }
+                if let Some(recorder) = ui_state.recorder.as_ref().filter(|_| save_recording) {
+                    let path = Path::new(&ui_state.export_path).with_extension("edits.xyz");
+                    ui_state.status_message = match std::fs::write(&path, recorder.to_xyz()) {
+                        Ok(()) => {
+                            let frames = recorder.len();
+                            ui_state.provenance.record(format!(
+                                "save recording of {frames} frames {}",
+                                path.display()
+                            ));
+                            format!("saved {}", path.display())
+                        }
+                        Err(err) => format!("save failed: {err}"),
+                    };
+                }
+                if let Some(molecule_ref) = molecule.as_ref().filter(|_| save_sdf) {
+                    let path = Path::new(&ui_state.export_path).with_extension("sdf");
+                    ui_state.status_message =
+                        match std::fs::write(&path, sdf::write_sdf(molecule_ref)) {
+                            Ok(()) => {
+                                ui_state
+                                    .provenance
+                                    .record(format!("save SDF {}", path.display()));
+                                history.mark_saved();
+                                format!("saved {}", path.display())
+                            }
+                            Err(err) => format!("save failed: {err}"),
+                        };
+                }
+                if let Some(molecule_ref) = molecule.as_ref().filter(|_| save_extxyz) {
+                    let path = Path::new(&ui_state.export_path).with_extension("extxyz");
+                    let contents = extxyz::write_extxyz(molecule_ref, ui_state.xyz_precision);
+                    ui_state.status_message = match std::fs::write(&path, contents) {
+                        Ok(()) => {
+                            ui_state
+                                .provenance
+                                .record(format!("save extended XYZ {}", path.display()));
+                            history.mark_saved();
+                            format!("saved {}", path.display())
+                        }
+                        Err(err) => format!("save failed: {err}"),
+                    };
+                }
+                if let Some(molecule_ref) = molecule.as_ref().filter(|_| save_cml) {
+                    let path = Path::new(&ui_state.export_path).with_extension("cml");
+                    ui_state.status_message =
+                        match std::fs::write(&path, cml::write_cml(molecule_ref)) {
+                            Ok(()) => {
+                                ui_state
+                                    .provenance
+                                    .record(format!("save CML {}", path.display()));
+                                history.mark_saved();
+                                format!("saved {}", path.display())
+                            }
+                            Err(err) => format!("save failed: {err}"),
+                        };
+                }
+                if let Some(molecule_ref) = molecule.as_ref().filter(|_| save_gaussian) {
+                    let path = Path::new(&ui_state.export_path).with_extension("gjf");
+                    let input = gaussian::write_gaussian(molecule_ref, &ui_state.gaussian);
+                    ui_state.status_message = match std::fs::write(&path, input) {
+                        Ok(()) => {
+                            ui_state.provenance.record(format!(
+                                "save Gaussian input {} ({})",
+                                path.display(),
+                                ui_state.gaussian.route.trim()
+                            ));
+                            history.mark_saved();
+                            format!("saved {}", path.display())
+                        }
+                        Err(err) => format!("save failed: {err}"),
+                    };
+                }
+                if let (Some(format), Some(molecule_ref)) = (pending_export, molecule.as_ref()) {
+                    let aspect =
+                        render_state.size.width as f32 / render_state.size.height.max(1) as f32;
+                    let scene = render_state.scene(molecule_ref, &ui_state.camera, aspect);
+                    let path = Path::new(&ui_state.export_path).with_extension(format.extension());
+                    ui_state.status_message = match format.write(
+                        &scene,
+                        &ui_state.print_settings,
+                        &path,
+                    ) {
+                        Ok(()) => {
+                            let settings = &ui_state.print_settings;
+                            let parameters = match format {
+                                ExportFormat::Stl => format!(
+                                    " (scale {} mm/Å, minimum strut {} mm)",
+                                    settings.scale, settings.min_strut_diameter
+                                ),
+                                _ => String::new(),
+                            };
+                            ui_state.provenance.record(format!(
+                                "export {} {}{parameters}",
+                                format.label(),
+                                path.display()
+                            ));
+                            format!("exported {}", path.display())
+                        }
+                        Err(err) => format!("export failed: {err}"),
+                    };
+                }
+                if let Some(molecule_ref) = molecule.as_ref().filter(|_| export_html) {
+                    let aspect =
+                        render_state.size.width as f32 / render_state.size.height.max(1) as f32;
+                    let scene = render_state.scene(molecule_ref, &ui_state.camera, aspect);
+                    let page =
+                        write_html(&scene, molecule_ref, &ui_state.notes, &ui_state.annotations);
+                    let path = Path::new(&ui_state.export_path).with_extension("html");
+                    ui_state.status_message = match std::fs::write(&path, page) {
+                        Ok(()) => {
+                            ui_state
+                                .provenance
+                                .record(format!("export HTML {}", path.display()));
+                            format!("exported {}", path.display())
+                        }
+                        Err(err) => format!("export failed: {err}"),
+                    };
+                }
+                if let Some(representation) = pending_representation {
+                    ui_state.representation = representation;
+                    if let Some(molecule_ref) = molecule.as_ref() {
+                        render_state.set_representation(representation, molecule_ref);
+                    }
+                    ui_state.grid_dirty = true;
+                }
+                let paint_jobs = egui_ctx.tessellate(output.shapes, output.pixels_per_point);
+                let screen_descriptor = egui_wgpu::ScreenDescriptor {
+                    size_in_pixels: [render_state.config.width, render_state.config.height],
+                    pixels_per_point: output.pixels_per_point,
+                };
+
+                for (id, image_delta) in &output.textures_delta.set {
+                    egui_renderer.update_texture(
+                        &render_state.device,
+                        &render_state.queue,
+                        *id,
+                        image_delta,
+                    );
+                }
+
+                let render_result =
+                    render_state.render(egui_renderer, &paint_jobs, &screen_descriptor);
+                match render_result {
+                    Ok(()) => {}
+                    Err(wgpu::SurfaceError::Lost) => render_state.resize(render_state.size),
+                    Err(wgpu::SurfaceError::OutOfMemory) => target.exit(),
+                    Err(wgpu::SurfaceError::Timeout) => {
+                        std::thread::sleep(Duration::from_millis(16));
+                    }
+                    Err(wgpu::SurfaceError::Outdated) => {}
+                }
+
+                for id in &output.textures_delta.free {
+                    egui_renderer.free_texture(id);
+                }
+            }
+            Event::WindowEvent { event, window_id } => {
+                let (window, render_state, egui_state) =
+                    match (window.as_ref(), render_state.as_mut(), egui_state.as_mut()) {
+                        (Some(window), Some(render_state), Some(egui_state)) => {
+                            (window, render_state, egui_state)
+                        }
+                        _ => return,
+                    };
+                if window_id != window.id() {
+                    return;
+                }
+                if matches!(
+                    event,
+                    WindowEvent::CursorMoved { .. }
+                        | WindowEvent::MouseInput { .. }
+                        | WindowEvent::MouseWheel { .. }
+                        | WindowEvent::KeyboardInput { .. }
+                ) {
+                    ui_state.last_interaction = Instant::now();
+                }
+                if egui_state.on_window_event(window, &event).consumed {
+                    return;
+                }
+                match event {
+                    WindowEvent::CloseRequested if history.is_dirty() => {
+                        ui_state.confirm_discard = Some(DiscardAction::Exit);
+                        window.request_redraw();
+                    }
+                    WindowEvent::CloseRequested => target.exit(),
+                    WindowEvent::Resized(size) => {
+                        render_state.resize(size);
+                        ui_state.camera_dirty = true;
+                    }
+                    WindowEvent::ScaleFactorChanged {
+                        mut inner_size_writer,
+                        ..
+                    } => {
+                        let new_size = window.inner_size();
+                        let _ = inner_size_writer.request_inner_size(new_size);
+                        render_state.resize(new_size);
+                    }
+                    WindowEvent::ModifiersChanged(modifiers) => {
+                        ui_state.modifiers = modifiers.state();
+                    }
+                    WindowEvent::KeyboardInput { event, .. }
+                        if event.state == ElementState::Pressed
+                            && event.logical_key == Key::Named(NamedKey::F1) =>
+                    {
+                        ui_state.show_help = !ui_state.show_help;
+                    }
+                    WindowEvent::KeyboardInput { event, .. }
+                        if event.state == ElementState::Pressed
+                            && matches!(
+                                event.logical_key,
+                                Key::Named(NamedKey::Delete | NamedKey::Backspace)
+                            ) =>
+                    {
+                        if let Some(molecule_ref) = molecule.as_mut() {
+                            delete_selection(
+                                molecule_ref,
+                                &mut history,
+                                render_state,
+                                &mut ui_state,
+                            );
+                        }
+                    }
+                    WindowEvent::KeyboardInput { event, .. }
+                        if event.state == ElementState::Pressed
+                            && handle_shortcuts(&event.logical_key, &ui_state.modifiers) =>
+                    {
+                        if let Some(molecule_ref) = molecule.as_mut() {
+                            match &event.logical_key {
+                                Key::Character(key) if key.eq_ignore_ascii_case("z") => {
+                                    if ui_state.modifiers.shift_key() {
+                                        redo_command(
                                             &mut history,
-                                            render_state,
-                                            &mut ui_state,
-                                        );
-                                    }
-                                    if ui.button("-X").clicked() {
-                                        apply_move(
-                                            selection,
-                                            -Vec3::X * step,
                                             molecule_ref,
-                                            &mut history,
                                             render_state,
                                             &mut ui_state,
                                         );
-                                    }
-                                    if ui.button("+Y").clicked() {
-                                        apply_move(
-                                            selection,
-                                            Vec3::Y * step,
-                                            molecule_ref,
+                                    } else {
+                                        undo_command(
                                             &mut history,
-                                            render_state,
-                                            &mut ui_state,
-                                        );
-                                    }
-                                    if ui.button("-Y").clicked() {
-                                        apply_move(
-                                            selection,
-                                            -Vec3::Y * step,
                                             molecule_ref,
-                                            &mut history,
                                             render_state,
                                             &mut ui_state,
                                         );
                                     }
-                                    if ui.button("+Z").clicked() {
-                                        apply_move(
-                                            selection,
-                                            Vec3::Z * step,
-                                            molecule_ref,
-                                            &mut history,
-                                            render_state,
-                                            &mut ui_state,
-                                        );
+                                }
+                                Key::Character(key) if key.eq_ignore_ascii_case("y") => {
+                                    redo_command(
+                                        &mut history,
+                                        molecule_ref,
+                                        render_state,
+                                        &mut ui_state,
+                                    );
+                                }
+                                Key::Character(key) if key.eq_ignore_ascii_case("c") => {
+                                    copy_selection(molecule_ref, &mut ui_state);
+                                }
+                                Key::Character(key) if key.eq_ignore_ascii_case("v") => {
+                                    paste_clipboard(
+                                        molecule_ref,
+                                        &mut history,
+                                        render_state,
+                                        &mut ui_state,
+                                    );
+                                }
+                                Key::Character(key) if key.eq_ignore_ascii_case("d") => {
+                                    duplicate_selection(
+                                        molecule_ref,
+                                        &mut history,
+                                        render_state,
+                                        &mut ui_state,
+                                    );
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    WindowEvent::KeyboardInput { event, .. }
+                        if event.state == ElementState::Pressed
+                            && !event.repeat
+                            && !ui_state.modifiers.control_key()
+                            && !ui_state.modifiers.super_key()
+                            && matches!(&event.logical_key, Key::Character(key) if key.eq_ignore_ascii_case("f")) =>
+                    {
+                        ui_state.toggle_fly();
+                    }
+                    WindowEvent::KeyboardInput { event, .. }
+                        if ui_state.camera.mode == CameraMode::Fly =>
+                    {
+                        if let Key::Character(key) = &event.logical_key {
+                            let key = key.to_lowercase();
+                            if FLY_KEYS.contains(&key.as_str()) {
+                                match event.state {
+                                    ElementState::Pressed => ui_state.fly_keys.insert(key),
+                                    ElementState::Released => ui_state.fly_keys.remove(&key),
+                                };
+                            }
+                        }
+                    }
+                    WindowEvent::Focused(false) => ui_state.fly_keys.clear(),
+                    WindowEvent::CursorMoved { position, .. } => {
+                        ui_state.update_cursor(Vec2::new(position.x as f32, position.y as f32));
+                        if let (Some(atom_id), Some(molecule_ref)) =
+                            (ui_state.atom_drag, molecule.as_mut())
+                        {
+                            drag_atom(
+                                atom_id,
+                                molecule_ref,
+                                &mut history,
+                                render_state,
+                                &mut ui_state,
+                            );
+                        }
+                    }
+                    WindowEvent::MouseInput {
+                        state,
+                        button: MouseButton::Left,
+                        ..
+                    } => match state {
+                        ElementState::Pressed => {
+                            ui_state.begin_drag();
+                            if matches!(ui_state.tool, Tool::BoxSelect | Tool::Lasso)
+                                && molecule.is_some()
+                            {
+                                ui_state.region_drag = ui_state.last_cursor.map(|cursor| vec![cursor]);
+                            }
+                            if ui_state.tool == Tool::Move && molecule.is_some() {
+                                if let Some(cursor) = ui_state.last_cursor {
+                                    let picked = render_state.pick_atom(
+                                        cursor,
+                                        &ui_state.camera,
+                                        render_state.view_size(),
+                                    );
+                                    // Dragging a selected atom carries the whole selection.
+                                    if let Some(atom_id) = picked {
+                                        let mode = select_mode(&ui_state.modifiers);
+                                        if !ui_state.selection.contains(atom_id)
+                                            || mode == SelectMode::Toggle
+                                        {
+                                            let mut selection = ui_state.selection.clone();
+                                            selection.select(atom_id, mode);
+                                            set_selection(selection, render_state, &mut ui_state);
+                                        }
+                                        if ui_state.selection.contains(atom_id) {
+                                            ui_state.atom_drag = Some(atom_id);
+                                        }
                                     }
-                                    if ui.button("-Z").clicked() {
-                                        apply_move(
-                                            selection,
-                                            -Vec3::Z * step,
-                                            molecule_ref,
-                                            &mut history,
-                                            render_state,
-                                            &mut ui_state,
-                                        );
+                                }
+                            }
+                        }
+                        ElementState::Released => {
+                            let moved_atom = ui_state.atom_drag.take().is_some();
+                            let region = ui_state.drag_region();
+                            ui_state.region_drag = None;
+                            if let Some(region) = region.filter(|_| ui_state.drag_distance >= 4.0) {
+                                let atoms = render_state.atoms_in_region(
+                                    &region,
+                                    &ui_state.camera,
+                                    render_state.view_size(),
+                                );
+                                let mut selection = ui_state.selection.clone();
+                                selection.select_all(&atoms, select_mode(&ui_state.modifiers));
+                                set_selection(selection, render_state, &mut ui_state);
+                            } else if !moved_atom && ui_state.drag_distance < 4.0 {
+                                if let Some(cursor) = ui_state.last_cursor {
+                                    let picked = render_state.pick_atom(
+                                        cursor,
+                                        &ui_state.camera,
+                                        render_state.view_size(),
+                                    );
+                                    let picks_bonds =
+                                        matches!(ui_state.tool, Tool::Select | Tool::AddBond);
+                                    if picked.is_none() && picks_bonds {
+                                        let bond = render_state
+                                            .pick_bond(
+                                                cursor,
+                                                &ui_state.camera,
+                                                render_state.view_size(),
+                                            )
+                                            .zip(molecule.as_ref())
+                                            .and_then(|(id, molecule_ref)| {
+                                                molecule_ref.bonds().find(|bond| bond.id == id)
+                                            })
+                                            .cloned();
+                                        // Selects the first atom with the second as bond target,
+                                        // as picking the bond in the Notes window does.
+                                        if let Some(bond) = &bond {
+                                            select_only(bond.a, render_state, &mut ui_state);
+                                            ui_state.bond_target = Some(bond.b);
+                                        }
+                                        // The Add Bond tool steps the order: single, double,
+                                        // triple, then single again, going back to single
+                                        // early when the atoms have no valence left.
+                                        let cycled = bond
+                                            .filter(|_| ui_state.tool == Tool::AddBond)
+                                            .zip(molecule.as_mut());
+                                        if let Some((bond, molecule_ref)) = cycled {
+                                            let next = bond.order % 3 + 1;
+                                            let order = if molecule_ref
+                                                .check_bond_order(bond.id, next)
+                                                .is_ok()
+                                            {
+                                                next
+                                            } else {
+                                                1
+                                            };
+                                            let command = Command::SetBondOrder {
+                                                bond_id: bond.id,
+                                                order,
+                                                previous: None,
+                                            };
+                                            apply_command(
+                                                command,
+                                                molecule_ref,
+                                                &mut history,
+                                                render_state,
+                                                &mut ui_state,
+                                            );
+                                        }
                                     }
-                                } else {
-                                    ui.label("Select an atom to move.");
+                                    handle_click(
+                                        picked,
+                                        render_state,
+                                        &mut ui_state,
+                                        molecule.as_mut(),
+                                        &mut history,
+                                    );
                                 }
                             }
+                            ui_state.end_drag();
+                        }
+                    },
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        let scroll = match delta {
+                            MouseScrollDelta::LineDelta(_, y) => y,
+                            MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 100.0,
+                        };
+                        if scroll.abs() > f32::EPSILON {
+                            ui_state.zoom(scroll * 0.1);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::AboutToWait => {
+                if let Some(window) = window.as_ref() {
+                    window.request_redraw();
+                }
+            }
+            _ => {}
+        })
+        .expect("event loop run");
+}
+
+fn apply_startup_options(
+    options: &StartupOptions,
+    molecule: &Molecule,
+    render_state: &mut RenderState,
+    ui_state: &mut UiState,
+) {
+    if let Some(representation) = options.representation {
+        let representation = match representation {
+            RepresentationArg::BallAndStick => Representation::BallAndStick,
+            RepresentationArg::SpaceFilling => Representation::SpaceFilling,
+        };
+        ui_state.representation = representation;
+        render_state.set_representation(representation, molecule);
+    }
+    if let Some(camera) = options.camera {
+        ui_state
+            .camera
+            .set_orbit(camera.yaw, camera.pitch, camera.distance);
+        ui_state.camera_dirty = true;
+    }
+    for value in &options.select {
+        let id = AtomId::new(*value);
+        if molecule.get_atom(id).is_none() {
+            ui_state.status_message = format!("--select: atom {value} not found");
+            continue;
+        }
+        let mut selection = ui_state.selection.clone();
+        selection.select(id, SelectMode::Add);
+        set_selection(selection, render_state, ui_state);
+    }
+    ui_state.pending_screenshot = options.screenshot.clone();
+    ui_state.presentation = options.presentation;
+    ui_state.exit_after_screenshot = options.exit;
+}
+
+/// Writes the frame on a worker thread, running the presentation post-processing first when
+/// `presentation` is set.
+fn spawn_png_write(
+    path: String,
+    frame: Frame,
+    presentation: Option<PresentationSettings>,
+) -> mpsc::Receiver<Result<String, String>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let frame = match presentation {
+            Some(settings) => postprocess::present(&frame, &settings),
+            None => frame,
+        };
+        let result = write_png(&path, &frame).map(|()| path);
+        let _ = tx.send(result);
+    });
+    rx
+}
+
+/// Frames of a camera animation still to render, one per redraw so the window stays live.
+struct MovieRender {
+    cameras: Vec<Camera>,
+    base: PathBuf,
+    next: usize,
+    /// Frame rate to encode the finished frames at with ffmpeg, when wanted.
+    encode: Option<u32>,
+}
+
+/// Renders and writes the next frame of `ui_state.movie`, then restores the view camera.
+fn render_movie_frame(render_state: &RenderState, ui_state: &mut UiState, aspect: f32) {
+    let Some(movie) = ui_state.movie.as_mut() else {
+        return;
+    };
+    let Some(camera) = movie.cameras.get(movie.next) else {
+        ui_state.movie = None;
+        return;
+    };
+    let presentation = ui_state.presentation.then_some(PresentationSettings {
+        focus_distance: camera.distance,
+        ..ui_state.presentation_settings
+    });
+    let scale = presentation.map_or(1, |settings| settings.supersample);
+    render_state.update_camera(camera, aspect);
+    let path = trajectory::frame_path(&movie.base, movie.next + 1, "png");
+    let written = render_state.capture(scale).and_then(|frame| {
+        let frame = match presentation {
+            Some(settings) => postprocess::present(&frame, &settings),
+            None => frame,
+        };
+        write_png(&path.to_string_lossy(), &frame)
+    });
+    render_state.update_camera(&ui_state.camera, aspect);
+    movie.next += 1;
+    match written {
+        Ok(()) if movie.next == movie.cameras.len() => {
+            let first = trajectory::frame_path(&movie.base, 1, "png");
+            ui_state.provenance.record(format!(
+                "render {} animation frames as {}",
+                movie.cameras.len(),
+                first.display()
+            ));
+            ui_state.status_message = format!("rendered {} frames", movie.cameras.len());
+            if let Some(fps) = movie.encode {
+                ui_state.status_message = "encoding video with ffmpeg…".to_string();
+                ui_state.video_rx = Some(spawn_ffmpeg(&movie.base, fps));
+            }
+            ui_state.movie = None;
+        }
+        Ok(()) => {}
+        Err(err) => {
+            ui_state.status_message = format!("{}: {err}", path.display());
+            ui_state.movie = None;
+        }
+    }
+}
+
+/// Encodes the frames numbered next to `base` into an MP4 on a worker thread.
+fn spawn_ffmpeg(base: &Path, fps: u32) -> mpsc::Receiver<Result<PathBuf, String>> {
+    let (args, video) = animation::ffmpeg_args(base, fps);
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let result = match std::process::Command::new("ffmpeg").args(&args).output() {
+            Ok(output) if output.status.success() => Ok(video),
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(stderr.lines().last().unwrap_or("failed").to_string())
+            }
+            Err(err) => Err(format!("could not run ffmpeg: {err}")),
+        };
+        let _ = tx.send(result);
+    });
+    rx
+}
+
+/// A rendered figure panel and the presentation post-processing it still needs.
+type FigurePanel = (Frame, Option<PresentationSettings>);
+
+/// How [`spawn_figure_write`] arranges its panels.
+enum FigureLayout {
+    /// Separate renders tiled `columns` to a row.
+    Tiled { columns: usize },
+    /// One render of the comparison grid, labeled at these cells of the output.
+    Cells(Vec<GridCell>),
+}
+
+/// Renders the figure panels: the comparison grid in one frame when it is on, otherwise one
+/// frame per saved view. The caller restores the view camera.
+fn capture_figure(
+    render_state: &RenderState,
+    ui_state: &UiState,
+) -> Result<(Vec<FigurePanel>, FigureLayout), String> {
+    let presentation = |camera: &Camera| {
+        ui_state.presentation.then_some(PresentationSettings {
+            focus_distance: camera.distance,
+            ..ui_state.presentation_settings
+        })
+    };
+    let scale = if ui_state.presentation {
+        ui_state.presentation_settings.supersample
+    } else {
+        1
+    };
+    if render_state.grid.is_some() {
+        let frame = render_state.capture(scale)?;
+        let size = render_state.size;
+        let cells = render_state.cells(size.width as f32, size.height as f32);
+        let settings = presentation(&ui_state.camera);
+        return Ok((vec![(frame, settings)], FigureLayout::Cells(cells)));
+    }
+    let mut cameras: Vec<Camera> = ui_state.views.iter().map(|view| view.camera).collect();
+    if ui_state.figure_same_scale {
+        figure::match_scale(&mut cameras);
+    }
+    let aspect = render_state.view_aspect();
+    let panels = cameras
+        .iter()
+        .map(|camera| {
+            render_state.update_camera(camera, aspect);
+            Ok((render_state.capture(scale)?, presentation(camera)))
+        })
+        .collect::<Result<_, String>>()?;
+    let columns = ui_state.figure_columns;
+    Ok((panels, FigureLayout::Tiled { columns }))
+}
+
+/// Post-processes and lays out the figure panels on a worker thread, then writes the PNG.
+fn spawn_figure_write(
+    path: String,
+    panels: Vec<FigurePanel>,
+    layout: FigureLayout,
+) -> mpsc::Receiver<Result<String, String>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let frames: Vec<Frame> = panels
+            .into_iter()
+            .map(|(frame, presentation)| match presentation {
+                Some(settings) => postprocess::present(&frame, &settings),
+                None => frame,
+            })
+            .collect();
+        let figure = match layout {
+            FigureLayout::Tiled { columns } => figure::compose(&frames, columns),
+            FigureLayout::Cells(cells) => {
+                let mut frame = frames.into_iter().next().unwrap_or_else(|| Frame {
+                    width: 0,
+                    height: 0,
+                    pixels: Vec::new(),
+                    depth: Vec::new(),
+                });
+                figure::label_cells(&mut frame, &cells);
+                frame
+            }
+        };
+        let result = write_png(&path, &figure).map(|()| path);
+        let _ = tx.send(result);
+    });
+    rx
+}
+
+fn write_png(path: &str, screenshot: &Frame) -> Result<(), String> {
+    let file = std::fs::File::create(path).map_err(|err| err.to_string())?;
+    let mut encoder = png::Encoder::new(
+        std::io::BufWriter::new(file),
+        screenshot.width,
+        screenshot.height,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+    writer
+        .write_image_data(&screenshot.pixels)
+        .map_err(|err| err.to_string())
+}
+
+fn handle_checkpoint_action(
+    action: CheckpointAction,
+    molecule: &mut Molecule,
+    history: &mut CommandHistory,
+    render_state: &mut RenderState,
+    ui_state: &mut UiState,
+) {
+    match action {
+        CheckpointAction::Take => {
+            let name = match ui_state.checkpoint_name.trim() {
+                "" => format!("checkpoint {}", ui_state.checkpoints.len() + 1),
+                name => name.to_string(),
+            };
+            ui_state
+                .provenance
+                .record(format!("take checkpoint \"{name}\""));
+            ui_state.checkpoints.push(Checkpoint {
+                name,
+                molecule: molecule.clone(),
+            });
+            ui_state.checkpoint_name.clear();
+        }
+        CheckpointAction::Revert(index) | CheckpointAction::Branch(index) => {
+            let Some(checkpoint) = ui_state.checkpoints.get(index) else {
+                return;
+            };
+            let snapshot = checkpoint.molecule.clone();
+            ui_state
+                .provenance
+                .record(format!("revert to checkpoint \"{}\"", checkpoint.name));
+            if matches!(action, CheckpointAction::Branch(_)) {
+                let name = format!("{} (branch)", checkpoint.name);
+                ui_state.checkpoints.push(Checkpoint {
+                    name,
+                    molecule: molecule.clone(),
+                });
+            }
+            let command = Command::Restore {
+                snapshot: Box::new(snapshot),
+                previous: None,
+            };
+            apply_command(command, molecule, history, render_state, ui_state);
+        }
+        CheckpointAction::Delete(index) => {
+            if index < ui_state.checkpoints.len() {
+                ui_state.checkpoints.remove(index);
+            }
+        }
+        CheckpointAction::SaveSession => {
+            ui_state
+                .provenance
+                .record(format!("save session {}", ui_state.session_path));
+            let session = Session {
+                molecule: molecule.clone(),
+                checkpoints: ui_state.checkpoints.clone(),
+                style: ui_state.style.clone(),
+                notes: ui_state.notes.clone(),
+                annotations: ui_state.annotations.clone(),
+                provenance: ui_state.provenance.clone(),
+                camera: Some(ui_state.camera),
+                views: ui_state.views.clone(),
+            };
+            ui_state.save_rx = Some(spawn_save(
+                ui_state.session_path.clone(),
+                write_session(&session),
+            ));
+        }
+        CheckpointAction::OpenSession => {}
+    }
+}
+
+fn stats_ui(ui: &mut egui::Ui, stats: &SceneStats) {
+    ui.label(format!("Fragments: {}", stats.fragments));
+    let elements: Vec<String> = stats
+        .elements
+        .iter()
+        .map(|(symbol, count)| format!("{symbol} {count}"))
+        .collect();
+    ui.label(format!("Elements: {}", elements.join(", ")));
+    ui.label(format!(
+        "Molecule memory: ~{} KiB",
+        stats.molecule_bytes.div_ceil(1024)
+    ));
+    if let Some(gpu) = stats.gpu {
+        ui.label(format!(
+            "GPU instances: {} atoms, {} bonds",
+            gpu.atom_instances, gpu.bond_instances
+        ));
+        ui.label(format!(
+            "Instance buffers: {} KiB, {} KiB pooled",
+            gpu.buffer_bytes / 1024,
+            gpu.pooled_bytes / 1024
+        ));
+    }
+}
+
+fn spawn_save(path: String, contents: String) -> mpsc::Receiver<Result<String, String>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let result = std::fs::write(&path, contents)
+            .map(|()| path)
+            .map_err(|err| err.to_string());
+        let _ = tx.send(result);
+    });
+    rx
+}
+
+fn spawn_session_load(path: String) -> mpsc::Receiver<Result<Session, String>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let result = gzip::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| parse_session(&contents).map_err(|err| err.to_string()));
+        let _ = tx.send(result);
+    });
+    rx
+}
+
+fn run_find(
+    pattern: &Molecule,
+    molecule: &Molecule,
+    render_state: &mut RenderState,
+    ui_state: &mut UiState,
+) {
+    let mut seen = HashSet::new();
+    ui_state.find_matches = substructure::find_substructures(pattern, molecule, FIND_MATCH_LIMIT)
+        .into_iter()
+        .filter_map(|mapping| {
+            let mut atoms: Vec<AtomId> = mapping.values().copied().collect();
+            atoms.sort();
+            seen.insert(atoms.clone()).then(|| FindMatch {
+                bonds: substructure::matched_bonds(pattern, molecule, &mapping),
+                atoms,
+            })
+        })
+        .collect();
+    ui_state.find_focus = None;
+    ui_state.status_message = match ui_state.find_matches.len() {
+        0 => "no substructure matches".to_string(),
+        count => format!("{count} substructure matches"),
+    };
+    show_find_matches(render_state, ui_state);
+}
+
+fn run_query(molecule: &Molecule, render_state: &mut RenderState, ui_state: &mut UiState) {
+    let parsed = match query::parse_query(&ui_state.find_query) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            ui_state.status_message = format!("query error: {err}");
+            return;
+        }
+    };
+    let mut seen = HashSet::new();
+    ui_state.find_matches = parsed
+        .find(molecule, FIND_MATCH_LIMIT)
+        .iter()
+        .filter_map(|mapping| {
+            let atoms = parsed.selection(mapping);
+            let bonds = molecule
+                .bonds()
+                .filter(|bond| atoms.contains(&bond.a) && atoms.contains(&bond.b))
+                .map(|bond| bond.id)
+                .collect();
+            seen.insert(atoms.clone())
+                .then_some(FindMatch { atoms, bonds })
+        })
+        .collect();
+    ui_state.find_focus = None;
+    let selected: HashSet<AtomId> = ui_state
+        .find_matches
+        .iter()
+        .flat_map(|found| found.atoms.iter().copied())
+        .collect();
+    ui_state.status_message = format!("query selected {} atoms", selected.len());
+    show_find_matches(render_state, ui_state);
+}
+
+/// Matches the atoms a script selection accepts as one find result.
+fn run_script_selection(
+    molecule: &Molecule,
+    render_state: &mut RenderState,
+    ui_state: &mut UiState,
+) {
+    let atoms = match ui_state.hooks.select(&ui_state.find_selection, molecule) {
+        Ok(atoms) => atoms,
+        Err(err) => {
+            ui_state.status_message = format!("selection error: {err}");
+            return;
+        }
+    };
+    ui_state.status_message = format!("{} selected {} atoms", ui_state.find_selection, atoms.len());
+    let selected: HashSet<AtomId> = atoms.iter().copied().collect();
+    let bonds = molecule
+        .bonds()
+        .filter(|bond| selected.contains(&bond.a) && selected.contains(&bond.b))
+        .map(|bond| bond.id)
+        .collect();
+    ui_state.find_matches = if atoms.is_empty() {
+        Vec::new()
+    } else {
+        vec![FindMatch { atoms, bonds }]
+    };
+    ui_state.find_focus = None;
+    show_find_matches(render_state, ui_state);
+}
 
-                            if !ui_state.status_message.is_empty() {
-                                ui.separator();
-                                ui.label(format!("Status: {}", ui_state.status_message));
-                            }
-                        });
-                })
-                egui_state.handle_platform_output(window, output.platform_output);
-                if let Some(representation) = pending_representation {
-                    ui_state.representation = representation;
-                    if let Some(molecule_ref) = molecule.as_ref() {
-                        render_state.set_representation(representation, molecule_ref);
-                    }
-                }
-                let paint_jobs = egui_ctx.tessellate(output.shapes, output.pixels_per_point);
-                let screen_descriptor = egui_wgpu::ScreenDescriptor {
-                    size_in_pixels: [render_state.config.width, render_state.config.height],
-                    pixels_per_point: output.pixels_per_point,
-                };
+fn rules_text(rules: &[StyleRule]) -> String {
+    rules.iter().map(|rule| format!("{rule}\n")).collect()
+}
 
-                for (id, image_delta) in &output.textures_delta.set {
-                    egui_renderer.update_texture(
-                        &render_state.device,
-                        &render_state.queue,
-                        *id,
-                        image_delta,
-                    );
-                }
+/// Re-evaluates the style rules and recomputes the active script coloring; a coloring error
+/// turns it off.
+fn refresh_coloring(molecule: &Molecule, render_state: &mut RenderState, ui_state: &mut UiState) {
+    ui_state.coloring_dirty = false;
+    ui_state.recolor_atoms.clear();
+    render_state.apply_style_rules(molecule);
+    let colors = match &ui_state.coloring {
+        Some(name) => ui_state.hooks.colors(name, molecule),
+        None => Ok(HashMap::new()),
+    };
+    match colors {
+        Ok(colors) => render_state.set_coloring(colors, molecule),
+        Err(err) => {
+            ui_state.status_message = format!("coloring error: {err}");
+            ui_state.coloring = None;
+            render_state.set_coloring(HashMap::new(), molecule);
+        }
+    }
+}
 
-                let render_result =
-                    render_state.render(egui_renderer, &paint_jobs, &screen_descriptor);
-                match render_result {
-                    Ok(()) => {}
-                    Err(wgpu::SurfaceError::Lost) => render_state.resize(render_state.size),
-                    Err(wgpu::SurfaceError::OutOfMemory) => target.exit(),
-                    Err(wgpu::SurfaceError::Timeout) => {
-                        std::thread::sleep(Duration::from_millis(16));
+/// Re-evaluates the style rules and script coloring for just the atoms edits touched, and
+/// restyles those atoms and any whose ruled style changed with them.
+fn recolor_atoms(molecule: &Molecule, render_state: &mut RenderState, ui_state: &mut UiState) {
+    let atoms: Vec<AtomId> = ui_state.recolor_atoms.drain().collect();
+    let mut restyled: HashSet<AtomId> = atoms.iter().copied().collect();
+    if !render_state.style.rules.is_empty() {
+        restyled.extend(render_state.style.apply_rules_to(molecule, &atoms));
+    }
+    for id in &atoms {
+        if molecule.get_atom(*id).is_none() {
+            render_state.coloring.remove(id);
+        }
+    }
+    if let Some(name) = &ui_state.coloring {
+        let present = atoms.iter().filter_map(|&id| molecule.get_atom(id));
+        match ui_state.hooks.colors_of(name, molecule, present) {
+            Ok(colors) => render_state.coloring.extend(colors),
+            Err(err) => {
+                ui_state.status_message = format!("coloring error: {err}");
+                ui_state.coloring = None;
+                render_state.set_coloring(HashMap::new(), molecule);
+                return;
+            }
+        }
+    }
+    render_state.restyle_atoms(molecule, &restyled);
+}
+
+/// Descriptor positions in world space: stereocenters at the atom, double bonds at their
+/// midpoint.
+fn stereo_labels(molecule: &Molecule) -> Vec<(Vec3, &'static str)> {
+    let mut labels: Vec<(Vec3, &'static str)> = molecule
+        .atoms_in_order()
+        .filter_map(|atom| Some((Vec3::from_array(atom.position), atom.stereo?.label())))
+        .collect();
+    for bond in molecule.bonds() {
+        let (Some(stereo), Some(a), Some(b)) = (
+            bond.stereo,
+            molecule.get_atom(bond.a),
+            molecule.get_atom(bond.b),
+        ) else {
+            continue;
+        };
+        let midpoint = (Vec3::from_array(a.position) + Vec3::from_array(b.position)) * 0.5;
+        labels.push((midpoint, stereo.label()));
+    }
+    labels
+}
+
+fn show_find_matches(render_state: &mut RenderState, ui_state: &UiState) {
+    let shown = ui_state
+        .find_matches
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| ui_state.find_focus.is_none_or(|focus| focus == *index));
+    let mut atoms = HashSet::new();
+    let mut bonds = HashSet::new();
+    for (_, found) in shown {
+        atoms.extend(found.atoms.iter().copied());
+        bonds.extend(found.bonds.iter().copied());
+    }
+    render_state.set_match_highlight(&atoms, &bonds);
+}
+
+fn show_diff(diff: MoleculeDiff, render_state: &mut RenderState, ui_state: &mut UiState) {
+    render_state.set_diff_highlight(Some(&diff));
+    ui_state.status_message = if diff.is_empty() {
+        "no differences".to_string()
+    } else {
+        String::new()
+    };
+    ui_state.diff = Some(diff);
+}
+
+fn spawn_gallery_watch(dir: PathBuf, palette: Palette, lenient: bool) -> Gallery {
+    let (tx, rx) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    thread::spawn(move || {
+        let mut seen: HashMap<PathBuf, std::time::SystemTime> = HashMap::new();
+        while !stopped.load(Ordering::Relaxed) {
+            let mut events = Vec::new();
+            match gallery::scan_directory(&dir) {
+                Ok(files) => {
+                    let present: HashSet<&PathBuf> = files.iter().map(|(path, _)| path).collect();
+                    seen.retain(|path, _| {
+                        let keep = present.contains(path);
+                        if !keep {
+                            events.push(GalleryEvent::Removed(path.clone()));
+                        }
+                        keep
+                    });
+                    for (path, modified) in &files {
+                        if seen.insert(path.clone(), *modified) != Some(*modified) {
+                            let options = ImportOptions::defaults(lenient);
+                            let result = load_molecule(
+                                &path.to_string_lossy(),
+                                &options,
+                                &LoadProgress::default(),
+                            )
+                            .map(|(molecule, ..)| Box::new(molecule));
+                            events.push(GalleryEvent::Updated(path.clone(), result));
+                        }
                     }
-                    Err(wgpu::SurfaceError::Outdated) => {}
                 }
+                Err(err) => events.push(GalleryEvent::Failed(format!("{}: {err}", dir.display()))),
+            }
+            if events.into_iter().any(|event| tx.send(event).is_err()) {
+                return;
+            }
+            thread::sleep(GALLERY_POLL_INTERVAL);
+        }
+    });
+    Gallery {
+        items: Vec::new(),
+        palette,
+        rx,
+        stop,
+    }
+}
 
-                for id in &output.textures_delta.free {
-                    egui_renderer.free_texture(id);
-                }
+fn color32(color: [f32; 3]) -> egui::Color32 {
+    let [r, g, b] = color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+    egui::Color32::from_rgb(r, g, b)
+}
+
+fn show_thumbnail(ui: &mut egui::Ui, item: &GalleryItem) -> egui::Response {
+    let name = item
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    ui.vertical(|ui| {
+        let size = egui::vec2(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+        let painter = ui.painter_at(rect);
+        let background = if response.hovered() {
+            egui::Color32::from_gray(60)
+        } else {
+            egui::Color32::from_gray(35)
+        };
+        painter.rect_filled(rect, 4.0, background);
+        for disc in &item.discs {
+            let center = rect.min + egui::vec2(disc.center[0], disc.center[1]) * THUMBNAIL_SIZE;
+            painter.circle(
+                center,
+                disc.radius * THUMBNAIL_SIZE,
+                color32(disc.color),
+                egui::Stroke::new(1.0, egui::Color32::from_gray(20)),
+            );
+        }
+        let response = match &item.molecule {
+            Ok(_) => response,
+            Err(err) => {
+                painter.text(
+                    rect.center(),
+                    egui::Align2::CENTER_CENTER,
+                    "!",
+                    egui::FontId::proportional(24.0),
+                    egui::Color32::LIGHT_RED,
+                );
+                response.on_hover_text(err)
             }
-            _ => {}
-        })
-        .expect("event loop run");
+        };
+        ui.add_sized(
+            [THUMBNAIL_SIZE, 14.0],
+            egui::Label::new(name).truncate(true),
+        );
+        response
+    })
+    .inner
+}
+
+/// File name without extension, for labeling grid cells.
+fn sample_name(path: &str) -> String {
+    Path::new(path).file_stem().map_or_else(
+        || path.to_string(),
+        |stem| stem.to_string_lossy().into_owned(),
+    )
+}
+
+/// A loaded molecule with whatever a lenient read skipped and the links inferred for it.
+type LoadResult = Result<(Molecule, Vec<Problem>, Vec<InferredLink>), String>;
+
+/// How far a background load has read into its file.
+#[derive(Debug, Default)]
+struct LoadProgress {
+    read: AtomicU64,
+    /// File size in bytes; 0 when unknown, as for stdin.
+    total: AtomicU64,
+}
+
+impl LoadProgress {
+    /// Bytes read and the file size, when known.
+    fn bytes(&self) -> (u64, Option<u64>) {
+        let total = self.total.load(Ordering::Relaxed);
+        (
+            self.read.load(Ordering::Relaxed),
+            (total > 0).then_some(total),
+        )
+    }
+}
+
+fn spawn_cleanup(molecule: Molecule) -> mpsc::Receiver<Minimized> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(optimize::minimize(&molecule, &MinimizeOptions::default()));
+    });
+    rx
+}
+
+fn spawn_load(
+    path: String,
+    options: ImportOptions,
+    progress: Arc<LoadProgress>,
+) -> mpsc::Receiver<LoadResult> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(load_molecule(&path, &options, &progress));
+    });
+    rx
+}
+
+/// Progress counts bytes of the file as stored, so it works for gzipped files too.
+fn load_molecule(path: &str, options: &ImportOptions, progress: &LoadProgress) -> LoadResult {
+    let file: Box<dyn std::io::Read> = if path == cli::STDIN_PATH {
+        Box::new(std::io::stdin())
+    } else {
+        let file = std::fs::File::open(path).map_err(|err| format!("{path}: {err}"))?;
+        let size = file.metadata().map_or(0, |metadata| metadata.len());
+        progress.total.store(size, Ordering::Relaxed);
+        Box::new(file)
+    };
+    let counted =
+        stream::Progress::new(file, |bytes| progress.read.store(bytes, Ordering::Relaxed));
+    // Input from stdin has no name, so its format is told from the contents.
+    let name = (path != cli::STDIN_PATH).then_some(path);
+    let loaded = formats::load_reader(counted, name, &options.load)
+        .map_err(|err| format!("{path}: {err}"))?;
+    let mut molecule = loaded.molecule;
+    // PDB files rarely carry CONECT records for disulfides.
+    let links = if loaded.format == Format::Pdb && options.infer_links {
+        let links = residues::infer_links(&molecule);
+        molecule.apply_links(links)
+    } else {
+        Vec::new()
+    };
+    Ok((molecule, loaded.problems, links))
+}
+
+/// Converts the input without opening a window; never returns.
+fn run_headless(path: &str, format: OutputFormat, lenient: bool) -> ! {
+    let options = ImportOptions::defaults(lenient);
+    let molecule = match load_molecule(path, &options, &LoadProgress::default()) {
+        Ok((molecule, problems, links)) => {
+            for problem in problems {
+                eprintln!("warning: {problem}");
+            }
+            for link in &links {
+                eprintln!("note: inferred {}", link.describe(&molecule));
+            }
+            molecule
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+    let mut stdout = std::io::stdout().lock();
+    if let Err(err) = stdout
+        .write_all(format.write(&molecule).as_bytes())
+        .and_then(|()| stdout.flush())
+    {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+    std::process::exit(0);
 }
 
 fn handle_shortcuts(key: &Key, modifiers: &winit::keyboard::ModifiersState) -> bool {
@@ -1670,10 +8313,333 @@ fn handle_shortcuts(key: &Key, modifiers: &winit::keyboard::ModifiersState) -> b
     }
     matches!(
         key,
-        Key::Character(key) if key.eq_ignore_ascii_case("z") || key.eq_ignore_ascii_case("y")
+        Key::Character(key) if ["z", "y", "c", "v", "d"].iter().any(|k| key.eq_ignore_ascii_case(k))
     )
 }
 
+fn copy_selection(molecule: &Molecule, ui_state: &mut UiState) {
+    if ui_state.selection.is_empty() {
+        ui_state.status_message = "nothing selected to copy".to_string();
+        return;
+    }
+    ui_state.clipboard = Some(molecule.extract(ui_state.selection.atoms()));
+    ui_state.status_message = "copied selection".to_string();
+}
+
+fn paste_clipboard(
+    molecule: &mut Molecule,
+    history: &mut CommandHistory,
+    render_state: &mut RenderState,
+    ui_state: &mut UiState,
+) {
+    let Some(mut fragment) = ui_state.clipboard.clone() else {
+        ui_state.status_message = "clipboard is empty".to_string();
+        return;
+    };
+    let anchor = ui_state
+        .selection
+        .primary()
+        .filter(|&anchor| ui_state.paste_onto_selection && !molecule.neighbors(anchor).is_empty());
+    if let Some(anchor) = anchor {
+        attach_fragment(&fragment, anchor, molecule, history, render_state, ui_state);
+        return;
+    }
+    for id in fragment.atom_ids() {
+        if let Some(atom) = fragment.get_atom(id) {
+            let position = Vec3::from_array(atom.position) + PASTE_OFFSET;
+            fragment.set_atom_position(id, position.to_array());
+        }
+    }
+    let command = Command::Merge {
+        fragment: Box::new(fragment),
+        mapping: None,
+    };
+    apply_command(command, molecule, history, render_state, ui_state);
+}
+
+/// Adds a copy of the selection next to it and selects the copy, so repeating units can be
+/// built by duplicating and moving.
+fn duplicate_selection(
+    molecule: &mut Molecule,
+    history: &mut CommandHistory,
+    render_state: &mut RenderState,
+    ui_state: &mut UiState,
+) {
+    let atoms = ui_state.selection.atoms().to_vec();
+    let Some(command) = Command::duplicate_atoms(molecule, &atoms, PASTE_OFFSET) else {
+        ui_state.status_message = "nothing selected to duplicate".to_string();
+        return;
+    };
+    let Some(applied) = apply_command(command, molecule, history, render_state, ui_state) else {
+        return;
+    };
+    let mut selection = Selection::new();
+    selection.select_all(&applied.merged_atoms(), SelectMode::Replace);
+    set_selection(selection, render_state, ui_state);
+    ui_state.status_message = format!("duplicated {} atoms", ui_state.selection.len());
+}
+
+/// Attaches a library template to the selected atom, or places it as a separate piece at the
+/// view center and selects it.
+fn insert_library_fragment(
+    fragment: &Fragment,
+    molecule: &mut Option<Molecule>,
+    history: &mut CommandHistory,
+    render_state: &mut RenderState,
+    ui_state: &mut UiState,
+) {
+    let built = match fragment.build_at(ui_state.camera.pivot()) {
+        Ok(built) => built,
+        Err(err) => {
+            ui_state.status_message = format!("{}: {err}", fragment.name);
+            return;
+        }
+    };
+    let Some(molecule_ref) = molecule.as_mut() else {
+        render_state.set_molecule(&built);
+        ui_state.stereo_dirty = true;
+        ui_state.cell_info_dirty = true;
+        ui_state.composition_dirty = true;
+        ui_state.coloring_dirty = true;
+        *molecule = Some(built);
+        return;
+    };
+    if let Some(anchor) = ui_state.selection.primary() {
+        attach_fragment(
+            &built,
+            anchor,
+            molecule_ref,
+            history,
+            render_state,
+            ui_state,
+        );
+        return;
+    }
+    let command = Command::Merge {
+        fragment: Box::new(built),
+        mapping: None,
+    };
+    if let Some(applied) = apply_command(command, molecule_ref, history, render_state, ui_state) {
+        let mut selection = Selection::new();
+        selection.select_all(&applied.merged_atoms(), SelectMode::Replace);
+        set_selection(selection, render_state, ui_state);
+        ui_state.status_message = format!("inserted {}", fragment.name);
+    }
+}
+
+/// Bonds `fragment`'s first atom to `anchor`, oriented by [`Command::attach_fragment`].
+fn attach_fragment(
+    fragment: &Molecule,
+    anchor: AtomId,
+    molecule: &mut Molecule,
+    history: &mut CommandHistory,
+    render_state: &mut RenderState,
+    ui_state: &mut UiState,
+) {
+    let Some(command) = Command::attach_fragment(molecule, anchor, fragment) else {
+        ui_state.status_message = "nothing to attach".to_string();
+        return;
+    };
+    apply_command(command, molecule, history, render_state, ui_state);
+    if ui_state.status_message.is_empty() {
+        ui_state.status_message =
+            format!("attached {} to atom {}", fragment.formula(), anchor.value());
+    }
+}
+
+/// World-space ray through `cursor` in the view.
+fn cursor_ray(cursor: Vec2, camera: &Camera, size: winit::dpi::PhysicalSize<u32>) -> Option<Ray> {
+    let viewport = Vec2::new(size.width as f32, size.height as f32);
+    let aspect = viewport.x / viewport.y.max(1.0);
+    pick::ray_from_screen(cursor, viewport, camera.view_proj(aspect))
+}
+
+/// Where the cursor ray meets the plane through `point` facing the camera.
+fn cursor_on_plane(
+    cursor: Vec2,
+    camera: &Camera,
+    size: winit::dpi::PhysicalSize<u32>,
+    point: Vec3,
+) -> Option<Vec3> {
+    let ray = cursor_ray(cursor, camera, size)?;
+    let normal = camera.eye_direction();
+    let facing = ray.direction.dot(normal);
+    if facing.abs() < f32::EPSILON {
+        return None;
+    }
+    let t = (point - ray.origin).dot(normal) / facing;
+    (t > 0.0).then(|| ray.at(t))
+}
+
+/// `position` snapped onto an ideal angle at `anchor` when snapping is on, with the guide the
+/// HUD shows for it.
+fn sketch_position(
+    molecule: &Molecule,
+    anchor: Option<AtomId>,
+    moving: Option<AtomId>,
+    position: Vec3,
+    snap: bool,
+) -> (Vec3, Option<BondGuide>) {
+    let Some(guide) =
+        anchor.and_then(|anchor| sketch::bond_guide(molecule, anchor, moving, position.to_array()))
+    else {
+        return (position, None);
+    };
+    let snapped = snap
+        .then(|| {
+            sketch::snap_angle(
+                molecule,
+                &guide,
+                position.to_array(),
+                &SNAP_ANGLES,
+                ANGLE_SNAP_TOLERANCE,
+            )
+        })
+        .flatten();
+    match snapped {
+        Some(snapped) => (
+            Vec3::from_array(snapped),
+            sketch::bond_guide(molecule, guide.anchor, moving, snapped),
+        ),
+        None => (position, Some(guide)),
+    }
+}
+
+/// Where a click with the Add Atom tool would put the new atom: under the cursor, level with
+/// the selected atom (bonded to it) or with the view center.
+fn placement(
+    molecule: &Molecule,
+    render_state: &RenderState,
+    ui_state: &UiState,
+) -> Option<(Vec3, Option<BondGuide>)> {
+    let cursor = ui_state.last_cursor?;
+    let anchor = ui_state
+        .selection
+        .primary()
+        .filter(|id| molecule.get_atom(*id).is_some());
+    let point = anchor
+        .and_then(|id| molecule.get_atom(id))
+        .map_or(ui_state.camera.target, |atom| {
+            Vec3::from_array(atom.position)
+        });
+    let position = cursor_on_plane(cursor, &ui_state.camera, render_state.view_size(), point)?;
+    Some(sketch_position(
+        molecule,
+        anchor,
+        None,
+        position,
+        ui_state.angle_snap,
+    ))
+}
+
+/// Moves the atom held with the Move tool under the cursor, keeping its depth, and the rest
+/// of the selection with it when it is selected.
+fn drag_atom(
+    atom_id: AtomId,
+    molecule: &mut Molecule,
+    history: &mut CommandHistory,
+    render_state: &mut RenderState,
+    ui_state: &mut UiState,
+) {
+    let (Some(cursor), Some(atom)) = (ui_state.last_cursor, molecule.get_atom(atom_id)) else {
+        return;
+    };
+    let from = atom.position;
+    let Some(position) = cursor_on_plane(
+        cursor,
+        &ui_state.camera,
+        render_state.view_size(),
+        Vec3::from_array(from),
+    ) else {
+        return;
+    };
+    // A selected group moves rigidly; a lone atom snaps to the sketch angles.
+    let group = ui_state.selection.len() > 1 && ui_state.selection.contains(atom_id);
+    let (atoms, to) = if group {
+        (ui_state.selection.atoms().to_vec(), position)
+    } else {
+        let anchor = sketch::nearest_bonded(molecule, atom_id, position.to_array());
+        let (to, _) = sketch_position(
+            molecule,
+            anchor,
+            Some(atom_id),
+            position,
+            ui_state.angle_snap,
+        );
+        (vec![atom_id], to)
+    };
+    apply_move(
+        &atoms,
+        to - Vec3::from_array(from),
+        molecule,
+        history,
+        render_state,
+        ui_state,
+    );
+}
+
+/// Anchor position, sketched position and guide for the bond-angle HUD, while dragging an
+/// atom or placing one next to the selection.
+fn sketch_hud(
+    molecule: &Molecule,
+    render_state: &RenderState,
+    ui_state: &UiState,
+) -> Option<(Vec3, Vec3, BondGuide)> {
+    let (position, guide) = match ui_state.atom_drag {
+        Some(atom_id) => {
+            let position = molecule.get_atom(atom_id)?.position;
+            let anchor = sketch::nearest_bonded(molecule, atom_id, position)?;
+            let guide = sketch::bond_guide(molecule, anchor, Some(atom_id), position)?;
+            (Vec3::from_array(position), guide)
+        }
+        None if ui_state.tool == Tool::AddAtom => {
+            let (position, guide) = placement(molecule, render_state, ui_state)?;
+            (position, guide?)
+        }
+        None => return None,
+    };
+    let anchor = Vec3::from_array(molecule.get_atom(guide.anchor)?.position);
+    Some((anchor, position, guide))
+}
+
+fn bond_order_label(order: u8) -> &'static str {
+    match order {
+        1 => "Single",
+        2 => "Double",
+        _ => "Triple",
+    }
+}
+
+/// Chooses whether an arrow end is an atom or the bond between two atoms.
+fn site_kind_combo(ui: &mut egui::Ui, id: &str, bond: &mut bool) {
+    egui::ComboBox::from_id_source(id)
+        .selected_text(if *bond { "bond" } else { "atom" })
+        .show_ui(ui, |ui| {
+            ui.selectable_value(bond, false, "atom");
+            ui.selectable_value(bond, true, "bond");
+        });
+}
+
+/// Shift adds the picked atom to the selection and ctrl (cmd) toggles it.
+fn select_mode(modifiers: &winit::keyboard::ModifiersState) -> SelectMode {
+    if modifiers.shift_key() {
+        SelectMode::Add
+    } else if modifiers.control_key() || modifiers.super_key() {
+        SelectMode::Toggle
+    } else {
+        SelectMode::Replace
+    }
+}
+
+fn set_selection(selection: Selection, render_state: &mut RenderState, ui_state: &mut UiState) {
+    render_state.update_selection(&ui_state.selection, &selection);
+    ui_state.selection = selection;
+}
+
+fn select_only(atom_id: AtomId, render_state: &mut RenderState, ui_state: &mut UiState) {
+    set_selection(Selection::only(atom_id), render_state, ui_state);
+}
+
 fn handle_click(
     picked: Option<AtomId>,
     render_state: &mut RenderState,
@@ -1682,46 +8648,108 @@ fn handle_click(
     history: &mut CommandHistory,
 ) {
     if let Some(picked_id) = picked {
-        let previous = ui_state.selection;
-        ui_state.selection = Some(picked_id);
-        render_state.update_selection(previous, ui_state.selection);
-    }
-
-    if ui_state.tool == Tool::AddBond {
-        if let (Some(picked_id), Some(molecule_ref)) = (picked, molecule) {
-            match ui_state.bond_target {
-                None => {
-                    ui_state.bond_target = Some(picked_id);
-                }
-                Some(target_id) if target_id != picked_id => {
-                    let command = Command::AddBond {
-                        atom_a: target_id,
-                        atom_b: picked_id,
-                        bond_id: None,
-                    };
-                    apply_command(command, molecule_ref, history, render_state, ui_state);
-                    ui_state.bond_target = None;
-                }
-                _ => {}
+        let mut selection = ui_state.selection.clone();
+        selection.select(picked_id, select_mode(&ui_state.modifiers));
+        set_selection(selection, render_state, ui_state);
+    }
+
+    let Some(molecule_ref) = molecule else {
+        return;
+    };
+    match (ui_state.tool, picked) {
+        (Tool::AddBond, Some(picked_id)) => match ui_state.bond_target {
+            None => {
+                ui_state.bond_target = Some(picked_id);
+            }
+            Some(target_id) if target_id != picked_id => {
+                let command = Command::AddBond {
+                    atom_a: target_id,
+                    atom_b: picked_id,
+                    bond_id: None,
+                };
+                apply_command(command, molecule_ref, history, render_state, ui_state);
+                ui_state.bond_target = None;
+            }
+            _ => {}
+        },
+        (Tool::Measure, Some(picked_id)) => {
+            if let Some(measurement) = ui_state.measurements.pick(picked_id) {
+                ui_state.status_message = format!(
+                    "{}: {}",
+                    measurement.label(),
+                    measurement.value_label(molecule_ref).unwrap_or_default()
+                );
+            }
+        }
+        (Tool::Sprout, Some(anchor)) => {
+            let element = ui_state.edit_element.trim().to_string();
+            if element.is_empty() {
+                ui_state.status_message = "choose an element to sprout".to_string();
+                return;
+            }
+            let Some(command) = Command::sprout(molecule_ref, anchor, &element) else {
+                return;
+            };
+            apply_command(command, molecule_ref, history, render_state, ui_state);
+        }
+        (Tool::AddAtom, None) => {
+            let Some((position, guide)) = placement(molecule_ref, render_state, ui_state) else {
+                return;
+            };
+            let atom_id = molecule_ref.next_atom_id();
+            let insert = Command::InsertAtom {
+                element: ui_state.edit_element.trim().to_string(),
+                position: position.to_array(),
+                atom_id: Some(atom_id),
+                order_index: None,
+            };
+            let command = match guide {
+                Some(guide) => Command::Composite {
+                    commands: vec![
+                        insert,
+                        Command::AddBond {
+                            atom_a: guide.anchor,
+                            atom_b: atom_id,
+                            bond_id: None,
+                        },
+                    ],
+                },
+                None => insert,
+            };
+            apply_command(command, molecule_ref, history, render_state, ui_state);
+            if molecule_ref.get_atom(atom_id).is_some() {
+                // Keep building the chain from the new atom.
+                select_only(atom_id, render_state, ui_state);
             }
         }
+        _ => {}
     }
 }
 
+/// Runs `command` as one undo step and returns it as applied, with the ids it assigned;
+/// `None` when it failed.
 fn apply_command(
     command: Command,
     molecule: &mut Molecule,
     history: &mut CommandHistory,
     render_state: &mut RenderState,
     ui_state: &mut UiState,
-) {
-    match history.execute(command, molecule) {
+) -> Option<Command> {
+    let events = molecule.subscribe();
+    let result = history.execute(command, molecule);
+    sync_render(&events, molecule, render_state, ui_state);
+    match result {
         Ok(applied) => {
             ui_state.status_message.clear();
-            apply_render_delta(&applied, false, molecule, render_state, ui_state);
+            ui_state.provenance.record_command(&applied);
+            if let Some(recorder) = ui_state.recorder.as_mut() {
+                recorder.record_command(molecule, &applied);
+            }
+            Some(applied)
         }
         Err(err) => {
             ui_state.status_message = err;
+            None
         }
     }
 }
@@ -1732,9 +8760,22 @@ fn undo_command(
     render_state: &mut RenderState,
     ui_state: &mut UiState,
 ) {
-    match history.undo(molecule) {
+    let events = molecule.subscribe();
+    let result = history.undo(molecule);
+    sync_render(&events, molecule, render_state, ui_state);
+    match result {
         Ok(Some(command)) => {
-            apply_render_delta(&command, true, molecule, render_state, ui_state);
+            let action = format!("undo {}", provenance::describe(&command));
+            if let Some(recorder) = ui_state.recorder.as_mut() {
+                recorder.record(molecule, &action);
+            }
+            ui_state.provenance.record(action);
+            let restored = command.deleted_atoms();
+            if !restored.is_empty() && ui_state.selection.is_empty() {
+                let mut selection = Selection::new();
+                selection.select_all(&restored, SelectMode::Replace);
+                set_selection(selection, render_state, ui_state);
+            }
         }
         Ok(None) => {}
         Err(err) => ui_state.status_message = err,
@@ -1747,98 +8788,190 @@ fn redo_command(
     render_state: &mut RenderState,
     ui_state: &mut UiState,
 ) {
-    match history.redo(molecule) {
+    let events = molecule.subscribe();
+    let result = history.redo(molecule);
+    sync_render(&events, molecule, render_state, ui_state);
+    match result {
         Ok(Some(command)) => {
-            apply_render_delta(&command, false, molecule, render_state, ui_state);
+            let action = format!("redo {}", provenance::describe(&command));
+            if let Some(recorder) = ui_state.recorder.as_mut() {
+                recorder.record(molecule, &action);
+            }
+            ui_state.provenance.record(action);
         }
         Ok(None) => {}
         Err(err) => ui_state.status_message = err,
     }
 }
 
-fn apply_render_delta(
-    command: &Command,
-    is_undo: bool,
+/// Brings the GPU instances and selection in line with the changes `events` reported.
+/// Instances are built from the molecule's current state, so atoms and bonds that came and
+/// went within one step (e.g. a rolled-back batch) are skipped.
+fn sync_render(
+    events: &mpsc::Receiver<MoleculeEvent>,
     molecule: &Molecule,
     render_state: &mut RenderState,
     ui_state: &mut UiState,
 ) {
-    match command {
-        Command::InsertAtom {
-            element,
-            position,
-            atom_id: Some(atom_id),
-            ..
-        } => {
-            if is_undo {
-                render_state.remove_atom_instance(*atom_id);
-                if ui_state.selection == Some(*atom_id) {
-                    ui_state.selection = None;
+    let events: Vec<MoleculeEvent> = events.try_iter().collect();
+    if events.is_empty() {
+        return;
+    }
+    // The formula and composition depend on elements and the cell, not on positions.
+    ui_state.composition_dirty |= events.iter().any(|event| {
+        matches!(
+            event,
+            MoleculeEvent::AtomAdded(_)
+                | MoleculeEvent::AtomRemoved(_)
+                | MoleculeEvent::AtomChanged(_)
+                | MoleculeEvent::CellChanged
+                | MoleculeEvent::Reset
+        )
+    });
+    // Cell metrics depend on the atoms and the cell but not on bonds.
+    ui_state.cell_info_dirty |= events.iter().any(|event| {
+        !matches!(
+            event,
+            MoleculeEvent::BondAdded(_)
+                | MoleculeEvent::BondRemoved(_)
+                | MoleculeEvent::BondChanged(_)
+        )
+    });
+    if events.contains(&MoleculeEvent::Reset) {
+        render_state.set_molecule(molecule);
+        ui_state.coloring_dirty = true;
+        ui_state.diff = None;
+        ui_state.selection.clear();
+        ui_state.bond_target = None;
+    } else {
+        // Moves rewrite the extra lines of nearby double and triple bonds in place; bond edits
+        // redraw the lines of the bonds they touch.
+        let mut moved = HashSet::new();
+        let mut strands_stale = HashSet::new();
+        // Moves leave colors alone; other edits recolor the atoms they name.
+        let mut touched: HashSet<AtomId> = HashSet::new();
+        let mut bonds_removed = false;
+        for event in events {
+            match event {
+                MoleculeEvent::AtomAdded(atom_id) => {
+                    touched.insert(atom_id);
+                    if let Some(atom) = molecule.get_atom(atom_id) {
+                        if !render_state.atom_lookup.contains_key(&atom_id) {
+                            render_state.add_atom_instance(atom);
+                        }
+                    }
                 }
-            } else {
-                let atom = Atom {
-                    id: *atom_id,
-                    element: element.clone(),
-                    position: *position,
-                };
-                render_state.add_atom_instance(&atom);
-            }
-        }
-        Command::DeleteAtom { atom_id, removed } => {
-            if is_undo {
-                if let Some(removed) = removed {
-                    render_state.add_atom_instance(&removed.atom);
-                    render_state.update_bonds_for_atom(removed.atom.id, molecule);
-                    if ui_state.selection.is_none() {
-                        render_state.update_selection(None, Some(removed.atom.id));
-                        ui_state.selection = Some(removed.atom.id);
+                MoleculeEvent::AtomRemoved(atom_id) => {
+                    touched.insert(atom_id);
+                    render_state.remove_atom_instance(atom_id);
+                    ui_state.selection.remove(atom_id);
+                    if ui_state.bond_target == Some(atom_id) {
+                        ui_state.bond_target = None;
                     }
                 }
-            } else {
-                render_state.remove_atom_instance(*atom_id);
-                if ui_state.selection == Some(*atom_id) {
-                    ui_state.selection = None;
+                MoleculeEvent::AtomMoved(atom_id) => {
+                    if let Some(atom) = molecule.get_atom(atom_id) {
+                        render_state.update_atom_position(atom_id, atom.position);
+                        render_state.update_bonds_for_atom(atom_id, molecule);
+                        moved.insert(atom_id);
+                    }
+                }
+                MoleculeEvent::BondAdded(bond_id) => {
+                    if !render_state.bond_lookup.contains_key(&bond_id) {
+                        render_state.add_bond_instance(bond_id, molecule);
+                    }
+                    strands_stale.extend(render_state.strands_near(molecule, bond_id));
+                    touched.extend(bond_ends(molecule, bond_id));
                 }
+                MoleculeEvent::BondRemoved(bond_id) => {
+                    bonds_removed = true;
+                    render_state.remove_bond_instance(bond_id);
+                    strands_stale.extend(render_state.strands_near(molecule, bond_id));
+                }
+                MoleculeEvent::BondChanged(bond_id) => {
+                    render_state.update_bond_instance(bond_id, molecule);
+                    strands_stale.extend(render_state.strands_near(molecule, bond_id));
+                    touched.extend(bond_ends(molecule, bond_id));
+                }
+                MoleculeEvent::AtomChanged(atom_id) => {
+                    touched.insert(atom_id);
+                }
+                MoleculeEvent::CellChanged | MoleculeEvent::Reset => {}
             }
         }
-        Command::MoveAtom { atom_id, from, to } => {
-            let position = if is_undo { *from } else { *to };
-            render_state.update_atom_position(*atom_id, position);
-            render_state.update_bonds_for_atom(*atom_id, molecule);
+        render_state.compact_instances();
+        if !strands_stale.is_empty() {
+            render_state.update_order_strands(molecule, &strands_stale);
         }
-        Command::AddBond {
-            bond_id: Some(bond_id),
-            ..
-        } => {
-            if is_undo {
-                render_state.remove_bond_instance(*bond_id);
-            } else {
-                render_state.add_bond_instance(*bond_id, molecule);
-            }
+        if !moved.is_empty() {
+            render_state.refresh_order_strands(molecule, &moved);
         }
-        Command::RemoveBond { bond_id, .. } => {
-            if is_undo {
-                render_state.add_bond_instance(*bond_id, molecule);
-            } else {
-                render_state.remove_bond_instance(*bond_id);
-            }
+        // Splitting a fragment changes the size of atoms the events don't name.
+        if bonds_removed && render_state.style.rules_use_fragments() {
+            ui_state.coloring_dirty = true;
+        } else {
+            ui_state.recolor_atoms.extend(touched);
         }
-        _ => {}
     }
+    if let Some(tutorial) = ui_state.tutorial.as_mut() {
+        tutorial.update(WATER_TUTORIAL, molecule);
+    }
+    if ui_state.check_render {
+        resync_if_diverged(molecule, render_state, ui_state);
+    }
+    ui_state.stereo_dirty = true;
+}
+
+/// The two atoms of bond `id`; none when it is gone.
+fn bond_ends(molecule: &Molecule, id: BondId) -> Vec<AtomId> {
+    molecule
+        .get_bond(id)
+        .map(|bond| vec![bond.a, bond.b])
+        .unwrap_or_default()
+}
+
+/// Rebuilds the GPU instances from scratch when they no longer match `molecule`, logging how
+/// they had diverged.
+fn resync_if_diverged(molecule: &Molecule, render_state: &mut RenderState, ui_state: &mut UiState) {
+    let found = render_state.divergences(molecule);
+    if found.is_empty() {
+        return;
+    }
+    for divergence in &found {
+        log::warn!("renderer out of sync: {divergence}");
+    }
+    render_state.set_molecule(molecule);
+    render_state.update_selection(&Selection::new(), &ui_state.selection);
+    render_state.set_diff_highlight(ui_state.diff.as_ref());
+    ui_state.status_message = format!(
+        "Renderer was out of sync ({}, {} problems); rebuilt",
+        found[0],
+        found.len()
+    );
 }
 
 fn apply_move(
-    atom_id: AtomId,
+    atoms: &[AtomId],
     delta: Vec3,
     molecule: &mut Molecule,
     history: &mut CommandHistory,
     render_state: &mut RenderState,
     ui_state: &mut UiState,
 ) {
-    if let Some(atom) = molecule.get_atom(atom_id) {
-        let from = atom.position;
-        let to = (Vec3::from_array(atom.position) + delta).to_array();
-        let command = Command::MoveAtom { atom_id, from, to };
+    if let Some(command) = Command::move_atoms(molecule, atoms, delta) {
         apply_command(command, molecule, history, render_state, ui_state);
     }
 }
+
+fn delete_selection(
+    molecule: &mut Molecule,
+    history: &mut CommandHistory,
+    render_state: &mut RenderState,
+    ui_state: &mut UiState,
+) {
+    let Some(command) = Command::delete_atoms(ui_state.selection.atoms()) else {
+        ui_state.status_message = "nothing selected to delete".to_string();
+        return;
+    };
+    apply_command(command, molecule, history, render_state, ui_state);
+}
@@ -0,0 +1,289 @@
+//! Measurement monitors: distances, angles and dihedrals between picked atoms that stay on
+//! screen and follow the atoms as they move.
+//!
+//! Monitors refer to atoms by id, like annotations, so a monitor whose atom was deleted is
+//! kept but has no value and is not drawn until the atom comes back.
+
+use glam::Vec3;
+
+use crate::geometry::{angle, dihedral};
+use crate::{AtomId, Molecule};
+
+/// What the next picks measure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MeasureKind {
+    #[default]
+    Distance,
+    Angle,
+    Dihedral,
+}
+
+impl MeasureKind {
+    pub const ALL: [MeasureKind; 3] = [
+        MeasureKind::Distance,
+        MeasureKind::Angle,
+        MeasureKind::Dihedral,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MeasureKind::Distance => "Distance",
+            MeasureKind::Angle => "Angle",
+            MeasureKind::Dihedral => "Dihedral",
+        }
+    }
+
+    /// Atoms picked to make one monitor.
+    pub fn arity(self) -> usize {
+        match self {
+            MeasureKind::Distance => 2,
+            MeasureKind::Angle => 3,
+            MeasureKind::Dihedral => 4,
+        }
+    }
+}
+
+/// One monitored quantity. Angles have their vertex in the middle; dihedrals turn about the
+/// bond between the middle two atoms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Measurement {
+    Distance(AtomId, AtomId),
+    Angle(AtomId, AtomId, AtomId),
+    Dihedral(AtomId, AtomId, AtomId, AtomId),
+}
+
+impl Measurement {
+    /// The monitor of `kind` over `atoms` in pick order; `None` for the wrong number of atoms.
+    pub fn new(kind: MeasureKind, atoms: &[AtomId]) -> Option<Self> {
+        match (kind, atoms) {
+            (MeasureKind::Distance, &[a, b]) => Some(Measurement::Distance(a, b)),
+            (MeasureKind::Angle, &[a, b, c]) => Some(Measurement::Angle(a, b, c)),
+            (MeasureKind::Dihedral, &[a, b, c, d]) => Some(Measurement::Dihedral(a, b, c, d)),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> MeasureKind {
+        match self {
+            Measurement::Distance(..) => MeasureKind::Distance,
+            Measurement::Angle(..) => MeasureKind::Angle,
+            Measurement::Dihedral(..) => MeasureKind::Dihedral,
+        }
+    }
+
+    pub fn atoms(&self) -> Vec<AtomId> {
+        match *self {
+            Measurement::Distance(a, b) => vec![a, b],
+            Measurement::Angle(a, b, c) => vec![a, b, c],
+            Measurement::Dihedral(a, b, c, d) => vec![a, b, c, d],
+        }
+    }
+
+    /// Current value in Å or degrees; `None` while an atom is missing or the value is
+    /// undefined, as for an angle with two atoms on top of each other.
+    pub fn value(&self, molecule: &Molecule) -> Option<f32> {
+        let at = |id| Some(Vec3::from_array(molecule.get_atom(id)?.position));
+        match *self {
+            Measurement::Distance(a, b) => Some(at(a)?.distance(at(b)?)),
+            Measurement::Angle(a, b, c) => angle(molecule, a, b, c),
+            Measurement::Dihedral(a, b, c, d) => dihedral(molecule, a, b, c, d),
+        }
+    }
+
+    /// The value with its unit, as drawn in the view.
+    pub fn value_label(&self, molecule: &Molecule) -> Option<String> {
+        let value = self.value(molecule)?;
+        Some(match self {
+            Measurement::Distance(..) => format!("{value:.3} Å"),
+            _ => format!("{value:.1}°"),
+        })
+    }
+
+    /// Written as `distance 3–5` or `angle 3–5–6`.
+    pub fn label(&self) -> String {
+        let ids: Vec<String> = self
+            .atoms()
+            .iter()
+            .map(|id| id.value().to_string())
+            .collect();
+        format!("{} {}", self.kind().label().to_lowercase(), ids.join("–"))
+    }
+
+    /// Whether both measure the same thing, whichever end was picked first.
+    fn same_as(&self, other: &Measurement) -> bool {
+        let (ours, mut theirs) = (self.atoms(), other.atoms());
+        if self.kind() != other.kind() {
+            return false;
+        }
+        if ours == theirs {
+            return true;
+        }
+        theirs.reverse();
+        ours == theirs
+    }
+}
+
+/// Monitors in the order they were made, plus the atoms picked so far towards the next one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Measurements {
+    pub items: Vec<Measurement>,
+    /// What the next picks make.
+    pub kind: MeasureKind,
+    picks: Vec<AtomId>,
+}
+
+impl Measurements {
+    /// Atoms picked towards the next monitor.
+    pub fn picks(&self) -> &[AtomId] {
+        &self.picks
+    }
+
+    /// Adds `atom` to the picks; once there are enough for [`Self::kind`], they make a
+    /// monitor, which is returned. Picking an atom already picked does nothing, and a monitor
+    /// that already exists is not added again.
+    pub fn pick(&mut self, atom: AtomId) -> Option<Measurement> {
+        if self.picks.contains(&atom) {
+            return None;
+        }
+        self.picks.push(atom);
+        let measurement = Measurement::new(self.kind, &self.picks)?;
+        self.picks.clear();
+        if !self.items.iter().any(|item| item.same_as(&measurement)) {
+            self.items.push(measurement);
+        }
+        Some(measurement)
+    }
+
+    /// Drops unfinished picks.
+    pub fn cancel(&mut self) {
+        self.picks.clear();
+    }
+
+    /// Switches what the next picks make, dropping unfinished picks.
+    pub fn set_kind(&mut self, kind: MeasureKind) {
+        if kind != self.kind {
+            self.kind = kind;
+            self.picks.clear();
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Measurement> {
+        (index < self.items.len()).then(|| self.items.remove(index))
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.picks.clear();
+    }
+
+    /// Each monitor whose atoms all exist, with the positions to draw it at and its label.
+    pub fn visible(&self, molecule: &Molecule) -> Vec<(Measurement, Vec<Vec3>, String)> {
+        self.items
+            .iter()
+            .filter_map(|measurement| {
+                let positions = measurement
+                    .atoms()
+                    .into_iter()
+                    .map(|id| Some(Vec3::from_array(molecule.get_atom(id)?.position)))
+                    .collect::<Option<Vec<_>>>()?;
+                Some((*measurement, positions, measurement.value_label(molecule)?))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_make_live_distance_monitors() {
+        let mut molecule = Molecule::new("pair");
+        let a = molecule.insert_atom("C".into(), [0.0, 0.0, 0.0]);
+        let b = molecule.insert_atom("O".into(), [1.2, 0.0, 0.0]);
+        let mut measurements = Measurements::default();
+
+        assert_eq!(measurements.pick(a), None);
+        assert_eq!(measurements.pick(a), None);
+        assert_eq!(measurements.picks(), [a]);
+        let made = measurements.pick(b).unwrap();
+        assert_eq!(made, Measurement::Distance(a, b));
+        assert!(measurements.picks().is_empty());
+        assert_eq!(made.value_label(&molecule).unwrap(), "1.200 Å");
+        assert_eq!(
+            made.label(),
+            format!("distance {}–{}", a.value(), b.value())
+        );
+
+        measurements.pick(b);
+        measurements.pick(a);
+        assert_eq!(
+            measurements.items.len(),
+            1,
+            "reversed pair is the same monitor"
+        );
+
+        molecule.set_atom_position(b, [0.0, 2.5, 0.0]);
+        let visible = measurements.visible(&molecule);
+        assert_eq!(visible[0].2, "2.500 Å");
+        assert_eq!(visible[0].1, [Vec3::ZERO, Vec3::new(0.0, 2.5, 0.0)]);
+
+        molecule.remove_atom(b);
+        assert!(measurements.visible(&molecule).is_empty());
+        assert_eq!(measurements.items.len(), 1);
+    }
+
+    #[test]
+    fn angles_and_dihedrals_follow_the_geometry() {
+        let mut molecule = Molecule::new("butane");
+        let ids: Vec<AtomId> = [
+            [1.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [1.5, 0.0, 0.0],
+            [2.5, 1.0, 0.0],
+        ]
+        .into_iter()
+        .map(|position| molecule.insert_atom("C".into(), position))
+        .collect();
+        let mut measurements = Measurements::default();
+
+        measurements.set_kind(MeasureKind::Angle);
+        measurements.pick(ids[0]);
+        measurements.pick(ids[1]);
+        assert_eq!(measurements.pick(ids[1]), None, "already picked");
+        let bend = measurements.pick(ids[2]).unwrap();
+        assert_eq!(bend, Measurement::Angle(ids[0], ids[1], ids[2]));
+        assert_eq!(bend.value_label(&molecule).unwrap(), "45.0°");
+
+        measurements.set_kind(MeasureKind::Dihedral);
+        for &id in &ids {
+            measurements.pick(id);
+        }
+        for &id in ids.iter().rev() {
+            measurements.pick(id);
+        }
+        assert_eq!(
+            measurements.items.len(),
+            2,
+            "reversed dihedral is the same monitor"
+        );
+        let torsion = measurements.items[1];
+        assert_eq!(torsion.label(), {
+            let [a, b, c, d] = [0, 1, 2, 3].map(|i| ids[i].value());
+            format!("dihedral {a}–{b}–{c}–{d}")
+        });
+        assert!(torsion.value(&molecule).unwrap().abs() < 1e-3, "cis");
+        molecule.set_atom_position(ids[3], [2.5, -1.0, 0.0]);
+        assert!(
+            (torsion.value(&molecule).unwrap().abs() - 180.0).abs() < 1e-3,
+            "trans"
+        );
+
+        measurements.pick(ids[0]);
+        measurements.set_kind(MeasureKind::Distance);
+        assert!(measurements.picks().is_empty());
+        assert_eq!(measurements.remove(0), Some(bend));
+        assert_eq!(measurements.items, [torsion]);
+        assert_eq!(measurements.remove(5), None);
+    }
+}
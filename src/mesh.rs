@@ -0,0 +1,117 @@
+//! Unit primitive meshes for the exporters, wound counter-clockwise seen from outside.
+
+use std::f32::consts::{PI, TAU};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// Unit sphere at the origin. Pole triangles that would have zero area are left out.
+    pub fn sphere(segments: u32, rings: u32) -> Self {
+        let mut mesh = Mesh::default();
+        for ring in 0..=rings {
+            // Exact poles and a repeated seam column keep shared vertices bit-identical, so
+            // the transformed mesh stays closed.
+            let (sin_theta, cos_theta) = match ring {
+                0 => (0.0, 1.0),
+                _ if ring == rings => (0.0, -1.0),
+                _ => (ring as f32 / rings as f32 * PI).sin_cos(),
+            };
+            for segment in 0..=segments {
+                let phi = (segment % segments) as f32 / segments as f32 * TAU;
+                let (sin_phi, cos_phi) = phi.sin_cos();
+                let point = [sin_theta * cos_phi, cos_theta, sin_theta * sin_phi];
+                mesh.positions.push(point);
+                mesh.normals.push(point);
+            }
+        }
+        let stride = segments + 1;
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let upper = ring * stride + segment;
+                let lower = upper + stride;
+                if ring > 0 {
+                    mesh.indices.extend([upper, upper + 1, lower]);
+                }
+                if ring + 1 < rings {
+                    mesh.indices.extend([upper + 1, lower + 1, lower]);
+                }
+            }
+        }
+        mesh
+    }
+
+    /// Unit-radius cylinder along Y from -0.5 to 0.5; `capped` closes both ends.
+    pub fn cylinder(segments: u32, capped: bool) -> Self {
+        let mut mesh = Mesh::default();
+        let ring = |i: u32| {
+            let (sin, cos) = ((i % segments) as f32 / segments as f32 * TAU).sin_cos();
+            (cos, sin)
+        };
+        for i in 0..=segments {
+            let (cos, sin) = ring(i);
+            mesh.positions.extend([[cos, -0.5, sin], [cos, 0.5, sin]]);
+            mesh.normals.extend([[cos, 0.0, sin]; 2]);
+        }
+        for i in 0..segments {
+            let base = i * 2;
+            mesh.indices
+                .extend([base, base + 1, base + 2, base + 1, base + 3, base + 2]);
+        }
+        if capped {
+            for (y, top) in [(-0.5, false), (0.5, true)] {
+                let center = mesh.positions.len() as u32;
+                mesh.positions.push([0.0, y, 0.0]);
+                mesh.normals.push([0.0, 2.0 * y, 0.0]);
+                for i in 0..=segments {
+                    let (cos, sin) = ring(i);
+                    mesh.positions.push([cos, y, sin]);
+                    mesh.normals.push([0.0, 2.0 * y, 0.0]);
+                }
+                for i in 0..segments {
+                    let (current, next) = (center + 1 + i, center + 2 + i);
+                    if top {
+                        mesh.indices.extend([center, next, current]);
+                    } else {
+                        mesh.indices.extend([center, current, next]);
+                    }
+                }
+            }
+        }
+        mesh
+    }
+
+    /// Triangle corners in index order.
+    pub fn triangles(&self) -> impl Iterator<Item = [[f32; 3]; 3]> + '_ {
+        self.indices.chunks_exact(3).map(|triangle| {
+            [
+                self.positions[triangle[0] as usize],
+                self.positions[triangle[1] as usize],
+                self.positions[triangle[2] as usize],
+            ]
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    #[test]
+    fn faces_point_outwards() {
+        for mesh in [Mesh::sphere(12, 8), Mesh::cylinder(12, true)] {
+            assert_eq!(mesh.positions.len(), mesh.normals.len());
+            for [a, b, c] in mesh.triangles() {
+                let (a, b, c) = (Vec3::from(a), Vec3::from(b), Vec3::from(c));
+                let normal = (b - a).cross(c - a);
+                assert!(normal.length() > 1e-6);
+                assert!(normal.dot((a + b + c) / 3.0) > 0.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+
+use crate::{AtomId, BondId, Molecule};
+
+/// What a note is attached to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NoteTarget {
+    Atom(AtomId),
+    Bond(BondId),
+}
+
+/// Free-text notes on atoms and bonds, such as "protonation uncertain here", kept with the
+/// project rather than the structure.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Notes {
+    pub atoms: BTreeMap<AtomId, String>,
+    pub bonds: BTreeMap<BondId, String>,
+}
+
+impl Notes {
+    pub fn is_empty(&self) -> bool {
+        self.atoms.is_empty() && self.bonds.is_empty()
+    }
+
+    pub fn get(&self, target: NoteTarget) -> Option<&str> {
+        match target {
+            NoteTarget::Atom(id) => self.atoms.get(&id),
+            NoteTarget::Bond(id) => self.bonds.get(&id),
+        }
+        .map(String::as_str)
+    }
+
+    /// Blank text removes the note.
+    pub fn set(&mut self, target: NoteTarget, text: &str) {
+        let text = text.trim();
+        match target {
+            NoteTarget::Atom(id) if text.is_empty() => {
+                self.atoms.remove(&id);
+            }
+            NoteTarget::Atom(id) => {
+                self.atoms.insert(id, text.to_string());
+            }
+            NoteTarget::Bond(id) if text.is_empty() => {
+                self.bonds.remove(&id);
+            }
+            NoteTarget::Bond(id) => {
+                self.bonds.insert(id, text.to_string());
+            }
+        }
+    }
+
+    /// Marker position and text of every note whose atom or bond is in `molecule`; bond notes
+    /// sit at the bond midpoint. Notes on deleted atoms are kept so undo brings them back.
+    pub fn markers<'a>(&'a self, molecule: &Molecule) -> Vec<(NoteTarget, [f32; 3], &'a str)> {
+        let atoms = self.atoms.iter().filter_map(|(&id, text)| {
+            let atom = molecule.get_atom(id)?;
+            Some((NoteTarget::Atom(id), atom.position, text.as_str()))
+        });
+        let bonds = self.bonds.iter().filter_map(|(&id, text)| {
+            let bond = molecule.bonds().find(|bond| bond.id == id)?;
+            let (a, b) = (molecule.get_atom(bond.a)?, molecule.get_atom(bond.b)?);
+            let midpoint = std::array::from_fn(|axis| (a.position[axis] + b.position[axis]) * 0.5);
+            Some((NoteTarget::Bond(id), midpoint, text.as_str()))
+        });
+        atoms.chain(bonds).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markers_follow_the_structure() {
+        let mut molecule = Molecule::new("water");
+        let o = molecule.insert_atom("O".into(), [0.0; 3]);
+        let h = molecule.insert_atom("H".into(), [1.0, 0.0, 0.0]);
+        let bond = molecule.add_bond(o, h).unwrap();
+        let mut notes = Notes::default();
+        notes.set(NoteTarget::Atom(o), "  protonation uncertain here ");
+        notes.set(NoteTarget::Bond(bond), "elongated?");
+        assert_eq!(
+            notes.get(NoteTarget::Atom(o)),
+            Some("protonation uncertain here")
+        );
+        assert_eq!(
+            notes.markers(&molecule),
+            [
+                (NoteTarget::Atom(o), [0.0; 3], "protonation uncertain here"),
+                (NoteTarget::Bond(bond), [0.5, 0.0, 0.0], "elongated?"),
+            ]
+        );
+
+        molecule.remove_atom(h);
+        assert_eq!(notes.markers(&molecule).len(), 1);
+        notes.set(NoteTarget::Atom(o), " ");
+        assert!(notes.markers(&molecule).is_empty());
+        assert!(!notes.is_empty());
+    }
+}
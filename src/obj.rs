@@ -0,0 +1,201 @@
+//! Wavefront OBJ export of the rendered geometry, with colors in a companion MTL file.
+//!
+//! OBJ has no instancing, so every atom and bond is baked into its own named object; atoms
+//! and bonds of one color share a material.
+
+use std::fmt::Write as _;
+
+use glam::{Quat, Vec3};
+
+use crate::mesh::Mesh;
+use crate::scene::Scene;
+
+const SPHERE_SEGMENTS: u32 = 24;
+const SPHERE_RINGS: u32 = 12;
+const CYLINDER_SEGMENTS: u32 = 16;
+
+/// The OBJ text; `material_library` is the MTL file name it refers to, relative to the OBJ.
+pub fn write_obj(scene: &Scene, material_library: &str) -> String {
+    let sphere = Mesh::sphere(SPHERE_SEGMENTS, SPHERE_RINGS);
+    let cylinder = Mesh::cylinder(CYLINDER_SEGMENTS, false);
+    let materials = materials(scene);
+    let mut out = String::new();
+    let _ = writeln!(out, "# MolWeaver scene");
+    let _ = writeln!(out, "mtllib {material_library}");
+    let mut vertices = 0;
+    for (index, atom) in scene.spheres.iter().enumerate() {
+        let center = Vec3::from(atom.center);
+        let placed = transformed(
+            &sphere,
+            |point| center + point * atom.radius,
+            Quat::IDENTITY,
+        );
+        let material = material_index(&materials, atom.color);
+        write_object(
+            &mut out,
+            vertices,
+            &format!("atom_{}", index + 1),
+            &placed,
+            material,
+        );
+        vertices += placed.positions.len();
+    }
+    for (index, bond) in scene.cylinders.iter().enumerate() {
+        let (start, end) = (Vec3::from(bond.start), Vec3::from(bond.end));
+        let length = start.distance(end);
+        if length <= f32::EPSILON {
+            continue;
+        }
+        let rotation = Quat::from_rotation_arc(Vec3::Y, (end - start) / length);
+        let midpoint = (start + end) * 0.5;
+        let size = Vec3::new(bond.radius, length, bond.radius);
+        let placed = transformed(
+            &cylinder,
+            |point| midpoint + rotation * (point * size),
+            rotation,
+        );
+        let material = material_index(&materials, bond.color);
+        write_object(
+            &mut out,
+            vertices,
+            &format!("bond_{}", index + 1),
+            &placed,
+            material,
+        );
+        vertices += placed.positions.len();
+    }
+    out
+}
+
+/// `mesh` with its positions moved by `place` and its normals turned by `rotation`.
+fn transformed(mesh: &Mesh, place: impl Fn(Vec3) -> Vec3, rotation: Quat) -> Mesh {
+    Mesh {
+        positions: mesh
+            .positions
+            .iter()
+            .map(|point| place(Vec3::from(*point)).to_array())
+            .collect(),
+        normals: mesh
+            .normals
+            .iter()
+            .map(|normal| {
+                (rotation * Vec3::from(*normal))
+                    .normalize_or_zero()
+                    .to_array()
+            })
+            .collect(),
+        indices: mesh.indices.clone(),
+    }
+}
+
+/// One named object; OBJ indices are 1-based and count every vertex written before.
+fn write_object(out: &mut String, first_vertex: usize, name: &str, mesh: &Mesh, material: usize) {
+    let _ = writeln!(out, "o {name}");
+    for [x, y, z] in &mesh.positions {
+        let _ = writeln!(out, "v {x} {y} {z}");
+    }
+    for [x, y, z] in &mesh.normals {
+        let _ = writeln!(out, "vn {x} {y} {z}");
+    }
+    let _ = writeln!(out, "usemtl color_{material}");
+    for triangle in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|corner| first_vertex + triangle[corner] as usize + 1);
+        let _ = writeln!(out, "f {a}//{a} {b}//{b} {c}//{c}");
+    }
+}
+
+/// The MTL text defining the materials [`write_obj`] uses for the same scene.
+pub fn write_mtl(scene: &Scene) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# MolWeaver materials");
+    for (index, [r, g, b]) in materials(scene).into_iter().enumerate() {
+        let _ = writeln!(out, "newmtl color_{index}");
+        let _ = writeln!(out, "Kd {r} {g} {b}");
+        let _ = writeln!(out, "Ks 0.2 0.2 0.2");
+        let _ = writeln!(out, "Ns 50");
+    }
+    out
+}
+
+/// Distinct colors in first-use order, atoms before bonds.
+fn materials(scene: &Scene) -> Vec<[f32; 3]> {
+    let mut colors: Vec<[f32; 3]> = Vec::new();
+    let used = scene
+        .spheres
+        .iter()
+        .map(|atom| atom.color)
+        .chain(scene.cylinders.iter().map(|bond| bond.color));
+    for color in used {
+        if !colors.contains(&color) {
+            colors.push(color);
+        }
+    }
+    colors
+}
+
+fn material_index(materials: &[[f32; 3]], color: [f32; 3]) -> usize {
+    materials
+        .iter()
+        .position(|known| *known == color)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{Cylinder, SceneCamera, Sphere};
+
+    #[test]
+    fn bakes_every_instance_with_shared_materials() {
+        let white = [1.0; 3];
+        let scene = Scene {
+            spheres: vec![
+                Sphere {
+                    center: [0.0; 3],
+                    radius: 0.5,
+                    color: [1.0, 0.0, 0.0],
+                },
+                Sphere {
+                    center: [1.5, 0.0, 0.0],
+                    radius: 0.3,
+                    color: white,
+                },
+            ],
+            cylinders: vec![Cylinder {
+                start: [0.0; 3],
+                end: [1.5, 0.0, 0.0],
+                radius: 0.1,
+                color: white,
+            }],
+            camera: SceneCamera::framing(&[], 45.0, 1.0),
+        };
+        let obj = write_obj(&scene, "scene.mtl");
+        let count = |prefix: &str| obj.lines().filter(|line| line.starts_with(prefix)).count();
+        let sphere = Mesh::sphere(SPHERE_SEGMENTS, SPHERE_RINGS);
+        let cylinder = Mesh::cylinder(CYLINDER_SEGMENTS, false);
+        assert_eq!(
+            count("v "),
+            2 * sphere.positions.len() + cylinder.positions.len()
+        );
+        assert_eq!(
+            count("f "),
+            (2 * sphere.indices.len() + cylinder.indices.len()) / 3
+        );
+        assert_eq!(count("o "), 3);
+        assert!(obj.contains("mtllib scene.mtl\n"));
+        let last = obj.lines().last().unwrap();
+        let highest = count("v ");
+        assert!(last.split_whitespace().skip(1).all(|corner| {
+            let index: usize = corner.split("//").next().unwrap().parse().unwrap();
+            index <= highest
+        }));
+
+        let mtl = write_mtl(&scene);
+        assert_eq!(mtl.matches("newmtl").count(), 2);
+        assert!(mtl.contains("newmtl color_1\nKd 1 1 1\n"));
+        assert_eq!(
+            obj.lines().filter(|line| *line == "usemtl color_1").count(),
+            2
+        );
+    }
+}
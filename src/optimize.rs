@@ -0,0 +1,656 @@
+//! Geometry cleanup: a small UFF-style force field and an L-BFGS minimizer.
+//!
+//! The energy has four terms: harmonic bond stretch about the sum of covalent radii
+//! (shortened for double and triple bonds), angle bend in the cosine about the ideal angle of
+//! each center's geometry, a cosine torsion about bonds between tetrahedral or trigonal
+//! centers, and 12-6 van der Waals between atoms more than two bonds apart. Parameters are
+//! rough; the aim is tidy structures after sketching, not energies to report. Work is done in
+//! `f64` so the line search can tell small improvements apart.
+//!
+//! Atoms flagged with the [`FIXED`] property stay where they are while the rest relax around
+//! them, as when cleaning up a ligand inside a rigid pocket.
+
+use std::collections::{HashMap, HashSet};
+
+use glam::{DVec3, Vec3};
+
+use crate::docking::well_depth;
+use crate::elements::{covalent_radius, vdw_radius};
+use crate::spatial::SpatialGrid;
+use crate::{AtomId, Command, Molecule};
+
+/// Bond stretch force constant in kcal/(mol·Å²).
+const STRETCH: f64 = 350.0;
+/// Angle bend force constant in kcal/mol, per unit of cosine squared.
+const BEND: f64 = 100.0;
+/// Torsion barriers in kcal/mol, shared among the torsions about one bond.
+const TORSION_SP3: f64 = 2.0;
+const TORSION_SP2: f64 = 5.0;
+const TORSION_MIXED: f64 = 1.0;
+/// Pairs farther apart than this at the start, in Å, get no van der Waals term.
+const CUTOFF: f32 = 8.0;
+/// Largest distance, in Å, any atom moves in one step.
+const MAX_STEP: f64 = 0.3;
+/// Steps remembered by L-BFGS.
+const MEMORY: usize = 8;
+
+/// Per-atom property marking atoms the minimizer must not move.
+pub const FIXED: &str = "fixed";
+
+/// Whether `atom` carries the [`FIXED`] flag.
+pub fn is_fixed(molecule: &Molecule, atom: AtomId) -> bool {
+    molecule.properties.get(FIXED, atom).is_some()
+}
+
+/// Electron geometry of a bonded center, from the orders of its bonds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Geometry {
+    Linear,
+    Trigonal,
+    Tetrahedral,
+}
+
+impl Geometry {
+    fn ideal_cosine(self) -> f64 {
+        match self {
+            Geometry::Linear => -1.0,
+            Geometry::Trigonal => -0.5,
+            Geometry::Tetrahedral => -1.0 / 3.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Stretch {
+    atoms: [usize; 2],
+    length: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bend {
+    /// End, vertex, end.
+    atoms: [usize; 3],
+    cosine: f64,
+}
+
+/// `barrier / 2 · (1 − phase · cos(periodicity · φ))`.
+#[derive(Debug, Clone, Copy)]
+struct Torsion {
+    atoms: [usize; 4],
+    barrier: f64,
+    periodicity: f64,
+    phase: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Contact {
+    atoms: [usize; 2],
+    distance: f64,
+    depth: f64,
+}
+
+/// Energy terms for one structure, over its atoms in [`ForceField::atoms`] order.
+#[derive(Debug, Clone, Default)]
+pub struct ForceField {
+    atoms: Vec<AtomId>,
+    stretches: Vec<Stretch>,
+    bends: Vec<Bend>,
+    torsions: Vec<Torsion>,
+    contacts: Vec<Contact>,
+}
+
+impl ForceField {
+    /// Terms for `molecule`'s bonds as they are and its current contacts.
+    pub fn new(molecule: &Molecule) -> Self {
+        let atoms = molecule.atom_ids();
+        let index: HashMap<AtomId, usize> =
+            atoms.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+        let elements: Vec<String> = atoms
+            .iter()
+            .map(|&id| {
+                molecule
+                    .get_atom(id)
+                    .map_or(String::new(), |atom| atom.element.clone())
+            })
+            .collect();
+        let mut neighbors = vec![Vec::new(); atoms.len()];
+        let mut orders = vec![Vec::new(); atoms.len()];
+        let mut stretches = Vec::new();
+        for bond in molecule.bonds() {
+            let (Some(&a), Some(&b)) = (index.get(&bond.a), index.get(&bond.b)) else {
+                continue;
+            };
+            neighbors[a].push(b);
+            neighbors[b].push(a);
+            orders[a].push(bond.order);
+            orders[b].push(bond.order);
+            let single = f64::from(covalent_radius(&elements[a]) + covalent_radius(&elements[b]));
+            // UFF's bond order correction.
+            let length = single - 0.1332 * single * f64::from(bond.order.max(1)).ln();
+            stretches.push(Stretch {
+                atoms: [a, b],
+                length,
+            });
+        }
+        let geometry: Vec<Geometry> = orders
+            .iter()
+            .map(|orders| {
+                let doubles = orders.iter().filter(|&&order| order == 2).count();
+                if orders.iter().any(|&order| order >= 3) || doubles >= 2 {
+                    Geometry::Linear
+                } else if doubles == 1 {
+                    Geometry::Trigonal
+                } else {
+                    Geometry::Tetrahedral
+                }
+            })
+            .collect();
+
+        let mut bends = Vec::new();
+        for (vertex, around) in neighbors.iter().enumerate() {
+            // Metal centers and hypervalent atoms have no single ideal angle.
+            if around.len() > 4 {
+                continue;
+            }
+            for (i, &a) in around.iter().enumerate() {
+                for &c in &around[i + 1..] {
+                    bends.push(Bend {
+                        atoms: [a, vertex, c],
+                        cosine: geometry[vertex].ideal_cosine(),
+                    });
+                }
+            }
+        }
+
+        let mut torsions = Vec::new();
+        for stretch in &stretches {
+            let [j, k] = stretch.atoms;
+            let (barrier, periodicity, phase) = match (geometry[j], geometry[k]) {
+                (Geometry::Linear, _) | (_, Geometry::Linear) => continue,
+                (Geometry::Tetrahedral, Geometry::Tetrahedral) => (TORSION_SP3, 3.0, -1.0),
+                (Geometry::Trigonal, Geometry::Trigonal) => {
+                    let order = f64::from(bond_order(&neighbors, &orders, j, k));
+                    (TORSION_SP2 * (1.0 + 4.18 * order.ln()), 2.0, 1.0)
+                }
+                _ => (TORSION_MIXED, 6.0, 1.0),
+            };
+            let quads: Vec<[usize; 4]> = neighbors[j]
+                .iter()
+                .filter(|&&i| i != k)
+                .flat_map(|&i| {
+                    neighbors[k]
+                        .iter()
+                        .filter(move |&&l| l != j && l != i)
+                        .map(move |&l| [i, j, k, l])
+                })
+                .collect();
+            let share = barrier / quads.len().max(1) as f64;
+            torsions.extend(quads.into_iter().map(|atoms| Torsion {
+                atoms,
+                barrier: share,
+                periodicity,
+                phase,
+            }));
+        }
+
+        let mut excluded: HashSet<(usize, usize)> = HashSet::new();
+        for (center, around) in neighbors.iter().enumerate() {
+            for &a in around {
+                excluded.insert((center.min(a), center.max(a)));
+                for &b in around {
+                    excluded.insert((a.min(b), a.max(b)));
+                }
+            }
+        }
+        let positions: Vec<Vec3> = atoms
+            .iter()
+            .map(|&id| {
+                molecule
+                    .get_atom(id)
+                    .map_or(Vec3::ZERO, |atom| Vec3::from_array(atom.position))
+            })
+            .collect();
+        let grid = SpatialGrid::new(&positions, CUTOFF);
+        let mut contacts = Vec::new();
+        for (a, position) in positions.iter().enumerate() {
+            for b in grid.candidates(*position) {
+                if b <= a || excluded.contains(&(a, b)) || position.distance(positions[b]) > CUTOFF
+                {
+                    continue;
+                }
+                contacts.push(Contact {
+                    atoms: [a, b],
+                    distance: f64::from(vdw_radius(&elements[a]) + vdw_radius(&elements[b])),
+                    depth: f64::from(well_depth(&elements[a]) * well_depth(&elements[b])).sqrt(),
+                });
+            }
+        }
+
+        Self {
+            atoms,
+            stretches,
+            bends,
+            torsions,
+            contacts,
+        }
+    }
+
+    /// The atoms, in the order positions and gradients use.
+    pub fn atoms(&self) -> &[AtomId] {
+        &self.atoms
+    }
+
+    /// Energy in kcal/mol at `positions`.
+    pub fn energy(&self, positions: &[DVec3]) -> f64 {
+        self.evaluate(positions, None)
+    }
+
+    /// Energy at `positions`, writing its gradient in kcal/(mol·Å) into `gradient`.
+    pub fn gradient(&self, positions: &[DVec3], gradient: &mut [DVec3]) -> f64 {
+        gradient.fill(DVec3::ZERO);
+        self.evaluate(positions, Some(gradient))
+    }
+
+    fn evaluate(&self, positions: &[DVec3], mut gradient: Option<&mut [DVec3]>) -> f64 {
+        let mut energy = 0.0;
+        for stretch in &self.stretches {
+            let [a, b] = stretch.atoms;
+            let delta = positions[a] - positions[b];
+            let distance = delta.length();
+            let stretched = distance - stretch.length;
+            energy += STRETCH * stretched * stretched;
+            if let (Some(gradient), true) = (gradient.as_deref_mut(), distance > 1e-9) {
+                let force = delta * (2.0 * STRETCH * stretched / distance);
+                gradient[a] += force;
+                gradient[b] -= force;
+            }
+        }
+        for bend in &self.bends {
+            let [a, vertex, c] = bend.atoms;
+            let (u, v) = (
+                positions[a] - positions[vertex],
+                positions[c] - positions[vertex],
+            );
+            let (lu, lv) = (u.length(), v.length());
+            if lu < 1e-9 || lv < 1e-9 {
+                continue;
+            }
+            let cosine = u.dot(v) / (lu * lv);
+            let off = cosine - bend.cosine;
+            energy += BEND * off * off;
+            if let Some(gradient) = gradient.as_deref_mut() {
+                let scale = 2.0 * BEND * off;
+                let da = (v / (lu * lv) - u * (cosine / (lu * lu))) * scale;
+                let dc = (u / (lu * lv) - v * (cosine / (lv * lv))) * scale;
+                gradient[a] += da;
+                gradient[c] += dc;
+                gradient[vertex] -= da + dc;
+            }
+        }
+        for torsion in &self.torsions {
+            let [i, j, k, l] = torsion.atoms;
+            let f = positions[i] - positions[j];
+            let g = positions[j] - positions[k];
+            let h = positions[l] - positions[k];
+            let (a, b) = (f.cross(g), h.cross(g));
+            let (aa, bb, lg) = (a.length_squared(), b.length_squared(), g.length());
+            if aa < 1e-12 || bb < 1e-12 || lg < 1e-9 {
+                continue;
+            }
+            let phi = b.cross(a).dot(g).atan2(a.dot(b) * lg);
+            let n = torsion.periodicity;
+            energy += torsion.barrier / 2.0 * (1.0 - torsion.phase * (n * phi).cos());
+            if let Some(gradient) = gradient.as_deref_mut() {
+                let d_phi = torsion.barrier / 2.0 * torsion.phase * n * (n * phi).sin();
+                let di = -a * (lg / aa);
+                let dl = b * (lg / bb);
+                let fg = f.dot(g) / (aa * lg);
+                let hg = h.dot(g) / (bb * lg);
+                let dj = -di + a * fg - b * hg;
+                let dk = -dl - a * fg + b * hg;
+                gradient[i] += di * d_phi;
+                gradient[j] += dj * d_phi;
+                gradient[k] += dk * d_phi;
+                gradient[l] += dl * d_phi;
+            }
+        }
+        for contact in &self.contacts {
+            let [a, b] = contact.atoms;
+            let delta = positions[a] - positions[b];
+            let distance = delta.length().max(0.1);
+            let ratio = (contact.distance / distance).powi(6);
+            energy += contact.depth * (ratio * ratio - 2.0 * ratio);
+            if let Some(gradient) = gradient.as_deref_mut() {
+                let d_distance = 12.0 * contact.depth / distance * (ratio - ratio * ratio);
+                let force = delta * (d_distance / distance);
+                gradient[a] += force;
+                gradient[b] -= force;
+            }
+        }
+        energy
+    }
+}
+
+/// Order of the bond between `j` and `k`, 1 when they are not bonded.
+fn bond_order(neighbors: &[Vec<usize>], orders: &[Vec<u8>], j: usize, k: usize) -> u8 {
+    neighbors[j]
+        .iter()
+        .position(|&n| n == k)
+        .map_or(1, |at| orders[j][at].max(1))
+}
+
+/// Limits for [`minimize`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MinimizeOptions {
+    pub max_steps: usize,
+    /// Root-mean-square gradient, in kcal/(mol·Å), below which the structure counts as
+    /// converged.
+    pub tolerance: f64,
+}
+
+impl Default for MinimizeOptions {
+    fn default() -> Self {
+        Self {
+            max_steps: 500,
+            tolerance: 0.05,
+        }
+    }
+}
+
+/// Outcome of [`minimize`]: where each atom started and ended up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Minimized {
+    pub atoms: Vec<AtomId>,
+    pub initial: Vec<[f32; 3]>,
+    pub positions: Vec<[f32; 3]>,
+    pub initial_energy: f64,
+    pub energy: f64,
+    pub steps: usize,
+    pub converged: bool,
+}
+
+/// Relaxes `molecule` with L-BFGS, falling back to steepest descent whenever the quasi-Newton
+/// direction does not go downhill. Bonds are taken as they are; nothing is added or removed.
+/// Fixed atoms feel the others but never move, since their gradient is zeroed.
+pub fn minimize(molecule: &Molecule, options: &MinimizeOptions) -> Minimized {
+    let field = ForceField::new(molecule);
+    let initial: Vec<[f32; 3]> = field
+        .atoms()
+        .iter()
+        .map(|&id| molecule.get_atom(id).map_or([0.0; 3], |atom| atom.position))
+        .collect();
+    let mut x: Vec<DVec3> = initial
+        .iter()
+        .map(|&p| Vec3::from_array(p).as_dvec3())
+        .collect();
+    let free: Vec<bool> = field
+        .atoms()
+        .iter()
+        .map(|&id| !is_fixed(molecule, id))
+        .collect();
+    let free_count = free.iter().filter(|&&free| free).count();
+    let pin = |gradient: &mut [DVec3]| {
+        for (g, &free) in gradient.iter_mut().zip(&free) {
+            if !free {
+                *g = DVec3::ZERO;
+            }
+        }
+    };
+    let mut g = vec![DVec3::ZERO; x.len()];
+    let mut energy = field.gradient(&x, &mut g);
+    pin(&mut g);
+    let initial_energy = energy;
+    let mut memory: Vec<(Vec<DVec3>, Vec<DVec3>, f64)> = Vec::new();
+    let mut steps = 0;
+    let mut converged = false;
+    let mut trial = x.clone();
+    let mut trial_g = g.clone();
+    while steps < options.max_steps {
+        if rms(&g, free_count) < options.tolerance {
+            converged = true;
+            break;
+        }
+        steps += 1;
+        let mut direction = two_loop(&g, &memory);
+        let mut slope = dot(&direction, &g);
+        if slope >= 0.0 {
+            memory.clear();
+            direction = g.iter().map(|&v| -v).collect();
+            slope = dot(&direction, &g);
+        }
+        let longest = direction.iter().map(|v| v.length()).fold(0.0, f64::max);
+        if longest > MAX_STEP {
+            let scale = MAX_STEP / longest;
+            direction.iter_mut().for_each(|v| *v *= scale);
+            slope *= scale;
+        }
+        // Backtrack until the energy drops enough (Armijo).
+        let mut t = 1.0;
+        let mut accepted = None;
+        for _ in 0..30 {
+            for ((out, &p), &d) in trial.iter_mut().zip(&x).zip(&direction) {
+                *out = p + d * t;
+            }
+            let trial_energy = field.gradient(&trial, &mut trial_g);
+            pin(&mut trial_g);
+            if trial_energy <= energy + 1e-4 * t * slope {
+                accepted = Some(trial_energy);
+                break;
+            }
+            t *= 0.5;
+        }
+        let Some(trial_energy) = accepted else {
+            if memory.is_empty() {
+                break;
+            }
+            memory.clear();
+            continue;
+        };
+        let s: Vec<DVec3> = trial.iter().zip(&x).map(|(&a, &b)| a - b).collect();
+        let y: Vec<DVec3> = trial_g.iter().zip(&g).map(|(&a, &b)| a - b).collect();
+        let sy = dot(&s, &y);
+        if sy > 1e-10 {
+            if memory.len() == MEMORY {
+                memory.remove(0);
+            }
+            memory.push((s, y, 1.0 / sy));
+        }
+        std::mem::swap(&mut x, &mut trial);
+        std::mem::swap(&mut g, &mut trial_g);
+        energy = trial_energy;
+    }
+    Minimized {
+        atoms: field.atoms,
+        initial,
+        positions: x.iter().map(|p| p.as_vec3().to_array()).collect(),
+        initial_energy,
+        energy,
+        steps,
+        converged,
+    }
+}
+
+/// L-BFGS search direction from the gradient and the remembered steps.
+fn two_loop(g: &[DVec3], memory: &[(Vec<DVec3>, Vec<DVec3>, f64)]) -> Vec<DVec3> {
+    let mut q: Vec<DVec3> = g.to_vec();
+    let mut alphas = Vec::with_capacity(memory.len());
+    for (s, y, rho) in memory.iter().rev() {
+        let alpha = rho * dot(s, &q);
+        q.iter_mut().zip(y).for_each(|(q, &y)| *q -= y * alpha);
+        alphas.push(alpha);
+    }
+    if let Some((s, y, _)) = memory.last() {
+        let gamma = dot(s, y) / dot(y, y);
+        q.iter_mut().for_each(|q| *q *= gamma);
+    }
+    for ((s, y, rho), alpha) in memory.iter().zip(alphas.into_iter().rev()) {
+        let beta = rho * dot(y, &q);
+        q.iter_mut()
+            .zip(s)
+            .for_each(|(q, &s)| *q += s * (alpha - beta));
+    }
+    q.iter_mut().for_each(|q| *q = -*q);
+    q
+}
+
+fn dot(a: &[DVec3], b: &[DVec3]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a.dot(*b)).sum()
+}
+
+/// Root-mean-square of the gradient over the `free` atoms that can move.
+fn rms(g: &[DVec3], free: usize) -> f64 {
+    if free == 0 {
+        return 0.0;
+    }
+    (dot(g, g) / free as f64).sqrt()
+}
+
+impl Command {
+    /// One step setting or clearing the [`FIXED`] flag on `atoms`, skipping those already in
+    /// the requested state. `None` when nothing changes.
+    pub fn set_fixed(molecule: &Molecule, atoms: &[AtomId], fixed: bool) -> Option<Command> {
+        let mut commands: Vec<Command> = atoms
+            .iter()
+            .filter(|&&atom| molecule.get_atom(atom).is_some() && is_fixed(molecule, atom) != fixed)
+            .map(|&atom_id| Command::SetProperty {
+                atom_id,
+                name: FIXED.to_string(),
+                value: fixed.then(|| "1".to_string()),
+                previous: None,
+            })
+            .collect();
+        match commands.len() {
+            0 => None,
+            1 => commands.pop(),
+            _ => Some(Command::Composite { commands }),
+        }
+    }
+}
+
+impl Minimized {
+    /// One step moving every atom that changed to its relaxed position. Fails when the
+    /// structure was edited after minimization started, since the result no longer fits it.
+    pub fn command(&self, molecule: &Molecule) -> Result<Command, String> {
+        let mut commands = Vec::new();
+        for ((&atom_id, &from), &to) in self.atoms.iter().zip(&self.initial).zip(&self.positions) {
+            match molecule.get_atom(atom_id) {
+                Some(atom) if atom.position == from => {}
+                _ => return Err("structure changed during cleanup".to_string()),
+            }
+            if from != to {
+                commands.push(Command::MoveAtom { atom_id, from, to });
+            }
+        }
+        if molecule.atom_count() != self.atoms.len() {
+            return Err("structure changed during cleanup".to_string());
+        }
+        if commands.is_empty() {
+            return Err("geometry already clean".to_string());
+        }
+        Ok(Command::Composite { commands })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::smiles::parse_smiles;
+    use crate::geometry::angle;
+    use crate::CommandHistory;
+
+    #[test]
+    fn gradient_matches_finite_differences() {
+        let molecule = parse_smiles("CC(=O)NC=C").unwrap();
+        let field = ForceField::new(&molecule);
+        let mut positions: Vec<DVec3> = field
+            .atoms()
+            .iter()
+            .enumerate()
+            .map(|(i, &id)| {
+                let p = Vec3::from_array(molecule.get_atom(id).unwrap().position).as_dvec3();
+                // Knock the structure off its embedding so every term is strained.
+                p + DVec3::new((i as f64 * 0.7).sin(), (i as f64 * 1.3).cos(), 0.2) * 0.15
+            })
+            .collect();
+        let mut gradient = vec![DVec3::ZERO; positions.len()];
+        field.gradient(&positions, &mut gradient);
+        let h = 1e-6;
+        for atom in 0..positions.len() {
+            for axis in 0..3 {
+                let start = positions[atom][axis];
+                positions[atom][axis] = start + h;
+                let up = field.energy(&positions);
+                positions[atom][axis] = start - h;
+                let down = field.energy(&positions);
+                positions[atom][axis] = start;
+                let numeric = (up - down) / (2.0 * h);
+                let analytic = gradient[atom][axis];
+                assert!(
+                    (numeric - analytic).abs() < 1e-3 * (1.0 + numeric.abs()),
+                    "atom {atom} axis {axis}: {numeric} vs {analytic}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cleanup_relaxes_a_distorted_structure_in_one_step() {
+        let mut molecule = parse_smiles("CCO").unwrap();
+        let ids = molecule.atom_ids();
+        let carbon = ids[1];
+        let squashed = molecule.get_atom(carbon).unwrap().position;
+        molecule.set_atom_position(carbon, [squashed[0] + 0.4, squashed[1] - 0.3, squashed[2]]);
+
+        let result = minimize(&molecule, &MinimizeOptions::default());
+        assert!(result.energy < result.initial_energy);
+        assert!(result.converged, "{} steps", result.steps);
+
+        let mut history = CommandHistory::new(4);
+        history
+            .execute(result.command(&molecule).unwrap(), &mut molecule)
+            .unwrap();
+        assert_eq!(history.undo_len(), 1);
+        let length = |a, b| {
+            let p = |id| Vec3::from_array(molecule.get_atom(id).unwrap().position);
+            p(a).distance(p(b))
+        };
+        assert!(
+            (length(ids[0], ids[1]) - 1.52).abs() < 0.05,
+            "{}",
+            length(ids[0], ids[1])
+        );
+        assert!((length(ids[1], ids[2]) - 1.42).abs() < 0.05);
+        let bend = angle(&molecule, ids[0], ids[1], ids[2]).unwrap();
+        assert!((bend - 109.5).abs() < 5.0, "{bend}");
+
+        assert!(result.command(&molecule).is_err(), "stale once applied");
+        history.undo(&mut molecule).unwrap();
+        assert!(result.command(&molecule).is_ok());
+    }
+
+    #[test]
+    fn fixed_atoms_stay_put() {
+        let mut molecule = parse_smiles("CCO").unwrap();
+        let ids = molecule.atom_ids();
+        let squashed = molecule.get_atom(ids[1]).unwrap().position;
+        molecule.set_atom_position(ids[1], [squashed[0] + 0.4, squashed[1] - 0.3, squashed[2]]);
+
+        let mut history = CommandHistory::new(4);
+        let pinned = Command::set_fixed(&molecule, &ids[..2], true).unwrap();
+        history.execute(pinned, &mut molecule).unwrap();
+        assert!(is_fixed(&molecule, ids[0]) && !is_fixed(&molecule, ids[2]));
+        assert!(Command::set_fixed(&molecule, &ids[..2], true).is_none());
+
+        let result = minimize(&molecule, &MinimizeOptions::default());
+        assert!(result.converged && result.energy < result.initial_energy);
+        for (i, &id) in result.atoms.iter().enumerate() {
+            let moved = result.positions[i] != result.initial[i];
+            assert_eq!(moved, !ids[..2].contains(&id), "{id:?}");
+        }
+
+        history.undo(&mut molecule).unwrap();
+        assert!(!is_fixed(&molecule, ids[0]));
+        let free = minimize(&molecule, &MinimizeOptions::default());
+        assert_ne!(free.positions[1], free.initial[1]);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::element_color;
+
+/// Color scheme for atoms, bonds and highlight overlays.
+///
+/// The color-blind schemes draw from the Okabe–Ito set so that elements and highlight
+/// kinds stay distinguishable without relying on red/green contrast.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Palette {
+    #[default]
+    Standard,
+    Deuteranopia,
+    Protanopia,
+}
+
+const ORANGE: [f32; 3] = [0.902, 0.624, 0.0];
+const SKY_BLUE: [f32; 3] = [0.337, 0.706, 0.914];
+const BLUISH_GREEN: [f32; 3] = [0.0, 0.620, 0.451];
+const YELLOW: [f32; 3] = [0.941, 0.894, 0.259];
+const BLUE: [f32; 3] = [0.0, 0.447, 0.698];
+const VERMILLION: [f32; 3] = [0.835, 0.369, 0.0];
+const REDDISH_PURPLE: [f32; 3] = [0.800, 0.475, 0.655];
+
+impl Palette {
+    pub const ALL: [Palette; 3] = [
+        Palette::Standard,
+        Palette::Deuteranopia,
+        Palette::Protanopia,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Palette::Standard => "Standard",
+            Palette::Deuteranopia => "Deuteranopia",
+            Palette::Protanopia => "Protanopia",
+        }
+    }
+
+    pub fn element_color(self, element: &str) -> [f32; 3] {
+        if self == Palette::Standard {
+            return element_color(element);
+        }
+        match element.trim().to_ascii_uppercase().as_str() {
+            "H" => [1.0, 1.0, 1.0],
+            "C" => [0.35, 0.35, 0.35],
+            "N" => BLUE,
+            // Protanopes perceive vermillion as dark olive, close to carbon grey.
+            "O" if self == Palette::Protanopia => ORANGE,
+            "O" => VERMILLION,
+            "S" => YELLOW,
+            "P" => REDDISH_PURPLE,
+            "F" | "CL" => BLUISH_GREEN,
+            _ => [0.7, 0.7, 0.7],
+        }
+    }
+
+    pub fn bond_color(self) -> [f32; 3] {
+        [0.7, 0.7, 0.7]
+    }
+
+    pub fn selection_color(self) -> [f32; 3] {
+        match self {
+            Palette::Standard => [1.0, 0.8, 0.2],
+            Palette::Deuteranopia | Palette::Protanopia => YELLOW,
+        }
+    }
+
+    /// Tint for atoms and bonds reported as added by a comparison.
+    pub fn added_color(self) -> [f32; 3] {
+        match self {
+            Palette::Standard => [0.2, 0.9, 0.3],
+            Palette::Deuteranopia | Palette::Protanopia => SKY_BLUE,
+        }
+    }
+
+    /// Tint for atoms and bonds matched by a substructure search.
+    pub fn match_color(self) -> [f32; 3] {
+        match self {
+            Palette::Standard => [0.9, 0.3, 0.9],
+            Palette::Deuteranopia | Palette::Protanopia => BLUISH_GREEN,
+        }
+    }
+
+    /// Tint for atoms in a docking clash.
+    pub fn clash_color(self) -> [f32; 3] {
+        match self {
+            Palette::Standard => [1.0, 0.15, 0.1],
+            Palette::Deuteranopia | Palette::Protanopia => ORANGE,
+        }
+    }
+
+    /// Tint for atoms reported as modified by a comparison.
+    pub fn modified_color(self) -> [f32; 3] {
+        match self {
+            Palette::Standard => [0.3, 0.5, 1.0],
+            Palette::Deuteranopia | Palette::Protanopia => REDDISH_PURPLE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palettes_keep_highlights_distinct() {
+        for palette in Palette::ALL {
+            let colors = [
+                palette.selection_color(),
+                palette.added_color(),
+                palette.modified_color(),
+                palette.match_color(),
+                palette.clash_color(),
+            ];
+            for (i, a) in colors.iter().enumerate() {
+                for b in &colors[i + 1..] {
+                    assert_ne!(a, b, "{palette:?}");
+                }
+            }
+            assert_ne!(palette.element_color("O"), palette.element_color("C"));
+        }
+        assert_eq!(Palette::Standard.element_color("O"), element_color("O"));
+    }
+}
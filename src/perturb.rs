@@ -0,0 +1,134 @@
+//! Randomly displaced copies of a structure, as starting points for global optimization.
+//!
+//! Each atom moves by a vector drawn uniformly from a ball of the given amplitude. The
+//! generator is seeded, so the same seed gives the same copies on every machine.
+
+use std::collections::HashSet;
+
+use glam::Vec3;
+
+use crate::{AtomId, Molecule};
+
+/// SplitMix64: small, fast and good enough for scattering coordinates.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [-1, 1).
+    fn next_signed(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+
+    /// Uniform in the unit ball, by rejection from the enclosing cube.
+    fn next_in_ball(&mut self) -> Vec3 {
+        loop {
+            let v = Vec3::new(self.next_signed(), self.next_signed(), self.next_signed());
+            if v.length_squared() <= 1.0 {
+                return v;
+            }
+        }
+    }
+}
+
+impl Molecule {
+    /// A copy with every atom displaced by up to `amplitude` Å in a random direction.
+    pub fn perturb(&self, seed: u64, amplitude: f32) -> Molecule {
+        self.perturb_except(seed, amplitude, &HashSet::new())
+    }
+
+    /// Like [`Molecule::perturb`], but `frozen` atoms stay where they are. Moving atoms get
+    /// the same displacements whichever atoms are frozen.
+    pub fn perturb_except(&self, seed: u64, amplitude: f32, frozen: &HashSet<AtomId>) -> Molecule {
+        let mut rng = SplitMix64(seed);
+        let mut copy = self.clone();
+        for atom in self.atoms_in_order() {
+            let offset = rng.next_in_ball() * amplitude;
+            if !frozen.contains(&atom.id) {
+                let moved = Vec3::from_array(atom.position) + offset;
+                copy.set_atom_position(atom.id, moved.to_array());
+            }
+        }
+        copy
+    }
+}
+
+/// `count` copies from [`Molecule::perturb_except`], each with its own seed drawn from
+/// `seed`, so batches from neighboring seeds don't share copies.
+pub fn perturbations(
+    molecule: &Molecule,
+    seed: u64,
+    amplitude: f32,
+    count: usize,
+    frozen: &HashSet<AtomId>,
+) -> Vec<Molecule> {
+    let mut seeds = SplitMix64(seed);
+    (0..count)
+        .map(|_| molecule.perturb_except(seeds.next_u64(), amplitude, frozen))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain() -> Molecule {
+        let mut molecule = Molecule::new("chain");
+        for x in 0..6 {
+            molecule.insert_atom("C".into(), [x as f32 * 1.5, 0.0, 0.0]);
+        }
+        molecule
+    }
+
+    fn displacements(original: &Molecule, copy: &Molecule) -> Vec<f32> {
+        original
+            .atoms_in_order()
+            .map(|atom| {
+                let moved = copy.get_atom(atom.id).unwrap().position;
+                Vec3::from(atom.position).distance(Vec3::from(moved))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn displaces_within_the_amplitude_reproducibly() {
+        let molecule = chain();
+        let copy = molecule.perturb(7, 0.3);
+        let moved = displacements(&molecule, &copy);
+        assert!(
+            moved.iter().all(|&d| d > 0.0 && d <= 0.3 + 1e-5),
+            "{moved:?}"
+        );
+        assert_eq!(displacements(&molecule, &molecule.perturb(7, 0.3)), moved);
+        assert_ne!(displacements(&molecule, &molecule.perturb(8, 0.3)), moved);
+        assert_eq!(copy.atom_count(), molecule.atom_count());
+        let first = molecule.atoms_in_order().next().unwrap();
+        assert_eq!(first.position, [0.0; 3]);
+    }
+
+    #[test]
+    fn frozen_atoms_stay_and_the_rest_move_the_same() {
+        let molecule = chain();
+        let ids = molecule.atom_ids();
+        let frozen: HashSet<AtomId> = [ids[1], ids[4]].into();
+        let free = displacements(&molecule, &molecule.perturb(3, 0.5));
+        let partly = displacements(&molecule, &molecule.perturb_except(3, 0.5, &frozen));
+        for (index, (&all, &some)) in free.iter().zip(&partly).enumerate() {
+            let expected = if index == 1 || index == 4 { 0.0 } else { all };
+            assert_eq!(some, expected);
+        }
+
+        let batch = perturbations(&molecule, 3, 0.5, 4, &frozen);
+        assert_eq!(batch.len(), 4);
+        assert_ne!(
+            displacements(&molecule, &batch[0]),
+            displacements(&molecule, &batch[1])
+        );
+    }
+}
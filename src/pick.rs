@@ -0,0 +1,277 @@
+//! Finding the atom or bond under the cursor.
+//!
+//! A click becomes a [`Ray`] with [`ray_from_screen`]; the `closest_*` functions return what
+//! the ray meets first, from a [`Molecule`] with caller-chosen radii or from a [`Scene`] as
+//! drawn. Dragging out a rectangle or lasso gives a [`Region`], which takes the atoms whose
+//! centers [`screen_from_world`] puts inside it.
+
+use glam::{Mat4, Vec2, Vec3, Vec4};
+
+use crate::scene::Scene;
+use crate::{Atom, AtomId, BondId, Molecule};
+
+/// A half-line from `origin` along the unit vector `direction`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+}
+
+/// World-space ray through `cursor`, in pixels from the top left of a `viewport` of that many
+/// pixels, starting on the near plane of `view_proj`. `None` when the cursor is outside.
+pub fn ray_from_screen(cursor: Vec2, viewport: Vec2, view_proj: Mat4) -> Option<Ray> {
+    if viewport.x <= 0.0 || viewport.y <= 0.0 {
+        return None;
+    }
+    if cursor.x > viewport.x || cursor.y > viewport.y {
+        return None;
+    }
+    let ndc = Vec2::new(
+        2.0 * cursor.x / viewport.x - 1.0,
+        1.0 - 2.0 * cursor.y / viewport.y,
+    );
+    let inverse = view_proj.inverse();
+    let near = inverse * Vec4::new(ndc.x, ndc.y, 0.0, 1.0);
+    let far = inverse * Vec4::new(ndc.x, ndc.y, 1.0, 1.0);
+    let near = near.truncate() / near.w;
+    let far = far.truncate() / far.w;
+    Some(Ray {
+        origin: near,
+        direction: (far - near).try_normalize()?,
+    })
+}
+
+/// Where `point` lands in pixels from the top left of a `viewport`, the inverse of
+/// [`ray_from_screen`]. `None` behind the camera.
+pub fn screen_from_world(point: Vec3, viewport: Vec2, view_proj: Mat4) -> Option<Vec2> {
+    let clip = view_proj * point.extend(1.0);
+    if clip.w <= f32::EPSILON {
+        return None;
+    }
+    let ndc = clip.truncate() / clip.w;
+    Some(Vec2::new(
+        (ndc.x + 1.0) * 0.5 * viewport.x,
+        (1.0 - ndc.y) * 0.5 * viewport.y,
+    ))
+}
+
+/// An area dragged out on screen, in pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Region {
+    /// The rectangle with these opposite corners.
+    Rectangle(Vec2, Vec2),
+    /// The polygon traced by the cursor, closed back to its start.
+    Lasso(Vec<Vec2>),
+}
+
+impl Region {
+    pub fn contains(&self, point: Vec2) -> bool {
+        match self {
+            Region::Rectangle(a, b) => point.cmpge(a.min(*b)).all() && point.cmple(a.max(*b)).all(),
+            Region::Lasso(points) => {
+                // Even-odd rule: count the edges a ray to the right crosses.
+                let mut inside = false;
+                for (index, a) in points.iter().enumerate() {
+                    let b = points[(index + 1) % points.len()];
+                    if (a.y > point.y) != (b.y > point.y) {
+                        let x = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                        if point.x < x {
+                            inside = !inside;
+                        }
+                    }
+                }
+                inside
+            }
+        }
+    }
+}
+
+/// Distance along `ray` to where it enters the sphere, or leaves it when the ray starts
+/// inside.
+pub fn ray_sphere(ray: &Ray, center: Vec3, radius: f32) -> Option<f32> {
+    let to_center = center - ray.origin;
+    let along = ray.direction.dot(to_center);
+    let miss_sq = to_center.length_squared() - along * along;
+    let half_chord_sq = radius * radius - miss_sq;
+    if half_chord_sq < 0.0 {
+        return None;
+    }
+    let half_chord = half_chord_sq.sqrt();
+    [along - half_chord, along + half_chord]
+        .into_iter()
+        .find(|&t| t >= 0.0)
+}
+
+/// Distance along `ray` to the side of the cylinder from `start` to `end`. The ends are open;
+/// in a model they sit inside atoms.
+pub fn ray_cylinder(ray: &Ray, start: Vec3, end: Vec3, radius: f32) -> Option<f32> {
+    let axis = end - start;
+    let length = axis.length();
+    let axis = axis.try_normalize()?;
+    // Solve in the plane perpendicular to the axis.
+    let direction = ray.direction - axis * ray.direction.dot(axis);
+    let offset = ray.origin - start;
+    let offset = offset - axis * offset.dot(axis);
+    let a = direction.length_squared();
+    if a <= f32::EPSILON {
+        return None;
+    }
+    let b = direction.dot(offset);
+    let c = offset.length_squared() - radius * radius;
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    [(-b - root) / a, (-b + root) / a]
+        .into_iter()
+        .filter(|&t| t >= 0.0)
+        .find(|&t| (0.0..=length).contains(&(ray.at(t) - start).dot(axis)))
+}
+
+/// The item with the smallest hit distance.
+fn nearest<T>(hits: impl Iterator<Item = (T, Option<f32>)>) -> Option<(T, f32)> {
+    hits.filter_map(|(item, t)| Some((item, t?)))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+/// The first atom `ray` meets, drawing each atom as a sphere of `radius(atom)`, with the
+/// distance to it.
+pub fn closest_atom(
+    molecule: &Molecule,
+    ray: &Ray,
+    radius: impl Fn(&Atom) -> f32,
+) -> Option<(AtomId, f32)> {
+    nearest(molecule.atoms_in_order().map(|atom| {
+        let center = Vec3::from_array(atom.position);
+        (atom.id, ray_sphere(ray, center, radius(atom)))
+    }))
+}
+
+/// The first bond `ray` meets, drawing bonds as cylinders of `radius`, with the distance to it.
+pub fn closest_bond(molecule: &Molecule, ray: &Ray, radius: f32) -> Option<(BondId, f32)> {
+    nearest(molecule.bonds().filter_map(|bond| {
+        let a = Vec3::from_array(molecule.get_atom(bond.a)?.position);
+        let b = Vec3::from_array(molecule.get_atom(bond.b)?.position);
+        Some((bond.id, ray_cylinder(ray, a, b, radius)))
+    }))
+}
+
+/// Index into `scene.spheres` of the first sphere `ray` meets, with the distance to it.
+pub fn closest_sphere(scene: &Scene, ray: &Ray) -> Option<(usize, f32)> {
+    nearest(scene.spheres.iter().enumerate().map(|(index, sphere)| {
+        let center = Vec3::from_array(sphere.center);
+        (index, ray_sphere(ray, center, sphere.radius))
+    }))
+}
+
+/// Index into `scene.cylinders` of the first cylinder `ray` meets, with the distance to it.
+pub fn closest_cylinder(scene: &Scene, ray: &Ray) -> Option<(usize, f32)> {
+    nearest(scene.cylinders.iter().enumerate().map(|(index, cylinder)| {
+        let (start, end) = (
+            Vec3::from_array(cylinder.start),
+            Vec3::from_array(cylinder.end),
+        );
+        (index, ray_cylinder(ray, start, end, cylinder.radius))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_center_looks_at_the_target() {
+        let view = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 10.0), Vec3::ZERO, Vec3::Y);
+        let proj = Mat4::perspective_rh(45f32.to_radians(), 2.0, 0.1, 100.0);
+        let ray = ray_from_screen(
+            Vec2::new(400.0, 200.0),
+            Vec2::new(800.0, 400.0),
+            proj * view,
+        )
+        .unwrap();
+        assert!(ray.direction.distance(-Vec3::Z) < 1e-4);
+        assert!((ray.origin.z - 9.9).abs() < 1e-3);
+        assert_eq!(
+            ray_from_screen(Vec2::new(900.0, 0.0), Vec2::new(800.0, 400.0), proj * view),
+            None
+        );
+    }
+
+    #[test]
+    fn picks_the_nearest_atom_then_bonds_between_atoms() {
+        let mut molecule = Molecule::new("chain");
+        let near = molecule.insert_atom("C".to_string(), [0.0, 0.0, 2.0]);
+        let far = molecule.insert_atom("C".to_string(), [0.0, 0.0, -2.0]);
+        let side = molecule.insert_atom("C".to_string(), [3.0, 0.0, 2.0]);
+        molecule.add_bond(near, side).unwrap();
+        let bond = molecule.add_bond(near, far).unwrap();
+
+        let down_z = |x: f32| Ray {
+            origin: Vec3::new(x, 0.0, 10.0),
+            direction: -Vec3::Z,
+        };
+        let (atom, t) = closest_atom(&molecule, &down_z(0.0), |_| 0.5).unwrap();
+        assert_eq!(atom, near);
+        assert!((t - 7.5).abs() < 1e-5);
+        assert_eq!(closest_atom(&molecule, &down_z(1.5), |_| 0.5), None);
+
+        // Across the near–side bond, missing every atom.
+        let (picked, t) = closest_bond(&molecule, &down_z(1.5), 0.2).unwrap();
+        assert_ne!(picked, bond);
+        assert!((t - 7.8).abs() < 1e-4);
+        let sideways = Ray {
+            origin: Vec3::new(-5.0, 0.0, 0.0),
+            direction: Vec3::X,
+        };
+        let (picked, t) = closest_bond(&molecule, &sideways, 0.2).unwrap();
+        assert_eq!(picked, bond);
+        assert!((t - 4.8).abs() < 1e-4);
+        // Beyond the end of the near–side bond.
+        assert_eq!(closest_bond(&molecule, &down_z(3.5), 0.2), None);
+    }
+
+    #[test]
+    fn regions_hold_projected_points() {
+        let view = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 10.0), Vec3::ZERO, Vec3::Y);
+        let proj = Mat4::perspective_rh(45f32.to_radians(), 2.0, 0.1, 100.0);
+        let viewport = Vec2::new(800.0, 400.0);
+        let center = screen_from_world(Vec3::ZERO, viewport, proj * view).unwrap();
+        assert!(center.distance(Vec2::new(400.0, 200.0)) < 1e-3);
+        let up = screen_from_world(Vec3::Y, viewport, proj * view).unwrap();
+        assert!(up.y < 200.0);
+        let ray = ray_from_screen(up, viewport, proj * view).unwrap();
+        assert!(ray.at(-ray.origin.z / ray.direction.z).distance(Vec3::Y) < 1e-3);
+        assert_eq!(
+            screen_from_world(Vec3::Z * 20.0, viewport, proj * view),
+            None
+        );
+
+        let rectangle = Region::Rectangle(Vec2::new(10.0, 50.0), Vec2::new(0.0, 0.0));
+        assert!(rectangle.contains(Vec2::new(5.0, 25.0)));
+        assert!(!rectangle.contains(Vec2::new(11.0, 25.0)));
+        // An L shape: the notch is outside.
+        let lasso = Region::Lasso(
+            [
+                (0.0, 0.0),
+                (10.0, 0.0),
+                (10.0, 4.0),
+                (4.0, 4.0),
+                (4.0, 10.0),
+                (0.0, 10.0),
+            ]
+            .map(|(x, y)| Vec2::new(x, y))
+            .to_vec(),
+        );
+        assert!(lasso.contains(Vec2::new(2.0, 8.0)));
+        assert!(lasso.contains(Vec2::new(8.0, 2.0)));
+        assert!(!lasso.contains(Vec2::new(8.0, 8.0)));
+        assert!(!Region::Lasso(Vec::new()).contains(Vec2::ZERO));
+    }
+}
@@ -0,0 +1,276 @@
+//! Cluster models for quantum chemistry: a ligand and the residues around it, cut out of a
+//! protein read from PDB.
+//!
+//! A residue is kept whole when any of its atoms lies within the radius of a ligand atom.
+//! Every bond the cut breaks, including peptide bonds that PDB files leave implicit, is capped
+//! with a hydrogen on the kept atom, pointing at the removed one at the usual X–H length.
+
+use std::collections::{HashMap, HashSet};
+
+use glam::Vec3;
+
+use crate::formats::pdb::WATER_RESIDUES;
+use crate::hydrogens::bond_length;
+use crate::residues::{residues, Residue, ResidueId, AMINO_ACIDS, RESIDUE_PROPERTIES};
+use crate::spatial::SpatialGrid;
+use crate::{AtomId, Molecule};
+
+/// Default distance from the ligand within which residues are kept, in Å.
+pub const DEFAULT_RADIUS: f32 = 5.0;
+/// `atom_name` of capping hydrogens.
+pub const CAP_NAME: &str = "HL";
+/// Longest backbone C–N distance between two residues read as a peptide bond, in Å.
+const PEPTIDE_CUTOFF: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PocketOptions {
+    pub radius: f32,
+    /// Keep waters near the ligand; otherwise they are left out like distant residues.
+    pub keep_waters: bool,
+}
+
+impl Default for PocketOptions {
+    fn default() -> Self {
+        Self {
+            radius: DEFAULT_RADIUS,
+            keep_waters: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pocket {
+    /// The ligand, the kept residues and the caps, without a unit cell.
+    pub molecule: Molecule,
+    /// Kept residues other than the ligand, in atom order.
+    pub residues: Vec<ResidueId>,
+    pub caps: usize,
+}
+
+fn is_water(name: &str) -> bool {
+    WATER_RESIDUES.contains(&name)
+}
+
+/// Residues that are neither standard amino acids nor waters, usually the HETATM ligands.
+pub fn ligands(molecule: &Molecule) -> Vec<Residue> {
+    residues(molecule)
+        .into_iter()
+        .filter(|residue| !AMINO_ACIDS.contains(&residue.name.as_str()) && !is_water(&residue.name))
+        .collect()
+}
+
+fn position(molecule: &Molecule, atom: AtomId) -> Vec3 {
+    molecule
+        .get_atom(atom)
+        .map_or(Vec3::ZERO, |atom| Vec3::from_array(atom.position))
+}
+
+/// Cuts the cluster model around `ligand` out of `molecule`.
+pub fn carve_pocket(
+    molecule: &Molecule,
+    ligand: &ResidueId,
+    options: &PocketOptions,
+) -> Result<Pocket, String> {
+    let all = residues(molecule);
+    let ligand_positions: Vec<Vec3> = all
+        .iter()
+        .find(|residue| residue.id == *ligand)
+        .ok_or_else(|| format!("no residue {}{}", ligand.chain, ligand.number))?
+        .atoms
+        .iter()
+        .map(|&atom| position(molecule, atom))
+        .collect();
+    let grid = SpatialGrid::new(&ligand_positions, options.radius);
+    let radius_sq = options.radius * options.radius;
+    let near = |atom: AtomId| {
+        let point = position(molecule, atom);
+        grid.candidates(point)
+            .any(|index| ligand_positions[index].distance_squared(point) <= radius_sq)
+    };
+
+    let mut kept = Vec::new();
+    let mut kept_residues = Vec::new();
+    for residue in &all {
+        let is_ligand = residue.id == *ligand;
+        if is_ligand
+            || (options.keep_waters || !is_water(&residue.name))
+                && residue.atoms.iter().any(|&atom| near(atom))
+        {
+            kept.extend(&residue.atoms);
+            if !is_ligand {
+                kept_residues.push(residue.id.clone());
+            }
+        }
+    }
+    let inside: HashSet<AtomId> = kept.iter().copied().collect();
+
+    // Broken bonds as (kept atom, removed atom).
+    let mut cuts = Vec::new();
+    let mut seen = HashSet::new();
+    let bonds = molecule.bonds().map(|bond| (bond.a, bond.b));
+    for (a, b) in bonds.chain(peptide_bonds(molecule, &all)) {
+        let cut = match (inside.contains(&a), inside.contains(&b)) {
+            (true, false) => (a, b),
+            (false, true) => (b, a),
+            _ => continue,
+        };
+        if seen.insert((a.min(b), a.max(b))) {
+            cuts.push(cut);
+        }
+    }
+
+    let mut pocket = molecule.extract(&kept);
+    pocket.name = format!(
+        "{} pocket around {}{}",
+        molecule.name, ligand.chain, ligand.number
+    );
+    pocket.cell = None;
+    let mapping: HashMap<AtomId, AtomId> = molecule
+        .atoms_in_order()
+        .filter(|atom| inside.contains(&atom.id))
+        .map(|atom| atom.id)
+        .zip(pocket.atoms_in_order().map(|atom| atom.id))
+        .collect();
+    for &(kept_atom, removed) in &cuts {
+        let from = position(molecule, kept_atom);
+        let direction = (position(molecule, removed) - from).normalize_or_zero();
+        let element = molecule
+            .get_atom(kept_atom)
+            .map_or("C", |atom| atom.element.as_str());
+        let cap = pocket.insert_atom(
+            "H".to_string(),
+            (from + direction * bond_length(element)).to_array(),
+        );
+        pocket.add_bond(mapping[&kept_atom], cap)?;
+        for name in RESIDUE_PROPERTIES {
+            if let Some(value) = molecule.properties.get(name, kept_atom) {
+                pocket.properties.set(name, cap, value);
+            }
+        }
+        pocket.properties.set("atom_name", cap, CAP_NAME);
+    }
+    Ok(Pocket {
+        molecule: pocket,
+        residues: kept_residues,
+        caps: cuts.len(),
+    })
+}
+
+/// Backbone C–N pairs of neighbouring amino acids, found by distance.
+fn peptide_bonds(molecule: &Molecule, residues: &[Residue]) -> Vec<(AtomId, AtomId)> {
+    let named = |residue: &Residue, name: &str| {
+        residue
+            .atoms
+            .iter()
+            .copied()
+            .find(|&atom| molecule.properties.get("atom_name", atom) == Some(name))
+    };
+    let amino_acids: Vec<&Residue> = residues
+        .iter()
+        .filter(|residue| AMINO_ACIDS.contains(&residue.name.as_str()))
+        .collect();
+    let nitrogens: Vec<AtomId> = amino_acids
+        .iter()
+        .filter_map(|residue| named(residue, "N"))
+        .collect();
+    let points: Vec<Vec3> = nitrogens
+        .iter()
+        .map(|&atom| position(molecule, atom))
+        .collect();
+    let grid = SpatialGrid::new(&points, PEPTIDE_CUTOFF);
+    let mut bonds = Vec::new();
+    for residue in &amino_acids {
+        let Some(carbon) = named(residue, "C") else {
+            continue;
+        };
+        let point = position(molecule, carbon);
+        for index in grid.candidates(point) {
+            let nitrogen = nitrogens[index];
+            if !residue.atoms.contains(&nitrogen) && points[index].distance(point) <= PEPTIDE_CUTOFF
+            {
+                bonds.push((carbon, nitrogen));
+            }
+        }
+    }
+    bonds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three glycine backbones along x with a one-atom ligand above the middle one.
+    fn tripeptide_with_ligand() -> Molecule {
+        let mut molecule = Molecule::new("tripeptide");
+        let atoms = [
+            ("N", "N", 0.0, 0.0),
+            ("CA", "C", 1.45, 0.0),
+            ("C", "C", 2.47, 0.0),
+            ("O", "O", 2.47, 1.23),
+        ];
+        for number in 1..=3 {
+            let offset = 3.8 * (number - 1) as f32;
+            for (name, element, x, y) in atoms {
+                let id = molecule.insert_atom(element.to_string(), [offset + x, y, 0.0]);
+                molecule.properties.set("atom_name", id, name);
+                molecule.properties.set("residue", id, "GLY");
+                molecule
+                    .properties
+                    .set("residue_number", id, number.to_string());
+                molecule.properties.set("chain", id, "A");
+            }
+        }
+        for (residue, number) in [("LIG", "101"), ("HOH", "201")] {
+            let id = molecule.insert_atom("Zn".to_string(), [5.25, 0.0, 2.5]);
+            molecule.properties.set("atom_name", id, "ZN");
+            molecule.properties.set("residue", id, residue);
+            molecule.properties.set("residue_number", id, number);
+            molecule.properties.set("chain", id, "A");
+        }
+        molecule
+    }
+
+    #[test]
+    fn keeps_near_residues_and_caps_the_cut_peptide_bonds() {
+        let molecule = tripeptide_with_ligand();
+        let ligand = ResidueId {
+            chain: "A".to_string(),
+            number: "101".to_string(),
+        };
+        assert_eq!(ligands(&molecule).len(), 1);
+        let options = PocketOptions {
+            radius: 3.0,
+            ..PocketOptions::default()
+        };
+        let pocket = carve_pocket(&molecule, &ligand, &options).unwrap();
+        assert_eq!(pocket.residues.len(), 1);
+        assert_eq!(pocket.residues[0].number, "2");
+        assert_eq!(pocket.caps, 2);
+        // Four backbone atoms, the ligand and two caps.
+        assert_eq!(pocket.molecule.atom_count(), 7);
+        let caps: Vec<_> = pocket
+            .molecule
+            .atoms_in_order()
+            .filter(|atom| pocket.molecule.properties.get("atom_name", atom.id) == Some(CAP_NAME))
+            .map(|atom| atom.position)
+            .collect();
+        // N–H toward the previous C, C–H toward the next N.
+        assert!((caps[0][0] - (3.8 - 1.01)).abs() < 1e-4);
+        assert!((caps[1][0] - (6.27 + 1.09)).abs() < 1e-4);
+
+        let wet = PocketOptions {
+            keep_waters: true,
+            ..options
+        };
+        let pocket = carve_pocket(&molecule, &ligand, &wet).unwrap();
+        assert_eq!(pocket.residues.len(), 2);
+        let missing = ResidueId {
+            chain: "B".to_string(),
+            number: "1".to_string(),
+        };
+        assert_eq!(
+            carve_pocket(&molecule, &missing, &options).unwrap_err(),
+            "no residue B1"
+        );
+    }
+}
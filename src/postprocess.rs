@@ -0,0 +1,274 @@
+//! CPU post-processing for final screenshots: supersample resolve, screen-space soft shadows
+//! and depth of field. Interactive frames skip all of this.
+
+use std::f32::consts::TAU;
+
+/// Golden-angle spiral sample count for the disk kernels.
+const DISK_SAMPLES: usize = 48;
+/// Depth differences beyond this many Å don't cast shadows, so silhouettes against distant
+/// atoms stay clean.
+const SHADOW_RANGE: f32 = 2.0;
+const SHADOW_BIAS: f32 = 0.05;
+
+/// An RGBA8 frame with a linear eye-space depth per pixel (`f32::INFINITY` for background).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    pub depth: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PresentationSettings {
+    /// Render resolution multiplier resolved by box filtering; 1 disables supersampling.
+    pub supersample: u32,
+    /// Largest blur radius in output pixels; 0 disables depth of field.
+    pub aperture: f32,
+    /// Eye distance, in Å, that stays sharp.
+    pub focus_distance: f32,
+    /// Darkening of fully occluded creases, 0 to 1.
+    pub shadow_strength: f32,
+    /// Shadow search radius in output pixels.
+    pub shadow_radius: f32,
+}
+
+impl Default for PresentationSettings {
+    fn default() -> Self {
+        Self {
+            supersample: 2,
+            aperture: 6.0,
+            focus_distance: 8.0,
+            shadow_strength: 0.6,
+            shadow_radius: 12.0,
+        }
+    }
+}
+
+/// Converts a perspective depth-buffer value in 0..=1 back to eye distance.
+pub fn linearize_depth(depth: f32, near: f32, far: f32) -> f32 {
+    if depth >= 1.0 {
+        return f32::INFINITY;
+    }
+    near * far / (far - depth * (far - near))
+}
+
+/// Shadows at render resolution, then the supersample resolve, then depth of field at the
+/// output size.
+pub fn present(frame: &Frame, settings: &PresentationSettings) -> Frame {
+    let scale = settings.supersample.max(1);
+    let mut frame = frame.clone();
+    if settings.shadow_strength > 0.0 {
+        soft_shadows(
+            &mut frame,
+            settings.shadow_radius * scale as f32,
+            settings.shadow_strength,
+        );
+    }
+    let mut frame = downsample(&frame, scale);
+    if settings.aperture > 0.0 {
+        frame = depth_of_field(&frame, settings.focus_distance, settings.aperture);
+    }
+    frame
+}
+
+/// Box-filters `factor`×`factor` blocks; depth keeps the nearest sample of each block.
+pub fn downsample(frame: &Frame, factor: u32) -> Frame {
+    if factor <= 1 {
+        return frame.clone();
+    }
+    let (width, height) = (frame.width / factor, frame.height / factor);
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    let mut depth = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = [0u32; 4];
+            let mut nearest = f32::INFINITY;
+            for dy in 0..factor {
+                for dx in 0..factor {
+                    let index = ((y * factor + dy) * frame.width + x * factor + dx) as usize;
+                    for (channel, total) in sum.iter_mut().enumerate() {
+                        *total += u32::from(frame.pixels[index * 4 + channel]);
+                    }
+                    nearest = nearest.min(frame.depth[index]);
+                }
+            }
+            let count = factor * factor;
+            pixels.extend(sum.map(|total| ((total + count / 2) / count) as u8));
+            depth.push(nearest);
+        }
+    }
+    Frame {
+        width,
+        height,
+        pixels,
+        depth,
+    }
+}
+
+/// Screen-space ambient occlusion: a pixel darkens in proportion to how many nearby samples
+/// sit in front of it within [`SHADOW_RANGE`], which shades creases and contacts between
+/// atoms softly.
+pub fn soft_shadows(frame: &mut Frame, radius: f32, strength: f32) {
+    let kernel = disk_kernel();
+    let original = frame.depth.clone();
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let index = (y * frame.width + x) as usize;
+            let depth = original[index];
+            if !depth.is_finite() {
+                continue;
+            }
+            let mut occluded = 0;
+            for [dx, dy] in &kernel {
+                let Some(sample) = sample_at(frame, &original, x, y, dx * radius, dy * radius)
+                else {
+                    continue;
+                };
+                let difference = depth - sample;
+                if difference > SHADOW_BIAS && difference < SHADOW_RANGE {
+                    occluded += 1;
+                }
+            }
+            let shade = 1.0 - strength.clamp(0.0, 1.0) * occluded as f32 / kernel.len() as f32;
+            for channel in &mut frame.pixels[index * 4..index * 4 + 3] {
+                *channel = (f32::from(*channel) * shade).round() as u8;
+            }
+        }
+    }
+}
+
+/// Gather-based depth of field. Each pixel's circle of confusion grows with its distance
+/// from the focal plane up to `aperture`; a neighbor only contributes when its own circle
+/// reaches back to the pixel, which keeps sharp foreground edges from bleeding.
+pub fn depth_of_field(frame: &Frame, focus_distance: f32, aperture: f32) -> Frame {
+    let kernel = disk_kernel();
+    let focus = focus_distance.max(f32::EPSILON);
+    let coc: Vec<f32> = frame
+        .depth
+        .iter()
+        .map(|depth| {
+            if depth.is_finite() {
+                (aperture * (depth - focus).abs() / depth.max(f32::EPSILON)).min(aperture)
+            } else {
+                aperture
+            }
+        })
+        .collect();
+    let mut pixels = frame.pixels.clone();
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let index = (y * frame.width + x) as usize;
+            let radius = coc[index];
+            if radius < 0.5 {
+                continue;
+            }
+            let mut sum = [0.0f32; 3];
+            let mut weight_sum = 0.0;
+            let mut add = |at: usize, weight: f32| {
+                for (channel, total) in sum.iter_mut().enumerate() {
+                    *total += f32::from(frame.pixels[at * 4 + channel]) * weight;
+                }
+                weight_sum += weight;
+            };
+            add(index, 1.0);
+            for [dx, dy] in &kernel {
+                let (ox, oy) = (dx * radius, dy * radius);
+                let Some(sample) = offset_index(frame, x, y, ox, oy) else {
+                    continue;
+                };
+                let distance = (ox * ox + oy * oy).sqrt();
+                let weight = (coc[sample] - distance + 1.0).clamp(0.0, 1.0);
+                if weight > 0.0 {
+                    add(sample, weight);
+                }
+            }
+            for (channel, total) in sum.iter().enumerate() {
+                pixels[index * 4 + channel] = (total / weight_sum).round() as u8;
+            }
+        }
+    }
+    Frame {
+        pixels,
+        ..frame.clone()
+    }
+}
+
+/// Unit-disk offsets on a golden-angle spiral.
+fn disk_kernel() -> Vec<[f32; 2]> {
+    let golden_angle = TAU * (1.0 - 1.0 / 1.618_034);
+    (0..DISK_SAMPLES)
+        .map(|i| {
+            let radius = ((i as f32 + 0.5) / DISK_SAMPLES as f32).sqrt();
+            let (sin, cos) = (i as f32 * golden_angle).sin_cos();
+            [radius * cos, radius * sin]
+        })
+        .collect()
+}
+
+fn offset_index(frame: &Frame, x: u32, y: u32, dx: f32, dy: f32) -> Option<usize> {
+    let sx = (x as f32 + dx).round();
+    let sy = (y as f32 + dy).round();
+    if sx < 0.0 || sy < 0.0 || sx >= frame.width as f32 || sy >= frame.height as f32 {
+        return None;
+    }
+    Some(sy as usize * frame.width as usize + sx as usize)
+}
+
+fn sample_at(frame: &Frame, depth: &[f32], x: u32, y: u32, dx: f32, dy: f32) -> Option<f32> {
+    offset_index(frame, x, y, dx, dy).map(|index| depth[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u32, height: u32, color: impl Fn(u32, u32) -> u8, depth: f32) -> Frame {
+        let mut pixels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let value = color(x, y);
+                pixels.extend([value, value, value, 255]);
+            }
+        }
+        Frame {
+            width,
+            height,
+            pixels,
+            depth: vec![depth; (width * height) as usize],
+        }
+    }
+
+    #[test]
+    fn downsample_averages_blocks() {
+        let mut input = frame(4, 2, |x, _| if x % 2 == 0 { 0 } else { 200 }, 5.0);
+        input.depth[1] = 3.0;
+        let output = downsample(&input, 2);
+        assert_eq!((output.width, output.height), (2, 1));
+        assert_eq!(&output.pixels[..4], &[100, 100, 100, 255]);
+        assert_eq!(output.depth, [3.0, 5.0]);
+        assert!(linearize_depth(1.0, 0.1, 200.0).is_infinite());
+        assert!((linearize_depth(0.0, 0.1, 200.0) - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn focus_plane_stays_sharp_and_creases_darken() {
+        let stripes = frame(16, 16, |x, _| if x < 8 { 0 } else { 255 }, 8.0);
+        assert_eq!(depth_of_field(&stripes, 8.0, 4.0), stripes);
+        let blurred = depth_of_field(&stripes, 2.0, 4.0);
+        let edge = (8 * 16 + 7) * 4;
+        assert!(blurred.pixels[edge] > 0 && blurred.pixels[edge] < 255);
+
+        let mut step = frame(16, 16, |_, _| 200, 10.0);
+        for y in 0..16 {
+            for x in 0..8 {
+                step.depth[y * 16 + x] = 9.0;
+            }
+        }
+        let mut shaded = step.clone();
+        soft_shadows(&mut shaded, 4.0, 1.0);
+        // The far side of the step is occluded by the near side; the near side is not.
+        assert!(shaded.pixels[(8 * 16 + 8) * 4] < 200);
+        assert_eq!(shaded.pixels[(8 * 16 + 2) * 4], 200);
+    }
+}
@@ -0,0 +1,59 @@
+//! Named per-atom values carried with a structure, such as partial charges from an SD file.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::AtomId;
+
+/// Text values by property name and atom. Entries of removed atoms are kept, so undoing the
+/// removal brings them back; atom ids are never reused.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AtomProperties {
+    columns: BTreeMap<String, BTreeMap<AtomId, String>>,
+}
+
+impl AtomProperties {
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    pub fn set(&mut self, name: &str, atom: AtomId, value: impl Into<String>) {
+        self.columns
+            .entry(name.to_string())
+            .or_default()
+            .insert(atom, value.into());
+    }
+
+    pub fn get(&self, name: &str, atom: AtomId) -> Option<&str> {
+        self.columns.get(name)?.get(&atom).map(String::as_str)
+    }
+
+    /// Property names in sorted order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.columns.keys().map(String::as_str)
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.columns.remove(name);
+    }
+
+    /// Clears one atom's value, returning it; a property left without values goes away.
+    pub fn unset(&mut self, name: &str, atom: AtomId) -> Option<String> {
+        let column = self.columns.get_mut(name)?;
+        let value = column.remove(&atom);
+        if column.is_empty() {
+            self.columns.remove(name);
+        }
+        value
+    }
+
+    /// Moves every value to the atom `mapping` sends it to, dropping unmapped atoms.
+    pub(crate) fn remap(&mut self, mapping: &HashMap<AtomId, AtomId>) {
+        for column in self.columns.values_mut() {
+            *column = std::mem::take(column)
+                .into_iter()
+                .filter_map(|(atom, value)| Some((*mapping.get(&atom)?, value)))
+                .collect();
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Human-readable history of how a structure was prepared: who did what and when, which
+//! files it came from and the parameters of automated steps.
+
+use std::fmt::Write as _;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{BondKind, Command, CoordinateLock};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProvenanceEntry {
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    pub user: String,
+    pub action: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProvenanceLog {
+    pub entries: Vec<ProvenanceEntry>,
+}
+
+impl ProvenanceLog {
+    /// Appends `action` stamped with the current time and the login name from `USER` or
+    /// `USERNAME`.
+    pub fn record(&mut self, action: impl Into<String>) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        self.entries.push(ProvenanceEntry {
+            time,
+            user,
+            action: action.into(),
+        });
+    }
+
+    /// Records an applied command. Successive moves of one atom, or of one group of atoms,
+    /// merge into a single history step, so they update the previous entry instead of adding
+    /// one each.
+    pub fn record_command(&mut self, command: &Command) {
+        let action = describe(command);
+        let start = |action: &str| action.split(" to ").next().map(str::to_string);
+        let moves = match command {
+            Command::MoveAtom { .. } => true,
+            Command::Composite { commands } => commands
+                .iter()
+                .all(|command| matches!(command, Command::MoveAtom { .. })),
+            _ => false,
+        };
+        let continues = moves
+            && self
+                .entries
+                .last()
+                .is_some_and(|last| start(&last.action) == start(&action));
+        if continues {
+            self.entries.pop();
+        }
+        self.record(action);
+    }
+
+    /// One line per entry: UTC timestamp, user, action.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for entry in &self.entries {
+            let _ = writeln!(
+                out,
+                "{}  {}  {}",
+                format_utc(entry.time),
+                entry.user,
+                entry.action
+            );
+        }
+        out
+    }
+}
+
+/// What a command does, for the log. Composite steps list how many of each change they made.
+pub fn describe(command: &Command) -> String {
+    match command {
+        Command::InsertAtom {
+            element,
+            position: [x, y, z],
+            atom_id,
+            ..
+        } => format!(
+            "insert {element} atom{} at {}",
+            id_suffix(atom_id.map(|id| id.value())),
+            point([*x, *y, *z])
+        ),
+        Command::DeleteAtom { atom_id, removed } => {
+            let element = removed.as_ref().map_or(String::new(), |removed| {
+                format!("{} ", removed.atom.element)
+            });
+            format!("delete {element}atom {}", atom_id.value())
+        }
+        Command::AddBond {
+            atom_a,
+            atom_b,
+            bond_id,
+        } => format!(
+            "add bond{} between atoms {} and {}",
+            id_suffix(bond_id.map(|id| id.value())),
+            atom_a.value(),
+            atom_b.value()
+        ),
+        Command::RemoveBond { bond_id, .. } => format!("remove bond {}", bond_id.value()),
+        Command::AddCoordinationBond {
+            atom_a,
+            atom_b,
+            bond_id,
+        } => format!(
+            "add coordination bond{} between atoms {} and {}",
+            id_suffix(bond_id.map(|id| id.value())),
+            atom_a.value(),
+            atom_b.value()
+        ),
+        Command::SetBondKind { bond_id, kind, .. } => {
+            let kind = match kind {
+                BondKind::Covalent => "covalent",
+                BondKind::Coordination => "coordination",
+            };
+            format!("make bond {} {kind}", bond_id.value())
+        }
+        Command::SetBondOrder { bond_id, order, .. } => {
+            format!("set bond {} order to {order}", bond_id.value())
+        }
+        Command::MoveAtom { atom_id, from, to } => format!(
+            "move atom {} from {} to {}",
+            atom_id.value(),
+            point(*from),
+            point(*to)
+        ),
+        Command::TransformAtoms { ids, .. } => format!("transform {} atoms", ids.len()),
+        Command::SetProperty {
+            atom_id,
+            name,
+            value,
+            ..
+        } => match value {
+            Some(value) => format!("set {name} of atom {} to {value}", atom_id.value()),
+            None => format!("clear {name} of atom {}", atom_id.value()),
+        },
+        Command::Merge { fragment, .. } => format!(
+            "paste fragment {} ({} atoms)",
+            fragment.name,
+            fragment.atom_count()
+        ),
+        Command::SetCell { cell, lock, .. } => {
+            let lock = match lock {
+                CoordinateLock::Cartesian => "Cartesian",
+                CoordinateLock::Fractional => "fractional",
+            };
+            match cell {
+                Some(cell) => format!(
+                    "set cell a={} b={} c={} alpha={} beta={} gamma={} ({lock} coordinates kept)",
+                    cell.a, cell.b, cell.c, cell.alpha, cell.beta, cell.gamma
+                ),
+                None => "remove cell".to_string(),
+            }
+        }
+        Command::Restore { snapshot, .. } => format!("restore {}", snapshot.name),
+        Command::Composite { commands } => {
+            let mut counts: Vec<(&str, usize)> = Vec::new();
+            for command in commands {
+                let kind = match command {
+                    Command::InsertAtom { .. } => "inserted atoms",
+                    Command::DeleteAtom { .. } => "deleted atoms",
+                    Command::AddBond { .. } | Command::AddCoordinationBond { .. } => "added bonds",
+                    Command::RemoveBond { .. } => "removed bonds",
+                    Command::MoveAtom { .. } => "moved atoms",
+                    Command::TransformAtoms { .. } => "transformed atom sets",
+                    Command::SetProperty { .. } => "property changes",
+                    _ => "other steps",
+                };
+                match counts.iter_mut().find(|(known, _)| *known == kind) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((kind, 1)),
+                }
+            }
+            let parts: Vec<String> = counts
+                .iter()
+                .map(|(kind, count)| format!("{count} {kind}"))
+                .collect();
+            format!("batch edit: {}", parts.join(", "))
+        }
+    }
+}
+
+fn point([x, y, z]: [f32; 3]) -> String {
+    format!("({x:.3}, {y:.3}, {z:.3})")
+}
+
+fn id_suffix(id: Option<u64>) -> String {
+    id.map_or(String::new(), |id| format!(" {id}"))
+}
+
+/// `YYYY-MM-DDTHH:MM:SSZ` for `seconds` since the Unix epoch.
+pub fn format_utc(seconds: u64) -> String {
+    let days = (seconds / 86_400) as i64;
+    let of_day = seconds % 86_400;
+    // Civil-from-days over 400-year eras, with years starting in March.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        of_day / 3600,
+        of_day / 60 % 60,
+        of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AtomId, Molecule};
+
+    #[test]
+    fn formats_entries_and_commands() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc(951_827_696), "2000-02-29T12:34:56Z");
+        assert_eq!(format_utc(1_792_022_400), "2026-10-15T00:00:00Z");
+
+        let mut molecule = Molecule::new("ethane");
+        let a = molecule.insert_atom("C".into(), [0.0; 3]);
+        let b = molecule.insert_atom("C".into(), [1.54, 0.0, 0.0]);
+        molecule.add_bond(a, b).unwrap();
+        let mut hydrogens = Command::add_hydrogens(&molecule);
+        hydrogens.apply(&mut molecule).unwrap();
+        assert_eq!(
+            describe(&hydrogens),
+            "batch edit: 6 inserted atoms, 6 added bonds"
+        );
+        let mut log = ProvenanceLog::default();
+        let step = |to| Command::MoveAtom {
+            atom_id: AtomId::new(2),
+            from: [0.0; 3],
+            to,
+        };
+        log.record_command(&step([0.5, 0.0, 0.0]));
+        log.record_command(&step([1.0, 2.5, -0.25]));
+        assert_eq!(log.entries.len(), 1);
+        assert_eq!(
+            log.entries[0].action,
+            "move atom 2 from (0.000, 0.000, 0.000) to (1.000, 2.500, -0.250)"
+        );
+
+        let log = ProvenanceLog {
+            entries: vec![ProvenanceEntry {
+                time: 0,
+                user: "alice".into(),
+                action: "open water.xyz".into(),
+            }],
+        };
+        assert_eq!(
+            log.to_text(),
+            "1970-01-01T00:00:00Z  alice  open water.xyz\n"
+        );
+    }
+}
@@ -0,0 +1,484 @@
+//! A small SMARTS-like query language layered over the substructure matcher.
+//!
+//! Atoms are element symbols (`C`, `Cl`), `*` for any atom, or bracket expressions such as
+//! `[C,N;R]`, `[O-]` or `[#7+]`. Inside brackets `!` negates, `&` or plain juxtaposition
+//! means "and", `,` means "or" and `;` is a low-precedence "and". Primitives are element
+//! symbols, `#n` atomic numbers, `+`/`-` charges (`+2`, `--`), `R` (in a ring) and `R0`
+//! (not in a ring); a trailing `:n` marks the atom for selection. Bonds are `-` (single,
+//! the default), `=`, `#` and `~` (any order). Branches use parentheses and ring closures
+//! use the digits 1-9.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::elements::{atomic_number, normalize_symbol};
+use crate::substructure::{find_matches, AtomMapping};
+use crate::{Atom, AtomId, BondId, Molecule};
+
+#[derive(Debug, Clone, PartialEq)]
+enum AtomExpr {
+    Any,
+    Element(String),
+    AtomicNumber(u8),
+    Charge(i32),
+    InRing(bool),
+    Not(Box<AtomExpr>),
+    And(Vec<AtomExpr>),
+    Or(Vec<AtomExpr>),
+}
+
+impl AtomExpr {
+    fn matches(&self, atom: &Atom, in_ring: bool) -> bool {
+        match self {
+            AtomExpr::Any => true,
+            AtomExpr::Element(symbol) => normalize_symbol(&atom.element) == *symbol,
+            AtomExpr::AtomicNumber(number) => atomic_number(&atom.element) == Some(*number),
+            AtomExpr::Charge(charge) => atom.charge == *charge,
+            AtomExpr::InRing(expected) => in_ring == *expected,
+            AtomExpr::Not(inner) => !inner.matches(atom, in_ring),
+            AtomExpr::And(terms) => terms.iter().all(|term| term.matches(atom, in_ring)),
+            AtomExpr::Or(terms) => terms.iter().any(|term| term.matches(atom, in_ring)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BondExpr {
+    Any,
+    Order(u8),
+}
+
+/// A parsed query: a pattern graph plus the atom and bond tests attached to it.
+#[derive(Debug, Clone)]
+pub struct Query {
+    pattern: Molecule,
+    atoms: HashMap<AtomId, AtomExpr>,
+    bonds: HashMap<BondId, BondExpr>,
+    marked: Vec<AtomId>,
+}
+
+impl Query {
+    /// Up to `limit` embeddings, keyed by query atom in the order they were written.
+    pub fn find(&self, target: &Molecule, limit: usize) -> Vec<AtomMapping> {
+        let ring_atoms = target.ring_atoms();
+        find_matches(
+            &self.pattern,
+            target,
+            limit,
+            |query, atom| {
+                self.atoms
+                    .get(&query.id)
+                    .is_some_and(|expr| expr.matches(atom, ring_atoms.contains(&atom.id)))
+            },
+            |query, bond| match self.bonds.get(&query.id) {
+                Some(BondExpr::Any) => true,
+                Some(BondExpr::Order(order)) => bond.order == *order,
+                None => false,
+            },
+        )
+    }
+
+    /// Target atoms one embedding selects: those matched by `:n`-marked query atoms, or every
+    /// matched atom when nothing is marked. Sorted.
+    pub fn selection(&self, mapping: &AtomMapping) -> Vec<AtomId> {
+        let mut atoms: Vec<AtomId> = mapping
+            .iter()
+            .filter(|(query, _)| self.marked.is_empty() || self.marked.contains(query))
+            .map(|(_, atom)| *atom)
+            .collect();
+        atoms.sort();
+        atoms
+    }
+
+    /// Union of `selection` over up to `limit` embeddings, sorted.
+    pub fn select(&self, target: &Molecule, limit: usize) -> Vec<AtomId> {
+        let selected: HashSet<AtomId> = self
+            .find(target, limit)
+            .iter()
+            .flat_map(|mapping| self.selection(mapping))
+            .collect();
+        let mut selected: Vec<AtomId> = selected.into_iter().collect();
+        selected.sort();
+        selected
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryError {
+    details: String,
+}
+
+impl QueryError {
+    fn new(position: usize, details: impl Into<String>) -> Self {
+        Self {
+            details: format!("column {}: {}", position + 1, details.into()),
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+pub fn parse_query(text: &str) -> Result<Query, QueryError> {
+    let mut parser = Parser {
+        chars: text.trim().chars().collect(),
+        position: 0,
+        rings: HashMap::new(),
+        query: Query {
+            pattern: Molecule::new("query"),
+            atoms: HashMap::new(),
+            bonds: HashMap::new(),
+            marked: Vec::new(),
+        },
+    };
+    parser.parse_chain(None)?;
+    if let Some(c) = parser.peek() {
+        return Err(parser.error(format!("unexpected '{c}'")));
+    }
+    if let Some(digit) = parser.rings.keys().min() {
+        return Err(parser.error(format!("unclosed ring {digit}")));
+    }
+    if parser.query.pattern.atom_count() == 0 {
+        return Err(QueryError::new(0, "empty query"));
+    }
+    Ok(parser.query)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    /// Open ring closures by digit: the opening atom and any bond written before the digit.
+    rings: HashMap<char, (AtomId, Option<BondExpr>)>,
+    query: Query,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        let found = self.peek() == Some(expected);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn error(&self, details: impl Into<String>) -> QueryError {
+        QueryError::new(self.position, details)
+    }
+
+    /// Atoms, bonds, branches and ring closures until `)` or the end; `previous` is the atom a
+    /// branch hangs off.
+    fn parse_chain(&mut self, mut previous: Option<AtomId>) -> Result<(), QueryError> {
+        let mut pending_bond = None;
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            match c {
+                ')' => break,
+                '-' | '=' | '#' | '~' => {
+                    if pending_bond.is_some() {
+                        return Err(self.error("two bonds in a row"));
+                    }
+                    self.position += 1;
+                    pending_bond = Some(match c {
+                        '-' => BondExpr::Order(1),
+                        '=' => BondExpr::Order(2),
+                        '#' => BondExpr::Order(3),
+                        _ => BondExpr::Any,
+                    });
+                }
+                '(' => {
+                    let Some(anchor) = previous.filter(|_| pending_bond.is_none()) else {
+                        return Err(self.error("branch without a preceding atom"));
+                    };
+                    self.position += 1;
+                    self.parse_chain(Some(anchor))?;
+                    if !self.eat(')') {
+                        return Err(self.error("missing ')'"));
+                    }
+                }
+                '1'..='9' => {
+                    let Some(atom) = previous else {
+                        return Err(self.error("ring closure without a preceding atom"));
+                    };
+                    self.position += 1;
+                    match self.rings.remove(&c) {
+                        Some((open, open_bond)) => {
+                            let bond = pending_bond.take().or(open_bond);
+                            self.connect(open, atom, bond)?;
+                        }
+                        None => {
+                            self.rings.insert(c, (atom, pending_bond.take()));
+                        }
+                    }
+                }
+                _ => {
+                    let (expr, marked) = self.parse_atom()?;
+                    let id = self.query.pattern.insert_atom("*".into(), [0.0; 3]);
+                    self.query.atoms.insert(id, expr);
+                    if marked {
+                        self.query.marked.push(id);
+                    }
+                    if let Some(previous) = previous {
+                        self.connect(previous, id, pending_bond.take())?;
+                    } else if pending_bond.is_some() {
+                        return Err(self.error("bond without a preceding atom"));
+                    }
+                    previous = Some(id);
+                }
+            }
+        }
+        if pending_bond.is_some() {
+            return Err(self.error("dangling bond"));
+        }
+        if self.position == start {
+            return Err(self.error("expected an atom"));
+        }
+        Ok(())
+    }
+
+    fn connect(&mut self, a: AtomId, b: AtomId, bond: Option<BondExpr>) -> Result<(), QueryError> {
+        if a == b || self.query.pattern.bond_between(a, b).is_some() {
+            return Err(self.error("atoms bonded twice"));
+        }
+        let position = self.position;
+        let id = self
+            .query
+            .pattern
+            .add_bond(a, b)
+            .map_err(|err| QueryError::new(position, err))?;
+        self.query
+            .bonds
+            .insert(id, bond.unwrap_or(BondExpr::Order(1)));
+        Ok(())
+    }
+
+    /// The atom test and whether it carries a `:n` selection mark.
+    fn parse_atom(&mut self) -> Result<(AtomExpr, bool), QueryError> {
+        if self.eat('*') {
+            return Ok((AtomExpr::Any, false));
+        }
+        if !self.eat('[') {
+            return Ok((AtomExpr::Element(self.parse_symbol()?), false));
+        }
+        let expr = self.parse_low_and()?;
+        let marked = self.eat(':');
+        if marked {
+            self.parse_number()?;
+        }
+        if !self.eat(']') {
+            return Err(self.error("missing ']'"));
+        }
+        Ok((expr, marked))
+    }
+
+    fn parse_low_and(&mut self) -> Result<AtomExpr, QueryError> {
+        let mut terms = vec![self.parse_or()?];
+        while self.eat(';') {
+            terms.push(self.parse_or()?);
+        }
+        Ok(combine(terms, AtomExpr::And))
+    }
+
+    fn parse_or(&mut self) -> Result<AtomExpr, QueryError> {
+        let mut terms = vec![self.parse_high_and()?];
+        while self.eat(',') {
+            terms.push(self.parse_high_and()?);
+        }
+        Ok(combine(terms, AtomExpr::Or))
+    }
+
+    fn parse_high_and(&mut self) -> Result<AtomExpr, QueryError> {
+        let mut terms = vec![self.parse_unary()?];
+        loop {
+            // Juxtaposed primitives such as `O-` are joined like an explicit `&`.
+            let joined = self.eat('&')
+                || matches!(self.peek(), Some(c) if !matches!(c, ']' | ',' | ';' | ':'));
+            if joined {
+                terms.push(self.parse_unary()?);
+            } else {
+                return Ok(combine(terms, AtomExpr::And));
+            }
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<AtomExpr, QueryError> {
+        if self.eat('!') {
+            return Ok(AtomExpr::Not(Box::new(self.parse_unary()?)));
+        }
+        match self.peek() {
+            Some('*') => {
+                self.position += 1;
+                Ok(AtomExpr::Any)
+            }
+            Some('#') => {
+                self.position += 1;
+                let number = self.parse_number()?;
+                u8::try_from(number)
+                    .map(AtomExpr::AtomicNumber)
+                    .map_err(|_| self.error("atomic number out of range"))
+            }
+            Some(sign @ ('+' | '-')) => {
+                self.position += 1;
+                let mut magnitude = 1;
+                if self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    magnitude = self.parse_number()?;
+                } else {
+                    while self.eat(sign) {
+                        magnitude += 1;
+                    }
+                }
+                let magnitude =
+                    i32::try_from(magnitude).map_err(|_| self.error("charge too large"))?;
+                Ok(AtomExpr::Charge(if sign == '+' {
+                    magnitude
+                } else {
+                    -magnitude
+                }))
+            }
+            Some('R') => {
+                self.position += 1;
+                if self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    match self.parse_number()? {
+                        0 => Ok(AtomExpr::InRing(false)),
+                        _ => Err(self.error("ring sizes are not supported; use R or R0")),
+                    }
+                } else {
+                    Ok(AtomExpr::InRing(true))
+                }
+            }
+            _ => self.parse_symbol().map(AtomExpr::Element),
+        }
+    }
+
+    /// An element symbol: one uppercase letter, plus a lowercase one when that names a known
+    /// element (so `Cl` is chlorine but `CO` is carbon then oxygen).
+    fn parse_symbol(&mut self) -> Result<String, QueryError> {
+        let Some(first) = self.peek().filter(char::is_ascii_uppercase) else {
+            return Err(match self.peek() {
+                Some(c) if c.is_ascii_lowercase() => {
+                    self.error("aromatic atoms are not supported; use uppercase symbols")
+                }
+                Some(c) => self.error(format!("unexpected '{c}'")),
+                None => self.error("expected an atom"),
+            });
+        };
+        if let Some(second) = self
+            .chars
+            .get(self.position + 1)
+            .filter(|c| c.is_ascii_lowercase())
+        {
+            let symbol = format!("{first}{second}");
+            if atomic_number(&symbol).is_some() {
+                self.position += 2;
+                return Ok(symbol);
+            }
+        }
+        let symbol = first.to_string();
+        if atomic_number(&symbol).is_none() {
+            return Err(self.error(format!("unknown element {symbol}")));
+        }
+        self.position += 1;
+        Ok(symbol)
+    }
+
+    fn parse_number(&mut self) -> Result<u32, QueryError> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        self.chars[start..self.position]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .map_err(|_| QueryError::new(start, "expected a number"))
+    }
+}
+
+fn combine(mut terms: Vec<AtomExpr>, join: fn(Vec<AtomExpr>) -> AtomExpr) -> AtomExpr {
+    if terms.len() == 1 {
+        terms.remove(0)
+    } else {
+        join(terms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ketone on a cyclopropylmethyl group, C(=O)(C)CC1CC1, with only the C=O double.
+    fn ketone() -> (Molecule, Vec<AtomId>) {
+        let mut molecule = Molecule::new("ketone");
+        let ids: Vec<AtomId> = ["C", "O", "C", "C", "C", "C", "C"]
+            .iter()
+            .enumerate()
+            .map(|(i, element)| molecule.insert_atom(element.to_string(), [i as f32, 0.0, 0.0]))
+            .collect();
+        for (a, b) in [(0, 1), (0, 2), (0, 3), (3, 4), (4, 5), (5, 6), (6, 4)] {
+            let bond = molecule.add_bond(ids[a], ids[b]).unwrap();
+            if (a, b) == (0, 1) {
+                molecule.set_bond_order(bond, 2);
+            }
+        }
+        (molecule, ids)
+    }
+
+    #[test]
+    fn selects_carbonyl_carbons_and_ring_atoms() {
+        let (mut molecule, ids) = ketone();
+        let carbonyl = parse_query("[C:1]=O").unwrap();
+        assert_eq!(carbonyl.select(&molecule, 100), [ids[0]]);
+        assert_eq!(
+            parse_query("C=O").unwrap().select(&molecule, 100),
+            [ids[0], ids[1]]
+        );
+        assert!(parse_query("C-O").unwrap().find(&molecule, 100).is_empty());
+        assert_eq!(parse_query("C~O").unwrap().find(&molecule, 100).len(), 1);
+        assert_eq!(
+            parse_query("[C;R]").unwrap().select(&molecule, 100),
+            ids[4..].to_vec()
+        );
+        assert_eq!(parse_query("C1CC1").unwrap().find(&molecule, 100).len(), 6);
+        assert_eq!(
+            parse_query("[C;R0](C)(C)=O")
+                .unwrap()
+                .find(&molecule, 100)
+                .len(),
+            2
+        );
+        molecule.set_formal_charge(ids[1], -1);
+        assert_eq!(
+            parse_query("[O-]").unwrap().select(&molecule, 100),
+            [ids[1]]
+        );
+        assert!(parse_query("[#8;+]")
+            .unwrap()
+            .select(&molecule, 100)
+            .is_empty());
+        assert_eq!(
+            parse_query("[!C]").unwrap().select(&molecule, 100),
+            [ids[1]]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        for text in [
+            "", "C(", "C=", "C1CC", "[C", "c1ccccc1", "Xx", "(C)", "C==O", "[C;R5]",
+        ] {
+            assert!(parse_query(text).is_err(), "{text}");
+        }
+        let err = parse_query("CC)").unwrap_err();
+        assert!(err.to_string().starts_with("column 3"));
+        assert!(parse_query("ClCBr").is_ok());
+        assert!(parse_query("C1CC(C1)O").is_ok());
+    }
+}
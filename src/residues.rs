@@ -0,0 +1,719 @@
+//! Amino acid residues of a structure read from PDB, and swapping one residue's side chain for
+//! another's.
+//!
+//! A residue is the atoms sharing a `chain` and `residue_number` property, named by the
+//! `residue` property of its first atom; atoms are told apart by `atom_name`. Side chains are
+//! built from the backbone N, CA and C with standard bond lengths and angles and a common
+//! rotamer, as heavy atoms with single bonds.
+//!
+//! [`infer_links`] finds what a PDB file often leaves out: disulfide bonds between cysteines
+//! without CONECT records, and salt bridges between acidic and basic side chains.
+
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+use crate::elements::normalize_symbol;
+use crate::zmatrix::place;
+use crate::{AtomId, Command, Molecule};
+
+/// The twenty standard amino acids by three-letter code.
+pub const AMINO_ACIDS: [&str; 20] = [
+    "ALA", "ARG", "ASN", "ASP", "CYS", "GLN", "GLU", "GLY", "HIS", "ILE", "LEU", "LYS", "MET",
+    "PHE", "PRO", "SER", "THR", "TRP", "TYR", "VAL",
+];
+
+/// Backbone atom names, kept through a mutation.
+const BACKBONE: [&str; 5] = ["N", "CA", "C", "O", "OXT"];
+/// Hydrogens on the backbone nitrogen and alpha carbon.
+const BACKBONE_HYDROGENS: [&str; 8] = ["H", "HN", "H1", "H2", "H3", "HA", "HA2", "HA3"];
+/// Properties that identify the residue, copied onto new side chain atoms.
+pub(crate) const RESIDUE_PROPERTIES: [&str; 3] = ["residue", "residue_number", "chain"];
+
+/// An alpha hydrogen this close to the new CB is replaced by it, in Å.
+const HYDROGEN_CLASH: f32 = 1.0;
+
+/// Longest SG–SG distance read as a disulfide, in Å; the ideal is 2.05.
+pub const DISULFIDE_CUTOFF: f32 = 2.5;
+/// Longest N–O distance between charged side chains read as a salt bridge, in Å.
+pub const SALT_BRIDGE_CUTOFF: f32 = 4.0;
+
+/// Carboxylate oxygens of aspartate and glutamate.
+const ACIDIC_ATOMS: [(&str, &str); 4] = [
+    ("ASP", "OD1"),
+    ("ASP", "OD2"),
+    ("GLU", "OE1"),
+    ("GLU", "OE2"),
+];
+/// Charged nitrogens of lysine and arginine.
+const BASIC_ATOMS: [(&str, &str); 4] =
+    [("LYS", "NZ"), ("ARG", "NE"), ("ARG", "NH1"), ("ARG", "NH2")];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ResidueId {
+    /// Empty when the file gives no chain.
+    pub chain: String,
+    /// With any insertion code, e.g. `101A`.
+    pub number: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Residue {
+    pub id: ResidueId,
+    pub name: String,
+    /// In atom order.
+    pub atoms: Vec<AtomId>,
+}
+
+/// The residue `atom` belongs to, if it has a residue number.
+pub fn residue_of(molecule: &Molecule, atom: AtomId) -> Option<ResidueId> {
+    let number = molecule.properties.get("residue_number", atom)?;
+    Some(ResidueId {
+        chain: molecule
+            .properties
+            .get("chain", atom)
+            .unwrap_or("")
+            .to_string(),
+        number: number.to_string(),
+    })
+}
+
+/// Every residue in order of its first atom.
+pub fn residues(molecule: &Molecule) -> Vec<Residue> {
+    let mut residues: Vec<Residue> = Vec::new();
+    let mut index: HashMap<ResidueId, usize> = HashMap::new();
+    for atom in molecule.atoms_in_order() {
+        let Some(id) = residue_of(molecule, atom.id) else {
+            continue;
+        };
+        match index.get(&id) {
+            Some(&position) => residues[position].atoms.push(atom.id),
+            None => {
+                index.insert(id.clone(), residues.len());
+                residues.push(Residue {
+                    name: molecule
+                        .properties
+                        .get("residue", atom.id)
+                        .unwrap_or("")
+                        .to_string(),
+                    id,
+                    atoms: vec![atom.id],
+                });
+            }
+        }
+    }
+    residues
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    Disulfide,
+    SaltBridge,
+}
+
+/// A link between two residues found from distances: for a disulfide the two SG atoms, for a
+/// salt bridge the closest acidic oxygen and basic nitrogen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InferredLink {
+    pub kind: LinkKind,
+    pub a: AtomId,
+    pub b: AtomId,
+    /// In Å.
+    pub distance: f32,
+}
+
+impl InferredLink {
+    /// E.g. `disulfide CYS A22 SG – CYS A97 SG, 2.04 Å`.
+    pub fn describe(&self, molecule: &Molecule) -> String {
+        let label = |atom: AtomId| {
+            let property = |name| molecule.properties.get(name, atom).unwrap_or("");
+            format!(
+                "{} {}{} {}",
+                property("residue"),
+                property("chain"),
+                property("residue_number"),
+                property("atom_name")
+            )
+        };
+        let kind = match self.kind {
+            LinkKind::Disulfide => "disulfide",
+            LinkKind::SaltBridge => "salt bridge",
+        };
+        format!(
+            "{kind} {} – {}, {:.2} Å",
+            label(self.a),
+            label(self.b),
+            self.distance
+        )
+    }
+}
+
+/// Unbonded cysteine SG pairs within [`DISULFIDE_CUTOFF`], closest first with each SG used
+/// once, then one salt bridge per acidic and basic residue pair within
+/// [`SALT_BRIDGE_CUTOFF`]. Atoms of different models are never linked.
+pub fn infer_links(molecule: &Molecule) -> Vec<InferredLink> {
+    let find = |wanted: &[(&str, &str)]| -> Vec<(AtomId, Vec3)> {
+        molecule
+            .atoms_in_order()
+            .filter(|atom| {
+                let property = |name| molecule.properties.get(name, atom.id).unwrap_or("");
+                wanted.contains(&(property("residue"), property("atom_name")))
+            })
+            .map(|atom| (atom.id, Vec3::from_array(atom.position)))
+            .collect()
+    };
+    let same_model = |a: AtomId, b: AtomId| {
+        molecule.properties.get("model", a) == molecule.properties.get("model", b)
+    };
+    let link = |kind, (a, pa): (AtomId, Vec3), (b, pb): (AtomId, Vec3)| InferredLink {
+        kind,
+        a,
+        b,
+        distance: pa.distance(pb),
+    };
+
+    // Sulfurs already in a disulfide are taken.
+    let sulfurs = find(&[("CYS", "SG")]);
+    let mut used = Vec::new();
+    let mut candidates = Vec::new();
+    for (index, &a) in sulfurs.iter().enumerate() {
+        for &b in &sulfurs[index + 1..] {
+            let candidate = link(LinkKind::Disulfide, a, b);
+            if molecule.bond_between(a.0, b.0).is_some() {
+                used.extend([a.0, b.0]);
+            } else if candidate.distance <= DISULFIDE_CUTOFF && same_model(a.0, b.0) {
+                candidates.push(candidate);
+            }
+        }
+    }
+    candidates.sort_by(|x, y| x.distance.total_cmp(&y.distance));
+    let mut links = Vec::new();
+    for candidate in candidates {
+        if !used.contains(&candidate.a) && !used.contains(&candidate.b) {
+            used.extend([candidate.a, candidate.b]);
+            links.push(candidate);
+        }
+    }
+
+    let mut bridges: Vec<((ResidueId, ResidueId), InferredLink)> = Vec::new();
+    for &acid in &find(&ACIDIC_ATOMS) {
+        for &base in &find(&BASIC_ATOMS) {
+            let candidate = link(LinkKind::SaltBridge, acid, base);
+            let residues = (residue_of(molecule, acid.0), residue_of(molecule, base.0));
+            let (Some(acidic), Some(basic)) = residues else {
+                continue;
+            };
+            if candidate.distance > SALT_BRIDGE_CUTOFF || !same_model(acid.0, base.0) {
+                continue;
+            }
+            let pair = (acidic, basic);
+            match bridges.iter_mut().find(|(key, _)| *key == pair) {
+                Some((_, best)) if best.distance <= candidate.distance => {}
+                Some((_, best)) => *best = candidate,
+                None => bridges.push((pair, candidate)),
+            }
+        }
+    }
+    links.extend(bridges.into_iter().map(|(_, link)| link));
+    links
+}
+
+impl Molecule {
+    /// Bonds the disulfides among `links` and returns the links that stand: the disulfides
+    /// that could be bonded and every salt bridge, which is not a bond.
+    pub fn apply_links(&mut self, links: Vec<InferredLink>) -> Vec<InferredLink> {
+        links
+            .into_iter()
+            .filter(|link| {
+                link.kind == LinkKind::SaltBridge || self.add_bond(link.a, link.b).is_ok()
+            })
+            .collect()
+    }
+}
+
+/// A side chain atom placed from three atoms before it: `bond` Å from the last, `angle`
+/// degrees at the last and `dihedral` degrees about the middle two.
+type TemplateAtom = (&'static str, &'static str, [&'static str; 3], f32, f32, f32);
+
+type RingBond = (&'static str, &'static str);
+
+const CB: TemplateAtom = ("CB", "C", ["N", "C", "CA"], 1.52, 109.5, 122.69);
+
+/// Side chain atoms after CB, and ring bonds besides each atom's bond to the last of its
+/// three references.
+fn template(residue: &str) -> Option<(&'static [TemplateAtom], &'static [RingBond])> {
+    const NK: [&str; 3] = ["N", "CA", "CB"];
+    const AC: [&str; 3] = ["CA", "CB", "CG"];
+    const BD: [&str; 3] = ["CB", "CG", "CD"];
+    let template: (&[TemplateAtom], &[(&str, &str)]) = match residue {
+        "GLY" | "ALA" => (&[], &[]),
+        "SER" => (&[("OG", "O", NK, 1.417, 110.77, -63.3)], &[]),
+        "CYS" => (&[("SG", "S", NK, 1.808, 113.82, -62.2)], &[]),
+        "VAL" => (
+            &[
+                ("CG1", "C", NK, 1.527, 110.7, 177.2),
+                ("CG2", "C", NK, 1.527, 110.4, -63.3),
+            ],
+            &[],
+        ),
+        "THR" => (
+            &[
+                ("OG1", "O", NK, 1.43, 109.18, 60.0),
+                ("CG2", "C", NK, 1.53, 111.13, -60.3),
+            ],
+            &[],
+        ),
+        "LEU" => (
+            &[
+                ("CG", "C", NK, 1.53, 116.1, -60.1),
+                ("CD1", "C", AC, 1.524, 110.27, 174.9),
+                ("CD2", "C", AC, 1.525, 110.58, 66.7),
+            ],
+            &[],
+        ),
+        "ILE" => (
+            &[
+                ("CG1", "C", NK, 1.527, 110.7, 59.7),
+                ("CG2", "C", NK, 1.527, 110.4, -61.6),
+                ("CD1", "C", ["CA", "CB", "CG1"], 1.52, 113.97, 169.8),
+            ],
+            &[],
+        ),
+        "MET" => (
+            &[
+                ("CG", "C", NK, 1.52, 113.68, -64.4),
+                ("SD", "S", AC, 1.81, 112.69, -179.6),
+                ("CE", "C", ["CB", "CG", "SD"], 1.79, 100.61, 70.0),
+            ],
+            &[],
+        ),
+        "PHE" => (
+            &[
+                ("CG", "C", NK, 1.50, 113.85, -64.7),
+                ("CD1", "C", AC, 1.39, 120.0, 93.3),
+                ("CD2", "C", AC, 1.39, 120.0, -86.7),
+                ("CE1", "C", ["CB", "CG", "CD1"], 1.39, 120.0, 180.0),
+                ("CE2", "C", ["CB", "CG", "CD2"], 1.39, 120.0, 180.0),
+                ("CZ", "C", ["CG", "CD1", "CE1"], 1.39, 120.0, 0.0),
+            ],
+            &[("CE2", "CZ")],
+        ),
+        "TYR" => (
+            &[
+                ("CG", "C", NK, 1.51, 113.8, -64.3),
+                ("CD1", "C", AC, 1.39, 120.98, 93.1),
+                ("CD2", "C", AC, 1.39, 120.82, -86.9),
+                ("CE1", "C", ["CB", "CG", "CD1"], 1.39, 120.0, 180.0),
+                ("CE2", "C", ["CB", "CG", "CD2"], 1.39, 120.0, 180.0),
+                ("CZ", "C", ["CG", "CD1", "CE1"], 1.39, 120.0, 0.0),
+                ("OH", "O", ["CD1", "CE1", "CZ"], 1.36, 120.0, 180.0),
+            ],
+            &[("CE2", "CZ")],
+        ),
+        "TRP" => (
+            &[
+                ("CG", "C", NK, 1.50, 114.1, -66.4),
+                ("CD1", "C", AC, 1.37, 127.07, 96.3),
+                ("CD2", "C", AC, 1.43, 126.66, -83.7),
+                ("NE1", "N", ["CB", "CG", "CD1"], 1.38, 108.5, 180.0),
+                ("CE2", "C", ["CB", "CG", "CD2"], 1.40, 108.5, 180.0),
+                ("CE3", "C", ["CB", "CG", "CD2"], 1.40, 133.83, 0.0),
+                ("CZ2", "C", ["CG", "CD2", "CE2"], 1.40, 120.0, 180.0),
+                ("CZ3", "C", ["CG", "CD2", "CE3"], 1.40, 120.0, 180.0),
+                ("CH2", "C", ["CD2", "CE2", "CZ2"], 1.40, 120.0, 0.0),
+            ],
+            &[("NE1", "CE2"), ("CZ3", "CH2")],
+        ),
+        "HIS" => (
+            &[
+                ("CG", "C", NK, 1.49, 113.74, -63.2),
+                ("ND1", "N", AC, 1.38, 122.85, -75.7),
+                ("CD2", "C", AC, 1.36, 130.61, 104.3),
+                ("CE1", "C", ["CB", "CG", "ND1"], 1.32, 108.5, 180.0),
+                ("NE2", "N", ["CB", "CG", "CD2"], 1.37, 108.5, 180.0),
+            ],
+            &[("CE1", "NE2")],
+        ),
+        "ASP" => (
+            &[
+                ("CG", "C", NK, 1.52, 113.06, -66.4),
+                ("OD1", "O", AC, 1.25, 119.22, -46.7),
+                ("OD2", "O", AC, 1.25, 118.22, 133.3),
+            ],
+            &[],
+        ),
+        "ASN" => (
+            &[
+                ("CG", "C", NK, 1.52, 112.62, -65.5),
+                ("OD1", "O", AC, 1.23, 120.85, -58.3),
+                ("ND2", "N", AC, 1.33, 116.48, 121.7),
+            ],
+            &[],
+        ),
+        "GLU" => (
+            &[
+                ("CG", "C", NK, 1.52, 113.82, -63.8),
+                ("CD", "C", AC, 1.52, 113.31, -179.8),
+                ("OE1", "O", BD, 1.25, 119.02, -6.2),
+                ("OE2", "O", BD, 1.25, 118.08, 173.8),
+            ],
+            &[],
+        ),
+        "GLN" => (
+            &[
+                ("CG", "C", NK, 1.52, 113.75, -60.2),
+                ("CD", "C", AC, 1.52, 112.78, -69.6),
+                ("OE1", "O", BD, 1.24, 120.86, -50.5),
+                ("NE2", "N", BD, 1.33, 116.5, 129.5),
+            ],
+            &[],
+        ),
+        "LYS" => (
+            &[
+                ("CG", "C", NK, 1.52, 113.83, -64.5),
+                ("CD", "C", AC, 1.52, 111.79, -178.1),
+                ("CE", "C", BD, 1.52, 111.68, -179.6),
+                ("NZ", "N", ["CG", "CD", "CE"], 1.49, 111.9, 179.6),
+            ],
+            &[],
+        ),
+        "ARG" => (
+            &[
+                ("CG", "C", NK, 1.52, 113.83, -65.2),
+                ("CD", "C", AC, 1.52, 111.79, -179.2),
+                ("NE", "N", BD, 1.46, 111.68, -179.3),
+                ("CZ", "C", ["CG", "CD", "NE"], 1.33, 124.79, -178.7),
+                ("NH1", "N", ["CD", "NE", "CZ"], 1.33, 120.64, 0.0),
+                ("NH2", "N", ["CD", "NE", "CZ"], 1.33, 119.63, 180.0),
+            ],
+            &[],
+        ),
+        "PRO" => (
+            &[
+                ("CG", "C", NK, 1.49, 104.21, 29.6),
+                ("CD", "C", AC, 1.50, 105.03, -34.8),
+            ],
+            &[("CD", "N")],
+        ),
+        _ => return None,
+    };
+    Some(template)
+}
+
+impl Molecule {
+    /// Replaces the side chain of `residue` with that of the amino acid `target`; see
+    /// [`Command::mutate_residue`].
+    pub fn mutate_residue(&mut self, residue: &ResidueId, target: &str) -> Result<(), String> {
+        Command::mutate_residue(self, residue, target)?.apply(self)
+    }
+}
+
+impl Command {
+    /// One composite step that deletes the side chain of `residue`, with its hydrogens, and
+    /// builds the side chain of `target` (a three-letter code) on the unmoved backbone. The
+    /// residue is renamed; an alpha hydrogen where CB goes is removed, as is the amide
+    /// hydrogen for proline. New atoms follow the residue's last atom and are bonded when
+    /// the alpha carbon has bonds.
+    pub fn mutate_residue(
+        molecule: &Molecule,
+        residue: &ResidueId,
+        target: &str,
+    ) -> Result<Command, String> {
+        let target = target.trim().to_ascii_uppercase();
+        let (side_chain, rings) =
+            template(&target).ok_or_else(|| format!("unknown amino acid {target}"))?;
+        let atoms: Vec<AtomId> = molecule
+            .atoms_in_order()
+            .map(|atom| atom.id)
+            .filter(|&id| residue_of(molecule, id).as_ref() == Some(residue))
+            .collect();
+        if atoms.is_empty() {
+            return Err(format!("residue {} not found", residue.number));
+        }
+        let name_of = |id: AtomId| molecule.properties.get("atom_name", id).unwrap_or("");
+        let named = |name: &str| atoms.iter().copied().find(|&id| name_of(id) == name);
+        let position = |id: AtomId| {
+            molecule
+                .get_atom(id)
+                .map_or(Vec3::ZERO, |atom| Vec3::from_array(atom.position))
+        };
+        let (Some(n), Some(ca), Some(c)) = (named("N"), named("CA"), named("C")) else {
+            return Err("the residue needs backbone N, CA and C atoms".to_string());
+        };
+
+        let mut positions: HashMap<&str, Vec3> = HashMap::new();
+        positions.insert("N", position(n));
+        positions.insert("CA", position(ca));
+        positions.insert("C", position(c));
+        let mut built = Vec::new();
+        if target != "GLY" {
+            for &(name, element, [a, b, c], bond, angle, dihedral) in
+                std::iter::once(&CB).chain(side_chain)
+            {
+                let at = place(
+                    positions[a],
+                    positions[b],
+                    positions[c],
+                    bond,
+                    angle,
+                    dihedral,
+                );
+                positions.insert(name, at);
+                built.push((name, element, c, at));
+            }
+        }
+
+        let removed: Vec<AtomId> = atoms
+            .iter()
+            .copied()
+            .filter(|&id| {
+                let name = name_of(id);
+                let hydrogen = molecule
+                    .get_atom(id)
+                    .is_some_and(|atom| normalize_symbol(&atom.element) == "H");
+                if BACKBONE.contains(&name) {
+                    false
+                } else if hydrogen && BACKBONE_HYDROGENS.contains(&name) {
+                    let clashes = positions.get("CB").is_some_and(|cb| {
+                        name.starts_with("HA") && cb.distance(position(id)) < HYDROGEN_CLASH
+                    });
+                    clashes || (target == "PRO" && matches!(name, "H" | "HN"))
+                } else {
+                    true
+                }
+            })
+            .collect();
+        let mut commands: Vec<Command> = removed
+            .iter()
+            .map(|&atom_id| Command::DeleteAtom {
+                atom_id,
+                removed: None,
+            })
+            .collect();
+
+        let kept: Vec<AtomId> = atoms
+            .iter()
+            .copied()
+            .filter(|id| !removed.contains(id))
+            .collect();
+        for &atom_id in &kept {
+            if molecule.properties.get("residue", atom_id) != Some(target.as_str()) {
+                commands.push(Command::SetProperty {
+                    atom_id,
+                    name: "residue".to_string(),
+                    value: Some(target.clone()),
+                    previous: None,
+                });
+            }
+        }
+        // A glycine's remaining alpha hydrogen is the only one now.
+        if target != "GLY" {
+            let alphas: Vec<AtomId> = kept
+                .iter()
+                .copied()
+                .filter(|&id| name_of(id).starts_with("HA"))
+                .collect();
+            if let [alpha] = alphas[..] {
+                if name_of(alpha) != "HA" {
+                    commands.push(Command::SetProperty {
+                        atom_id: alpha,
+                        name: "atom_name".to_string(),
+                        value: Some("HA".to_string()),
+                        previous: None,
+                    });
+                }
+            }
+        }
+
+        let last = kept.last().copied().unwrap_or(ca);
+        let order_index = molecule
+            .atoms_in_order()
+            .filter(|atom| !removed.contains(&atom.id))
+            .position(|atom| atom.id == last)
+            .map_or(molecule.atom_count(), |index| index + 1);
+        let bonded = molecule.bonds().any(|bond| bond.a == ca || bond.b == ca);
+        let mut ids: HashMap<&str, AtomId> = HashMap::from([("N", n), ("CA", ca), ("C", c)]);
+        let mut bonds = Vec::new();
+        for (offset, (name, element, parent, at)) in built.into_iter().enumerate() {
+            let id = AtomId::new(molecule.next_atom_id + offset as u64);
+            ids.insert(name, id);
+            commands.push(Command::InsertAtom {
+                element: element.to_string(),
+                position: at.to_array(),
+                atom_id: Some(id),
+                order_index: Some(order_index + offset),
+            });
+            for property in RESIDUE_PROPERTIES {
+                let value = match property {
+                    "residue" => Some(target.clone()),
+                    _ => molecule.properties.get(property, ca).map(str::to_string),
+                };
+                if let Some(value) = value {
+                    commands.push(Command::SetProperty {
+                        atom_id: id,
+                        name: property.to_string(),
+                        value: Some(value),
+                        previous: None,
+                    });
+                }
+            }
+            commands.push(Command::SetProperty {
+                atom_id: id,
+                name: "atom_name".to_string(),
+                value: Some(name.to_string()),
+                previous: None,
+            });
+            bonds.push((parent, name));
+        }
+        if bonded {
+            for &(a, b) in bonds.iter().chain(rings) {
+                commands.push(Command::AddBond {
+                    atom_a: ids[a],
+                    atom_b: ids[b],
+                    bond_id: None,
+                });
+            }
+        }
+        Ok(Command::Composite { commands })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::dihedral;
+
+    /// A serine between two other residues, bonded, with the alpha hydrogen.
+    fn serine() -> (Molecule, ResidueId) {
+        let mut molecule = Molecule::new("peptide");
+        let mut add = |name: &str, element: &str, number: &str, position: [f32; 3]| {
+            let id = molecule.insert_atom(element.to_string(), position);
+            molecule.properties.set("atom_name", id, name);
+            molecule.properties.set("residue", id, "SER");
+            molecule.properties.set("residue_number", id, number);
+            molecule.properties.set("chain", id, "A");
+            id
+        };
+        let n = add("N", "N", "7", [1.458, 0.0, 0.0]);
+        let ca = add("CA", "C", "7", [0.0, 0.0, 0.0]);
+        let c = add("C", "C", "7", [-0.547, 1.424, 0.0]);
+        let o = add("O", "O", "7", [-1.7, 1.6, 0.3]);
+        let ha = add("HA", "H", "7", [-0.36, -0.5, -0.9]);
+        let cb = add("CB", "C", "7", [-0.52, -0.77, 1.2]);
+        let og = add("OG", "O", "7", [-0.1, -2.1, 1.2]);
+        let next = add("N", "N", "8", [0.2, 2.5, 0.0]);
+        for (a, b) in [
+            (n, ca),
+            (ca, c),
+            (c, o),
+            (ca, ha),
+            (ca, cb),
+            (cb, og),
+            (c, next),
+        ] {
+            molecule.add_bond(a, b).unwrap();
+        }
+        let residue = residue_of(&molecule, ca).unwrap();
+        (molecule, residue)
+    }
+
+    #[test]
+    fn builds_an_l_side_chain_on_the_fixed_backbone_and_undoes() {
+        let (mut molecule, residue) = serine();
+        let before = molecule.clone();
+        assert_eq!(residues(&molecule).len(), 2);
+
+        let mut command = Command::mutate_residue(&molecule, &residue, "phe").unwrap();
+        command.apply(&mut molecule).unwrap();
+        let phe = &residues(&molecule)[0];
+        assert_eq!(phe.name, "PHE");
+        // N, CA, C, O, HA and seven side chain carbons, the last after the backbone.
+        assert_eq!(phe.atoms.len(), 12);
+        assert_eq!(molecule.formula(), "C9HN2O");
+        assert_eq!(molecule.bonds().count(), 5 + 8);
+        assert_eq!(molecule.atom_ids()[5..12], phe.atoms[5..]);
+
+        let named = |name: &str| {
+            phe.atoms
+                .iter()
+                .copied()
+                .find(|&id| molecule.properties.get("atom_name", id) == Some(name))
+                .unwrap()
+        };
+        let at = |name: &str| Vec3::from_array(molecule.get_atom(named(name)).unwrap().position);
+        // L configuration, as in the CORN rule.
+        let (ca, cb) = (at("CA"), at("CB"));
+        assert!((at("N") - ca).dot((at("C") - ca).cross(cb - ca)) > 0.0);
+        assert!((cb.distance(ca) - 1.52).abs() < 1e-3);
+        let chi1 = dihedral(&molecule, named("N"), named("CA"), named("CB"), named("CG"));
+        assert!((chi1.unwrap() + 64.7).abs() < 0.1);
+        assert_eq!(molecule.properties.get("chain", named("CZ")), Some("A"));
+        assert_eq!(at("N"), Vec3::new(1.458, 0.0, 0.0));
+
+        command.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.atom_ids(), before.atom_ids());
+        assert_eq!(molecule.bonds().count(), before.bonds().count());
+        assert_eq!(residues(&molecule), residues(&before));
+    }
+
+    #[test]
+    fn infers_disulfides_and_salt_bridges_once_each() {
+        let mut molecule = Molecule::new("protein");
+        let mut add = |residue: &str, number: &str, name: &str, position: [f32; 3]| {
+            let element = &name[..1];
+            let id = molecule.insert_atom(element.to_string(), position);
+            molecule.properties.set("residue", id, residue);
+            molecule.properties.set("residue_number", id, number);
+            molecule.properties.set("chain", id, "A");
+            molecule.properties.set("atom_name", id, name);
+            id
+        };
+        let sg1 = add("CYS", "3", "SG", [0.0, 0.0, 0.0]);
+        let sg2 = add("CYS", "40", "SG", [2.04, 0.0, 0.0]);
+        add("CYS", "41", "SG", [0.0, 2.3, 0.0]);
+        let od1 = add("ASP", "10", "OD1", [10.0, 0.0, 0.0]);
+        add("ASP", "10", "OD2", [10.0, 1.0, 0.0]);
+        let nz = add("LYS", "20", "NZ", [12.8, 0.0, 0.0]);
+        add("LYS", "21", "NZ", [20.0, 0.0, 0.0]);
+
+        let links = infer_links(&molecule);
+        assert_eq!(links.len(), 2);
+        assert_eq!(
+            (links[0].kind, links[0].a, links[0].b),
+            (LinkKind::Disulfide, sg1, sg2)
+        );
+        assert_eq!(
+            (links[1].kind, links[1].a, links[1].b),
+            (LinkKind::SaltBridge, od1, nz)
+        );
+        assert_eq!(
+            links[0].describe(&molecule),
+            "disulfide CYS A3 SG – CYS A40 SG, 2.04 Å"
+        );
+
+        let applied = molecule.apply_links(links);
+        assert_eq!(applied.len(), 2);
+        assert!(molecule.bond_between(sg1, sg2).is_some());
+        assert_eq!(molecule.bonds().count(), 1);
+        assert_eq!(infer_links(&molecule).len(), 1);
+    }
+
+    #[test]
+    fn glycine_loses_the_side_chain_and_proline_the_amide_hydrogen() {
+        let (mut molecule, residue) = serine();
+        molecule.mutate_residue(&residue, "GLY").unwrap();
+        assert_eq!(residues(&molecule)[0].atoms.len(), 5);
+        molecule.mutate_residue(&residue, "PRO").unwrap();
+        let pro = &residues(&molecule)[0];
+        assert_eq!(pro.atoms.len(), 8);
+        let n = pro.atoms[0];
+        assert_eq!(molecule.bonds().filter(|b| b.a == n || b.b == n).count(), 2);
+
+        assert!(molecule.mutate_residue(&residue, "XYZ").is_err());
+        let missing = ResidueId {
+            chain: "B".to_string(),
+            number: "7".to_string(),
+        };
+        assert!(molecule.mutate_residue(&missing, "ALA").is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Renderer-independent description of what is on screen, shared by the offline exporters.
+
+use glam::Vec3;
+
+use crate::palette::Palette;
+use crate::style::StyleOverrides;
+use crate::Molecule;
+
+/// Coordination bonds are drawn this much thinner than covalent ones.
+pub const COORDINATION_RADIUS_SCALE: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sphere {
+    pub center: [f32; 3],
+    pub radius: f32,
+    pub color: [f32; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cylinder {
+    pub start: [f32; 3],
+    pub end: [f32; 3],
+    pub radius: f32,
+    pub color: [f32; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SceneCamera {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    pub up: [f32; 3],
+    /// Vertical field of view.
+    pub fov_degrees: f32,
+    /// Width over height.
+    pub aspect: f32,
+}
+
+impl SceneCamera {
+    /// Looks down -Z at the spheres from far enough away that all of them fit vertically.
+    pub fn framing(spheres: &[Sphere], fov_degrees: f32, aspect: f32) -> Self {
+        let (min, max) = spheres.iter().fold(
+            (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+            |(min, max), sphere| {
+                let center = Vec3::from_array(sphere.center);
+                (
+                    min.min(center - sphere.radius),
+                    max.max(center + sphere.radius),
+                )
+            },
+        );
+        let (center, radius) = if spheres.is_empty() {
+            (Vec3::ZERO, 1.0)
+        } else {
+            ((min + max) * 0.5, (max - min).length() * 0.5)
+        };
+        let distance = radius / (fov_degrees.to_radians() * 0.5).sin();
+        Self {
+            position: (center + Vec3::Z * distance).to_array(),
+            target: center.to_array(),
+            up: Vec3::Y.to_array(),
+            fov_degrees,
+            aspect,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scene {
+    pub spheres: Vec<Sphere>,
+    pub cylinders: Vec<Cylinder>,
+    pub camera: SceneCamera,
+}
+
+/// How atoms and bonds are drawn; mirrors the on-screen representation.
+#[derive(Debug, Clone, Copy)]
+pub struct SceneStyle<'a> {
+    pub palette: Palette,
+    pub overrides: &'a StyleOverrides,
+    pub atom_radius: f32,
+    /// `None` draws no bonds, as in space filling.
+    pub bond_radius: Option<f32>,
+}
+
+impl Scene {
+    /// Atoms in id order and bonds sorted by id, framed by [`SceneCamera::framing`]; atoms a
+    /// style rule hides are left out with their bonds.
+    pub fn from_molecule(
+        molecule: &Molecule,
+        style: &SceneStyle,
+        fov_degrees: f32,
+        aspect: f32,
+    ) -> Self {
+        let spheres: Vec<Sphere> = molecule
+            .atoms_in_order()
+            .filter(|atom| !style.overrides.is_hidden(atom.id))
+            .map(|atom| Sphere {
+                center: atom.position,
+                radius: style.overrides.radius(atom, style.atom_radius),
+                color: style.overrides.color(atom, style.palette),
+            })
+            .collect();
+        let mut cylinders = Vec::new();
+        if let Some(radius) = style.bond_radius {
+            let mut bonds: Vec<_> = molecule.bonds().collect();
+            bonds.sort_by_key(|bond| bond.id);
+            let hidden = |id| style.overrides.is_hidden(id);
+            for bond in bonds
+                .into_iter()
+                .filter(|bond| !hidden(bond.a) && !hidden(bond.b))
+            {
+                if let (Some(a), Some(b)) = (molecule.get_atom(bond.a), molecule.get_atom(bond.b)) {
+                    cylinders.push(Cylinder {
+                        start: a.position,
+                        end: b.position,
+                        radius: style.overrides.bond_radius(bond, radius),
+                        color: style.overrides.bond_color(bond.id, style.palette),
+                    });
+                }
+            }
+        }
+        let camera = SceneCamera::framing(&spheres, fov_degrees, aspect);
+        Self {
+            spheres,
+            cylinders,
+            camera,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_spheres_bonds_and_a_framing_camera() {
+        let mut molecule = Molecule::new("water");
+        let o = molecule.insert_atom("O".into(), [0.0, 0.0, 0.0]);
+        let h = molecule.insert_atom("H".into(), [0.96, 0.0, 0.0]);
+        molecule.add_bond(o, h).unwrap();
+        let overrides = StyleOverrides::default();
+        let mut style = SceneStyle {
+            palette: Palette::Standard,
+            overrides: &overrides,
+            atom_radius: 0.5,
+            bond_radius: Some(0.15),
+        };
+        let scene = Scene::from_molecule(&molecule, &style, 45.0, 1.5);
+        assert_eq!(scene.spheres.len(), 2);
+        assert_eq!(scene.spheres[1].color, Palette::Standard.element_color("H"));
+        assert_eq!(scene.cylinders[0].end, [0.96, 0.0, 0.0]);
+        assert!(
+            (Vec3::from_array(scene.camera.target) - Vec3::new(0.48, 0.0, 0.0)).length() < 1e-6
+        );
+        assert!(scene.camera.position[2] > 1.0);
+
+        style.bond_radius = None;
+        assert!(Scene::from_molecule(&molecule, &style, 45.0, 1.5)
+            .cylinders
+            .is_empty());
+    }
+}
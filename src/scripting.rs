@@ -0,0 +1,391 @@
+//! Custom atom colorings and selection predicates, registered from Rust or from a user script
+//! written in [Rhai](https://rhai.rs), so per-atom data can be shown without changing the crate.
+//!
+//! A script registers functions of one atom, passed as a map with `id`, `element`, `x`, `y`,
+//! `z`, `charge` and `mass`:
+//!
+//! ```text
+//! let shifts = #{ "1": 0.2, "2": 0.9 };
+//! register_coloring("Shift", |atom| ramp(shifts.get(`${atom.id}`) ?? 0.5));
+//! register_selection("Above plane", |atom| atom.z > 0.0);
+//! ```
+//!
+//! Colorings return `[r, g, b]` with components in 0–1; `ramp(t)` maps 0–1 onto blue, white
+//! and red. Selections return a bool. Inside a hook, `scene_stats()` returns the molecule's
+//! [`SceneStats`] as a map with `atoms`, `bonds`, `fragments`, `molecule_bytes` and
+//! `elements` (symbol to count). `nearest_atom(x, y, z)` returns the id of the atom closest to
+//! a point (`()` for an empty molecule) and `atoms_within(x, y, z, radius)` the ids of the
+//! atoms within `radius` Å of it, both searching the molecule the hook runs on:
+//!
+//! ```text
+//! register_selection("Crowded", |atom| atoms_within(atom.x, atom.y, atom.z, 2.0).len() > 5);
+//! ```
+//!
+//! `add_style_rule("element == Fe -> color orange")` adds a [`StyleRule`] for the frontend to
+//! apply alongside the project's own.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use rhai::{Array, Dynamic, Engine, FnPtr, Map, FLOAT, INT};
+
+use crate::elements::atomic_mass;
+use crate::stats::SceneStats;
+use crate::style::StyleRule;
+use crate::{Atom, AtomId, Molecule};
+
+type Coloring = Box<dyn Fn(&Molecule, &Atom) -> Result<[f32; 3], String>>;
+type Predicate = Box<dyn Fn(&Molecule, &Atom) -> Result<bool, String>>;
+
+#[derive(Debug, Clone)]
+pub struct ScriptError {
+    details: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// Named colorings and selections, in registration order. Registering a name again replaces
+/// the earlier entry.
+#[derive(Default)]
+pub struct Hooks {
+    colorings: Vec<(String, Coloring)>,
+    selections: Vec<(String, Predicate)>,
+    style_rules: Vec<StyleRule>,
+    /// What `scene_stats()` returns to scripts; set once a script is loaded, and refreshed
+    /// for each molecule the hooks run on.
+    scene_stats: Option<Rc<RefCell<Map>>>,
+    /// What `nearest_atom()` and `atoms_within()` search; set and refreshed like
+    /// `scene_stats`.
+    molecule: Option<Rc<RefCell<Molecule>>>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("colorings", &self.coloring_names().collect::<Vec<_>>())
+            .field("selections", &self.selection_names().collect::<Vec<_>>())
+            .field("style_rules", &self.style_rules)
+            .finish()
+    }
+}
+
+impl Hooks {
+    pub fn register_coloring(
+        &mut self,
+        name: impl Into<String>,
+        coloring: impl Fn(&Molecule, &Atom) -> Result<[f32; 3], String> + 'static,
+    ) {
+        replace_or_push(&mut self.colorings, name.into(), Box::new(coloring));
+    }
+
+    pub fn register_selection(
+        &mut self,
+        name: impl Into<String>,
+        predicate: impl Fn(&Molecule, &Atom) -> Result<bool, String> + 'static,
+    ) {
+        replace_or_push(&mut self.selections, name.into(), Box::new(predicate));
+    }
+
+    /// Rules scripts added, in order, without repeats.
+    pub fn style_rules(&self) -> &[StyleRule] {
+        &self.style_rules
+    }
+
+    pub fn coloring_names(&self) -> impl Iterator<Item = &str> {
+        self.colorings.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn selection_names(&self) -> impl Iterator<Item = &str> {
+        self.selections.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Color of every atom under the coloring `name`.
+    pub fn colors(
+        &self,
+        name: &str,
+        molecule: &Molecule,
+    ) -> Result<HashMap<AtomId, [f32; 3]>, String> {
+        self.colors_of(name, molecule, molecule.atoms_in_order())
+    }
+
+    /// Colors under `name` of just `atoms`, as after an edit that touched only them.
+    pub fn colors_of<'a>(
+        &self,
+        name: &str,
+        molecule: &Molecule,
+        atoms: impl IntoIterator<Item = &'a Atom>,
+    ) -> Result<HashMap<AtomId, [f32; 3]>, String> {
+        let (_, coloring) = self
+            .colorings
+            .iter()
+            .find(|(entry, _)| entry == name)
+            .ok_or_else(|| format!("no coloring named {name}"))?;
+        self.refresh_context(molecule);
+        atoms
+            .into_iter()
+            .map(|atom| Ok((atom.id, coloring(molecule, atom)?)))
+            .collect()
+    }
+
+    /// Atoms the selection `name` accepts, in molecule order.
+    pub fn select(&self, name: &str, molecule: &Molecule) -> Result<Vec<AtomId>, String> {
+        let (_, predicate) = self
+            .selections
+            .iter()
+            .find(|(entry, _)| entry == name)
+            .ok_or_else(|| format!("no selection named {name}"))?;
+        self.refresh_context(molecule);
+        let mut selected = Vec::new();
+        for atom in molecule.atoms_in_order() {
+            if predicate(molecule, atom)? {
+                selected.push(atom.id);
+            }
+        }
+        Ok(selected)
+    }
+
+    fn refresh_context(&self, molecule: &Molecule) {
+        if let Some(stats) = &self.scene_stats {
+            *stats.borrow_mut() = stats_map(&SceneStats::compute(molecule));
+        }
+        if let Some(shared) = &self.molecule {
+            *shared.borrow_mut() = molecule.clone();
+        }
+    }
+
+    /// Runs `source` and adds whatever it registers. Returns how many entries it registered.
+    pub fn load_script(&mut self, source: &str) -> Result<usize, ScriptError> {
+        let registered: Rc<RefCell<Vec<(bool, String, FnPtr)>>> = Rc::default();
+        let mut engine = Engine::new();
+        let sink = Rc::clone(&registered);
+        engine.register_fn("register_coloring", move |name: &str, hook: FnPtr| {
+            sink.borrow_mut().push((true, name.to_string(), hook));
+        });
+        let sink = Rc::clone(&registered);
+        engine.register_fn("register_selection", move |name: &str, hook: FnPtr| {
+            sink.borrow_mut().push((false, name.to_string(), hook));
+        });
+        let rules: Rc<RefCell<Vec<String>>> = Rc::default();
+        let sink = Rc::clone(&rules);
+        engine.register_fn("add_style_rule", move |rule: &str| {
+            sink.borrow_mut().push(rule.to_string());
+        });
+        let stats = Rc::clone(self.scene_stats.get_or_insert_with(Rc::default));
+        engine.register_fn("scene_stats", move || stats.borrow().clone());
+        let molecule = Rc::clone(
+            self.molecule
+                .get_or_insert_with(|| Rc::new(RefCell::new(Molecule::new("")))),
+        );
+        let shared = Rc::clone(&molecule);
+        engine.register_fn("nearest_atom", move |x: FLOAT, y: FLOAT, z: FLOAT| {
+            shared
+                .borrow()
+                .nearest_atom([x as f32, y as f32, z as f32])
+                .map_or(Dynamic::UNIT, |id| Dynamic::from_int(id.value() as INT))
+        });
+        engine.register_fn(
+            "atoms_within",
+            move |x: FLOAT, y: FLOAT, z: FLOAT, radius: FLOAT| -> Array {
+                molecule
+                    .borrow()
+                    .atoms_within([x as f32, y as f32, z as f32], radius as f32)
+                    .into_iter()
+                    .map(|id| Dynamic::from_int(id.value() as INT))
+                    .collect()
+            },
+        );
+        engine.register_fn("ramp", |t: FLOAT| -> Array {
+            ramp(t as f32)
+                .into_iter()
+                .map(|channel| Dynamic::from_float(channel as FLOAT))
+                .collect()
+        });
+        let ast = engine.compile(source).map_err(|err| ScriptError {
+            details: err.to_string(),
+        })?;
+        engine.run_ast(&ast).map_err(|err| ScriptError {
+            details: err.to_string(),
+        })?;
+
+        let mut parsed = Vec::new();
+        for rule in rules.take() {
+            parsed.push(StyleRule::parse(&rule).map_err(|err| ScriptError {
+                details: format!("style rule {rule:?}: {err}"),
+            })?);
+        }
+        let count = parsed.len();
+        for rule in parsed {
+            if !self.style_rules.contains(&rule) {
+                self.style_rules.push(rule);
+            }
+        }
+
+        let script = Rc::new((engine, ast));
+        let registered = registered.take();
+        let count = count + registered.len();
+        for (is_coloring, name, hook) in registered {
+            let script = Rc::clone(&script);
+            if is_coloring {
+                self.register_coloring(name, move |_, atom| {
+                    let (engine, ast) = &*script;
+                    let value: Dynamic = hook
+                        .call(engine, ast, (atom_map(atom),))
+                        .map_err(|err| err.to_string())?;
+                    color(value)
+                });
+            } else {
+                self.register_selection(name, move |_, atom| {
+                    let (engine, ast) = &*script;
+                    hook.call(engine, ast, (atom_map(atom),))
+                        .map_err(|err| err.to_string())
+                });
+            }
+        }
+        Ok(count)
+    }
+}
+
+fn replace_or_push<T>(entries: &mut Vec<(String, T)>, name: String, hook: T) {
+    match entries.iter_mut().find(|(entry, _)| *entry == name) {
+        Some(entry) => entry.1 = hook,
+        None => entries.push((name, hook)),
+    }
+}
+
+/// Blue at 0, white at 0.5 and red at 1; `t` is clamped.
+pub fn ramp(t: f32) -> [f32; 3] {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        let s = t * 2.0;
+        [s, s, 1.0]
+    } else {
+        let s = (1.0 - t) * 2.0;
+        [1.0, s, s]
+    }
+}
+
+fn atom_map(atom: &Atom) -> Map {
+    let mut map = Map::new();
+    map.insert("id".into(), Dynamic::from_int(atom.id.value() as INT));
+    map.insert("element".into(), atom.element.clone().into());
+    for (key, value) in ["x", "y", "z"].into_iter().zip(atom.position) {
+        map.insert(key.into(), Dynamic::from_float(value as FLOAT));
+    }
+    map.insert("charge".into(), Dynamic::from_int(atom.charge as INT));
+    let mass = atomic_mass(&atom.element).unwrap_or(0.0);
+    map.insert("mass".into(), Dynamic::from_float(mass as FLOAT));
+    map
+}
+
+fn stats_map(stats: &SceneStats) -> Map {
+    let mut map = Map::new();
+    for (key, value) in [
+        ("atoms", stats.atoms),
+        ("bonds", stats.bonds),
+        ("fragments", stats.fragments),
+        ("molecule_bytes", stats.molecule_bytes),
+    ] {
+        map.insert(key.into(), Dynamic::from_int(value as INT));
+    }
+    let elements: Map = stats
+        .elements
+        .iter()
+        .map(|(symbol, count)| (symbol.into(), Dynamic::from_int(*count as INT)))
+        .collect();
+    map.insert("elements".into(), elements.into());
+    map
+}
+
+fn color(value: Dynamic) -> Result<[f32; 3], String> {
+    let components = value
+        .into_array()
+        .map_err(|kind| format!("coloring returned {kind}, expected [r, g, b]"))?;
+    if components.len() != 3 {
+        return Err(format!(
+            "coloring returned {} components, expected 3",
+            components.len()
+        ));
+    }
+    let mut rgb = [0.0; 3];
+    for (channel, component) in rgb.iter_mut().zip(components) {
+        let component = component
+            .as_float()
+            .or_else(|_| component.as_int().map(|int| int as FLOAT))
+            .map_err(|kind| format!("color component is {kind}, expected a number"))?;
+        *channel = (component as f32).clamp(0.0, 1.0);
+    }
+    Ok(rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_register_colorings_and_selections() {
+        let mut molecule = Molecule::new("ions");
+        let na = molecule.insert_atom("Na".into(), [0.0, 0.0, 1.0]);
+        let cl = molecule.insert_atom("Cl".into(), [2.8, 0.0, -1.0]);
+        molecule.set_formal_charge(na, 1);
+        molecule.set_formal_charge(cl, -1);
+
+        let mut hooks = Hooks::default();
+        hooks.register_coloring("Charge", |_, atom| Ok(ramp(0.5 + atom.charge as f32 / 2.0)));
+        let source = r#"
+            let data = #{ "1": 0.25 };
+            register_coloring("Data", |atom| ramp(data.get(`${atom.id}`) ?? 1.0));
+            register_coloring("Charge", |atom| [0, 0, 1]);
+            register_selection("Above", |atom| atom.z > 0.0 && atom.mass > 20.0);
+            register_selection("Lone", |atom| scene_stats().elements[atom.element] == 1);
+            register_selection("Paired", |atom| atoms_within(atom.x, atom.y, atom.z, 3.5).len() > 1);
+            register_selection("Origin", |atom| nearest_atom(0.0, 0.0, 0.0) == atom.id);
+        "#;
+        assert_eq!(hooks.load_script(source).unwrap(), 6);
+        assert_eq!(
+            hooks.coloring_names().collect::<Vec<_>>(),
+            ["Charge", "Data"]
+        );
+
+        let colors = hooks.colors("Data", &molecule).unwrap();
+        assert_eq!(colors[&na], [0.5, 0.5, 1.0]);
+        assert_eq!(colors[&cl], [1.0, 0.0, 0.0]);
+        assert_eq!(
+            hooks.colors("Charge", &molecule).unwrap()[&cl],
+            [0.0, 0.0, 1.0]
+        );
+        let edited = hooks.colors_of("Data", &molecule, molecule.get_atom(cl));
+        assert_eq!(edited.unwrap(), HashMap::from([(cl, colors[&cl])]));
+        assert_eq!(hooks.select("Above", &molecule).unwrap(), [na]);
+        assert_eq!(hooks.select("Paired", &molecule).unwrap(), [na, cl]);
+        assert_eq!(hooks.select("Origin", &molecule).unwrap(), [na]);
+        let far = molecule.insert_atom("Cl".into(), [5.0, 0.0, 0.0]);
+        assert_eq!(hooks.select("Lone", &molecule).unwrap(), [na]);
+        assert_eq!(hooks.select("Paired", &molecule).unwrap(), [na, cl, far]);
+        molecule.set_atom_position(na, [9.0, 0.0, 0.0]);
+        assert_eq!(hooks.select("Origin", &molecule).unwrap(), [cl]);
+        assert_eq!(hooks.select("Paired", &molecule).unwrap(), [cl, far]);
+
+        hooks
+            .load_script(r#"register_coloring("Bad", |atom| atom.element);"#)
+            .unwrap();
+        let err = hooks.colors("Bad", &molecule).unwrap_err();
+        assert!(err.contains("expected [r, g, b]"), "{err}");
+        assert!(hooks.load_script("register_selection(").is_err());
+
+        let rule = r#"add_style_rule("element == Na -> color purple");"#;
+        let err = hooks.load_script(rule).unwrap_err();
+        assert!(err.to_string().contains("unknown color purple"), "{err}");
+        let rule = r#"add_style_rule("charge < 0 -> hide");"#;
+        assert_eq!(hooks.load_script(rule).unwrap(), 1);
+        hooks.load_script(rule).unwrap();
+        assert_eq!(hooks.style_rules().len(), 1);
+    }
+}
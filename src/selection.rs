@@ -0,0 +1,323 @@
+//! The set of selected atoms, and edits that act on all of them as one undo step.
+//!
+//! Atoms keep the order they were picked in. The last one is the primary atom, which tools
+//! that work on a single atom (notes, attaching, sweeps…) use.
+
+use std::collections::HashSet;
+
+use glam::Vec3;
+
+use crate::{AtomId, Command, Molecule};
+
+/// How a picked atom changes the selection: a plain click replaces it, shift-click adds and
+/// ctrl-click toggles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectMode {
+    #[default]
+    Replace,
+    Add,
+    Toggle,
+}
+
+/// Selected atoms in the order they were picked, without repeats.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    atoms: Vec<AtomId>,
+    /// The same atoms, for membership tests that don't scan the list.
+    members: HashSet<AtomId>,
+}
+
+impl Selection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A selection of just `atom`.
+    pub fn only(atom: AtomId) -> Self {
+        Self {
+            atoms: vec![atom],
+            members: HashSet::from([atom]),
+        }
+    }
+
+    /// The most recently picked atom.
+    pub fn primary(&self) -> Option<AtomId> {
+        self.atoms.last().copied()
+    }
+
+    pub fn atoms(&self) -> &[AtomId] {
+        &self.atoms
+    }
+
+    pub fn len(&self) -> usize {
+        self.atoms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.atoms.is_empty()
+    }
+
+    pub fn contains(&self, atom: AtomId) -> bool {
+        self.members.contains(&atom)
+    }
+
+    pub fn clear(&mut self) {
+        self.atoms.clear();
+        self.members.clear();
+    }
+
+    /// Applies one pick. An atom added again moves to the end and becomes the primary atom.
+    pub fn select(&mut self, atom: AtomId, mode: SelectMode) {
+        self.select_all(&[atom], mode);
+    }
+
+    /// Applies one pick of several atoms at once, as from a rectangle or lasso: replacing
+    /// selects just `atoms`, adding appends them and toggling flips each.
+    pub fn select_all(&mut self, atoms: &[AtomId], mode: SelectMode) {
+        let mut picked = HashSet::with_capacity(atoms.len());
+        let picked_atoms: Vec<AtomId> = atoms
+            .iter()
+            .copied()
+            .filter(|&atom| picked.insert(atom))
+            .collect();
+        let appended: Vec<AtomId> = match mode {
+            SelectMode::Replace => {
+                self.clear();
+                picked_atoms
+            }
+            SelectMode::Add => picked_atoms,
+            SelectMode::Toggle => picked_atoms
+                .into_iter()
+                .filter(|atom| !self.members.contains(atom))
+                .collect(),
+        };
+        if picked.iter().any(|atom| self.members.contains(atom)) {
+            self.atoms.retain(|atom| !picked.contains(atom));
+            self.members.retain(|atom| !picked.contains(atom));
+        }
+        self.members.extend(&appended);
+        self.atoms.extend(appended);
+    }
+
+    /// Drops `atom`; true when it was selected.
+    pub fn remove(&mut self, atom: AtomId) -> bool {
+        if !self.members.remove(&atom) {
+            return false;
+        }
+        self.atoms.retain(|&id| id != atom);
+        true
+    }
+}
+
+impl Command {
+    /// One step deleting `atoms` with their bonds. `None` when there is nothing to delete.
+    pub fn delete_atoms(atoms: &[AtomId]) -> Option<Command> {
+        let mut commands: Vec<Command> = atoms
+            .iter()
+            .map(|&atom_id| Command::DeleteAtom {
+                atom_id,
+                removed: None,
+            })
+            .collect();
+        match commands.len() {
+            0 => None,
+            1 => commands.pop(),
+            _ => Some(Command::Composite { commands }),
+        }
+    }
+
+    /// Atoms this command deleted when it ran, in order; empty for commands that delete none
+    /// or have not run. Undoing the command brings them back.
+    pub fn deleted_atoms(&self) -> Vec<AtomId> {
+        match self {
+            Command::DeleteAtom {
+                removed: Some(removed),
+                ..
+            } => vec![removed.atom.id],
+            Command::Composite { commands } => {
+                commands.iter().flat_map(Command::deleted_atoms).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// One step adding a copy of `atoms`, with the bonds among them, shifted by `offset`.
+    /// `None` when none of the atoms exist.
+    pub fn duplicate_atoms(molecule: &Molecule, atoms: &[AtomId], offset: Vec3) -> Option<Command> {
+        let mut fragment = molecule.extract(atoms);
+        if fragment.atom_count() == 0 {
+            return None;
+        }
+        for id in fragment.atom_ids() {
+            let Some(atom) = fragment.get_atom(id) else {
+                continue;
+            };
+            let moved = Vec3::from_array(atom.position) + offset;
+            fragment.set_atom_position(id, moved.to_array());
+        }
+        Some(Command::Merge {
+            fragment: Box::new(fragment),
+            mapping: None,
+        })
+    }
+
+    /// Atoms this command added by merging a fragment, in the fragment's order; empty for
+    /// other commands or before it has run.
+    pub fn merged_atoms(&self) -> Vec<AtomId> {
+        match self {
+            Command::Merge {
+                fragment,
+                mapping: Some(mapping),
+            } => fragment
+                .atom_ids()
+                .iter()
+                .filter_map(|id| mapping.atoms.get(id).copied())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// One step moving `atoms` by `delta`. Repeated moves of the same atoms, as while
+    /// dragging, merge into one history entry.
+    pub fn move_atoms(molecule: &Molecule, atoms: &[AtomId], delta: Vec3) -> Option<Command> {
+        let mut commands: Vec<Command> = atoms
+            .iter()
+            .filter_map(|&atom_id| {
+                let from = molecule.get_atom(atom_id)?.position;
+                let to = (Vec3::from_array(from) + delta).to_array();
+                Some(Command::MoveAtom { atom_id, from, to })
+            })
+            .collect();
+        match commands.len() {
+            0 => None,
+            1 => commands.pop(),
+            _ => Some(Command::Composite { commands }),
+        }
+    }
+
+    /// One step bonding each of `atoms` to `target`, or unbonding them when `remove` is set.
+    /// Pairs already in the requested state, and `target` itself, are skipped.
+    pub fn bond_atoms_to(
+        molecule: &Molecule,
+        atoms: &[AtomId],
+        target: AtomId,
+        remove: bool,
+    ) -> Option<Command> {
+        let mut commands: Vec<Command> = atoms
+            .iter()
+            .filter(|&&atom| atom != target)
+            .filter_map(|&atom| match molecule.bond_between(atom, target) {
+                Some(bond_id) if remove => Some(Command::RemoveBond {
+                    bond_id,
+                    removed: None,
+                }),
+                None if !remove => Some(Command::AddBond {
+                    atom_a: atom,
+                    atom_b: target,
+                    bond_id: None,
+                }),
+                _ => None,
+            })
+            .collect();
+        match commands.len() {
+            0 => None,
+            1 => commands.pop(),
+            _ => Some(Command::Composite { commands }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CommandHistory;
+
+    #[test]
+    fn picks_replace_add_and_toggle() {
+        let [a, b, c] = [1, 2, 3].map(AtomId::new);
+        let mut selection = Selection::only(a);
+        selection.select(b, SelectMode::Add);
+        selection.select(c, SelectMode::Toggle);
+        assert_eq!(selection.atoms(), [a, b, c]);
+        selection.select(a, SelectMode::Add);
+        assert_eq!(selection.atoms(), [b, c, a]);
+        assert_eq!(selection.primary(), Some(a));
+        selection.select(c, SelectMode::Toggle);
+        assert_eq!(selection.atoms(), [b, a]);
+        assert!(!selection.contains(c));
+        selection.select(c, SelectMode::Replace);
+        assert_eq!(selection, Selection::only(c));
+        assert!(selection.remove(c));
+        assert!(selection.is_empty() && selection.primary().is_none());
+
+        selection.select_all(&[a, b], SelectMode::Add);
+        selection.select_all(&[b, c], SelectMode::Toggle);
+        assert_eq!(selection.atoms(), [a, c]);
+        selection.select_all(&[b], SelectMode::Replace);
+        assert_eq!(selection, Selection::only(b));
+    }
+
+    #[test]
+    fn large_picks_keep_order_and_membership() {
+        let atoms: Vec<AtomId> = (1..=50_000).map(AtomId::new).collect();
+        let mut selection = Selection::new();
+        selection.select_all(&atoms, SelectMode::Replace);
+        selection.select_all(&atoms[..10], SelectMode::Add);
+        assert_eq!(selection.len(), atoms.len());
+        assert_eq!(selection.atoms()[..2], atoms[10..12]);
+        assert_eq!(selection.primary(), Some(atoms[9]));
+        selection.select_all(&atoms[..25_000], SelectMode::Toggle);
+        assert_eq!(selection.atoms(), &atoms[25_000..]);
+        assert!(!selection.contains(atoms[0]) && selection.contains(atoms[25_000]));
+    }
+
+    #[test]
+    fn edits_on_the_set_are_single_undo_steps() {
+        let mut molecule = Molecule::new("chain");
+        let ids: Vec<AtomId> = (0..4)
+            .map(|x| molecule.insert_atom("C".into(), [x as f32 * 1.5, 0.0, 0.0]))
+            .collect();
+        molecule.add_bond(ids[0], ids[1]).unwrap();
+        let mut history = CommandHistory::new(10);
+
+        let bond = Command::bond_atoms_to(&molecule, &ids, ids[3], false).unwrap();
+        history.execute(bond, &mut molecule).unwrap();
+        assert_eq!(molecule.neighbors(ids[3]).len(), 3);
+        assert!(Command::bond_atoms_to(&molecule, &ids, ids[3], false).is_none());
+        let unbond = Command::bond_atoms_to(&molecule, &ids[..2], ids[3], true).unwrap();
+        history.execute(unbond, &mut molecule).unwrap();
+        assert_eq!(molecule.neighbors(ids[3]), [ids[2]]);
+
+        let selected = &ids[..2];
+        for _ in 0..3 {
+            let moved = Command::move_atoms(&molecule, selected, Vec3::Y * 0.5).unwrap();
+            history.execute(moved, &mut molecule).unwrap();
+        }
+        assert_eq!(history.undo_len(), 3, "drag steps merge");
+        assert_eq!(molecule.get_atom(ids[1]).unwrap().position, [1.5, 1.5, 0.0]);
+
+        let delete = Command::delete_atoms(selected).unwrap();
+        assert!(delete.deleted_atoms().is_empty());
+        history.execute(delete, &mut molecule).unwrap();
+        assert_eq!(molecule.atom_count(), 2);
+        let undone = history.undo(&mut molecule).unwrap().unwrap();
+        assert_eq!(undone.deleted_atoms(), selected);
+        assert_eq!(molecule.atom_count(), 4);
+        assert!(molecule.bond_between(ids[0], ids[1]).is_some());
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.get_atom(ids[1]).unwrap().position, [1.5, 0.0, 0.0]);
+        assert!(Command::delete_atoms(&[]).is_none());
+
+        let duplicate = Command::duplicate_atoms(&molecule, &ids[..2], Vec3::Z).unwrap();
+        let applied = history.execute(duplicate, &mut molecule).unwrap();
+        let copies = applied.merged_atoms();
+        assert_eq!(copies.len(), 2);
+        assert!(copies.iter().all(|id| !ids.contains(id)));
+        assert_eq!(
+            molecule.get_atom(copies[1]).unwrap().position,
+            [1.5, 0.0, 1.0]
+        );
+        assert!(molecule.bond_between(copies[0], copies[1]).is_some());
+        assert!(Command::duplicate_atoms(&molecule, &[], Vec3::Z).is_none());
+    }
+}
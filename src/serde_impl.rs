@@ -0,0 +1,135 @@
+//! Serialized form of [`Molecule`]: atoms in order and bonds by id, without the lookup tables
+//! that are rebuilt on load. The valence model is not stored; loaded molecules use
+//! [`StandardValence`](crate::valence::StandardValence) but keep every bond they were saved with.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Atom, AtomProperties, Bond, BondKind, Molecule, UnitCell};
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct MoleculeData {
+    name: String,
+    atoms: Vec<Atom>,
+    bonds: Vec<Bond>,
+    cell: Option<UnitCell>,
+    #[serde(default, skip_serializing_if = "AtomProperties::is_empty")]
+    properties: AtomProperties,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    info: BTreeMap<String, String>,
+    next_atom_id: u64,
+    next_bond_id: u64,
+}
+
+impl From<Molecule> for MoleculeData {
+    fn from(molecule: Molecule) -> Self {
+        Self {
+            atoms: molecule.atoms_in_order().cloned().collect(),
+            bonds: molecule.sorted_bonds().into_iter().cloned().collect(),
+            cell: molecule.cell,
+            properties: molecule.properties,
+            info: molecule.info,
+            next_atom_id: molecule.next_atom_id,
+            next_bond_id: molecule.next_bond_id,
+            name: molecule.name,
+        }
+    }
+}
+
+impl TryFrom<MoleculeData> for Molecule {
+    type Error = String;
+
+    fn try_from(data: MoleculeData) -> Result<Self, Self::Error> {
+        let mut molecule = Molecule::new(data.name);
+        molecule.cell = data.cell;
+        molecule.properties = data.properties;
+        molecule.info = data.info;
+        molecule.next_atom_id = data.next_atom_id;
+        molecule.next_bond_id = data.next_bond_id;
+        for atom in data.atoms {
+            let id = atom.id;
+            if molecule.atoms.insert(id, atom).is_some() {
+                return Err(format!("duplicate atom id {}", id.value()));
+            }
+            molecule.atom_order.push(id);
+            molecule.valence_counts.insert(id, 0);
+            molecule.next_atom_id = molecule.next_atom_id.max(id.value() + 1);
+        }
+        let mut pairs = HashMap::new();
+        for bond in data.bonds {
+            let id = bond.id;
+            molecule.ensure_atoms_exist(bond.a, bond.b)?;
+            let pair = (bond.a.min(bond.b), bond.a.max(bond.b));
+            if pairs.insert(pair, id).is_some() {
+                return Err(format!(
+                    "duplicate bond between atoms {} and {}",
+                    bond.a.value(),
+                    bond.b.value()
+                ));
+            }
+            if bond.kind == BondKind::Covalent {
+                molecule.add_valence(bond.a, bond.order);
+                molecule.add_valence(bond.b, bond.order);
+            }
+            if molecule.bonds.insert(id, bond).is_some() {
+                return Err(format!("duplicate bond id {}", id.value()));
+            }
+            molecule.next_bond_id = molecule.next_bond_id.max(id.value() + 1);
+        }
+        Ok(molecule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AtomId, Command, Molecule, UnitCell};
+
+    #[test]
+    fn molecules_and_commands_round_trip() {
+        let mut molecule = Molecule::new("formate");
+        let c = molecule.insert_atom("C".into(), [0.0; 3]);
+        let o1 = molecule.insert_atom("O".into(), [1.25, 0.0, 0.0]);
+        let o2 = molecule.insert_atom("O".into(), [-0.6, 1.1, 0.0]);
+        let gone = molecule.insert_atom("Xe".into(), [5.0; 3]);
+        let double = molecule.add_bond(c, o1).unwrap();
+        molecule.add_bond(c, o2).unwrap();
+        molecule.set_bond_order(double, 2);
+        molecule.set_formal_charge(o2, -1);
+        molecule.set_cell(Some(UnitCell {
+            a: 10.0,
+            b: 11.0,
+            c: 12.0,
+            alpha: 90.0,
+            beta: 95.0,
+            gamma: 90.0,
+        }));
+        molecule.remove_atom(gone);
+
+        let json = serde_json::to_string(&molecule).unwrap();
+        let mut loaded: Molecule = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+        assert_eq!(loaded.atom_ids(), [c, o1, o2]);
+        assert_eq!(loaded.get_atom(o2).unwrap().charge, -1);
+        assert_eq!(loaded.cell(), molecule.cell());
+        assert_eq!(loaded.next_atom_id(), AtomId::new(5));
+
+        // An applied command carries what it needs to undo on the loaded copy.
+        let mut delete = Command::DeleteAtom {
+            atom_id: c,
+            removed: None,
+        };
+        delete.apply(&mut molecule).unwrap();
+        let mut delete: Command =
+            serde_json::from_str(&serde_json::to_string(&delete).unwrap()).unwrap();
+        loaded.remove_atom(c);
+        delete.undo(&mut loaded).unwrap();
+        assert_eq!(loaded.bonds().count(), 2);
+        assert!(loaded
+            .bonds()
+            .any(|bond| bond.id == double && bond.order == 2));
+
+        let dangling = json.replace(r#"{"id":1,"a":1"#, r#"{"id":1,"a":9"#);
+        assert!(serde_json::from_str::<Molecule>(&dangling).is_err());
+    }
+}
@@ -0,0 +1,707 @@
+use std::fmt::{self, Write as _};
+use std::sync::Arc;
+
+use glam::{Quat, Vec3};
+
+use crate::annotations::{Annotations, Arrow, ArrowKind, Site};
+use crate::camera::{Camera, CameraMode, SavedView};
+use crate::formats::text;
+use crate::notes::{NoteTarget, Notes};
+use crate::provenance::{ProvenanceEntry, ProvenanceLog};
+use crate::style::{BondDash, BondStyle, StyleOverride, StyleOverrides, StyleRule};
+use crate::valence::{StandardValence, Unrestricted};
+use crate::{AtomId, Bond, BondId, BondKind, Molecule, UnitCell};
+
+const HEADER: &str = "molweaver-session 1";
+
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub name: String,
+    pub molecule: Molecule,
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub molecule: Molecule,
+    pub checkpoints: Vec<Checkpoint>,
+    pub style: StyleOverrides,
+    pub notes: Notes,
+    pub annotations: Annotations,
+    pub provenance: ProvenanceLog,
+    /// The view when the session was saved; `None` keeps the current one on load.
+    pub camera: Option<Camera>,
+    pub views: Vec<SavedView>,
+}
+
+impl Session {
+    pub fn new(molecule: Molecule) -> Self {
+        Self {
+            molecule,
+            checkpoints: Vec::new(),
+            style: StyleOverrides::default(),
+            notes: Notes::default(),
+            annotations: Annotations::default(),
+            provenance: ProvenanceLog::default(),
+            camera: None,
+            views: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionError {
+    details: String,
+}
+
+impl SessionError {
+    fn new(line: usize, details: impl Into<String>) -> Self {
+        Self {
+            details: format!("line {line}: {}", details.into()),
+        }
+    }
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+pub fn write_session(session: &Session) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "{HEADER}");
+    write_molecule_section(&mut out, &session.molecule);
+    for (element, style) in &session.style.elements {
+        write_style(&mut out, &format!("element {element}"), style);
+    }
+    for (id, style) in &session.style.atoms {
+        write_style(&mut out, &format!("atom {}", id.value()), style);
+    }
+    for (id, style) in &session.style.bonds {
+        let _ = write!(out, "style bond {}", id.value());
+        write_style_fields(&mut out, style.color, style.radius_scale);
+        if let Some(dash) = style.dash {
+            let _ = write!(out, " dash {}", dash.label().to_lowercase());
+        }
+        let _ = writeln!(out);
+    }
+    for rule in &session.style.rules {
+        let _ = writeln!(out, "rule {rule}");
+    }
+    // Notes on atoms or bonds that were deleted only matter to undo, which isn't saved.
+    for (target, _, text) in session.notes.markers(&session.molecule) {
+        let target = match target {
+            NoteTarget::Atom(id) => format!("atom {}", id.value()),
+            NoteTarget::Bond(id) => format!("bond {}", id.value()),
+        };
+        let _ = writeln!(out, "note {target} {}", escape_text(text));
+    }
+    for (arrow, _, _) in session.annotations.arrow_ends(&session.molecule) {
+        let _ = writeln!(
+            out,
+            "arrow {} {} {}",
+            arrow.kind.label().to_lowercase(),
+            arrow.from.label(),
+            arrow.to.label()
+        );
+    }
+    for (bond, _, _) in session.annotations.partial_bond_ends(&session.molecule) {
+        let (a, b) = (bond.a.value(), bond.b.value());
+        let _ = writeln!(out, "partial {a} {b} {}", bond.order);
+    }
+    for entry in &session.provenance.entries {
+        let user: String = entry
+            .user
+            .chars()
+            .map(|c| if c.is_whitespace() { '_' } else { c })
+            .collect();
+        let _ = writeln!(
+            out,
+            "log {} {user} {}",
+            entry.time,
+            escape_text(&entry.action)
+        );
+    }
+    if let Some(camera) = &session.camera {
+        let _ = writeln!(out, "camera {}", camera_fields(camera));
+    }
+    for view in &session.views {
+        let _ = writeln!(out, "view {} {}", camera_fields(&view.camera), view.name);
+    }
+    for checkpoint in &session.checkpoints {
+        let _ = writeln!(out, "checkpoint {}", checkpoint.name);
+        write_molecule_section(&mut out, &checkpoint.molecule);
+    }
+    out
+}
+
+fn write_style(out: &mut String, target: &str, style: &StyleOverride) {
+    let _ = write!(out, "style {target}");
+    write_style_fields(out, style.color, style.radius_scale);
+    let _ = writeln!(out);
+}
+
+fn write_style_fields(out: &mut String, color: Option<[f32; 3]>, radius_scale: Option<f32>) {
+    if let Some([r, g, b]) = color {
+        let _ = write!(out, " color {r} {g} {b}");
+    }
+    if let Some(scale) = radius_scale {
+        let _ = write!(out, " radius {scale}");
+    }
+}
+
+/// Mode, yaw, pitch, distance, target, eye and orientation quaternion.
+fn camera_fields(camera: &Camera) -> String {
+    let mut out = format!(
+        "{} {} {} {}",
+        camera.mode.label().to_lowercase(),
+        camera.yaw,
+        camera.pitch,
+        camera.distance
+    );
+    let [qx, qy, qz, qw] = camera.orientation.to_array();
+    for value in [camera.target.to_array(), camera.eye.to_array()]
+        .concat()
+        .into_iter()
+        .chain([qx, qy, qz, qw])
+    {
+        let _ = write!(out, " {value}");
+    }
+    out
+}
+
+/// Keeps free text on one line.
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                out.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                out.push('\\');
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn write_molecule_section(out: &mut String, molecule: &Molecule) {
+    let _ = writeln!(out, "molecule {}", molecule.name);
+    if let Some(cell) = molecule.cell() {
+        let _ = writeln!(
+            out,
+            "cell {} {} {} {} {} {}",
+            cell.a, cell.b, cell.c, cell.alpha, cell.beta, cell.gamma
+        );
+    }
+    for (key, value) in &molecule.info {
+        let key = key.split_whitespace().collect::<Vec<_>>().join("_");
+        let _ = writeln!(out, "info {key} {}", escape_text(value));
+    }
+    for atom in molecule.atoms_in_order() {
+        let [x, y, z] = atom.position;
+        let _ = write!(out, "atom {} {} {x} {y} {z}", atom.id.value(), atom.element);
+        if atom.charge != 0 {
+            let _ = write!(out, " charge {}", atom.charge);
+        }
+        let _ = writeln!(out);
+    }
+    for bond in molecule.sorted_bonds() {
+        let _ = write!(
+            out,
+            "bond {} {} {}",
+            bond.id.value(),
+            bond.a.value(),
+            bond.b.value()
+        );
+        if bond.order != 1 {
+            let _ = write!(out, " order {}", bond.order);
+        }
+        if bond.kind == BondKind::Coordination {
+            let _ = write!(out, " coordination");
+        }
+        let _ = writeln!(out);
+    }
+    // Property names are single words; whitespace in one becomes `_`.
+    for name in molecule.properties.names() {
+        let word = name.split_whitespace().collect::<Vec<_>>().join("_");
+        for atom in molecule.atoms_in_order() {
+            if let Some(value) = molecule.properties.get(name, atom.id) {
+                let _ = writeln!(
+                    out,
+                    "property {} {word} {}",
+                    atom.id.value(),
+                    escape_text(value)
+                );
+            }
+        }
+    }
+    let _ = writeln!(out, "end");
+}
+
+pub fn parse_session(contents: &str) -> Result<Session, SessionError> {
+    let mut lines = text::lines(contents).map(|line| (line.number, line.text));
+    match lines.next() {
+        Some((_, line)) if line.trim() == HEADER => {}
+        _ => return Err(SessionError::new(1, "missing session header")),
+    }
+    let mut molecule = None;
+    let mut checkpoints = Vec::new();
+    let mut pending_checkpoint: Option<String> = None;
+    let mut style = StyleOverrides::default();
+    let mut notes = Notes::default();
+    let mut annotations = Annotations::default();
+    let mut provenance = ProvenanceLog::default();
+    let mut camera = None;
+    let mut views = Vec::new();
+    while let Some((number, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match keyword {
+            "checkpoint" => pending_checkpoint = Some(rest.to_string()),
+            "style" => parse_style(rest, number, &mut style)?,
+            "rule" => style
+                .rules
+                .push(StyleRule::parse(rest).map_err(|err| SessionError::new(number, err))?),
+            "note" => parse_note(rest, number, &mut notes)?,
+            "arrow" => annotations.arrows.push(parse_arrow(rest, number)?),
+            "partial" => {
+                let mut parts = rest.split_whitespace();
+                let a = parse_id(parts.next(), number)?;
+                let b = parse_id(parts.next(), number)?;
+                let [order] = parse_floats(&mut parts, number)?;
+                annotations.set_partial_bond(AtomId(a), AtomId(b), order);
+            }
+            "log" => provenance.entries.push(parse_log(rest, number)?),
+            "camera" => camera = Some(parse_camera(&mut rest.split_whitespace(), number)?),
+            "view" => {
+                let mut parts = rest.splitn(15, ' ');
+                let camera = parse_camera(&mut parts, number)?;
+                let name = parts.next().unwrap_or("").to_string();
+                views.push(SavedView { name, camera });
+            }
+            "molecule" => {
+                let parsed = parse_molecule_section(rest, &mut lines)?;
+                match pending_checkpoint.take() {
+                    Some(name) => checkpoints.push(Checkpoint {
+                        name,
+                        molecule: parsed,
+                    }),
+                    None if molecule.is_none() => molecule = Some(parsed),
+                    None => return Err(SessionError::new(number, "duplicate molecule section")),
+                }
+            }
+            _ => {
+                return Err(SessionError::new(
+                    number,
+                    format!("unknown record {keyword}"),
+                ))
+            }
+        }
+    }
+    let molecule = molecule.ok_or_else(|| SessionError::new(1, "missing molecule section"))?;
+    Ok(Session {
+        molecule,
+        checkpoints,
+        style,
+        notes,
+        annotations,
+        provenance,
+        camera,
+        views,
+    })
+}
+
+fn parse_camera<'a>(
+    parts: &mut impl Iterator<Item = &'a str>,
+    line: usize,
+) -> Result<Camera, SessionError> {
+    let mode = parts.next().unwrap_or("");
+    let mode = CameraMode::ALL
+        .into_iter()
+        .find(|option| option.label().eq_ignore_ascii_case(mode))
+        .ok_or_else(|| SessionError::new(line, format!("unknown camera mode {mode}")))?;
+    let [yaw, pitch, distance] = parse_floats::<3>(parts, line)?;
+    let target = Vec3::from_array(parse_floats::<3>(parts, line)?);
+    let eye = Vec3::from_array(parse_floats::<3>(parts, line)?);
+    let orientation = Quat::from_array(parse_floats::<4>(parts, line)?).normalize();
+    Ok(Camera {
+        mode,
+        yaw,
+        pitch,
+        orientation,
+        distance,
+        target,
+        eye,
+    })
+}
+
+fn parse_molecule_section<'a>(
+    name: &str,
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+) -> Result<Molecule, SessionError> {
+    let mut molecule = Molecule::new(name);
+    // Saved bonds were valid when made; an atom over its limit must still load.
+    molecule.set_valence_model(Arc::new(Unrestricted));
+    for (number, line) in lines.by_ref() {
+        let mut parts = line.split_whitespace();
+        match parts.next() {
+            Some("end") => {
+                molecule.set_valence_model(Arc::new(StandardValence));
+                return Ok(molecule);
+            }
+            Some("cell") => {
+                let values = parse_floats::<6>(&mut parts, number)?;
+                let [a, b, c, alpha, beta, gamma] = values;
+                molecule.set_cell(Some(UnitCell::new(a, b, c, alpha, beta, gamma)));
+            }
+            Some("info") => {
+                let key = parts
+                    .next()
+                    .ok_or_else(|| SessionError::new(number, "missing info key"))?;
+                let value = line.trim_start().splitn(3, ' ').nth(2).unwrap_or("");
+                molecule.info.insert(key.to_string(), unescape_text(value));
+            }
+            Some("atom") => {
+                let id = parse_id(parts.next(), number)?;
+                let element = parts
+                    .next()
+                    .ok_or_else(|| SessionError::new(number, "missing element"))?
+                    .to_string();
+                let position = parse_floats::<3>(&mut parts, number)?;
+                molecule.insert_atom_with_id(AtomId(id), element, position, None);
+                if let Some(charge) = parse_optional(&mut parts, "charge", number)? {
+                    molecule.set_formal_charge(AtomId(id), charge);
+                }
+            }
+            Some("bond") => {
+                let id = parse_id(parts.next(), number)?;
+                let a = parse_id(parts.next(), number)?;
+                let b = parse_id(parts.next(), number)?;
+                let mut bond = Bond {
+                    id: BondId(id),
+                    a: AtomId(a),
+                    b: AtomId(b),
+                    order: 1,
+                    kind: BondKind::Covalent,
+                    stereo: None,
+                };
+                while let Some(field) = parts.next() {
+                    match field {
+                        "order" => {
+                            bond.order = parts
+                                .next()
+                                .and_then(|value| value.parse().ok())
+                                .ok_or_else(|| SessionError::new(number, "invalid order"))?;
+                        }
+                        "coordination" => bond.kind = BondKind::Coordination,
+                        other => {
+                            return Err(SessionError::new(number, format!("unexpected {other}")))
+                        }
+                    }
+                }
+                molecule
+                    .restore_bond(&bond)
+                    .map_err(|err| SessionError::new(number, err))?;
+            }
+            Some("property") => {
+                let id = parse_id(parts.next(), number)?;
+                let name = parts
+                    .next()
+                    .ok_or_else(|| SessionError::new(number, "missing property name"))?;
+                let value = line.trim_start().splitn(4, ' ').nth(3).unwrap_or("");
+                molecule
+                    .properties
+                    .set(name, AtomId(id), unescape_text(value));
+            }
+            Some(other) => {
+                return Err(SessionError::new(number, format!("unknown record {other}")))
+            }
+            None => {}
+        }
+    }
+    Err(SessionError::new(0, "unterminated molecule section"))
+}
+
+fn parse_style(rest: &str, line: usize, style: &mut StyleOverrides) -> Result<(), SessionError> {
+    let mut parts = rest.split_whitespace();
+    let kind = parts.next();
+    let target = parts
+        .next()
+        .ok_or_else(|| SessionError::new(line, "missing style target"))?;
+    let mut parsed = StyleOverride::default();
+    let mut dash = None;
+    while let Some(field) = parts.next() {
+        match field {
+            "color" => parsed.color = Some(parse_floats::<3>(&mut parts, line)?),
+            "radius" => parsed.radius_scale = Some(parse_floats::<1>(&mut parts, line)?[0]),
+            "dash" if kind == Some("bond") => {
+                let name = parts.next().unwrap_or("");
+                dash = Some(BondDash::parse(name).ok_or_else(|| {
+                    SessionError::new(line, format!("unknown dash style {name}"))
+                })?);
+            }
+            other => {
+                return Err(SessionError::new(
+                    line,
+                    format!("unknown style field {other}"),
+                ))
+            }
+        }
+    }
+    match kind {
+        Some("element") => style.set_element(target, parsed),
+        Some("atom") => style.set_atom(AtomId(parse_id(Some(target), line)?), parsed),
+        Some("bond") => style.set_bond(
+            BondId(parse_id(Some(target), line)?),
+            BondStyle {
+                color: parsed.color,
+                radius_scale: parsed.radius_scale,
+                dash,
+            },
+        ),
+        _ => {
+            return Err(SessionError::new(
+                line,
+                "style target must be element, atom or bond",
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn parse_note(rest: &str, line: usize, notes: &mut Notes) -> Result<(), SessionError> {
+    let mut parts = rest.splitn(3, ' ');
+    let kind = parts.next();
+    let id = parse_id(parts.next(), line)?;
+    let text = unescape_text(parts.next().unwrap_or(""));
+    match kind {
+        Some("atom") => notes.set(NoteTarget::Atom(AtomId(id)), &text),
+        Some("bond") => notes.set(NoteTarget::Bond(BondId(id)), &text),
+        _ => return Err(SessionError::new(line, "note target must be atom or bond")),
+    }
+    Ok(())
+}
+
+fn parse_arrow(rest: &str, line: usize) -> Result<Arrow, SessionError> {
+    let mut parts = rest.split_whitespace();
+    let kind = parts
+        .next()
+        .and_then(ArrowKind::parse)
+        .ok_or_else(|| SessionError::new(line, "unknown arrow kind"))?;
+    let mut site = || {
+        parts
+            .next()
+            .and_then(Site::parse)
+            .ok_or_else(|| SessionError::new(line, "arrow ends must be atom ids or id pairs"))
+    };
+    let (from, to) = (site()?, site()?);
+    Ok(Arrow { kind, from, to })
+}
+
+fn parse_log(rest: &str, line: usize) -> Result<ProvenanceEntry, SessionError> {
+    let mut parts = rest.splitn(3, ' ');
+    let time = parts
+        .next()
+        .and_then(|token| token.parse().ok())
+        .ok_or_else(|| SessionError::new(line, "invalid log time"))?;
+    let user = parts
+        .next()
+        .filter(|user| !user.is_empty())
+        .ok_or_else(|| SessionError::new(line, "missing log user"))?;
+    Ok(ProvenanceEntry {
+        time,
+        user: user.to_string(),
+        action: unescape_text(parts.next().unwrap_or("")),
+    })
+}
+
+fn parse_id(token: Option<&str>, line: usize) -> Result<u64, SessionError> {
+    token
+        .and_then(|token| token.parse().ok())
+        .ok_or_else(|| SessionError::new(line, "invalid id"))
+}
+
+/// Trailing `key value` pair; older sessions omit it.
+fn parse_optional<'a, T: std::str::FromStr>(
+    parts: &mut impl Iterator<Item = &'a str>,
+    key: &str,
+    line: usize,
+) -> Result<Option<T>, SessionError> {
+    match parts.next() {
+        None => Ok(None),
+        Some(token) if token == key => parts
+            .next()
+            .and_then(|value| value.parse().ok())
+            .map(Some)
+            .ok_or_else(|| SessionError::new(line, format!("invalid {key}"))),
+        Some(other) => Err(SessionError::new(line, format!("unexpected {other}"))),
+    }
+}
+
+fn parse_floats<'a, const N: usize>(
+    parts: &mut impl Iterator<Item = &'a str>,
+    line: usize,
+) -> Result<[f32; N], SessionError> {
+    let mut values = [0.0; N];
+    for value in values.iter_mut() {
+        *value = parts
+            .next()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| SessionError::new(line, "invalid number"))?;
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_round_trip_preserves_ids() {
+        let mut molecule = Molecule::new("water");
+        let o = molecule.insert_atom("O".into(), [0.0, 0.0, 0.1]);
+        let removed = molecule.insert_atom("H".into(), [0.0, 0.0, 0.0]);
+        let h = molecule.insert_atom("H".into(), [0.757, 0.586, 0.0]);
+        molecule.remove_atom(removed);
+        let bond = molecule.add_bond(o, h).unwrap();
+        molecule.set_formal_charge(o, -1);
+        molecule.set_bond_order(bond, 2);
+        let ion = molecule.insert_atom("Na".into(), [2.5, 0.0, 0.0]);
+        let dative = molecule.add_coordination_bond(o, ion).unwrap();
+        molecule.set_cell(Some(UnitCell::cubic(12.5)));
+        molecule.properties.set("residue", o, "HOH");
+        molecule.properties.set("label", h, "two words\nand a line");
+        molecule
+            .info
+            .insert("energy".to_string(), "-76.4 Eh".to_string());
+        let mut session = Session::new(molecule.clone());
+        session.style.set_element(
+            "C",
+            StyleOverride {
+                color: Some([1.0, 0.5, 0.0]),
+                radius_scale: None,
+            },
+        );
+        session.style.set_atom(
+            h,
+            StyleOverride {
+                color: None,
+                radius_scale: Some(1.5),
+            },
+        );
+        session.style.set_bond(
+            dative,
+            BondStyle {
+                color: Some([0.9, 0.1, 0.1]),
+                radius_scale: Some(2.0),
+                dash: Some(BondDash::Dotted),
+            },
+        );
+        session
+            .style
+            .rules
+            .push(StyleRule::parse("fragment size < 3 -> hide").unwrap());
+        session.notes.set(
+            NoteTarget::Atom(o),
+            "protonation uncertain here\nsee C:\\runs",
+        );
+        session.notes.set(NoteTarget::Bond(bond), "elongated?");
+        session.annotations.arrows.push(Arrow {
+            kind: ArrowKind::FishHook,
+            from: Site::Between(o, h),
+            to: Site::Atom(ion),
+        });
+        session.annotations.set_partial_bond(h, ion, 0.5);
+        session.provenance.entries.push(ProvenanceEntry {
+            time: 1_792_022_400,
+            user: "alice".into(),
+            action: "open water.xyz".into(),
+        });
+        session.checkpoints.push(Checkpoint {
+            name: "before substituents".into(),
+            molecule: Molecule::new("empty"),
+        });
+        let mut camera = Camera::default();
+        camera.set_mode(CameraMode::Trackball);
+        camera.rotate(glam::Vec2::new(30.0, -12.0));
+        session.camera = Some(camera);
+        session.views.push(SavedView {
+            name: "down the  O–H bond".into(),
+            camera: Camera::default(),
+        });
+
+        let parsed = parse_session(&write_session(&session)).unwrap();
+        assert_eq!(parsed.molecule.atom_ids(), [o, h, ion]);
+        assert_eq!(
+            parsed.molecule.get_atom(h).unwrap().position,
+            [0.757, 0.586, 0.0]
+        );
+        assert_eq!(parsed.molecule.bond_between(o, h), Some(bond));
+        assert_eq!(parsed.molecule.get_atom(o).unwrap().charge, -1);
+        assert_eq!(parsed.molecule.get_atom(h).unwrap().charge, 0);
+        let kinds: Vec<(u8, BondKind)> = parsed
+            .molecule
+            .sorted_bonds()
+            .into_iter()
+            .map(|bond| (bond.order, bond.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [(2, BondKind::Covalent), (1, BondKind::Coordination)]
+        );
+        assert_eq!(parsed.molecule.bond_between(ion, o), Some(dative));
+        assert_eq!(parsed.molecule.cell(), Some(&UnitCell::cubic(12.5)));
+        assert_eq!(parsed.molecule.properties, molecule.properties);
+        assert_eq!(parsed.molecule.info, molecule.info);
+        assert_eq!(parsed.style, session.style);
+        assert_eq!(parsed.notes, session.notes);
+        assert_eq!(parsed.annotations, session.annotations);
+        assert_eq!(parsed.provenance, session.provenance);
+        assert_eq!(parsed.camera, session.camera);
+        assert_eq!(parsed.views, session.views);
+        assert_eq!(parsed.checkpoints.len(), 1);
+        assert_eq!(parsed.checkpoints[0].name, "before substituents");
+        assert_eq!(parsed.checkpoints[0].molecule.atom_count(), 0);
+    }
+
+    #[test]
+    fn session_reloads_over_valent_atoms() {
+        let mut molecule = Molecule::new("ferrocene fragment");
+        molecule.set_valence_model(Arc::new(Unrestricted));
+        let fe = molecule.insert_atom("Fe".into(), [0.0; 3]);
+        for i in 0..6 {
+            let c = molecule.insert_atom("C".into(), [i as f32, 1.0, 0.0]);
+            molecule.add_bond(fe, c).unwrap();
+        }
+        molecule.set_valence_model(Arc::new(StandardValence));
+
+        let mut parsed = parse_session(&write_session(&Session::new(molecule)))
+            .unwrap()
+            .molecule;
+        assert_eq!(parsed.sorted_bonds().len(), 6);
+        assert_eq!(parsed.valence_warnings().len(), 1);
+        let c = parsed.insert_atom("C".into(), [0.0, -1.0, 0.0]);
+        assert!(parsed.add_bond(fe, c).is_err());
+    }
+
+    #[test]
+    fn session_rejects_missing_header() {
+        let err = parse_session("molecule x\nend\n").unwrap_err();
+        assert!(err.to_string().contains("header"));
+    }
+}
@@ -1,6 +1,11 @@
 struct Camera {
     view_proj: mat4x4<f32>,
     camera_pos: vec4<f32>,
+    selected_color: vec4<f32>,
+    added_color: vec4<f32>,
+    modified_color: vec4<f32>,
+    match_color: vec4<f32>,
+    clash_color: vec4<f32>,
 };
 
 @group(0) @binding(0)
@@ -38,8 +43,15 @@ struct BondVertexOutput {
     @location(0) world_normal: vec3<f32>,
     @location(1) color: vec3<f32>,
     @location(2) flags: u32,
+    // Distance from the bond midpoint along its axis, in Å.
+    @location(3) along: f32,
 };
 
+// Dash period for coordination bonds, in Å.
+const DASH_LENGTH: f32 = 0.3;
+// Dot period for dotted bonds, in Å; a quarter of each period is drawn.
+const DOT_LENGTH: f32 = 0.16;
+
 @vertex
 fn vs_main(input: VertexInput) -> VertexOutput {
     var out: VertexOutput;
@@ -73,6 +85,7 @@ fn vs_bond(input: BondVertexInput) -> BondVertexOutput {
     out.world_normal = normalize(basis * input.normal);
     out.color = input.bond_color;
     out.flags = input.bond_flags;
+    out.along = scaled.y;
     return out;
 }
 
@@ -81,19 +94,46 @@ fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
     let light_dir = normalize(vec3<f32>(0.4, 0.8, 0.6));
     let diffuse = max(dot(input.world_normal, light_dir), 0.2);
     var color = input.color * diffuse;
+    if ((input.flags & 2u) == 2u) {
+        color = mix(color, camera.added_color.rgb, 0.5);
+    }
+    if ((input.flags & 4u) == 4u) {
+        color = mix(color, camera.modified_color.rgb, 0.5);
+    }
+    if ((input.flags & 8u) == 8u) {
+        color = mix(color, camera.match_color.rgb, 0.5);
+    }
+    if ((input.flags & 32u) == 32u) {
+        color = mix(color, camera.clash_color.rgb, 0.7);
+    }
     if ((input.flags & 1u) == 1u) {
-        color = mix(color, vec3<f32>(1.0, 0.8, 0.2), 0.6);
+        color = mix(color, camera.selected_color.rgb, 0.6);
     }
     return vec4<f32>(color, 1.0);
 }
 
 @fragment
 fn fs_bond(input: BondVertexOutput) -> @location(0) vec4<f32> {
+    if ((input.flags & 16u) == 16u && fract(input.along / DASH_LENGTH) > 0.5) {
+        discard;
+    }
+    if ((input.flags & 64u) == 64u && fract(input.along / DOT_LENGTH) > 0.25) {
+        discard;
+    }
     let light_dir = normalize(vec3<f32>(0.4, 0.8, 0.6));
     let diffuse = max(dot(input.world_normal, light_dir), 0.2);
     var color = input.color * diffuse;
+    if ((input.flags & 2u) == 2u) {
+        color = mix(color, camera.added_color.rgb, 0.5);
+    }
+    if ((input.flags & 4u) == 4u) {
+        color = mix(color, camera.modified_color.rgb, 0.5);
+    }
+    if ((input.flags & 8u) == 8u) {
+        color = mix(color, camera.match_color.rgb, 0.5);
+    }
     if ((input.flags & 1u) == 1u) {
-        color = mix(color, vec3<f32>(1.0, 0.8, 0.2), 0.6);
+        color = mix(color, camera.selected_color.rgb, 0.6);
     }
     return vec4<f32>(color, 1.0);
 }
@@ -0,0 +1,123 @@
+//! Guides for building by hand: the bond length and angle a position being placed or dragged
+//! would form, with snapping to ideal angles.
+
+use glam::Vec3;
+
+use crate::{AtomId, Molecule};
+
+/// Ideal sp3, sp2 and sp angles in degrees.
+pub const SNAP_ANGLES: [f32; 3] = [109.5, 120.0, 180.0];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BondGuide {
+    /// Atom the position bonds to.
+    pub anchor: AtomId,
+    pub length: f32,
+    /// Another neighbor of `anchor` and the angle in degrees it forms at `anchor` with the
+    /// position; `None` while the anchor has no other neighbor.
+    pub angle: Option<(AtomId, f32)>,
+}
+
+/// Bonded neighbor of `atom` closest to `position`, where `atom` is about to be.
+pub fn nearest_bonded(molecule: &Molecule, atom: AtomId, position: [f32; 3]) -> Option<AtomId> {
+    let position = Vec3::from_array(position);
+    molecule
+        .neighbors(atom)
+        .into_iter()
+        .filter_map(|id| {
+            let neighbor = molecule.get_atom(id)?;
+            Some((id, position.distance(Vec3::from_array(neighbor.position))))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(id, _)| id)
+}
+
+/// Length and angle `position` forms with `anchor`. `moving` is the atom being dragged, if
+/// any, so it is not taken as its own angle reference; the reference is the anchor's other
+/// neighbor closest to `position`.
+pub fn bond_guide(
+    molecule: &Molecule,
+    anchor: AtomId,
+    moving: Option<AtomId>,
+    position: [f32; 3],
+) -> Option<BondGuide> {
+    let center = Vec3::from_array(molecule.get_atom(anchor)?.position);
+    let position = Vec3::from_array(position);
+    let angle = molecule
+        .neighbors(anchor)
+        .into_iter()
+        .filter(|&id| Some(id) != moving)
+        .filter_map(|id| Some((id, Vec3::from_array(molecule.get_atom(id)?.position))))
+        .min_by(|a, b| position.distance(a.1).total_cmp(&position.distance(b.1)))
+        .and_then(|(id, reference)| {
+            let (u, d) = (reference - center, position - center);
+            (u.length_squared() > 0.0 && d.length_squared() > 0.0)
+                .then(|| (id, u.angle_between(d).to_degrees()))
+        });
+    Some(BondGuide {
+        anchor,
+        length: position.distance(center),
+        angle,
+    })
+}
+
+/// Rotates `position` about the guide's anchor, within the plane it forms with the angle
+/// reference, onto the nearest of `angles` that is within `tolerance` degrees. The bond
+/// length is kept. Returns `None` when nothing is close enough to snap.
+pub fn snap_angle(
+    molecule: &Molecule,
+    guide: &BondGuide,
+    position: [f32; 3],
+    angles: &[f32],
+    tolerance: f32,
+) -> Option<[f32; 3]> {
+    let (reference, angle) = guide.angle?;
+    let target = angles
+        .iter()
+        .copied()
+        .filter(|target| (target - angle).abs() <= tolerance)
+        .min_by(|a, b| (a - angle).abs().total_cmp(&(b - angle).abs()))?;
+    let center = Vec3::from_array(molecule.get_atom(guide.anchor)?.position);
+    let u = (Vec3::from_array(molecule.get_atom(reference)?.position) - center).normalize();
+    let d = Vec3::from_array(position) - center;
+    let v = (d - u * d.dot(u)).normalize_or_zero();
+    if v == Vec3::ZERO && target < 180.0 {
+        return None;
+    }
+    let theta = target.to_radians();
+    Some((center + (u * theta.cos() + v * theta.sin()) * d.length()).to_array())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snaps_to_the_nearest_ideal_angle() {
+        let mut molecule = Molecule::new("sketch");
+        let c = molecule.insert_atom("C".into(), [0.0; 3]);
+        let h = molecule.insert_atom("H".into(), [1.0, 0.0, 0.0]);
+        let moving = molecule.insert_atom("C".into(), [-1.0, 1.4, 0.0]);
+        molecule.add_bond(c, h).unwrap();
+        molecule.add_bond(c, moving).unwrap();
+
+        let position = [-0.8, 1.3, 0.0];
+        assert_eq!(nearest_bonded(&molecule, moving, position), Some(c));
+        let guide = bond_guide(&molecule, c, Some(moving), position).unwrap();
+        let (reference, angle) = guide.angle.unwrap();
+        assert_eq!(reference, h);
+        assert!((angle - 121.6).abs() < 0.1);
+        assert!((guide.length - 1.5264).abs() < 1e-3);
+
+        let snapped = snap_angle(&molecule, &guide, position, &SNAP_ANGLES, 5.0).unwrap();
+        let snapped_guide = bond_guide(&molecule, c, Some(moving), snapped).unwrap();
+        assert!((snapped_guide.angle.unwrap().1 - 120.0).abs() < 1e-3);
+        assert!((snapped_guide.length - guide.length).abs() < 1e-4);
+        assert!(snap_angle(&molecule, &guide, position, &[109.5], 5.0).is_none());
+
+        let position = [-1.5, 0.05, 0.0];
+        let guide = bond_guide(&molecule, c, Some(moving), position).unwrap();
+        let [x, y, _] = snap_angle(&molecule, &guide, position, &SNAP_ANGLES, 5.0).unwrap();
+        assert!(x < -1.49 && y.abs() < 1e-5);
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+/// Smallest cell edge in Å. Finer cells only add empty ones, and cells much smaller than
+/// the coordinates would overflow their integer keys.
+const MIN_CELL_SIZE: f32 = 0.1;
+
+/// Uniform hash grid over point indices; a query visits the 27 cells around a point, so
+/// lookups are exact for radii up to the cell size.
+#[derive(Debug, Clone)]
+pub(crate) struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<[i32; 3], Vec<usize>>,
+}
+
+impl SpatialGrid {
+    pub(crate) fn new(points: &[Vec3], cell_size: f32) -> Self {
+        let mut grid = Self {
+            cell_size: cell_size.max(MIN_CELL_SIZE),
+            cells: HashMap::new(),
+        };
+        for (index, point) in points.iter().enumerate() {
+            let key = grid.key(*point);
+            grid.cells.entry(key).or_default().push(index);
+        }
+        grid
+    }
+
+    fn key(&self, point: Vec3) -> [i32; 3] {
+        (point / self.cell_size).floor().as_ivec3().to_array()
+    }
+
+    /// Indices in the cells adjacent to `point`, a superset of those within one cell size.
+    pub(crate) fn candidates(&self, point: Vec3) -> impl Iterator<Item = usize> + '_ {
+        let [x, y, z] = self.key(point);
+        (-1..=1)
+            .flat_map(move |dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| (dx, dy, dz))))
+            .filter_map(move |(dx, dy, dz)| {
+                // Keys saturate at the ends of the i32 range, so no cell lies past them.
+                let key = [x.checked_add(dx)?, y.checked_add(dy)?, z.checked_add(dz)?];
+                self.cells.get(&key)
+            })
+            .flatten()
+            .copied()
+    }
+}
+
+/// A point and its index.
+type Node = (Vec3, usize);
+
+/// Balanced k-d tree over point indices, for nearest-point and radius queries at any range.
+/// Each node is the median of its slice on the axis of its depth, with the slices before and
+/// after it as its children, so the tree needs no links.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct KdTree {
+    nodes: Vec<Node>,
+}
+
+impl KdTree {
+    pub(crate) fn new(points: &[Vec3]) -> Self {
+        let mut nodes: Vec<Node> = points.iter().copied().zip(0..).collect();
+        build(&mut nodes, 0);
+        Self { nodes }
+    }
+
+    /// Index of the point closest to `point`; `None` when the tree is empty.
+    pub(crate) fn nearest(&self, point: Vec3) -> Option<usize> {
+        let mut best = None;
+        nearest_in(&self.nodes, 0, point, &mut best);
+        best.map(|(index, _)| index)
+    }
+
+    /// Indices of the points no further than `radius` from `point`, in no particular order.
+    pub(crate) fn within(&self, point: Vec3, radius: f32) -> Vec<usize> {
+        let mut found = Vec::new();
+        if radius >= 0.0 {
+            within_in(&self.nodes, 0, point, radius, &mut found);
+        }
+        found
+    }
+}
+
+fn build(nodes: &mut [Node], depth: usize) {
+    if nodes.len() <= 1 {
+        return;
+    }
+    let axis = depth % 3;
+    let middle = nodes.len() / 2;
+    nodes.select_nth_unstable_by(middle, |a, b| a.0[axis].total_cmp(&b.0[axis]));
+    let (before, after) = nodes.split_at_mut(middle);
+    build(before, depth + 1);
+    build(&mut after[1..], depth + 1);
+}
+
+/// Splits a slice into its median node and the children nearer to and farther from `point`,
+/// with `point`'s offset from the splitting plane.
+fn split(nodes: &[Node], depth: usize, point: Vec3) -> (Node, &[Node], &[Node], f32) {
+    let middle = nodes.len() / 2;
+    let node = nodes[middle];
+    let offset = point[depth % 3] - node.0[depth % 3];
+    let (before, after) = (&nodes[..middle], &nodes[middle + 1..]);
+    if offset < 0.0 {
+        (node, before, after, offset)
+    } else {
+        (node, after, before, offset)
+    }
+}
+
+fn nearest_in(nodes: &[Node], depth: usize, point: Vec3, best: &mut Option<(usize, f32)>) {
+    if nodes.is_empty() {
+        return;
+    }
+    let ((position, index), near, far, offset) = split(nodes, depth, point);
+    let distance_sq = position.distance_squared(point);
+    if best.is_none_or(|(_, best_sq)| distance_sq < best_sq) {
+        *best = Some((index, distance_sq));
+    }
+    nearest_in(near, depth + 1, point, best);
+    if best.is_none_or(|(_, best_sq)| offset * offset < best_sq) {
+        nearest_in(far, depth + 1, point, best);
+    }
+}
+
+fn within_in(nodes: &[Node], depth: usize, point: Vec3, radius: f32, found: &mut Vec<usize>) {
+    if nodes.is_empty() {
+        return;
+    }
+    let ((position, index), near, far, offset) = split(nodes, depth, point);
+    if position.distance_squared(point) <= radius * radius {
+        found.push(index);
+    }
+    within_in(near, depth + 1, point, radius, found);
+    if offset.abs() <= radius {
+        within_in(far, depth + 1, point, radius, found);
+    }
+}
@@ -0,0 +1,110 @@
+//! Counts and size estimates for the loaded structure, computed on demand for the status
+//! window and for scripts.
+
+use std::mem::size_of;
+
+use crate::{Atom, AtomId, Bond, BondId, Molecule};
+
+/// What the renderer holds for the structure; the library can't see the GPU, so the frontend
+/// fills this in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GpuUsage {
+    pub atom_instances: usize,
+    pub bond_instances: usize,
+    /// Bytes in instance buffers being drawn from.
+    pub buffer_bytes: u64,
+    /// Bytes in idle buffers kept for reuse.
+    pub pooled_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SceneStats {
+    pub atoms: usize,
+    pub bonds: usize,
+    /// Bond-connected fragments; a lone atom counts as one.
+    pub fragments: usize,
+    /// Atoms per element in Hill order.
+    pub elements: Vec<(String, usize)>,
+    /// Rough heap footprint of the molecule in bytes: atom and bond records with their index
+    /// entries, element symbols and per-atom property values. Hash table overhead is ignored.
+    pub molecule_bytes: usize,
+    pub gpu: Option<GpuUsage>,
+}
+
+impl SceneStats {
+    pub fn compute(molecule: &Molecule) -> Self {
+        let atoms = molecule.atom_count();
+        let bonds = molecule.bonds().count();
+        let symbols: usize = molecule
+            .atoms_in_order()
+            .map(|atom| atom.element.capacity())
+            .sum();
+        let properties: usize = molecule
+            .properties
+            .names()
+            .map(|name| {
+                let values: usize = molecule
+                    .atoms_in_order()
+                    .filter_map(|atom| molecule.properties.get(name, atom.id))
+                    .map(|value| size_of::<AtomId>() + value.len())
+                    .sum();
+                name.len() + values
+            })
+            .sum();
+        Self {
+            atoms,
+            bonds,
+            fragments: molecule.connected_components().len(),
+            elements: molecule.element_counts(),
+            // Each atom sits in the id map and the order list, each bond in the id map.
+            molecule_bytes: atoms * (size_of::<Atom>() + 2 * size_of::<AtomId>())
+                + bonds * (size_of::<Bond>() + size_of::<BondId>())
+                + symbols
+                + properties,
+            gpu: None,
+        }
+    }
+
+    pub fn with_gpu(self, gpu: GpuUsage) -> Self {
+        Self {
+            gpu: Some(gpu),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_fragments_and_elements() {
+        let mut molecule = Molecule::new("water and ion");
+        let o = molecule.insert_atom("O".to_string(), [0.0, 0.0, 0.0]);
+        for x in [0.96, -0.24] {
+            let h = molecule.insert_atom("H".to_string(), [x, 0.93, 0.0]);
+            molecule.add_bond(o, h).unwrap();
+        }
+        let na = molecule.insert_atom("Na".to_string(), [5.0, 0.0, 0.0]);
+
+        let stats = SceneStats::compute(&molecule);
+        assert_eq!((stats.atoms, stats.bonds, stats.fragments), (4, 2, 2));
+        assert_eq!(
+            stats.elements,
+            [
+                ("H".to_string(), 2),
+                ("Na".to_string(), 1),
+                ("O".to_string(), 1)
+            ]
+        );
+        assert_eq!(stats.gpu, None);
+
+        molecule.properties.set("label", na, "counter ion");
+        let labelled = SceneStats::compute(&molecule);
+        assert!(labelled.molecule_bytes > stats.molecule_bytes);
+        assert_eq!(
+            SceneStats::compute(&Molecule::new("empty")).molecule_bytes,
+            0
+        );
+    }
+}
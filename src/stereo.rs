@@ -0,0 +1,349 @@
+//! CIP priorities and R/S, E/Z descriptors perceived from 3D coordinates.
+//!
+//! Ranking applies rule 1a (higher atomic number first) sphere by sphere over the
+//! hierarchical digraph, with duplicate atoms for multiple bonds and ring closures. Siblings
+//! with the same atomic number are explored in id order rather than by their own subtrees,
+//! which only matters for unusually symmetric branches.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+use crate::elements::atomic_number;
+use crate::geometry::dihedral;
+use crate::{AtomId, BondId, Molecule};
+
+/// Exploration stops, and the branches count as equal, once a sphere holds this many nodes.
+const MAX_SPHERE_NODES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Chirality {
+    R,
+    S,
+}
+
+impl Chirality {
+    pub fn label(self) -> &'static str {
+        match self {
+            Chirality::R => "R",
+            Chirality::S => "S",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BondStereo {
+    E,
+    Z,
+}
+
+impl BondStereo {
+    pub fn label(self) -> &'static str {
+        match self {
+            BondStereo::E => "E",
+            BondStereo::Z => "Z",
+        }
+    }
+}
+
+impl Molecule {
+    /// Recomputes every atom's and bond's `stereo` from the current coordinates. Descriptors
+    /// are derived data: edits leave them stale until this runs again.
+    pub fn assign_stereo(&mut self) {
+        let digraph = Digraph::new(self);
+        let centers: HashMap<AtomId, Chirality> = self
+            .atoms
+            .keys()
+            .filter_map(|&id| Some((id, chirality(self, &digraph, id)?)))
+            .collect();
+        let ring_bonds = self.ring_bonds();
+        let double_bonds: HashMap<BondId, BondStereo> = self
+            .bonds
+            .values()
+            .filter(|bond| bond.order == 2 && !ring_bonds.contains(&bond.id))
+            .filter_map(|bond| Some((bond.id, bond_stereo(self, &digraph, bond.a, bond.b)?)))
+            .collect();
+        for atom in self.atoms.values_mut() {
+            atom.stereo = centers.get(&atom.id).copied();
+        }
+        for bond in self.bonds.values_mut() {
+            bond.stereo = double_bonds.get(&bond.id).copied();
+        }
+    }
+}
+
+/// Neighbors of `center` from highest to lowest CIP priority, or `None` when two of them tie.
+pub fn cip_ranking(molecule: &Molecule, center: AtomId) -> Option<Vec<AtomId>> {
+    rank(&Digraph::new(molecule), center, None)
+}
+
+fn chirality(molecule: &Molecule, digraph: &Digraph, center: AtomId) -> Option<Chirality> {
+    if digraph.neighbors(center).len() != 4 {
+        return None;
+    }
+    let ranked = rank(digraph, center, None)?;
+    let position = |id: AtomId| {
+        molecule
+            .get_atom(id)
+            .map(|atom| Vec3::from_array(atom.position))
+    };
+    let [a, b, c, d] = [
+        position(ranked[0])?,
+        position(ranked[1])?,
+        position(ranked[2])?,
+        position(ranked[3])?,
+    ];
+    // Seen with the lowest priority pointing away, a → b → c runs clockwise for R exactly
+    // when this volume is negative.
+    let volume = (a - d).dot((b - d).cross(c - d));
+    if volume.abs() < 1e-6 {
+        return None;
+    }
+    Some(if volume < 0.0 {
+        Chirality::R
+    } else {
+        Chirality::S
+    })
+}
+
+fn bond_stereo(molecule: &Molecule, digraph: &Digraph, a: AtomId, b: AtomId) -> Option<BondStereo> {
+    let highest = |end: AtomId, partner: AtomId| -> Option<AtomId> {
+        match digraph.neighbors(end).len() {
+            2 => digraph
+                .neighbors(end)
+                .iter()
+                .map(|&(id, _)| id)
+                .find(|&id| id != partner),
+            3 => rank(digraph, end, Some(partner)).map(|ranked| ranked[0]),
+            _ => None,
+        }
+    };
+    let (first, last) = (highest(a, b)?, highest(b, a)?);
+    let torsion = dihedral(molecule, first, a, b, last)?;
+    Some(if torsion.abs() < 90.0 {
+        BondStereo::Z
+    } else {
+        BondStereo::E
+    })
+}
+
+/// Neighbors of `center` other than `exclude`, highest priority first; `None` on a tie.
+fn rank(digraph: &Digraph, center: AtomId, exclude: Option<AtomId>) -> Option<Vec<AtomId>> {
+    let mut ranked: Vec<AtomId> = digraph
+        .neighbors(center)
+        .iter()
+        .map(|&(id, _)| id)
+        .filter(|&id| Some(id) != exclude)
+        .collect();
+    ranked.sort_by(|&x, &y| digraph.compare(center, y, x));
+    let tied = ranked
+        .windows(2)
+        .any(|pair| digraph.compare(center, pair[0], pair[1]) == Ordering::Equal);
+    (!tied).then_some(ranked)
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    atom: AtomId,
+    /// Ancestors from the stereocenter down to and including `atom`.
+    path: Vec<AtomId>,
+    /// Duplicate atoms stand in for multiple bonds and ring closures and have no children.
+    duplicate: bool,
+}
+
+struct Digraph {
+    neighbors: HashMap<AtomId, Vec<(AtomId, u8)>>,
+    atomic_numbers: HashMap<AtomId, u8>,
+}
+
+impl Digraph {
+    fn new(molecule: &Molecule) -> Self {
+        let mut neighbors: HashMap<AtomId, Vec<(AtomId, u8)>> = HashMap::new();
+        for bond in molecule.bonds() {
+            neighbors
+                .entry(bond.a)
+                .or_default()
+                .push((bond.b, bond.order));
+            neighbors
+                .entry(bond.b)
+                .or_default()
+                .push((bond.a, bond.order));
+        }
+        for list in neighbors.values_mut() {
+            list.sort();
+        }
+        let atomic_numbers = molecule
+            .atoms_in_order()
+            .map(|atom| (atom.id, atomic_number(&atom.element).unwrap_or(0)))
+            .collect();
+        Self {
+            neighbors,
+            atomic_numbers,
+        }
+    }
+
+    fn neighbors(&self, id: AtomId) -> &[(AtomId, u8)] {
+        self.neighbors.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    fn z(&self, node: &Node) -> u8 {
+        self.atomic_numbers.get(&node.atom).copied().unwrap_or(0)
+    }
+
+    fn children(&self, node: &Node) -> Vec<Node> {
+        if node.duplicate {
+            return Vec::new();
+        }
+        let parent = node.path[node.path.len() - 2];
+        let duplicate = |atom: AtomId| Node {
+            atom,
+            path: Vec::new(),
+            duplicate: true,
+        };
+        let mut children = Vec::new();
+        for &(atom, order) in self.neighbors(node.atom) {
+            let extra = usize::from(order.max(1) - 1);
+            if atom == parent {
+                children.extend((0..extra).map(|_| duplicate(atom)));
+                continue;
+            }
+            if node.path.contains(&atom) {
+                children.push(duplicate(atom));
+            } else {
+                let mut path = node.path.clone();
+                path.push(atom);
+                children.push(Node {
+                    atom,
+                    path,
+                    duplicate: false,
+                });
+            }
+            children.extend((0..extra).map(|_| duplicate(atom)));
+        }
+        children.sort_by_key(|child| std::cmp::Reverse(self.z(child)));
+        children
+    }
+
+    /// Priority of the branch `center → a` against `center → b`.
+    fn compare(&self, center: AtomId, a: AtomId, b: AtomId) -> Ordering {
+        let root = |center: AtomId, atom: AtomId| Node {
+            atom,
+            path: vec![center, atom],
+            duplicate: false,
+        };
+        let mut left = vec![root(center, a)];
+        let mut right = vec![root(center, b)];
+        match self.z(&left[0]).cmp(&self.z(&right[0])) {
+            Ordering::Equal => {}
+            other => return other,
+        }
+        while !(left.is_empty() && right.is_empty()) {
+            if left.len().max(right.len()) > MAX_SPHERE_NODES {
+                break;
+            }
+            let left_groups: Vec<Vec<Node>> = left.iter().map(|node| self.children(node)).collect();
+            let right_groups: Vec<Vec<Node>> =
+                right.iter().map(|node| self.children(node)).collect();
+            for index in 0..left_groups.len().max(right_groups.len()) {
+                let zs = |groups: &[Vec<Node>]| -> Vec<u8> {
+                    groups
+                        .get(index)
+                        .map(|group| group.iter().map(|node| self.z(node)).collect())
+                        .unwrap_or_default()
+                };
+                let (mut x, mut y) = (zs(&left_groups), zs(&right_groups));
+                let length = x.len().max(y.len());
+                x.resize(length, 0);
+                y.resize(length, 0);
+                match x.cmp(&y) {
+                    Ordering::Equal => {}
+                    other => return other,
+                }
+            }
+            left = left_groups.into_iter().flatten().collect();
+            right = right_groups.into_iter().flatten().collect();
+        }
+        Ordering::Equal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tetrahedral directions; the last points down -Z.
+    const TETRAHEDRON: [[f32; 3]; 4] = [
+        [0.943, 0.0, 0.333],
+        [-0.471, -0.816, 0.333],
+        [-0.471, 0.816, 0.333],
+        [0.0, 0.0, -1.0],
+    ];
+
+    fn center_with(elements: [&str; 4]) -> (Molecule, AtomId, Vec<AtomId>) {
+        let mut molecule = Molecule::new("center");
+        let center = molecule.insert_atom("C".into(), [0.0; 3]);
+        let mut ligands = Vec::new();
+        for (element, direction) in elements.iter().zip(TETRAHEDRON) {
+            let position = (Vec3::from_array(direction) * 1.5).to_array();
+            let id = molecule.insert_atom(element.to_string(), position);
+            molecule.add_bond(center, id).unwrap();
+            ligands.push(id);
+        }
+        (molecule, center, ligands)
+    }
+
+    #[test]
+    fn assigns_r_and_s_from_coordinates() {
+        // Br, Cl, F clockwise seen from above with H pointing down.
+        let (mut molecule, center, _) = center_with(["Br", "Cl", "F", "H"]);
+        molecule.assign_stereo();
+        assert_eq!(
+            molecule.get_atom(center).unwrap().stereo,
+            Some(Chirality::R)
+        );
+        let (mut mirror, center, _) = center_with(["Br", "F", "Cl", "H"]);
+        mirror.assign_stereo();
+        assert_eq!(mirror.get_atom(center).unwrap().stereo, Some(Chirality::S));
+
+        // 2-butanol: ethyl outranks methyl only in the second sphere.
+        let (mut butanol, center, ligands) = center_with(["O", "C", "C", "H"]);
+        let methyl_end = butanol.insert_atom("C".into(), [-1.0, -2.5, 1.0]);
+        butanol.add_bond(ligands[1], methyl_end).unwrap();
+        assert_eq!(
+            cip_ranking(&butanol, center).unwrap(),
+            [ligands[0], ligands[1], ligands[2], ligands[3]]
+        );
+        butanol.assign_stereo();
+        assert_eq!(butanol.get_atom(center).unwrap().stereo, Some(Chirality::R));
+
+        let (mut symmetric, center, _) = center_with(["C", "C", "F", "H"]);
+        symmetric.assign_stereo();
+        assert_eq!(symmetric.get_atom(center).unwrap().stereo, None);
+    }
+
+    #[test]
+    fn assigns_e_and_z_to_double_bonds() {
+        let mut butene = Molecule::new("2-butene");
+        let c1 = butene.insert_atom("C".into(), [-1.2, 1.2, 0.0]);
+        let c2 = butene.insert_atom("C".into(), [-0.67, 0.0, 0.0]);
+        let c3 = butene.insert_atom("C".into(), [0.67, 0.0, 0.0]);
+        let c4 = butene.insert_atom("C".into(), [1.2, 1.2, 0.0]);
+        butene.add_bond(c1, c2).unwrap();
+        let double = butene.add_bond(c2, c3).unwrap();
+        butene.add_bond(c3, c4).unwrap();
+        butene.set_bond_order(double, 2);
+        butene.assign_stereo();
+        let stereo = |molecule: &Molecule| {
+            molecule
+                .bonds()
+                .find(|bond| bond.id == double)
+                .unwrap()
+                .stereo
+        };
+        assert_eq!(stereo(&butene), Some(BondStereo::Z));
+        butene.set_atom_position(c4, [1.2, -1.2, 0.0]);
+        butene.assign_stereo();
+        assert_eq!(stereo(&butene), Some(BondStereo::E));
+    }
+}
@@ -0,0 +1,139 @@
+//! Binary STL export for 3D-printed teaching models.
+//!
+//! Every atom and bond becomes its own closed shell (capped cylinders, sphere poles and seams
+//! welded), and shells overlap where bonds meet atoms; slicers merge overlapping closed shells
+//! into one solid.
+
+use glam::{Quat, Vec3};
+
+use crate::mesh::Mesh;
+use crate::scene::Scene;
+
+const SPHERE_SEGMENTS: u32 = 48;
+const SPHERE_RINGS: u32 = 24;
+const CYLINDER_SEGMENTS: u32 = 32;
+const HEADER_BYTES: usize = 80;
+const TRIANGLE_BYTES: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrintSettings {
+    /// Millimetres per Å.
+    pub scale: f32,
+    /// Bonds thinner than this, in mm, are thickened so the struts survive printing.
+    pub min_strut_diameter: f32,
+}
+
+impl Default for PrintSettings {
+    fn default() -> Self {
+        Self {
+            scale: 10.0,
+            min_strut_diameter: 3.0,
+        }
+    }
+}
+
+/// Triangles in millimetres, wound counter-clockwise seen from outside.
+pub fn print_triangles(scene: &Scene, settings: &PrintSettings) -> Vec<[Vec3; 3]> {
+    let sphere = Mesh::sphere(SPHERE_SEGMENTS, SPHERE_RINGS);
+    let cylinder = Mesh::cylinder(CYLINDER_SEGMENTS, true);
+    let mut triangles = Vec::new();
+    for atom in &scene.spheres {
+        let center = Vec3::from(atom.center) * settings.scale;
+        let radius = atom.radius * settings.scale;
+        triangles.extend(
+            sphere
+                .triangles()
+                .map(|corners| corners.map(|point| center + Vec3::from(point) * radius)),
+        );
+    }
+    for bond in &scene.cylinders {
+        let (start, end) = (
+            Vec3::from(bond.start) * settings.scale,
+            Vec3::from(bond.end) * settings.scale,
+        );
+        let length = start.distance(end);
+        if length <= f32::EPSILON {
+            continue;
+        }
+        let radius = (bond.radius * settings.scale).max(settings.min_strut_diameter * 0.5);
+        let rotation = Quat::from_rotation_arc(Vec3::Y, (end - start) / length);
+        let midpoint = (start + end) * 0.5;
+        let size = Vec3::new(radius, length, radius);
+        triangles.extend(
+            cylinder.triangles().map(|corners| {
+                corners.map(|point| midpoint + rotation * (Vec3::from(point) * size))
+            }),
+        );
+    }
+    triangles
+}
+
+pub fn write_stl(scene: &Scene, settings: &PrintSettings) -> Vec<u8> {
+    let triangles = print_triangles(scene, settings);
+    let mut out = Vec::with_capacity(HEADER_BYTES + 4 + triangles.len() * TRIANGLE_BYTES);
+    out.extend_from_slice(b"MolWeaver model, millimetres");
+    out.resize(HEADER_BYTES, b' ');
+    out.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+    for [a, b, c] in triangles {
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        for vector in [normal, a, b, c] {
+            for value in vector.to_array() {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        out.extend_from_slice(&0u16.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::scene::{Cylinder, SceneCamera, Sphere};
+
+    #[test]
+    fn shells_are_closed_and_struts_respect_the_minimum() {
+        let scene = Scene {
+            spheres: vec![Sphere {
+                center: [0.3, -0.2, 0.1],
+                radius: 0.5,
+                color: [1.0; 3],
+            }],
+            cylinders: vec![Cylinder {
+                start: [0.0; 3],
+                end: [0.6, 1.2, -0.4],
+                radius: 0.05,
+                color: [1.0; 3],
+            }],
+            camera: SceneCamera::framing(&[], 45.0, 1.0),
+        };
+        let settings = PrintSettings::default();
+        let triangles = print_triangles(&scene, &settings);
+        // Each directed edge must be matched by the reverse edge of a neighboring triangle.
+        let key = |point: Vec3| point.to_array().map(|value| (value + 0.0).to_bits());
+        let mut edges: HashMap<_, i32> = HashMap::new();
+        for triangle in &triangles {
+            for i in 0..3 {
+                let (from, to) = (key(triangle[i]), key(triangle[(i + 1) % 3]));
+                *edges.entry((from, to)).or_default() += 1;
+                *edges.entry((to, from)).or_default() -= 1;
+            }
+        }
+        assert!(edges.values().all(|&count| count == 0));
+
+        let axis = Vec3::new(0.6, 1.2, -0.4).normalize() * 10.0;
+        let widest = triangles[SPHERE_SEGMENTS as usize * (SPHERE_RINGS as usize - 1) * 2..]
+            .iter()
+            .flatten()
+            .map(|point| point.reject_from(axis).length())
+            .fold(0.0, f32::max);
+        assert!((widest - settings.min_strut_diameter * 0.5).abs() < 1e-3);
+
+        let stl = write_stl(&scene, &settings);
+        let count = u32::from_le_bytes(stl[80..84].try_into().unwrap()) as usize;
+        assert_eq!(count, triangles.len());
+        assert_eq!(stl.len(), HEADER_BYTES + 4 + count * TRIANGLE_BYTES);
+    }
+}
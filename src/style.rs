@@ -0,0 +1,660 @@
+//! Per-element, per-atom and per-bond appearance overrides, and the rules that set them from
+//! what an atom is rather than which atom it is.
+//!
+//! A rule is one line, `condition -> actions`:
+//!
+//! ```text
+//! element == Fe -> radius 1.2, color orange
+//! fragment size < 4 -> hide
+//! element != C and charge > 0 -> color 0.2 0.4 1
+//! ```
+//!
+//! Conditions test `element` (`==`, `!=`), `charge` or `fragment size` (any of `==`, `!=`,
+//! `<`, `<=`, `>`, `>=`), joined with `and`. Actions are `radius` with a scale, `color` with
+//! a name, `#rrggbb` or three components, and `hide`. Later rules win over earlier ones.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use crate::elements::normalize_symbol;
+use crate::palette::Palette;
+use crate::scene::COORDINATION_RADIUS_SCALE;
+use crate::{Atom, AtomId, Bond, BondId, BondKind, Molecule};
+
+/// Colors `color` accepts by name in a rule.
+const COLOR_NAMES: [(&str, [f32; 3]); 13] = [
+    ("black", [0.0, 0.0, 0.0]),
+    ("blue", [0.2, 0.3, 1.0]),
+    ("brown", [0.6, 0.35, 0.15]),
+    ("cyan", [0.0, 0.9, 0.9]),
+    ("gray", [0.5, 0.5, 0.5]),
+    ("green", [0.2, 0.8, 0.2]),
+    ("grey", [0.5, 0.5, 0.5]),
+    ("magenta", [0.9, 0.2, 0.9]),
+    ("orange", [1.0, 0.55, 0.0]),
+    ("pink", [1.0, 0.6, 0.75]),
+    ("red", [0.9, 0.1, 0.1]),
+    ("white", [1.0, 1.0, 1.0]),
+    ("yellow", [1.0, 0.9, 0.1]),
+];
+
+#[derive(Debug, Clone)]
+pub struct RuleError {
+    details: String,
+}
+
+impl RuleError {
+    fn new(line: usize, details: impl Into<String>) -> Self {
+        Self {
+            details: format!("line {line}: {}", details.into()),
+        }
+    }
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+/// Appearance changes for one element or atom; unset fields fall back to the palette and
+/// representation defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StyleOverride {
+    pub color: Option<[f32; 3]>,
+    /// Multiplier on the representation's sphere radius.
+    pub radius_scale: Option<f32>,
+}
+
+impl StyleOverride {
+    pub fn is_empty(&self) -> bool {
+        self.color.is_none() && self.radius_scale.is_none()
+    }
+}
+
+/// How a bond's cylinder is broken up along its length.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BondDash {
+    #[default]
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+impl BondDash {
+    pub const ALL: [BondDash; 3] = [BondDash::Solid, BondDash::Dashed, BondDash::Dotted];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BondDash::Solid => "Solid",
+            BondDash::Dashed => "Dashed",
+            BondDash::Dotted => "Dotted",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|dash| dash.label().eq_ignore_ascii_case(text))
+    }
+}
+
+/// Appearance changes for one bond, e.g. the forming bond of a transition state; unset fields
+/// fall back to the palette and the bond kind's look.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BondStyle {
+    pub color: Option<[f32; 3]>,
+    /// Multiplier on the bond radius, on top of the thinner coordination bonds.
+    pub radius_scale: Option<f32>,
+    pub dash: Option<BondDash>,
+}
+
+impl BondStyle {
+    pub fn is_empty(&self) -> bool {
+        self.color.is_none() && self.radius_scale.is_none() && self.dash.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn parse(token: &str) -> Option<Self> {
+        Some(match token {
+            "==" | "=" => Comparison::Eq,
+            "!=" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            "<=" => Comparison::Le,
+            ">" => Comparison::Gt,
+            ">=" => Comparison::Ge,
+            _ => return None,
+        })
+    }
+
+    fn holds<T: PartialOrd>(self, left: T, right: T) -> bool {
+        match self {
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
+            Comparison::Lt => left < right,
+            Comparison::Le => left <= right,
+            Comparison::Gt => left > right,
+            Comparison::Ge => left >= right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Element(Comparison, String),
+    Charge(Comparison, i32),
+    FragmentSize(Comparison, usize),
+}
+
+/// One `condition -> actions` line; see the module docs for the syntax.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleRule {
+    source: String,
+    conditions: Vec<Condition>,
+    style: StyleOverride,
+    hide: bool,
+}
+
+impl StyleRule {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let source = text.trim();
+        let (condition, actions) = source
+            .split_once("->")
+            .or_else(|| source.split_once('→'))
+            .ok_or("expected condition -> actions")?;
+        let mut conditions = Vec::new();
+        for clause in condition.split(" and ") {
+            conditions.push(parse_condition(clause)?);
+        }
+        let mut rule = StyleRule {
+            source: source.to_string(),
+            conditions,
+            style: StyleOverride::default(),
+            hide: false,
+        };
+        for action in actions.split(',') {
+            let mut words = action.split_whitespace();
+            match words.next() {
+                Some("hide") => rule.hide = true,
+                Some("radius") => {
+                    let scale = words
+                        .next()
+                        .and_then(|word| word.parse::<f32>().ok())
+                        .filter(|scale| *scale > 0.0)
+                        .ok_or("radius needs a positive scale")?;
+                    rule.style.radius_scale = Some(scale);
+                }
+                Some("color") => {
+                    rule.style.color = Some(parse_color(&words.collect::<Vec<_>>())?);
+                    continue;
+                }
+                Some(other) => return Err(format!("unknown action {other}")),
+                None => return Err("empty action".into()),
+            }
+            if let Some(extra) = words.next() {
+                return Err(format!("unexpected {extra}"));
+            }
+        }
+        Ok(rule)
+    }
+
+    fn matches(&self, atom: &Atom, fragment_size: usize) -> bool {
+        self.conditions.iter().all(|condition| match condition {
+            Condition::Element(comparison, symbol) => {
+                comparison.holds(normalize_symbol(&atom.element).as_str(), symbol.as_str())
+            }
+            Condition::Charge(comparison, charge) => comparison.holds(atom.charge, *charge),
+            Condition::FragmentSize(comparison, size) => comparison.holds(fragment_size, *size),
+        })
+    }
+}
+
+impl fmt::Display for StyleRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Parses one rule per line; blank lines and lines starting with `#` are skipped.
+pub fn parse_rules(text: &str) -> Result<Vec<StyleRule>, RuleError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(index, line)| StyleRule::parse(line).map_err(|err| RuleError::new(index + 1, err)))
+        .collect()
+}
+
+fn parse_condition(clause: &str) -> Result<Condition, String> {
+    let words: Vec<&str> = clause.split_whitespace().collect();
+    let (subject, rest) = match words.as_slice() {
+        ["fragment", "size", rest @ ..] => ("fragment size", rest),
+        [subject, rest @ ..] => (*subject, rest),
+        [] => return Err("empty condition".into()),
+    };
+    let [operator, value] = rest else {
+        return Err(format!("expected {subject} <comparison> <value>"));
+    };
+    let comparison =
+        Comparison::parse(operator).ok_or_else(|| format!("unknown comparison {operator}"))?;
+    let number = |value: &str| format!("{subject} needs a whole number, not {value}");
+    match subject {
+        "element" if matches!(comparison, Comparison::Eq | Comparison::Ne) => {
+            Ok(Condition::Element(comparison, normalize_symbol(value)))
+        }
+        "element" => Err("element only compares with == or !=".into()),
+        "charge" => value
+            .parse()
+            .map(|charge| Condition::Charge(comparison, charge))
+            .map_err(|_| number(value)),
+        "fragment size" => value
+            .parse()
+            .map(|size| Condition::FragmentSize(comparison, size))
+            .map_err(|_| number(value)),
+        other => Err(format!("unknown property {other}")),
+    }
+}
+
+fn parse_color(words: &[&str]) -> Result<[f32; 3], String> {
+    match words {
+        [name] if name.starts_with('#') => {
+            let channel = |range: std::ops::Range<usize>| {
+                let hex = name.get(range).filter(|_| name.len() == 7).unwrap_or("?");
+                u8::from_str_radix(hex, 16).map(|value| value as f32 / 255.0)
+            };
+            match (channel(1..3), channel(3..5), channel(5..7)) {
+                (Ok(r), Ok(g), Ok(b)) => Ok([r, g, b]),
+                _ => Err(format!("bad hex color {name}")),
+            }
+        }
+        [name] => COLOR_NAMES
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(name))
+            .map(|(_, rgb)| *rgb)
+            .ok_or_else(|| format!("unknown color {name}")),
+        [r, g, b] => {
+            let mut rgb = [0.0; 3];
+            for (channel, word) in rgb.iter_mut().zip([r, g, b]) {
+                *channel = word
+                    .parse::<f32>()
+                    .map_err(|_| format!("bad color component {word}"))?
+                    .clamp(0.0, 1.0);
+            }
+            Ok(rgb)
+        }
+        _ => Err("color needs a name, #rrggbb or three components".into()),
+    }
+}
+
+/// Project-wide appearance overrides; per-atom entries win over rule matches, which win over
+/// per-element ones.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StyleOverrides {
+    pub elements: BTreeMap<String, StyleOverride>,
+    pub atoms: BTreeMap<AtomId, StyleOverride>,
+    pub bonds: BTreeMap<BondId, BondStyle>,
+    /// Applied to the molecule by [`StyleOverrides::apply_rules`].
+    pub rules: Vec<StyleRule>,
+    ruled: BTreeMap<AtomId, StyleOverride>,
+    hidden: BTreeSet<AtomId>,
+}
+
+impl StyleOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty() && self.atoms.is_empty() && self.bonds.is_empty()
+    }
+
+    /// Re-evaluates the rules against `molecule`; call after loading or editing it.
+    pub fn apply_rules(&mut self, molecule: &Molecule) {
+        self.ruled.clear();
+        self.hidden.clear();
+        if self.rules.is_empty() {
+            return;
+        }
+        let mut fragment_sizes = HashMap::new();
+        if self.rules_use_fragments() {
+            for component in molecule.connected_components() {
+                for &id in &component {
+                    fragment_sizes.insert(id, component.len());
+                }
+            }
+        }
+        for atom in molecule.atoms_in_order() {
+            let size = fragment_sizes.get(&atom.id).copied().unwrap_or(1);
+            self.apply_rules_to_atom(atom, size);
+        }
+    }
+
+    /// Whether a rule tests fragment size, which a bond edit changes for a whole fragment.
+    pub fn rules_use_fragments(&self) -> bool {
+        self.rules.iter().any(|rule| {
+            rule.conditions
+                .iter()
+                .any(|condition| matches!(condition, Condition::FragmentSize(..)))
+        })
+    }
+
+    /// Re-evaluates the rules for `atoms` only, after an edit that touched just them; with
+    /// fragment-size rules, for their whole fragments. Atoms gone from `molecule` lose their
+    /// ruled style. Returns the atoms whose ruled style or visibility changed.
+    pub fn apply_rules_to(&mut self, molecule: &Molecule, atoms: &[AtomId]) -> Vec<AtomId> {
+        let mut fragment_sizes: HashMap<AtomId, usize> = HashMap::new();
+        let mut touched: Vec<AtomId> = Vec::new();
+        for &id in atoms {
+            if fragment_sizes.contains_key(&id) {
+                continue;
+            }
+            if self.rules_use_fragments() && molecule.get_atom(id).is_some() {
+                let fragment: Vec<AtomId> = molecule.bfs(id).collect();
+                for &member in &fragment {
+                    fragment_sizes.insert(member, fragment.len());
+                }
+                touched.extend(fragment);
+            } else {
+                fragment_sizes.insert(id, 1);
+                touched.push(id);
+            }
+        }
+        let mut changed = Vec::new();
+        for id in touched {
+            let before = (self.ruled.remove(&id), self.hidden.remove(&id));
+            if let Some(atom) = molecule.get_atom(id) {
+                self.apply_rules_to_atom(atom, fragment_sizes[&id]);
+            }
+            if before != (self.ruled.get(&id).copied(), self.hidden.contains(&id)) {
+                changed.push(id);
+            }
+        }
+        changed
+    }
+
+    fn apply_rules_to_atom(&mut self, atom: &Atom, fragment_size: usize) {
+        let mut style = StyleOverride::default();
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(atom, fragment_size))
+        {
+            style.color = rule.style.color.or(style.color);
+            style.radius_scale = rule.style.radius_scale.or(style.radius_scale);
+            if rule.hide {
+                self.hidden.insert(atom.id);
+            }
+        }
+        if !style.is_empty() {
+            self.ruled.insert(atom.id, style);
+        }
+    }
+
+    pub fn is_hidden(&self, id: AtomId) -> bool {
+        self.hidden.contains(&id)
+    }
+
+    pub fn hidden_count(&self) -> usize {
+        self.hidden.len()
+    }
+
+    pub fn set_element(&mut self, element: &str, style: StyleOverride) {
+        let key = normalize_symbol(element);
+        if style.is_empty() {
+            self.elements.remove(&key);
+        } else {
+            self.elements.insert(key, style);
+        }
+    }
+
+    pub fn set_atom(&mut self, id: AtomId, style: StyleOverride) {
+        if style.is_empty() {
+            self.atoms.remove(&id);
+        } else {
+            self.atoms.insert(id, style);
+        }
+    }
+
+    pub fn set_bond(&mut self, id: BondId, style: BondStyle) {
+        if style.is_empty() {
+            self.bonds.remove(&id);
+        } else {
+            self.bonds.insert(id, style);
+        }
+    }
+
+    pub fn bond_color(&self, id: BondId, palette: Palette) -> [f32; 3] {
+        self.bonds
+            .get(&id)
+            .and_then(|style| style.color)
+            .unwrap_or_else(|| palette.bond_color())
+    }
+
+    /// `base` scaled for the bond's kind and override; zero when a rule hides either end.
+    pub fn bond_radius(&self, bond: &Bond, base: f32) -> f32 {
+        if self.is_hidden(bond.a) || self.is_hidden(bond.b) {
+            return 0.0;
+        }
+        let kind = match bond.kind {
+            BondKind::Covalent => 1.0,
+            BondKind::Coordination => COORDINATION_RADIUS_SCALE,
+        };
+        let scale = self
+            .bonds
+            .get(&bond.id)
+            .and_then(|style| style.radius_scale);
+        base * kind * scale.unwrap_or(1.0)
+    }
+
+    /// The override's dash, else dashed for coordination bonds and solid otherwise.
+    pub fn bond_dash(&self, bond: &Bond) -> BondDash {
+        self.bonds
+            .get(&bond.id)
+            .and_then(|style| style.dash)
+            .unwrap_or(match bond.kind {
+                BondKind::Covalent => BondDash::Solid,
+                BondKind::Coordination => BondDash::Dashed,
+            })
+    }
+
+    pub fn color(&self, atom: &Atom, palette: Palette) -> [f32; 3] {
+        self.lookup(atom, |style| style.color)
+            .unwrap_or_else(|| palette.element_color(&atom.element))
+    }
+
+    /// Zero for atoms a rule hides.
+    pub fn radius(&self, atom: &Atom, base: f32) -> f32 {
+        if self.is_hidden(atom.id) {
+            return 0.0;
+        }
+        base * self.lookup(atom, |style| style.radius_scale).unwrap_or(1.0)
+    }
+
+    fn lookup<T>(&self, atom: &Atom, field: impl Fn(&StyleOverride) -> Option<T>) -> Option<T> {
+        self.atoms
+            .get(&atom.id)
+            .and_then(&field)
+            .or_else(|| self.ruled.get(&atom.id).and_then(&field))
+            .or_else(|| {
+                self.elements
+                    .get(&normalize_symbol(&atom.element))
+                    .and_then(&field)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atom_overrides_win_over_elements() {
+        let carbon = Atom {
+            id: AtomId::new(3),
+            element: "c".into(),
+            position: [0.0; 3],
+            charge: 0,
+            stereo: None,
+        };
+        let mut styles = StyleOverrides::default();
+        styles.set_element(
+            "C",
+            StyleOverride {
+                color: Some([0.1, 0.2, 0.3]),
+                radius_scale: Some(2.0),
+            },
+        );
+        assert_eq!(styles.color(&carbon, Palette::Standard), [0.1, 0.2, 0.3]);
+        assert_eq!(styles.radius(&carbon, 0.5), 1.0);
+        styles.set_atom(
+            carbon.id,
+            StyleOverride {
+                color: Some([1.0, 0.5, 0.0]),
+                radius_scale: None,
+            },
+        );
+        assert_eq!(styles.color(&carbon, Palette::Standard), [1.0, 0.5, 0.0]);
+        assert_eq!(styles.radius(&carbon, 0.5), 1.0);
+        styles.set_element("C", StyleOverride::default());
+        assert_eq!(styles.radius(&carbon, 0.5), 0.5);
+        assert!(!styles.is_empty());
+    }
+
+    #[test]
+    fn rules_style_and_hide_matching_atoms() {
+        let mut molecule = Molecule::new("complex");
+        let fe = molecule.insert_atom("Fe".to_string(), [0.0; 3]);
+        let o = molecule.insert_atom("O".to_string(), [5.0, 0.0, 0.0]);
+        for x in [5.96, 4.76] {
+            let h = molecule.insert_atom("H".to_string(), [x, 0.93, 0.0]);
+            molecule.add_bond(o, h).unwrap();
+        }
+        let mut styles = StyleOverrides {
+            rules: parse_rules(
+                "# metals stand out\n\
+                 element == Fe -> radius 1.2, color orange\n\
+                 fragment size < 4 and element != Fe -> hide\n",
+            )
+            .unwrap(),
+            ..StyleOverrides::default()
+        };
+        styles.apply_rules(&molecule);
+        let iron = molecule.get_atom(fe).unwrap();
+        assert_eq!(styles.color(iron, Palette::Standard), [1.0, 0.55, 0.0]);
+        assert_eq!(styles.radius(iron, 0.5), 0.6);
+        assert!(styles.is_hidden(o) && !styles.is_hidden(fe));
+        assert_eq!(styles.hidden_count(), 3);
+        assert_eq!(
+            styles.rules[1].to_string(),
+            "fragment size < 4 and element != Fe -> hide"
+        );
+
+        let err = parse_rules("charge > 0 -> color #12345\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: bad hex color #12345");
+        assert!(StyleRule::parse("mass > 3 -> hide").is_err());
+    }
+
+    #[test]
+    fn rules_reapply_to_edited_atoms_and_their_fragments() {
+        let mut molecule = Molecule::new("complex");
+        let fe = molecule.insert_atom("Fe".to_string(), [0.0; 3]);
+        let n = molecule.insert_atom("N".to_string(), [2.0, 0.0, 0.0]);
+        let hs: Vec<AtomId> = [2.96, 1.76]
+            .into_iter()
+            .map(|x| {
+                let h = molecule.insert_atom("H".to_string(), [x, 0.93, 0.0]);
+                molecule.add_bond(n, h).unwrap();
+                h
+            })
+            .collect();
+        let mut styles = StyleOverrides {
+            rules: parse_rules(
+                "fragment size < 4 -> hide
+charge > 0 -> color blue
+",
+            )
+            .unwrap(),
+            ..StyleOverrides::default()
+        };
+        styles.apply_rules(&molecule);
+        assert_eq!(styles.hidden_count(), 4);
+
+        let bond = molecule.add_bond(fe, n).unwrap();
+        molecule
+            .set_bond_kind(bond, BondKind::Coordination)
+            .unwrap();
+        let mut changed = styles.apply_rules_to(&molecule, &[fe, n]);
+        changed.sort();
+        assert_eq!(changed, [fe, n, hs[0], hs[1]]);
+        assert_eq!(styles.hidden_count(), 0);
+
+        molecule.set_formal_charge(fe, 2);
+        assert_eq!(styles.apply_rules_to(&molecule, &[fe]), [fe]);
+        let mut full = styles.clone();
+        full.apply_rules(&molecule);
+        assert_eq!(full, styles);
+
+        // Losing a hydrogen shrinks the fragment below four again.
+        molecule.remove_atom(hs[0]);
+        let mut changed = styles.apply_rules_to(&molecule, &[hs[0], n]);
+        changed.sort();
+        assert_eq!(changed, [fe, n, hs[1]]);
+        assert_eq!(styles.hidden_count(), 3);
+        assert!(styles.apply_rules_to(&molecule, &[]).is_empty());
+    }
+
+    #[test]
+    fn bond_overrides_restyle_one_bond() {
+        let mut molecule = Molecule::new("ts");
+        let c = molecule.insert_atom("C".to_string(), [0.0; 3]);
+        let o = molecule.insert_atom("O".to_string(), [2.1, 0.0, 0.0]);
+        let na = molecule.insert_atom("Na".to_string(), [-2.4, 0.0, 0.0]);
+        let forming = molecule.add_bond(c, o).unwrap();
+        let dative = molecule.add_coordination_bond(c, na).unwrap();
+        let bond = |id| molecule.bonds().find(|bond| bond.id == id).unwrap().clone();
+        let mut styles = StyleOverrides::default();
+        assert_eq!(styles.bond_dash(&bond(forming)), BondDash::Solid);
+        assert_eq!(styles.bond_dash(&bond(dative)), BondDash::Dashed);
+        assert_eq!(styles.bond_radius(&bond(dative), 0.2), 0.1);
+
+        styles.set_bond(
+            forming,
+            BondStyle {
+                color: Some([1.0, 0.0, 0.0]),
+                radius_scale: Some(1.5),
+                dash: Some(BondDash::Dotted),
+            },
+        );
+        styles.set_bond(
+            dative,
+            BondStyle {
+                dash: Some(BondDash::Solid),
+                ..BondStyle::default()
+            },
+        );
+        assert!(!styles.is_empty());
+        assert_eq!(
+            styles.bond_color(forming, Palette::Standard),
+            [1.0, 0.0, 0.0]
+        );
+        assert_eq!(
+            styles.bond_color(dative, Palette::Standard),
+            Palette::Standard.bond_color()
+        );
+        assert!((styles.bond_radius(&bond(forming), 0.2) - 0.3).abs() < 1e-6);
+        assert_eq!(styles.bond_dash(&bond(forming)), BondDash::Dotted);
+        assert_eq!(styles.bond_dash(&bond(dative)), BondDash::Solid);
+        assert_eq!(BondDash::parse("dotted"), Some(BondDash::Dotted));
+
+        styles.set_bond(dative, BondStyle::default());
+        assert_eq!(styles.bonds.len(), 1);
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::elements::normalize_symbol;
+use crate::{Atom, AtomId, Bond, BondId, Molecule};
+
+/// One embedding of a pattern: pattern atom id to target atom id.
+pub type AtomMapping = HashMap<AtomId, AtomId>;
+
+/// Whether `target` contains `pattern` as a bond-preserving subgraph with matching elements.
+/// Bond orders are not compared.
+pub fn contains_substructure(pattern: &Molecule, target: &Molecule) -> bool {
+    !find_substructures(pattern, target, 1).is_empty()
+}
+
+/// Up to `limit` embeddings of `pattern` in `target`. Extra target bonds between matched atoms
+/// are allowed, and symmetric embeddings of the same atoms are all reported.
+pub fn find_substructures(pattern: &Molecule, target: &Molecule, limit: usize) -> Vec<AtomMapping> {
+    find_matches(
+        pattern,
+        target,
+        limit,
+        |query, atom| normalize_symbol(&query.element) == normalize_symbol(&atom.element),
+        |_, _| true,
+    )
+}
+
+/// Target bonds covered by a mapping, for highlighting a match.
+pub fn matched_bonds(pattern: &Molecule, target: &Molecule, mapping: &AtomMapping) -> Vec<BondId> {
+    let mut bonds: Vec<BondId> = pattern
+        .bonds()
+        .filter_map(|bond| target.bond_between(*mapping.get(&bond.a)?, *mapping.get(&bond.b)?))
+        .collect();
+    bonds.sort();
+    bonds
+}
+
+/// VF2-style backtracking search: pattern atoms are visited in BFS order so each new atom is
+/// usually anchored to an already-mapped neighbor, and candidates are pruned by the atom
+/// predicate, degree and adjacency to the partial mapping. Predicates take the pattern item
+/// first.
+pub(crate) fn find_matches(
+    pattern: &Molecule,
+    target: &Molecule,
+    limit: usize,
+    atom_matches: impl Fn(&Atom, &Atom) -> bool,
+    bond_matches: impl Fn(&Bond, &Bond) -> bool,
+) -> Vec<AtomMapping> {
+    let mut results = Vec::new();
+    if limit == 0 || pattern.atom_count() == 0 || pattern.atom_count() > target.atom_count() {
+        return results;
+    }
+    let pattern_neighbors = neighbor_map(pattern);
+    let target_neighbors = neighbor_map(target);
+    let order = search_order(pattern, &pattern_neighbors);
+    let mut state = State {
+        pattern,
+        target,
+        pattern_neighbors: &pattern_neighbors,
+        target_neighbors: &target_neighbors,
+        order: &order,
+        atom_matches: &atom_matches,
+        bond_matches: &bond_matches,
+        mapping: HashMap::new(),
+        used: HashSet::new(),
+        limit,
+    };
+    state.extend(&mut results);
+    results
+}
+
+type Neighbors<'a> = HashMap<AtomId, Vec<(AtomId, &'a Bond)>>;
+
+struct State<'a, F, G> {
+    pattern: &'a Molecule,
+    target: &'a Molecule,
+    pattern_neighbors: &'a Neighbors<'a>,
+    target_neighbors: &'a Neighbors<'a>,
+    order: &'a [AtomId],
+    atom_matches: &'a F,
+    bond_matches: &'a G,
+    mapping: AtomMapping,
+    used: HashSet<AtomId>,
+    limit: usize,
+}
+
+impl<F, G> State<'_, F, G>
+where
+    F: Fn(&Atom, &Atom) -> bool,
+    G: Fn(&Bond, &Bond) -> bool,
+{
+    fn extend(&mut self, results: &mut Vec<AtomMapping>) {
+        if results.len() >= self.limit {
+            return;
+        }
+        let Some(&query) = self.order.get(self.mapping.len()) else {
+            results.push(self.mapping.clone());
+            return;
+        };
+        let query_neighbors = neighbors_of(self.pattern_neighbors, query);
+        // Candidates come from a mapped neighbor's neighborhood when one exists.
+        let anchor = query_neighbors
+            .iter()
+            .find_map(|(neighbor, _)| self.mapping.get(neighbor));
+        let candidates: Vec<AtomId> = match anchor {
+            Some(anchor) => neighbors_of(self.target_neighbors, *anchor)
+                .iter()
+                .map(|(id, _)| *id)
+                .collect(),
+            None => self.target.atom_ids(),
+        };
+        for candidate in candidates {
+            if self.feasible(query, candidate, query_neighbors) {
+                self.mapping.insert(query, candidate);
+                self.used.insert(candidate);
+                self.extend(results);
+                self.mapping.remove(&query);
+                self.used.remove(&candidate);
+                if results.len() >= self.limit {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn feasible(
+        &self,
+        query: AtomId,
+        candidate: AtomId,
+        query_neighbors: &[(AtomId, &Bond)],
+    ) -> bool {
+        if self.used.contains(&candidate) {
+            return false;
+        }
+        let candidate_neighbors = neighbors_of(self.target_neighbors, candidate);
+        if candidate_neighbors.len() < query_neighbors.len() {
+            return false;
+        }
+        let (Some(query_atom), Some(atom)) = (
+            self.pattern.get_atom(query),
+            self.target.get_atom(candidate),
+        ) else {
+            return false;
+        };
+        if !(self.atom_matches)(query_atom, atom) {
+            return false;
+        }
+        query_neighbors.iter().all(|(neighbor, query_bond)| {
+            self.mapping.get(neighbor).is_none_or(|mapped| {
+                candidate_neighbors
+                    .iter()
+                    .any(|(id, bond)| id == mapped && (self.bond_matches)(query_bond, bond))
+            })
+        })
+    }
+}
+
+fn neighbors_of<'a, 'b>(neighbors: &'b Neighbors<'a>, id: AtomId) -> &'b [(AtomId, &'a Bond)] {
+    neighbors.get(&id).map(Vec::as_slice).unwrap_or(&[])
+}
+
+fn neighbor_map(molecule: &Molecule) -> Neighbors<'_> {
+    let mut neighbors: Neighbors = HashMap::new();
+    for bond in molecule.bonds() {
+        neighbors.entry(bond.a).or_default().push((bond.b, bond));
+        neighbors.entry(bond.b).or_default().push((bond.a, bond));
+    }
+    neighbors
+}
+
+/// BFS order per component, starting each component from its highest-degree atom.
+fn search_order(pattern: &Molecule, neighbors: &Neighbors) -> Vec<AtomId> {
+    let mut order = Vec::with_capacity(pattern.atom_count());
+    let mut seen = HashSet::new();
+    for component in pattern.connected_components() {
+        let Some(&root) = component
+            .iter()
+            .max_by_key(|id| neighbors_of(neighbors, **id).len())
+        else {
+            continue;
+        };
+        for id in pattern.bfs(root) {
+            if seen.insert(id) {
+                order.push(id);
+            }
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(elements: &[&str], ring: bool) -> (Molecule, Vec<AtomId>) {
+        let mut molecule = Molecule::new("chain");
+        let ids: Vec<AtomId> = elements
+            .iter()
+            .enumerate()
+            .map(|(i, element)| molecule.insert_atom(element.to_string(), [i as f32, 0.0, 0.0]))
+            .collect();
+        for pair in ids.windows(2) {
+            molecule.add_bond(pair[0], pair[1]).unwrap();
+        }
+        if ring {
+            molecule.add_bond(ids[ids.len() - 1], ids[0]).unwrap();
+        }
+        (molecule, ids)
+    }
+
+    #[test]
+    fn finds_carbonyl_in_chain() {
+        let (target, ids) = chain(&["C", "C", "O", "N"], false);
+        let (pattern, pattern_ids) = chain(&["c", "O"], false);
+        let matches = find_substructures(&pattern, &target, 10);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0][&pattern_ids[0]], ids[1]);
+        assert_eq!(matches[0][&pattern_ids[1]], ids[2]);
+        assert_eq!(
+            matched_bonds(&pattern, &target, &matches[0]),
+            [target.bond_between(ids[1], ids[2]).unwrap()]
+        );
+        let (missing, _) = chain(&["O", "O"], false);
+        assert!(!contains_substructure(&missing, &target));
+    }
+
+    #[test]
+    fn ring_symmetry_and_limits() {
+        let (ring, _) = chain(&["C"; 6], true);
+        let (path, _) = chain(&["C"; 3], false);
+        // Six starting atoms times two directions.
+        assert_eq!(find_substructures(&path, &ring, 100).len(), 12);
+        assert_eq!(find_substructures(&path, &ring, 5).len(), 5);
+        let (small_ring, _) = chain(&["C"; 3], true);
+        assert!(!contains_substructure(&small_ring, &ring));
+        assert!(contains_substructure(&path, &path));
+        assert!(find_substructures(&Molecule::new("empty"), &ring, 10).is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+//! Substitution sweeps for screening studies: one structure per element or group put at a
+//! chosen site.
+//!
+//! A list entry is a named group (`Me`, `OMe`, `CF3`… see [`NAMED_GROUPS`]), an element
+//! symbol, or a SMILES group whose first atom bonds to the site's neighbor. Write the first
+//! atom in brackets with the hydrogens it keeps, e.g. `[CH2]C#N`, so the open valence marks
+//! where it attaches. Elements replace the site atom in place, with a terminal site's bond set
+//! to the estimated single-bond length; groups replace a terminal site and are placed as in
+//! [`crate::attach::place_fragment`].
+
+use std::path::{Path, PathBuf};
+
+use glam::Vec3;
+
+use crate::attach::single_bond_length;
+use crate::elements::{atomic_number, normalize_symbol};
+use crate::formats::smiles::parse_smiles;
+use crate::{AtomId, Command, Molecule};
+
+/// Common substituents by name, as SMILES attaching through their first atom.
+pub const NAMED_GROUPS: [(&str, &str); 14] = [
+    ("Me", "[CH3]"),
+    ("Et", "[CH2]C"),
+    ("iPr", "[CH](C)C"),
+    ("tBu", "[C](C)(C)C"),
+    ("Ph", "[c]1ccccc1"),
+    ("OH", "[OH]"),
+    ("OMe", "[O]C"),
+    ("NH2", "[NH2]"),
+    ("NMe2", "[N](C)C"),
+    ("CF3", "[C](F)(F)F"),
+    ("CN", "[C]#N"),
+    ("NO2", "[N+](=O)[O-]"),
+    ("CHO", "[CH]=O"),
+    ("COOH", "[C](=O)O"),
+];
+
+/// One entry of a sweep list.
+#[derive(Debug, Clone)]
+pub enum Substituent {
+    Element(String),
+    Group {
+        label: String,
+        fragment: Box<Molecule>,
+    },
+}
+
+impl Substituent {
+    /// Short name for file names: the element, the group name, or the SMILES with punctuation
+    /// replaced by underscores.
+    pub fn label(&self) -> &str {
+        match self {
+            Substituent::Element(symbol) => symbol,
+            Substituent::Group { label, .. } => label,
+        }
+    }
+}
+
+/// Reads a comma- or whitespace-separated list of substituents.
+pub fn parse_substituents(text: &str) -> Result<Vec<Substituent>, String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|entry| !entry.is_empty())
+        .map(parse_substituent)
+        .collect()
+}
+
+fn parse_substituent(entry: &str) -> Result<Substituent, String> {
+    if let Some((name, smiles)) = NAMED_GROUPS.iter().find(|(name, _)| *name == entry) {
+        let fragment = parse_smiles(smiles).map_err(|err| format!("{name}: {err}"))?;
+        return Ok(Substituent::Group {
+            label: name.to_string(),
+            fragment: Box::new(fragment),
+        });
+    }
+    if atomic_number(entry).is_some() {
+        return Ok(Substituent::Element(normalize_symbol(entry)));
+    }
+    let fragment = parse_smiles(entry).map_err(|err| format!("{entry}: {err}"))?;
+    let label = entry
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>()
+        .trim_matches('_')
+        .to_string();
+    Ok(Substituent::Group {
+        label,
+        fragment: Box::new(fragment),
+    })
+}
+
+/// A copy of `molecule` with `substituent` at `site`.
+pub fn substitute(
+    molecule: &Molecule,
+    site: AtomId,
+    substituent: &Substituent,
+) -> Result<Molecule, String> {
+    let atom = molecule
+        .get_atom(site)
+        .ok_or_else(|| "site atom not found".to_string())?;
+    let neighbors = molecule.neighbors(site);
+    let mut result = molecule.clone();
+    match substituent {
+        Substituent::Element(symbol) => {
+            result.set_element(site, symbol.clone());
+            if let [neighbor] = neighbors[..] {
+                let center = Vec3::from_array(
+                    molecule
+                        .get_atom(neighbor)
+                        .map_or(atom.position, |neighbor| neighbor.position),
+                );
+                let neighbor_element = &molecule.get_atom(neighbor).map_or("", |a| &a.element);
+                if let Some(direction) = (Vec3::from_array(atom.position) - center).try_normalize()
+                {
+                    let length = single_bond_length(neighbor_element, symbol);
+                    result.set_atom_position(site, (center + direction * length).to_array());
+                }
+            }
+        }
+        Substituent::Group { label, fragment } => {
+            let [anchor] = neighbors[..] else {
+                return Err(format!(
+                    "{label}: groups replace a terminal atom; the site has {} neighbors",
+                    neighbors.len()
+                ));
+            };
+            result.remove_atom(site);
+            let mut command = Command::attach_fragment(&result, anchor, fragment)
+                .ok_or_else(|| format!("{label}: empty group"))?;
+            command.apply(&mut result)?;
+        }
+    }
+    result.name = format!("{} {}", molecule.name, substituent.label());
+    Ok(result)
+}
+
+/// One structure per entry of `substituents`, with its label.
+pub fn sweep(
+    molecule: &Molecule,
+    site: AtomId,
+    substituents: &[Substituent],
+) -> Result<Vec<(String, Molecule)>, String> {
+    substituents
+        .iter()
+        .map(|substituent| {
+            let structure = substitute(molecule, site, substituent)?;
+            Ok((substituent.label().to_string(), structure))
+        })
+        .collect()
+}
+
+/// `base` with the substituent label appended to its stem, e.g. `benzene_Cl.xyz`.
+pub fn sweep_path(base: &Path, label: &str) -> PathBuf {
+    let stem = base
+        .file_stem()
+        .map_or_else(|| "sweep".into(), |stem| stem.to_string_lossy());
+    let extension = base
+        .extension()
+        .map_or_else(|| "xyz".into(), |extension| extension.to_string_lossy());
+    base.with_file_name(format!("{stem}_{label}.{extension}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Methane with its first hydrogen as the site.
+    fn methane() -> (Molecule, AtomId, AtomId) {
+        let mut molecule = Molecule::new("methane");
+        let c = molecule.insert_atom("C".into(), [0.0; 3]);
+        molecule.add_hydrogens().unwrap();
+        let site = molecule.neighbors(c)[0];
+        (molecule, c, site)
+    }
+
+    #[test]
+    fn parses_names_elements_and_smiles() {
+        let list = parse_substituents("F, cl Me [CH2]C#N").unwrap();
+        let labels: Vec<&str> = list.iter().map(Substituent::label).collect();
+        assert_eq!(labels, ["F", "Cl", "Me", "CH2_C_N"]);
+        assert!(
+            matches!(&list[2], Substituent::Group { fragment, .. } if fragment.formula() == "CH3")
+        );
+        assert!(parse_substituents("F, C((").is_err());
+        for (name, _) in NAMED_GROUPS {
+            assert!(parse_substituents(name).is_ok(), "{name}");
+        }
+    }
+
+    #[test]
+    fn sweeps_elements_and_groups_over_one_site() {
+        let (molecule, c, site) = methane();
+        let list = parse_substituents("Cl OH CF3 Ph").unwrap();
+        let structures = sweep(&molecule, site, &list).unwrap();
+        let formulas: Vec<String> = structures.iter().map(|(_, m)| m.formula()).collect();
+        assert_eq!(formulas, ["CH3Cl", "CH4O", "C2H3F3", "C7H8"]);
+
+        let chloro = &structures[0].1;
+        let bond = Vec3::from(chloro.get_atom(site).unwrap().position)
+            .distance(Vec3::from(chloro.get_atom(c).unwrap().position));
+        assert!((bond - 1.75).abs() < 0.01, "{bond}");
+        assert_eq!(molecule.get_atom(site).unwrap().element, "H");
+
+        let methanol = &structures[1].1;
+        assert_eq!(methanol.neighbors(c).len(), 4);
+        assert!(methanol.get_atom(site).is_none());
+        assert_eq!(
+            sweep_path(Path::new("out/methane.sdf"), "OH"),
+            Path::new("out/methane_OH.sdf")
+        );
+    }
+
+    #[test]
+    fn groups_need_a_terminal_site() {
+        let (molecule, c, _) = methane();
+        let list = parse_substituents("F Me").unwrap();
+        assert_eq!(substitute(&molecule, c, &list[0]).unwrap().formula(), "FH4");
+        assert!(substitute(&molecule, c, &list[1]).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Structures as sequences of frames: taking single frames out of a multi-frame XYZ file,
+//! and recording an editing session as a trajectory with one frame per command.
+//!
+//! A multi-frame file loads as one molecule whose atoms carry the 1-based
+//! [`XYZ_FRAME_PROPERTY`]. Recorded frames may differ in atom count, which XYZ allows.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::provenance::describe;
+use crate::{write_xyz, AtomId, Command, Molecule, XYZ_FRAME_PROPERTY};
+
+/// Frame numbers present in `molecule`, ascending; empty when it holds a single frame.
+pub fn frame_numbers(molecule: &Molecule) -> Vec<usize> {
+    let frames: BTreeSet<usize> = molecule
+        .atoms_in_order()
+        .filter_map(|atom| {
+            molecule
+                .properties
+                .get(XYZ_FRAME_PROPERTY, atom.id)?
+                .parse()
+                .ok()
+        })
+        .collect();
+    frames.into_iter().collect()
+}
+
+/// The atoms of one frame as a molecule of their own, without the frame property.
+pub fn extract_frame(molecule: &Molecule, frame: usize) -> Molecule {
+    let number = frame.to_string();
+    let atoms: Vec<AtomId> = molecule
+        .atoms_in_order()
+        .filter(|atom| molecule.properties.get(XYZ_FRAME_PROPERTY, atom.id) == Some(&number))
+        .map(|atom| atom.id)
+        .collect();
+    let mut extracted = molecule.extract(&atoms);
+    extracted.properties.remove(XYZ_FRAME_PROPERTY);
+    extracted
+}
+
+/// Parses a frame selection such as `1-5, 8`; blank or `all` selects every frame in
+/// `available`. Frames that are not available are an error.
+pub fn parse_frame_list(text: &str, available: &[usize]) -> Result<Vec<usize>, String> {
+    let text = text.trim();
+    if text.is_empty() || text.eq_ignore_ascii_case("all") {
+        return Ok(available.to_vec());
+    }
+    let mut frames = BTreeSet::new();
+    for part in text.split(',').map(str::trim) {
+        let number = |value: &str| {
+            value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("invalid frame {value:?}"))
+        };
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (number(first)?, number(last)?),
+            None => (number(part)?, number(part)?),
+        };
+        for frame in first..=last {
+            if !available.contains(&frame) {
+                return Err(format!("no frame {frame}"));
+            }
+            frames.insert(frame);
+        }
+    }
+    Ok(frames.into_iter().collect())
+}
+
+/// `base` with the frame number appended to its stem, e.g. `run_0007.xyz`.
+pub fn frame_path(base: &Path, frame: usize, extension: &str) -> PathBuf {
+    let stem = base
+        .file_stem()
+        .map_or_else(|| "frame".into(), |stem| stem.to_string_lossy());
+    base.with_file_name(format!("{stem}_{frame:04}.{extension}"))
+}
+
+/// Collects an XYZ frame of the structure after each edit, commented with what the edit
+/// did, for showing how a structure was built.
+#[derive(Debug, Clone)]
+pub struct EditRecorder {
+    frames: Vec<(String, String)>,
+    precision: usize,
+}
+
+impl EditRecorder {
+    /// Starts with the current structure as the first frame.
+    pub fn new(molecule: &Molecule, precision: usize) -> Self {
+        let mut recorder = Self {
+            frames: Vec::new(),
+            precision,
+        };
+        recorder.record(molecule, "start");
+        recorder
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Adds a frame labelled `label`.
+    pub fn record(&mut self, molecule: &Molecule, label: &str) {
+        let xyz = write_xyz(molecule, self.precision);
+        // Swap the molecule name on the comment line for the label.
+        let atoms = xyz.splitn(3, '\n').nth(2).unwrap_or("");
+        let frame = format!("{}\n{}\n{atoms}", molecule.atom_count(), label.trim());
+        self.frames.push((label.to_string(), frame));
+    }
+
+    /// Adds a frame for an applied command. Successive moves of one atom merge into one
+    /// history step, so they replace the previous frame as they do in the provenance log.
+    pub fn record_command(&mut self, molecule: &Molecule, command: &Command) {
+        let label = describe(command);
+        let start = |label: &str| label.split(" to ").next().map(str::to_string);
+        let continues = matches!(command, Command::MoveAtom { .. })
+            && self.frames.len() > 1
+            && self
+                .frames
+                .last()
+                .is_some_and(|(last, _)| start(last) == start(&label));
+        if continues {
+            self.frames.pop();
+        }
+        self.record(molecule, &label);
+    }
+
+    /// Every frame as one multi-frame XYZ file.
+    pub fn to_xyz(&self) -> String {
+        self.frames
+            .iter()
+            .map(|(_, frame)| frame.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::text::Recovery;
+    use crate::{parse_xyz_with, XyzOptions};
+
+    #[test]
+    fn selects_and_extracts_frames() {
+        let contents = "1\nfirst\nO 0 0 0\n2\nsecond\nO 0 0 1\nH 0 0 2\n1\nthird\nO 0 0 3\n";
+        let options = XyzOptions {
+            first_frame: 1,
+            last_frame: 3,
+        };
+        let molecule = parse_xyz_with(contents, &options, &mut Recovery::strict()).unwrap();
+        let frames = frame_numbers(&molecule);
+        assert_eq!(frames, [1, 2, 3]);
+        assert_eq!(parse_frame_list(" all ", &frames).unwrap(), [1, 2, 3]);
+        assert_eq!(parse_frame_list("3, 1-2", &frames).unwrap(), [1, 2, 3]);
+        assert_eq!(parse_frame_list("2-4", &frames).unwrap_err(), "no frame 4");
+        assert!(parse_frame_list("x", &frames).is_err());
+
+        let second = extract_frame(&molecule, 2);
+        assert_eq!(second.atom_count(), 2);
+        assert_eq!(
+            second.atoms_in_order().next().unwrap().position,
+            [0.0, 0.0, 1.0]
+        );
+        assert_eq!(second.properties.names().count(), 0);
+        assert_eq!(
+            frame_path(Path::new("out/run.xyz"), 7, "sdf"),
+            Path::new("out/run_0007.sdf")
+        );
+    }
+
+    #[test]
+    fn records_one_frame_per_edit_merging_drags() {
+        let mut molecule = Molecule::new("water");
+        let mut recorder = EditRecorder::new(&molecule, 3);
+        let mut insert = Command::InsertAtom {
+            element: "O".to_string(),
+            position: [0.0; 3],
+            atom_id: None,
+            order_index: None,
+        };
+        insert.apply(&mut molecule).unwrap();
+        recorder.record_command(&molecule, &insert);
+        let id = molecule.atoms_in_order().next().unwrap().id;
+        // A drag reaches the recorder as one move growing from its start, as merged in the
+        // history.
+        for z in [1.0, 2.0] {
+            let mut step = Command::MoveAtom {
+                atom_id: id,
+                from: [0.0; 3],
+                to: [0.0, 0.0, z],
+            };
+            step.apply(&mut molecule).unwrap();
+            recorder.record_command(&molecule, &step);
+        }
+        assert_eq!(recorder.len(), 3);
+        let xyz = recorder.to_xyz();
+        assert!(xyz.starts_with("0\nstart\n1\n"));
+        assert!(xyz.ends_with("2.000\n"));
+    }
+}
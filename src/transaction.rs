@@ -0,0 +1,206 @@
+//! Batches of edits that apply all together or not at all, as one undo step.
+
+use crate::{AtomId, BondId, Command, CommandHistory, Molecule};
+
+/// Edits collected by [`Molecule::edit`] or [`CommandHistory::edit`].
+///
+/// Each edit is tried on a copy of the molecule as it is made, so new atoms and bonds have
+/// their ids at once and later edits can refer to them. The first edit that fails, or a
+/// [`Transaction::fail`] from the caller's own checks, fails the whole transaction and the
+/// edits after it are ignored.
+#[derive(Debug)]
+pub struct Transaction {
+    scratch: Molecule,
+    commands: Vec<Command>,
+    error: Option<String>,
+}
+
+impl Transaction {
+    fn run(&mut self, mut command: Command) -> Option<&Command> {
+        if self.error.is_some() {
+            return None;
+        }
+        match command.apply(&mut self.scratch) {
+            Ok(()) => {
+                self.commands.push(command);
+                self.commands.last()
+            }
+            Err(err) => {
+                self.error = Some(err);
+                None
+            }
+        }
+    }
+
+    /// The id the atom will have. Once the transaction has failed the atom is not added and
+    /// the id means nothing.
+    pub fn add_atom(&mut self, element: impl Into<String>, position: [f32; 3]) -> AtomId {
+        let command = Command::InsertAtom {
+            element: element.into(),
+            position,
+            atom_id: None,
+            order_index: None,
+        };
+        match self.run(command) {
+            Some(Command::InsertAtom {
+                atom_id: Some(id), ..
+            }) => *id,
+            _ => self.scratch.next_atom_id(),
+        }
+    }
+
+    /// The id the bond will have, or `None` once the transaction has failed.
+    pub fn add_bond(&mut self, a: AtomId, b: AtomId) -> Option<BondId> {
+        let command = Command::AddBond {
+            atom_a: a,
+            atom_b: b,
+            bond_id: None,
+        };
+        match self.run(command) {
+            Some(Command::AddBond { bond_id, .. }) => *bond_id,
+            _ => None,
+        }
+    }
+
+    /// Like [`Transaction::add_bond`] for a coordination bond.
+    pub fn add_coordination_bond(&mut self, a: AtomId, b: AtomId) -> Option<BondId> {
+        let command = Command::AddCoordinationBond {
+            atom_a: a,
+            atom_b: b,
+            bond_id: None,
+        };
+        match self.run(command) {
+            Some(Command::AddCoordinationBond { bond_id, .. }) => *bond_id,
+            _ => None,
+        }
+    }
+
+    pub fn delete_atom(&mut self, id: AtomId) {
+        self.run(Command::DeleteAtom {
+            atom_id: id,
+            removed: None,
+        });
+    }
+
+    pub fn remove_bond(&mut self, id: BondId) {
+        self.run(Command::RemoveBond {
+            bond_id: id,
+            removed: None,
+        });
+    }
+
+    pub fn move_atom(&mut self, id: AtomId, to: [f32; 3]) {
+        let Some(atom) = self.scratch.get_atom(id) else {
+            self.fail(format!("atom {} not found", id.value()));
+            return;
+        };
+        let from = atom.position;
+        self.run(Command::MoveAtom {
+            atom_id: id,
+            from,
+            to,
+        });
+    }
+
+    /// Fails the transaction with `reason` unless it has failed already.
+    pub fn fail(&mut self, reason: impl Into<String>) {
+        self.error.get_or_insert_with(|| reason.into());
+    }
+
+    /// The molecule as it would be with the edits so far.
+    pub fn molecule(&self) -> &Molecule {
+        &self.scratch
+    }
+
+    /// Runs `edit` against a copy of `molecule` and returns its edits as one composite command,
+    /// not yet applied.
+    fn build(molecule: &Molecule, edit: impl FnOnce(&mut Transaction)) -> Result<Command, String> {
+        let mut transaction = Transaction {
+            scratch: molecule.clone(),
+            commands: Vec::new(),
+            error: None,
+        };
+        edit(&mut transaction);
+        match transaction.error {
+            Some(err) => Err(err),
+            None => Ok(Command::Composite {
+                commands: transaction.commands,
+            }),
+        }
+    }
+}
+
+impl Molecule {
+    /// Applies the edits `edit` makes all together, or none of them if any fails. Returns the
+    /// applied command, which undoes the whole batch.
+    pub fn edit(&mut self, edit: impl FnOnce(&mut Transaction)) -> Result<Command, String> {
+        let mut command = Transaction::build(self, edit)?;
+        command.apply(self)?;
+        Ok(command)
+    }
+}
+
+impl CommandHistory {
+    /// Like [`Molecule::edit`], recording the batch as one history entry. An empty batch
+    /// records nothing.
+    pub fn edit(
+        &mut self,
+        molecule: &mut Molecule,
+        edit: impl FnOnce(&mut Transaction),
+    ) -> Result<Command, String> {
+        let command = Transaction::build(molecule, edit)?;
+        if matches!(&command, Command::Composite { commands } if commands.is_empty()) {
+            return Ok(command);
+        }
+        self.execute(command, molecule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commits_all_edits_as_one_step_or_none() {
+        let mut molecule = Molecule::new("water");
+        let mut history = CommandHistory::new(10);
+        history
+            .edit(&mut molecule, |tx| {
+                let o = tx.add_atom("O", [0.0, 0.0, 0.0]);
+                for x in [0.96, -0.24] {
+                    let h = tx.add_atom("H", [x, 0.93, 0.0]);
+                    tx.add_bond(o, h);
+                }
+            })
+            .unwrap();
+        assert_eq!(molecule.formula(), "H2O");
+        assert_eq!(molecule.bonds().count(), 2);
+        assert_eq!(history.undo_len(), 1);
+
+        // A third hydrogen exceeds oxygen's valence, so nothing is applied.
+        let o = molecule.atom_ids()[0];
+        let err = history
+            .edit(&mut molecule, |tx| {
+                tx.move_atom(o, [0.0, 0.1, 0.0]);
+                let h = tx.add_atom("H", [0.0, -1.0, 0.0]);
+                tx.add_bond(o, h);
+            })
+            .unwrap_err();
+        assert!(!err.is_empty());
+        assert_eq!(molecule.formula(), "H2O");
+        assert_eq!(molecule.get_atom(o).unwrap().position, [0.0, 0.0, 0.0]);
+        assert_eq!(history.undo_len(), 1);
+
+        let err = molecule
+            .edit(|tx| {
+                tx.delete_atom(o);
+                if tx.molecule().atom_count() < 3 {
+                    tx.fail("keep the oxygen");
+                }
+            })
+            .unwrap_err();
+        assert_eq!(err, "keep the oxygen");
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(molecule.atom_count(), 0);
+    }
+}
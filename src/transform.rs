@@ -0,0 +1,247 @@
+//! Moving a set of atoms together as one [`Command::TransformAtoms`] step: rotating,
+//! translating, mirroring through a plane or inverting through a point, and putting a whole
+//! structure in a standard frame.
+//!
+//! Rotations, mirrors and inversions keep the atoms' centroid where it is, so the structure
+//! stays in view. Mirroring and inverting turn every stereocenter into its opposite, which is
+//! how an enantiomer is made from a structure.
+
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+use crate::geometry::principal_moments;
+use crate::{AtomId, Command, Molecule};
+
+/// A mirror plane through the atoms' centroid, named by the two axes it contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MirrorPlane {
+    #[default]
+    Yz,
+    Xz,
+    Xy,
+}
+
+/// Coordinate axes by name, for picking a rotation axis.
+pub const AXES: [(&str, Vec3); 3] = [("X", Vec3::X), ("Y", Vec3::Y), ("Z", Vec3::Z)];
+
+/// How far a transform's linear part may stray from orthonormal and still count as rigid.
+const RIGID_TOLERANCE: f32 = 1e-3;
+
+/// Whether `matrix` only rotates, reflects and translates: an orthonormal linear part and no
+/// projective row.
+pub fn is_rigid(matrix: &Mat4) -> bool {
+    let linear = Mat3::from_mat4(*matrix);
+    matrix.row(3).abs_diff_eq(glam::Vec4::W, RIGID_TOLERANCE)
+        && (linear.transpose() * linear).abs_diff_eq(Mat3::IDENTITY, RIGID_TOLERANCE)
+}
+
+/// `linear` applied about `center` instead of the origin.
+pub fn about(center: Vec3, linear: Mat3) -> Mat4 {
+    Mat4::from_translation(center) * Mat4::from_mat3(linear) * Mat4::from_translation(-center)
+}
+
+impl MirrorPlane {
+    pub const ALL: [MirrorPlane; 3] = [MirrorPlane::Yz, MirrorPlane::Xz, MirrorPlane::Xy];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MirrorPlane::Yz => "YZ",
+            MirrorPlane::Xz => "XZ",
+            MirrorPlane::Xy => "XY",
+        }
+    }
+
+    /// Unit vector perpendicular to the plane.
+    pub fn normal(self) -> Vec3 {
+        match self {
+            MirrorPlane::Yz => Vec3::X,
+            MirrorPlane::Xz => Vec3::Y,
+            MirrorPlane::Xy => Vec3::Z,
+        }
+    }
+}
+
+impl Command {
+    /// One step applying `matrix` to the atoms of `atoms` that exist. `None` when none do.
+    pub fn transform_atoms(molecule: &Molecule, atoms: &[AtomId], matrix: Mat4) -> Option<Command> {
+        let ids: Vec<AtomId> = atoms
+            .iter()
+            .copied()
+            .filter(|&id| molecule.get_atom(id).is_some())
+            .collect();
+        (!ids.is_empty()).then_some(Command::TransformAtoms {
+            ids,
+            matrix,
+            previous: None,
+        })
+    }
+
+    /// One step turning `atoms` by `degrees` about `axis` through their centroid.
+    pub fn rotate_atoms(
+        molecule: &Molecule,
+        atoms: &[AtomId],
+        axis: Vec3,
+        degrees: f32,
+    ) -> Option<Command> {
+        let center = centroid_of(molecule, atoms)?;
+        let turn = Mat3::from_quat(Quat::from_axis_angle(
+            axis.try_normalize()?,
+            degrees.to_radians(),
+        ));
+        Command::transform_atoms(molecule, atoms, about(center, turn))
+    }
+
+    /// One step reflecting `atoms` through `plane`, placed at their centroid.
+    pub fn mirror_atoms(
+        molecule: &Molecule,
+        atoms: &[AtomId],
+        plane: MirrorPlane,
+    ) -> Option<Command> {
+        let center = centroid_of(molecule, atoms)?;
+        let normal = plane.normal();
+        let reflect = Mat3::IDENTITY
+            - 2.0 * Mat3::from_cols(normal * normal.x, normal * normal.y, normal * normal.z);
+        Command::transform_atoms(molecule, atoms, about(center, reflect))
+    }
+
+    /// One step inverting `atoms` through their centroid.
+    pub fn invert_atoms(molecule: &Molecule, atoms: &[AtomId]) -> Option<Command> {
+        let center = centroid_of(molecule, atoms)?;
+        Command::transform_atoms(molecule, atoms, about(center, -Mat3::IDENTITY))
+    }
+
+    /// One step moving the whole structure so its centroid sits at the origin.
+    pub fn center_at_origin(molecule: &Molecule) -> Option<Command> {
+        let atoms = molecule.atom_ids();
+        let center = centroid_of(molecule, &atoms)?;
+        Command::transform_atoms(molecule, &atoms, Mat4::from_translation(-center))
+    }
+
+    /// One step centering the whole structure at the origin and turning it so its principal
+    /// axes of inertia lie along X, Y and Z, smallest moment first.
+    pub fn align_principal_axes(molecule: &Molecule) -> Option<Command> {
+        let atoms = molecule.atom_ids();
+        let center = centroid_of(molecule, &atoms)?;
+        let axes = principal_moments(molecule)?.axes.map(Vec3::from_array);
+        // The axes form a right-handed orthonormal frame; its transpose takes them to X, Y, Z.
+        let turn = Mat3::from_cols(axes[0], axes[1], axes[2]).transpose();
+        let matrix = Mat4::from_mat3(turn) * Mat4::from_translation(-center);
+        Command::transform_atoms(molecule, &atoms, matrix)
+    }
+}
+
+/// Mean position of the atoms of `atoms` that exist.
+pub fn centroid_of(molecule: &Molecule, atoms: &[AtomId]) -> Option<Vec3> {
+    let positions: Vec<Vec3> = atoms
+        .iter()
+        .filter_map(|&id| molecule.get_atom(id))
+        .map(|atom| Vec3::from_array(atom.position))
+        .collect();
+    (!positions.is_empty()).then(|| positions.iter().sum::<Vec3>() / positions.len() as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::smiles::parse_smiles;
+    use crate::stereo::Chirality;
+    use crate::CommandHistory;
+
+    #[test]
+    fn mirroring_and_inverting_make_the_enantiomer() {
+        let mut molecule = parse_smiles("C(F)(Cl)(Br)I").unwrap();
+        let ids = molecule.atom_ids();
+        let center = ids[0];
+        molecule.assign_stereo();
+        let before = molecule.get_atom(center).unwrap().stereo.unwrap();
+        let opposite = match before {
+            Chirality::R => Chirality::S,
+            Chirality::S => Chirality::R,
+        };
+        let middle = centroid_of(&molecule, &ids).unwrap();
+
+        let mut history = CommandHistory::new(8);
+        for plane in MirrorPlane::ALL {
+            let mirror = Command::mirror_atoms(&molecule, &ids, plane).unwrap();
+            history.execute(mirror, &mut molecule).unwrap();
+            molecule.assign_stereo();
+            assert_eq!(molecule.get_atom(center).unwrap().stereo, Some(opposite));
+            let moved = centroid_of(&molecule, &ids).unwrap();
+            assert!(moved.distance(middle) < 1e-5, "{}", plane.label());
+            history.undo(&mut molecule).unwrap();
+        }
+
+        let invert = Command::invert_atoms(&molecule, &ids).unwrap();
+        history.execute(invert, &mut molecule).unwrap();
+        molecule.assign_stereo();
+        assert_eq!(molecule.get_atom(center).unwrap().stereo, Some(opposite));
+        history.undo(&mut molecule).unwrap();
+        molecule.assign_stereo();
+        assert_eq!(molecule.get_atom(center).unwrap().stereo, Some(before));
+
+        assert!(Command::invert_atoms(&molecule, &[]).is_none());
+    }
+
+    #[test]
+    fn transforms_are_separate_undo_steps() {
+        let mut molecule = Molecule::new("pair");
+        let a = molecule.insert_atom("C".into(), [1.0, 0.0, 0.0]);
+        let b = molecule.insert_atom("O".into(), [3.0, 0.0, 0.0]);
+        let position =
+            |molecule: &Molecule, id| Vec3::from_array(molecule.get_atom(id).unwrap().position);
+
+        let mut history = CommandHistory::new(8);
+        for _ in 0..2 {
+            let turn = Command::rotate_atoms(&molecule, &[a, b], Vec3::Y, 90.0).unwrap();
+            history.execute(turn, &mut molecule).unwrap();
+        }
+        assert_eq!(history.undo_len(), 2);
+        assert!(position(&molecule, a).distance(Vec3::new(3.0, 0.0, 0.0)) < 1e-5);
+        history.undo(&mut molecule).unwrap();
+        assert!(position(&molecule, a).distance(Vec3::new(2.0, 0.0, 1.0)) < 1e-5);
+        history.undo(&mut molecule).unwrap();
+        assert_eq!(position(&molecule, b), Vec3::new(3.0, 0.0, 0.0));
+
+        let shift = Mat4::from_translation(Vec3::Z);
+        let moved = Command::transform_atoms(&molecule, &[b], shift).unwrap();
+        history.execute(moved, &mut molecule).unwrap();
+        assert_eq!(position(&molecule, b), Vec3::new(3.0, 0.0, 1.0));
+        assert_eq!(position(&molecule, a), Vec3::new(1.0, 0.0, 0.0));
+
+        let stretch = Command::transform_atoms(&molecule, &[a], Mat4::from_scale(Vec3::splat(2.0)));
+        let err = history
+            .execute(stretch.unwrap(), &mut molecule)
+            .unwrap_err();
+        assert_eq!(err, "transform is not rigid");
+        assert!(is_rigid(&about(Vec3::ONE, -Mat3::IDENTITY)));
+        assert!(Command::transform_atoms(&molecule, &[AtomId::new(99)], shift).is_none());
+    }
+
+    #[test]
+    fn aligns_to_principal_axes_at_the_origin() {
+        let mut molecule = parse_smiles("CC(=O)O").unwrap();
+        let ids = molecule.atom_ids();
+        let tilt = Command::rotate_atoms(&molecule, &ids, Vec3::new(1.0, 2.0, 0.5), 37.0).unwrap();
+        let shift =
+            Command::transform_atoms(&molecule, &ids, Mat4::from_translation(Vec3::splat(4.0)));
+        let mut history = CommandHistory::new(8);
+        history.execute(tilt, &mut molecule).unwrap();
+        history.execute(shift.unwrap(), &mut molecule).unwrap();
+
+        let center = Command::center_at_origin(&molecule).unwrap();
+        history.execute(center, &mut molecule).unwrap();
+        assert!(centroid_of(&molecule, &ids).unwrap().length() < 1e-4);
+        history.undo(&mut molecule).unwrap();
+
+        let align = Command::align_principal_axes(&molecule).unwrap();
+        history.execute(align, &mut molecule).unwrap();
+        assert!(centroid_of(&molecule, &ids).unwrap().length() < 1e-4);
+        let tensor = crate::geometry::inertia_tensor(&molecule).unwrap();
+        let scale = tensor.x_axis.x.abs() + tensor.y_axis.y.abs() + tensor.z_axis.z.abs();
+        for (row, column) in [(0, 1), (0, 2), (1, 2)] {
+            let off = tensor.col(column)[row];
+            assert!(off.abs() < 1e-3 * scale, "{tensor:?}");
+        }
+        assert!(tensor.x_axis.x <= tensor.y_axis.y && tensor.y_axis.y <= tensor.z_axis.z);
+        assert!(Command::align_principal_axes(&Molecule::new("empty")).is_none());
+    }
+}
@@ -0,0 +1,304 @@
+//! Limits on how many bonds an atom may form, each counted by its order, checked whenever a
+//! bond is added or its order raised.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::elements::normalize_symbol;
+use crate::{Atom, AtomId};
+
+/// The built-in limits as `(element, formal charge, bonds)`; a charged entry wins over the
+/// element's neutral one.
+const STANDARD_LIMITS: [(&str, Option<i32>, usize); 12] = [
+    ("H", None, 1),
+    ("C", None, 4),
+    ("N", None, 3),
+    ("N", Some(1), 4),
+    ("O", None, 2),
+    ("O", Some(1), 3),
+    ("F", None, 1),
+    ("Cl", None, 1),
+    ("Br", None, 1),
+    ("I", None, 1),
+    ("P", None, 5),
+    ("S", None, 6),
+];
+/// Limit for elements the table doesn't list.
+const STANDARD_OTHER: usize = 4;
+
+/// Decides how many bonds each atom may take. [`Molecule`](crate::Molecule) uses
+/// [`StandardValence`] unless another model is set with
+/// [`Molecule::set_valence_model`](crate::Molecule::set_valence_model), e.g. to allow
+/// hypervalent species or metal centers.
+pub trait ValenceModel: fmt::Debug + Send + Sync {
+    /// Most bonds `atom` may form, a double bond counting as two; `None` means no limit.
+    fn max_bonds(&self, atom: &Atom) -> Option<usize>;
+
+    /// When true, bonds past [`ValenceModel::max_bonds`] are still made and only reported by
+    /// [`Molecule::valence_warnings`](crate::Molecule::valence_warnings).
+    fn permissive(&self) -> bool {
+        false
+    }
+}
+
+/// The built-in table: H 1, C 4, N 3 (4 as N+), O 2 (3 as O+), halogens 1, P 5, S 6, and 4
+/// for anything else.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StandardValence;
+
+impl ValenceModel for StandardValence {
+    fn max_bonds(&self, atom: &Atom) -> Option<usize> {
+        let symbol = normalize_symbol(&atom.element);
+        let limit = |charge: Option<i32>| {
+            STANDARD_LIMITS
+                .iter()
+                .find(|entry| entry.0 == symbol && entry.1 == charge)
+                .map(|entry| entry.2)
+        };
+        Some(
+            limit(Some(atom.charge))
+                .or_else(|| limit(None))
+                .unwrap_or(STANDARD_OTHER),
+        )
+    }
+}
+
+/// Editable limits per element and per element and formal charge, starting from the
+/// [`StandardValence`] table. Limits of `None` mean no limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValenceRules {
+    elements: BTreeMap<String, Option<usize>>,
+    charged: BTreeMap<(String, i32), Option<usize>>,
+    other: Option<usize>,
+    /// Allow bonds past the limits and only warn about them.
+    pub permissive: bool,
+}
+
+impl Default for ValenceRules {
+    fn default() -> Self {
+        let mut rules = Self {
+            elements: BTreeMap::new(),
+            charged: BTreeMap::new(),
+            other: Some(STANDARD_OTHER),
+            permissive: false,
+        };
+        for (element, charge, limit) in STANDARD_LIMITS {
+            rules.set(element, charge, Some(limit));
+        }
+        rules
+    }
+}
+
+impl ValenceRules {
+    /// Sets the limit for `element`, or for `element` with formal charge `charge` only.
+    pub fn set(&mut self, element: &str, charge: Option<i32>, limit: Option<usize>) {
+        let symbol = normalize_symbol(element);
+        match charge {
+            Some(charge) => self.charged.insert((symbol, charge), limit),
+            None => self.elements.insert(symbol, limit),
+        };
+    }
+
+    /// Limit for elements without their own rule.
+    pub fn set_other(&mut self, limit: Option<usize>) {
+        self.other = limit;
+    }
+
+    /// Applies overrides written one per line as `ELEMENT [CHARGE] LIMIT`, e.g. `Fe 6`,
+    /// `N +1 4` or `Xe -` for no limit. `*` stands for every unlisted element and `#` starts a
+    /// comment. Nothing is applied when a line is malformed.
+    pub fn apply_overrides(&mut self, text: &str) -> Result<(), ValenceRulesError> {
+        let mut updated = self.clone();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (element, charge, limit) = match fields[..] {
+                [] => continue,
+                [element, limit] => (element, None, limit),
+                [element, charge, limit] => {
+                    let charge = charge
+                        .trim_start_matches('+')
+                        .parse()
+                        .map_err(|_| ValenceRulesError::new(index + 1, "invalid charge"))?;
+                    (element, Some(charge), limit)
+                }
+                _ => {
+                    return Err(ValenceRulesError::new(
+                        index + 1,
+                        "expected ELEMENT [CHARGE] LIMIT",
+                    ))
+                }
+            };
+            let limit = match limit {
+                "-" => None,
+                limit => Some(
+                    limit
+                        .parse()
+                        .map_err(|_| ValenceRulesError::new(index + 1, "invalid limit"))?,
+                ),
+            };
+            match (element, charge) {
+                ("*", None) => updated.set_other(limit),
+                ("*", Some(_)) => {
+                    return Err(ValenceRulesError::new(index + 1, "* takes no charge"))
+                }
+                (element, charge) => updated.set(element, charge, limit),
+            }
+        }
+        *self = updated;
+        Ok(())
+    }
+}
+
+impl ValenceModel for ValenceRules {
+    fn max_bonds(&self, atom: &Atom) -> Option<usize> {
+        let symbol = normalize_symbol(&atom.element);
+        if let Some(limit) = self.charged.get(&(symbol.clone(), atom.charge)) {
+            return *limit;
+        }
+        self.elements.get(&symbol).copied().unwrap_or(self.other)
+    }
+
+    fn permissive(&self) -> bool {
+        self.permissive
+    }
+}
+
+/// An atom with more bonds than its valence model allows, left by a permissive model or by a
+/// file loaded as written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValenceWarning {
+    pub atom: AtomId,
+    pub element: String,
+    /// Its covalent bond orders summed.
+    pub bonds: usize,
+    pub max: usize,
+}
+
+impl fmt::Display for ValenceWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "atom {} ({}) has {} bonds, max {}",
+            self.atom.value(),
+            self.element,
+            self.bonds,
+            self.max
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ValenceRulesError {
+    details: String,
+}
+
+impl ValenceRulesError {
+    fn new(line: usize, details: impl Into<String>) -> Self {
+        Self {
+            details: format!("line {line}: {}", details.into()),
+        }
+    }
+}
+
+impl fmt::Display for ValenceRulesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl std::error::Error for ValenceRulesError {}
+
+/// Accepts any number of bonds on every atom.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Unrestricted;
+
+impl ValenceModel for Unrestricted {
+    fn max_bonds(&self, _atom: &Atom) -> Option<usize> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::Molecule;
+
+    /// Lets transition metals take up to six ligands and defers to the table otherwise.
+    #[derive(Debug)]
+    struct Octahedral;
+
+    impl ValenceModel for Octahedral {
+        fn max_bonds(&self, atom: &Atom) -> Option<usize> {
+            match atom.element.as_str() {
+                "Fe" | "Co" | "Ni" => Some(6),
+                _ => StandardValence.max_bonds(atom),
+            }
+        }
+    }
+
+    #[test]
+    fn molecules_check_bonds_against_their_model() {
+        let mut complex = Molecule::new("hexaaqua");
+        let iron = complex.insert_atom("Fe".into(), [0.0; 3]);
+        let waters: Vec<_> = (0..6)
+            .map(|i| complex.insert_atom("O".into(), [i as f32, 2.0, 0.0]))
+            .collect();
+        for water in &waters[..4] {
+            complex.add_bond(iron, *water).unwrap();
+        }
+        assert!(complex.add_bond(iron, waters[4]).is_err());
+
+        complex.set_valence_model(Arc::new(Octahedral));
+        complex.add_bond(iron, waters[4]).unwrap();
+        complex.add_bond(iron, waters[5]).unwrap();
+        let extra = complex.insert_atom("Cl".into(), [0.0, -2.0, 0.0]);
+        assert!(complex.add_bond(iron, extra).is_err());
+        // Copies made from the molecule keep its model.
+        let copy = complex.extract(&complex.atom_ids());
+        assert_eq!(copy.bonds().count(), 6);
+
+        complex.set_valence_model(Arc::new(Unrestricted));
+        complex.add_bond(iron, extra).unwrap();
+    }
+
+    #[test]
+    fn rules_override_by_element_and_charge_and_can_only_warn() {
+        let mut molecule = Molecule::new("periodinane");
+        let iodine = molecule.insert_atom("I".into(), [0.0; 3]);
+        let ligands: Vec<_> = (0..4)
+            .map(|i| molecule.insert_atom("O".into(), [i as f32, 2.0, 0.0]))
+            .collect();
+        molecule.add_bond(iodine, ligands[0]).unwrap();
+        assert!(molecule.add_bond(iodine, ligands[1]).is_err());
+
+        let mut rules = ValenceRules::default();
+        rules
+            .apply_overrides("# hypervalent iodine\nI 3\nN +1 -\n* 8")
+            .unwrap();
+        let err = rules.apply_overrides("Fe 6\nI +one 5").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: invalid charge");
+        molecule.set_valence_model(Arc::new(rules.clone()));
+        molecule.add_bond(iodine, ligands[1]).unwrap();
+        molecule.add_bond(iodine, ligands[2]).unwrap();
+        assert!(molecule.add_bond(iodine, ligands[3]).is_err());
+        let iron = molecule.insert_atom("Fe".into(), [0.0; 3]);
+        assert_eq!(rules.max_bonds(molecule.get_atom(iron).unwrap()), Some(8));
+        molecule.set_formal_charge(ligands[0], 1);
+        let oxonium = molecule.get_atom(ligands[0]).unwrap();
+        assert_eq!(StandardValence.max_bonds(oxonium), Some(3));
+        assert!(molecule.valence_warnings().is_empty());
+
+        rules.permissive = true;
+        molecule.set_valence_model(Arc::new(rules));
+        molecule.add_bond(iodine, ligands[3]).unwrap();
+        let warnings = molecule.valence_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].to_string(),
+            format!("atom {} (I) has 4 bonds, max 3", iodine.value())
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! Noticing when another program rewrites the open file, so the viewer can reload it.
+//!
+//! The file is polled rather than watched through OS notifications, which keeps this free of
+//! platform code and works on network drives. A change is only reported once the file has
+//! looked the same on two polls in a row, so a file still being written isn't read half done.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Modification time and length, which between them change whenever a file is rewritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl FileStamp {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(Self {
+            modified: metadata.modified()?,
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Polls one file for changes.
+#[derive(Debug, Clone)]
+pub struct FileWatch {
+    path: PathBuf,
+    /// The file as last reported, or as found when the watch began.
+    seen: Option<FileStamp>,
+    /// A different stamp found on the previous poll, waiting to be seen again.
+    settling: Option<FileStamp>,
+}
+
+impl FileWatch {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let seen = FileStamp::read(&path).ok();
+        Self {
+            path,
+            seen,
+            settling: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks the file once; true when it has changed since the last change reported and
+    /// stayed unchanged since the previous poll. A missing file is waited out, as when a
+    /// program deletes and recreates it.
+    pub fn poll(&mut self) -> bool {
+        let Ok(stamp) = FileStamp::read(&self.path) else {
+            self.settling = None;
+            return false;
+        };
+        if Some(stamp) == self.seen {
+            self.settling = None;
+            return false;
+        }
+        if self.settling == Some(stamp) {
+            self.seen = Some(stamp);
+            self.settling = None;
+            return true;
+        }
+        self.settling = Some(stamp);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_a_rewrite_once_it_settles() {
+        let path = std::env::temp_dir().join(format!("molweaver-watch-{}.xyz", std::process::id()));
+        std::fs::write(&path, "1\n\nH 0 0 0\n").unwrap();
+        let mut watch = FileWatch::new(&path);
+        assert_eq!(watch.path(), path);
+        assert!(!watch.poll());
+
+        std::fs::write(&path, "2\n\nH 0 0 0\nH 0.74 0 0\n").unwrap();
+        assert!(!watch.poll(), "still settling");
+        assert!(watch.poll());
+        assert!(!watch.poll());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(!watch.poll());
+        std::fs::write(&path, "1\n\nO 0 0 0\n").unwrap();
+        assert!(!watch.poll());
+        assert!(watch.poll());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
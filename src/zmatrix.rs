@@ -0,0 +1,283 @@
+//! Z-matrix (internal coordinate) input and output.
+//!
+//! Each line is an element followed by up to three `reference value` pairs: a bond length in
+//! Å to the first reference, an angle in degrees with the second and a dihedral in degrees
+//! with the third. References are 1-based line numbers of earlier atoms. Values may be
+//! numbers or variable names, optionally negated, defined after a blank line as `name value`
+//! or `name = value`:
+//!
+//! ```text
+//! O
+//! H 1 0.96
+//! H 1 r 2 104.5
+//!
+//! r 0.96
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+
+use glam::Vec3;
+
+use crate::formats::text::{self, Field, FieldError, Line};
+use crate::geometry::{angle, dihedral};
+use crate::{AtomId, Molecule};
+
+#[derive(Debug, Clone)]
+pub struct ZMatrixError {
+    details: String,
+}
+
+impl ZMatrixError {
+    fn new(line: usize, details: impl Into<String>) -> Self {
+        Self {
+            details: format!("line {line}: {}", details.into()),
+        }
+    }
+}
+
+impl fmt::Display for ZMatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl std::error::Error for ZMatrixError {}
+
+impl From<FieldError> for ZMatrixError {
+    fn from(err: FieldError) -> Self {
+        Self {
+            details: err.to_string(),
+        }
+    }
+}
+
+/// Builds Cartesian coordinates: the first atom sits at the origin, the second on +Z and the
+/// third in the XZ plane. Each atom is bonded to its distance reference.
+pub fn parse_zmatrix(contents: &str) -> Result<Molecule, ZMatrixError> {
+    let mut lines = text::lines(contents).skip_while(Line::is_blank);
+    let rows: Vec<Line> = lines.by_ref().take_while(|line| !line.is_blank()).collect();
+    let mut variables = HashMap::new();
+    for line in lines.filter(|line| !line.is_blank()) {
+        let mut fields = line.fields_split_by(|c| c.is_whitespace() || c == '=');
+        let (Some(name), Some(value), None) = (fields.next(), fields.next(), fields.next()) else {
+            return Err(ZMatrixError::new(
+                line.number,
+                "expected a variable and a value",
+            ));
+        };
+        let value: f32 = value.parse(&format!("value for {}", name.text))?;
+        variables.insert(name.text.to_string(), value);
+    }
+
+    let mut molecule = Molecule::new("zmatrix");
+    let mut ids: Vec<AtomId> = Vec::with_capacity(rows.len());
+    let mut positions: Vec<Vec3> = Vec::with_capacity(rows.len());
+    for line in rows {
+        let number = line.number;
+        let mut fields = line.fields();
+        let element = fields.expect("element")?.text;
+        let fields: Vec<Field> = fields.collect();
+        let expected = 2 * positions.len().min(3);
+        if fields.len() != expected {
+            return Err(ZMatrixError::new(
+                number,
+                format!("expected {expected} fields after the element"),
+            ));
+        }
+        let mut references = Vec::with_capacity(3);
+        let mut values = Vec::with_capacity(3);
+        for pair in fields.chunks(2) {
+            let reference: usize = pair[0]
+                .parse("reference")
+                .ok()
+                .filter(|index| (1..=positions.len()).contains(index))
+                .ok_or_else(|| pair[0].error(format!("invalid reference {}", pair[0].text)))?;
+            if references.contains(&(reference - 1)) {
+                return Err(ZMatrixError::new(number, "repeated reference atom"));
+            }
+            references.push(reference - 1);
+            values.push(resolve(pair[1], &variables)?);
+        }
+        let position = match (references.as_slice(), values.as_slice()) {
+            (&[], &[]) => Vec3::ZERO,
+            (&[a], &[distance]) => positions[a] + Vec3::Z * distance,
+            (&[a, b], &[distance, theta]) => {
+                // Any point off the a-b axis fixes the plane; prefer the XZ plane.
+                let axis = positions[a] - positions[b];
+                let side = if axis.cross(Vec3::X).length_squared() > 1e-6 {
+                    Vec3::X
+                } else {
+                    Vec3::Y
+                };
+                place(
+                    positions[b] + side,
+                    positions[b],
+                    positions[a],
+                    distance,
+                    theta,
+                    0.0,
+                )
+            }
+            (&[a, b, c], &[distance, theta, phi]) => place(
+                positions[c],
+                positions[b],
+                positions[a],
+                distance,
+                theta,
+                phi,
+            ),
+            _ => unreachable!("field count checked above"),
+        };
+        let id = molecule.insert_atom(element.to_string(), position.to_array());
+        if let Some(&first) = references.first() {
+            // Valence errors only mean the distance reference is not a chemical bond.
+            let _ = molecule.add_bond(ids[first], id);
+        }
+        ids.push(id);
+        positions.push(position);
+    }
+    if ids.is_empty() {
+        return Err(ZMatrixError::new(1, "no atoms"));
+    }
+    Ok(molecule)
+}
+
+/// Writes atoms in `atoms_in_order` order. Each atom's references are the nearest earlier
+/// atom, then the earlier atom nearest to that one, then the next nearest that is not
+/// collinear with the first two.
+pub fn write_zmatrix(molecule: &Molecule) -> String {
+    let atoms: Vec<_> = molecule.atoms_in_order().collect();
+    let positions: Vec<Vec3> = atoms
+        .iter()
+        .map(|atom| Vec3::from_array(atom.position))
+        .collect();
+    let nearest = |point: Vec3, before: usize, exclude: &[usize]| {
+        let mut candidates: Vec<usize> = (0..before).filter(|j| !exclude.contains(j)).collect();
+        candidates.sort_by(|&x, &y| {
+            point
+                .distance_squared(positions[x])
+                .total_cmp(&point.distance_squared(positions[y]))
+        });
+        candidates
+    };
+    let mut out = String::new();
+    for (i, atom) in atoms.iter().enumerate() {
+        let _ = write!(out, "{}", atom.element);
+        let mut references: Vec<usize> = Vec::with_capacity(3);
+        if let Some(&first) = nearest(positions[i], i, &[]).first() {
+            references.push(first);
+            if let Some(&second) = nearest(positions[first], i, &[first]).first() {
+                references.push(second);
+                let candidates = nearest(positions[first], i, &[first, second]);
+                let third = candidates
+                    .iter()
+                    .copied()
+                    .find(|&third| {
+                        let (u, v) = (
+                            positions[first] - positions[second],
+                            positions[third] - positions[second],
+                        );
+                        u.normalize_or_zero().cross(v.normalize_or_zero()).length() > 1e-3
+                    })
+                    .or(candidates.first().copied());
+                references.extend(third);
+            }
+        }
+        let id = |index: usize| atoms[index].id;
+        for (slot, &reference) in references.iter().enumerate() {
+            let value = match slot {
+                0 => positions[i].distance(positions[reference]),
+                1 => angle(molecule, atom.id, id(references[0]), id(reference)).unwrap_or(0.0),
+                _ => dihedral(
+                    molecule,
+                    atom.id,
+                    id(references[0]),
+                    id(references[1]),
+                    id(reference),
+                )
+                .unwrap_or(0.0),
+            };
+            let _ = write!(out, " {} {value:.6}", reference + 1);
+        }
+        let _ = writeln!(out);
+    }
+    out
+}
+
+fn resolve(field: Field, variables: &HashMap<String, f32>) -> Result<f32, ZMatrixError> {
+    if let Ok(value) = field.parse("value") {
+        return Ok(value);
+    }
+    let (sign, name) = match field.text.strip_prefix('-') {
+        Some(name) => (-1.0, name),
+        None => (1.0, field.text),
+    };
+    variables
+        .get(name)
+        .map(|value| sign * value)
+        .ok_or_else(|| field.error(format!("undefined variable {name}")).into())
+}
+
+/// Natural extension reference frame: places `d` so that |cd| = `distance`, the angle b-c-d
+/// is `theta` and the dihedral a-b-c-d is `phi` (degrees).
+pub(crate) fn place(a: Vec3, b: Vec3, c: Vec3, distance: f32, theta: f32, phi: f32) -> Vec3 {
+    let (theta, phi) = (theta.to_radians(), phi.to_radians());
+    let bc = (c - b).normalize();
+    let normal = (b - a).cross(bc).normalize();
+    let in_plane = normal.cross(bc);
+    c + bc * (-distance * theta.cos())
+        + in_plane * (distance * theta.sin() * phi.cos())
+        + normal * (distance * theta.sin() * phi.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_variables_and_builds_geometry() {
+        let molecule = parse_zmatrix(
+            "C\nO 1 rco\nH 1 rch 2 a\nH 1 rch 2 a 3 -d\n\nrco 1.2\nrch = 1.1\na 120.0\nd 180.0\n",
+        )
+        .unwrap();
+        let ids = molecule.atom_ids();
+        assert_eq!(molecule.atom_count(), 4);
+        assert_eq!(molecule.get_atom(ids[1]).unwrap().position, [0.0, 0.0, 1.2]);
+        let distance = |a: AtomId, b: AtomId| {
+            let (a, b) = (molecule.get_atom(a).unwrap(), molecule.get_atom(b).unwrap());
+            Vec3::from_array(a.position).distance(Vec3::from_array(b.position))
+        };
+        assert!((distance(ids[0], ids[3]) - 1.1).abs() < 1e-4);
+        assert!((angle(&molecule, ids[2], ids[0], ids[1]).unwrap() - 120.0).abs() < 1e-2);
+        let planar = dihedral(&molecule, ids[3], ids[0], ids[1], ids[2]).unwrap();
+        assert!(planar.abs() < 1e-2 || (planar.abs() - 180.0).abs() < 1e-2);
+        assert_eq!(molecule.neighbors(ids[0]), [ids[1], ids[2], ids[3]]);
+        for bad in ["", "O\nH 2 0.9", "O\nH 1 x", "O\nH 1 0.9 1 90", "O\nH\n"] {
+            assert!(parse_zmatrix(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn write_then_parse_preserves_internal_coordinates() {
+        let mut molecule = Molecule::new("butane");
+        for position in [
+            [0.0, 0.0, 0.0],
+            [1.53, 0.0, 0.0],
+            [2.04, 1.44, 0.0],
+            [3.57, 1.44, 0.3],
+            [4.1, 2.2, 1.5],
+        ] {
+            molecule.insert_atom("C".into(), position);
+        }
+        let text = write_zmatrix(&molecule);
+        let rebuilt = parse_zmatrix(&text).unwrap();
+        let original = molecule.distance_matrix();
+        let distances = rebuilt.distance_matrix();
+        for (row, rebuilt_row) in original.iter().zip(&distances) {
+            for (a, b) in row.iter().zip(rebuilt_row) {
+                assert!((a - b).abs() < 1e-3, "{text}");
+            }
+        }
+    }
+}